@@ -0,0 +1,2 @@
+-- SHA-256 of file contents (NULL for folders)
+ALTER TABLE files ADD COLUMN hash TEXT;
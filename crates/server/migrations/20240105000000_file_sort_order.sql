@@ -0,0 +1,2 @@
+-- Custom ordering of files within a folder (NULL = alphabetical, after ordered files)
+ALTER TABLE files ADD COLUMN sort_order INTEGER;
@@ -0,0 +1,2 @@
+-- Bibliography tool for biblatex documents (NULL = whatever the document asks for)
+ALTER TABLE projects ADD COLUMN bib_tool TEXT;
@@ -0,0 +1,2 @@
+-- Whether the project asks for \write18; the server decides whether to allow it
+ALTER TABLE projects ADD COLUMN shell_escape INTEGER NOT NULL DEFAULT 0;
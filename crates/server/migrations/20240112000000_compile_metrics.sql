@@ -0,0 +1,6 @@
+-- Time spent waiting for a compile slot, separate from the compile itself,
+-- and whether the run produced a PDF without errors
+ALTER TABLE compiles ADD COLUMN queue_ms INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE compiles ADD COLUMN success INTEGER NOT NULL DEFAULT 0;
+
+UPDATE compiles SET success = 1 WHERE status = 'finished' AND error_count = 0;
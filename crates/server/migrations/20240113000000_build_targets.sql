@@ -0,0 +1,3 @@
+-- Named build targets (main file plus engine overrides) as a JSON array;
+-- NULL when the project only has its default build
+ALTER TABLE projects ADD COLUMN build_targets TEXT;
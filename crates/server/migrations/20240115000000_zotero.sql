@@ -0,0 +1,19 @@
+-- Zotero API key per user, with the account it belongs to
+ALTER TABLE users ADD COLUMN zotero_api_key TEXT;
+ALTER TABLE users ADD COLUMN zotero_user_id TEXT;
+ALTER TABLE users ADD COLUMN zotero_username TEXT;
+
+-- A Zotero library or collection whose items are exported to a project .bib
+CREATE TABLE IF NOT EXISTS zotero_links (
+    project_id TEXT PRIMARY KEY REFERENCES projects(id) ON DELETE CASCADE,
+    -- Whose API key is used to read the library
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    library_type TEXT NOT NULL CHECK (library_type IN ('user', 'group')),
+    library_id TEXT NOT NULL,
+    collection_key TEXT,
+    bib_path TEXT NOT NULL,
+    -- Library version of the last export, for conditional requests
+    last_version INTEGER,
+    last_synced_at TEXT,
+    created_at TEXT NOT NULL
+);
@@ -0,0 +1,19 @@
+-- Changes suggested in track-changes mode, waiting for review
+CREATE TABLE IF NOT EXISTS tracked_changes (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    file_path TEXT NOT NULL,
+    author_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- Character offset of the change and the text it replaces, empty for
+    -- insertions
+    start_offset INTEGER NOT NULL,
+    original TEXT NOT NULL,
+    content TEXT NOT NULL,
+    -- pending, accepted or rejected
+    status TEXT NOT NULL DEFAULT 'pending',
+    reviewed_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    reviewed_at TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_tracked_changes_file ON tracked_changes(project_id, file_path, status);
@@ -0,0 +1,13 @@
+-- Updates applied to collaborative documents, with snapshots to replay from
+CREATE TABLE IF NOT EXISTS document_history (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    file_id TEXT NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    user_id TEXT REFERENCES users(id) ON DELETE SET NULL,
+    -- snapshot or update
+    kind TEXT NOT NULL,
+    -- yrs v1 update; the whole document for snapshots
+    data BLOB NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_document_history_file ON document_history(file_id, kind, id);
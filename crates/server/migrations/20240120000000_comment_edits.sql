@@ -0,0 +1,12 @@
+-- Comments can be edited by their author; earlier wordings are kept
+ALTER TABLE comments ADD COLUMN updated_at TEXT;
+
+CREATE TABLE IF NOT EXISTS comment_revisions (
+    id TEXT PRIMARY KEY,
+    comment_id TEXT NOT NULL REFERENCES comments(id) ON DELETE CASCADE,
+    -- The content as it was before the edit made at replaced_at
+    content TEXT NOT NULL,
+    replaced_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_comment_revisions_comment ON comment_revisions(comment_id);
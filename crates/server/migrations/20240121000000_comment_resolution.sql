@@ -0,0 +1,3 @@
+-- Who resolved a comment and when; cleared when it is reopened
+ALTER TABLE comments ADD COLUMN resolved_by TEXT REFERENCES users(id) ON DELETE SET NULL;
+ALTER TABLE comments ADD COLUMN resolved_at TEXT;
@@ -0,0 +1,11 @@
+-- Projects backed by a git repository
+CREATE TABLE IF NOT EXISTS project_git (
+    project_id TEXT PRIMARY KEY REFERENCES projects(id) ON DELETE CASCADE,
+    -- Commit changes on the server's schedule as well as on request
+    auto_commit BOOLEAN NOT NULL DEFAULT 1,
+    -- Paths and content hashes of the files last committed, so an unchanged
+    -- project is not committed again
+    fingerprint TEXT,
+    created_at TEXT NOT NULL,
+    last_commit_at TEXT
+);
@@ -0,0 +1,15 @@
+-- Remote repositories git-backed projects push to and pull from
+ALTER TABLE project_git ADD COLUMN remote_url TEXT;
+ALTER TABLE project_git ADD COLUMN remote_branch TEXT;
+ALTER TABLE project_git ADD COLUMN last_push_at TEXT;
+ALTER TABLE project_git ADD COLUMN last_pull_at TEXT;
+
+-- Access tokens for git hosts, sealed with the server key
+CREATE TABLE IF NOT EXISTS git_credentials (
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    host TEXT NOT NULL,
+    username TEXT NOT NULL,
+    token TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, host)
+);
@@ -0,0 +1,24 @@
+-- Projects kept in two-way sync with a folder on a WebDAV server
+CREATE TABLE IF NOT EXISTS project_sync (
+    project_id TEXT PRIMARY KEY REFERENCES projects(id) ON DELETE CASCADE,
+    -- Collection the project's files live in, ending in a slash
+    url TEXT NOT NULL,
+    username TEXT NOT NULL,
+    -- Sealed with the server key
+    password TEXT NOT NULL,
+    -- User whose name edits arriving from the server are made under
+    created_by TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    created_at TEXT NOT NULL,
+    last_sync_at TEXT,
+    last_error TEXT
+);
+
+-- Each file as both sides had it after the last sync, so a later sync can
+-- tell which side changed it
+CREATE TABLE IF NOT EXISTS project_sync_files (
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    etag TEXT NOT NULL,
+    PRIMARY KEY (project_id, path)
+);
@@ -0,0 +1,13 @@
+-- Instance-wide state operators set from the admin API: maintenance mode,
+-- which makes the instance read-only, and a banner shown to every user.
+-- There is only ever the one row.
+CREATE TABLE IF NOT EXISTS system_status (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    maintenance BOOLEAN NOT NULL DEFAULT FALSE,
+    maintenance_message TEXT,
+    announcement TEXT,
+    updated_at TEXT,
+    updated_by TEXT REFERENCES users(id) ON DELETE SET NULL
+);
+
+INSERT INTO system_status (id) VALUES (1) ON CONFLICT DO NOTHING;
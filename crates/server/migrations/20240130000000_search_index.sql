@@ -0,0 +1,30 @@
+-- What the full-text index holds: file contents and names, comments, and
+-- project names
+CREATE TABLE IF NOT EXISTS search_entries (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    -- file, comment or project
+    kind TEXT NOT NULL,
+    -- Id of the file, comment or project
+    ref_id TEXT NOT NULL,
+    -- The file path, the commented file or the project name
+    title TEXT NOT NULL,
+    -- The file's hash or the comment's last edit when indexed; entries whose
+    -- source no longer matches are reindexed
+    version TEXT,
+    UNIQUE (kind, ref_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_search_entries_project ON search_entries(project_id, kind);
+
+-- One row per entry, with the entry's id as rowid
+CREATE VIRTUAL TABLE IF NOT EXISTS search_index USING fts5(
+    title,
+    body,
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS search_entries_delete AFTER DELETE ON search_entries
+BEGIN
+    DELETE FROM search_index WHERE rowid = old.id;
+END;
@@ -0,0 +1,5 @@
+-- Disabled accounts cannot sign in and are hidden from people searching for
+-- collaborators, but keep their name on what they wrote. After the retention
+-- window an admin purge erases their personal data and sets purged_at.
+ALTER TABLE users ADD COLUMN disabled_at TEXT;
+ALTER TABLE users ADD COLUMN purged_at TEXT;
@@ -0,0 +1,3 @@
+-- Errors and warnings of each run as JSON, so the next run can tell which
+-- ones it fixed or introduced
+ALTER TABLE compiles ADD COLUMN diagnostics TEXT;
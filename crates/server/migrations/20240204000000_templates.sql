@@ -0,0 +1,30 @@
+-- Projects published for everyone on the server to start new projects from;
+-- the files are a snapshot taken when publishing, kept as storage objects
+CREATE TABLE IF NOT EXISTS templates (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    project_id TEXT REFERENCES projects(id) ON DELETE SET NULL,
+    published_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    -- PNG of the first page of the project's last compile
+    preview_hash TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS template_files (
+    template_id TEXT NOT NULL REFERENCES templates(id) ON DELETE CASCADE,
+    path TEXT NOT NULL,
+    hash TEXT NOT NULL,
+    PRIMARY KEY (template_id, path)
+);
+
+CREATE INDEX IF NOT EXISTS idx_template_files_hash ON template_files(hash);
+
+CREATE TABLE IF NOT EXISTS template_tags (
+    template_id TEXT NOT NULL REFERENCES templates(id) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (template_id, tag)
+);
+
+CREATE INDEX IF NOT EXISTS idx_template_tags_tag ON template_tags(tag);
@@ -0,0 +1,2 @@
+-- Server administrators (backups, maintenance)
+ALTER TABLE users ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE;
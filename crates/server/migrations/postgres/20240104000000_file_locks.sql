@@ -0,0 +1,3 @@
+-- Advisory locks for editing files over REST
+ALTER TABLE files ADD COLUMN locked_by TEXT;
+ALTER TABLE files ADD COLUMN lock_expires_at TEXT;
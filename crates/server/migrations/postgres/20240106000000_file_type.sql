@@ -0,0 +1,3 @@
+-- Detected content type and editor language (NULL language = binary)
+ALTER TABLE files ADD COLUMN mime_type TEXT;
+ALTER TABLE files ADD COLUMN language TEXT;
@@ -0,0 +1,2 @@
+-- Per-project compile settings (NULL = server default)
+ALTER TABLE projects ADD COLUMN engine TEXT;
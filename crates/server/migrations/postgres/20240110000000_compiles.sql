@@ -0,0 +1,21 @@
+-- One row per compile run; PDFs of the most recent runs are kept under
+-- CACHE_PATH/history for before/after comparisons
+CREATE TABLE IF NOT EXISTS compiles (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    engine TEXT NOT NULL,
+    main_file TEXT NOT NULL,
+    started_at TEXT NOT NULL,
+    finished_at TEXT NOT NULL,
+    duration_ms BIGINT NOT NULL,
+    error_count BIGINT NOT NULL DEFAULT 0,
+    warning_count BIGINT NOT NULL DEFAULT 0,
+    -- Why the compile could not run at all, if it didn't
+    error TEXT,
+    log TEXT NOT NULL DEFAULT '',
+    has_pdf BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX IF NOT EXISTS idx_compiles_project ON compiles(project_id, started_at);
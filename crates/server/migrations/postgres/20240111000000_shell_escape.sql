@@ -0,0 +1,2 @@
+-- Whether the project asks for \write18; the server decides whether to allow it
+ALTER TABLE projects ADD COLUMN shell_escape BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,6 @@
+-- Time spent waiting for a compile slot, separate from the compile itself,
+-- and whether the run produced a PDF without errors
+ALTER TABLE compiles ADD COLUMN queue_ms BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE compiles ADD COLUMN success BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE compiles SET success = TRUE WHERE status = 'finished' AND error_count = 0;
@@ -0,0 +1,7 @@
+-- Words a project accepts in spell checking, e.g. names and jargon
+CREATE TABLE IF NOT EXISTS project_dictionary (
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    word TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (project_id, word)
+);
@@ -0,0 +1,25 @@
+-- Labelled snapshots of every file in a project
+CREATE TABLE IF NOT EXISTS project_versions (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    label TEXT NOT NULL,
+    description TEXT,
+    created_by TEXT REFERENCES users(id) ON DELETE SET NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_project_versions_project ON project_versions(project_id, created_at);
+
+-- The files of a version; contents are storage objects named by hash
+CREATE TABLE IF NOT EXISTS project_version_files (
+    version_id TEXT NOT NULL REFERENCES project_versions(id) ON DELETE CASCADE,
+    file_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    path TEXT NOT NULL,
+    is_folder BOOLEAN NOT NULL,
+    hash TEXT,
+    size BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (version_id, path)
+);
+
+CREATE INDEX IF NOT EXISTS idx_project_version_files_hash ON project_version_files(hash);
@@ -0,0 +1,4 @@
+-- How each user is emailed about notifications: immediate, daily or off
+ALTER TABLE users ADD COLUMN email_notifications TEXT NOT NULL DEFAULT 'immediate';
+-- When the user's last daily digest went out
+ALTER TABLE users ADD COLUMN digest_sent_at TEXT;
@@ -0,0 +1,30 @@
+-- What the full-text index holds: file contents and names, comments, and
+-- project names
+CREATE TABLE IF NOT EXISTS search_entries (
+    id BIGINT GENERATED ALWAYS AS IDENTITY PRIMARY KEY,
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    -- file, comment or project
+    kind TEXT NOT NULL,
+    -- Id of the file, comment or project
+    ref_id TEXT NOT NULL,
+    -- The file path, the commented file or the project name
+    title TEXT NOT NULL,
+    -- The file's hash or the comment's last edit when indexed; entries whose
+    -- source no longer matches are reindexed
+    version TEXT,
+    UNIQUE (kind, ref_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_search_entries_project ON search_entries(project_id, kind);
+
+-- One row per entry; matches in the title rank above the body
+CREATE TABLE IF NOT EXISTS search_index (
+    entry_id BIGINT PRIMARY KEY REFERENCES search_entries(id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    body TEXT NOT NULL,
+    document TSVECTOR GENERATED ALWAYS AS (
+        setweight(to_tsvector('simple', title), 'A') || setweight(to_tsvector('simple', body), 'B')
+    ) STORED
+);
+
+CREATE INDEX IF NOT EXISTS idx_search_index_document ON search_index USING GIN (document);
@@ -0,0 +1,3 @@
+-- Frozen projects refuse changes to their files, e.g. once submitted
+ALTER TABLE projects ADD COLUMN frozen_at TEXT;
+ALTER TABLE projects ADD COLUMN frozen_by TEXT;
@@ -0,0 +1,221 @@
+use std::path::PathBuf;
+
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    db::Database,
+    services::{backup::BackupService, health, storage::StorageService},
+};
+
+// Programs only some features need; the server runs without them
+const OPTIONAL_PROGRAMS: &[(&str, &str)] = &[
+    ("chktex", "LaTeX linting"),
+    ("git", "git-backed projects"),
+    ("hunspell", "spell checking"),
+    ("pandoc", "Word, HTML and EPUB export"),
+    ("pdftoppm", "PDF page thumbnails"),
+    ("synctex", "source and PDF navigation"),
+    ("texcount", "word counts"),
+    ("tlmgr", "installing missing packages"),
+];
+
+#[derive(Parser)]
+#[command(
+    name = "openleaf",
+    version,
+    about = "Collaborative LaTeX editor server"
+)]
+pub struct Cli {
+    /// TOML config file; openleaf.toml in the working directory is read when omitted
+    #[arg(long, global = true, env = "CONFIG_FILE", value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the server (the default)
+    Serve,
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// Manage accounts
+    Admin {
+        #[command(subcommand)]
+        command: AdminCommand,
+    },
+    /// Back up projects to the configured backup target
+    Backup {
+        /// Projects to back up; every project when none are given
+        #[arg(value_name = "PROJECT_ID")]
+        project_ids: Vec<String>,
+    },
+    /// Check the database, storage and the external programs compiles and features use
+    Doctor,
+}
+
+#[derive(Subcommand)]
+pub enum AdminCommand {
+    /// Create an account, with the same checks as registration
+    CreateUser {
+        /// Sign-in email address
+        #[arg(long)]
+        email: String,
+        /// Display name
+        #[arg(long)]
+        name: String,
+        /// Read from standard input when omitted, to keep it out of shell history
+        #[arg(long, env = "OPENLEAF_PASSWORD", hide_env_values = true)]
+        password: Option<String>,
+        /// Grant administrator rights
+        #[arg(long)]
+        admin: bool,
+    },
+}
+
+pub async fn migrate(config: &Config) -> anyhow::Result<()> {
+    let db = Database::connect(&config.database).await?;
+    db.run_migrations().await?;
+    println!("Database is up to date");
+
+    // ADMIN_EMAILS is applied here, when an operator runs this, rather than
+    // on every request: registration does not verify addresses, so whoever
+    // signed up with a listed one first would otherwise become an admin
+    for email in &config.admin_emails {
+        let granted = sqlx::query(
+            "UPDATE users SET is_admin = TRUE \
+             WHERE LOWER(email) = $1 AND is_admin = FALSE AND disabled_at IS NULL",
+        )
+        .bind(email)
+        .execute(&db.pool)
+        .await?;
+        if granted.rows_affected() > 0 {
+            println!("Granted admin rights to {email}");
+        } else {
+            let exists =
+                sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE LOWER(email) = $1")
+                    .bind(email)
+                    .fetch_one(&db.pool)
+                    .await?;
+            if exists == 0 {
+                println!(
+                    "No account for {email}; create it with `openleaf admin create-user --admin`"
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+pub async fn create_user(
+    config: &Config,
+    email: &str,
+    name: &str,
+    password: Option<String>,
+    admin: bool,
+) -> anyhow::Result<()> {
+    let password = match password {
+        Some(password) => password,
+        None => {
+            let mut line = String::new();
+            std::io::stdin().read_line(&mut line)?;
+            line.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+
+    if !crate::services::mailer::is_valid_address(email) {
+        anyhow::bail!("Invalid email address");
+    }
+    if name.is_empty() {
+        anyhow::bail!("Name is required");
+    }
+    if password.len() < 8 {
+        anyhow::bail!("Password must be at least 8 characters");
+    }
+
+    let db = Database::connect(&config.database).await?;
+    db.run_migrations().await?;
+
+    let existing = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE email = $1")
+        .bind(email)
+        .fetch_one(&db.pool)
+        .await?;
+    if existing > 0 {
+        anyhow::bail!("Email already registered");
+    }
+
+    let password_hash = crate::routes::auth::hash_password(&password)?;
+    let user_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO users (id, email, name, password_hash, is_admin, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(&user_id)
+    .bind(email)
+    .bind(name)
+    .bind(&password_hash)
+    .bind(admin || config.admin_emails.contains(&email.to_lowercase()))
+    .bind(Utc::now().to_rfc3339())
+    .execute(&db.pool)
+    .await?;
+
+    println!("Created user {user_id}");
+    Ok(())
+}
+
+pub async fn backup(config: &Config, project_ids: &[String]) -> anyhow::Result<()> {
+    let db = Database::connect(&config.database).await?;
+    let storage = StorageService::from_config(config)?;
+    let backups = BackupService::from_config(config)?;
+
+    if project_ids.is_empty() {
+        let completed = backups.backup_all(&db, &storage).await?;
+        println!("Backed up {completed} projects");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for project_id in project_ids {
+        match backups.backup_project(&db, &storage, project_id).await {
+            Ok(info) => println!("{project_id}: {} ({} bytes)", info.id, info.size),
+            Err(e) => {
+                eprintln!("{project_id}: {e}");
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{failed} of {} backups failed", project_ids.len());
+    }
+    Ok(())
+}
+
+/// Run the readiness checks, then look for optional programs. Fails when
+/// the server would not report itself ready.
+pub async fn doctor(config: &Config) -> anyhow::Result<()> {
+    let db = Database::connect(&config.database).await?;
+    let storage = StorageService::from_config(config)?;
+    let readiness = health::readiness(&db, &storage, &config.cache_path, &config.compile).await;
+
+    for (name, component) in &readiness.components {
+        match &component.error {
+            None => println!("ok       {name} ({} ms)", component.latency_ms),
+            Some(error) => println!("FAILED   {name}: {error}"),
+        }
+    }
+    for (program, purpose) in OPTIONAL_PROGRAMS {
+        if health::find_program(program).is_some() {
+            println!("ok       {program}");
+        } else {
+            println!("missing  {program}: needed for {purpose}");
+        }
+    }
+
+    if !readiness.is_ready() {
+        anyhow::bail!("Required checks failed");
+    }
+    Ok(())
+}
@@ -1,26 +1,774 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::env;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::Context;
+
+// Read when CONFIG_FILE is unset and the file exists
+const DEFAULT_CONFIG_FILE: &str = "openleaf.toml";
+
+// Signs tokens when JWT_SECRET is unset; only accepted in development mode
+const DEV_JWT_SECRET: &str = "development-secret-change-in-production";
+
+// The default and the placeholders from .env.example and docker-compose.yml
+const INSECURE_JWT_SECRETS: &[&str] = &[
+    DEV_JWT_SECRET,
+    "change-this-to-a-secure-random-string",
+    "change-this-in-production",
+];
 
 #[derive(Clone)]
 pub struct Config {
     pub port: u16,
-    pub database_url: String,
+    // HTTPS served by the server itself; off without a certificate and key
+    pub tls: Option<TlsConfig>,
+    pub cors: CorsConfig,
+    pub rate_limit: RateLimitConfig,
+    // Seconds to wait on shutdown for open requests and running compiles
+    pub shutdown_timeout_secs: u64,
+    pub database: DatabaseConfig,
+    pub storage_backend: String,
     pub storage_path: String,
+    pub s3: Option<S3Config>,
+    pub work_path: String,
+    // Minimum size in bytes for hardlink deduplication; None disables it
+    pub dedup_min_size: Option<u64>,
+    pub encryption_key: Option<String>,
+    pub cache_path: String,
+    pub upload_path: String,
+    // Directory of operator-provided templates, one per subdirectory
+    pub templates_path: Option<String>,
+    pub max_upload_size: u64,
+    pub watch_storage: bool,
+    // Hours between orphaned-storage sweeps; 0 disables the schedule
+    pub gc_interval_hours: u64,
+    // Whether scheduled sweeps delete what they find or only report it
+    pub gc_delete: bool,
+    // Seconds between saves of documents being edited collaboratively
+    pub collab_save_interval_secs: u64,
+    // Seconds a room stays in memory after its last client leaves
+    pub collab_room_ttl_secs: u64,
+    pub ws: WsConfig,
+    pub backup: BackupConfig,
+    pub git: GitConfig,
+    // Minutes between syncs of projects linked to a WebDAV folder; 0 syncs
+    // on request only
+    pub webdav_sync_minutes: u64,
+    pub compile: CompileConfig,
+    // OPENLEAF_ENV=development; allows the built-in JWT secret
+    pub dev_mode: bool,
     pub jwt_secret: String,
+    // Accounts `openleaf migrate` makes admins; never checked per request
+    pub admin_emails: Vec<String>,
+    // Days a disabled account is kept before an admin purge erases it
+    pub user_retention_days: u64,
+    pub pdf_provenance: bool,
+    // Bearer token for /metrics; the endpoint is off without one
+    pub metrics_token: Option<String>,
+    // Hunspell dictionary used when a spell check doesn't name one
+    pub spellcheck_language: String,
+    // Crossref and arXiv APIs used to import references by DOI or arXiv ID
+    pub crossref_url: String,
+    pub arxiv_url: String,
+    // Contact address sent to Crossref with each lookup
+    pub crossref_mailto: Option<String>,
+    // Zotero web API that linked libraries are exported from
+    pub zotero_url: String,
+    // SMTP delivery of notification emails; off without SMTP_HOST
+    pub mail: Option<MailConfig>,
 }
 
-impl Config {
-    pub fn from_env() -> Self {
+#[derive(Clone)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    pub endpoint: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub prefix: String,
+}
+
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    // PEM certificate chain, leaf first, and its private key; both are
+    // reloaded when they change, so renewals need no restart
+    pub cert_path: PathBuf,
+    pub key_path: PathBuf,
+}
+
+impl TlsConfig {
+    fn load(source: &Source) -> Option<Self> {
+        let cert = source.var("TLS_CERT_PATH").ok().filter(|v| !v.is_empty());
+        let key = source.var("TLS_KEY_PATH").ok().filter(|v| !v.is_empty());
+        match (cert, key) {
+            (Some(cert), Some(key)) => Some(Self {
+                cert_path: PathBuf::from(cert),
+                key_path: PathBuf::from(key),
+            }),
+            (None, None) => None,
+            _ => {
+                source.problem("TLS_CERT_PATH and TLS_KEY_PATH must be set together".to_string());
+                None
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CorsConfig {
+    // Origins other sites may call the API from, like "https://app.example.com",
+    // or "*" for any; empty allows none, which suits the bundled web app
+    pub allowed_origins: Vec<String>,
+    // Request headers cross-origin callers may send
+    pub allowed_headers: Vec<String>,
+    // Whether cross-origin requests may carry cookies and HTTP auth
+    pub allow_credentials: bool,
+    // Seconds browsers may cache a preflight response
+    pub max_age_secs: u64,
+}
+
+impl CorsConfig {
+    fn load(source: &Source) -> Self {
+        let list = |name: &str, default: &str| -> Vec<String> {
+            source
+                .var(name)
+                .unwrap_or_else(|_| default.to_string())
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        };
         Self {
-            port: env::var("PORT")
-                .ok()
-                .and_then(|p| p.parse().ok())
-                .unwrap_or(3000),
-            database_url: env::var("DATABASE_URL")
+            allowed_origins: list("CORS_ALLOWED_ORIGINS", ""),
+            allowed_headers: list(
+                "CORS_ALLOWED_HEADERS",
+                "authorization,content-type,if-none-match,range",
+            ),
+            allow_credentials: source.flag("CORS_ALLOW_CREDENTIALS"),
+            max_age_secs: source.parse("CORS_MAX_AGE_SECS").unwrap_or(3600),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct RateBudget {
+    // Sustained requests per minute; 0 turns the budget off
+    pub per_minute: u32,
+    // Requests allowed at once before the sustained rate applies
+    pub burst: u32,
+}
+
+#[derive(Clone)]
+pub struct RateLimitConfig {
+    // Every API request, per user when signed in and per IP otherwise
+    pub global: RateBudget,
+    // Sign-in and registration, per IP
+    pub auth: RateBudget,
+    // Starting compiles, per user
+    pub compile: RateBudget,
+    // File uploads and resumable upload chunks, per user
+    pub upload: RateBudget,
+    // Take the client IP from X-Forwarded-For, as set by a reverse proxy;
+    // without a proxy clients could pick their own address
+    pub trust_proxy_headers: bool,
+}
+
+impl RateLimitConfig {
+    fn load(source: &Source) -> Self {
+        let budget = |name: &str, per_minute: u32, burst: u32| RateBudget {
+            per_minute: source
+                .parse(&format!("RATE_LIMIT_{name}_PER_MINUTE"))
+                .unwrap_or(per_minute),
+            burst: source
+                .parse(&format!("RATE_LIMIT_{name}_BURST"))
+                .unwrap_or(burst),
+        };
+        Self {
+            global: budget("GLOBAL", 600, 200),
+            auth: budget("AUTH", 10, 10),
+            compile: budget("COMPILE", 20, 5),
+            upload: budget("UPLOAD", 120, 60),
+            trust_proxy_headers: source.flag("TRUST_PROXY_HEADERS"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DatabaseConfig {
+    pub url: String,
+    // Pool size; None uses the backend's default
+    pub max_connections: Option<u32>,
+    // Seconds a SQLite write waits for another writer before failing with
+    // "database is locked"
+    pub busy_timeout_secs: u64,
+}
+
+impl DatabaseConfig {
+    fn load(source: &Source) -> Self {
+        Self {
+            url: source
+                .var("DATABASE_URL")
                 .unwrap_or_else(|_| "sqlite:./data/openleaf.db?mode=rwc".to_string()),
-            storage_path: env::var("STORAGE_PATH")
+            max_connections: source.parse("DATABASE_MAX_CONNECTIONS").filter(|&n| n > 0),
+            busy_timeout_secs: source.parse("DATABASE_BUSY_TIMEOUT_SECS").unwrap_or(10),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct WsConfig {
+    // Seconds between pings the server sends on every websocket
+    pub ping_interval_secs: u64,
+    // Seconds without hearing from a client before its socket is closed
+    pub idle_timeout_secs: u64,
+    // Seconds a dropped editor connection can be resumed with its token
+    pub resume_window_secs: u64,
+    // Largest message an editor connection may send, in bytes
+    pub max_message_bytes: usize,
+    // Sustained messages per second a connection may send, and how many it
+    // may send in a burst; 0 disables the limit
+    pub message_rate: u32,
+    pub message_burst: u32,
+}
+
+impl WsConfig {
+    fn load(source: &Source) -> Self {
+        Self {
+            ping_interval_secs: source
+                .parse("WS_PING_INTERVAL_SECS")
+                .filter(|&secs| secs > 0)
+                .unwrap_or(30),
+            idle_timeout_secs: source
+                .parse("WS_IDLE_TIMEOUT_SECS")
+                .filter(|&secs| secs > 0)
+                .unwrap_or(90),
+            resume_window_secs: source.parse("WS_RESUME_WINDOW_SECS").unwrap_or(60),
+            max_message_bytes: source
+                .parse("WS_MAX_MESSAGE_BYTES")
+                .unwrap_or(8 * 1024 * 1024),
+            message_rate: source.parse("WS_MESSAGE_RATE").unwrap_or(60),
+            message_burst: source.parse("WS_MESSAGE_BURST").unwrap_or(300),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct BackupConfig {
+    // "local" or "s3"; s3 reuses the S3_* bucket settings
+    pub target: String,
+    pub path: String,
+    pub s3_prefix: String,
+    // Hours between scheduled backups; 0 disables the schedule
+    pub interval_hours: u64,
+    // Backups kept per project; 0 keeps all
+    pub retention: usize,
+}
+
+impl BackupConfig {
+    fn load(source: &Source) -> Self {
+        Self {
+            target: source
+                .var("BACKUP_TARGET")
+                .unwrap_or_else(|_| "local".to_string()),
+            path: source
+                .var("BACKUP_PATH")
+                .unwrap_or_else(|_| "./data/backups".to_string()),
+            s3_prefix: source
+                .var("BACKUP_S3_PREFIX")
+                .unwrap_or_else(|_| "backups".to_string()),
+            interval_hours: source.parse("BACKUP_INTERVAL_HOURS").unwrap_or(0),
+            retention: source.parse("BACKUP_RETENTION").unwrap_or(7),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct GitConfig {
+    // Directory holding one bare repository per git-backed project
+    pub path: String,
+    // Minutes between commits of git-backed projects that changed; 0 commits
+    // on request only
+    pub auto_commit_minutes: u64,
+}
+
+impl GitConfig {
+    fn load(source: &Source) -> Self {
+        Self {
+            path: source
+                .var("GIT_PATH")
+                .unwrap_or_else(|_| "./data/git".to_string()),
+            auto_commit_minutes: source.parse("GIT_AUTO_COMMIT_MINUTES").unwrap_or(5),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CompileConfig {
+    // "latexmk" (TeX Live) or "tectonic"
+    pub backend: String,
+    // Wall-clock seconds before a compile is killed; 0 disables the limit
+    pub timeout_secs: u64,
+    // Per-process CPU seconds (RLIMIT_CPU); 0 disables the limit
+    pub cpu_limit_secs: u64,
+    // Per-process address space in MiB (RLIMIT_AS); 0 disables the limit
+    pub memory_limit_mb: u64,
+    // Compile runs kept in each project's history; 0 keeps all
+    pub history_limit: u64,
+    // PDFs of the most recent runs kept for comparison; 0 keeps none
+    pub history_pdfs: u64,
+    // Compiles running at once, overall and per user; 0 disables the limit
+    pub max_concurrent: usize,
+    pub user_concurrent: usize,
+    // Compiles a user may have waiting on top of the running ones
+    pub user_queue: usize,
+    // Install packages the log reports missing with tlmgr, then retry once
+    pub install_packages: bool,
+    // "none" or "bubblewrap"
+    pub sandbox: String,
+    // Project IDs and owner user IDs allowed shell escape and a project
+    // latexmkrc ("*" for all); only honoured with a sandbox
+    pub shell_escape_allowlist: Vec<String>,
+}
+
+impl CompileConfig {
+    fn load(source: &Source) -> Self {
+        let var = |name: &str, default: u64| source.parse(name).unwrap_or(default);
+        Self {
+            backend: source
+                .var("COMPILE_BACKEND")
+                .unwrap_or_else(|_| "latexmk".to_string()),
+            timeout_secs: var("COMPILE_TIMEOUT_SECS", 300),
+            cpu_limit_secs: var("COMPILE_CPU_LIMIT_SECS", 240),
+            memory_limit_mb: var("COMPILE_MEMORY_LIMIT_MB", 2048),
+            history_limit: var("COMPILE_HISTORY_LIMIT", 100),
+            history_pdfs: var("COMPILE_HISTORY_PDFS", 5),
+            max_concurrent: var("COMPILE_MAX_CONCURRENT", 4) as usize,
+            user_concurrent: var("COMPILE_USER_CONCURRENT", 1) as usize,
+            user_queue: var("COMPILE_USER_QUEUE", 2) as usize,
+            install_packages: source.flag("COMPILE_INSTALL_PACKAGES"),
+            sandbox: source
+                .var("COMPILE_SANDBOX")
+                .unwrap_or_else(|_| "none".to_string()),
+            shell_escape_allowlist: source
+                .var("COMPILE_SHELL_ESCAPE_ALLOWLIST")
+                .map(|v| {
+                    v.split(',')
+                        .map(|entry| entry.trim().to_string())
+                        .filter(|entry| !entry.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MailConfig {
+    pub host: String,
+    pub port: u16,
+    // "starttls", "tls" (implicit TLS, usually port 465) or "none"
+    pub security: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // Sender, optionally with a display name: "OpenLeaf <noreply@example.com>"
+    pub from: String,
+    // Address of the web app, for links in emails
+    pub app_url: String,
+    // Hour of the day (UTC) daily digests are sent
+    pub digest_hour: u32,
+    // Delivery attempts per email before it is dropped
+    pub max_attempts: u32,
+}
+
+impl MailConfig {
+    fn load(source: &Source) -> Option<Self> {
+        let security = source
+            .var("SMTP_SECURITY")
+            .unwrap_or_else(|_| "starttls".to_string());
+        let default_port = if security == "tls" { 465 } else { 587 };
+        // Everything is read before the host so the settings count as known
+        // even while mail is off
+        let mail = Self {
+            host: String::new(),
+            port: source.parse("SMTP_PORT").unwrap_or(default_port),
+            security,
+            username: source.var("SMTP_USERNAME").ok().filter(|v| !v.is_empty()),
+            password: source.var("SMTP_PASSWORD").ok(),
+            from: source
+                .var("MAIL_FROM")
+                .unwrap_or_else(|_| "OpenLeaf <noreply@localhost>".to_string()),
+            app_url: source
+                .var("APP_URL")
+                .map(|v| v.trim_end_matches('/').to_string())
+                .unwrap_or_else(|_| "http://localhost:3000".to_string()),
+            digest_hour: source
+                .parse("MAIL_DIGEST_HOUR")
+                .filter(|&hour| hour < 24)
+                .unwrap_or(8),
+            max_attempts: source
+                .parse("MAIL_MAX_ATTEMPTS")
+                .filter(|&attempts| attempts > 0)
+                .unwrap_or(5),
+        };
+        Some(Self {
+            host: source.var("SMTP_HOST").ok().filter(|v| !v.is_empty())?,
+            ..mail
+        })
+    }
+}
+
+impl S3Config {
+    fn load(source: &Source) -> Option<Self> {
+        let s3 = Self {
+            bucket: String::new(),
+            region: source
+                .var("S3_REGION")
+                .unwrap_or_else(|_| "us-east-1".to_string()),
+            endpoint: source.var("S3_ENDPOINT").ok(),
+            access_key_id: source.var("S3_ACCESS_KEY_ID").ok(),
+            secret_access_key: source.var("S3_SECRET_ACCESS_KEY").ok(),
+            prefix: source.var("S3_PREFIX").unwrap_or_default(),
+        };
+        Some(Self {
+            bucket: source.var("S3_BUCKET").ok()?,
+            ..s3
+        })
+    }
+}
+
+impl Config {
+    /// Read the configuration from the environment and the config file, and
+    /// check it, listing every problem at once. `config_file` comes from
+    /// `--config` or CONFIG_FILE.
+    pub fn load(config_file: Option<&Path>) -> anyhow::Result<Self> {
+        let source = Source::open(config_file)?;
+        let config = Self::from_source(&source);
+        let mut problems = source.finish();
+        problems.extend(config.validate());
+        if !problems.is_empty() {
+            anyhow::bail!("Invalid configuration:\n  - {}", problems.join("\n  - "));
+        }
+        if config.jwt_secret.len() < 32 && !config.dev_mode {
+            tracing::warn!("JWT_SECRET is shorter than 32 characters; tokens are easier to forge");
+        }
+        Ok(config)
+    }
+
+    fn from_source(source: &Source) -> Self {
+        Self {
+            port: source.parse("PORT").unwrap_or(3000),
+            tls: TlsConfig::load(source),
+            cors: CorsConfig::load(source),
+            rate_limit: RateLimitConfig::load(source),
+            shutdown_timeout_secs: source.parse("SHUTDOWN_TIMEOUT_SECS").unwrap_or(30),
+            database: DatabaseConfig::load(source),
+            storage_backend: source
+                .var("STORAGE_BACKEND")
+                .unwrap_or_else(|_| "local".to_string()),
+            storage_path: source
+                .var("STORAGE_PATH")
                 .unwrap_or_else(|_| "./data/projects".to_string()),
-            jwt_secret: env::var("JWT_SECRET")
-                .unwrap_or_else(|_| "development-secret-change-in-production".to_string()),
+            s3: S3Config::load(source),
+            work_path: source
+                .var("WORK_PATH")
+                .unwrap_or_else(|_| "./data/work".to_string()),
+            dedup_min_size: source
+                .flag("STORAGE_DEDUP")
+                .then_some(source.parse("STORAGE_DEDUP_MIN_SIZE").unwrap_or(64 * 1024)),
+            // Prefer a key file so the secret can be mounted from a KMS/secret store
+            encryption_key: source
+                .var("ENCRYPTION_KEY_FILE")
+                .ok()
+                .and_then(|path| match std::fs::read_to_string(&path) {
+                    Ok(key) => Some(key),
+                    Err(e) => {
+                        source.problem(format!("ENCRYPTION_KEY_FILE: cannot read {path}: {e}"));
+                        None
+                    }
+                })
+                .or(source.var("ENCRYPTION_KEY").ok()),
+            cache_path: source
+                .var("CACHE_PATH")
+                .unwrap_or_else(|_| "./data/cache".to_string()),
+            upload_path: source
+                .var("UPLOAD_PATH")
+                .unwrap_or_else(|_| "./data/uploads".to_string()),
+            templates_path: source.var("TEMPLATES_PATH").ok().filter(|v| !v.is_empty()),
+            max_upload_size: source
+                .parse("MAX_UPLOAD_SIZE")
+                .unwrap_or(1024 * 1024 * 1024),
+            watch_storage: source.flag("WATCH_STORAGE"),
+            gc_interval_hours: source.parse("GC_INTERVAL_HOURS").unwrap_or(0),
+            gc_delete: source.flag("GC_DELETE"),
+            collab_save_interval_secs: source
+                .parse("COLLAB_SAVE_INTERVAL_SECS")
+                .filter(|&secs| secs > 0)
+                .unwrap_or(10),
+            collab_room_ttl_secs: source.parse("COLLAB_ROOM_TTL_SECS").unwrap_or(300),
+            ws: WsConfig::load(source),
+            backup: BackupConfig::load(source),
+            git: GitConfig::load(source),
+            webdav_sync_minutes: source.parse("WEBDAV_SYNC_MINUTES").unwrap_or(15),
+            compile: CompileConfig::load(source),
+            dev_mode: source
+                .var("OPENLEAF_ENV")
+                .is_ok_and(|v| v.eq_ignore_ascii_case("development")),
+            jwt_secret: source
+                .var("JWT_SECRET")
+                .unwrap_or_else(|_| DEV_JWT_SECRET.to_string()),
+            admin_emails: source
+                .var("ADMIN_EMAILS")
+                .map(|v| {
+                    v.split(',')
+                        .map(|email| email.trim().to_lowercase())
+                        .filter(|email| !email.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            user_retention_days: source.parse("USER_RETENTION_DAYS").unwrap_or(30),
+            pdf_provenance: source.flag("PDF_PROVENANCE"),
+            metrics_token: source.var("METRICS_TOKEN").ok().filter(|v| !v.is_empty()),
+            spellcheck_language: source
+                .var("SPELLCHECK_LANGUAGE")
+                .unwrap_or_else(|_| "en_US".to_string()),
+            crossref_url: source
+                .var("CROSSREF_API_URL")
+                .unwrap_or_else(|_| "https://api.crossref.org".to_string()),
+            arxiv_url: source
+                .var("ARXIV_API_URL")
+                .unwrap_or_else(|_| "https://export.arxiv.org/api".to_string()),
+            crossref_mailto: source.var("CROSSREF_MAILTO").ok().filter(|v| !v.is_empty()),
+            zotero_url: source
+                .var("ZOTERO_API_URL")
+                .unwrap_or_else(|_| "https://api.zotero.org".to_string()),
+            mail: MailConfig::load(source),
+        }
+    }
+
+    /// Settings that are each valid but won't work, or aren't safe, as set.
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut one_of = |name: &str, value: &str, allowed: &[&str]| {
+            if !allowed.contains(&value) {
+                problems.push(format!(
+                    "{name}: '{value}' is not one of {}",
+                    allowed.join(", ")
+                ));
+            }
+        };
+        one_of("STORAGE_BACKEND", &self.storage_backend, &["local", "s3"]);
+        one_of("BACKUP_TARGET", &self.backup.target, &["local", "s3"]);
+        one_of(
+            "COMPILE_BACKEND",
+            &self.compile.backend,
+            &["latexmk", "tectonic"],
+        );
+        one_of(
+            "COMPILE_SANDBOX",
+            &self.compile.sandbox,
+            &["none", "bubblewrap"],
+        );
+        if let Some(mail) = &self.mail {
+            one_of(
+                "SMTP_SECURITY",
+                &mail.security,
+                &["starttls", "tls", "none"],
+            );
+        }
+
+        for origin in &self.cors.allowed_origins {
+            if origin == "*" {
+                if self.cors.allow_credentials {
+                    problems.push(
+                        "CORS_ALLOWED_ORIGINS: '*' cannot be combined with \
+                         CORS_ALLOW_CREDENTIALS; list the origins instead"
+                            .to_string(),
+                    );
+                }
+                continue;
+            }
+            // Browsers send the origin as scheme://host[:port] with nothing after
+            let host = origin
+                .strip_prefix("https://")
+                .or_else(|| origin.strip_prefix("http://"));
+            if !host.is_some_and(|host| !host.is_empty() && !host.contains('/'))
+                || axum::http::HeaderValue::from_str(origin).is_err()
+            {
+                problems.push(format!(
+                    "CORS_ALLOWED_ORIGINS: '{origin}' is not an origin like https://app.example.com"
+                ));
+            }
+        }
+        for name in &self.cors.allowed_headers {
+            if axum::http::HeaderName::from_bytes(name.as_bytes()).is_err() {
+                problems.push(format!(
+                    "CORS_ALLOWED_HEADERS: '{name}' is not a header name"
+                ));
+            }
+        }
+
+        // Emails were once accepted here; refuse them rather than quietly
+        // stop trusting those projects
+        for entry in &self.compile.shell_escape_allowlist {
+            if entry.contains('@') {
+                problems.push(format!(
+                    "COMPILE_SHELL_ESCAPE_ALLOWLIST: '{entry}' is an email; list the owner's user ID instead"
+                ));
+            }
+        }
+
+        if self.s3.is_none() && (self.storage_backend == "s3" || self.backup.target == "s3") {
+            problems
+                .push("S3_BUCKET is required when storing projects or backups in S3".to_string());
+        }
+        if !self.dev_mode && INSECURE_JWT_SECRETS.contains(&self.jwt_secret.as_str()) {
+            problems.push(
+                "JWT_SECRET is unset or a placeholder; set it to a long random string, \
+                 or set OPENLEAF_ENV=development for a local setup"
+                    .to_string(),
+            );
+        }
+        problems
+    }
+}
+
+/// Where settings come from: environment variables, then the TOML config
+/// file. The file uses the same names in lowercase, and a table stands for
+/// a shared prefix, so `[compile] timeout_secs` is COMPILE_TIMEOUT_SECS.
+struct Source {
+    path: Option<PathBuf>,
+    // File settings by variable name, with the key as written in the file
+    file: HashMap<String, (String, String)>,
+    // Names looked up, so settings in the file that mean nothing stand out
+    read: RefCell<HashSet<String>>,
+    problems: RefCell<Vec<String>>,
+}
+
+impl Source {
+    /// Load the given file, or openleaf.toml in the working directory if
+    /// there is one. Without either, settings come from the environment alone.
+    fn open(config_file: Option<&Path>) -> anyhow::Result<Self> {
+        let path = match config_file {
+            Some(path) => Some(path.to_path_buf()),
+            None => Some(PathBuf::from(DEFAULT_CONFIG_FILE)).filter(|path| path.is_file()),
+        };
+        let mut file = HashMap::new();
+        if let Some(path) = &path {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read config file {}", path.display()))?;
+            let table: toml::Table = text
+                .parse()
+                .with_context(|| format!("Failed to parse config file {}", path.display()))?;
+            flatten("", &table, &mut file);
+            tracing::info!("Loaded configuration from {}", path.display());
+        }
+        Ok(Self {
+            path,
+            file,
+            read: RefCell::default(),
+            problems: RefCell::default(),
+        })
+    }
+
+    /// The setting as text, like `env::var`.
+    fn var(&self, name: &str) -> Result<String, env::VarError> {
+        self.read.borrow_mut().insert(name.to_string());
+        match env::var(name) {
+            Err(env::VarError::NotPresent) => self
+                .file
+                .get(name)
+                .map(|(_, value)| value.clone())
+                .ok_or(env::VarError::NotPresent),
+            result => result,
         }
     }
+
+    /// The setting parsed, or None when unset or empty. A value that doesn't
+    /// parse is a problem rather than a quiet fallback to the default.
+    fn parse<T: FromStr>(&self, name: &str) -> Option<T> {
+        let value = self.var(name).ok()?;
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        match value.parse() {
+            Ok(parsed) => Some(parsed),
+            Err(_) => {
+                self.problem(format!("{name}: '{value}' is not a valid value"));
+                None
+            }
+        }
+    }
+
+    /// A true/false setting; unset is false.
+    fn flag(&self, name: &str) -> bool {
+        let Ok(value) = self.var(name) else {
+            return false;
+        };
+        match value.trim().to_ascii_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => true,
+            "" | "0" | "false" | "no" | "off" => false,
+            _ => {
+                self.problem(format!("{name}: '{value}' is not true or false"));
+                false
+            }
+        }
+    }
+
+    fn problem(&self, message: String) {
+        self.problems.borrow_mut().push(message);
+    }
+
+    /// The problems found while reading, including settings in the file that
+    /// nothing reads, which are usually typos.
+    fn finish(self) -> Vec<String> {
+        let read = self.read.into_inner();
+        let mut problems = self.problems.into_inner();
+        let mut unknown: Vec<&String> = self
+            .file
+            .iter()
+            .filter(|(name, _)| !read.contains(*name))
+            .map(|(_, (key, _))| key)
+            .collect();
+        unknown.sort();
+        if let Some(path) = &self.path {
+            problems.extend(
+                unknown
+                    .into_iter()
+                    .map(|key| format!("{}: unknown setting '{key}'", path.display())),
+            );
+        }
+        problems
+    }
+}
+
+/// Flatten a TOML table into variable names and text values. Arrays become
+/// comma-separated lists, as the list settings are written in the environment.
+fn flatten(prefix: &str, table: &toml::Table, out: &mut HashMap<String, (String, String)>) {
+    for (key, value) in table {
+        let key = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{prefix}.{key}")
+        };
+        let text = match value {
+            toml::Value::Table(table) => {
+                flatten(&key, table, out);
+                continue;
+            }
+            toml::Value::String(text) => text.clone(),
+            toml::Value::Array(items) => items
+                .iter()
+                .map(|item| match item {
+                    toml::Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(","),
+            other => other.to_string(),
+        };
+        out.insert(key.replace('.', "_").to_uppercase(), (key, text));
+    }
 }
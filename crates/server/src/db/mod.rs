@@ -1,32 +1,92 @@
 pub mod models;
 
-use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
+use crate::config::DatabaseConfig;
+
+// The backend is chosen at build time: SQLite by default, PostgreSQL with the
+// `postgres` feature. Queries use $1-style placeholders, which both accept.
+#[cfg(not(feature = "postgres"))]
+pub type Db = sqlx::Sqlite;
+#[cfg(feature = "postgres")]
+pub type Db = sqlx::Postgres;
+
+pub type DbPool = sqlx::Pool<Db>;
+pub type DbConnection = <Db as sqlx::Database>::Connection;
+
+/// SQL for the current time, formatted like the RFC 3339 timestamps stored
+/// as text so the two compare in order.
+#[cfg(not(feature = "postgres"))]
+macro_rules! sql_now {
+    () => {
+        "strftime('%Y-%m-%dT%H:%M:%SZ', 'now')"
+    };
+}
+#[cfg(feature = "postgres")]
+macro_rules! sql_now {
+    () => {
+        "to_char(now() AT TIME ZONE 'UTC', 'YYYY-MM-DD\"T\"HH24:MI:SS\"Z\"')"
+    };
+}
+pub(crate) use sql_now;
 
 #[derive(Clone)]
 pub struct Database {
-    pub pool: SqlitePool,
+    pub pool: DbPool,
 }
 
 impl Database {
-    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+    #[cfg(not(feature = "postgres"))]
+    pub async fn connect(config: &DatabaseConfig) -> anyhow::Result<Self> {
+        use std::str::FromStr;
+
+        use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqliteSynchronous};
+
         // Ensure the data directory exists
-        if let Some(path) = url.strip_prefix("sqlite:") {
+        if let Some(path) = config.url.strip_prefix("sqlite:") {
             let path = path.split('?').next().unwrap_or(path);
             if let Some(parent) = std::path::Path::new(path).parent() {
                 std::fs::create_dir_all(parent)?;
             }
         }
 
-        let pool = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect(url)
+        // WAL lets readers carry on while a save is written, and the busy
+        // timeout makes concurrent writers queue instead of failing
+        let options = SqliteConnectOptions::from_str(&config.url)?
+            .journal_mode(SqliteJournalMode::Wal)
+            .synchronous(SqliteSynchronous::Normal)
+            .foreign_keys(true)
+            .busy_timeout(std::time::Duration::from_secs(config.busy_timeout_secs));
+
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(config.max_connections.unwrap_or(5))
+            .connect_with(options)
+            .await?;
+
+        Ok(Self { pool })
+    }
+
+    #[cfg(feature = "postgres")]
+    pub async fn connect(config: &DatabaseConfig) -> anyhow::Result<Self> {
+        // Postgres takes concurrent writers, so more connections pay off
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .max_connections(config.max_connections.unwrap_or(20))
+            .connect(&config.url)
             .await?;
 
         Ok(Self { pool })
     }
 
+    #[cfg(not(feature = "postgres"))]
     pub async fn run_migrations(&self) -> anyhow::Result<()> {
         sqlx::migrate!("./migrations").run(&self.pool).await?;
         Ok(())
     }
+
+    // Postgres has its own copy of every migration, with the same versions
+    #[cfg(feature = "postgres")]
+    pub async fn run_migrations(&self) -> anyhow::Result<()> {
+        sqlx::migrate!("./migrations/postgres")
+            .run(&self.pool)
+            .await?;
+        Ok(())
+    }
 }
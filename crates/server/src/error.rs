@@ -19,6 +19,9 @@ pub enum AppError {
     #[error("Forbidden: {0}")]
     Forbidden(String),
 
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 
@@ -27,6 +30,16 @@ pub enum AppError {
 
     #[error("Validation error: {0}")]
     Validation(String),
+
+    #[error("Service unavailable: {0}")]
+    ServiceUnavailable(String),
+
+    #[error("Too many requests: {message}")]
+    TooManyRequests {
+        message: String,
+        /// Seconds until trying again is likely to succeed, when known
+        retry_after: Option<u64>,
+    },
 }
 
 impl IntoResponse for AppError {
@@ -36,7 +49,11 @@ impl IntoResponse for AppError {
             AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
             AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
             AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg.clone()),
-            AppError::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg.clone()),
+            AppError::Conflict(msg) => (StatusCode::CONFLICT, msg.clone()),
+            AppError::Internal(msg) => {
+                tracing::error!("Internal error: {}", msg);
+                (StatusCode::INTERNAL_SERVER_ERROR, msg.clone())
+            }
             AppError::Database(e) => {
                 tracing::error!("Database error: {}", e);
                 (
@@ -45,13 +62,31 @@ impl IntoResponse for AppError {
                 )
             }
             AppError::Validation(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
+            AppError::ServiceUnavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, msg.clone()),
+            AppError::TooManyRequests { message, .. } => {
+                (StatusCode::TOO_MANY_REQUESTS, message.clone())
+            }
         };
 
-        let body = Json(json!({
+        let mut body = json!({
             "error": message
-        }));
+        });
+
+        if let AppError::TooManyRequests {
+            retry_after: Some(secs),
+            ..
+        } = &self
+        {
+            body["retry_after"] = json!(secs);
+            return (
+                status,
+                [(axum::http::header::RETRY_AFTER, secs.to_string())],
+                Json(body),
+            )
+                .into_response();
+        }
 
-        (status, body).into_response()
+        (status, Json(body)).into_response()
     }
 }
 
@@ -1,113 +1,859 @@
-// WebSocket handler for real-time collaboration
-// Using a simple message relay approach
+// WebSocket handlers for real-time collaboration
+// Editors speak the y-websocket protocol against the room's authoritative
+// document, either with one socket per open file or with one socket carrying
+// every open file of a project; one socket per project pushes events as JSON
+// text, and one per user pushes their notifications.
+// Browsers cannot set headers on websocket requests, so the session token
+// comes in the query string.
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use axum::{
     extract::{
-        ws::{Message, WebSocket},
+        ws::{close_code, CloseFrame, Message, WebSocket},
         Query, State, WebSocketUpgrade,
     },
     response::Response,
 };
-use futures::{SinkExt, StreamExt};
+use futures::{stream::SplitSink, SinkExt, StreamExt};
 use serde::Deserialize;
-use tokio::sync::{broadcast, RwLock};
+use tokio::sync::{broadcast::error::RecvError, mpsc};
+use tokio::task::JoinHandle;
 
-use crate::AppState;
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::{session_user, AuthUser},
+    services::{
+        accounts, chat, collab,
+        events::{ClientMessage, ProjectEvent},
+    },
+    AppState,
+};
 
-// Room state for broadcasting messages
-pub struct RoomState {
-    pub broadcast: broadcast::Sender<Vec<u8>>,
-}
+// Chat and follow messages are small; nothing on the project channel needs
+// more than this
+const PROJECT_MESSAGE_LIMIT: usize = 64 * 1024;
 
-impl RoomState {
-    pub fn new() -> Self {
-        let (broadcast, _) = broadcast::channel(256);
-        Self { broadcast }
-    }
-}
+// A client that cannot take a message for this long is treated as gone
+const SEND_TIMEOUT: Duration = Duration::from_secs(10);
 
-impl Default for RoomState {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-// Global room registry - keyed by "project_id:file_path"
-pub type DocumentRegistry = Arc<RwLock<HashMap<String, Arc<RoomState>>>>;
+// Messages queued for a socket before the rooms feeding it have to wait
+const OUTBOX_CAPACITY: usize = 64;
 
-pub fn create_document_registry() -> DocumentRegistry {
-    Arc::new(RwLock::new(HashMap::new()))
-}
+// Documents one multiplexed socket may have open at once
+const MAX_SUBSCRIPTIONS: usize = 64;
 
 #[derive(Debug, Deserialize)]
-#[allow(dead_code)]
 pub struct WsQuery {
     pub token: Option<String>,
     pub project_id: String,
     pub file_path: String,
+    /// Session token from a previous connection to the same file
+    pub resume: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProjectWsQuery {
+    pub token: Option<String>,
+    pub project_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationsWsQuery {
+    pub token: Option<String>,
+}
+
+async fn authenticate(state: &AppState, token: Option<&str>) -> Result<AuthUser> {
+    let token = token.ok_or(AppError::Unauthorized)?;
+    let user = session_user(state, token, None)
+        .await?
+        .ok_or(AppError::Unauthorized)?;
+    if !accounts::is_active(&state.db.pool, &user.id).await? {
+        return Err(AppError::Unauthorized);
+    }
+    Ok(user)
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
 }
 
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     Query(query): Query<WsQuery>,
     State(state): State<AppState>,
-) -> Response {
-    let doc_key = format!("{}:{}", query.project_id, query.file_path);
-    ws.on_upgrade(move |socket| handle_socket(socket, doc_key, state))
+) -> Result<Response> {
+    let user = authenticate(&state, query.token.as_deref()).await?;
+
+    check_project_access(&state.db.pool, &query.project_id, &user.id).await?;
+
+    Ok(ws
+        .max_message_size(state.config.ws.max_message_bytes)
+        .max_frame_size(state.config.ws.max_message_bytes)
+        .on_upgrade(move |socket| handle_socket(socket, query, user, state)))
+}
+
+pub async fn project_ws_handler(
+    ws: WebSocketUpgrade,
+    Query(query): Query<ProjectWsQuery>,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    let user = authenticate(&state, query.token.as_deref()).await?;
+
+    check_project_access(&state.db.pool, &query.project_id, &user.id).await?;
+
+    Ok(ws
+        .max_message_size(PROJECT_MESSAGE_LIMIT)
+        .max_frame_size(PROJECT_MESSAGE_LIMIT)
+        .on_upgrade(move |socket| handle_project_socket(socket, query.project_id, user, state)))
 }
 
-async fn handle_socket(socket: WebSocket, doc_key: String, state: AppState) {
-    let (sender, mut receiver) = socket.split();
+async fn handle_project_socket(
+    socket: WebSocket,
+    project_id: String,
+    user: AuthUser,
+    state: AppState,
+) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut events = state.events.subscribe(&project_id);
+    // The user this connection follows, if any
+    let mut following: Option<String> = None;
+    let mut heartbeat = Heartbeat::new(&state);
+    let mut rate_limit = RateLimit::new(&state);
+
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                if heartbeat.expired() {
+                    tracing::debug!("Project channel of {} timed out", user.id);
+                    let _ = sender.send(Heartbeat::close()).await;
+                    break;
+                }
+                if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+            event = events.recv() => {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(_)) => ProjectEvent::Resync,
+                    Err(RecvError::Closed) => break,
+                };
+                if let ProjectEvent::FollowUpdate { user_id, .. } = &event {
+                    if following.as_ref() != Some(user_id) {
+                        continue;
+                    }
+                }
+                let removed = matches!(
+                    &event,
+                    ProjectEvent::CollaboratorRemoved { user_id } if *user_id == user.id
+                );
+                let Ok(text) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                if !matches!(
+                    tokio::time::timeout(SEND_TIMEOUT, sender.send(Message::Text(text))).await,
+                    Ok(Ok(()))
+                ) {
+                    break;
+                }
+                // Lost access to the project; tell them, then hang up
+                if removed {
+                    break;
+                }
+            }
+            message = receiver.next() => {
+                let message = match message {
+                    Some(Ok(message)) => message,
+                    Some(Err(_)) | None => break,
+                };
+                heartbeat.seen();
+                rate_limit.acquire().await;
 
-    // Get or create room
-    let room = {
-        let mut registry = state.docs.write().await;
-        if !registry.contains_key(&doc_key) {
-            registry.insert(doc_key.clone(), Arc::new(RoomState::new()));
+                match message {
+                    Message::Ping(data) => {
+                        let _ = sender.send(Message::Pong(data)).await;
+                    }
+                    Message::Text(text) => {
+                        let result = match serde_json::from_str::<ClientMessage>(&text) {
+                            Ok(message) => {
+                                handle_client_message(&state, &project_id, &user, &mut following, message)
+                                    .await
+                            }
+                            Err(e) => Err(AppError::BadRequest(format!("Invalid message: {e}"))),
+                        };
+                        // Problems with a message only concern whoever sent it
+                        if let Err(e) = result {
+                            let error = serde_json::json!({ "type": "error", "message": e.to_string() });
+                            if sender.send(Message::Text(error.to_string())).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                    Message::Close(_) => break,
+                    _ => {}
+                }
+            }
         }
-        registry.get(&doc_key).unwrap().clone()
-    };
+    }
 
-    // Subscribe to room broadcasts
-    let mut broadcast_rx = room.broadcast.subscribe();
+    stop_following(&state, &project_id, &user, &mut following);
+}
 
-    // Sender wrapped in Arc<Mutex> for sharing
-    let sender = Arc::new(tokio::sync::Mutex::new(sender));
-    let sender_clone = sender.clone();
-    let room_clone = room.clone();
+pub async fn notifications_ws_handler(
+    ws: WebSocketUpgrade,
+    Query(query): Query<NotificationsWsQuery>,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    let user = authenticate(&state, query.token.as_deref()).await?;
 
-    // Task to forward broadcast messages to this client
-    let broadcast_task = tokio::spawn(async move {
-        while let Ok(data) = broadcast_rx.recv().await {
-            let mut sender = sender_clone.lock().await;
-            if sender.send(Message::Binary(data)).await.is_err() {
-                break;
+    Ok(ws
+        .max_message_size(PROJECT_MESSAGE_LIMIT)
+        .max_frame_size(PROJECT_MESSAGE_LIMIT)
+        .on_upgrade(move |socket| handle_notifications_socket(socket, user, state)))
+}
+
+/// Push the user's new notifications; clients only ever send pings.
+async fn handle_notifications_socket(socket: WebSocket, user: AuthUser, state: AppState) {
+    let (mut sender, mut receiver) = socket.split();
+    let mut notifications = state.notifications.subscribe(&user.id);
+    let mut heartbeat = Heartbeat::new(&state);
+    let mut rate_limit = RateLimit::new(&state);
+
+    loop {
+        tokio::select! {
+            _ = heartbeat.tick() => {
+                if heartbeat.expired() {
+                    let _ = sender.send(Heartbeat::close()).await;
+                    break;
+                }
+                if sender.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+            notification = notifications.recv() => {
+                let message = match notification {
+                    Ok(notification) => {
+                        serde_json::json!({ "type": "notification", "notification": notification })
+                    }
+                    // Missed some; the client refetches the list
+                    Err(RecvError::Lagged(_)) => serde_json::json!({ "type": "resync" }),
+                    Err(RecvError::Closed) => break,
+                };
+                if !matches!(
+                    tokio::time::timeout(SEND_TIMEOUT, sender.send(Message::Text(message.to_string()))).await,
+                    Ok(Ok(()))
+                ) {
+                    break;
+                }
+            }
+            message = receiver.next() => {
+                let message = match message {
+                    Some(Ok(message)) => message,
+                    Some(Err(_)) | None => break,
+                };
+                heartbeat.seen();
+                rate_limit.acquire().await;
+
+                match message {
+                    Message::Ping(data) => {
+                        let _ = sender.send(Message::Pong(data)).await;
+                    }
+                    Message::Close(_) => break,
+                    _ => {}
+                }
             }
         }
-    });
+    }
+}
+
+async fn handle_client_message(
+    state: &AppState,
+    project_id: &str,
+    user: &AuthUser,
+    following: &mut Option<String>,
+    message: ClientMessage,
+) -> Result<()> {
+    match message {
+        ClientMessage::Chat { content } => {
+            let message =
+                chat::post(&state.db.pool, project_id, &user.id, &user.name, &content).await?;
+            state
+                .events
+                .publish(project_id, ProjectEvent::ChatMessage(message));
+        }
+        ClientMessage::Follow { user_id } => {
+            if user_id == user.id || following.as_ref() == Some(&user_id) {
+                return Ok(());
+            }
+            stop_following(state, project_id, user, following);
+            state.events.follow(project_id, &user_id);
+            state.events.publish(
+                project_id,
+                ProjectEvent::FollowStarted {
+                    user_id: user_id.clone(),
+                    follower_id: user.id.clone(),
+                    follower_name: user.name.clone(),
+                },
+            );
+            *following = Some(user_id);
+        }
+        ClientMessage::Unfollow => stop_following(state, project_id, user, following),
+        ClientMessage::Viewport {
+            file_path,
+            cursor,
+            scroll,
+        } => {
+            if state.events.is_followed(project_id, &user.id) {
+                state.events.publish(
+                    project_id,
+                    ProjectEvent::FollowUpdate {
+                        user_id: user.id.clone(),
+                        file_path,
+                        cursor,
+                        scroll,
+                    },
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+fn stop_following(
+    state: &AppState,
+    project_id: &str,
+    user: &AuthUser,
+    following: &mut Option<String>,
+) {
+    if let Some(user_id) = following.take() {
+        state.events.unfollow(project_id, &user_id);
+        state.events.publish(
+            project_id,
+            ProjectEvent::FollowStopped {
+                user_id,
+                follower_id: user.id.clone(),
+            },
+        );
+    }
+}
+
+async fn handle_socket(socket: WebSocket, query: WsQuery, user: AuthUser, state: AppState) {
+    let (sink, mut receiver) = socket.split();
+    let (outbox, mut writer) = spawn_writer(sink);
+
+    let subscription = match Subscription::open(
+        &state,
+        &query.project_id,
+        &query.file_path,
+        query.resume.as_deref(),
+        &user,
+        outbox.clone(),
+        None,
+    )
+    .await
+    {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            tracing::debug!("Could not open {}: {}", query.file_path, e);
+            return;
+        }
+    };
+
+    let mut heartbeat = Heartbeat::new(&state);
+    let mut rate_limit = RateLimit::new(&state);
+    let mut status = state.system.subscribe();
+    loop {
+        let msg = tokio::select! {
+            message = receiver.next() => match message {
+                Some(Ok(message)) => message,
+                Some(Err(_)) | None => break,
+            },
+            _ = heartbeat.tick() => {
+                if heartbeat.expired() {
+                    tracing::debug!("Connection {} timed out", subscription.connection);
+                    let _ = outbox.send(Heartbeat::close()).await;
+                    break;
+                }
+                if outbox.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            changed = status.changed() => {
+                if changed.is_err() || status.borrow().maintenance {
+                    let _ = outbox.send(maintenance_close()).await;
+                    break;
+                }
+                continue;
+            }
+            _ = &mut writer => break,
+        };
+        heartbeat.seen();
+        rate_limit.acquire().await;
+
+        match msg {
+            Message::Binary(data) => {
+                let reply = match subscription.handle(&data) {
+                    Ok(Some(reply)) => Message::Binary(reply),
+                    Ok(None) => continue,
+                    Err(e) => {
+                        let error =
+                            serde_json::json!({ "type": "error", "message": e.to_string() });
+                        Message::Text(error.to_string())
+                    }
+                };
+                if outbox.send(reply).await.is_err() {
+                    break;
+                }
+            }
+            Message::Close(_) => break,
+            Message::Ping(data) => {
+                let _ = outbox.send(Message::Pong(data)).await;
+            }
+            _ => {}
+        }
+    }
+
+    subscription.close(&state, &user).await;
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DocumentsWsQuery {
+    pub token: Option<String>,
+    pub project_id: String,
+}
+
+/// Control messages of a multiplexed socket, sent as JSON text.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum DocumentControl {
+    Subscribe {
+        file_path: String,
+        /// Session token from an earlier subscription to the file
+        resume: Option<String>,
+    },
+    Unsubscribe {
+        file_path: String,
+    },
+}
+
+/// One socket for every file of a project the client has open. Binary
+/// messages in both directions are y-websocket messages prefixed with the
+/// file path as a lib0 string; subscribing and unsubscribing is done with
+/// JSON text messages.
+pub async fn documents_ws_handler(
+    ws: WebSocketUpgrade,
+    Query(query): Query<DocumentsWsQuery>,
+    State(state): State<AppState>,
+) -> Result<Response> {
+    let user = authenticate(&state, query.token.as_deref()).await?;
+
+    check_project_access(&state.db.pool, &query.project_id, &user.id).await?;
+
+    Ok(ws
+        .max_message_size(state.config.ws.max_message_bytes)
+        .max_frame_size(state.config.ws.max_message_bytes)
+        .on_upgrade(move |socket| handle_documents_socket(socket, query.project_id, user, state)))
+}
+
+async fn handle_documents_socket(
+    socket: WebSocket,
+    project_id: String,
+    user: AuthUser,
+    state: AppState,
+) {
+    let (sink, mut receiver) = socket.split();
+    let (outbox, mut writer) = spawn_writer(sink);
+    let mut subscriptions: HashMap<String, Subscription> = HashMap::new();
+
+    let mut heartbeat = Heartbeat::new(&state);
+    let mut rate_limit = RateLimit::new(&state);
+    let mut status = state.system.subscribe();
+    loop {
+        let msg = tokio::select! {
+            message = receiver.next() => match message {
+                Some(Ok(message)) => message,
+                Some(Err(_)) | None => break,
+            },
+            _ = heartbeat.tick() => {
+                if heartbeat.expired() {
+                    tracing::debug!("Document socket of {} timed out", user.id);
+                    let _ = outbox.send(Heartbeat::close()).await;
+                    break;
+                }
+                if outbox.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            changed = status.changed() => {
+                if changed.is_err() || status.borrow().maintenance {
+                    let _ = outbox.send(maintenance_close()).await;
+                    break;
+                }
+                continue;
+            }
+            _ = &mut writer => break,
+        };
+        heartbeat.seen();
+        rate_limit.acquire().await;
 
-    // Process incoming messages and broadcast to room
-    while let Some(Ok(msg)) = receiver.next().await {
         match msg {
             Message::Binary(data) => {
-                // Broadcast to all other clients in the room
-                let _ = room_clone.broadcast.send(data);
+                let Some((file_path, message)) = collab::decode_envelope(&data) else {
+                    tracing::debug!("Ignoring message without a document from {}", user.id);
+                    continue;
+                };
+                let Some(subscription) = subscriptions.get(file_path) else {
+                    continue;
+                };
+                let reply = match subscription.handle(message) {
+                    Ok(Some(reply)) => Message::Binary(collab::encode_envelope(file_path, &reply)),
+                    Ok(None) => continue,
+                    Err(e) => {
+                        let error = serde_json::json!({
+                            "type": "error",
+                            "file_path": file_path,
+                            "message": e.to_string(),
+                        });
+                        Message::Text(error.to_string())
+                    }
+                };
+                if outbox.send(reply).await.is_err() {
+                    break;
+                }
             }
             Message::Text(text) => {
-                // Also support text messages (JSON)
-                let _ = room_clone.broadcast.send(text.into_bytes());
+                let reply = match serde_json::from_str::<DocumentControl>(&text) {
+                    Ok(DocumentControl::Subscribe { file_path, resume }) => {
+                        subscribe(
+                            &state,
+                            &project_id,
+                            &user,
+                            &outbox,
+                            &mut subscriptions,
+                            file_path,
+                            resume,
+                        )
+                        .await
+                    }
+                    Ok(DocumentControl::Unsubscribe { file_path }) => {
+                        if let Some(subscription) = subscriptions.remove(&file_path) {
+                            subscription.close(&state, &user).await;
+                        }
+                        serde_json::json!({ "type": "unsubscribed", "file_path": file_path })
+                    }
+                    Err(e) => serde_json::json!({
+                        "type": "error",
+                        "message": format!("Invalid message: {e}"),
+                    }),
+                };
+                if outbox.send(Message::Text(reply.to_string())).await.is_err() {
+                    break;
+                }
             }
             Message::Close(_) => break,
             Message::Ping(data) => {
-                let mut sender = sender.lock().await;
-                let _ = sender.send(Message::Pong(data)).await;
+                let _ = outbox.send(Message::Pong(data)).await;
             }
             _ => {}
         }
     }
 
-    broadcast_task.abort();
+    for (_, subscription) in subscriptions {
+        subscription.close(&state, &user).await;
+    }
+}
+
+/// Open a document on a multiplexed socket, returning the reply for the
+/// client. The document's greeting is already queued when it is
+/// acknowledged.
+async fn subscribe(
+    state: &AppState,
+    project_id: &str,
+    user: &AuthUser,
+    outbox: &mpsc::Sender<Message>,
+    subscriptions: &mut HashMap<String, Subscription>,
+    file_path: String,
+    resume: Option<String>,
+) -> serde_json::Value {
+    if subscriptions.contains_key(&file_path) {
+        return serde_json::json!({ "type": "subscribed", "file_path": file_path });
+    }
+    if subscriptions.len() >= MAX_SUBSCRIPTIONS {
+        return serde_json::json!({
+            "type": "error",
+            "file_path": file_path,
+            "message": format!("At most {MAX_SUBSCRIPTIONS} documents can be open per connection"),
+        });
+    }
+
+    match Subscription::open(
+        state,
+        project_id,
+        &file_path,
+        resume.as_deref(),
+        user,
+        outbox.clone(),
+        Some(file_path.clone()),
+    )
+    .await
+    {
+        Ok(subscription) => {
+            subscriptions.insert(file_path.clone(), subscription);
+            serde_json::json!({ "type": "subscribed", "file_path": file_path })
+        }
+        Err(e) => serde_json::json!({
+            "type": "error",
+            "file_path": file_path,
+            "message": e.to_string(),
+        }),
+    }
+}
+
+/// Send everything queued for a socket from one task, so a client that reads
+/// slowly holds up its own queue and nothing else.
+fn spawn_writer(
+    mut sink: SplitSink<WebSocket, Message>,
+) -> (mpsc::Sender<Message>, JoinHandle<()>) {
+    let (outbox, mut queue) = mpsc::channel::<Message>(OUTBOX_CAPACITY);
+    let writer = tokio::spawn(async move {
+        while let Some(message) = queue.recv().await {
+            let close = matches!(message, Message::Close(_));
+            let sent = tokio::time::timeout(SEND_TIMEOUT, sink.send(message)).await;
+            if close || !matches!(sent, Ok(Ok(()))) {
+                break;
+            }
+        }
+    });
+    (outbox, writer)
+}
+
+/// A document open on a socket: the connection's place in the room and the
+/// task passing the room's broadcasts on to the client.
+struct Subscription {
+    room: Arc<collab::Room>,
+    connection: u64,
+    token: String,
+    forward: JoinHandle<()>,
+}
+
+impl Subscription {
+    /// Join the file's room and greet the client. On multiplexed sockets
+    /// messages are wrapped in an envelope naming `envelope`.
+    async fn open(
+        state: &AppState,
+        project_id: &str,
+        file_path: &str,
+        resume: Option<&str>,
+        user: &AuthUser,
+        outbox: mpsc::Sender<Message>,
+        envelope: Option<String>,
+    ) -> Result<Self> {
+        let connection = state.collab.connection_id();
+        let peer = collab::Peer {
+            user_id: user.id.clone(),
+            name: user.name.clone(),
+        };
+        let room = state
+            .collab
+            .join(project_id, file_path, connection, peer)
+            .await?;
+
+        // Subscribe before syncing so no update slips in between
+        let mut events = room.subscribe();
+        let wrap = move |message: Vec<u8>| match &envelope {
+            Some(name) => Message::Binary(collab::encode_envelope(name, &message)),
+            None => Message::Binary(message),
+        };
+
+        // A resumed client gets what it missed right away; everyone is asked
+        // for whatever the server is missing and shown who else is here
+        let resumed = resume.and_then(|token| state.collab.resume(token, &user.id, &room));
+        let token = state.collab.session_token();
+        let mut greeting = vec![collab::encode_session(&token, resumed.is_some())];
+        greeting.extend(resumed);
+        greeting.push(room.sync_step1());
+        greeting.extend(room.awareness());
+        for message in greeting {
+            if outbox.send(wrap(message)).await.is_err() {
+                state.collab.leave(&room, connection).await;
+                return Err(AppError::BadRequest("Connection closed".to_string()));
+            }
+        }
+
+        // Forward the rest of the room's messages to this client
+        let forward_room = room.clone();
+        let forward = tokio::spawn(async move {
+            loop {
+                let message = match events.recv().await {
+                    Ok(event) if event.from == connection => continue,
+                    Ok(event) => event.message.to_vec(),
+                    // Too slow to keep up and updates were dropped; catch it
+                    // up with the whole document instead of disconnecting it
+                    Err(RecvError::Lagged(skipped)) => {
+                        tracing::debug!(
+                            "Connection {} missed {} collaboration messages",
+                            connection,
+                            skipped
+                        );
+                        forward_room.full_update()
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                if outbox.send(wrap(message)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Self {
+            room,
+            connection,
+            token,
+            forward,
+        })
+    }
+
+    /// Handle a y-websocket message from the client, returning the reply.
+    /// Edits refused because the project is frozen come back as the error
+    /// for the client; other failures are only logged.
+    fn handle(&self, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some(message) = collab::Message::decode(data) else {
+            tracing::debug!(
+                "Ignoring malformed message from connection {}",
+                self.connection
+            );
+            return Ok(None);
+        };
+        match self.room.handle(self.connection, message) {
+            Ok(reply) => Ok(reply),
+            Err(e @ AppError::Conflict(_)) => Err(e),
+            Err(e) => {
+                tracing::debug!("Connection {}: {}", self.connection, e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Leave the room, keeping the session resumable for a while.
+    async fn close(self, state: &AppState, user: &AuthUser) {
+        self.forward.abort();
+        state.collab.suspend(self.token, &user.id, &self.room);
+        state.collab.leave(&self.room, self.connection).await;
+    }
+}
+
+/// Token bucket limiting how fast a connection's messages are handled. A
+/// client over the limit is not dropped; its messages just wait, and the
+/// socket stops being read until they may go through.
+struct RateLimit {
+    tokens: f64,
+    rate: f64,
+    burst: f64,
+    updated: Instant,
+}
+
+impl RateLimit {
+    fn new(state: &AppState) -> Self {
+        let burst = f64::from(state.config.ws.message_burst.max(1));
+        Self {
+            tokens: burst,
+            rate: f64::from(state.config.ws.message_rate),
+            burst,
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.burst);
+        self.updated = now;
+    }
+
+    async fn acquire(&mut self) {
+        if self.rate <= 0.0 {
+            return;
+        }
+        self.refill();
+        if self.tokens < 1.0 {
+            let wait = (1.0 - self.tokens) / self.rate;
+            tokio::time::sleep(Duration::from_secs_f64(wait)).await;
+            self.refill();
+        }
+        self.tokens -= 1.0;
+    }
+}
+
+/// Server-side pings, and the deadline for hearing back from the client.
+struct Heartbeat {
+    ticker: tokio::time::Interval,
+    last_seen: Instant,
+    timeout: Duration,
+}
+
+impl Heartbeat {
+    fn new(state: &AppState) -> Self {
+        let interval = Duration::from_secs(state.config.ws.ping_interval_secs);
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        Self {
+            ticker,
+            last_seen: Instant::now(),
+            timeout: Duration::from_secs(state.config.ws.idle_timeout_secs),
+        }
+    }
+
+    async fn tick(&mut self) {
+        self.ticker.tick().await;
+    }
+
+    /// Any frame, pongs included, shows the client is still there.
+    fn seen(&mut self) {
+        self.last_seen = Instant::now();
+    }
+
+    fn expired(&self) -> bool {
+        self.last_seen.elapsed() >= self.timeout
+    }
+
+    fn close() -> Message {
+        Message::Close(Some(CloseFrame {
+            code: close_code::AWAY,
+            reason: "Idle timeout".into(),
+        }))
+    }
+}
+
+/// Sent to editing sockets when maintenance starts. Clients keep their edits
+/// and sync them once they can reconnect.
+fn maintenance_close() -> Message {
+    Message::Close(Some(CloseFrame {
+        code: close_code::AGAIN,
+        reason: "Maintenance".into(),
+    }))
 }
@@ -8,14 +8,12 @@ use axum::{
     routing::get,
     Router,
 };
+use clap::Parser;
 use tower::util::ServiceExt;
-use tower_http::{
-    cors::{Any, CorsLayer},
-    services::ServeDir,
-    trace::TraceLayer,
-};
+use tower_http::{services::ServeDir, trace::TraceLayer};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+mod cli;
 mod config;
 mod db;
 mod error;
@@ -24,45 +22,227 @@ mod middleware;
 mod routes;
 mod services;
 
-use handlers::ws::{create_document_registry, DocumentRegistry};
-
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    // Initialize tracing
+    let cli = cli::Cli::parse();
+    let command = cli.command.unwrap_or(cli::Command::Serve);
+
+    // Initialize tracing; one-off commands only log problems by default.
+    // Warnings and errors are also kept for the admin API.
+    let error_log = services::error_log::ErrorLog::new();
+    let default_filter = match command {
+        cli::Command::Serve => "openleaf_server=debug,tower_http=debug",
+        _ => "openleaf_server=warn",
+    };
     tracing_subscriber::registry()
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "openleaf_server=debug,tower_http=debug".into()),
+                .unwrap_or_else(|_| default_filter.into()),
         )
         .with(tracing_subscriber::fmt::layer())
+        .with(error_log.clone())
         .init();
 
     // Load configuration
-    let config = config::Config::from_env();
+    let config = config::Config::load(cli.config.as_deref())?;
+
+    match command {
+        cli::Command::Serve => serve(config, error_log).await,
+        cli::Command::Migrate => cli::migrate(&config).await,
+        cli::Command::Admin {
+            command:
+                cli::AdminCommand::CreateUser {
+                    email,
+                    name,
+                    password,
+                    admin,
+                },
+        } => cli::create_user(&config, &email, &name, password, admin).await,
+        cli::Command::Backup { project_ids } => cli::backup(&config, &project_ids).await,
+        cli::Command::Doctor => cli::doctor(&config).await,
+    }
+}
 
+async fn serve(
+    config: config::Config,
+    error_log: services::error_log::ErrorLog,
+) -> anyhow::Result<()> {
     // Ensure storage directory exists
-    std::fs::create_dir_all(&config.storage_path)?;
+    let storage = services::storage::StorageService::from_config(&config)?;
+    storage.init().await?;
 
     // Initialize database
-    let db = db::Database::connect(&config.database_url).await?;
+    let db = db::Database::connect(&config.database).await?;
     db.run_migrations().await?;
 
-    // Create document registry for real-time collaboration
-    let docs = create_document_registry();
+    // Maintenance mode and announcements, refreshed for changes made by
+    // other processes
+    let system = services::system_status::SystemStatusService::load(db.clone()).await?;
+    services::system_status::spawn_refresh(system.clone());
+
+    // Detect types of files stored before detection existed
+    {
+        let db = db.clone();
+        let storage = storage.clone();
+        tokio::spawn(async move {
+            match services::filetype::backfill(&db, &storage).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Detected types for {} existing files", count),
+                Err(e) => tracing::warn!("File type backfill failed: {}", e),
+            }
+        });
+    }
+
+    // Pick up edits made directly in the storage directory
+    if config.watch_storage && storage.is_local() {
+        services::reconcile::spawn_watcher(db.clone(), storage.clone())?;
+    }
+
+    // Sweep storage left behind by interrupted deletes
+    if config.gc_interval_hours > 0 {
+        services::gc::spawn_scheduler(
+            db.clone(),
+            storage.clone(),
+            std::time::Duration::from_secs(config.gc_interval_hours * 3600),
+            config.gc_delete,
+        );
+    }
+
+    // Periodic project backups
+    let backups = services::backup::BackupService::from_config(&config)?;
+    if config.backup.interval_hours > 0 {
+        services::backup::spawn_scheduler(
+            db.clone(),
+            storage.clone(),
+            backups.clone(),
+            std::time::Duration::from_secs(config.backup.interval_hours * 3600),
+        );
+    }
+
+    let git = services::git::GitService::from_config(&config)?;
+
+    // Discard abandoned resumable uploads
+    services::uploads::spawn_cleanup(db.clone(), std::path::PathBuf::from(&config.upload_path));
+
+    // Write collaboratively edited documents back to storage
+    let collab = services::collab::CollabService::new(
+        db.clone(),
+        storage.clone(),
+        std::time::Duration::from_secs(config.ws.resume_window_secs),
+    );
+    services::collab::spawn_scheduler(
+        collab.clone(),
+        std::time::Duration::from_secs(config.collab_save_interval_secs),
+        std::time::Duration::from_secs(config.collab_room_ttl_secs),
+    );
+
+    // Commit git-backed projects that changed
+    if config.git.auto_commit_minutes > 0 {
+        services::git::spawn_scheduler(
+            git.clone(),
+            db.clone(),
+            storage.clone(),
+            collab.clone(),
+            std::time::Duration::from_secs(config.git.auto_commit_minutes * 60),
+        );
+    }
+
+    // Sync projects linked to WebDAV folders
+    let events = services::events::ProjectEvents::new();
+    let webdav = services::webdav::WebDavService::from_config(&config)?;
+    if config.webdav_sync_minutes > 0 {
+        services::webdav::spawn_scheduler(
+            webdav.clone(),
+            db.clone(),
+            storage.clone(),
+            collab.clone(),
+            events.clone(),
+            std::time::Duration::from_secs(config.webdav_sync_minutes * 60),
+        );
+    }
+
+    // Templates operators put on disk
+    let templates = services::templates::TemplateLibrary::new(config.templates_path.as_deref());
+    if let Err(e) = templates.reload().await {
+        tracing::warn!("Failed to load templates: {}", e);
+    }
+
+    let mailer = config.mail.clone().map(services::mailer::Mailer::spawn);
+    let notifications = services::notifications::NotificationService::new(db.clone(), mailer);
+    if let Some(mail) = &config.mail {
+        services::notifications::spawn_digest_scheduler(notifications.clone(), mail.digest_hour);
+    }
 
     // Build application state
     let state = AppState {
         db,
         config: config.clone(),
-        docs,
+        collab,
+        events,
+        storage,
+        backups,
+        git,
+        webdav,
+        compile_jobs: services::compile_jobs::CompileJobs::new(services::compile_jobs::JobLimits {
+            max_running: config.compile.max_concurrent,
+            max_running_per_user: config.compile.user_concurrent,
+            max_queued_per_user: config.compile.user_queue,
+        }),
+        metrics: services::metrics::Metrics::new(),
+        rate_limits: services::rate_limit::RateLimiter::new(config.rate_limit.clone()),
+        error_log,
+        system,
+        symbols: services::symbols::SymbolIndex::new(),
+        notifications,
+        templates,
     };
 
+    services::rate_limit::spawn_cleanup(state.rate_limits.clone());
+
+    // Kept for shutdown, after the state has moved into the router
+    let collab = state.collab.clone();
+    let compile_jobs = state.compile_jobs.clone();
+
     // Build protected routes (require authentication)
     let protected_routes = Router::new()
-        .nest("/projects", routes::projects::router())
-        .nest("/files", routes::files::router())
+        .nest(
+            "/projects",
+            routes::projects::router()
+                .merge(routes::spellcheck::router())
+                .merge(routes::bib_import::router())
+                .merge(routes::symbols::router())
+                .merge(routes::figures::router())
+                .merge(routes::digest::router())
+                .merge(routes::presence::router())
+                .merge(routes::chat::router())
+                .merge(routes::versions::router())
+                .merge(routes::git::router())
+                .merge(routes::webdav::router())
+                .merge(routes::export::router())
+                .merge(routes::latexdiff::router()),
+        )
+        .nest(
+            "/files",
+            routes::files::router()
+                .merge(routes::uploads::router())
+                .merge(routes::bibtex::router())
+                .merge(routes::history::router()),
+        )
         .nest("/compile", routes::compile::router())
         .nest("/comments", routes::comments::router())
+        .nest("/changes", routes::track_changes::router())
+        .nest("/notifications", routes::notifications::router())
+        .nest("/search", routes::search::router())
+        .nest("/templates", routes::templates::router())
+        .nest("/users/me/sessions", routes::sessions::router())
+        .nest("/admin", routes::admin::router())
+        .nest("/zotero", routes::zotero::router())
+        .nest("/git/credentials", routes::git_credentials::router())
+        .nest("/tokens", routes::api_tokens::router())
+        .route_layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            middleware::rate_limit::rate_limit,
+        ))
         .route_layer(axum_middleware::from_fn_with_state(
             state.clone(),
             middleware::auth::auth_middleware,
@@ -70,36 +250,137 @@ async fn main() -> anyhow::Result<()> {
 
     // Build API router
     let api_router = Router::new()
-        .nest("/auth", routes::auth::router())
+        .nest(
+            "/auth",
+            routes::auth::router().route_layer(axum_middleware::from_fn_with_state(
+                state.clone(),
+                middleware::rate_limit::rate_limit,
+            )),
+        )
+        .nest("/system", routes::system::router())
+        .nest("/users", routes::users::router())
+        .merge(routes::openapi::router())
         .merge(protected_routes);
 
     // Build main router with SPA fallback
     let app = Router::new()
-        .route("/health", get(health_check))
+        .merge(routes::health::router())
         .route("/ws", get(handlers::ws::ws_handler))
+        .route("/ws/documents", get(handlers::ws::documents_ws_handler))
+        .route("/ws/project", get(handlers::ws::project_ws_handler))
+        .route(
+            "/ws/notifications",
+            get(handlers::ws::notifications_ws_handler),
+        )
+        .merge(routes::git_http::router())
+        .merge(routes::metrics::router())
         .nest("/api", api_router)
         .fallback(serve_spa)
+        .layer(axum_middleware::from_fn_with_state(
+            state.clone(),
+            middleware::maintenance::read_only,
+        ))
         .with_state(state)
         .layer(TraceLayer::new_for_http())
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Any)
-                .allow_headers(Any),
-        );
+        .layer(middleware::cors::layer(&config.cors));
 
     // Start server
+    let tls = config
+        .tls
+        .as_ref()
+        .map(services::tls::acceptor)
+        .transpose()?;
     let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
-    tracing::info!("Starting server on {}", addr);
+    tracing::info!(
+        "Starting server on {} ({})",
+        addr,
+        if tls.is_some() { "HTTPS" } else { "HTTP" }
+    );
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    let (stop, mut stopped) = tokio::sync::watch::channel(());
+    let mut server = tokio::spawn(async move {
+        let stopped = async move {
+            let _ = stopped.changed().await;
+        };
+        match tls {
+            Some(tls) => services::tls::serve(listener, app, tls, stopped).await,
+            None => {
+                axum::serve(
+                    listener,
+                    app.into_make_service_with_connect_info::<SocketAddr>(),
+                )
+                .with_graceful_shutdown(stopped)
+                .await
+            }
+        }
+    });
+
+    tokio::select! {
+        result = &mut server => return Ok(result??),
+        () = shutdown_signal() => {}
+    }
+
+    // Stop accepting connections and let requests in progress finish, then
+    // the compiles running in the background, then save open documents
+    let timeout = std::time::Duration::from_secs(config.shutdown_timeout_secs);
+    tracing::info!(
+        "Shutting down; waiting up to {}s for work in progress",
+        timeout.as_secs()
+    );
+    let deadline = tokio::time::Instant::now() + timeout;
+    let _ = stop.send(());
+    if tokio::time::timeout_at(deadline, server).await.is_err() {
+        tracing::warn!(
+            "Closing connections still open after {}s",
+            timeout.as_secs()
+        );
+    }
+    if tokio::time::timeout_at(deadline, compile_jobs.drained())
+        .await
+        .is_err()
+    {
+        let (running, queued) = compile_jobs.load();
+        tracing::warn!(
+            "Abandoning {} running and {} queued compiles",
+            running,
+            queued
+        );
+    }
+    let saved = collab.persist_all().await;
+    tracing::info!("Saved {} collaborative documents; exiting", saved);
 
     Ok(())
 }
 
-async fn health_check() -> &'static str {
-    "OK"
+/// Resolves on Ctrl-C, or SIGTERM from a container runtime or service manager.
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = interrupt => {}
+        () = terminate => {}
+    }
 }
 
 async fn serve_spa(req: Request<Body>) -> Response {
@@ -131,5 +412,18 @@ async fn serve_spa(req: Request<Body>) -> Response {
 pub struct AppState {
     pub db: db::Database,
     pub config: config::Config,
-    pub docs: DocumentRegistry,
+    pub collab: services::collab::CollabService,
+    pub events: services::events::ProjectEvents,
+    pub storage: services::storage::StorageService,
+    pub backups: services::backup::BackupService,
+    pub git: services::git::GitService,
+    pub webdav: services::webdav::WebDavService,
+    pub compile_jobs: services::compile_jobs::CompileJobs,
+    pub metrics: services::metrics::Metrics,
+    pub rate_limits: services::rate_limit::RateLimiter,
+    pub error_log: services::error_log::ErrorLog,
+    pub system: services::system_status::SystemStatusService,
+    pub symbols: services::symbols::SymbolIndex,
+    pub notifications: services::notifications::NotificationService,
+    pub templates: services::templates::TemplateLibrary,
 }
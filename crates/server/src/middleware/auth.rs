@@ -1,13 +1,21 @@
+use std::{convert::Infallible, net::IpAddr};
+
 use axum::{
     async_trait,
     extract::{FromRequestParts, Request, State},
-    http::{request::Parts, StatusCode},
+    http::{header, request::Parts, StatusCode},
     middleware::Next,
     response::Response,
 };
 use jsonwebtoken::{decode, DecodingKey, Validation};
 
-use crate::{routes::auth::Claims, AppState};
+use crate::{
+    error::AppError,
+    middleware::rate_limit::client_ip,
+    routes::auth::Claims,
+    services::{accounts, api_tokens, sessions},
+    AppState,
+};
 
 #[derive(Clone, Debug)]
 #[allow(dead_code)]
@@ -15,6 +23,8 @@ pub struct AuthUser {
     pub id: String,
     pub email: String,
     pub name: String,
+    /// The sign-in behind a session token; None for API tokens
+    pub session_id: Option<String>,
 }
 
 pub async fn auth_middleware(
@@ -33,24 +43,60 @@ pub async fn auth_middleware(
         None => return Err(StatusCode::UNAUTHORIZED),
     };
 
-    let token_data = decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(state.config.jwt_secret.as_bytes()),
-        &Validation::default(),
-    )
-    .map_err(|_| StatusCode::UNAUTHORIZED)?;
+    let user = if token.starts_with(api_tokens::PREFIX) {
+        api_tokens::authenticate(&state.db.pool, token).await
+    } else {
+        let ip = client_ip(
+            request.headers(),
+            request.extensions(),
+            state.rate_limits.trust_proxy_headers(),
+        );
+        session_user(&state, token, ip).await
+    }
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .ok_or(StatusCode::UNAUTHORIZED)?;
 
-    let user = AuthUser {
-        id: token_data.claims.sub,
-        email: token_data.claims.email,
-        name: token_data.claims.name,
-    };
+    // Sessions of disabled accounts end at once, not when they expire
+    let active = accounts::is_active(&state.db.pool, &user.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if !active {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
 
     request.extensions_mut().insert(user);
 
     Ok(next.run(request).await)
 }
 
+// The user a session token belongs to, if it is validly signed and its
+// session has not been signed out
+pub async fn session_user(
+    state: &AppState,
+    token: &str,
+    ip: Option<IpAddr>,
+) -> Result<Option<AuthUser>, AppError> {
+    let Ok(token_data) = decode::<Claims>(
+        token,
+        &DecodingKey::from_secret(state.config.jwt_secret.as_bytes()),
+        &Validation::default(),
+    ) else {
+        return Ok(None);
+    };
+    let claims = token_data.claims;
+
+    let ip = ip.map(|ip| ip.to_string());
+    if !sessions::check(&state.db.pool, &claims.sid, &claims.sub, ip).await? {
+        return Ok(None);
+    }
+    Ok(Some(AuthUser {
+        id: claims.sub,
+        email: claims.email,
+        name: claims.name,
+        session_id: Some(claims.sid),
+    }))
+}
+
 // Extractor for getting the authenticated user from request extensions
 #[async_trait]
 impl<S> FromRequestParts<S> for AuthUser
@@ -67,3 +113,64 @@ where
             .ok_or(StatusCode::UNAUTHORIZED)
     }
 }
+
+// Extractor for routes restricted to server administrators
+#[derive(Clone, Debug)]
+pub struct AdminUser(pub AuthUser);
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = parts
+            .extensions
+            .get::<AuthUser>()
+            .cloned()
+            .ok_or(AppError::Unauthorized)?;
+
+        let is_admin = sqlx::query_scalar::<_, bool>("SELECT is_admin FROM users WHERE id = $1")
+            .bind(&user.id)
+            .fetch_optional(&state.db.pool)
+            .await?
+            .unwrap_or(false);
+
+        if !is_admin {
+            return Err(AppError::Forbidden("Admin access required".to_string()));
+        }
+        Ok(Self(user))
+    }
+}
+
+// Extractor for where a request comes from, recorded with new sessions
+#[derive(Clone, Debug)]
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        Ok(Self {
+            ip: client_ip(
+                &parts.headers,
+                &parts.extensions,
+                state.rate_limits.trust_proxy_headers(),
+            ),
+            user_agent: parts
+                .headers
+                .get(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        })
+    }
+}
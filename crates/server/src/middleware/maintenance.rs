@@ -0,0 +1,44 @@
+use axum::{
+    extract::{Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{error::AppError, AppState};
+
+/// Refuse changes while the instance is in maintenance mode. Reading goes
+/// on, people can still sign in, and admins can still reach the admin API
+/// to run backups and end maintenance.
+pub async fn read_only(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    if state.system.is_read_only() && is_change(request.method(), request.uri().path()) {
+        let message = state
+            .system
+            .current()
+            .maintenance_message
+            .unwrap_or_else(|| {
+                "OpenLeaf is in maintenance mode; changes are disabled for now".to_string()
+            });
+        return Err(AppError::ServiceUnavailable(message));
+    }
+    Ok(next.run(request).await)
+}
+
+fn is_change(method: &Method, path: &str) -> bool {
+    // Editing sockets are opened with GET but write documents
+    if matches!(path, "/ws" | "/ws/documents") {
+        return true;
+    }
+    if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS) {
+        return false;
+    }
+    // Fetching over git uses POST without changing anything
+    if path.ends_with("/git-upload-pack") {
+        return false;
+    }
+    !(path.starts_with("/api/admin/") || path == "/api/auth/login")
+}
@@ -1 +1,4 @@
 pub mod auth;
+pub mod cors;
+pub mod maintenance;
+pub mod rate_limit;
@@ -0,0 +1,103 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::{Extensions, HeaderMap, Method},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::{
+    error::AppError,
+    middleware::auth::AuthUser,
+    services::rate_limit::{Budget, Client},
+    AppState,
+};
+
+/// Charge the request to the global budget and, for costly routes, to that
+/// route's budget as well. Layered inside authentication, so signed-in users
+/// are counted per account rather than per address.
+pub async fn rate_limit(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let limiter = &state.rate_limits;
+    let ip = client_ip(
+        request.headers(),
+        request.extensions(),
+        limiter.trust_proxy_headers(),
+    )
+    .map(Client::Ip);
+    let user = request
+        .extensions()
+        .get::<AuthUser>()
+        .map(|user| Client::User(user.id.clone()));
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .and_then(|path| route_budget(request.method(), path.as_str()));
+
+    let mut charges = vec![(Budget::Global, user.clone().or(ip.clone()))];
+    match route {
+        // There is no account yet when signing in
+        Some(Budget::Auth) => charges.push((Budget::Auth, ip)),
+        Some(budget) => charges.push((budget, user.or(ip))),
+        None => {}
+    }
+    for (budget, client) in charges {
+        let Some(client) = client else {
+            continue;
+        };
+        if let Err(retry_after) = limiter.check(budget, &client) {
+            return Err(AppError::TooManyRequests {
+                message: format!("Too many requests; try again in {retry_after}s"),
+                retry_after: Some(retry_after),
+            });
+        }
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// The tighter budget a route draws from, by its pattern.
+fn route_budget(method: &Method, path: &str) -> Option<Budget> {
+    if path.starts_with("/api/auth/") {
+        return Some(Budget::Auth);
+    }
+    if method == Method::GET {
+        return None;
+    }
+    match path {
+        "/api/compile/project/:project_id"
+        | "/api/compile/project/:project_id/jobs"
+        | "/api/projects/:id/latexdiff" => Some(Budget::Compile),
+        "/api/files/project/:project_id/upload" => Some(Budget::Upload),
+        _ if path.starts_with("/api/files/project/:project_id/uploads") => Some(Budget::Upload),
+        _ => None,
+    }
+}
+
+/// The address a request came from: the hop a trusted reverse proxy appended
+/// to X-Forwarded-For, or else the peer of the connection.
+pub fn client_ip(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    trust_proxy_headers: bool,
+) -> Option<IpAddr> {
+    if trust_proxy_headers {
+        let forwarded = headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .last()
+            .and_then(|hop| hop.trim().parse().ok());
+        if forwarded.is_some() {
+            return forwarded;
+        }
+    }
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
@@ -0,0 +1,326 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AdminUser,
+    services::{
+        accounts::{self, Account, PurgeReport},
+        backup::{BackupInfo, RestoreReport},
+        gc::{self, GcReport},
+        instance_stats::{self, InstanceStats},
+        system_status::{StatusUpdate, SystemStatus},
+        templates::LibraryReport,
+    },
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/backups", get(list_all_backups))
+        .route(
+            "/backups/:project_id",
+            get(list_project_backups).post(create_backup),
+        )
+        .route(
+            "/backups/:project_id/:backup_id/restore",
+            post(restore_backup),
+        )
+        .route("/gc", post(collect_garbage))
+        .route("/stats", get(get_stats))
+        .route("/system/status", put(update_system_status))
+        .route("/templates/reload", post(reload_templates))
+        .route("/users", get(list_users))
+        .route("/users/purge", post(purge_users))
+        .route("/users/:id/disable", post(disable_user))
+        .route("/users/:id/enable", post(enable_user))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserListResponse {
+    pub users: Vec<Account>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BackupListResponse {
+    pub backups: Vec<BackupInfo>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/backups",
+    tag = "admin",
+    responses((status = 200, body = BackupListResponse))
+)]
+async fn list_all_backups(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+) -> Result<Json<BackupListResponse>> {
+    let backups = state.backups.list(None).await?;
+    Ok(Json(BackupListResponse { backups }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/backups/{project_id}",
+    tag = "admin",
+    params(("project_id" = String, Path, description = "Project id")),
+    responses((status = 200, body = BackupListResponse))
+)]
+async fn list_project_backups(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<BackupListResponse>> {
+    let backups = state.backups.list(Some(&project_id)).await?;
+    Ok(Json(BackupListResponse { backups }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/backups/{project_id}",
+    tag = "admin",
+    params(("project_id" = String, Path, description = "Project id")),
+    responses((status = 200, body = BackupInfo))
+)]
+async fn create_backup(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<BackupInfo>> {
+    let backup = state
+        .backups
+        .backup_project(&state.db, &state.storage, &project_id)
+        .await?;
+    Ok(Json(backup))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/backups/{project_id}/{backup_id}/restore",
+    tag = "admin",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        ("backup_id" = String, Path, description = "Backup id"),
+    ),
+    responses((status = 200, body = BackupRestoreReport))
+)]
+async fn restore_backup(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Path((project_id, backup_id)): Path<(String, String)>,
+) -> Result<Json<RestoreReport>> {
+    let report = state
+        .backups
+        .restore(
+            &state.db,
+            &state.storage,
+            &state.collab,
+            &project_id,
+            &backup_id,
+        )
+        .await?;
+
+    tracing::info!(
+        "Admin {} restored project {} from backup {}",
+        admin.0.email,
+        project_id,
+        backup_id
+    );
+    Ok(Json(report))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct GcQuery {
+    /// Delete the orphans instead of only reporting them
+    #[serde(default)]
+    pub delete: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/gc",
+    tag = "admin",
+    params(GcQuery),
+    responses((status = 200, body = GcReport))
+)]
+async fn collect_garbage(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Query(query): Query<GcQuery>,
+) -> Result<Json<GcReport>> {
+    let report = gc::collect(&state.db, &state.storage, query.delete).await?;
+
+    if query.delete && !report.is_empty() {
+        tracing::info!(
+            "Admin {} deleted orphaned storage: {} projects, {} files, {} working copies, {} objects",
+            admin.0.email,
+            report.orphaned_projects.len(),
+            report.orphaned_files.len(),
+            report.orphaned_work_dirs.len(),
+            report.orphaned_objects.len()
+        );
+    }
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/stats",
+    tag = "admin",
+    responses((status = 200, body = InstanceStats))
+)]
+async fn get_stats(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+) -> Result<Json<InstanceStats>> {
+    let stats = instance_stats::collect(
+        &state.db,
+        &state.storage,
+        &state.compile_jobs,
+        &state.collab,
+        &state.error_log,
+    )
+    .await?;
+    Ok(Json(stats))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/admin/system/status",
+    tag = "admin",
+    request_body = StatusUpdate,
+    responses((status = 200, body = SystemStatus))
+)]
+async fn update_system_status(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Json(body): Json<StatusUpdate>,
+) -> Result<Json<SystemStatus>> {
+    let was_read_only = state.system.is_read_only();
+    let status = state.system.update(body, &admin.0.id).await?;
+
+    if status.maintenance && !was_read_only {
+        // Editing sockets are closing; save what they changed so backups
+        // taken during maintenance have it
+        let saved = state.collab.persist_all().await;
+        tracing::info!(
+            "Admin {} started maintenance; saved {} open documents",
+            admin.0.email,
+            saved
+        );
+    } else if !status.maintenance && was_read_only {
+        tracing::info!("Admin {} ended maintenance", admin.0.email);
+    }
+    Ok(Json(status))
+}
+
+/// Read the templates directory again, to pick up templates added to or
+/// removed from it since the server started.
+#[utoipa::path(
+    post,
+    path = "/api/admin/templates/reload",
+    tag = "admin",
+    responses((status = 200, body = LibraryReport))
+)]
+async fn reload_templates(
+    State(state): State<AppState>,
+    admin: AdminUser,
+) -> Result<Json<LibraryReport>> {
+    if state.config.templates_path.is_none() {
+        return Err(AppError::BadRequest(
+            "No templates directory is configured; set TEMPLATES_PATH".to_string(),
+        ));
+    }
+    let report = state.templates.reload().await?;
+    tracing::info!(
+        "Admin {} reloaded templates: {} loaded, {} skipped",
+        admin.0.email,
+        report.loaded.len(),
+        report.skipped.len()
+    );
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/users",
+    tag = "admin",
+    responses((status = 200, body = UserListResponse))
+)]
+async fn list_users(
+    State(state): State<AppState>,
+    _admin: AdminUser,
+) -> Result<Json<UserListResponse>> {
+    let users = accounts::list(&state.db.pool).await?;
+    Ok(Json(UserListResponse { users }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{id}/disable",
+    tag = "admin",
+    params(("id" = String, Path, description = "User id")),
+    responses((status = 200, body = Account))
+)]
+async fn disable_user(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Path(id): Path<String>,
+) -> Result<Json<Account>> {
+    if id == admin.0.id {
+        return Err(AppError::BadRequest(
+            "You cannot disable your own account".to_string(),
+        ));
+    }
+    let account = accounts::disable(&state.db.pool, &id).await?;
+    tracing::info!("Admin {} disabled user {}", admin.0.email, account.email);
+    Ok(Json(account))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/{id}/enable",
+    tag = "admin",
+    params(("id" = String, Path, description = "User id")),
+    responses((status = 200, body = Account))
+)]
+async fn enable_user(
+    State(state): State<AppState>,
+    admin: AdminUser,
+    Path(id): Path<String>,
+) -> Result<Json<Account>> {
+    let account = accounts::enable(&state.db.pool, &id).await?;
+    tracing::info!("Admin {} enabled user {}", admin.0.email, account.email);
+    Ok(Json(account))
+}
+
+/// Erase accounts disabled for longer than USER_RETENTION_DAYS.
+#[utoipa::path(
+    post,
+    path = "/api/admin/users/purge",
+    tag = "admin",
+    responses((status = 200, body = PurgeReport))
+)]
+async fn purge_users(State(state): State<AppState>, admin: AdminUser) -> Result<Json<PurgeReport>> {
+    let report = accounts::purge(
+        &state.db.pool,
+        &state.storage,
+        &state.git,
+        state.config.user_retention_days,
+    )
+    .await?;
+    tracing::info!(
+        "Admin {} purged {} users ({} projects transferred, {} deleted)",
+        admin.0.email,
+        report.users,
+        report.projects_transferred,
+        report.projects_deleted
+    );
+    Ok(Json(report))
+}
@@ -0,0 +1,89 @@
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::api_tokens::{self, ApiToken},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_tokens).post(create_token))
+        .route("/:id", delete(revoke_token))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateTokenRequest {
+    pub name: String,
+    /// Days until the token expires; never when omitted
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CreateTokenResponse {
+    #[serde(flatten)]
+    pub api_token: ApiToken,
+    /// The secret itself, shown only this once
+    pub token: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TokensListResponse {
+    pub tokens: Vec<ApiToken>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/tokens",
+    tag = "api_tokens",
+    request_body = CreateTokenRequest,
+    responses((status = 200, body = CreateTokenResponse))
+)]
+async fn create_token(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<CreateTokenRequest>,
+) -> Result<Json<CreateTokenResponse>> {
+    let (api_token, token) =
+        api_tokens::create(&state.db.pool, &user.id, &body.name, body.expires_in_days).await?;
+    Ok(Json(CreateTokenResponse { api_token, token }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/tokens",
+    tag = "api_tokens",
+    responses((status = 200, body = TokensListResponse))
+)]
+async fn list_tokens(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<TokensListResponse>> {
+    let tokens = api_tokens::list(&state.db.pool, &user.id).await?;
+    Ok(Json(TokensListResponse { tokens }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/tokens/{id}",
+    tag = "api_tokens",
+    params(("id" = String, Path, description = "Token id")),
+    responses((status = 200, description = "Done"))
+)]
+async fn revoke_token(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<()>> {
+    if !api_tokens::revoke(&state.db.pool, &user.id, &id).await? {
+        return Err(AppError::NotFound("Token not found".to_string()));
+    }
+    Ok(Json(()))
+}
@@ -6,10 +6,13 @@ use axum::{extract::State, routing::post, Json, Router};
 use chrono::Utc;
 use jsonwebtoken::{encode, EncodingKey, Header};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::{
     error::{AppError, Result},
+    middleware::auth::ClientInfo,
+    services::{identicon, mailer, sessions},
     AppState,
 };
 
@@ -19,30 +22,45 @@ pub fn router() -> Router<AppState> {
         .route("/login", post(login))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct RegisterRequest {
     pub email: String,
     pub name: String,
     pub password: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct AuthResponse {
     pub token: String,
     pub user: UserResponse,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct UserResponse {
     pub id: String,
     pub email: String,
     pub name: String,
+    /// Display color, also used for the user's cursor
+    pub color: String,
+    pub avatar_url: String,
+}
+
+impl UserResponse {
+    fn new(id: String, email: String, name: String) -> Self {
+        Self {
+            color: identicon::color(&id).to_string(),
+            avatar_url: identicon::url(&id),
+            id,
+            email,
+            name,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -50,10 +68,12 @@ pub struct Claims {
     pub sub: String, // user id
     pub email: String,
     pub name: String,
+    /// The session the token belongs to
+    pub sid: String,
     pub exp: usize,
 }
 
-fn hash_password(password: &str) -> Result<String> {
+pub(crate) fn hash_password(password: &str) -> Result<String> {
     let salt = SaltString::generate(&mut OsRng);
     let argon2 = Argon2::default();
     argon2
@@ -70,33 +90,52 @@ fn verify_password(password: &str, hash: &str) -> Result<bool> {
         .is_ok())
 }
 
-fn create_token(user_id: &str, email: &str, name: &str, secret: &str) -> Result<String> {
-    let expiration = Utc::now()
-        .checked_add_signed(chrono::Duration::days(7))
-        .expect("valid timestamp")
-        .timestamp() as usize;
+// Start a session for the user and sign a token for it
+async fn create_token(
+    state: &AppState,
+    client: ClientInfo,
+    user_id: &str,
+    email: &str,
+    name: &str,
+) -> Result<String> {
+    let (session_id, expires_at) = sessions::create(
+        &state.db.pool,
+        user_id,
+        client.ip.map(|ip| ip.to_string()),
+        client.user_agent.as_deref(),
+    )
+    .await?;
 
     let claims = Claims {
         sub: user_id.to_string(),
         email: email.to_string(),
         name: name.to_string(),
-        exp: expiration,
+        sid: session_id,
+        exp: expires_at.timestamp() as usize,
     };
 
     encode(
         &Header::default(),
         &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
+        &EncodingKey::from_secret(state.config.jwt_secret.as_bytes()),
     )
     .map_err(|_| AppError::Internal("Failed to create token".to_string()))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/auth/register",
+    tag = "auth",
+    request_body = RegisterRequest,
+    responses((status = 200, body = AuthResponse))
+)]
 async fn register(
     State(state): State<AppState>,
+    client: ClientInfo,
     Json(body): Json<RegisterRequest>,
 ) -> Result<Json<AuthResponse>> {
     // Validate input
-    if body.email.is_empty() || !body.email.contains('@') {
+    if !mailer::is_valid_address(&body.email) {
         return Err(AppError::Validation("Invalid email address".to_string()));
     }
     if body.name.is_empty() {
@@ -109,7 +148,7 @@ async fn register(
     }
 
     // Check if user already exists
-    let existing = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE email = ?")
+    let existing = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE email = $1")
         .bind(&body.email)
         .fetch_one(&state.db.pool)
         .await?;
@@ -126,7 +165,7 @@ async fn register(
     let now = Utc::now().to_rfc3339();
 
     sqlx::query(
-        "INSERT INTO users (id, email, name, password_hash, created_at) VALUES (?, ?, ?, ?, ?)",
+        "INSERT INTO users (id, email, name, password_hash, created_at) VALUES ($1, $2, $3, $4, $5)",
     )
     .bind(&user_id)
     .bind(&body.email)
@@ -137,25 +176,29 @@ async fn register(
     .await?;
 
     // Create token
-    let token = create_token(&user_id, &body.email, &body.name, &state.config.jwt_secret)?;
+    let token = create_token(&state, client, &user_id, &body.email, &body.name).await?;
 
     Ok(Json(AuthResponse {
         token,
-        user: UserResponse {
-            id: user_id,
-            email: body.email,
-            name: body.name,
-        },
+        user: UserResponse::new(user_id, body.email, body.name),
     }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/auth/login",
+    tag = "auth",
+    request_body = LoginRequest,
+    responses((status = 200, body = AuthResponse))
+)]
 async fn login(
     State(state): State<AppState>,
+    client: ClientInfo,
     Json(body): Json<LoginRequest>,
 ) -> Result<Json<AuthResponse>> {
     // Find user by email
     let user = sqlx::query_as::<_, (String, String, String, String)>(
-        "SELECT id, email, name, password_hash FROM users WHERE email = ?",
+        "SELECT id, email, name, password_hash FROM users WHERE email = $1 AND disabled_at IS NULL",
     )
     .bind(&body.email)
     .fetch_optional(&state.db.pool)
@@ -170,14 +213,10 @@ async fn login(
     }
 
     // Create token
-    let token = create_token(&user_id, &email, &name, &state.config.jwt_secret)?;
+    let token = create_token(&state, client, &user_id, &email, &name).await?;
 
     Ok(Json(AuthResponse {
         token,
-        user: UserResponse {
-            id: user_id,
-            email,
-            name,
-        },
+        user: UserResponse::new(user_id, email, name),
     }))
 }
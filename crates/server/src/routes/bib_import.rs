@@ -0,0 +1,102 @@
+use axum::{
+    extract::{Path, State},
+    routing::post,
+    Json, Router,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use super::{
+    bibtex::{open_database, save_entry},
+    files::ensure_unlocked,
+};
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{
+        bib_import,
+        bibtex::{self, BibEntry},
+        freeze,
+    },
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/:id/bib/import", post(import_reference))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ImportRequest {
+    /// DOI or arXiv ID, bare or as a URL
+    pub identifier: String,
+    /// The .bib file to append the entry to
+    pub file_id: String,
+    /// Citation key; generated from the author, year and title when omitted
+    pub key: Option<String>,
+}
+
+/// Look up a DOI or arXiv ID and append it to a .bib file of the project.
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/bib/import",
+    tag = "bib_import",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = ImportRequest,
+    responses((status = 200, body = BibEntry))
+)]
+async fn import_reference(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<ImportRequest>,
+) -> Result<Json<BibEntry>> {
+    let (file, content) = open_database(&state, &body.file_id, &user.id).await?;
+    if file.project_id != id {
+        return Err(AppError::NotFound("File not found".to_string()));
+    }
+    ensure_unlocked(&file, &user.id)?;
+    freeze::ensure_writable(&state.db.pool, &file.project_id).await?;
+
+    let identifier = bib_import::parse_identifier(&body.identifier)?;
+    let reference = bib_import::lookup(&state.config, &identifier).await?;
+
+    let database = bibtex::parse(&content);
+    if let Some(doi) = &reference.doi {
+        if let Some(existing) = database.entries.iter().find(|entry| {
+            entry
+                .field("doi")
+                .is_some_and(|existing| existing.trim().eq_ignore_ascii_case(doi))
+        }) {
+            return Err(AppError::Conflict(format!(
+                "{} already has this reference as '{}'",
+                file.name, existing.key
+            )));
+        }
+    }
+
+    let key = match body.key {
+        Some(key) => {
+            if database
+                .entries
+                .iter()
+                .any(|entry| entry.key.eq_ignore_ascii_case(&key))
+            {
+                return Err(AppError::Conflict(format!(
+                    "An entry with the key '{key}' already exists"
+                )));
+            }
+            key
+        }
+        None => bib_import::citation_key(&reference, &database.entries),
+    };
+    bibtex::validate(reference.entry_type, &key, &reference.fields)?;
+
+    let text = bibtex::format_entry(
+        reference.entry_type,
+        &key,
+        &reference.fields,
+        &database.strings,
+    );
+    let content = bibtex::splice(&content, None, &text);
+    save_entry(&state, &file, &user.id, content, &key).await
+}
@@ -0,0 +1,250 @@
+use axum::{
+    extract::{Path, State},
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use super::files::{ensure_unlocked, fetch_file, write_content, FileResponse};
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{
+        bibtex::{self, BibDatabase, BibEntry, BibField, Duplicate},
+        freeze,
+    },
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/:id/bib", get(get_database))
+        .route("/:id/bib/duplicates", get(get_duplicates))
+        .route("/:id/bib/entries", post(add_entry))
+        .route(
+            "/:id/bib/entries/:key",
+            put(update_entry).delete(delete_entry),
+        )
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EntryRequest {
+    pub entry_type: String,
+    pub key: String,
+    #[serde(default)]
+    pub fields: Vec<BibField>,
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+// The file and its current content, once it's known to be a readable .bib
+pub(super) async fn open_database(
+    state: &AppState,
+    id: &str,
+    user_id: &str,
+) -> Result<(FileResponse, String)> {
+    let file = fetch_file(&state.db.pool, id).await?;
+    check_project_access(&state.db.pool, &file.project_id, user_id).await?;
+
+    if file.is_folder || !file.path.to_lowercase().ends_with(".bib") {
+        return Err(AppError::BadRequest("Not a BibTeX file".to_string()));
+    }
+
+    let content = state.collab.content(&file.project_id, &file.path).await?;
+    Ok((file, content))
+}
+
+// The only entry with this key; several would make the edit ambiguous
+fn find_entry<'a>(database: &'a BibDatabase, key: &str) -> Result<&'a BibEntry> {
+    let mut matches = database.entries.iter().filter(|entry| entry.key == key);
+    let entry = matches
+        .next()
+        .ok_or_else(|| AppError::NotFound(format!("No entry with key '{key}'")))?;
+    if matches.next().is_some() {
+        return Err(AppError::Conflict(format!(
+            "Several entries have the key '{key}'"
+        )));
+    }
+    Ok(entry)
+}
+
+// BibTeX treats keys differing only in case as the same
+fn ensure_key_free(database: &BibDatabase, key: &str, except: Option<&str>) -> Result<()> {
+    let taken = database
+        .entries
+        .iter()
+        .any(|entry| entry.key.eq_ignore_ascii_case(key) && Some(entry.key.as_str()) != except);
+    if taken {
+        return Err(AppError::Conflict(format!(
+            "An entry with the key '{key}' already exists"
+        )));
+    }
+    Ok(())
+}
+
+// Write the new content and return the entry as it now reads. A broken entry
+// earlier in the file can swallow the new one, so check before writing.
+pub(super) async fn save_entry(
+    state: &AppState,
+    file: &FileResponse,
+    author: &str,
+    content: String,
+    key: &str,
+) -> Result<Json<BibEntry>> {
+    let entry = bibtex::parse(&content)
+        .entries
+        .into_iter()
+        .find(|entry| entry.key == key)
+        .ok_or_else(|| {
+            AppError::BadRequest(
+                "The file has an unterminated entry; fix it before editing entries".to_string(),
+            )
+        })?;
+
+    write_content(state, file, author, &content).await?;
+    Ok(Json(entry))
+}
+
+/// Entries of a .bib file, with @string names and parse errors.
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}/bib",
+    tag = "bibtex",
+    params(("id" = String, Path, description = "File id")),
+    responses((status = 200, body = BibDatabase))
+)]
+async fn get_database(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<BibDatabase>> {
+    let (_, content) = open_database(&state, &id, &user.id).await?;
+    Ok(Json(bibtex::parse(&content)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}/bib/duplicates",
+    tag = "bibtex",
+    params(("id" = String, Path, description = "File id")),
+    responses((status = 200, body = [Duplicate]))
+)]
+async fn get_duplicates(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<Duplicate>>> {
+    let (_, content) = open_database(&state, &id, &user.id).await?;
+    Ok(Json(bibtex::duplicates(&bibtex::parse(&content).entries)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/files/{id}/bib/entries",
+    tag = "bibtex",
+    params(("id" = String, Path, description = "File id")),
+    request_body = EntryRequest,
+    responses((status = 200, body = BibEntry))
+)]
+async fn add_entry(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<EntryRequest>,
+) -> Result<Json<BibEntry>> {
+    let (file, content) = open_database(&state, &id, &user.id).await?;
+    ensure_unlocked(&file, &user.id)?;
+    freeze::ensure_writable(&state.db.pool, &file.project_id).await?;
+    bibtex::validate(&body.entry_type, &body.key, &body.fields)?;
+
+    let database = bibtex::parse(&content);
+    ensure_key_free(&database, &body.key, None)?;
+
+    let text = bibtex::format_entry(&body.entry_type, &body.key, &body.fields, &database.strings);
+    let content = bibtex::splice(&content, None, &text);
+    save_entry(&state, &file, &user.id, content, &body.key).await
+}
+
+/// Replace an entry, possibly under a new key. The rest of the file is left
+/// as it was.
+#[utoipa::path(
+    put,
+    path = "/api/files/{id}/bib/entries/{key}",
+    tag = "bibtex",
+    params(
+        ("id" = String, Path, description = "File id"),
+        ("key" = String, Path, description = "Citation key"),
+    ),
+    request_body = EntryRequest,
+    responses((status = 200, body = BibEntry))
+)]
+async fn update_entry(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, key)): Path<(String, String)>,
+    Json(body): Json<EntryRequest>,
+) -> Result<Json<BibEntry>> {
+    let (file, content) = open_database(&state, &id, &user.id).await?;
+    ensure_unlocked(&file, &user.id)?;
+    freeze::ensure_writable(&state.db.pool, &file.project_id).await?;
+    bibtex::validate(&body.entry_type, &body.key, &body.fields)?;
+
+    let database = bibtex::parse(&content);
+    let entry = find_entry(&database, &key)?;
+    ensure_key_free(&database, &body.key, Some(&key))?;
+
+    let text = bibtex::format_entry(&body.entry_type, &body.key, &body.fields, &database.strings);
+    let content = bibtex::splice(&content, Some(entry.span.clone()), &text);
+    save_entry(&state, &file, &user.id, content, &body.key).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/files/{id}/bib/entries/{key}",
+    tag = "bibtex",
+    params(
+        ("id" = String, Path, description = "File id"),
+        ("key" = String, Path, description = "Citation key"),
+    ),
+    responses((status = 200, description = "Done"))
+)]
+async fn delete_entry(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, key)): Path<(String, String)>,
+) -> Result<Json<()>> {
+    let (file, content) = open_database(&state, &id, &user.id).await?;
+    ensure_unlocked(&file, &user.id)?;
+    freeze::ensure_writable(&state.db.pool, &file.project_id).await?;
+
+    let database = bibtex::parse(&content);
+    let entry = find_entry(&database, &key)?;
+
+    let content = bibtex::remove(&content, entry.span.clone());
+    write_content(&state, &file, &user.id, &content).await?;
+
+    Ok(Json(()))
+}
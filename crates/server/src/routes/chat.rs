@@ -0,0 +1,84 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::chat::{self, ChatMessage},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/:id/chat", get(list_messages))
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ChatQuery {
+    /// ID of the oldest message already shown
+    pub before: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChatHistoryResponse {
+    pub messages: Vec<ChatMessage>,
+    pub has_more: bool,
+}
+
+/// Chat history, newest first, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/chat",
+    tag = "chat",
+    params(
+        ("id" = String, Path, description = "Project id"),
+        ChatQuery,
+    ),
+    responses((status = 200, body = ChatHistoryResponse))
+)]
+async fn list_messages(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Query(query): Query<ChatQuery>,
+) -> Result<Json<ChatHistoryResponse>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+    // One extra row tells whether there is another page
+    let mut messages =
+        chat::history(&state.db.pool, &id, query.before.as_deref(), limit + 1).await?;
+    let has_more = messages.len() as i64 > limit;
+    messages.truncate(limit as usize);
+
+    Ok(Json(ChatHistoryResponse { messages, has_more }))
+}
@@ -1,28 +1,41 @@
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     routing::{get, post},
     Json, Router,
 };
-use chrono::Utc;
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 use crate::{
     error::{AppError, Result},
     middleware::auth::AuthUser,
+    services::{
+        events::ProjectEvent,
+        mentions,
+        notifications::{self, NewNotification},
+        search,
+    },
     AppState,
 };
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/project/:project_id", get(list_comments))
+        .route("/project/:project_id/counts", get(count_open_comments))
         .route("/project/:project_id/file", get(list_file_comments))
         .route("/", post(create_comment))
-        .route("/:id", get(get_comment).delete(delete_comment))
+        .route(
+            "/:id",
+            get(get_comment).put(update_comment).delete(delete_comment),
+        )
         .route("/:id/resolve", post(resolve_comment))
+        .route("/:id/unresolve", post(unresolve_comment))
+        .route("/:id/revisions", get(list_revisions))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateCommentRequest {
     pub project_id: String,
     pub file_path: String,
@@ -31,12 +44,31 @@ pub struct CreateCommentRequest {
     pub line_end: i32,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateCommentRequest {
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CommentsQuery {
+    pub resolved: Option<bool>,
+    pub author_id: Option<String>,
+    pub file_path: Option<String>,
+    /// Comments created at or after this time (RFC 3339 or a date)
+    pub since: Option<String>,
+    /// Comments created before this time; a date includes that whole day
+    pub until: Option<String>,
+    /// Page size, at most 500; every comment when absent
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct FileCommentsQuery {
     pub file_path: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
 pub struct CommentResponse {
     pub id: String,
     pub project_id: String,
@@ -47,17 +79,71 @@ pub struct CommentResponse {
     pub line_start: i32,
     pub line_end: i32,
     pub resolved: bool,
+    pub resolved_by: Option<String>,
+    pub resolved_by_name: Option<String>,
+    pub resolved_at: Option<String>,
     pub created_at: String,
+    /// When the content was last edited
+    pub updated_at: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct CommentsListResponse {
     pub comments: Vec<CommentResponse>,
 }
 
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CommentsPageResponse {
+    pub comments: Vec<CommentResponse>,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+pub struct FileCommentCount {
+    pub file_path: String,
+    pub open: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CommentCountsResponse {
+    pub files: Vec<FileCommentCount>,
+}
+
+/// An earlier wording of an edited comment.
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+pub struct CommentRevision {
+    pub id: String,
+    pub content: String,
+    /// When this wording was replaced
+    pub replaced_at: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RevisionsListResponse {
+    pub revisions: Vec<CommentRevision>,
+}
+
+// Columns selected into a CommentResponse, and the tables they come from
+const COMMENT_COLUMNS: &str =
+    "c.id, c.project_id, c.file_path, c.author_id, u.name AS author_name, \
+    c.content, c.line_start, c.line_end, c.resolved, c.resolved_by, r.name AS resolved_by_name, \
+    c.resolved_at, c.created_at, c.updated_at";
+const COMMENT_FROM: &str = "comments c JOIN users u ON c.author_id = u.id \
+    LEFT JOIN users r ON c.resolved_by = r.id";
+
+async fn fetch_comment(pool: &crate::db::DbPool, id: &str) -> Result<CommentResponse> {
+    sqlx::query_as::<_, CommentResponse>(&format!(
+        "SELECT {COMMENT_COLUMNS} FROM {COMMENT_FROM} WHERE c.id = $1"
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Comment not found".to_string()))
+}
+
 // Helper to check if user has access to project
 async fn check_project_access(
-    pool: &sqlx::SqlitePool,
+    pool: &crate::db::DbPool,
     project_id: &str,
     user_id: &str,
 ) -> Result<()> {
@@ -65,7 +151,7 @@ async fn check_project_access(
         r#"
         SELECT COUNT(*) FROM projects p
         LEFT JOIN project_collaborators pc ON p.id = pc.project_id
-        WHERE p.id = ? AND (p.owner_id = ? OR pc.user_id = ?)
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
         "#,
     )
     .bind(project_id)
@@ -80,60 +166,126 @@ async fn check_project_access(
     Ok(())
 }
 
+/// A `since`/`until` bound in the format comments store `created_at` in, so
+/// the two compare as text. An `until` date is moved to the next day.
+fn time_bound(value: &str, until: bool) -> Result<String> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(value) {
+        return Ok(time.with_timezone(&Utc).to_rfc3339());
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| AppError::Validation(format!("Invalid date: {value}")))?;
+    let date = if until {
+        date.succ_opt().unwrap_or(date)
+    } else {
+        date
+    };
+    Ok(date.format("%Y-%m-%d").to_string())
+}
+
+/// A project's comments, newest first, a page at a time.
+#[utoipa::path(
+    get,
+    path = "/api/comments/project/{project_id}",
+    tag = "comments",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        CommentsQuery,
+    ),
+    responses((status = 200, body = CommentsPageResponse))
+)]
 async fn list_comments(
     State(state): State<AppState>,
     user: AuthUser,
     Path(project_id): Path<String>,
-) -> Result<Json<CommentsListResponse>> {
+    Query(query): Query<CommentsQuery>,
+) -> Result<Json<CommentsPageResponse>> {
     check_project_access(&state.db.pool, &project_id, &user.id).await?;
 
-    let comments = sqlx::query_as::<_, (String, String, String, String, String, String, i32, i32, bool, String)>(
-        r#"
-        SELECT c.id, c.project_id, c.file_path, c.author_id, u.name, c.content, c.line_start, c.line_end, c.resolved, c.created_at
-        FROM comments c
-        JOIN users u ON c.author_id = u.id
-        WHERE c.project_id = ?
-        ORDER BY c.created_at DESC
-        "#,
-    )
+    let since = query
+        .since
+        .as_deref()
+        .map(|v| time_bound(v, false))
+        .transpose()?;
+    let until = query
+        .until
+        .as_deref()
+        .map(|v| time_bound(v, true))
+        .transpose()?;
+    // Clients that predate paging ask for no limit and expect every comment
+    let limit = query.limit.map(|limit| limit.clamp(1, 500));
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    // One extra row tells whether there is another page
+    let mut comments = sqlx::query_as::<_, CommentResponse>(&format!(
+        "SELECT {COMMENT_COLUMNS} FROM {COMMENT_FROM} \
+         WHERE c.project_id = $1 \
+         AND ($2 IS NULL OR c.resolved = $3) \
+         AND ($4 IS NULL OR c.author_id = $5) \
+         AND ($6 IS NULL OR c.file_path = $7) \
+         AND ($8 IS NULL OR c.created_at >= $9) \
+         AND ($10 IS NULL OR c.created_at < $11) \
+         ORDER BY c.created_at DESC LIMIT $12 OFFSET $13"
+    ))
     .bind(&project_id)
+    .bind(query.resolved)
+    .bind(query.resolved)
+    .bind(&query.author_id)
+    .bind(&query.author_id)
+    .bind(&query.file_path)
+    .bind(&query.file_path)
+    .bind(&since)
+    .bind(&since)
+    .bind(&until)
+    .bind(&until)
+    .bind(limit.map_or(i64::MAX, |limit| limit + 1))
+    .bind(offset)
     .fetch_all(&state.db.pool)
     .await?;
 
-    let comments = comments
-        .into_iter()
-        .map(
-            |(
-                id,
-                project_id,
-                file_path,
-                author_id,
-                author_name,
-                content,
-                line_start,
-                line_end,
-                resolved,
-                created_at,
-            )| {
-                CommentResponse {
-                    id,
-                    project_id,
-                    file_path,
-                    author_id,
-                    author_name,
-                    content,
-                    line_start,
-                    line_end,
-                    resolved,
-                    created_at,
-                }
-            },
-        )
-        .collect();
+    let has_more = limit.is_some_and(|limit| comments.len() as i64 > limit);
+    if let Some(limit) = limit {
+        comments.truncate(limit as usize);
+    }
 
-    Ok(Json(CommentsListResponse { comments }))
+    Ok(Json(CommentsPageResponse { comments, has_more }))
+}
+
+/// Unresolved comments per file, for badges in the file tree.
+#[utoipa::path(
+    get,
+    path = "/api/comments/project/{project_id}/counts",
+    tag = "comments",
+    params(("project_id" = String, Path, description = "Project id")),
+    responses((status = 200, body = CommentCountsResponse))
+)]
+async fn count_open_comments(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<CommentCountsResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    let files = sqlx::query_as::<_, FileCommentCount>(
+        "SELECT file_path, COUNT(*) AS open FROM comments \
+         WHERE project_id = $1 AND resolved = FALSE GROUP BY file_path ORDER BY file_path",
+    )
+    .bind(&project_id)
+    .fetch_all(&state.db.pool)
+    .await?;
+
+    Ok(Json(CommentCountsResponse { files }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/comments/project/{project_id}/file",
+    tag = "comments",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        FileCommentsQuery,
+    ),
+    responses((status = 200, body = CommentsListResponse))
+)]
 async fn list_file_comments(
     State(state): State<AppState>,
     user: AuthUser,
@@ -142,54 +294,25 @@ async fn list_file_comments(
 ) -> Result<Json<CommentsListResponse>> {
     check_project_access(&state.db.pool, &project_id, &user.id).await?;
 
-    let comments = sqlx::query_as::<_, (String, String, String, String, String, String, i32, i32, bool, String)>(
-        r#"
-        SELECT c.id, c.project_id, c.file_path, c.author_id, u.name, c.content, c.line_start, c.line_end, c.resolved, c.created_at
-        FROM comments c
-        JOIN users u ON c.author_id = u.id
-        WHERE c.project_id = ? AND c.file_path = ?
-        ORDER BY c.line_start ASC, c.created_at ASC
-        "#,
-    )
+    let comments = sqlx::query_as::<_, CommentResponse>(&format!(
+        "SELECT {COMMENT_COLUMNS} FROM {COMMENT_FROM} \
+         WHERE c.project_id = $1 AND c.file_path = $2 ORDER BY c.line_start ASC, c.created_at ASC"
+    ))
     .bind(&project_id)
     .bind(&query.file_path)
     .fetch_all(&state.db.pool)
     .await?;
 
-    let comments = comments
-        .into_iter()
-        .map(
-            |(
-                id,
-                project_id,
-                file_path,
-                author_id,
-                author_name,
-                content,
-                line_start,
-                line_end,
-                resolved,
-                created_at,
-            )| {
-                CommentResponse {
-                    id,
-                    project_id,
-                    file_path,
-                    author_id,
-                    author_name,
-                    content,
-                    line_start,
-                    line_end,
-                    resolved,
-                    created_at,
-                }
-            },
-        )
-        .collect();
-
     Ok(Json(CommentsListResponse { comments }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/comments",
+    tag = "comments",
+    request_body = CreateCommentRequest,
+    responses((status = 200, body = CommentResponse))
+)]
 async fn create_comment(
     State(state): State<AppState>,
     user: AuthUser,
@@ -211,7 +334,7 @@ async fn create_comment(
     let now = Utc::now().to_rfc3339();
 
     sqlx::query(
-        "INSERT INTO comments (id, project_id, file_path, author_id, content, line_start, line_end, resolved, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        "INSERT INTO comments (id, project_id, file_path, author_id, content, line_start, line_end, resolved, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
     )
     .bind(&comment_id)
     .bind(&body.project_id)
@@ -225,6 +348,61 @@ async fn create_comment(
     .execute(&state.db.pool)
     .await?;
 
+    index_comment(
+        &state,
+        &body.project_id,
+        &comment_id,
+        &body.file_path,
+        &now,
+        &body.content,
+    )
+    .await;
+
+    state.events.publish(
+        &body.project_id,
+        ProjectEvent::CommentAdded {
+            comment_id: comment_id.clone(),
+            file_path: body.file_path.clone(),
+            author_id: user.id.clone(),
+            author_name: user.name.clone(),
+        },
+    );
+
+    let mentioned =
+        mentions::record(&state.db.pool, &body.project_id, &comment_id, &body.content).await?;
+
+    // Members the comment mentions hear about it as a mention instead
+    let members = mentions::members(&state.db.pool, &body.project_id).await?;
+    let recipients: Vec<String> = members
+        .into_iter()
+        .filter(|member| member.id != user.id && !mentioned.iter().any(|m| m.id == member.id))
+        .map(|member| member.id)
+        .collect();
+    state
+        .notifications
+        .notify(
+            &recipients,
+            NewNotification {
+                kind: notifications::COMMENT,
+                project_id: body.project_id.clone(),
+                actor_id: Some(user.id.clone()),
+                target_id: Some(comment_id.clone()),
+                file_path: Some(body.file_path.clone()),
+                message: format!("{} commented on {}", user.name, body.file_path),
+            },
+        )
+        .await;
+
+    notify_mentioned(
+        &state,
+        &body.project_id,
+        &comment_id,
+        &body.file_path,
+        &user,
+        mentioned,
+    )
+    .await;
+
     Ok(Json(CommentResponse {
         id: comment_id,
         project_id: body.project_id,
@@ -235,64 +413,244 @@ async fn create_comment(
         line_start: body.line_start,
         line_end: body.line_end,
         resolved: false,
+        resolved_by: None,
+        resolved_by_name: None,
+        resolved_at: None,
         created_at: now,
+        updated_at: None,
     }))
 }
 
+/// Index a comment for search. A search catches up on comments that failed
+/// to index, so failing here does not fail the request.
+async fn index_comment(
+    state: &AppState,
+    project_id: &str,
+    comment_id: &str,
+    file_path: &str,
+    version: &str,
+    content: &str,
+) {
+    if let Err(e) = search::index_comment(
+        &state.db.pool,
+        project_id,
+        comment_id,
+        file_path,
+        version,
+        content,
+    )
+    .await
+    {
+        tracing::warn!("Failed to index comment {} for search: {}", comment_id, e);
+    }
+}
+
+async fn notify_mentioned(
+    state: &AppState,
+    project_id: &str,
+    comment_id: &str,
+    file_path: &str,
+    author: &AuthUser,
+    mentioned: Vec<mentions::Member>,
+) {
+    let mut recipients = Vec::new();
+    for member in mentioned
+        .into_iter()
+        .filter(|member| member.id != author.id)
+    {
+        state.events.publish(
+            project_id,
+            ProjectEvent::UserMentioned {
+                comment_id: comment_id.to_string(),
+                file_path: file_path.to_string(),
+                user_id: member.id.clone(),
+                author_id: author.id.clone(),
+                author_name: author.name.clone(),
+            },
+        );
+        recipients.push(member.id);
+    }
+
+    state
+        .notifications
+        .notify(
+            &recipients,
+            NewNotification {
+                kind: notifications::MENTION,
+                project_id: project_id.to_string(),
+                actor_id: Some(author.id.clone()),
+                target_id: Some(comment_id.to_string()),
+                file_path: Some(file_path.to_string()),
+                message: format!(
+                    "{} mentioned you in a comment on {}",
+                    author.name, file_path
+                ),
+            },
+        )
+        .await;
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/comments/{id}",
+    tag = "comments",
+    params(("id" = String, Path, description = "Comment id")),
+    responses((status = 200, body = CommentResponse))
+)]
 async fn get_comment(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<String>,
 ) -> Result<Json<CommentResponse>> {
-    let comment = sqlx::query_as::<_, (String, String, String, String, String, String, i32, i32, bool, String)>(
-        r#"
-        SELECT c.id, c.project_id, c.file_path, c.author_id, u.name, c.content, c.line_start, c.line_end, c.resolved, c.created_at
-        FROM comments c
-        JOIN users u ON c.author_id = u.id
-        WHERE c.id = ?
-        "#,
+    let comment = fetch_comment(&state.db.pool, &id).await?;
+    check_project_access(&state.db.pool, &comment.project_id, &user.id).await?;
+
+    Ok(Json(comment))
+}
+
+/// Reword a comment. Only its author can; the previous content is kept as a
+/// revision.
+#[utoipa::path(
+    put,
+    path = "/api/comments/{id}",
+    tag = "comments",
+    params(("id" = String, Path, description = "Comment id")),
+    request_body = UpdateCommentRequest,
+    responses((status = 200, body = CommentResponse))
+)]
+async fn update_comment(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<UpdateCommentRequest>,
+) -> Result<Json<CommentResponse>> {
+    let comment = fetch_comment(&state.db.pool, &id).await?;
+    check_project_access(&state.db.pool, &comment.project_id, &user.id).await?;
+
+    if comment.author_id != user.id {
+        return Err(AppError::Forbidden(
+            "Only the author can edit this comment".to_string(),
+        ));
+    }
+    if body.content.trim().is_empty() {
+        return Err(AppError::Validation(
+            "Comment content is required".to_string(),
+        ));
+    }
+    if body.content == comment.content {
+        return Ok(Json(comment));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let mut tx = state.db.pool.begin().await?;
+
+    // Guard on the old content so concurrent edits each keep a revision
+    let updated = sqlx::query(
+        "UPDATE comments SET content = $1, updated_at = $2 WHERE id = $3 AND content = $4",
     )
+    .bind(&body.content)
+    .bind(&now)
     .bind(&id)
-    .fetch_optional(&state.db.pool)
-    .await?
-    .ok_or_else(|| AppError::NotFound("Comment not found".to_string()))?;
+    .bind(&comment.content)
+    .execute(&mut *tx)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(AppError::Conflict(
+            "The comment was edited meanwhile".to_string(),
+        ));
+    }
 
-    let (
-        id,
-        project_id,
-        file_path,
-        author_id,
-        author_name,
-        content,
-        line_start,
-        line_end,
-        resolved,
-        created_at,
-    ) = comment;
+    sqlx::query(
+        "INSERT INTO comment_revisions (id, comment_id, content, replaced_at) VALUES ($1, $2, $3, $4)",
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(&id)
+    .bind(&comment.content)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await?;
 
-    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+    tx.commit().await?;
+
+    index_comment(
+        &state,
+        &comment.project_id,
+        &id,
+        &comment.file_path,
+        &now,
+        &body.content,
+    )
+    .await;
+
+    state.events.publish(
+        &comment.project_id,
+        ProjectEvent::CommentEdited {
+            comment_id: id.clone(),
+            file_path: comment.file_path.clone(),
+        },
+    );
+
+    // Only people the edit newly mentions are told
+    let mentioned =
+        mentions::record(&state.db.pool, &comment.project_id, &id, &body.content).await?;
+    notify_mentioned(
+        &state,
+        &comment.project_id,
+        &id,
+        &comment.file_path,
+        &user,
+        mentioned,
+    )
+    .await;
 
     Ok(Json(CommentResponse {
-        id,
-        project_id,
-        file_path,
-        author_id,
-        author_name,
-        content,
-        line_start,
-        line_end,
-        resolved,
-        created_at,
+        content: body.content,
+        updated_at: Some(now),
+        ..comment
     }))
 }
 
+/// Earlier wordings of a comment, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/comments/{id}/revisions",
+    tag = "comments",
+    params(("id" = String, Path, description = "Comment id")),
+    responses((status = 200, body = RevisionsListResponse))
+)]
+async fn list_revisions(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<RevisionsListResponse>> {
+    let comment = fetch_comment(&state.db.pool, &id).await?;
+    check_project_access(&state.db.pool, &comment.project_id, &user.id).await?;
+
+    let revisions = sqlx::query_as::<_, CommentRevision>(
+        "SELECT id, content, replaced_at FROM comment_revisions \
+         WHERE comment_id = $1 ORDER BY replaced_at DESC",
+    )
+    .bind(&id)
+    .fetch_all(&state.db.pool)
+    .await?;
+
+    Ok(Json(RevisionsListResponse { revisions }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/comments/{id}",
+    tag = "comments",
+    params(("id" = String, Path, description = "Comment id")),
+    responses((status = 200, description = "Done"))
+)]
 async fn delete_comment(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<String>,
 ) -> Result<Json<()>> {
     let comment = sqlx::query_as::<_, (String, String)>(
-        "SELECT project_id, author_id FROM comments WHERE id = ?",
+        "SELECT project_id, author_id FROM comments WHERE id = $1",
     )
     .bind(&id)
     .fetch_optional(&state.db.pool)
@@ -302,12 +660,13 @@ async fn delete_comment(
     let (project_id, author_id) = comment;
 
     // Only author or project owner can delete
-    let is_owner =
-        sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM projects WHERE id = ? AND owner_id = ?")
-            .bind(&project_id)
-            .bind(&user.id)
-            .fetch_one(&state.db.pool)
-            .await?;
+    let is_owner = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM projects WHERE id = $1 AND owner_id = $2",
+    )
+    .bind(&project_id)
+    .bind(&user.id)
+    .fetch_one(&state.db.pool)
+    .await?;
 
     if author_id != user.id && is_owner == 0 {
         return Err(AppError::Forbidden(
@@ -315,7 +674,7 @@ async fn delete_comment(
         ));
     }
 
-    sqlx::query("DELETE FROM comments WHERE id = ?")
+    sqlx::query("DELETE FROM comments WHERE id = $1")
         .bind(&id)
         .execute(&state.db.pool)
         .await?;
@@ -323,24 +682,76 @@ async fn delete_comment(
     Ok(Json(()))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/comments/{id}/resolve",
+    tag = "comments",
+    params(("id" = String, Path, description = "Comment id")),
+    responses((status = 200, body = CommentResponse))
+)]
 async fn resolve_comment(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<String>,
 ) -> Result<Json<CommentResponse>> {
-    let comment = sqlx::query_as::<_, (String,)>("SELECT project_id FROM comments WHERE id = ?")
-        .bind(&id)
-        .fetch_optional(&state.db.pool)
-        .await?
-        .ok_or_else(|| AppError::NotFound("Comment not found".to_string()))?;
+    set_resolved(state, user, id, true).await
+}
 
-    let (project_id,) = comment;
-    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+/// Reopen a resolved discussion.
+#[utoipa::path(
+    post,
+    path = "/api/comments/{id}/unresolve",
+    tag = "comments",
+    params(("id" = String, Path, description = "Comment id")),
+    responses((status = 200, body = CommentResponse))
+)]
+async fn unresolve_comment(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<CommentResponse>> {
+    set_resolved(state, user, id, false).await
+}
+
+async fn set_resolved(
+    state: AppState,
+    user: AuthUser,
+    id: String,
+    resolved: bool,
+) -> Result<Json<CommentResponse>> {
+    let comment = fetch_comment(&state.db.pool, &id).await?;
+    check_project_access(&state.db.pool, &comment.project_id, &user.id).await?;
+
+    if comment.resolved == resolved {
+        return Ok(Json(comment));
+    }
 
-    sqlx::query("UPDATE comments SET resolved = 1 WHERE id = ?")
+    if resolved {
+        sqlx::query(
+            "UPDATE comments SET resolved = TRUE, resolved_by = $1, resolved_at = $2 WHERE id = $3",
+        )
+        .bind(&user.id)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&id)
+        .execute(&state.db.pool)
+        .await?;
+    } else {
+        sqlx::query(
+            "UPDATE comments SET resolved = FALSE, resolved_by = NULL, resolved_at = NULL WHERE id = $1",
+        )
         .bind(&id)
         .execute(&state.db.pool)
         .await?;
+    }
+
+    state.events.publish(
+        &comment.project_id,
+        ProjectEvent::CommentResolved {
+            comment_id: id.clone(),
+            file_path: comment.file_path,
+            resolved,
+        },
+    );
 
     // Return updated comment
     get_comment(State(state), user, Path(id)).await
@@ -1,58 +1,189 @@
-use std::process::Command;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 use axum::{
-    extract::{Path, State},
-    routing::post,
+    extract::{Path, Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{get, post},
     Json, Router,
 };
+use chrono::Utc;
+use futures::{stream, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+use utoipa::{IntoParams, ToSchema};
 
+use super::projects::{is_valid_target_name, load_settings};
 use crate::{
     error::{AppError, Result},
     middleware::auth::AuthUser,
+    services::{
+        bibliography::BibIssue,
+        build_cache::{Artifact, BuildCache},
+        compile_history::{
+            self, CompileRecord, CompileRecordDetail, CompileStats, DiagnosticsDelta,
+        },
+        compile_jobs::{CompileJob, JobInfo, JobStatus, LogEvent},
+        compiler::{
+            self, BibTool, CompileError, CompileOptions, CompileResult, CompileWarning, Engine,
+        },
+        events::ProjectEvent,
+        lint::{self, LintWarning},
+        notifications::{self, NewNotification},
+        packages::MissingPackage,
+        pdf_pages::{self, PageInfo},
+        provenance::{self, Provenance},
+        synctex::{self, PdfLocation, SourceLocation},
+        wordcount::{self, WordCount},
+    },
     AppState,
 };
 
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/project/:project_id", post(compile_project))
+        .route("/project/:project_id/jobs", post(start_compile_job))
+        .route("/project/:project_id/pdf/:filename", get(get_pdf))
         .route(
-            "/project/:project_id/pdf/:filename",
-            axum::routing::get(get_pdf),
+            "/project/:project_id/pdf/:filename/provenance",
+            get(get_pdf_provenance),
         )
+        .route(
+            "/project/:project_id/pdf/:filename/pages",
+            get(get_pdf_pages),
+        )
+        .route(
+            "/project/:project_id/pdf/:filename/pages/:page/thumbnail",
+            get(get_page_thumbnail),
+        )
+        .route(
+            "/project/:project_id/pdf/:filename/synctex/forward",
+            get(synctex_forward),
+        )
+        .route(
+            "/project/:project_id/pdf/:filename/synctex/inverse",
+            get(synctex_inverse),
+        )
+        .route("/project/:project_id/history", get(list_history))
+        .route("/project/:project_id/stats", get(compile_stats))
+        .route(
+            "/project/:project_id/history/:compile_id",
+            get(get_history_entry),
+        )
+        .route(
+            "/project/:project_id/history/:compile_id/pdf",
+            get(get_history_pdf),
+        )
+        .route("/project/:project_id/artifacts", get(list_artifacts))
+        .route(
+            "/project/:project_id/artifacts/:filename",
+            get(download_artifact),
+        )
+        .route("/project/:project_id/log", get(get_log))
+        .route("/project/:project_id/clean", post(clean_build))
+        .route("/project/:project_id/wordcount", get(word_count))
+        .route("/project/:project_id/lint", post(lint_file))
+        .route("/jobs/:id", get(get_compile_job))
+        .route("/jobs/:id/cancel", post(cancel_compile_job))
+        .route("/jobs/:id/log/stream", get(stream_compile_log))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CompileRequest {
     pub main_file: Option<String>,
+    /// Overrides the project's engine setting for this compile
+    pub engine: Option<Engine>,
+    /// Overrides the project's bibliography tool setting for this compile
+    pub bib_tool: Option<BibTool>,
+    /// Overrides the project's shell escape setting for this compile
+    pub shell_escape: Option<bool>,
+    /// Build target from the project settings; `main_file`, `engine` and
+    /// `bib_tool` still override what it sets
+    pub target: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct CompileResponse {
+    pub compile_id: String,
     pub success: bool,
     pub pdf_url: Option<String>,
+    pub engine: Engine,
+    /// The end of the compile output; the whole of it is at `log_url`
     pub log: String,
+    /// Whether `log` was cut short
+    pub log_truncated: bool,
+    pub log_url: String,
     pub errors: Vec<CompileError>,
     pub warnings: Vec<CompileWarning>,
+    pub bibliography: Vec<BibIssue>,
+    pub missing_packages: Vec<MissingPackage>,
+    pub timed_out: bool,
+    /// What this run fixed and broke compared with the previous one
+    pub diagnostics_delta: Option<DiagnosticsDelta>,
 }
 
-#[derive(Debug, Serialize, Clone)]
-pub struct CompileError {
-    pub file: String,
-    pub line: Option<i32>,
-    pub message: String,
+impl CompileResponse {
+    fn new(job: &CompileJob, result: CompileResult) -> Self {
+        let (project_id, compile_id) = (&job.project_id, &job.id);
+        let (log, log_truncated) = log_tail(result.log);
+        let target_query = target_query(job.target.as_deref());
+        Self {
+            compile_id: compile_id.to_string(),
+            success: result.success,
+            pdf_url: result
+                .pdf_path
+                .map(|pdf| format!("/api/compile/project/{project_id}/pdf/{pdf}{target_query}")),
+            engine: result.engine,
+            log,
+            log_truncated,
+            log_url: format!("/api/compile/project/{project_id}/log?compile_id={compile_id}"),
+            errors: result.errors,
+            warnings: result.warnings,
+            bibliography: result.bibliography,
+            missing_packages: result.missing_packages,
+            timed_out: result.timed_out,
+            diagnostics_delta: result.diagnostics_delta,
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Clone)]
-pub struct CompileWarning {
-    pub file: String,
-    pub line: Option<i32>,
-    pub message: String,
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CompileJobResponse {
+    #[serde(flatten)]
+    pub job: JobInfo,
+    /// Present once the job has finished
+    pub result: Option<CompileResponse>,
+}
+
+impl CompileJobResponse {
+    fn new(job: &CompileJob) -> Self {
+        Self {
+            job: job.info(),
+            result: job.result().map(|result| CompileResponse::new(job, result)),
+        }
+    }
+}
+
+// Bytes of compile output included in a compile response; a runaway
+// document can print megabytes, which the log endpoint pages through instead
+const INLINE_LOG_LIMIT: usize = 64 * 1024;
+
+// The end of the log, where the errors that stopped a compile are
+fn log_tail(mut log: String) -> (String, bool) {
+    if log.len() <= INLINE_LOG_LIMIT {
+        return (log, false);
+    }
+    let mut start = log.len() - INLINE_LOG_LIMIT;
+    while !log.is_char_boundary(start) {
+        start += 1;
+    }
+    (log.split_off(start), true)
 }
 
 // Helper to check if user has access to project
 async fn check_project_access(
-    pool: &sqlx::SqlitePool,
+    pool: &crate::db::DbPool,
     project_id: &str,
     user_id: &str,
 ) -> Result<()> {
@@ -60,7 +191,7 @@ async fn check_project_access(
         r#"
         SELECT COUNT(*) FROM projects p
         LEFT JOIN project_collaborators pc ON p.id = pc.project_id
-        WHERE p.id = ? AND (p.owner_id = ? OR pc.user_id = ?)
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
         "#,
     )
     .bind(project_id)
@@ -75,168 +206,1323 @@ async fn check_project_access(
     Ok(())
 }
 
-fn parse_latex_log(log: &str) -> (Vec<CompileError>, Vec<CompileWarning>) {
-    let mut errors = Vec::new();
-    let mut warnings = Vec::new();
-
-    let lines: Vec<&str> = log.lines().collect();
-    let mut i = 0;
-
-    while i < lines.len() {
-        let line = lines[i];
-
-        // Look for error patterns
-        if line.starts_with('!') {
-            let message = line.trim_start_matches('!').trim().to_string();
-            let mut file = String::new();
-            let mut line_num = None;
-
-            // Look back for file:line pattern
-            for j in (0..i).rev() {
-                let prev_line = lines[j];
-                if let Some(pos) = prev_line.find(".tex:") {
-                    if let Some(colon_pos) = prev_line[pos + 5..].find(':') {
-                        let line_str = &prev_line[pos + 5..pos + 5 + colon_pos];
-                        line_num = line_str.parse().ok();
-                    } else if let Some(space_pos) = prev_line[pos + 5..].find(' ') {
-                        let line_str = &prev_line[pos + 5..pos + 5 + space_pos];
-                        line_num = line_str.parse().ok();
-                    }
-                    file = prev_line[..pos + 4].to_string();
-                    // Extract just the filename
-                    if let Some(last_paren) = file.rfind('(') {
-                        file = file[last_paren + 1..].to_string();
-                    }
-                    break;
-                }
-            }
+/// Validate the request and start compiling in the background. The compile
+/// keeps running if the client goes away, so its log can still be followed.
+async fn spawn_compile(
+    state: &AppState,
+    user: &AuthUser,
+    project_id: &str,
+    body: CompileRequest,
+) -> Result<(
+    Arc<CompileJob>,
+    tokio::task::JoinHandle<Result<CompileResult>>,
+)> {
+    check_project_access(&state.db.pool, project_id, &user.id).await?;
+
+    let settings = load_settings(&state.db.pool, project_id).await?;
+    let target = match &body.target {
+        Some(name) => Some(
+            settings
+                .targets
+                .iter()
+                .find(|target| target.name == *name)
+                .ok_or_else(|| AppError::NotFound(format!("Build target '{name}' not found")))?,
+        ),
+        None => None,
+    };
 
-            errors.push(CompileError {
-                file,
-                line: line_num,
-                message,
-            });
+    let project_path = state.storage.materialize(project_id).await?;
+    let main_file = compiler::normalize_project_path(
+        body.main_file
+            .as_deref()
+            .or(target.map(|target| target.main_file.as_str()))
+            .unwrap_or("main.tex"),
+    )?;
+
+    // Check if main file exists
+    let main_file_path = project_path.join(&main_file);
+    if !main_file_path.exists() {
+        return Err(AppError::NotFound(format!(
+            "Main file '{main_file}' not found"
+        )));
+    }
+
+    let options = CompileOptions {
+        main_file,
+        engine: body
+            .engine
+            .or(target.and_then(|target| target.engine))
+            .or(settings.engine)
+            .unwrap_or_default(),
+        bib_tool: body
+            .bib_tool
+            .or(target.and_then(|target| target.bib_tool))
+            .or(settings.bib_tool),
+        shell_escape: body.shell_escape.unwrap_or(settings.shell_escape),
+        trusted: is_trusted(state, project_id).await?,
+    };
+
+    let job = state
+        .compile_jobs
+        .start(project_id, &user.id, body.target.as_deref())?;
+    let handle = tokio::spawn(run_compile(
+        state.clone(),
+        job.clone(),
+        project_path,
+        options,
+        body.target,
+    ));
+    Ok((job, handle))
+}
+
+// Whether the project may run its own code, going by the server allowlist of
+// project and owner IDs. Not emails: registration does not verify them.
+pub(crate) async fn is_trusted(state: &AppState, project_id: &str) -> Result<bool> {
+    let allowlist = &state.config.compile.shell_escape_allowlist;
+    if allowlist
+        .iter()
+        .any(|entry| entry == "*" || entry == project_id)
+    {
+        return Ok(true);
+    }
+    if allowlist.is_empty() {
+        return Ok(false);
+    }
+
+    let owner_id = sqlx::query_scalar::<_, String>("SELECT owner_id FROM projects WHERE id = $1")
+        .bind(project_id)
+        .fetch_optional(&state.db.pool)
+        .await?;
+    Ok(owner_id.is_some_and(|owner_id| allowlist.contains(&owner_id)))
+}
+
+async fn run_compile(
+    state: AppState,
+    job: Arc<CompileJob>,
+    project_path: PathBuf,
+    options: CompileOptions,
+    target: Option<String>,
+) -> Result<CompileResult> {
+    let cache = BuildCache::new(
+        std::path::Path::new(&state.config.cache_path),
+        &job.project_id,
+        target.as_deref(),
+    );
+    let mut outcome = match state.compile_jobs.acquire(&job).await {
+        Some(_slot) => build(&state, &job, &project_path, &options, &cache).await,
+        None => Err(AppError::Conflict("Compile was cancelled".to_string())),
+    };
+    // Compared before this run is recorded, which makes it the previous one
+    if let Ok(result) = &mut outcome {
+        match compile_history::delta(&state.db.pool, &job.project_id, &options.main_file, result)
+            .await
+        {
+            Ok(delta) => result.diagnostics_delta = delta,
+            Err(e) => tracing::warn!("Failed to compare compile {} with the last: {}", job.id, e),
         }
+    }
 
-        // Look for warning patterns
-        if line.contains("Warning:") || line.contains("warning:") {
-            let message = line.to_string();
-            warnings.push(CompileWarning {
-                file: String::new(),
-                line: None,
-                message,
-            });
+    job.finish(&outcome);
+    let (queue, run) = job.timings();
+    let status = job.status();
+    let success =
+        status == JobStatus::Finished && outcome.as_ref().is_ok_and(|result| result.success);
+    state.metrics.record_compile(status, success, queue, run);
+    state.events.publish(
+        &job.project_id,
+        ProjectEvent::CompileFinished {
+            job_id: job.id.clone(),
+            status: status.as_str().to_string(),
+            success,
+        },
+    );
+    // A cancelled compile failed on purpose; anything else unsuccessful is news
+    if status != JobStatus::Cancelled && !success {
+        let message = match status {
+            JobStatus::TimedOut => "Your compile timed out",
+            _ => "Your compile failed",
+        };
+        state
+            .notifications
+            .notify(
+                std::slice::from_ref(&job.user_id),
+                NewNotification {
+                    kind: notifications::COMPILE_FAILED,
+                    project_id: job.project_id.clone(),
+                    actor_id: None,
+                    target_id: Some(job.id.clone()),
+                    file_path: None,
+                    message: message.to_string(),
+                },
+            )
+            .await;
+    }
+    if let Err(e) = compile_history::record(
+        &state.db.pool,
+        std::path::Path::new(&state.config.cache_path),
+        &state.config.compile,
+        &job,
+        &options,
+        &outcome,
+        cache.dir(),
+    )
+    .await
+    {
+        tracing::warn!("Failed to record compile {} in history: {}", job.id, e);
+    }
+    outcome
+}
+
+async fn build(
+    state: &AppState,
+    job: &CompileJob,
+    project_path: &std::path::Path,
+    options: &CompileOptions,
+    cache: &BuildCache,
+) -> Result<CompileResult> {
+    let fingerprint = BuildCache::fingerprint(options, &state.config.compile.backend);
+    let source_hash = match hash_sources(state, &job.project_id, project_path).await {
+        Ok(hash) => Some(hash),
+        Err(e) => {
+            tracing::warn!("Failed to hash sources of {}: {}", job.project_id, e);
+            None
         }
+    };
 
-        i += 1;
+    // Nothing changed since the last successful compile, so its PDF is current
+    if let Some(hash) = &source_hash {
+        if let Some(mut result) = cache.lookup(&fingerprint, hash).await {
+            job.append_log("Sources unchanged since the last compile, reusing its output\n\n");
+            job.append_log(&result.log);
+            result.log = job.log();
+            return Ok(result);
+        }
     }
 
-    (errors, warnings)
+    let outcome = match cache.prepare(&fingerprint).await {
+        Ok(build_dir) => {
+            compiler::compile(
+                project_path,
+                &build_dir,
+                options,
+                job,
+                &state.config.compile,
+            )
+            .await
+        }
+        Err(e) => Err(e),
+    };
+    cache
+        .record(&fingerprint, source_hash.clone(), &outcome)
+        .await;
+
+    if let Ok(CompileResult {
+        pdf_path: Some(pdf_name),
+        ..
+    }) = &outcome
+    {
+        if state.config.pdf_provenance {
+            stamp_provenance(job, cache.dir(), pdf_name, source_hash).await;
+        }
+
+        // Thumbnails are rendered in the background; until they're ready the
+        // thumbnail endpoint renders on demand
+        pdf_pages::clear_thumbnails(cache.dir(), pdf_name).await;
+        let build_dir = cache.dir().to_path_buf();
+        let pdf_name = pdf_name.clone();
+        tokio::spawn(async move {
+            if let Err(e) = pdf_pages::generate_thumbnails(&build_dir, &pdf_name).await {
+                tracing::warn!("Failed to render page thumbnails of {}: {}", pdf_name, e);
+            }
+        });
+    }
+    outcome
 }
 
+async fn hash_sources(
+    state: &AppState,
+    project_id: &str,
+    project_path: &std::path::Path,
+) -> Result<String> {
+    let source_files = sqlx::query_scalar::<_, String>(
+        "SELECT path FROM files WHERE project_id = $1 AND is_folder = FALSE",
+    )
+    .bind(project_id)
+    .fetch_all(&state.db.pool)
+    .await?;
+
+    provenance::source_hash(project_path, &source_files).await
+}
+
+async fn stamp_provenance(
+    job: &CompileJob,
+    build_dir: &std::path::Path,
+    pdf_name: &str,
+    source_hash: Option<String>,
+) {
+    let Some(source_hash) = source_hash else {
+        tracing::warn!(
+            "Skipping provenance for {}: sources could not be hashed",
+            pdf_name
+        );
+        return;
+    };
+    let stamp = Provenance {
+        project_id: job.project_id.clone(),
+        source_hash,
+        compile_id: job.id.clone(),
+        toolchain: provenance::toolchain_version().await,
+        compiled_at: Utc::now().to_rfc3339(),
+    };
+
+    // A PDF without provenance is still a usable PDF
+    if let Err(e) = provenance::stamp(&build_dir.join(pdf_name), &stamp).await {
+        tracing::warn!("Failed to stamp provenance into {}: {}", pdf_name, e);
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/compile/project/{project_id}",
+    tag = "compile",
+    params(("project_id" = String, Path, description = "Project id")),
+    request_body = CompileRequest,
+    responses((status = 200, body = CompileResponse))
+)]
 async fn compile_project(
     State(state): State<AppState>,
     user: AuthUser,
     Path(project_id): Path<String>,
     Json(body): Json<CompileRequest>,
 ) -> Result<Json<CompileResponse>> {
+    let (job, handle) = spawn_compile(&state, &user, &project_id, body).await?;
+
+    let result = handle
+        .await
+        .map_err(|e| AppError::Internal(format!("Compile task failed: {e}")))??;
+
+    Ok(Json(CompileResponse::new(&job, result)))
+}
+
+/// Start a compile without waiting for it; poll the job or stream its log.
+#[utoipa::path(
+    post,
+    path = "/api/compile/project/{project_id}/jobs",
+    tag = "compile",
+    params(("project_id" = String, Path, description = "Project id")),
+    request_body = CompileRequest,
+    responses((status = 200, body = CompileJobResponse))
+)]
+async fn start_compile_job(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(body): Json<CompileRequest>,
+) -> Result<Json<CompileJobResponse>> {
+    let (job, _) = spawn_compile(&state, &user, &project_id, body).await?;
+    Ok(Json(CompileJobResponse::new(&job)))
+}
+
+async fn fetch_job(state: &AppState, user: &AuthUser, id: &str) -> Result<Arc<CompileJob>> {
+    let job = state
+        .compile_jobs
+        .get(id)
+        .ok_or_else(|| AppError::NotFound("Compile job not found".to_string()))?;
+
+    check_project_access(&state.db.pool, &job.project_id, &user.id).await?;
+    Ok(job)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/compile/jobs/{id}",
+    tag = "compile",
+    params(("id" = String, Path, description = "Compile job id")),
+    responses((status = 200, body = CompileJobResponse))
+)]
+async fn get_compile_job(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<CompileJobResponse>> {
+    let job = fetch_job(&state, &user, &id).await?;
+    Ok(Json(CompileJobResponse::new(&job)))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/compile/jobs/{id}/cancel",
+    tag = "compile",
+    params(("id" = String, Path, description = "Compile job id")),
+    responses((status = 200, body = CompileJobResponse))
+)]
+async fn cancel_compile_job(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<CompileJobResponse>> {
+    let job = fetch_job(&state, &user, &id).await?;
+
+    if !job.cancel() {
+        return Err(AppError::Conflict("Compile job is not running".to_string()));
+    }
+    tracing::info!("User {} cancelled compile job {}", user.id, job.id);
+
+    Ok(Json(CompileJobResponse::new(&job)))
+}
+
+/// Server-sent events: `log` events carry JSON-encoded chunks of output
+/// (starting with everything produced so far) and a final `done` event
+/// carries the job status.
+#[utoipa::path(
+    get,
+    path = "/api/compile/jobs/{id}/log/stream",
+    tag = "compile",
+    params(("id" = String, Path, description = "Compile job id")),
+    responses((status = 200, description = "Server-sent events carrying the compile log as it is written", content_type = "text/event-stream", body = String))
+)]
+async fn stream_compile_log(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Sse<impl Stream<Item = std::result::Result<Event, Infallible>>>> {
+    let job = fetch_job(&state, &user, &id).await?;
+    let (backlog, status, receiver) = job.subscribe();
+
+    let backlog = (!backlog.is_empty()).then(|| log_event(&backlog));
+    let live = stream::unfold(
+        status.is_active().then_some(receiver),
+        |receiver| async move {
+            let mut receiver = receiver?;
+            loop {
+                match receiver.recv().await {
+                    Ok(LogEvent::Output(text)) => return Some((log_event(&text), Some(receiver))),
+                    Ok(LogEvent::Done(status)) => return Some((done_event(status), None)),
+                    // Skipped lines are still in the full log of the job
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+    let finished = (!status.is_active()).then(|| done_event(status));
+
+    let events = stream::iter(backlog)
+        .chain(live)
+        .chain(stream::iter(finished))
+        .map(Ok);
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+fn log_event(text: &str) -> Event {
+    Event::default()
+        .event("log")
+        .data(serde_json::Value::from(text).to_string())
+}
+
+fn done_event(status: JobStatus) -> Event {
+    Event::default()
+        .event("done")
+        .data(serde_json::json!({ "status": status }).to_string())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct PdfParams {
+    project_id: String,
+    /// Name of the file in the build output, such as main.pdf
+    filename: String,
+}
+
+/// Streams the PDF with byte range support for pdf.js, and answers
+/// conditional requests with 304 so an unchanged PDF isn't downloaded again.
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/pdf/{filename}",
+    tag = "compile",
+    params(
+        PdfParams,
+        TargetQuery,
+    ),
+    responses(
+        (status = 200, description = "The PDF; supports range requests", content_type = "application/pdf", body = Vec<u8>),
+        (status = 206, description = "The requested byte range", content_type = "application/pdf", body = Vec<u8>),
+    )
+)]
+async fn get_pdf(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(params): Path<PdfParams>,
+    Query(target): Query<TargetQuery>,
+    request: axum::extract::Request,
+) -> Result<axum::response::Response> {
+    use axum::body::Body;
+    use axum::http::{header, HeaderValue, Response, StatusCode};
+    use tower::ServiceExt;
+    use tower_http::services::ServeFile;
+
+    let pdf_path = compiled_pdf(&state, &user, &params, target.target.as_deref()).await?;
+
+    let metadata = tokio::fs::metadata(&pdf_path)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to read PDF: {e}")))?;
+    let etag = pdf_etag(&metadata);
+    let etag_value = HeaderValue::from_str(&etag)
+        .map_err(|e| AppError::Internal(format!("Invalid ETag: {e}")))?;
+
+    if etag_matches(request.headers().get(header::IF_NONE_MATCH), &etag) {
+        return Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, etag_value)
+            .body(Body::empty())
+            .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")));
+    }
+
+    // ServeFile handles Range, If-Modified-Since and Last-Modified
+    let mut response = ServeFile::new(&pdf_path)
+        .oneshot(request)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to read PDF: {e}")))?
+        .map(Body::new);
+
+    let headers = response.headers_mut();
+    headers.insert(header::ETAG, etag_value);
+    // Cached, but checked with the server each time since a recompile
+    // replaces the PDF under the same URL
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, no-cache"),
+    );
+    if let Ok(disposition) =
+        HeaderValue::from_str(&format!("inline; filename=\"{}\"", params.filename))
+    {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+
+    Ok(response)
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ArtifactResponse {
+    #[serde(flatten)]
+    pub artifact: Artifact,
+    pub url: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TargetQuery {
+    /// Build target; the default build when absent
+    pub target: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ArtifactsResponse {
+    /// The PDF of the last compile, if it succeeded
+    pub pdf_url: Option<String>,
+    pub artifacts: Vec<ArtifactResponse>,
+}
+
+// The build directory of a target a request names. The name becomes part of
+// a path, so only the project's own build targets are accepted.
+async fn target_cache(
+    state: &AppState,
+    project_id: &str,
+    target: Option<&str>,
+) -> Result<BuildCache> {
+    if let Some(name) = target {
+        let settings = load_settings(&state.db.pool, project_id).await?;
+        if !is_valid_target_name(name) || !settings.targets.iter().any(|t| t.name == name) {
+            return Err(AppError::NotFound(format!(
+                "Build target '{name}' not found"
+            )));
+        }
+    }
+    Ok(BuildCache::new(
+        std::path::Path::new(&state.config.cache_path),
+        project_id,
+        target,
+    ))
+}
+
+// The query string that names a build target in a build output URL
+fn target_query(target: Option<&str>) -> String {
+    target
+        .map(|target| {
+            format!(
+                "?target={}",
+                percent_encoding::utf8_percent_encode(target, percent_encoding::NON_ALPHANUMERIC)
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Everything the last build wrote, so clients don't have to guess names.
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/artifacts",
+    tag = "compile",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        TargetQuery,
+    ),
+    responses((status = 200, body = ArtifactsResponse))
+)]
+async fn list_artifacts(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Query(query): Query<TargetQuery>,
+) -> Result<Json<ArtifactsResponse>> {
     check_project_access(&state.db.pool, &project_id, &user.id).await?;
 
-    let project_path = std::path::Path::new(&state.config.storage_path).join(&project_id);
-    let main_file = body.main_file.unwrap_or_else(|| "main.tex".to_string());
+    let cache = target_cache(&state, &project_id, query.target.as_deref()).await?;
+    let target_query = target_query(query.target.as_deref());
+    let artifacts = cache
+        .artifacts()
+        .await?
+        .into_iter()
+        .map(|artifact| ArtifactResponse {
+            url: format!(
+                "/api/compile/project/{project_id}/artifacts/{}{target_query}",
+                artifact.name
+            ),
+            artifact,
+        })
+        .collect();
+    let pdf_url = cache
+        .last_pdf()
+        .await
+        .filter(|pdf| cache.artifact_path(pdf).is_some())
+        .map(|pdf| format!("/api/compile/project/{project_id}/pdf/{pdf}{target_query}"));
 
-    // Check if main file exists
-    let main_file_path = project_path.join(&main_file);
-    if !main_file_path.exists() {
+    Ok(Json(ArtifactsResponse { pdf_url, artifacts }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/artifacts/{filename}",
+    tag = "compile",
+    params(
+        PdfParams,
+        TargetQuery,
+    ),
+    responses(
+        (status = 200, description = "The build output file", content_type = "application/octet-stream", body = Vec<u8>),
+        (status = 206, description = "The requested byte range", content_type = "application/octet-stream", body = Vec<u8>),
+    )
+)]
+async fn download_artifact(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(params): Path<PdfParams>,
+    Query(query): Query<TargetQuery>,
+    request: axum::extract::Request,
+) -> Result<axum::response::Response> {
+    use axum::body::Body;
+    use axum::http::{header, HeaderValue};
+    use tower::ServiceExt;
+    use tower_http::services::ServeFile;
+
+    check_project_access(&state.db.pool, &params.project_id, &user.id).await?;
+
+    let path = target_cache(&state, &params.project_id, query.target.as_deref())
+        .await?
+        .artifact_path(&params.filename)
+        .ok_or_else(|| AppError::NotFound("Build output not found".to_string()))?;
+
+    let mut response = ServeFile::new(&path)
+        .oneshot(request)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to read build output: {e}")))?
+        .map(Body::new);
+
+    let headers = response.headers_mut();
+    headers.insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("private, no-cache"),
+    );
+    if let Ok(disposition) =
+        HeaderValue::from_str(&format!("attachment; filename=\"{}\"", params.filename))
+    {
+        headers.insert(header::CONTENT_DISPOSITION, disposition);
+    }
+
+    Ok(response)
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSource {
+    /// What latexmk printed during the compile
+    #[default]
+    Output,
+    /// The .log file TeX wrote into the build directory
+    Tex,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct LogQuery {
+    /// Compile run to read; the most recent when absent
+    pub compile_id: Option<String>,
+    #[serde(default)]
+    #[param(inline)]
+    pub source: LogSource,
+    /// Build target whose directory holds the TeX log
+    pub target: Option<String>,
+    /// First byte to return
+    #[serde(default)]
+    pub offset: u64,
+    /// Most bytes to return
+    pub limit: Option<u64>,
+}
+
+// Bytes returned by one log request, and the most a request may ask for
+const LOG_PAGE: u64 = 256 * 1024;
+const MAX_LOG_PAGE: u64 = 1024 * 1024;
+
+/// The raw log of a compile, a page at a time. Pages are chosen with
+/// `offset` and `limit` or a `Range` header; `X-Log-Size` gives the size of
+/// the whole log and `X-Next-Offset` where the next page starts, if any.
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/log",
+    tag = "compile",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        LogQuery,
+    ),
+    responses(
+        (status = 200, description = "The log, or the page of it asked for", content_type = "text/plain", body = String),
+        (status = 206, description = "The requested byte range", content_type = "text/plain", body = String),
+        (status = 416, description = "The range lies past the end of the log"),
+    )
+)]
+async fn get_log(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Query(query): Query<LogQuery>,
+    headers: axum::http::HeaderMap,
+) -> Result<axum::response::Response> {
+    use axum::body::Body;
+    use axum::http::{header, Response, StatusCode};
+
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    let compile_id = match query.compile_id {
+        Some(id) => id,
+        None => compile_history::list(&state.db.pool, &project_id, 1)
+            .await?
+            .pop()
+            .map(|record| record.id)
+            .ok_or_else(|| AppError::NotFound("The project has not been compiled".to_string()))?,
+    };
+    // A job that is running or just finished has its output in memory
+    let job = state
+        .compile_jobs
+        .get(&compile_id)
+        .filter(|job| job.project_id == project_id);
+    let log = match (query.source, job) {
+        (LogSource::Output, Some(job)) => job.log().into_bytes(),
+        (LogSource::Output, None) => compile_history::get(&state.db.pool, &project_id, &compile_id)
+            .await?
+            .log
+            .into_bytes(),
+        (LogSource::Tex, _) => {
+            let record = compile_history::get(&state.db.pool, &project_id, &compile_id).await?;
+            let path = target_cache(&state, &project_id, query.target.as_deref())
+                .await?
+                .artifact_path(&compiler::output_name(&record.record.main_file, "log"))
+                .ok_or_else(|| AppError::NotFound("The build has no TeX log".to_string()))?;
+            tokio::fs::read(&path)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to read TeX log: {e}")))?
+        }
+    };
+
+    let size = log.len() as u64;
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| value.trim_start().starts_with("bytes="))
+        .map(|value| byte_range(value, size));
+    let (start, end) = match range {
+        Some(Some((start, end))) => (start, end.min(start + MAX_LOG_PAGE)),
+        Some(None) => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{size}"))
+                .body(Body::empty())
+                .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")));
+        }
+        None => {
+            let start = query.offset.min(size);
+            let limit = query.limit.unwrap_or(LOG_PAGE).clamp(1, MAX_LOG_PAGE);
+            (start, (start + limit).min(size))
+        }
+    };
+
+    let mut response = Response::builder()
+        .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+        .header(header::CACHE_CONTROL, "private, no-cache")
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header("X-Log-Size", size);
+    if end < size {
+        response = response.header("X-Next-Offset", end);
+    }
+    if range.is_some() {
+        response = response.status(StatusCode::PARTIAL_CONTENT).header(
+            header::CONTENT_RANGE,
+            format!("bytes {start}-{}/{size}", end - 1),
+        );
+    }
+    response
+        .body(Body::from(log[start as usize..end as usize].to_vec()))
+        .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")))
+}
+
+// The half-open span of a single `bytes=` range, or None if it can't be served
+fn byte_range(value: &str, size: u64) -> Option<(u64, u64)> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    let (first, last) = spec.trim().split_once('-')?;
+    let (start, end) = match (first.trim(), last.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            (size.saturating_sub(suffix), size)
+        }
+        (first, "") => (first.parse().ok()?, size),
+        (first, last) => {
+            let last: u64 = last.parse().ok()?;
+            (first.parse().ok()?, last.saturating_add(1).min(size))
+        }
+    };
+    (start < end).then_some((start, end))
+}
+
+// Size and modification time change with every compile that rewrites the PDF
+fn pdf_etag(metadata: &std::fs::Metadata) -> String {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_nanos());
+    format!("\"{:x}-{:x}\"", metadata.len(), modified)
+}
+
+fn etag_matches(if_none_match: Option<&axum::http::HeaderValue>, etag: &str) -> bool {
+    let Some(value) = if_none_match.and_then(|value| value.to_str().ok()) else {
+        return false;
+    };
+    value
+        .split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/pdf/{filename}/provenance",
+    tag = "compile",
+    params(
+        PdfParams,
+        TargetQuery,
+    ),
+    responses((status = 200, body = Provenance))
+)]
+async fn get_pdf_provenance(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(params): Path<PdfParams>,
+    Query(target): Query<TargetQuery>,
+) -> Result<Json<Provenance>> {
+    let pdf_path = compiled_pdf(&state, &user, &params, target.target.as_deref()).await?;
+
+    provenance::read(&pdf_path)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("PDF has no provenance metadata".to_string()))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ForwardSearchQuery {
+    /// Source file relative to the project root
+    pub file: String,
+    pub line: u32,
+    #[serde(default)]
+    pub column: u32,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ForwardSearchResponse {
+    pub locations: Vec<PdfLocation>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct InverseSearchQuery {
+    pub page: u32,
+    /// PDF points from the left edge of the page
+    pub x: f64,
+    /// PDF points from the top edge of the page
+    pub y: f64,
+}
+
+/// Path of a compiled PDF in the build directory of the named target, or in
+/// whichever of the project's build directories has it when none is named.
+async fn compiled_pdf(
+    state: &AppState,
+    user: &AuthUser,
+    params: &PdfParams,
+    target: Option<&str>,
+) -> Result<PathBuf> {
+    check_project_access(&state.db.pool, &params.project_id, &user.id).await?;
+
+    let filename = &params.filename;
+    if !filename.ends_with(".pdf") || filename.contains('/') || filename.starts_with('.') {
+        return Err(AppError::NotFound("PDF not found".to_string()));
+    }
+
+    let cache = match target {
+        Some(_) => Some(target_cache(state, &params.project_id, target).await?),
+        None => {
+            BuildCache::containing(
+                std::path::Path::new(&state.config.cache_path),
+                &params.project_id,
+                filename,
+            )
+            .await
+        }
+    };
+    cache
+        .and_then(|cache| cache.artifact_path(filename))
+        .ok_or_else(|| AppError::NotFound("PDF not found".to_string()))
+}
+
+/// Source position to PDF boxes, for jumping from the editor to the preview.
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/pdf/{filename}/synctex/forward",
+    tag = "compile",
+    params(
+        PdfParams,
+        ForwardSearchQuery,
+        TargetQuery,
+    ),
+    responses((status = 200, body = ForwardSearchResponse))
+)]
+async fn synctex_forward(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(params): Path<PdfParams>,
+    Query(query): Query<ForwardSearchQuery>,
+    Query(target): Query<TargetQuery>,
+) -> Result<Json<ForwardSearchResponse>> {
+    let pdf_path = compiled_pdf(&state, &user, &params, target.target.as_deref()).await?;
+
+    if query.file.split('/').any(|part| part == "..") {
+        return Err(AppError::BadRequest("Invalid file path".to_string()));
+    }
+
+    let project_path = state.storage.project_path(&params.project_id);
+    let locations = synctex::forward(
+        &project_path,
+        &pdf_path,
+        &query.file,
+        query.line,
+        query.column,
+    )
+    .await?;
+    Ok(Json(ForwardSearchResponse { locations }))
+}
+
+/// PDF point to source position, for jumping from the preview to the editor.
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/pdf/{filename}/synctex/inverse",
+    tag = "compile",
+    params(
+        PdfParams,
+        InverseSearchQuery,
+        TargetQuery,
+    ),
+    responses((status = 200, body = SourceLocation))
+)]
+async fn synctex_inverse(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(params): Path<PdfParams>,
+    Query(query): Query<InverseSearchQuery>,
+    Query(target): Query<TargetQuery>,
+) -> Result<Json<SourceLocation>> {
+    let pdf_path = compiled_pdf(&state, &user, &params, target.target.as_deref()).await?;
+
+    let project_path = state.storage.project_path(&params.project_id);
+    synctex::inverse(&project_path, &pdf_path, query.page, query.x, query.y)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("No source location at this point".to_string()))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct WordCountQuery {
+    pub main_file: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WordCountResponse {
+    pub main_file: String,
+    #[serde(flatten)]
+    pub count: WordCount,
+}
+
+/// Word count of the main file and everything it includes.
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/wordcount",
+    tag = "compile",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        WordCountQuery,
+    ),
+    responses((status = 200, body = WordCountResponse))
+)]
+async fn word_count(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Query(query): Query<WordCountQuery>,
+) -> Result<Json<WordCountResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    let main_file =
+        compiler::normalize_project_path(query.main_file.as_deref().unwrap_or("main.tex"))?;
+
+    let project_path = state.storage.materialize(&project_id).await?;
+    if !project_path.join(&main_file).is_file() {
         return Err(AppError::NotFound(format!(
             "Main file '{main_file}' not found"
         )));
     }
 
-    // Clean auxiliary files first to ensure fresh compilation
-    let _ = Command::new("latexmk")
-        .args(["-C", &main_file])
-        .current_dir(&project_path)
-        .output();
-
-    // Run latexmk with -g to force regeneration
-    let output = Command::new("latexmk")
-        .args([
-            "-pdf",
-            "-g",
-            "-interaction=nonstopmode",
-            "-file-line-error",
-            &main_file,
-        ])
-        .current_dir(&project_path)
-        .output()
-        .map_err(|e| AppError::Internal(format!("Failed to run latexmk: {e}")))?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
-    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-    let log = format!("{stdout}\n{stderr}");
-
-    let (errors, warnings) = parse_latex_log(&log);
-
-    let pdf_name = main_file.replace(".tex", ".pdf");
-    let pdf_path = project_path.join(&pdf_name);
-
-    // Consider compilation successful if PDF exists, even if latexmk reported warnings
-    let pdf_exists = pdf_path.exists();
-    let success = pdf_exists;
-
-    let pdf_url = if pdf_exists {
-        Some(format!("/api/compile/project/{project_id}/pdf/{pdf_name}"))
-    } else {
-        None
-    };
+    let count = wordcount::count(&project_path, &main_file).await?;
+    Ok(Json(WordCountResponse { main_file, count }))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LintRequest {
+    /// Source file relative to the project root
+    pub file: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LintResponse {
+    pub file: String,
+    pub warnings: Vec<LintWarning>,
+}
+
+/// Run chktex on one file, for editor diagnostics without a full compile.
+#[utoipa::path(
+    post,
+    path = "/api/compile/project/{project_id}/lint",
+    tag = "compile",
+    params(("project_id" = String, Path, description = "Project id")),
+    request_body = LintRequest,
+    responses((status = 200, body = LintResponse))
+)]
+async fn lint_file(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(body): Json<LintRequest>,
+) -> Result<Json<LintResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    if body.file.split('/').any(|part| part == "..") {
+        return Err(AppError::BadRequest("Invalid file path".to_string()));
+    }
 
-    Ok(Json(CompileResponse {
-        success,
-        pdf_url,
-        log,
-        errors,
+    let project_path = state.storage.materialize(&project_id).await?;
+    if !project_path.join(&body.file).is_file() {
+        return Err(AppError::NotFound(format!(
+            "File '{}' not found",
+            body.file
+        )));
+    }
+
+    let warnings = lint::check(&project_path, &body.file).await?;
+    Ok(Json(LintResponse {
+        file: body.file,
         warnings,
     }))
 }
 
-#[derive(Debug, Deserialize)]
-pub struct PdfParams {
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct HistoryQuery {
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct HistoryResponse {
+    pub compiles: Vec<CompileRecord>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct HistoryParams {
     project_id: String,
-    filename: String,
+    /// Id of the compile run, as listed in the history
+    compile_id: String,
 }
 
-async fn get_pdf(
+/// Past compile runs, newest first, without their logs.
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/history",
+    tag = "compile",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        HistoryQuery,
+    ),
+    responses((status = 200, body = HistoryResponse))
+)]
+async fn list_history(
     State(state): State<AppState>,
     user: AuthUser,
-    Path(params): Path<PdfParams>,
+    Path(project_id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let compiles = compile_history::list(&state.db.pool, &project_id, limit).await?;
+    Ok(Json(HistoryResponse { compiles }))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct StatsQuery {
+    /// How far back to look, in days
+    pub days: Option<i64>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/stats",
+    tag = "compile",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        StatsQuery,
+    ),
+    responses((status = 200, body = CompileStats))
+)]
+async fn compile_stats(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<CompileStats>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    let days = query.days.unwrap_or(30).clamp(1, 365);
+    let stats = compile_history::stats(&state.db.pool, &project_id, days).await?;
+    Ok(Json(stats))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/history/{compile_id}",
+    tag = "compile",
+    params(HistoryParams),
+    responses((status = 200, body = CompileRecordDetail))
+)]
+async fn get_history_entry(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(params): Path<HistoryParams>,
+) -> Result<Json<CompileRecordDetail>> {
+    check_project_access(&state.db.pool, &params.project_id, &user.id).await?;
+
+    let record =
+        compile_history::get(&state.db.pool, &params.project_id, &params.compile_id).await?;
+    Ok(Json(record))
+}
+
+/// The PDF a past run produced, while it is still kept.
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/history/{compile_id}/pdf",
+    tag = "compile",
+    params(HistoryParams),
+    responses((status = 200, description = "The PDF that compile produced", content_type = "application/pdf", body = Vec<u8>))
+)]
+async fn get_history_pdf(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(params): Path<HistoryParams>,
 ) -> Result<axum::response::Response> {
     use axum::body::Body;
     use axum::http::{header, Response, StatusCode};
 
     check_project_access(&state.db.pool, &params.project_id, &user.id).await?;
 
-    let pdf_path = std::path::Path::new(&state.config.storage_path)
-        .join(&params.project_id)
-        .join(&params.filename);
-
-    if !pdf_path.exists() || !params.filename.ends_with(".pdf") {
-        return Err(AppError::NotFound("PDF not found".to_string()));
+    let record =
+        compile_history::get(&state.db.pool, &params.project_id, &params.compile_id).await?;
+    if !record.record.has_pdf {
+        return Err(AppError::NotFound(
+            "PDF of this compile is no longer kept".to_string(),
+        ));
     }
 
+    let pdf_path = compile_history::pdf_path(
+        std::path::Path::new(&state.config.cache_path),
+        &params.project_id,
+        &params.compile_id,
+    );
     let pdf_data = tokio::fs::read(&pdf_path)
         .await
-        .map_err(|e| AppError::Internal(format!("Failed to read PDF: {e}")))?;
+        .map_err(|_| AppError::NotFound("PDF of this compile is no longer kept".to_string()))?;
 
-    let response = Response::builder()
+    Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "application/pdf")
         .header(
             header::CONTENT_DISPOSITION,
-            format!("inline; filename=\"{}\"", params.filename),
+            format!("inline; filename=\"{}.pdf\"", params.compile_id),
         )
         .body(Body::from(pdf_data))
-        .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")))?;
+        .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")))
+}
 
-    Ok(response)
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PdfPage {
+    #[serde(flatten)]
+    pub info: PageInfo,
+    pub thumbnail_url: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PdfPagesResponse {
+    pub page_count: usize,
+    pub pages: Vec<PdfPage>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct PageParams {
+    project_id: String,
+    filename: String,
+    /// Page number, starting at 1
+    page: u32,
+}
+
+/// Page count and sizes, for laying out the preview before pages load.
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/pdf/{filename}/pages",
+    tag = "compile",
+    params(
+        PdfParams,
+        TargetQuery,
+    ),
+    responses((status = 200, body = PdfPagesResponse))
+)]
+async fn get_pdf_pages(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(params): Path<PdfParams>,
+    Query(target): Query<TargetQuery>,
+) -> Result<Json<PdfPagesResponse>> {
+    let pdf_path = compiled_pdf(&state, &user, &params, target.target.as_deref()).await?;
+
+    let pages: Vec<PdfPage> = pdf_pages::pages(&pdf_path)
+        .await?
+        .into_iter()
+        .map(|info| PdfPage {
+            thumbnail_url: format!(
+                "/api/compile/project/{}/pdf/{}/pages/{}/thumbnail{}",
+                params.project_id,
+                params.filename,
+                info.page,
+                target_query(target.target.as_deref())
+            ),
+            info,
+        })
+        .collect();
+    Ok(Json(PdfPagesResponse {
+        page_count: pages.len(),
+        pages,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/compile/project/{project_id}/pdf/{filename}/pages/{page}/thumbnail",
+    tag = "compile",
+    params(
+        PageParams,
+        TargetQuery,
+    ),
+    responses((status = 200, description = "PNG rendering of the page", content_type = "image/png", body = Vec<u8>))
+)]
+async fn get_page_thumbnail(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(params): Path<PageParams>,
+    Query(target): Query<TargetQuery>,
+) -> Result<axum::response::Response> {
+    use axum::body::Body;
+    use axum::http::{header, Response, StatusCode};
+
+    let pdf = PdfParams {
+        project_id: params.project_id,
+        filename: params.filename,
+    };
+    let pdf_path = compiled_pdf(&state, &user, &pdf, target.target.as_deref()).await?;
+    let build_dir = pdf_path
+        .parent()
+        .ok_or_else(|| AppError::Internal("PDF has no build directory".to_string()))?;
+
+    let thumbnail = pdf_pages::thumbnail_path(build_dir, &pdf.filename, params.page);
+    if !pdf_pages::thumbnail_dir(build_dir, &pdf.filename).exists() {
+        // Still rendering after the compile, or the render failed
+        if let Err(e) = pdf_pages::generate_thumbnails(build_dir, &pdf.filename).await {
+            // A concurrent render may have finished first
+            if !thumbnail.exists() {
+                return Err(e);
+            }
+        }
+    }
+    let png = tokio::fs::read(&thumbnail)
+        .await
+        .map_err(|_| AppError::NotFound("Page not found".to_string()))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/png")
+        .header(header::CACHE_CONTROL, "private, no-cache")
+        .body(Body::from(png))
+        .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")))
+}
+
+/// Delete the project's build directory, so the next compile starts from
+/// scratch without stale .aux/.toc files.
+#[utoipa::path(
+    post,
+    path = "/api/compile/project/{project_id}/clean",
+    tag = "compile",
+    params(("project_id" = String, Path, description = "Project id")),
+    responses((status = 200, description = "Done"))
+)]
+async fn clean_build(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<()>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    if state.compile_jobs.has_active(&project_id) {
+        return Err(AppError::Conflict(
+            "A compile of this project is in progress".to_string(),
+        ));
+    }
+
+    BuildCache::clear_project(std::path::Path::new(&state.config.cache_path), &project_id).await?;
+    tracing::info!("User {} cleaned build of project {}", user.id, project_id);
+
+    Ok(Json(()))
 }
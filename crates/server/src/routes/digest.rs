@@ -0,0 +1,82 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::digest::{self, ProjectChanges},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/:id/changes", get(get_changes))
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ChangesQuery {
+    /// RFC 3339 time or YYYY-MM-DD date; defaults to a day ago
+    pub since: Option<String>,
+}
+
+/// Files changed, lines added and removed, comments added and compiles run
+/// since a moment, for catching up on a project.
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/changes",
+    tag = "projects",
+    params(("id" = String, Path, description = "Project id"), ChangesQuery),
+    responses((status = 200, body = ProjectChanges))
+)]
+async fn get_changes(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<ProjectChanges>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    // In the format timestamps are stored in, so the two compare as text
+    let since = match query.since.as_deref() {
+        None => (Utc::now() - Duration::days(1)).to_rfc3339(),
+        Some(value) => match DateTime::parse_from_rfc3339(value) {
+            Ok(time) => time.with_timezone(&Utc).to_rfc3339(),
+            Err(_) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .map_err(|_| AppError::Validation(format!("Invalid time: {value}")))?
+                .format("%Y-%m-%d")
+                .to_string(),
+        },
+    };
+
+    let changes = digest::since(&state.db.pool, &id, &since).await?;
+    Ok(Json(changes))
+}
@@ -0,0 +1,159 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::Response,
+    routing::post,
+    Json, Router,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use super::{files::content_disposition, projects::load_settings};
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{
+        build_cache::BuildCache,
+        compiler,
+        export::{self, ExportFormat},
+        submission,
+    },
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/:id/export/:format", post(export_project))
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ExportRequest {
+    pub main_file: Option<String>,
+    /// Build target from the project settings whose main file to export
+    pub target: Option<String>,
+}
+
+/// Convert the main file to docx, html or epub and download the result, or
+/// download it as a submission bundle for arXiv or a journal.
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/export/{format}",
+    tag = "export",
+    params(
+        ("id" = String, Path, description = "Project id"),
+        ("format" = String, Path, description = "docx, html, epub or submission"),
+    ),
+    request_body = Option<ExportRequest>,
+    responses((status = 200, description = "The exported document or submission bundle", content_type = "application/octet-stream", body = Vec<u8>))
+)]
+async fn export_project(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, format)): Path<(String, String)>,
+    body: Option<Json<ExportRequest>>,
+) -> Result<Response> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+    let body = body.map(|Json(body)| body).unwrap_or_default();
+    // None for a submission bundle
+    let format = match format.as_str() {
+        "submission" => None,
+        other => Some(ExportFormat::parse(other).ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Cannot export to '{other}'; choose docx, html, epub or submission"
+            ))
+        })?),
+    };
+
+    let settings = load_settings(&state.db.pool, &id).await?;
+    let target = match &body.target {
+        Some(name) => Some(
+            settings
+                .targets
+                .iter()
+                .find(|target| target.name == *name)
+                .ok_or_else(|| AppError::NotFound(format!("Build target '{name}' not found")))?,
+        ),
+        None => None,
+    };
+    let main_file = compiler::normalize_project_path(
+        body.main_file
+            .as_deref()
+            .or(target.map(|target| target.main_file.as_str()))
+            .unwrap_or("main.tex"),
+    )?;
+
+    // Include what is being typed right now
+    state.collab.persist_project(&id).await?;
+    let project_path = state.storage.materialize(&id).await?;
+    if !project_path.join(&main_file).is_file() {
+        return Err(AppError::NotFound(format!(
+            "Main file '{main_file}' not found"
+        )));
+    }
+
+    let main_name = main_file.rsplit('/').next().unwrap_or(&main_file);
+    let stem = main_name
+        .rsplit_once('.')
+        .map_or(main_name, |(stem, _)| stem);
+
+    let (document, content_type, file_name) = match format {
+        Some(format) => (
+            export::export(&project_path, &main_file, format, &state.config.compile).await?,
+            format.content_type(),
+            format!("{stem}.{}", format.extension()),
+        ),
+        None => {
+            // The bibliography of the last compile of this main file
+            let bbl = BuildCache::new(
+                std::path::Path::new(&state.config.cache_path),
+                &id,
+                body.target.as_deref(),
+            )
+            .artifact_path(&format!("{stem}.bbl"));
+            let bbl = match bbl {
+                Some(path) => Some(tokio::fs::read(&path).await.map_err(|e| {
+                    AppError::Internal(format!("Failed to read bibliography: {e}"))
+                })?),
+                None => None,
+            };
+            (
+                submission::bundle(&project_path, &main_file, bbl).await?,
+                "application/gzip",
+                format!("{stem}-submission.tar.gz"),
+            )
+        }
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_DISPOSITION, content_disposition(&file_name))
+        .header(header::CONTENT_LENGTH, document.len())
+        .header("X-Content-Type-Options", "nosniff")
+        .body(Body::from(document))
+        .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")))
+}
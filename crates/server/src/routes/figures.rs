@@ -0,0 +1,62 @@
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Json, Router,
+};
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::figures::{self, FigureReport},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/:id/figures", get(get_figures))
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+/// The project's image files, which .tex files include each of them, and
+/// which nothing includes any more.
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/figures",
+    tag = "figures",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = FigureReport))
+)]
+async fn get_figures(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<FigureReport>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    // A figure included a moment ago must not be reported as unused
+    state.collab.persist_project(&id).await?;
+    let report = figures::list(&state.db.pool, &state.storage, &id).await?;
+    Ok(Json(report))
+}
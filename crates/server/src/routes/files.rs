@@ -1,15 +1,30 @@
 use axum::{
-    extract::{Multipart, Path, State},
+    extract::{Multipart, Path, Query, State},
     routing::{get, post},
     Json, Router,
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 use crate::{
+    db::{sql_now, Db, DbConnection, DbPool},
     error::{AppError, Result},
     middleware::auth::AuthUser,
+    services::{
+        compiler,
+        convert::{self, TargetFormat},
+        diff::{self, DiffResult},
+        events::ProjectEvent,
+        exclude::ExcludeRules,
+        filetype, freeze, history,
+        outline::{self, Outline},
+        reconcile::{self, RescanReport},
+        search,
+        storage::{content_hash, StorageService},
+        thumbnail, versions,
+    },
     AppState,
 };
 
@@ -21,14 +36,28 @@ pub fn router() -> Router<AppState> {
             get(|| async { "ok" }).post(create_file),
         )
         .route("/project/:project_id/upload", post(upload_files))
+        .route("/project/:project_id/bulk", post(bulk_operations))
+        .route("/project/:project_id/rescan", post(rescan_project))
+        .route(
+            "/project/:project_id/order",
+            axum::routing::put(reorder_files),
+        )
         .route("/:id", get(get_file).put(update_file).delete(delete_file))
         .route(
             "/:id/content",
             get(get_file_content).put(update_file_content),
         )
+        .route("/:id/diff", get(diff_file))
+        .route("/:id/outline", get(get_outline))
+        .route("/:id/verify", get(verify_file))
+        .route("/:id/lock", post(lock_file).delete(unlock_file))
+        .route("/:id/copy-to", post(copy_file_to))
+        .route("/:id/convert", post(convert_file))
+        .route("/:id/download", get(download_file))
+        .route("/:id/thumbnail", get(get_thumbnail))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateFileRequest {
     pub name: String,
     pub path: String,
@@ -36,47 +65,241 @@ pub struct CreateFileRequest {
     pub content: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct UpdateFileRequest {
     pub name: Option<String>,
     pub path: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct UpdateContentRequest {
     pub content: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DiffQuery {
+    /// Old side of the diff: a project version, by id or label, or the
+    /// number of an entry in the file's editing history
+    pub from: Option<String>,
+    /// New side of the diff, given the same way
+    pub to: Option<String>,
+    pub context: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ThumbnailQuery {
+    pub w: Option<u32>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ReorderRequest {
+    /// Folder whose children are being ordered; empty or omitted for the project root
+    #[serde(default)]
+    pub folder: String,
+    /// Child file ids in their new order
+    pub ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CopyToRequest {
+    pub project_id: String,
+    /// Destination path; defaults to the source path
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ConvertRequest {
+    pub format: TargetFormat,
+    /// Destination path; defaults to the source path with the new extension
+    pub path: Option<String>,
+    /// Replace an existing file at the destination
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct LockRequest {
+    /// Lock lifetime; defaults to DEFAULT_LOCK_TTL_SECS
+    pub ttl_seconds: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct DiffRequest {
+    /// Old side of the diff as raw content, instead of a revision in the
+    /// query; defaults to the current file content
+    pub from: Option<String>,
+    /// New side of the diff as raw content; defaults to the current file
+    /// content
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
 pub struct FileResponse {
     pub id: String,
     pub project_id: String,
     pub name: String,
     pub path: String,
     pub is_folder: bool,
+    pub hash: Option<String>,
+    pub mime_type: Option<String>,
+    /// Editor language; None for binary files and folders
+    pub language: Option<String>,
+    /// Position within the parent folder; None sorts alphabetically after ordered files
+    pub sort_order: Option<i64>,
+    /// Holder of an unexpired lock, if any
+    pub locked_by: Option<String>,
+    pub lock_expires_at: Option<String>,
+}
+
+// Custom order first, then folders before files, alphabetically
+const FILE_ORDER: &str = "sort_order IS NULL, sort_order ASC, is_folder DESC, path ASC";
+
+// Lock expiry is stored in this format so the database can compare it as text
+const LOCK_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%SZ";
+const DEFAULT_LOCK_TTL_SECS: u64 = 300;
+const MAX_LOCK_TTL_SECS: u64 = 3600;
+
+// Columns selected into a FileResponse; expired locks read as unlocked
+const FILE_COLUMNS: &str = concat!(
+    "id, project_id, name, path, is_folder, hash, mime_type, language, sort_order, ",
+    "CASE WHEN lock_expires_at > ",
+    sql_now!(),
+    " THEN locked_by END AS locked_by, ",
+    "CASE WHEN lock_expires_at > ",
+    sql_now!(),
+    " THEN lock_expires_at END AS lock_expires_at",
+);
+
+pub(super) async fn fetch_file<'e, E>(executor: E, id: &str) -> Result<FileResponse>
+where
+    E: sqlx::Executor<'e, Database = Db>,
+{
+    sqlx::query_as::<_, FileResponse>(&format!("SELECT {FILE_COLUMNS} FROM files WHERE id = $1"))
+        .bind(id)
+        .fetch_optional(executor)
+        .await?
+        .ok_or_else(|| AppError::NotFound("File not found".to_string()))
+}
+
+fn parent_of(path: &str) -> &str {
+    path.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+fn file_name_of(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+// A new name can change the extension, and with it the detected type
+async fn refresh_file_type<'e, E>(
+    executor: E,
+    storage: &StorageService,
+    project_id: &str,
+    file_id: &str,
+    path: &str,
+) -> Result<filetype::FileType>
+where
+    E: sqlx::Executor<'e, Database = Db>,
+{
+    let content = storage.read_bytes(project_id, path).await?;
+    let file_type = filetype::detect(path, &content);
+
+    sqlx::query("UPDATE files SET mime_type = $1, language = $2 WHERE id = $3")
+        .bind(&file_type.mime_type)
+        .bind(&file_type.language)
+        .bind(file_id)
+        .execute(executor)
+        .await?;
+
+    Ok(file_type)
 }
 
-#[derive(Debug, Serialize)]
+// Reject changes to a file locked by someone else
+pub(super) fn ensure_unlocked(file: &FileResponse, user_id: &str) -> Result<()> {
+    match &file.locked_by {
+        Some(holder) if holder != user_id => Err(AppError::Conflict(format!(
+            "File is locked until {}",
+            file.lock_expires_at.as_deref().unwrap_or("unknown")
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VerifyResponse {
+    pub id: String,
+    pub stored_hash: Option<String>,
+    pub actual_hash: String,
+    pub matches: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct FileListResponse {
     pub files: Vec<FileResponse>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct FileContentResponse {
     pub content: String,
+    pub total_lines: usize,
+    /// First and last line (1-based, inclusive) of `content` for ranged reads
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start_line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_line: Option<usize>,
+}
+
+impl FileContentResponse {
+    fn full(content: String) -> Self {
+        Self {
+            total_lines: content.lines().count(),
+            content,
+            start_line: None,
+            end_line: None,
+        }
+    }
+
+    /// Keep only lines `start..=end` (1-based); either bound may be open.
+    fn slice(content: String, start: Option<usize>, end: Option<usize>) -> Result<Self> {
+        if start.is_none() && end.is_none() {
+            return Ok(Self::full(content));
+        }
+
+        let total_lines = content.lines().count();
+        let start = start.unwrap_or(1);
+        let end = end.unwrap_or(total_lines).min(total_lines);
+        if start == 0 || end < start.saturating_sub(1) {
+            return Err(AppError::Validation("Invalid line range".to_string()));
+        }
+
+        // split_inclusive keeps the original line endings in the slice
+        let content = content
+            .split_inclusive('\n')
+            .skip(start - 1)
+            .take(end + 1 - start)
+            .collect();
+
+        Ok(Self {
+            content,
+            total_lines,
+            start_line: Some(start),
+            end_line: Some(end),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ContentQuery {
+    pub start_line: Option<usize>,
+    pub end_line: Option<usize>,
 }
 
 // Helper to check if user has access to project
-async fn check_project_access(
-    pool: &sqlx::SqlitePool,
-    project_id: &str,
-    user_id: &str,
-) -> Result<()> {
+async fn check_project_access(pool: &DbPool, project_id: &str, user_id: &str) -> Result<()> {
     let exists = sqlx::query_scalar::<_, i64>(
         r#"
         SELECT COUNT(*) FROM projects p
         LEFT JOIN project_collaborators pc ON p.id = pc.project_id
-        WHERE p.id = ? AND (p.owner_id = ? OR pc.user_id = ?)
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
         "#,
     )
     .bind(project_id)
@@ -91,6 +314,37 @@ async fn check_project_access(
     Ok(())
 }
 
+// Like check_project_access, but viewers are refused
+async fn check_project_write_access(pool: &DbPool, project_id: &str, user_id: &str) -> Result<()> {
+    check_project_access(pool, project_id, user_id).await?;
+    let writable = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR (pc.user_id = $3 AND pc.role = 'editor'))
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if writable == 0 {
+        return Err(AppError::Forbidden(
+            "Viewers cannot change this project".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/files/project/{project_id}",
+    tag = "files",
+    params(("project_id" = String, Path, description = "Project id")),
+    responses((status = 200, body = FileListResponse))
+)]
 async fn list_files(
     State(state): State<AppState>,
     user: AuthUser,
@@ -98,27 +352,31 @@ async fn list_files(
 ) -> Result<Json<FileListResponse>> {
     check_project_access(&state.db.pool, &project_id, &user.id).await?;
 
-    let files = sqlx::query_as::<_, (String, String, String, String, bool)>(
-        "SELECT id, project_id, name, path, is_folder FROM files WHERE project_id = ? ORDER BY is_folder DESC, path ASC",
-    )
+    let files = sqlx::query_as::<_, FileResponse>(&format!(
+        "SELECT {FILE_COLUMNS} FROM files WHERE project_id = $1 ORDER BY {FILE_ORDER}"
+    ))
     .bind(&project_id)
     .fetch_all(&state.db.pool)
     .await?;
 
+    // Hide compile artifacts and anything matched by the project's ignore file
+    let rules = ExcludeRules::load(&state.storage, &project_id).await?;
     let files = files
         .into_iter()
-        .map(|(id, project_id, name, path, is_folder)| FileResponse {
-            id,
-            project_id,
-            name,
-            path,
-            is_folder,
-        })
+        .filter(|file| !rules.is_excluded(&file.path, file.is_folder))
         .collect();
 
     Ok(Json(FileListResponse { files }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/files/project/{project_id}/file",
+    tag = "files",
+    params(("project_id" = String, Path, description = "Project id")),
+    request_body = CreateFileRequest,
+    responses((status = 200, body = FileResponse))
+)]
 async fn create_file(
     State(state): State<AppState>,
     user: AuthUser,
@@ -126,6 +384,7 @@ async fn create_file(
     Json(body): Json<CreateFileRequest>,
 ) -> Result<Json<FileResponse>> {
     check_project_access(&state.db.pool, &project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &project_id).await?;
 
     if body.name.trim().is_empty() {
         return Err(AppError::Validation("File name is required".to_string()));
@@ -133,7 +392,7 @@ async fn create_file(
 
     // Check if file already exists
     let exists = sqlx::query_scalar::<_, i64>(
-        "SELECT COUNT(*) FROM files WHERE project_id = ? AND path = ?",
+        "SELECT COUNT(*) FROM files WHERE project_id = $1 AND path = $2",
     )
     .bind(&project_id)
     .bind(&body.path)
@@ -148,55 +407,79 @@ async fn create_file(
 
     let file_id = Uuid::new_v4().to_string();
     let now = Utc::now().to_rfc3339();
+    let content = body.content.unwrap_or_default();
+    let hash = (!body.is_folder).then(|| content_hash(content.as_bytes()));
+    let file_type = (!body.is_folder).then(|| filetype::detect(&body.path, content.as_bytes()));
+    let (mime_type, language) = file_type
+        .map(|file_type| (Some(file_type.mime_type), file_type.language))
+        .unwrap_or_default();
 
     // Create in database
     sqlx::query(
-        "INSERT INTO files (id, project_id, name, path, is_folder, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        "INSERT INTO files (id, project_id, name, path, is_folder, hash, mime_type, language, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
     )
     .bind(&file_id)
     .bind(&project_id)
     .bind(&body.name)
     .bind(&body.path)
     .bind(body.is_folder)
+    .bind(&hash)
+    .bind(&mime_type)
+    .bind(&language)
     .bind(&now)
     .bind(&now)
     .execute(&state.db.pool)
     .await?;
 
     // Create on filesystem
-    let file_path = std::path::Path::new(&state.config.storage_path)
-        .join(&project_id)
-        .join(&body.path);
-
     if body.is_folder {
-        std::fs::create_dir_all(&file_path)
-            .map_err(|e| AppError::Internal(format!("Failed to create folder: {e}")))?;
+        state.storage.create_folder(&project_id, &body.path).await?;
     } else {
-        // Create parent directories if needed
-        if let Some(parent) = file_path.parent() {
-            std::fs::create_dir_all(parent)
-                .map_err(|e| AppError::Internal(format!("Failed to create directories: {e}")))?;
-        }
-        let content = body.content.unwrap_or_default();
-        std::fs::write(&file_path, &content)
-            .map_err(|e| AppError::Internal(format!("Failed to create file: {e}")))?;
+        state
+            .storage
+            .write_file(&project_id, &body.path, &content)
+            .await?;
     }
 
+    state.events.publish(
+        &project_id,
+        ProjectEvent::FileCreated {
+            file_id: file_id.clone(),
+            path: body.path.clone(),
+            is_folder: body.is_folder,
+        },
+    );
+
     Ok(Json(FileResponse {
         id: file_id,
         project_id,
         name: body.name,
         path: body.path,
         is_folder: body.is_folder,
+        hash,
+        mime_type,
+        language,
+        sort_order: None,
+        locked_by: None,
+        lock_expires_at: None,
     }))
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = FileUploadResponse)]
 pub struct UploadResponse {
     pub uploaded: Vec<FileResponse>,
     pub errors: Vec<String>,
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/files/project/{project_id}/upload",
+    tag = "files",
+    params(("project_id" = String, Path, description = "Project id")),
+    request_body(content = String, description = "Files as multipart/form-data, one part per file named by its project path", content_type = "multipart/form-data"),
+    responses((status = 200, body = FileUploadResponse))
+)]
 async fn upload_files(
     State(state): State<AppState>,
     user: AuthUser,
@@ -204,6 +487,7 @@ async fn upload_files(
     mut multipart: Multipart,
 ) -> Result<Json<UploadResponse>> {
     check_project_access(&state.db.pool, &project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &project_id).await?;
 
     let mut uploaded = Vec::new();
     let mut errors = Vec::new();
@@ -232,7 +516,7 @@ async fn upload_files(
 
         // Check if file already exists
         let exists = sqlx::query_scalar::<_, i64>(
-            "SELECT COUNT(*) FROM files WHERE project_id = ? AND path = ?",
+            "SELECT COUNT(*) FROM files WHERE project_id = $1 AND path = $2",
         )
         .bind(&project_id)
         .bind(&file_name)
@@ -246,16 +530,21 @@ async fn upload_files(
 
         let file_id = Uuid::new_v4().to_string();
         let now = Utc::now().to_rfc3339();
+        let hash = content_hash(&data);
+        let file_type = filetype::detect(&file_name, &data);
 
         // Create in database
         if let Err(e) = sqlx::query(
-            "INSERT INTO files (id, project_id, name, path, is_folder, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+            "INSERT INTO files (id, project_id, name, path, is_folder, hash, mime_type, language, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
         )
         .bind(&file_id)
         .bind(&project_id)
         .bind(&file_name)
         .bind(&file_name)
         .bind(false)
+        .bind(&hash)
+        .bind(&file_type.mime_type)
+        .bind(&file_type.language)
         .bind(&now)
         .bind(&now)
         .execute(&state.db.pool)
@@ -266,192 +555,217 @@ async fn upload_files(
         }
 
         // Write to filesystem
-        let file_path = std::path::Path::new(&state.config.storage_path)
-            .join(&project_id)
-            .join(&file_name);
-
-        // Create parent directories if needed
-        if let Some(parent) = file_path.parent() {
-            if let Err(e) = std::fs::create_dir_all(parent) {
-                errors.push(format!("Failed to create directories for {file_name}: {e}"));
-                // Clean up the database entry
-                let _ = sqlx::query("DELETE FROM files WHERE id = ?")
-                    .bind(&file_id)
-                    .execute(&state.db.pool)
-                    .await;
-                continue;
-            }
-        }
-
-        if let Err(e) = std::fs::write(&file_path, &data) {
+        if let Err(e) = state
+            .storage
+            .write_file(&project_id, &file_name, &data)
+            .await
+        {
             errors.push(format!("Failed to write file {file_name}: {e}"));
             // Clean up the database entry
-            let _ = sqlx::query("DELETE FROM files WHERE id = ?")
+            let _ = sqlx::query("DELETE FROM files WHERE id = $1")
                 .bind(&file_id)
                 .execute(&state.db.pool)
                 .await;
             continue;
         }
 
+        state.events.publish(
+            &project_id,
+            ProjectEvent::FileCreated {
+                file_id: file_id.clone(),
+                path: file_name.clone(),
+                is_folder: false,
+            },
+        );
         uploaded.push(FileResponse {
             id: file_id,
             project_id: project_id.clone(),
             name: file_name.clone(),
             path: file_name,
             is_folder: false,
+            hash: Some(hash),
+            mime_type: Some(file_type.mime_type),
+            language: file_type.language,
+            sort_order: None,
+            locked_by: None,
+            lock_expires_at: None,
         });
     }
 
     Ok(Json(UploadResponse { uploaded, errors }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    responses((status = 200, body = FileResponse))
+)]
 async fn get_file(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<String>,
 ) -> Result<Json<FileResponse>> {
-    let file = sqlx::query_as::<_, (String, String, String, String, bool)>(
-        "SELECT id, project_id, name, path, is_folder FROM files WHERE id = ?",
-    )
-    .bind(&id)
-    .fetch_optional(&state.db.pool)
-    .await?
-    .ok_or_else(|| AppError::NotFound("File not found".to_string()))?;
+    let file = fetch_file(&state.db.pool, &id).await?;
 
-    let (id, project_id, name, path, is_folder) = file;
+    check_project_access(&state.db.pool, &file.project_id, &user.id).await?;
 
-    check_project_access(&state.db.pool, &project_id, &user.id).await?;
-
-    Ok(Json(FileResponse {
-        id,
-        project_id,
-        name,
-        path,
-        is_folder,
-    }))
+    Ok(Json(file))
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/files/{id}",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    request_body = UpdateFileRequest,
+    responses((status = 200, body = FileResponse))
+)]
 async fn update_file(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<String>,
     Json(body): Json<UpdateFileRequest>,
 ) -> Result<Json<FileResponse>> {
-    let file = sqlx::query_as::<_, (String, String, String, String, bool)>(
-        "SELECT id, project_id, name, path, is_folder FROM files WHERE id = ?",
-    )
-    .bind(&id)
-    .fetch_optional(&state.db.pool)
-    .await?
-    .ok_or_else(|| AppError::NotFound("File not found".to_string()))?;
+    let mut file = fetch_file(&state.db.pool, &id).await?;
 
-    let (file_id, project_id, mut name, mut path, is_folder) = file;
+    check_project_access(&state.db.pool, &file.project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &file.project_id).await?;
+    ensure_unlocked(&file, &user.id)?;
 
-    check_project_access(&state.db.pool, &project_id, &user.id).await?;
-
-    let old_path = path.clone();
+    let old_path = file.path.clone();
 
     if let Some(new_name) = body.name {
-        name = new_name;
+        file.name = new_name;
     }
     if let Some(new_path) = body.path {
-        path = new_path;
+        file.path = new_path;
+    }
+
+    // A position only means something within the old folder
+    if parent_of(&old_path) != parent_of(&file.path) {
+        file.sort_order = None;
     }
 
     // Update in database
     let now = Utc::now().to_rfc3339();
-    sqlx::query("UPDATE files SET name = ?, path = ?, updated_at = ? WHERE id = ?")
-        .bind(&name)
-        .bind(&path)
-        .bind(now)
-        .bind(&file_id)
-        .execute(&state.db.pool)
-        .await?;
+    sqlx::query(
+        "UPDATE files SET name = $1, path = $2, sort_order = $3, updated_at = $4 WHERE id = $5",
+    )
+    .bind(&file.name)
+    .bind(&file.path)
+    .bind(file.sort_order)
+    .bind(now)
+    .bind(&file.id)
+    .execute(&state.db.pool)
+    .await?;
 
     // Rename on filesystem if path changed
-    if old_path != path {
-        let old_file_path = std::path::Path::new(&state.config.storage_path)
-            .join(&project_id)
-            .join(&old_path);
-        let new_file_path = std::path::Path::new(&state.config.storage_path)
-            .join(&project_id)
-            .join(&path);
-
-        if let Some(parent) = new_file_path.parent() {
-            std::fs::create_dir_all(parent)
-                .map_err(|e| AppError::Internal(format!("Failed to create directories: {e}")))?;
+    if old_path != file.path {
+        state
+            .storage
+            .rename(&file.project_id, &old_path, &file.path)
+            .await?;
+        state.events.publish(
+            &file.project_id,
+            ProjectEvent::FileRenamed {
+                file_id: file.id.clone(),
+                old_path: old_path.clone(),
+                path: file.path.clone(),
+            },
+        );
+
+        if !file.is_folder && file_name_of(&old_path) != file_name_of(&file.path) {
+            let file_type = refresh_file_type(
+                &state.db.pool,
+                &state.storage,
+                &file.project_id,
+                &file.id,
+                &file.path,
+            )
+            .await?;
+            file.mime_type = Some(file_type.mime_type);
+            file.language = file_type.language;
         }
-
-        std::fs::rename(&old_file_path, &new_file_path)
-            .map_err(|e| AppError::Internal(format!("Failed to rename file: {e}")))?;
     }
 
-    Ok(Json(FileResponse {
-        id: file_id,
-        project_id,
-        name,
-        path,
-        is_folder,
-    }))
+    Ok(Json(file))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/files/{id}",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    responses((status = 200, description = "Done"))
+)]
 async fn delete_file(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<String>,
 ) -> Result<Json<()>> {
-    let file = sqlx::query_as::<_, (String, String, bool)>(
-        "SELECT project_id, path, is_folder FROM files WHERE id = ?",
-    )
-    .bind(&id)
-    .fetch_optional(&state.db.pool)
-    .await?
-    .ok_or_else(|| AppError::NotFound("File not found".to_string()))?;
+    let file = fetch_file(&state.db.pool, &id).await?;
 
-    let (project_id, path, is_folder) = file;
+    check_project_access(&state.db.pool, &file.project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &file.project_id).await?;
+    ensure_unlocked(&file, &user.id)?;
 
-    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+    let FileResponse {
+        project_id,
+        path,
+        is_folder,
+        ..
+    } = file;
 
     // Delete from filesystem
-    let file_path = std::path::Path::new(&state.config.storage_path)
-        .join(&project_id)
-        .join(&path);
-
-    if file_path.exists() {
-        if is_folder {
-            std::fs::remove_dir_all(&file_path)
-                .map_err(|e| AppError::Internal(format!("Failed to delete folder: {e}")))?;
-        } else {
-            std::fs::remove_file(&file_path)
-                .map_err(|e| AppError::Internal(format!("Failed to delete file: {e}")))?;
-        }
-    }
+    state.storage.delete_file(&project_id, &path).await?;
 
     // Delete from database
-    sqlx::query("DELETE FROM files WHERE id = ?")
+    sqlx::query("DELETE FROM files WHERE id = $1")
         .bind(&id)
         .execute(&state.db.pool)
         .await?;
 
     // If folder, delete all children
     if is_folder {
-        sqlx::query("DELETE FROM files WHERE project_id = ? AND path LIKE ?")
+        sqlx::query("DELETE FROM files WHERE project_id = $1 AND path LIKE $2")
             .bind(&project_id)
             .bind(format!("{path}/%"))
             .execute(&state.db.pool)
             .await?;
     }
 
+    state.events.publish(
+        &project_id,
+        ProjectEvent::FileDeleted {
+            file_id: id,
+            path,
+            is_folder,
+        },
+    );
+
     Ok(Json(()))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}/content",
+    tag = "files",
+    params(
+        ("id" = String, Path, description = "File id"),
+        ContentQuery,
+    ),
+    responses((status = 200, body = FileContentResponse))
+)]
 async fn get_file_content(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<String>,
+    Query(query): Query<ContentQuery>,
 ) -> Result<Json<FileContentResponse>> {
     let file = sqlx::query_as::<_, (String, String, bool)>(
-        "SELECT project_id, path, is_folder FROM files WHERE id = ?",
+        "SELECT project_id, path, is_folder FROM files WHERE id = $1",
     )
     .bind(&id)
     .fetch_optional(&state.db.pool)
@@ -468,24 +782,173 @@ async fn get_file_content(
 
     check_project_access(&state.db.pool, &project_id, &user.id).await?;
 
-    let file_path = std::path::Path::new(&state.config.storage_path)
-        .join(&project_id)
-        .join(&path);
+    let content = state.collab.content(&project_id, &path).await?;
 
-    let content = std::fs::read_to_string(&file_path)
-        .map_err(|e| AppError::Internal(format!("Failed to read file: {e}")))?;
-
-    Ok(Json(FileContentResponse { content }))
+    Ok(Json(FileContentResponse::slice(
+        content,
+        query.start_line,
+        query.end_line,
+    )?))
 }
 
+#[utoipa::path(
+    put,
+    path = "/api/files/{id}/content",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    request_body = UpdateContentRequest,
+    responses((status = 200, body = FileContentResponse))
+)]
 async fn update_file_content(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<String>,
     Json(body): Json<UpdateContentRequest>,
 ) -> Result<Json<FileContentResponse>> {
+    let file = fetch_file(&state.db.pool, &id).await?;
+
+    if file.is_folder {
+        return Err(AppError::BadRequest(
+            "Cannot set content of a folder".to_string(),
+        ));
+    }
+
+    check_project_access(&state.db.pool, &file.project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &file.project_id).await?;
+    ensure_unlocked(&file, &user.id)?;
+
+    write_content(&state, &file, &user.id, &body.content).await?;
+
+    Ok(Json(FileContentResponse::full(body.content)))
+}
+
+/// Write new content for a file through its document if someone has it open,
+/// so the document does not overwrite it on its next save, otherwise straight
+/// to storage.
+pub(super) async fn write_content(
+    state: &AppState,
+    file: &FileResponse,
+    author: &str,
+    content: &str,
+) -> Result<()> {
+    if !state
+        .collab
+        .replace_open(&file.project_id, &file.path, author, content)
+        .await?
+    {
+        return save_content(state, file, content).await;
+    }
+
+    state.events.publish(
+        &file.project_id,
+        ProjectEvent::FileUpdated {
+            file_id: file.id.clone(),
+            path: file.path.clone(),
+        },
+    );
+    Ok(())
+}
+
+/// Write new content for a file and update its checksum and detected type.
+async fn save_content(state: &AppState, file: &FileResponse, content: &str) -> Result<()> {
+    let hash = state
+        .storage
+        .write_file(&file.project_id, &file.path, content)
+        .await?;
+
+    let file_type = filetype::detect(&file.path, content.as_bytes());
+
+    // Update timestamp, checksum and detected type
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        "UPDATE files SET hash = $1, mime_type = $2, language = $3, updated_at = $4 WHERE id = $5",
+    )
+    .bind(&hash)
+    .bind(file_type.mime_type)
+    .bind(file_type.language)
+    .bind(now)
+    .bind(&file.id)
+    .execute(&state.db.pool)
+    .await?;
+
+    // A search catches up on files that failed to index
+    if let Err(e) = search::index_file(
+        &state.db.pool,
+        &file.project_id,
+        &file.id,
+        &file.path,
+        &hash,
+        content.as_bytes(),
+    )
+    .await
+    {
+        tracing::warn!("Failed to index {} for search: {}", file.path, e);
+    }
+
+    state.events.publish(
+        &file.project_id,
+        ProjectEvent::FileUpdated {
+            file_id: file.id.clone(),
+            path: file.path.clone(),
+        },
+    );
+
+    Ok(())
+}
+
+/// Sections, labels, environments and includes of a LaTeX file.
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}/outline",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    responses((status = 200, body = Outline))
+)]
+async fn get_outline(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<Outline>> {
+    let file = sqlx::query_as::<_, (String, String, bool)>(
+        "SELECT project_id, path, is_folder FROM files WHERE id = $1",
+    )
+    .bind(&id)
+    .fetch_optional(&state.db.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("File not found".to_string()))?;
+
+    let (project_id, path, is_folder) = file;
+
+    if is_folder {
+        return Err(AppError::BadRequest("Cannot outline a folder".to_string()));
+    }
+
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    let content = state.collab.content(&project_id, &path).await?;
+    Ok(Json(outline::parse(&content)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}/diff",
+    tag = "files",
+    params(
+        ("id" = String, Path, description = "File id"),
+        DiffQuery,
+    ),
+    request_body = Option<DiffRequest>,
+    responses((status = 200, body = DiffResult))
+)]
+async fn diff_file(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Query(query): Query<DiffQuery>,
+    body: Option<Json<DiffRequest>>,
+) -> Result<Json<DiffResult>> {
     let file = sqlx::query_as::<_, (String, String, bool)>(
-        "SELECT project_id, path, is_folder FROM files WHERE id = ?",
+        "SELECT project_id, path, is_folder FROM files WHERE id = $1",
     )
     .bind(&id)
     .fetch_optional(&state.db.pool)
@@ -495,29 +958,935 @@ async fn update_file_content(
     let (project_id, path, is_folder) = file;
 
     if is_folder {
+        return Err(AppError::BadRequest("Cannot diff a folder".to_string()));
+    }
+
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    let body = body.map(|Json(body)| body).unwrap_or_default();
+    if (body.from.is_some() && query.from.is_some()) || (body.to.is_some() && query.to.is_some()) {
+        return Err(AppError::Validation(
+            "Give each side as a revision or as content, not both".to_string(),
+        ));
+    }
+    if body.from.is_none() && body.to.is_none() && query.from.is_none() && query.to.is_none() {
+        return Err(AppError::Validation(
+            "At least one of 'from' or 'to' is required".to_string(),
+        ));
+    }
+
+    let current = state.collab.content(&project_id, &path).await?;
+    let (from, from_label) = match (body.from, query.from) {
+        (Some(content), _) => (content, format!("a/{path}")),
+        (None, Some(revision)) => (
+            file_at_revision(&state, &project_id, &id, &path, &revision).await?,
+            format!("a/{path} ({revision})"),
+        ),
+        (None, None) => (current.clone(), format!("a/{path} (current)")),
+    };
+    let (to, to_label) = match (body.to, query.to) {
+        (Some(content), _) => (content, format!("b/{path}")),
+        (None, Some(revision)) => (
+            file_at_revision(&state, &project_id, &id, &path, &revision).await?,
+            format!("b/{path} ({revision})"),
+        ),
+        (None, None) => (current, format!("b/{path} (current)")),
+    };
+
+    Ok(Json(diff::unified_diff(
+        &from,
+        &to,
+        &from_label,
+        &to_label,
+        query.context.unwrap_or(3),
+    )))
+}
+
+// The file's content at a project version, or after an entry of its history.
+// A file the version did not have yet diffs as empty.
+async fn file_at_revision(
+    state: &AppState,
+    project_id: &str,
+    file_id: &str,
+    path: &str,
+    revision: &str,
+) -> Result<String> {
+    if let Some(version) = versions::find(&state.db.pool, project_id, revision).await? {
+        let files = versions::files(&state.db.pool, &version.id).await?;
+        let hash = files
+            .iter()
+            .find(|file| file.file_id == file_id)
+            .or_else(|| files.iter().find(|file| file.path == path))
+            .and_then(|file| file.hash.clone());
+        return match hash {
+            Some(hash) => String::from_utf8(state.storage.read_object(&hash).await?)
+                .map_err(|_| AppError::BadRequest(format!("File is not valid UTF-8: {path}"))),
+            None => Ok(String::new()),
+        };
+    }
+    match revision.parse::<i64>() {
+        Ok(seq) => history::content_at(&state.db.pool, file_id, seq).await,
+        Err(_) => Err(AppError::NotFound(format!(
+            "No version or history entry '{revision}'"
+        ))),
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum BulkOperation {
+    Delete { id: String },
+    Move { id: String, path: String },
+    Rename { id: String, name: String },
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BulkRequest {
+    pub operations: Vec<BulkOperation>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkItemResult {
+    pub index: usize,
+    pub id: String,
+    pub success: bool,
+    pub error: Option<String>,
+    pub file: Option<FileResponse>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BulkResponse {
+    pub results: Vec<BulkItemResult>,
+}
+
+impl BulkOperation {
+    fn id(&self) -> &str {
+        match self {
+            BulkOperation::Delete { id }
+            | BulkOperation::Move { id, .. }
+            | BulkOperation::Rename { id, .. } => id,
+        }
+    }
+}
+
+// Paths below a folder are matched by prefix rather than with LIKE, which
+// would take % and _ in the folder name as wildcards. The prefix, with its
+// trailing slash, is $2; the database measures it, as substr counts characters
+const BELOW_FOLDER: &str = "substr(path, 1, length($2)) = $2";
+
+// Reject moving or deleting a folder with a file inside locked by someone else
+async fn ensure_folder_unlocked(
+    conn: &mut DbConnection,
+    project_id: &str,
+    folder: &str,
+    user_id: &str,
+) -> Result<()> {
+    let prefix = format!("{folder}/");
+    let locked = sqlx::query_scalar::<_, String>(&format!(
+        "SELECT path FROM files WHERE project_id = $1 AND {BELOW_FOLDER} AND locked_by <> $3 AND lock_expires_at > {} LIMIT 1",
+        sql_now!()
+    ))
+    .bind(project_id)
+    .bind(&prefix)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    match locked {
+        Some(path) => Err(AppError::Conflict(format!("File {path} is locked"))),
+        None => Ok(()),
+    }
+}
+
+// Apply a single bulk operation. Database changes go through the caller's
+// savepoint so a failed filesystem step can roll them back. Deleted files are
+// only collected in `deleted`, to be removed from storage after the commit.
+async fn apply_bulk_operation(
+    conn: &mut DbConnection,
+    storage: &StorageService,
+    project_id: &str,
+    user_id: &str,
+    op: &BulkOperation,
+    deleted: &mut Vec<String>,
+) -> Result<Option<FileResponse>> {
+    let file = fetch_file(&mut *conn, op.id()).await?;
+    if file.project_id != project_id {
+        return Err(AppError::NotFound("File not found".to_string()));
+    }
+    ensure_unlocked(&file, user_id)?;
+    if file.is_folder {
+        ensure_folder_unlocked(&mut *conn, project_id, &file.path, user_id).await?;
+    }
+
+    let FileResponse {
+        id: file_id,
+        path,
+        is_folder,
+        ..
+    } = file;
+    let prefix = format!("{path}/");
+
+    let (new_name, new_path) = match op {
+        BulkOperation::Delete { .. } => {
+            sqlx::query("DELETE FROM files WHERE id = $1")
+                .bind(&file_id)
+                .execute(&mut *conn)
+                .await?;
+            if is_folder {
+                sqlx::query(&format!(
+                    "DELETE FROM files WHERE project_id = $1 AND {BELOW_FOLDER}"
+                ))
+                .bind(project_id)
+                .bind(&prefix)
+                .execute(&mut *conn)
+                .await?;
+            }
+
+            deleted.push(path);
+            return Ok(None);
+        }
+        BulkOperation::Move { path: target, .. } => {
+            let target = compiler::normalize_project_path(target.trim_matches('/'))?;
+            let target_name = target.rsplit('/').next().unwrap_or(&target).to_string();
+            (target_name, target)
+        }
+        BulkOperation::Rename { name: new_name, .. } => {
+            if new_name.trim().is_empty()
+                || new_name.contains('/')
+                || matches!(new_name.as_str(), "." | "..")
+            {
+                return Err(AppError::Validation("Invalid file name".to_string()));
+            }
+            let new_path = match path.rsplit_once('/') {
+                Some((parent, _)) => format!("{parent}/{new_name}"),
+                None => new_name.clone(),
+            };
+            (new_name.clone(), new_path)
+        }
+    };
+
+    if new_path.is_empty() {
+        return Err(AppError::Validation("Target path is required".to_string()));
+    }
+    if is_folder && new_path.starts_with(&prefix) {
+        return Err(AppError::Validation(
+            "Cannot move a folder into itself".to_string(),
+        ));
+    }
+
+    if new_path != path {
+        let exists = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM files WHERE project_id = $1 AND path = $2",
+        )
+        .bind(project_id)
+        .bind(&new_path)
+        .fetch_one(&mut *conn)
+        .await?;
+
+        if exists > 0 {
+            return Err(AppError::Validation(
+                "File already exists at this path".to_string(),
+            ));
+        }
+    }
+
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        "UPDATE files SET name = $1, path = $2, sort_order = CASE WHEN $3 THEN sort_order END, updated_at = $4 WHERE id = $5",
+    )
+    .bind(&new_name)
+    .bind(&new_path)
+    .bind(parent_of(&path) == parent_of(&new_path))
+    .bind(&now)
+    .bind(&file_id)
+    .execute(&mut *conn)
+    .await?;
+
+    if is_folder && new_path != path {
+        // Re-root every descendant under the new folder path
+        sqlx::query(&format!(
+            "UPDATE files SET path = $3 || substr(path, length($2) + 1), updated_at = $4 WHERE project_id = $1 AND {BELOW_FOLDER}"
+        ))
+        .bind(project_id)
+        .bind(&prefix)
+        .bind(format!("{new_path}/"))
+        .bind(&now)
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    if new_path != path {
+        storage.rename(project_id, &path, &new_path).await?;
+
+        if !is_folder && file_name_of(&path) != file_name_of(&new_path) {
+            refresh_file_type(&mut *conn, storage, project_id, &file_id, &new_path).await?;
+        }
+    }
+
+    Ok(Some(fetch_file(&mut *conn, &file_id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/files/project/{project_id}/bulk",
+    tag = "files",
+    params(("project_id" = String, Path, description = "Project id")),
+    request_body = BulkRequest,
+    responses((status = 200, body = BulkResponse))
+)]
+async fn bulk_operations(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(body): Json<BulkRequest>,
+) -> Result<Json<BulkResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &project_id).await?;
+
+    if body.operations.is_empty() {
+        return Err(AppError::Validation("No operations given".to_string()));
+    }
+
+    let mut results = Vec::with_capacity(body.operations.len());
+    let mut deleted = Vec::new();
+    let mut tx = state.db.pool.begin().await?;
+
+    for (index, op) in body.operations.iter().enumerate() {
+        let mut savepoint = sqlx::Connection::begin(&mut *tx).await?;
+
+        match apply_bulk_operation(
+            &mut savepoint,
+            &state.storage,
+            &project_id,
+            &user.id,
+            op,
+            &mut deleted,
+        )
+        .await
+        {
+            Ok(file) => {
+                savepoint.commit().await?;
+                results.push(BulkItemResult {
+                    index,
+                    id: op.id().to_string(),
+                    success: true,
+                    error: None,
+                    file,
+                });
+            }
+            Err(e) => {
+                savepoint.rollback().await?;
+                results.push(BulkItemResult {
+                    index,
+                    id: op.id().to_string(),
+                    success: false,
+                    error: Some(e.to_string()),
+                    file: None,
+                });
+            }
+        }
+    }
+
+    tx.commit().await?;
+
+    // The rows are gone; a file left behind is picked up as an orphan
+    for path in &deleted {
+        if let Err(e) = state.storage.delete_file(&project_id, path).await {
+            tracing::warn!("Failed to delete {} in project {}: {}", path, project_id, e);
+        }
+    }
+
+    if results.iter().any(|result| result.success) {
+        state
+            .events
+            .publish(&project_id, ProjectEvent::FilesChanged);
+    }
+
+    Ok(Json(BulkResponse { results }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}/thumbnail",
+    tag = "files",
+    params(
+        ("id" = String, Path, description = "File id"),
+        ThumbnailQuery,
+    ),
+    responses((status = 200, description = "PNG thumbnail of the image", content_type = "image/png", body = Vec<u8>))
+)]
+async fn get_thumbnail(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Query(query): Query<ThumbnailQuery>,
+) -> Result<axum::response::Response> {
+    use axum::body::Body;
+    use axum::http::{header, Response, StatusCode};
+
+    let file = fetch_file(&state.db.pool, &id).await?;
+
+    check_project_access(&state.db.pool, &file.project_id, &user.id).await?;
+
+    if file.is_folder || !thumbnail::is_image(&file.path) {
         return Err(AppError::BadRequest(
-            "Cannot set content of a folder".to_string(),
+            "Thumbnails are only available for images".to_string(),
         ));
     }
 
+    let width = query
+        .w
+        .unwrap_or(thumbnail::DEFAULT_WIDTH)
+        .clamp(1, thumbnail::MAX_WIDTH);
+
+    let hash = match file.hash {
+        Some(hash) => hash,
+        None => {
+            state
+                .storage
+                .hash_file(&file.project_id, &file.path)
+                .await?
+        }
+    };
+    let cached = thumbnail::cache_path(
+        std::path::Path::new(&state.config.cache_path),
+        &file.project_id,
+        &file.id,
+        &hash,
+        width,
+    );
+
+    let bytes = match tokio::fs::read(&cached).await {
+        Ok(bytes) => bytes,
+        Err(_) => {
+            let source = state
+                .storage
+                .read_bytes(&file.project_id, &file.path)
+                .await?;
+            let bytes = tokio::task::spawn_blocking(move || thumbnail::generate(&source, width))
+                .await
+                .map_err(|e| AppError::Internal(format!("Thumbnail task failed: {e}")))??;
+
+            // A failed cache write only costs a regeneration next time
+            if let Some(parent) = cached.parent() {
+                let _ = tokio::fs::create_dir_all(parent).await;
+            }
+            if let Err(e) = tokio::fs::write(&cached, &bytes).await {
+                tracing::warn!("Failed to cache thumbnail {}: {}", cached.display(), e);
+            }
+            bytes
+        }
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/png")
+        .header(header::CACHE_CONTROL, "private, max-age=60")
+        .body(Body::from(bytes))
+        .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}/verify",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    responses((status = 200, body = VerifyResponse))
+)]
+async fn verify_file(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<VerifyResponse>> {
+    let file = fetch_file(&state.db.pool, &id).await?;
+
+    check_project_access(&state.db.pool, &file.project_id, &user.id).await?;
+
+    if file.is_folder {
+        return Err(AppError::BadRequest("Cannot verify a folder".to_string()));
+    }
+
+    let actual_hash = state
+        .storage
+        .hash_file(&file.project_id, &file.path)
+        .await?;
+
+    Ok(Json(VerifyResponse {
+        matches: file.hash.as_deref() == Some(actual_hash.as_str()),
+        id: file.id,
+        stored_hash: file.hash,
+        actual_hash,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/files/project/{project_id}/rescan",
+    tag = "files",
+    params(("project_id" = String, Path, description = "Project id")),
+    responses((status = 200, body = RescanReport))
+)]
+async fn rescan_project(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<RescanReport>> {
     check_project_access(&state.db.pool, &project_id, &user.id).await?;
 
-    let file_path = std::path::Path::new(&state.config.storage_path)
-        .join(&project_id)
-        .join(&path);
+    let report = reconcile::rescan_project(&state.db, &state.storage, &project_id).await?;
+    if !report.is_empty() {
+        state
+            .events
+            .publish(&project_id, ProjectEvent::FilesChanged);
+    }
 
-    std::fs::write(&file_path, &body.content)
-        .map_err(|e| AppError::Internal(format!("Failed to write file: {e}")))?;
+    Ok(Json(report))
+}
 
-    // Update timestamp
-    let now = Utc::now().to_rfc3339();
-    sqlx::query("UPDATE files SET updated_at = ? WHERE id = ?")
-        .bind(now)
+#[utoipa::path(
+    post,
+    path = "/api/files/{id}/lock",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    request_body = Option<LockRequest>,
+    responses((status = 200, body = FileResponse))
+)]
+async fn lock_file(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    body: Option<Json<LockRequest>>,
+) -> Result<Json<FileResponse>> {
+    let file = fetch_file(&state.db.pool, &id).await?;
+
+    check_project_access(&state.db.pool, &file.project_id, &user.id).await?;
+
+    if file.is_folder {
+        return Err(AppError::BadRequest("Cannot lock a folder".to_string()));
+    }
+
+    let ttl = body
+        .and_then(|Json(body)| body.ttl_seconds)
+        .unwrap_or(DEFAULT_LOCK_TTL_SECS)
+        .clamp(1, MAX_LOCK_TTL_SECS);
+    let expires_at = (Utc::now() + chrono::Duration::seconds(ttl as i64))
+        .format(LOCK_TIME_FORMAT)
+        .to_string();
+
+    // Take the lock if it is free, expired, or already ours (which extends it)
+    let acquired = sqlx::query(concat!(
+        "UPDATE files SET locked_by = $1, lock_expires_at = $2 \
+         WHERE id = $3 AND (locked_by IS NULL OR locked_by = $4 \
+         OR lock_expires_at IS NULL OR lock_expires_at <= ",
+        sql_now!(),
+        ")",
+    ))
+    .bind(&user.id)
+    .bind(&expires_at)
+    .bind(&id)
+    .bind(&user.id)
+    .execute(&state.db.pool)
+    .await?;
+
+    let file = fetch_file(&state.db.pool, &id).await?;
+    if acquired.rows_affected() == 0 {
+        ensure_unlocked(&file, &user.id)?;
+    }
+
+    Ok(Json(file))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/files/{id}/lock",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    responses((status = 200, body = FileResponse))
+)]
+async fn unlock_file(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<FileResponse>> {
+    let file = fetch_file(&state.db.pool, &id).await?;
+
+    check_project_access(&state.db.pool, &file.project_id, &user.id).await?;
+
+    if let Some(holder) = &file.locked_by {
+        // The project owner can break a stale lock left by a collaborator
+        let owner_id =
+            sqlx::query_scalar::<_, String>("SELECT owner_id FROM projects WHERE id = $1")
+                .bind(&file.project_id)
+                .fetch_one(&state.db.pool)
+                .await?;
+        if *holder != user.id && owner_id != user.id {
+            return Err(AppError::Forbidden(
+                "Only the lock holder or project owner can unlock this file".to_string(),
+            ));
+        }
+    }
+
+    sqlx::query("UPDATE files SET locked_by = NULL, lock_expires_at = NULL WHERE id = $1")
         .bind(&id)
         .execute(&state.db.pool)
         .await?;
 
-    Ok(Json(FileContentResponse {
-        content: body.content,
-    }))
+    Ok(Json(fetch_file(&state.db.pool, &id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/files/project/{project_id}/order",
+    tag = "files",
+    params(("project_id" = String, Path, description = "Project id")),
+    request_body = ReorderRequest,
+    responses((status = 200, body = FileListResponse))
+)]
+async fn reorder_files(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(body): Json<ReorderRequest>,
+) -> Result<Json<FileListResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &project_id).await?;
+
+    let folder = body.folder.trim_matches('/');
+    let mut tx = state.db.pool.begin().await?;
+
+    // Children left out of the list fall back to alphabetical order
+    let (children, grandchildren) = if folder.is_empty() {
+        ("%".to_string(), "%/%".to_string())
+    } else {
+        (format!("{folder}/%"), format!("{folder}/%/%"))
+    };
+    sqlx::query(
+        "UPDATE files SET sort_order = NULL WHERE project_id = $1 AND path LIKE $2 AND path NOT LIKE $3",
+    )
+    .bind(&project_id)
+    .bind(&children)
+    .bind(&grandchildren)
+    .execute(&mut *tx)
+    .await?;
+
+    for (position, id) in body.ids.iter().enumerate() {
+        let file = fetch_file(&mut *tx, id).await?;
+        if file.project_id != project_id {
+            return Err(AppError::NotFound("File not found".to_string()));
+        }
+        if parent_of(&file.path) != folder {
+            return Err(AppError::Validation(format!(
+                "{} is not in folder '{folder}'",
+                file.path
+            )));
+        }
+
+        sqlx::query("UPDATE files SET sort_order = $1 WHERE id = $2")
+            .bind(position as i64)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+
+    tx.commit().await?;
+
+    let files = sqlx::query_as::<_, FileResponse>(&format!(
+        "SELECT {FILE_COLUMNS} FROM files WHERE project_id = $1 ORDER BY {FILE_ORDER}"
+    ))
+    .bind(&project_id)
+    .fetch_all(&state.db.pool)
+    .await?;
+
+    state
+        .events
+        .publish(&project_id, ProjectEvent::FilesChanged);
+
+    Ok(Json(FileListResponse { files }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/files/{id}/copy-to",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    request_body = CopyToRequest,
+    responses((status = 200, body = FileResponse))
+)]
+async fn copy_file_to(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<CopyToRequest>,
+) -> Result<Json<FileResponse>> {
+    let source = fetch_file(&state.db.pool, &id).await?;
+
+    check_project_access(&state.db.pool, &source.project_id, &user.id).await?;
+    check_project_write_access(&state.db.pool, &body.project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &body.project_id).await?;
+
+    if source.is_folder {
+        return Err(AppError::BadRequest("Cannot copy a folder".to_string()));
+    }
+
+    let target_path = match body
+        .path
+        .as_deref()
+        .map(|path| path.trim().trim_matches('/'))
+    {
+        Some("") => return Err(AppError::Validation("Target path is required".to_string())),
+        Some(path) => compiler::normalize_project_path(path)?,
+        None => source.path.clone(),
+    };
+    let target_name = target_path
+        .rsplit('/')
+        .next()
+        .unwrap_or(&target_path)
+        .to_string();
+
+    let exists = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM files WHERE project_id = $1 AND path = $2",
+    )
+    .bind(&body.project_id)
+    .bind(&target_path)
+    .fetch_one(&state.db.pool)
+    .await?;
+
+    if exists > 0 {
+        return Err(AppError::Validation(
+            "File already exists at this path".to_string(),
+        ));
+    }
+
+    let content = state
+        .storage
+        .read_bytes(&source.project_id, &source.path)
+        .await?;
+    let hash = state
+        .storage
+        .write_file(&body.project_id, &target_path, &content)
+        .await?;
+
+    let file_type = filetype::detect(&target_path, &content);
+    let file_id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+
+    if let Err(e) = sqlx::query(
+        "INSERT INTO files (id, project_id, name, path, is_folder, hash, mime_type, language, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+    )
+    .bind(&file_id)
+    .bind(&body.project_id)
+    .bind(&target_name)
+    .bind(&target_path)
+    .bind(false)
+    .bind(&hash)
+    .bind(&file_type.mime_type)
+    .bind(&file_type.language)
+    .bind(&now)
+    .bind(&now)
+    .execute(&state.db.pool)
+    .await
+    {
+        // Don't leave an unregistered copy behind
+        let _ = state
+            .storage
+            .delete_file(&body.project_id, &target_path)
+            .await;
+        return Err(e.into());
+    }
+
+    state.events.publish(
+        &body.project_id,
+        ProjectEvent::FileCreated {
+            file_id: file_id.clone(),
+            path: target_path,
+            is_folder: false,
+        },
+    );
+
+    Ok(Json(fetch_file(&state.db.pool, &file_id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/files/{id}/convert",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    request_body = ConvertRequest,
+    responses((status = 200, body = FileResponse))
+)]
+async fn convert_file(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<ConvertRequest>,
+) -> Result<Json<FileResponse>> {
+    let source = fetch_file(&state.db.pool, &id).await?;
+
+    check_project_access(&state.db.pool, &source.project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &source.project_id).await?;
+
+    if source.is_folder {
+        return Err(AppError::BadRequest("Cannot convert a folder".to_string()));
+    }
+
+    let target_path = match body.path.as_deref() {
+        Some(path) => compiler::normalize_project_path(path.trim().trim_matches('/'))?,
+        None => convert::target_path(&source.path, body.format),
+    };
+    if target_path == source.path {
+        return Err(AppError::Validation("Invalid target path".to_string()));
+    }
+
+    let existing = sqlx::query_as::<_, FileResponse>(&format!(
+        "SELECT {FILE_COLUMNS} FROM files WHERE project_id = $1 AND path = $2"
+    ))
+    .bind(&source.project_id)
+    .bind(&target_path)
+    .fetch_optional(&state.db.pool)
+    .await?;
+
+    if let Some(existing) = &existing {
+        if !body.overwrite || existing.is_folder {
+            return Err(AppError::Validation(
+                "File already exists at this path".to_string(),
+            ));
+        }
+        ensure_unlocked(existing, &user.id)?;
+    }
+
+    let content = state
+        .storage
+        .read_bytes(&source.project_id, &source.path)
+        .await?;
+    let converted = convert::convert(&source.path, content, body.format).await?;
+
+    let hash = state
+        .storage
+        .write_file(&source.project_id, &target_path, &converted)
+        .await?;
+    let file_type = filetype::detect(&target_path, &converted);
+    let now = Utc::now().to_rfc3339();
+
+    let file_id = match existing {
+        Some(existing) => {
+            sqlx::query(
+                "UPDATE files SET hash = $1, mime_type = $2, language = $3, updated_at = $4 WHERE id = $5",
+            )
+            .bind(&hash)
+            .bind(&file_type.mime_type)
+            .bind(&file_type.language)
+            .bind(&now)
+            .bind(&existing.id)
+            .execute(&state.db.pool)
+            .await?;
+            state.events.publish(
+                &source.project_id,
+                ProjectEvent::FileUpdated {
+                    file_id: existing.id.clone(),
+                    path: target_path,
+                },
+            );
+            existing.id
+        }
+        None => {
+            let file_id = Uuid::new_v4().to_string();
+            let name = target_path
+                .rsplit('/')
+                .next()
+                .unwrap_or(&target_path)
+                .to_string();
+            sqlx::query(
+                "INSERT INTO files (id, project_id, name, path, is_folder, hash, mime_type, language, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            )
+            .bind(&file_id)
+            .bind(&source.project_id)
+            .bind(&name)
+            .bind(&target_path)
+            .bind(false)
+            .bind(&hash)
+            .bind(&file_type.mime_type)
+            .bind(&file_type.language)
+            .bind(&now)
+            .bind(&now)
+            .execute(&state.db.pool)
+            .await?;
+            state.events.publish(
+                &source.project_id,
+                ProjectEvent::FileCreated {
+                    file_id: file_id.clone(),
+                    path: target_path,
+                    is_folder: false,
+                },
+            );
+            file_id
+        }
+    };
+
+    Ok(Json(fetch_file(&state.db.pool, &file_id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}/download",
+    tag = "files",
+    params(("id" = String, Path, description = "File id")),
+    responses((status = 200, description = "The file as stored", content_type = "application/octet-stream", body = Vec<u8>))
+)]
+async fn download_file(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<axum::response::Response> {
+    use axum::body::Body;
+    use axum::http::{header, Response, StatusCode};
+
+    let file = fetch_file(&state.db.pool, &id).await?;
+
+    check_project_access(&state.db.pool, &file.project_id, &user.id).await?;
+
+    if file.is_folder {
+        return Err(AppError::BadRequest("Cannot download a folder".to_string()));
+    }
+
+    let bytes = state
+        .storage
+        .read_bytes(&file.project_id, &file.path)
+        .await?;
+    let content_type = file
+        .mime_type
+        .unwrap_or_else(|| "application/octet-stream".to_string());
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_DISPOSITION, content_disposition(&file.name))
+        .header(header::CONTENT_LENGTH, bytes.len())
+        .header("X-Content-Type-Options", "nosniff")
+        .body(Body::from(bytes))
+        .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")))
+}
+
+/// `attachment` disposition with an ASCII fallback name and the exact
+/// UTF-8 name per RFC 6266 / RFC 5987.
+pub(crate) fn content_disposition(file_name: &str) -> String {
+    let fallback: String = file_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() && c != '"' && c != '\\' || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let mut encoded = String::new();
+    for byte in file_name.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    format!("attachment; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
 }
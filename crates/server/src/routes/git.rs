@@ -0,0 +1,424 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{
+        events::ProjectEvent,
+        freeze,
+        git::{CheckoutReport, Commit, GitDiff, GitStatus, PullReport},
+    },
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/:id/git",
+            get(get_status).post(enable_git).delete(disable_git),
+        )
+        .route("/:id/git/commit", post(commit))
+        .route("/:id/git/log", get(get_log))
+        .route("/:id/git/diff", get(get_diff))
+        .route("/:id/git/checkout", post(checkout))
+        .route("/:id/git/remote", put(set_remote).delete(remove_remote))
+        .route("/:id/git/push", post(push))
+        .route("/:id/git/pull", post(pull))
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+async fn check_project_owner(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let owner_id = sqlx::query_scalar::<_, String>("SELECT owner_id FROM projects WHERE id = $1")
+        .bind(project_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Project not found".to_string()))?;
+
+    if owner_id != user_id {
+        return Err(AppError::Forbidden(
+            "Only the owner can change git backing".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct EnableGitRequest {
+    /// Commit on the server's schedule as well as on request
+    pub auto_commit: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct CommitRequest {
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CommitResponse {
+    /// None when nothing changed since the last commit
+    pub commit: Option<Commit>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct LogQuery {
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LogResponse {
+    pub commits: Vec<Commit>,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct DiffQuery {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetRemoteRequest {
+    /// https URL of the repository, as for `git clone`
+    pub url: String,
+    /// Branch to sync with; "main" when omitted
+    pub branch: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CheckoutRequest {
+    /// Commit SHA, or any revision git understands such as "main~2"
+    pub rev: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/git",
+    tag = "git",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = GitStatus))
+)]
+async fn get_status(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<GitStatus>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    Ok(Json(state.git.status(&state.db, &id).await?))
+}
+
+/// Back the project with a git repository and commit its files.
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/git",
+    tag = "git",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = Option<EnableGitRequest>,
+    responses((status = 200, body = GitStatus))
+)]
+async fn enable_git(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    body: Option<Json<EnableGitRequest>>,
+) -> Result<Json<GitStatus>> {
+    check_project_owner(&state.db.pool, &id, &user.id).await?;
+    let body = body.map(|Json(body)| body).unwrap_or_default();
+
+    let status = state
+        .git
+        .enable(
+            &state.db,
+            &state.storage,
+            &state.collab,
+            &id,
+            &user.id,
+            body.auto_commit.unwrap_or(true),
+        )
+        .await?;
+    Ok(Json(status))
+}
+
+/// Stop backing the project with git, deleting its repository.
+#[utoipa::path(
+    delete,
+    path = "/api/projects/{id}/git",
+    tag = "git",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, description = "Done"))
+)]
+async fn disable_git(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<()>> {
+    check_project_owner(&state.db.pool, &id, &user.id).await?;
+
+    state.git.disable(&state.db, &id).await?;
+    Ok(Json(()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/git/commit",
+    tag = "git",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = Option<CommitRequest>,
+    responses((status = 200, body = CommitResponse))
+)]
+async fn commit(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    body: Option<Json<CommitRequest>>,
+) -> Result<Json<CommitResponse>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+    let body = body.map(|Json(body)| body).unwrap_or_default();
+    let message = body
+        .message
+        .as_deref()
+        .map(str::trim)
+        .filter(|message| !message.is_empty())
+        .unwrap_or("Update project");
+
+    let commit = state
+        .git
+        .commit(
+            &state.db,
+            &state.storage,
+            &state.collab,
+            &id,
+            Some(&user.id),
+            message,
+        )
+        .await?;
+    Ok(Json(CommitResponse { commit }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/git/log",
+    tag = "git",
+    params(
+        ("id" = String, Path, description = "Project id"),
+        LogQuery,
+    ),
+    responses((status = 200, body = LogResponse))
+)]
+async fn get_log(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Query(query): Query<LogQuery>,
+) -> Result<Json<LogResponse>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    // One extra commit tells whether there is another page
+    let mut commits = state
+        .git
+        .log(&state.db, &id, limit + 1, query.offset.unwrap_or(0))
+        .await?;
+    let has_more = commits.len() > limit;
+    commits.truncate(limit);
+    Ok(Json(LogResponse { commits, has_more }))
+}
+
+/// What a commit changed, or what changed between two commits.
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/git/diff",
+    tag = "git",
+    params(
+        ("id" = String, Path, description = "Project id"),
+        DiffQuery,
+    ),
+    responses((status = 200, body = GitDiff))
+)]
+async fn get_diff(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<GitDiff>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let diff = state
+        .git
+        .diff(
+            &state.db,
+            &id,
+            query.from.as_deref(),
+            query.to.as_deref(),
+            query.path.as_deref(),
+        )
+        .await?;
+    Ok(Json(diff))
+}
+
+/// Roll every file back to a commit.
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/git/checkout",
+    tag = "git",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = CheckoutRequest,
+    responses((status = 200, body = CheckoutReport))
+)]
+async fn checkout(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<CheckoutRequest>,
+) -> Result<Json<CheckoutReport>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &id).await?;
+
+    let report = state
+        .git
+        .checkout(
+            &state.db,
+            &state.storage,
+            &state.collab,
+            &id,
+            body.rev.trim(),
+            &user.id,
+        )
+        .await?;
+
+    state.events.publish(&id, ProjectEvent::FilesChanged);
+    Ok(Json(report))
+}
+
+/// Link the project to a remote repository, such as one on GitHub.
+#[utoipa::path(
+    put,
+    path = "/api/projects/{id}/git/remote",
+    tag = "git",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = SetRemoteRequest,
+    responses((status = 200, body = GitStatus))
+)]
+async fn set_remote(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<SetRemoteRequest>,
+) -> Result<Json<GitStatus>> {
+    check_project_owner(&state.db.pool, &id, &user.id).await?;
+
+    let branch = body
+        .branch
+        .as_deref()
+        .map(str::trim)
+        .filter(|branch| !branch.is_empty())
+        .unwrap_or("main");
+    let status = state
+        .git
+        .set_remote(&state.db, &id, body.url.trim(), branch)
+        .await?;
+    Ok(Json(status))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/projects/{id}/git/remote",
+    tag = "git",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, description = "Done"))
+)]
+async fn remove_remote(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<()>> {
+    check_project_owner(&state.db.pool, &id, &user.id).await?;
+
+    state.git.remove_remote(&state.db, &id).await?;
+    Ok(Json(()))
+}
+
+/// Commit the project and push it to the remote, with the user's token for
+/// the remote's host.
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/git/push",
+    tag = "git",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = Commit))
+)]
+async fn push(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<Commit>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let head = state
+        .git
+        .push(&state.db, &state.storage, &state.collab, &id, &user.id)
+        .await?;
+    Ok(Json(head))
+}
+
+/// Bring the remote's changes into the project.
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/git/pull",
+    tag = "git",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = PullReport))
+)]
+async fn pull(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<PullReport>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &id).await?;
+
+    let report = state
+        .git
+        .pull(&state.db, &state.storage, &state.collab, &id, &user.id)
+        .await?;
+
+    if report.restored > 0 || report.removed > 0 {
+        state.events.publish(&id, ProjectEvent::FilesChanged);
+    }
+    Ok(Json(report))
+}
@@ -0,0 +1,103 @@
+use axum::{
+    extract::{Path, State},
+    routing::{get, put},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::git_credentials::CredentialInfo,
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_credentials))
+        .route("/:host", put(set_credential).delete(delete_credential))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialsResponse {
+    pub credentials: Vec<CredentialInfo>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetCredentialRequest {
+    /// Personal access token with permission to push
+    pub token: String,
+    /// Username to send with the token, for hosts that check it
+    pub username: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/git/credentials",
+    tag = "git_credentials",
+    responses((status = 200, body = CredentialsResponse))
+)]
+async fn list_credentials(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<CredentialsResponse>> {
+    let credentials = state
+        .git
+        .credentials()
+        .list(&state.db.pool, &user.id)
+        .await?;
+    Ok(Json(CredentialsResponse { credentials }))
+}
+
+/// Store the user's token for a git host, such as "github.com".
+#[utoipa::path(
+    put,
+    path = "/api/git/credentials/{host}",
+    tag = "git_credentials",
+    params(("host" = String, Path, description = "Git host, such as github.com")),
+    request_body = SetCredentialRequest,
+    responses((status = 200, body = CredentialsResponse))
+)]
+async fn set_credential(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(host): Path<String>,
+    Json(body): Json<SetCredentialRequest>,
+) -> Result<Json<CredentialsResponse>> {
+    state
+        .git
+        .credentials()
+        .set(
+            &state.db.pool,
+            &user.id,
+            &host,
+            body.username.as_deref(),
+            &body.token,
+        )
+        .await?;
+    list_credentials(State(state), user).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/git/credentials/{host}",
+    tag = "git_credentials",
+    params(("host" = String, Path, description = "Git host, such as github.com")),
+    responses((status = 200, description = "Done"))
+)]
+async fn delete_credential(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(host): Path<String>,
+) -> Result<Json<()>> {
+    if !state
+        .git
+        .credentials()
+        .remove(&state.db.pool, &user.id, &host)
+        .await?
+    {
+        return Err(AppError::NotFound("No token for this host".to_string()));
+    }
+    Ok(Json(()))
+}
@@ -0,0 +1,164 @@
+use axum::{
+    body::Body,
+    extract::{Path, RawQuery, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tokio_util::io::ReaderStream;
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{api_tokens, events::ProjectEvent, freeze, git::HttpRequest},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/git/:repo/info/refs", get(info_refs))
+        .route("/git/:repo/git-upload-pack", post(upload_pack))
+        .route("/git/:repo/git-receive-pack", post(receive_pack))
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+/// Asks git to send a username and API token.
+fn challenge() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Basic realm=\"OpenLeaf\"")],
+    )
+        .into_response()
+}
+
+/// The user whose API token is the Basic auth password; the username is
+/// ignored, as git asks for one anyway.
+async fn basic_auth(state: &AppState, headers: &HeaderMap) -> Result<Option<AuthUser>> {
+    let credentials = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|value| STANDARD.decode(value.trim()).ok())
+        .and_then(|value| String::from_utf8(value).ok());
+    let Some((_, token)) = credentials.as_deref().and_then(|c| c.split_once(':')) else {
+        return Ok(None);
+    };
+    api_tokens::authenticate(&state.db.pool, token).await
+}
+
+async fn info_refs(
+    State(state): State<AppState>,
+    Path(repo): Path<String>,
+    RawQuery(query): RawQuery,
+    request: Request,
+) -> Result<Response> {
+    serve(state, repo, "/info/refs", query, request).await
+}
+
+async fn upload_pack(
+    State(state): State<AppState>,
+    Path(repo): Path<String>,
+    request: Request,
+) -> Result<Response> {
+    serve(state, repo, "/git-upload-pack", None, request).await
+}
+
+async fn receive_pack(
+    State(state): State<AppState>,
+    Path(repo): Path<String>,
+    request: Request,
+) -> Result<Response> {
+    serve(state, repo, "/git-receive-pack", None, request).await
+}
+
+/// Hand a git client's request for `/git/<project id>.git` to the project's
+/// repository.
+async fn serve(
+    state: AppState,
+    repo: String,
+    path: &str,
+    query: Option<String>,
+    request: Request,
+) -> Result<Response> {
+    let Some(project_id) = repo.strip_suffix(".git") else {
+        return Err(AppError::NotFound("Repository not found".to_string()));
+    };
+    let (parts, body) = request.into_parts();
+    let Some(user) = basic_auth(&state, &parts.headers).await? else {
+        return Ok(challenge());
+    };
+    check_project_access(&state.db.pool, project_id, &user.id).await?;
+    if path == "/git-receive-pack" {
+        freeze::ensure_writable(&state.db.pool, project_id).await?;
+    }
+
+    let header = |name: HeaderName| {
+        parts
+            .headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+    };
+    let request = HttpRequest {
+        method: parts.method.as_str(),
+        path,
+        query: query.as_deref().unwrap_or(""),
+        content_type: header(header::CONTENT_TYPE),
+        content_encoding: header(header::CONTENT_ENCODING),
+        git_protocol: header(HeaderName::from_static("git-protocol")),
+    };
+    let response = state
+        .git
+        .serve_http(
+            &state.db,
+            &state.storage,
+            &state.collab,
+            project_id,
+            &user,
+            request,
+            body.into_data_stream(),
+        )
+        .await?;
+
+    if response.files_changed {
+        state.events.publish(project_id, ProjectEvent::FilesChanged);
+    }
+
+    let mut builder = Response::builder()
+        .status(StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR));
+    for (name, value) in &response.headers {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::try_from(name.as_str()),
+            HeaderValue::try_from(value.as_str()),
+        ) {
+            builder = builder.header(name, value);
+        }
+    }
+    builder
+        .body(Body::from_stream(ReaderStream::new(response.body)))
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
@@ -0,0 +1,42 @@
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use serde_json::{json, Value};
+
+use crate::{
+    services::health::{self, Readiness},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/health", get(health_check))
+        .route("/health/live", get(live))
+        .route("/health/ready", get(ready))
+}
+
+async fn health_check() -> &'static str {
+    "OK"
+}
+
+/// Liveness probe: the process is up and serving requests.
+async fn live() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+/// Readiness probe: 503 with the failing components until the database,
+/// storage and TeX toolchain all work.
+async fn ready(State(state): State<AppState>) -> (StatusCode, Json<Readiness>) {
+    let readiness = health::readiness(
+        &state.db,
+        &state.storage,
+        &state.config.cache_path,
+        &state.config.compile,
+    )
+    .await;
+
+    let status = if readiness.is_ready() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(readiness))
+}
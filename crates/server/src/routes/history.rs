@@ -0,0 +1,183 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use super::files::{ensure_unlocked, fetch_file, FileResponse};
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{
+        authorship::{self, Authorship},
+        freeze,
+        history::{self, HistoryEntry},
+    },
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/:id/authorship", get(get_authorship))
+        .route("/:id/history", get(get_timeline))
+        .route("/:id/history/:seq", get(get_content_at))
+        .route("/:id/history/:seq/restore", post(restore_content))
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TimelineQuery {
+    /// `seq` of the oldest entry already shown
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TimelineResponse {
+    pub entries: Vec<HistoryEntry>,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ContentAtResponse {
+    pub seq: i64,
+    pub content: String,
+}
+
+async fn open_file(state: &AppState, id: &str, user_id: &str) -> Result<FileResponse> {
+    let file = fetch_file(&state.db.pool, id).await?;
+    check_project_access(&state.db.pool, &file.project_id, user_id).await?;
+
+    if file.is_folder {
+        return Err(AppError::BadRequest("Folders have no history".to_string()));
+    }
+    Ok(file)
+}
+
+/// Edits made to a file in the collaborative editor, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}/history",
+    tag = "history",
+    params(
+        ("id" = String, Path, description = "File id"),
+        TimelineQuery,
+    ),
+    responses((status = 200, body = TimelineResponse))
+)]
+async fn get_timeline(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Query(query): Query<TimelineQuery>,
+) -> Result<Json<TimelineResponse>> {
+    let file = open_file(&state, &id, &user.id).await?;
+
+    let limit = query.limit.unwrap_or(100).clamp(1, 500);
+    // One extra row tells whether there is another page
+    let mut entries = history::timeline(&state.db.pool, &file.id, query.before, limit + 1).await?;
+    let has_more = entries.len() as i64 > limit;
+    entries.truncate(limit as usize);
+
+    Ok(Json(TimelineResponse { entries, has_more }))
+}
+
+/// The file as it was right after an edit.
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}/history/{seq}",
+    tag = "history",
+    params(
+        ("id" = String, Path, description = "File id"),
+        ("seq" = i64, Path, description = "Sequence number of the history entry"),
+    ),
+    responses((status = 200, body = ContentAtResponse))
+)]
+async fn get_content_at(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, seq)): Path<(String, i64)>,
+) -> Result<Json<ContentAtResponse>> {
+    let file = open_file(&state, &id, &user.id).await?;
+
+    let content = history::content_at(&state.db.pool, &file.id, seq).await?;
+    Ok(Json(ContentAtResponse { seq, content }))
+}
+
+/// Who wrote each part of the file, for coloring text by contributor.
+#[utoipa::path(
+    get,
+    path = "/api/files/{id}/authorship",
+    tag = "history",
+    params(("id" = String, Path, description = "File id")),
+    responses((status = 200, body = Authorship))
+)]
+async fn get_authorship(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<Authorship>> {
+    let file = open_file(&state, &id, &user.id).await?;
+
+    let content = state.collab.content(&file.project_id, &file.path).await?;
+    let authorship = authorship::authorship(&state.db.pool, &file.id, content).await?;
+    Ok(Json(authorship))
+}
+
+/// Put the file back the way it was right after an edit. The restore goes
+/// through the document like any edit, so editors pick it up and it can be
+/// undone the same way.
+#[utoipa::path(
+    post,
+    path = "/api/files/{id}/history/{seq}/restore",
+    tag = "history",
+    params(
+        ("id" = String, Path, description = "File id"),
+        ("seq" = i64, Path, description = "Sequence number of the history entry"),
+    ),
+    responses((status = 200, description = "Done"))
+)]
+async fn restore_content(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, seq)): Path<(String, i64)>,
+) -> Result<Json<()>> {
+    let file = open_file(&state, &id, &user.id).await?;
+    ensure_unlocked(&file, &user.id)?;
+    freeze::ensure_writable(&state.db.pool, &file.project_id).await?;
+
+    let content = history::content_at(&state.db.pool, &file.id, seq).await?;
+    state
+        .collab
+        .edit(&file.project_id, &file.path, &user.id, |text| {
+            Ok((0..text.len(), content))
+        })
+        .await?;
+
+    Ok(Json(()))
+}
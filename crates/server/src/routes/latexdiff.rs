@@ -0,0 +1,225 @@
+use std::path::Path as FsPath;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::Response,
+    routing::post,
+    Json, Router,
+};
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::{compile::is_trusted, files::content_disposition, projects::load_settings};
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{
+        compiler::{self, CompileOptions},
+        latexdiff::{self, Revision},
+    },
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/:id/latexdiff", post(latexdiff_project))
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LatexdiffRequest {
+    /// The earlier state: a version ID or label, or a git revision
+    pub from: String,
+    /// The later state, named the same way; the project as it is now when
+    /// absent
+    pub to: Option<String>,
+    pub main_file: Option<String>,
+    /// Build target from the project settings whose main file and engine
+    /// to use
+    pub target: Option<String>,
+}
+
+/// Compile a PDF of the document with the changes between two versions or
+/// git revisions highlighted by latexdiff.
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/latexdiff",
+    tag = "export",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = LatexdiffRequest,
+    responses((status = 200, description = "The PDF with changes highlighted", content_type = "application/pdf", body = Vec<u8>))
+)]
+async fn latexdiff_project(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<LatexdiffRequest>,
+) -> Result<Response> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let settings = load_settings(&state.db.pool, &id).await?;
+    let target = match &body.target {
+        Some(name) => Some(
+            settings
+                .targets
+                .iter()
+                .find(|target| target.name == *name)
+                .ok_or_else(|| AppError::NotFound(format!("Build target '{name}' not found")))?,
+        ),
+        None => None,
+    };
+    let main_file = compiler::normalize_project_path(
+        body.main_file
+            .as_deref()
+            .or(target.map(|target| target.main_file.as_str()))
+            .unwrap_or("main.tex"),
+    )?;
+
+    let old = latexdiff::resolve(&state.db, &state.git, &id, Some(&body.from)).await?;
+    let new = latexdiff::resolve(&state.db, &state.git, &id, body.to.as_deref()).await?;
+
+    // The marked-up document sits beside the main file of the later state, so
+    // it finds the same figures and bibliography
+    let main_name = main_file.rsplit('/').next().unwrap_or(&main_file);
+    let stem = main_name
+        .rsplit_once('.')
+        .map_or(main_name, |(stem, _)| stem);
+    let diff_file = match main_file.rsplit_once('/') {
+        Some((dir, _)) => format!("{dir}/{stem}-diff.tex"),
+        None => format!("{stem}-diff.tex"),
+    };
+    let options = CompileOptions {
+        main_file: diff_file,
+        engine: target
+            .and_then(|target| target.engine)
+            .or(settings.engine)
+            .unwrap_or_default(),
+        bib_tool: target
+            .and_then(|target| target.bib_tool)
+            .or(settings.bib_tool),
+        shell_escape: settings.shell_escape,
+        trusted: is_trusted(&state, &id).await?,
+    };
+
+    let scratch = std::env::temp_dir().join(format!("openleaf-latexdiff-{}", Uuid::new_v4()));
+    let pdf = build(
+        &state, &user, &id, &old, &new, &main_file, &options, &scratch,
+    )
+    .await;
+    let _ = tokio::fs::remove_dir_all(&scratch).await;
+    let pdf = pdf?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/pdf")
+        .header(
+            header::CONTENT_DISPOSITION,
+            content_disposition(&format!("{stem}-diff.pdf")),
+        )
+        .header(header::CONTENT_LENGTH, pdf.len())
+        .body(Body::from(pdf))
+        .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")))
+}
+
+// Write both states into `scratch`, mark up the differences and compile the
+// result, returning the PDF
+#[allow(clippy::too_many_arguments)]
+async fn build(
+    state: &AppState,
+    user: &AuthUser,
+    project_id: &str,
+    old: &Revision,
+    new: &Revision,
+    main_file: &str,
+    options: &CompileOptions,
+    scratch: &FsPath,
+) -> Result<Vec<u8>> {
+    let (old_dir, new_dir, build_dir) = (
+        scratch.join("old"),
+        scratch.join("new"),
+        scratch.join("build"),
+    );
+    for (revision, dir) in [(old, &old_dir), (new, &new_dir)] {
+        latexdiff::write_tree(
+            &state.db,
+            &state.storage,
+            &state.collab,
+            &state.git,
+            project_id,
+            revision,
+            dir,
+        )
+        .await?;
+        if !dir.join(main_file).is_file() {
+            return Err(AppError::NotFound(format!(
+                "Main file '{main_file}' not found in one of the compared states"
+            )));
+        }
+    }
+    tokio::fs::create_dir_all(&build_dir)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to create build directory: {e}")))?;
+
+    let marked_up = latexdiff::markup(
+        scratch,
+        FsPath::new("old"),
+        FsPath::new("new"),
+        main_file,
+        &state.config.compile,
+    )
+    .await?;
+    tokio::fs::write(new_dir.join(&options.main_file), marked_up)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to write marked-up document: {e}")))?;
+
+    // Runs as a compile job, so it waits for a slot and counts toward the
+    // user's compile limit like any other
+    let job = state.compile_jobs.start(project_id, &user.id, None)?;
+    let outcome = match state.compile_jobs.acquire(&job).await {
+        Some(_slot) => {
+            let build_dir = tokio::fs::canonicalize(&build_dir).await.map_err(|e| {
+                AppError::Internal(format!("Failed to resolve build directory: {e}"))
+            })?;
+            compiler::compile(&new_dir, &build_dir, options, &job, &state.config.compile).await
+        }
+        None => Err(AppError::Conflict("Compile was cancelled".to_string())),
+    };
+    job.finish(&outcome);
+
+    let result = outcome?;
+    match result.pdf_path {
+        Some(pdf) if result.success => tokio::fs::read(build_dir.join(pdf))
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to read PDF: {e}"))),
+        _ => Err(AppError::BadRequest(format!(
+            "The marked-up document did not compile; its log is at \
+             /api/compile/project/{project_id}/log?compile_id={}",
+            job.id
+        ))),
+    }
+}
@@ -0,0 +1,38 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+
+use crate::{
+    error::{AppError, Result},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/metrics", get(metrics))
+}
+
+/// Prometheus scrape endpoint, authenticated with METRICS_TOKEN rather than a
+/// user session.
+async fn metrics(State(state): State<AppState>, headers: HeaderMap) -> Result<impl IntoResponse> {
+    let Some(token) = &state.config.metrics_token else {
+        return Err(AppError::NotFound("Metrics are disabled".to_string()));
+    };
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if provided != Some(token.as_str()) {
+        return Err(AppError::Unauthorized);
+    }
+
+    let (running, queued) = state.compile_jobs.load();
+    let collab = state.collab.stats().await;
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.metrics.render(running, queued, collab),
+    ))
+}
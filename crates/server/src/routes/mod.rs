@@ -1,5 +1,35 @@
+pub mod admin;
+pub mod api_tokens;
 pub mod auth;
+pub mod bib_import;
+pub mod bibtex;
+pub mod chat;
 pub mod comments;
 pub mod compile;
+pub mod digest;
+pub mod export;
+pub mod figures;
 pub mod files;
+pub mod git;
+pub mod git_credentials;
+pub mod git_http;
+pub mod health;
+pub mod history;
+pub mod latexdiff;
+pub mod metrics;
+pub mod notifications;
+pub mod openapi;
+pub mod presence;
 pub mod projects;
+pub mod search;
+pub mod sessions;
+pub mod spellcheck;
+pub mod symbols;
+pub mod system;
+pub mod templates;
+pub mod track_changes;
+pub mod uploads;
+pub mod users;
+pub mod versions;
+pub mod webdav;
+pub mod zotero;
@@ -0,0 +1,194 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::notifications::{self, Notification},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_notifications))
+        .route("/unread", get(get_unread_count))
+        .route("/read", post(mark_all_read))
+        .route("/settings", get(get_settings).put(update_settings))
+        .route("/:id/read", post(mark_read))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct NotificationsQuery {
+    #[serde(default)]
+    pub unread: bool,
+    /// `created_at` of the oldest notification already shown
+    pub before: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct MarkAllReadQuery {
+    /// Only mark this project's notifications read
+    pub project_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct NotificationsListResponse {
+    pub notifications: Vec<Notification>,
+    pub unread_count: i64,
+    pub has_more: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UnreadCountResponse {
+    pub count: i64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateSettingsRequest {
+    /// "immediate", "daily" or "off"
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SettingsResponse {
+    pub email: String,
+    /// Whether the server can send email at all
+    pub email_enabled: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/notifications",
+    tag = "notifications",
+    params(NotificationsQuery),
+    responses((status = 200, body = NotificationsListResponse))
+)]
+async fn list_notifications(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(query): Query<NotificationsQuery>,
+) -> Result<Json<NotificationsListResponse>> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 200);
+    // One extra row tells whether there is another page
+    let mut notifications = notifications::list(
+        &state.db.pool,
+        &user.id,
+        query.unread,
+        query.before.as_deref(),
+        limit + 1,
+    )
+    .await?;
+    let has_more = notifications.len() as i64 > limit;
+    notifications.truncate(limit as usize);
+
+    let unread_count = notifications::unread_count(&state.db.pool, &user.id).await?;
+    Ok(Json(NotificationsListResponse {
+        notifications,
+        unread_count,
+        has_more,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/notifications/unread",
+    tag = "notifications",
+    responses((status = 200, body = UnreadCountResponse))
+)]
+async fn get_unread_count(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<UnreadCountResponse>> {
+    let count = notifications::unread_count(&state.db.pool, &user.id).await?;
+    Ok(Json(UnreadCountResponse { count }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/notifications/settings",
+    operation_id = "get_notification_settings",
+    tag = "notifications",
+    responses((status = 200, body = SettingsResponse))
+)]
+async fn get_settings(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<SettingsResponse>> {
+    let email = notifications::email_preference(&state.db.pool, &user.id).await?;
+    Ok(Json(SettingsResponse {
+        email,
+        email_enabled: state.notifications.email_enabled(),
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/notifications/settings",
+    operation_id = "update_notification_settings",
+    tag = "notifications",
+    request_body = UpdateSettingsRequest,
+    responses((status = 200, body = SettingsResponse))
+)]
+async fn update_settings(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<UpdateSettingsRequest>,
+) -> Result<Json<SettingsResponse>> {
+    if ![
+        notifications::EMAIL_IMMEDIATE,
+        notifications::EMAIL_DAILY,
+        notifications::EMAIL_OFF,
+    ]
+    .contains(&body.email.as_str())
+    {
+        return Err(AppError::Validation(
+            "Email preference must be 'immediate', 'daily' or 'off'".to_string(),
+        ));
+    }
+
+    notifications::set_email_preference(&state.db.pool, &user.id, &body.email).await?;
+    Ok(Json(SettingsResponse {
+        email: body.email,
+        email_enabled: state.notifications.email_enabled(),
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/notifications/{id}/read",
+    tag = "notifications",
+    params(("id" = String, Path, description = "Notification id")),
+    responses((status = 200, description = "Done"))
+)]
+async fn mark_read(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<()>> {
+    if !notifications::mark_read(&state.db.pool, &user.id, &id).await? {
+        return Err(AppError::NotFound("Notification not found".to_string()));
+    }
+    Ok(Json(()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/notifications/read",
+    tag = "notifications",
+    params(MarkAllReadQuery),
+    responses((status = 200, description = "Done"))
+)]
+async fn mark_all_read(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(query): Query<MarkAllReadQuery>,
+) -> Result<Json<()>> {
+    notifications::mark_all_read(&state.db.pool, &user.id, query.project_id.as_deref()).await?;
+    Ok(Json(()))
+}
@@ -0,0 +1,479 @@
+use axum::{
+    http::header,
+    response::{Html, IntoResponse},
+    routing::get,
+    Json, Router,
+};
+use utoipa::{
+    openapi::{
+        security::{HttpAuthScheme, HttpBuilder, SecurityRequirement, SecurityScheme},
+        ContentBuilder, ObjectBuilder, RefOr, ResponseBuilder, SchemaType,
+    },
+    Modify, OpenApi,
+};
+
+use super::{
+    admin, api_tokens, auth, bib_import, bibtex, chat, comments, compile, digest, export, figures,
+    files, git, git_credentials, history, latexdiff, notifications, presence, projects, search,
+    sessions, spellcheck, symbols, system, templates, track_changes, uploads, users, versions,
+    webdav, zotero,
+};
+use crate::{services, AppState};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/openapi.json", get(openapi_json))
+        .route("/docs", get(swagger_ui))
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "OpenLeaf API"),
+    paths(
+        projects::list_projects,
+        projects::create_project,
+        projects::get_project,
+        projects::delete_project,
+        projects::freeze_project,
+        projects::unfreeze_project,
+        projects::get_settings,
+        projects::update_settings,
+        projects::list_collaborators,
+        projects::add_collaborator,
+        projects::remove_collaborator,
+        digest::get_changes,
+        spellcheck::check_spelling,
+        spellcheck::list_words,
+        spellcheck::add_word,
+        spellcheck::remove_word,
+        bib_import::import_reference,
+        symbols::get_symbols,
+        figures::get_figures,
+        system::get_status,
+        users::get_identicon,
+        presence::get_presence,
+        chat::list_messages,
+        versions::list_versions,
+        versions::create_version,
+        versions::compare_versions,
+        versions::get_version,
+        versions::delete_version,
+        versions::restore_version,
+        git::get_status,
+        git::enable_git,
+        git::disable_git,
+        git::commit,
+        git::get_log,
+        git::get_diff,
+        git::checkout,
+        git::set_remote,
+        git::remove_remote,
+        git::push,
+        git::pull,
+        webdav::get_link,
+        webdav::link_folder,
+        webdav::unlink_folder,
+        webdav::sync,
+        export::export_project,
+        latexdiff::latexdiff_project,
+        files::list_files,
+        files::create_file,
+        files::upload_files,
+        files::bulk_operations,
+        files::rescan_project,
+        files::reorder_files,
+        files::get_file,
+        files::update_file,
+        files::delete_file,
+        files::get_file_content,
+        files::update_file_content,
+        files::diff_file,
+        files::get_outline,
+        files::verify_file,
+        files::lock_file,
+        files::unlock_file,
+        files::copy_file_to,
+        files::convert_file,
+        files::download_file,
+        files::get_thumbnail,
+        uploads::create_upload,
+        uploads::get_upload,
+        uploads::upload_chunk,
+        uploads::cancel_upload,
+        uploads::finalize_upload,
+        bibtex::get_database,
+        bibtex::get_duplicates,
+        bibtex::add_entry,
+        bibtex::update_entry,
+        bibtex::delete_entry,
+        history::get_authorship,
+        history::get_timeline,
+        history::get_content_at,
+        history::restore_content,
+        compile::compile_project,
+        compile::start_compile_job,
+        compile::get_pdf,
+        compile::get_pdf_provenance,
+        compile::get_pdf_pages,
+        compile::get_page_thumbnail,
+        compile::synctex_forward,
+        compile::synctex_inverse,
+        compile::list_history,
+        compile::compile_stats,
+        compile::get_history_entry,
+        compile::get_history_pdf,
+        compile::list_artifacts,
+        compile::download_artifact,
+        compile::get_log,
+        compile::clean_build,
+        compile::word_count,
+        compile::lint_file,
+        compile::get_compile_job,
+        compile::cancel_compile_job,
+        compile::stream_compile_log,
+        comments::list_comments,
+        comments::count_open_comments,
+        comments::list_file_comments,
+        comments::create_comment,
+        comments::get_comment,
+        comments::update_comment,
+        comments::delete_comment,
+        comments::resolve_comment,
+        comments::unresolve_comment,
+        comments::list_revisions,
+        track_changes::list_changes,
+        track_changes::suggest_change,
+        track_changes::get_change,
+        track_changes::delete_change,
+        track_changes::accept_change,
+        track_changes::reject_change,
+        notifications::list_notifications,
+        notifications::get_unread_count,
+        notifications::mark_all_read,
+        notifications::get_settings,
+        notifications::update_settings,
+        notifications::mark_read,
+        search::search_projects,
+        templates::list_templates,
+        templates::publish_template,
+        templates::get_template,
+        templates::unpublish_template,
+        templates::get_template_preview,
+        admin::list_all_backups,
+        admin::list_project_backups,
+        admin::create_backup,
+        admin::restore_backup,
+        admin::collect_garbage,
+        admin::get_stats,
+        admin::update_system_status,
+        admin::reload_templates,
+        admin::list_users,
+        admin::disable_user,
+        admin::enable_user,
+        admin::purge_users,
+        zotero::get_key,
+        zotero::set_key,
+        zotero::delete_key,
+        zotero::get_link,
+        zotero::set_link,
+        zotero::delete_link,
+        zotero::sync_project,
+        git_credentials::list_credentials,
+        git_credentials::set_credential,
+        git_credentials::delete_credential,
+        api_tokens::list_tokens,
+        api_tokens::create_token,
+        api_tokens::revoke_token,
+        sessions::list_sessions,
+        sessions::revoke_session,
+        sessions::revoke_other_sessions,
+        auth::register,
+        auth::login,
+    ),
+    components(schemas(
+        admin::BackupListResponse,
+        admin::UserListResponse,
+        api_tokens::CreateTokenRequest,
+        api_tokens::CreateTokenResponse,
+        api_tokens::TokensListResponse,
+        auth::AuthResponse,
+        auth::LoginRequest,
+        auth::RegisterRequest,
+        auth::UserResponse,
+        bib_import::ImportRequest,
+        bibtex::EntryRequest,
+        chat::ChatHistoryResponse,
+        comments::CommentCountsResponse,
+        comments::CommentResponse,
+        comments::CommentRevision,
+        comments::CommentsListResponse,
+        comments::CommentsPageResponse,
+        comments::CreateCommentRequest,
+        comments::FileCommentCount,
+        comments::RevisionsListResponse,
+        comments::UpdateCommentRequest,
+        compile::ArtifactResponse,
+        compile::ArtifactsResponse,
+        compile::CompileJobResponse,
+        compile::CompileRequest,
+        compile::CompileResponse,
+        compile::ForwardSearchResponse,
+        compile::HistoryResponse,
+        compile::LintRequest,
+        compile::LintResponse,
+        compile::PdfPage,
+        compile::PdfPagesResponse,
+        compile::WordCountResponse,
+        export::ExportRequest,
+        latexdiff::LatexdiffRequest,
+        files::BulkItemResult,
+        files::BulkOperation,
+        files::BulkRequest,
+        files::BulkResponse,
+        files::ConvertRequest,
+        files::CopyToRequest,
+        files::CreateFileRequest,
+        files::DiffRequest,
+        files::FileContentResponse,
+        files::FileListResponse,
+        files::FileResponse,
+        files::LockRequest,
+        files::ReorderRequest,
+        files::UpdateContentRequest,
+        files::UpdateFileRequest,
+        files::UploadResponse,
+        files::VerifyResponse,
+        git::CheckoutRequest,
+        git::CommitRequest,
+        git::CommitResponse,
+        git::EnableGitRequest,
+        git::LogResponse,
+        git::SetRemoteRequest,
+        git_credentials::CredentialsResponse,
+        git_credentials::SetCredentialRequest,
+        history::ContentAtResponse,
+        history::TimelineResponse,
+        notifications::NotificationsListResponse,
+        notifications::SettingsResponse,
+        notifications::UnreadCountResponse,
+        notifications::UpdateSettingsRequest,
+        projects::AddCollaboratorRequest,
+        projects::BuildTarget,
+        projects::CollaboratorResponse,
+        projects::CollaboratorsListResponse,
+        projects::CreateProjectRequest,
+        projects::ProjectListResponse,
+        projects::ProjectResponse,
+        projects::ProjectSettings,
+        search::SearchResponse,
+        sessions::RevokedSessionsResponse,
+        sessions::SessionsListResponse,
+        spellcheck::AddWordRequest,
+        spellcheck::DictionaryResponse,
+        spellcheck::SpellcheckRequest,
+        spellcheck::SpellcheckResponse,
+        templates::PublishTemplateRequest,
+        templates::TemplateListResponse,
+        track_changes::ChangesListResponse,
+        track_changes::SuggestChangeRequest,
+        uploads::CreateUploadRequest,
+        uploads::UploadResponse,
+        versions::CompareResponse,
+        versions::CreateVersionRequest,
+        versions::VersionResponse,
+        versions::VersionsListResponse,
+        webdav::LinkRequest,
+        webdav::LinkResponse,
+        zotero::KeyResponse,
+        zotero::LinkResponse,
+        zotero::SetKeyRequest,
+        zotero::SetLinkRequest,
+        zotero::SyncResponse,
+        services::accounts::Account,
+        services::accounts::PurgeReport,
+        services::api_tokens::ApiToken,
+        services::authorship::AuthorSummary,
+        services::authorship::Authorship,
+        services::authorship::AuthorshipRange,
+        services::backup::BackupInfo,
+        services::backup::RestoreReport,
+        services::bibliography::BibIssue,
+        services::bibliography::BibIssueKind,
+        services::bibtex::BibDatabase,
+        services::bibtex::BibEntry,
+        services::bibtex::BibError,
+        services::bibtex::BibField,
+        services::bibtex::Duplicate,
+        services::build_cache::Artifact,
+        services::chat::ChatMessage,
+        services::collab::Presence,
+        services::compile_history::CompileRecord,
+        services::compile_history::CompileRecordDetail,
+        services::compile_history::CompileStats,
+        services::compile_history::DiagnosticsDelta,
+        services::compile_history::DurationStats,
+        services::compile_jobs::JobInfo,
+        services::compile_jobs::JobStatus,
+        services::compiler::BibTool,
+        services::compiler::CompileError,
+        services::compiler::CompileWarning,
+        services::compiler::Engine,
+        services::convert::TargetFormat,
+        services::diff::DiffResult,
+        services::digest::CompileActivity,
+        services::digest::FileChange,
+        services::digest::NewComment,
+        services::digest::ProjectChanges,
+        services::error_log::LoggedError,
+        services::figures::Figure,
+        services::figures::FigureReport,
+        services::figures::MissingFigure,
+        services::gc::GcReport,
+        services::gc::OrphanedFile,
+        services::git::CheckoutReport,
+        services::git::Commit,
+        services::git::DiffFile,
+        services::git::GitDiff,
+        services::git::GitStatus,
+        services::git::PullOutcome,
+        services::git::PullReport,
+        services::git::Remote,
+        services::git_credentials::CredentialInfo,
+        services::history::HistoryEntry,
+        services::instance_stats::CompileVolume,
+        services::instance_stats::InstanceStats,
+        services::instance_stats::SessionStats,
+        services::lint::LintSeverity,
+        services::lint::LintWarning,
+        services::notifications::Notification,
+        services::outline::Environment,
+        services::outline::Include,
+        services::outline::Label,
+        services::outline::Outline,
+        services::outline::Section,
+        services::packages::MissingPackage,
+        services::pdf_pages::PageInfo,
+        services::provenance::Provenance,
+        services::reconcile::RescanReport,
+        services::search::Highlight,
+        services::search::SearchHit,
+        services::sessions::Session,
+        services::spellcheck::Misspelling,
+        services::symbols::CitationSymbol,
+        services::symbols::CommandSymbol,
+        services::symbols::GlossarySymbol,
+        services::symbols::LabelSymbol,
+        services::symbols::Symbols,
+        services::synctex::PdfLocation,
+        services::synctex::SourceLocation,
+        services::system_status::StatusUpdate,
+        services::system_status::SystemStatus,
+        services::templates::LibraryReport,
+        services::templates::SkippedTemplate,
+        services::templates::TemplateDetails,
+        services::templates::TemplateInfo,
+        services::templates::TemplateVariables,
+        services::track_changes::TrackedChange,
+        services::versions::ChangeKind,
+        services::versions::FileChange,
+        services::versions::ProjectVersion,
+        services::versions::RestoreReport,
+        services::versions::VersionFile,
+        services::webdav::SyncLink,
+        services::webdav::SyncReport,
+        services::wordcount::WordCount,
+    )),
+    modifiers(&ApiConventions)
+)]
+pub struct ApiDoc;
+
+/// What every handler shares and so isn't repeated on each: the bearer
+/// token, which can be a session JWT or an API token, and the error body.
+struct ApiConventions;
+
+impl Modify for ApiConventions {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.schemas.insert(
+            "Error".to_string(),
+            ObjectBuilder::new()
+                .property(
+                    "error",
+                    ObjectBuilder::new().schema_type(SchemaType::String),
+                )
+                .required("error")
+                .property(
+                    "retry_after",
+                    ObjectBuilder::new()
+                        .schema_type(SchemaType::Integer)
+                        .description(Some("Seconds to wait, on 429 responses")),
+                )
+                .into(),
+        );
+
+        let error = ResponseBuilder::new()
+            .description("The request failed; `error` says why")
+            .content(
+                "application/json",
+                ContentBuilder::new()
+                    .schema(utoipa::openapi::Ref::from_schema_name("Error"))
+                    .build(),
+            )
+            .build();
+        for (path, item) in openapi.paths.paths.iter_mut() {
+            for operation in item.operations.values_mut() {
+                operation
+                    .responses
+                    .responses
+                    .insert("default".to_string(), RefOr::T(error.clone()));
+                // Registering and logging in are how a token is obtained, the
+                // system status is shown before signing in, and identicons are
+                // loaded by image tags that cannot send a token
+                if !path.starts_with("/api/auth/")
+                    && path != "/api/system/status"
+                    && path != "/api/users/{id}/identicon.svg"
+                {
+                    operation.security = Some(vec![SecurityRequirement::new(
+                        "bearer",
+                        Vec::<String>::new(),
+                    )]);
+                }
+            }
+        }
+    }
+}
+
+async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// Swagger UI over the document above. The page pulls its scripts from a
+/// CDN rather than bundling them into the binary.
+async fn swagger_ui() -> impl IntoResponse {
+    ([(header::CACHE_CONTROL, "no-cache")], Html(SWAGGER_UI))
+}
+
+const SWAGGER_UI: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>OpenLeaf API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.ui = SwaggerUIBundle({
+      url: "openapi.json",
+      dom_id: "#swagger-ui",
+      persistAuthorization: true,
+    });
+  </script>
+</body>
+</html>
+"##;
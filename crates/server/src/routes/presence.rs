@@ -0,0 +1,58 @@
+use axum::{
+    extract::{Path, State},
+    routing::get,
+    Json, Router,
+};
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::collab::Presence,
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/:id/presence", get(get_presence))
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+/// Who has files of the project open, and where their cursors are.
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/presence",
+    tag = "presence",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = [Presence]))
+)]
+async fn get_presence(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<Presence>>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    Ok(Json(state.collab.presence(&id).await))
+}
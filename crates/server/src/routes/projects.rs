@@ -1,15 +1,23 @@
 use axum::{
     extract::{Path, State},
-    routing::get,
+    routing::{get, post},
     Json, Router,
 };
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 use uuid::Uuid;
 
 use crate::{
     error::{AppError, Result},
     middleware::auth::AuthUser,
+    services::{
+        compiler::{self, BibTool, Engine},
+        events::ProjectEvent,
+        freeze, identicon,
+        notifications::{self, NewNotification},
+        templates::{self, TemplateVariables},
+    },
     AppState,
 };
 
@@ -17,6 +25,9 @@ pub fn router() -> Router<AppState> {
     Router::new()
         .route("/", get(list_projects).post(create_project))
         .route("/:id", get(get_project).delete(delete_project))
+        .route("/:id/freeze", post(freeze_project))
+        .route("/:id/unfreeze", post(unfreeze_project))
+        .route("/:id/settings", get(get_settings).put(update_settings))
         .route(
             "/:id/collaborators",
             get(list_collaborators).post(add_collaborator),
@@ -27,36 +38,132 @@ pub fn router() -> Router<AppState> {
         )
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateProjectRequest {
     pub name: String,
+    /// Template to start from; a blank article when absent
+    pub template_id: Option<String>,
+    /// Filled into the template's placeholders
+    #[serde(default)]
+    pub variables: TemplateVariables,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ProjectResponse {
     pub id: String,
     pub name: String,
     pub owner_id: String,
     pub created_at: String,
     pub updated_at: String,
+    /// Set while the project is frozen and its files cannot be changed
+    pub frozen_at: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ProjectListResponse {
     pub projects: Vec<ProjectResponse>,
 }
 
+/// Project-level defaults; `None` falls back to the server default.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ProjectSettings {
+    pub engine: Option<Engine>,
+    pub bib_tool: Option<BibTool>,
+    /// Ask for --shell-escape; only honoured where the server allows it
+    #[serde(default)]
+    pub shell_escape: bool,
+    /// Builds that can be selected by name instead of the default
+    #[serde(default)]
+    pub targets: Vec<BuildTarget>,
+}
+
+/// A named build, e.g. the paper and its supplement from one source tree.
+/// Unset engine and bibliography tool fall back to the project settings.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BuildTarget {
+    pub name: String,
+    pub main_file: String,
+    pub engine: Option<Engine>,
+    pub bib_tool: Option<BibTool>,
+}
+
+// Target names end up in build directory paths
+pub(crate) fn is_valid_target_name(name: &str) -> bool {
+    name.len() <= 64
+        && name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+}
+
+pub(crate) async fn load_settings(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+) -> Result<ProjectSettings> {
+    let (engine, bib_tool, shell_escape, build_targets) =
+        sqlx::query_as::<_, (Option<String>, Option<String>, bool, Option<String>)>(
+            "SELECT engine, bib_tool, shell_escape, build_targets FROM projects WHERE id = $1",
+        )
+        .bind(project_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Project not found".to_string()))?;
+
+    Ok(ProjectSettings {
+        engine: engine.as_deref().and_then(Engine::parse),
+        bib_tool: bib_tool.as_deref().and_then(BibTool::parse),
+        shell_escape,
+        targets: build_targets
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
+    })
+}
+
+// Helper to check if user has access to project
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/projects",
+    tag = "projects",
+    responses((status = 200, body = ProjectListResponse))
+)]
 async fn list_projects(
     State(state): State<AppState>,
     user: AuthUser,
 ) -> Result<Json<ProjectListResponse>> {
     // Get projects owned by user or shared with user
-    let projects = sqlx::query_as::<_, (String, String, String, String, String)>(
+    let projects = sqlx::query_as::<_, (String, String, String, String, String, Option<String>)>(
         r#"
-        SELECT DISTINCT p.id, p.name, p.owner_id, p.created_at, p.updated_at
+        SELECT DISTINCT p.id, p.name, p.owner_id, p.created_at, p.updated_at, p.frozen_at
         FROM projects p
         LEFT JOIN project_collaborators pc ON p.id = pc.project_id
-        WHERE p.owner_id = ? OR pc.user_id = ?
+        WHERE p.owner_id = $1 OR pc.user_id = $2
         ORDER BY p.updated_at DESC
         "#,
     )
@@ -68,12 +175,13 @@ async fn list_projects(
     let projects = projects
         .into_iter()
         .map(
-            |(id, name, owner_id, created_at, updated_at)| ProjectResponse {
+            |(id, name, owner_id, created_at, updated_at, frozen_at)| ProjectResponse {
                 id,
                 name,
                 owner_id,
                 created_at,
                 updated_at,
+                frozen_at,
             },
         )
         .collect();
@@ -81,6 +189,13 @@ async fn list_projects(
     Ok(Json(ProjectListResponse { projects }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/projects",
+    tag = "projects",
+    request_body = CreateProjectRequest,
+    responses((status = 200, body = ProjectResponse))
+)]
 async fn create_project(
     State(state): State<AppState>,
     user: AuthUser,
@@ -89,12 +204,21 @@ async fn create_project(
     if body.name.trim().is_empty() {
         return Err(AppError::Validation("Project name is required".to_string()));
     }
+    let files = templates::files(
+        &state.db.pool,
+        &state.storage,
+        &state.templates,
+        body.template_id
+            .as_deref()
+            .unwrap_or(templates::DEFAULT_TEMPLATE),
+    )
+    .await?;
 
     let project_id = Uuid::new_v4().to_string();
     let now = Utc::now().to_rfc3339();
 
     sqlx::query(
-        "INSERT INTO projects (id, name, owner_id, created_at, updated_at) VALUES (?, ?, ?, ?, ?)",
+        "INSERT INTO projects (id, name, owner_id, created_at, updated_at) VALUES ($1, $2, $3, $4, $5)",
     )
     .bind(&project_id)
     .bind(&body.name)
@@ -105,46 +229,16 @@ async fn create_project(
     .await?;
 
     // Create project directory
-    let project_path = std::path::Path::new(&state.config.storage_path).join(&project_id);
-    std::fs::create_dir_all(&project_path)
-        .map_err(|e| AppError::Internal(format!("Failed to create project directory: {e}")))?;
-
-    // Create default main.tex file
-    let main_tex_content = r#"\documentclass{article}
-\usepackage[utf8]{inputenc}
-
-\title{Untitled Document}
-\author{}
-\date{\today}
-
-\begin{document}
-
-\maketitle
-
-\section{Introduction}
-
-Your content here.
-
-\end{document}
-"#;
-
-    let main_tex_path = project_path.join("main.tex");
-    std::fs::write(&main_tex_path, main_tex_content)
-        .map_err(|e| AppError::Internal(format!("Failed to create main.tex: {e}")))?;
-
-    // Add file to database
-    let file_id = Uuid::new_v4().to_string();
-    sqlx::query(
-        "INSERT INTO files (id, project_id, name, path, is_folder, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)",
+    state.storage.create_project_dir(&project_id).await?;
+
+    let variables = body.variables.resolve(&body.name, &user.name);
+    templates::instantiate(
+        &state.db.pool,
+        &state.storage,
+        &project_id,
+        files,
+        &variables,
     )
-    .bind(&file_id)
-    .bind(&project_id)
-    .bind("main.tex")
-    .bind("main.tex")
-    .bind(false)
-    .bind(&now)
-    .bind(&now)
-    .execute(&state.db.pool)
     .await?;
 
     Ok(Json(ProjectResponse {
@@ -153,21 +247,29 @@ Your content here.
         owner_id: user.id,
         created_at: now.clone(),
         updated_at: now,
+        frozen_at: None,
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}",
+    tag = "projects",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = ProjectResponse))
+)]
 async fn get_project(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<String>,
 ) -> Result<Json<ProjectResponse>> {
     // Check if user has access to project
-    let project = sqlx::query_as::<_, (String, String, String, String, String)>(
+    let project = sqlx::query_as::<_, (String, String, String, String, String, Option<String>)>(
         r#"
-        SELECT DISTINCT p.id, p.name, p.owner_id, p.created_at, p.updated_at
+        SELECT DISTINCT p.id, p.name, p.owner_id, p.created_at, p.updated_at, p.frozen_at
         FROM projects p
         LEFT JOIN project_collaborators pc ON p.id = pc.project_id
-        WHERE p.id = ? AND (p.owner_id = ? OR pc.user_id = ?)
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
         "#,
     )
     .bind(&id)
@@ -177,7 +279,7 @@ async fn get_project(
     .await?
     .ok_or_else(|| AppError::NotFound("Project not found".to_string()))?;
 
-    let (id, name, owner_id, created_at, updated_at) = project;
+    let (id, name, owner_id, created_at, updated_at, frozen_at) = project;
 
     Ok(Json(ProjectResponse {
         id,
@@ -185,16 +287,100 @@ async fn get_project(
         owner_id,
         created_at,
         updated_at,
+        frozen_at,
     }))
 }
 
+/// Stop the project's files from being changed, e.g. once it has been
+/// submitted. It can still be read, compiled and commented on.
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/freeze",
+    tag = "projects",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = ProjectResponse))
+)]
+async fn freeze_project(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<ProjectResponse>> {
+    set_frozen(&state, &user, &id, true).await?;
+    get_project(State(state), user, Path(id)).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/unfreeze",
+    tag = "projects",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = ProjectResponse))
+)]
+async fn unfreeze_project(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<ProjectResponse>> {
+    set_frozen(&state, &user, &id, false).await?;
+    get_project(State(state), user, Path(id)).await
+}
+
+async fn set_frozen(
+    state: &AppState,
+    user: &AuthUser,
+    project_id: &str,
+    frozen: bool,
+) -> Result<()> {
+    // Only the owner can freeze or unfreeze the project
+    let owner_id = sqlx::query_scalar::<_, String>("SELECT owner_id FROM projects WHERE id = $1")
+        .bind(project_id)
+        .fetch_optional(&state.db.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Project not found".to_string()))?;
+    if owner_id != user.id {
+        return Err(AppError::Forbidden(
+            "Only the owner can freeze or unfreeze this project".to_string(),
+        ));
+    }
+
+    freeze::set(
+        &state.db.pool,
+        project_id,
+        frozen.then_some(user.id.as_str()),
+    )
+    .await?;
+    // Open documents stop taking edits at once; what was typed before is
+    // saved, so the frozen files are what everyone last saw
+    state.collab.set_read_only(project_id, frozen).await;
+    if frozen {
+        state.collab.persist_project(project_id).await?;
+    }
+
+    state.events.publish(
+        project_id,
+        ProjectEvent::ProjectFrozen {
+            frozen,
+            user_id: user.id.clone(),
+            name: user.name.clone(),
+        },
+    );
+    Ok(())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/projects/{id}",
+    tag = "projects",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, description = "Done"))
+)]
 async fn delete_project(
     State(state): State<AppState>,
     user: AuthUser,
     Path(id): Path<String>,
 ) -> Result<Json<()>> {
     // Only owner can delete project
-    let project = sqlx::query_as::<_, (String,)>("SELECT owner_id FROM projects WHERE id = ?")
+    let project = sqlx::query_as::<_, (String,)>("SELECT owner_id FROM projects WHERE id = $1")
         .bind(&id)
         .fetch_optional(&state.db.pool)
         .await?
@@ -207,14 +393,11 @@ async fn delete_project(
     }
 
     // Delete project directory
-    let project_path = std::path::Path::new(&state.config.storage_path).join(&id);
-    if project_path.exists() {
-        std::fs::remove_dir_all(&project_path)
-            .map_err(|e| AppError::Internal(format!("Failed to delete project directory: {e}")))?;
-    }
+    state.storage.delete_project_dir(&id).await?;
+    state.git.delete_repo(&id).await?;
 
     // Delete from database (cascades to files and comments)
-    sqlx::query("DELETE FROM projects WHERE id = ?")
+    sqlx::query("DELETE FROM projects WHERE id = $1")
         .bind(&id)
         .execute(&state.db.pool)
         .await?;
@@ -222,32 +405,116 @@ async fn delete_project(
     Ok(Json(()))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/settings",
+    operation_id = "get_project_settings",
+    tag = "projects",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = ProjectSettings))
+)]
+async fn get_settings(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<ProjectSettings>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    Ok(Json(load_settings(&state.db.pool, &id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/projects/{id}/settings",
+    operation_id = "update_project_settings",
+    tag = "projects",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = ProjectSettings,
+    responses((status = 200, body = ProjectSettings))
+)]
+async fn update_settings(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(mut body): Json<ProjectSettings>,
+) -> Result<Json<ProjectSettings>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let mut names = std::collections::HashSet::new();
+    for target in &mut body.targets {
+        if !is_valid_target_name(&target.name) {
+            return Err(AppError::BadRequest(format!(
+                "Invalid build target name '{}'",
+                target.name
+            )));
+        }
+        if !names.insert(target.name.clone()) {
+            return Err(AppError::BadRequest(format!(
+                "Duplicate build target '{}'",
+                target.name
+            )));
+        }
+        target.main_file = compiler::normalize_project_path(&target.main_file)?;
+    }
+    let build_targets = if body.targets.is_empty() {
+        None
+    } else {
+        Some(
+            serde_json::to_string(&body.targets)
+                .map_err(|e| AppError::Internal(format!("Failed to store build targets: {e}")))?,
+        )
+    };
+
+    sqlx::query(
+        "UPDATE projects SET engine = $1, bib_tool = $2, shell_escape = $3, build_targets = $4, updated_at = $5 WHERE id = $6",
+    )
+    .bind(body.engine.map(Engine::as_str))
+    .bind(body.bib_tool.map(BibTool::as_str))
+    .bind(body.shell_escape)
+    .bind(build_targets)
+    .bind(Utc::now().to_rfc3339())
+        .bind(&id)
+        .execute(&state.db.pool)
+        .await?;
+
+    Ok(Json(body))
+}
+
 // Collaborator types
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct AddCollaboratorRequest {
     pub email: String,
     pub role: String, // "editor" or "viewer"
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct CollaboratorResponse {
     pub user_id: String,
     pub user_name: String,
     pub user_email: String,
     pub role: String,
+    pub color: String,
+    pub avatar_url: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct CollaboratorsListResponse {
     pub collaborators: Vec<CollaboratorResponse>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, IntoParams)]
 pub struct CollaboratorPathParams {
     pub id: String,
     pub user_id: String,
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/collaborators",
+    tag = "projects",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = CollaboratorsListResponse))
+)]
 async fn list_collaborators(
     State(state): State<AppState>,
     user: AuthUser,
@@ -258,7 +525,7 @@ async fn list_collaborators(
         r#"
         SELECT COUNT(*) FROM projects p
         LEFT JOIN project_collaborators pc ON p.id = pc.project_id
-        WHERE p.id = ? AND (p.owner_id = ? OR pc.user_id = ?)
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
         "#,
     )
     .bind(&project_id)
@@ -276,7 +543,7 @@ async fn list_collaborators(
         SELECT u.id, u.name, u.email, pc.role
         FROM project_collaborators pc
         JOIN users u ON pc.user_id = u.id
-        WHERE pc.project_id = ?
+        WHERE pc.project_id = $1
         ORDER BY u.name ASC
         "#,
     )
@@ -288,6 +555,8 @@ async fn list_collaborators(
         .into_iter()
         .map(
             |(user_id, user_name, user_email, role)| CollaboratorResponse {
+                color: identicon::color(&user_id).to_string(),
+                avatar_url: identicon::url(&user_id),
                 user_id,
                 user_name,
                 user_email,
@@ -299,6 +568,14 @@ async fn list_collaborators(
     Ok(Json(CollaboratorsListResponse { collaborators }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/collaborators",
+    tag = "projects",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = AddCollaboratorRequest,
+    responses((status = 200, body = CollaboratorResponse))
+)]
 async fn add_collaborator(
     State(state): State<AppState>,
     user: AuthUser,
@@ -306,7 +583,7 @@ async fn add_collaborator(
     Json(body): Json<AddCollaboratorRequest>,
 ) -> Result<Json<CollaboratorResponse>> {
     // Only owner can add collaborators
-    let project = sqlx::query_as::<_, (String,)>("SELECT owner_id FROM projects WHERE id = ?")
+    let project = sqlx::query_as::<_, (String,)>("SELECT owner_id FROM projects WHERE id = $1")
         .bind(&project_id)
         .fetch_optional(&state.db.pool)
         .await?
@@ -325,9 +602,9 @@ async fn add_collaborator(
         ));
     }
 
-    // Find user by email
+    // Find user by email; disabled accounts cannot be added
     let target_user = sqlx::query_as::<_, (String, String, String)>(
-        "SELECT id, name, email FROM users WHERE email = ?",
+        "SELECT id, name, email FROM users WHERE email = $1 AND disabled_at IS NULL",
     )
     .bind(&body.email)
     .fetch_optional(&state.db.pool)
@@ -345,7 +622,7 @@ async fn add_collaborator(
 
     // Check if already a collaborator
     let exists = sqlx::query_scalar::<_, i64>(
-        "SELECT COUNT(*) FROM project_collaborators WHERE project_id = ? AND user_id = ?",
+        "SELECT COUNT(*) FROM project_collaborators WHERE project_id = $1 AND user_id = $2",
     )
     .bind(&project_id)
     .bind(&target_user_id)
@@ -355,7 +632,7 @@ async fn add_collaborator(
     if exists > 0 {
         // Update role instead
         sqlx::query(
-            "UPDATE project_collaborators SET role = ? WHERE project_id = ? AND user_id = ?",
+            "UPDATE project_collaborators SET role = $1 WHERE project_id = $2 AND user_id = $3",
         )
         .bind(&body.role)
         .bind(&project_id)
@@ -364,7 +641,7 @@ async fn add_collaborator(
         .await?;
     } else {
         sqlx::query(
-            "INSERT INTO project_collaborators (project_id, user_id, role) VALUES (?, ?, ?)",
+            "INSERT INTO project_collaborators (project_id, user_id, role) VALUES ($1, $2, $3)",
         )
         .bind(&project_id)
         .bind(&target_user_id)
@@ -373,7 +650,35 @@ async fn add_collaborator(
         .await?;
     }
 
+    state.events.publish(
+        &project_id,
+        ProjectEvent::CollaboratorJoined {
+            user_id: target_user_id.clone(),
+            name: target_user_name.clone(),
+            role: body.role.clone(),
+        },
+    );
+
+    if exists == 0 {
+        state
+            .notifications
+            .notify(
+                std::slice::from_ref(&target_user_id),
+                NewNotification {
+                    kind: notifications::INVITE,
+                    project_id: project_id.clone(),
+                    actor_id: Some(user.id.clone()),
+                    target_id: None,
+                    file_path: None,
+                    message: format!("{} added you to a project as {}", user.name, body.role),
+                },
+            )
+            .await;
+    }
+
     Ok(Json(CollaboratorResponse {
+        color: identicon::color(&target_user_id).to_string(),
+        avatar_url: identicon::url(&target_user_id),
         user_id: target_user_id,
         user_name: target_user_name,
         user_email: target_user_email,
@@ -381,13 +686,20 @@ async fn add_collaborator(
     }))
 }
 
+#[utoipa::path(
+    delete,
+    path = "/api/projects/{id}/collaborators/{user_id}",
+    tag = "projects",
+    params(CollaboratorPathParams),
+    responses((status = 200, description = "Done"))
+)]
 async fn remove_collaborator(
     State(state): State<AppState>,
     user: AuthUser,
     Path(params): Path<CollaboratorPathParams>,
 ) -> Result<Json<()>> {
     // Only owner can remove collaborators (or user can remove themselves)
-    let project = sqlx::query_as::<_, (String,)>("SELECT owner_id FROM projects WHERE id = ?")
+    let project = sqlx::query_as::<_, (String,)>("SELECT owner_id FROM projects WHERE id = $1")
         .bind(&params.id)
         .fetch_optional(&state.db.pool)
         .await?
@@ -399,11 +711,18 @@ async fn remove_collaborator(
         ));
     }
 
-    sqlx::query("DELETE FROM project_collaborators WHERE project_id = ? AND user_id = ?")
+    sqlx::query("DELETE FROM project_collaborators WHERE project_id = $1 AND user_id = $2")
         .bind(&params.id)
         .bind(&params.user_id)
         .execute(&state.db.pool)
         .await?;
 
+    state.events.publish(
+        &params.id,
+        ProjectEvent::CollaboratorRemoved {
+            user_id: params.user_id,
+        },
+    );
+
     Ok(Json(()))
 }
@@ -0,0 +1,107 @@
+use axum::{
+    extract::{Query, State},
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::search::{self, SearchHit},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/", get(search_projects))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SearchQuery {
+    pub q: String,
+    /// Only search this project; otherwise every project the user can open
+    pub project_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResponse {
+    /// Best match first
+    pub hits: Vec<SearchHit>,
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+/// File contents, file names, comments and project names matching every
+/// word of `q`, best first.
+#[utoipa::path(
+    get,
+    path = "/api/search",
+    tag = "search",
+    params(SearchQuery),
+    responses((status = 200, body = SearchResponse))
+)]
+async fn search_projects(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResponse>> {
+    let project_ids = match &query.project_id {
+        Some(project_id) => {
+            check_project_access(&state.db.pool, project_id, &user.id).await?;
+            vec![project_id.clone()]
+        }
+        None => {
+            sqlx::query_scalar::<_, String>(
+                r#"
+                SELECT DISTINCT p.id FROM projects p
+                LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+                WHERE p.owner_id = $1 OR pc.user_id = $2
+                "#,
+            )
+            .bind(&user.id)
+            .bind(&user.id)
+            .fetch_all(&state.db.pool)
+            .await?
+        }
+    };
+
+    // Pick up changes that did not go through a save
+    for project_id in &project_ids {
+        search::refresh(&state.db.pool, &state.storage, project_id).await?;
+    }
+
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let hits = search::search(
+        &state.db.pool,
+        &user.id,
+        query.project_id.as_deref(),
+        &query.q,
+        limit,
+    )
+    .await?;
+    Ok(Json(SearchResponse { hits }))
+}
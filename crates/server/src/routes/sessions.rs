@@ -0,0 +1,79 @@
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get},
+    Json, Router,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::sessions::{self, Session},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_sessions).delete(revoke_other_sessions))
+        .route("/:id", delete(revoke_session))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionsListResponse {
+    pub sessions: Vec<Session>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RevokedSessionsResponse {
+    pub revoked: u64,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me/sessions",
+    tag = "sessions",
+    responses((status = 200, body = SessionsListResponse))
+)]
+async fn list_sessions(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<SessionsListResponse>> {
+    let sessions = sessions::list(&state.db.pool, &user.id, user.session_id.as_deref()).await?;
+    Ok(Json(SessionsListResponse { sessions }))
+}
+
+/// Sign a device out. Its token stops working at once.
+#[utoipa::path(
+    delete,
+    path = "/api/users/me/sessions/{id}",
+    tag = "sessions",
+    params(("id" = String, Path, description = "Session id")),
+    responses((status = 200, description = "Done"))
+)]
+async fn revoke_session(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<()>> {
+    if !sessions::revoke(&state.db.pool, &user.id, &id).await? {
+        return Err(AppError::NotFound("Session not found".to_string()));
+    }
+    Ok(Json(()))
+}
+
+/// Sign out every device but the one making the request.
+#[utoipa::path(
+    delete,
+    path = "/api/users/me/sessions",
+    tag = "sessions",
+    responses((status = 200, body = RevokedSessionsResponse))
+)]
+async fn revoke_other_sessions(
+    State(state): State<AppState>,
+    user: AuthUser,
+) -> Result<Json<RevokedSessionsResponse>> {
+    let revoked =
+        sessions::revoke_others(&state.db.pool, &user.id, user.session_id.as_deref()).await?;
+    Ok(Json(RevokedSessionsResponse { revoked }))
+}
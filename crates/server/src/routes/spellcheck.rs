@@ -0,0 +1,206 @@
+use std::collections::HashSet;
+
+use axum::{
+    extract::{Path, State},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::spellcheck::{self, Misspelling},
+    AppState,
+};
+
+// Larger documents are checked in chunks by the client
+const MAX_TEXT_LENGTH: usize = 200_000;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/:id/spellcheck", post(check_spelling))
+        .route("/:id/dictionary", get(list_words).post(add_word))
+        .route("/:id/dictionary/:word", delete(remove_word))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SpellcheckRequest {
+    /// LaTeX source; offsets in the response are relative to it
+    pub text: String,
+    /// Hunspell dictionary name, e.g. "en_GB"; defaults to the server's
+    pub language: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SpellcheckResponse {
+    pub language: String,
+    pub misspellings: Vec<Misspelling>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DictionaryResponse {
+    pub words: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AddWordRequest {
+    pub word: String,
+}
+
+// Helper to check if user has access to project
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/spellcheck",
+    tag = "spellcheck",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = SpellcheckRequest,
+    responses((status = 200, body = SpellcheckResponse))
+)]
+async fn check_spelling(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<SpellcheckRequest>,
+) -> Result<Json<SpellcheckResponse>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    if body.text.len() > MAX_TEXT_LENGTH {
+        return Err(AppError::BadRequest(format!(
+            "Text is too long to check at once (limit {MAX_TEXT_LENGTH} bytes)"
+        )));
+    }
+    let language = body
+        .language
+        .unwrap_or_else(|| state.config.spellcheck_language.clone());
+    // Passed to hunspell as a dictionary name
+    let valid_language = !language.is_empty()
+        && language
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-'));
+    if !valid_language {
+        return Err(AppError::BadRequest(format!(
+            "Invalid language '{language}'"
+        )));
+    }
+
+    let dictionary: HashSet<String> = spellcheck::dictionary(&state.db.pool, &id)
+        .await?
+        .into_iter()
+        .collect();
+    let misspellings = spellcheck::check(&body.text, &language, &dictionary).await?;
+
+    Ok(Json(SpellcheckResponse {
+        language,
+        misspellings,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/dictionary",
+    tag = "spellcheck",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = DictionaryResponse))
+)]
+async fn list_words(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<DictionaryResponse>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let words = spellcheck::dictionary(&state.db.pool, &id).await?;
+    Ok(Json(DictionaryResponse { words }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/dictionary",
+    tag = "spellcheck",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = AddWordRequest,
+    responses((status = 200, description = "Done"))
+)]
+async fn add_word(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<AddWordRequest>,
+) -> Result<Json<()>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let word = body.word.trim();
+    if !spellcheck::is_valid_word(word) {
+        return Err(AppError::BadRequest(format!("Invalid word '{word}'")));
+    }
+
+    sqlx::query(
+        "INSERT INTO project_dictionary (project_id, word, created_at) VALUES ($1, $2, $3) \
+         ON CONFLICT DO NOTHING",
+    )
+    .bind(&id)
+    .bind(word)
+    .bind(Utc::now().to_rfc3339())
+    .execute(&state.db.pool)
+    .await?;
+
+    Ok(Json(()))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/projects/{id}/dictionary/{word}",
+    tag = "spellcheck",
+    params(
+        ("id" = String, Path, description = "Project id"),
+        ("word" = String, Path, description = "Word to remove"),
+    ),
+    responses((status = 200, description = "Done"))
+)]
+async fn remove_word(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, word)): Path<(String, String)>,
+) -> Result<Json<()>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let removed = sqlx::query("DELETE FROM project_dictionary WHERE project_id = $1 AND word = $2")
+        .bind(&id)
+        .bind(&word)
+        .execute(&state.db.pool)
+        .await?;
+    if removed.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "'{word}' is not in the dictionary"
+        )));
+    }
+
+    Ok(Json(()))
+}
@@ -0,0 +1,17 @@
+use axum::{extract::State, routing::get, Json, Router};
+
+use crate::{services::system_status::SystemStatus, AppState};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/status", get(get_status))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/system/status",
+    tag = "system",
+    responses((status = 200, body = SystemStatus))
+)]
+async fn get_status(State(state): State<AppState>) -> Json<SystemStatus> {
+    Json(state.system.current())
+}
@@ -0,0 +1,171 @@
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::Response,
+    routing::get,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::{AdminUser, AuthUser},
+    services::templates::{self, TemplateDetails, TemplateInfo},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", get(list_templates).post(publish_template))
+        .route("/:id", get(get_template).delete(unpublish_template))
+        .route("/:id/preview.png", get(get_template_preview))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct TemplatesQuery {
+    /// Only templates with this tag
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TemplateListResponse {
+    pub templates: Vec<TemplateInfo>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PublishTemplateRequest {
+    /// Project to publish; only its owner can
+    pub project_id: String,
+    #[serde(flatten)]
+    pub details: TemplateDetails,
+}
+
+/// Templates a new project can start from.
+#[utoipa::path(
+    get,
+    path = "/api/templates",
+    tag = "templates",
+    params(TemplatesQuery),
+    responses((status = 200, body = TemplateListResponse))
+)]
+async fn list_templates(
+    State(state): State<AppState>,
+    _user: AuthUser,
+    Query(query): Query<TemplatesQuery>,
+) -> Result<Json<TemplateListResponse>> {
+    Ok(Json(TemplateListResponse {
+        templates: templates::list(&state.db.pool, &state.templates, query.tag.as_deref()).await?,
+    }))
+}
+
+/// Publish a snapshot of a project for everyone on the server to start new
+/// projects from. Compile it first to give the template a preview.
+#[utoipa::path(
+    post,
+    path = "/api/templates",
+    tag = "templates",
+    request_body = PublishTemplateRequest,
+    responses((status = 200, body = TemplateInfo))
+)]
+async fn publish_template(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<PublishTemplateRequest>,
+) -> Result<Json<TemplateInfo>> {
+    let owner_id = sqlx::query_scalar::<_, String>("SELECT owner_id FROM projects WHERE id = $1")
+        .bind(&body.project_id)
+        .fetch_optional(&state.db.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Project not found".to_string()))?;
+    if owner_id != user.id {
+        return Err(AppError::Forbidden(
+            "Only the owner can publish this project as a template".to_string(),
+        ));
+    }
+
+    let template = templates::publish(
+        &state.db.pool,
+        &state.storage,
+        &state.collab,
+        std::path::Path::new(&state.config.cache_path),
+        &body.project_id,
+        &user.id,
+        body.details,
+    )
+    .await?;
+    tracing::info!(
+        "User {} published project {} as template {}",
+        user.id,
+        body.project_id,
+        template.id
+    );
+    Ok(Json(template))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/templates/{id}",
+    tag = "templates",
+    params(("id" = String, Path, description = "Template id")),
+    responses((status = 200, body = TemplateInfo))
+)]
+async fn get_template(
+    State(state): State<AppState>,
+    _user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<TemplateInfo>> {
+    Ok(Json(
+        templates::get(&state.db.pool, &state.templates, &id).await?,
+    ))
+}
+
+/// Take a published template down; its publisher and admins can.
+#[utoipa::path(
+    delete,
+    path = "/api/templates/{id}",
+    tag = "templates",
+    params(("id" = String, Path, description = "Template id")),
+    responses((status = 200, description = "Done"))
+)]
+async fn unpublish_template(
+    State(state): State<AppState>,
+    user: AuthUser,
+    admin: Option<AdminUser>,
+    Path(id): Path<String>,
+) -> Result<Json<()>> {
+    let template = templates::published(&state.db.pool, &id).await?;
+    if admin.is_none() && template.published_by.as_deref() != Some(user.id.as_str()) {
+        return Err(AppError::Forbidden(
+            "Only the publisher can take this template down".to_string(),
+        ));
+    }
+
+    templates::unpublish(&state.db.pool, &state.storage, &id).await?;
+    tracing::info!("User {} took down template {}", user.id, id);
+    Ok(Json(()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/templates/{id}/preview.png",
+    tag = "templates",
+    params(("id" = String, Path, description = "Template id")),
+    responses((status = 200, description = "The first page of the template", content_type = "image/png", body = Vec<u8>))
+)]
+async fn get_template_preview(
+    State(state): State<AppState>,
+    _user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Response> {
+    let png = templates::preview_png(&state.db.pool, &state.storage, &state.templates, &id).await?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/png")
+        // A template's preview never changes; taking it down removes it
+        .header(header::CACHE_CONTROL, "private, max-age=86400")
+        .body(Body::from(png))
+        .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")))
+}
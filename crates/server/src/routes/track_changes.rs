@@ -0,0 +1,268 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{
+        events::ProjectEvent,
+        freeze,
+        track_changes::{self, TrackedChange},
+    },
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/project/:project_id", get(list_changes))
+        .route("/", post(suggest_change))
+        .route("/:id", get(get_change).delete(delete_change))
+        .route("/:id/accept", post(accept_change))
+        .route("/:id/reject", post(reject_change))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SuggestChangeRequest {
+    pub project_id: String,
+    pub file_path: String,
+    /// Character offsets of the text to replace; equal for an insertion
+    pub start: usize,
+    pub end: usize,
+    /// Replacement text; empty for a deletion
+    #[serde(default)]
+    pub content: String,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct ChangesQuery {
+    pub file_path: Option<String>,
+    /// pending (the default), accepted, rejected or all
+    pub status: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ChangesListResponse {
+    pub changes: Vec<TrackedChange>,
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/changes/project/{project_id}",
+    tag = "track_changes",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        ChangesQuery,
+    ),
+    responses((status = 200, body = ChangesListResponse))
+)]
+async fn list_changes(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<ChangesListResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    let status = match query.status.as_deref() {
+        None => Some(track_changes::PENDING),
+        Some("all") => None,
+        Some(
+            status @ (track_changes::PENDING | track_changes::ACCEPTED | track_changes::REJECTED),
+        ) => Some(status),
+        Some(other) => {
+            return Err(AppError::Validation(format!("Unknown status: {other}")));
+        }
+    };
+
+    let changes = track_changes::list(
+        &state.db.pool,
+        &project_id,
+        query.file_path.as_deref(),
+        status,
+    )
+    .await?;
+
+    Ok(Json(ChangesListResponse { changes }))
+}
+
+/// Record an edit made in suggestion mode instead of applying it.
+#[utoipa::path(
+    post,
+    path = "/api/changes",
+    tag = "track_changes",
+    request_body = SuggestChangeRequest,
+    responses((status = 200, body = TrackedChange))
+)]
+async fn suggest_change(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<SuggestChangeRequest>,
+) -> Result<Json<TrackedChange>> {
+    check_project_access(&state.db.pool, &body.project_id, &user.id).await?;
+
+    let id = track_changes::suggest(
+        &state.db.pool,
+        &state.collab,
+        &body.project_id,
+        &body.file_path,
+        &user.id,
+        body.start..body.end,
+        &body.content,
+    )
+    .await?;
+
+    state.events.publish(
+        &body.project_id,
+        ProjectEvent::ChangeSuggested {
+            change_id: id.clone(),
+            file_path: body.file_path.clone(),
+            author_id: user.id.clone(),
+            author_name: user.name.clone(),
+        },
+    );
+
+    Ok(Json(track_changes::get(&state.db.pool, &id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/changes/{id}",
+    tag = "track_changes",
+    params(("id" = String, Path, description = "Suggested change id")),
+    responses((status = 200, body = TrackedChange))
+)]
+async fn get_change(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<TrackedChange>> {
+    let change = track_changes::get(&state.db.pool, &id).await?;
+    check_project_access(&state.db.pool, &change.project_id, &user.id).await?;
+
+    Ok(Json(change))
+}
+
+/// Apply a suggested change to the document.
+#[utoipa::path(
+    post,
+    path = "/api/changes/{id}/accept",
+    tag = "track_changes",
+    params(("id" = String, Path, description = "Suggested change id")),
+    responses((status = 200, body = TrackedChange))
+)]
+async fn accept_change(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<TrackedChange>> {
+    let change = track_changes::get(&state.db.pool, &id).await?;
+    check_project_access(&state.db.pool, &change.project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &change.project_id).await?;
+
+    track_changes::accept(&state.db.pool, &state.collab, &change, &user.id).await?;
+
+    state.events.publish(
+        &change.project_id,
+        ProjectEvent::ChangeReviewed {
+            change_id: change.id.clone(),
+            file_path: change.file_path.clone(),
+            status: track_changes::ACCEPTED.to_string(),
+        },
+    );
+
+    Ok(Json(track_changes::get(&state.db.pool, &id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/changes/{id}/reject",
+    tag = "track_changes",
+    params(("id" = String, Path, description = "Suggested change id")),
+    responses((status = 200, body = TrackedChange))
+)]
+async fn reject_change(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<TrackedChange>> {
+    let change = track_changes::get(&state.db.pool, &id).await?;
+    check_project_access(&state.db.pool, &change.project_id, &user.id).await?;
+
+    track_changes::reject(&state.db.pool, &id, &user.id).await?;
+
+    state.events.publish(
+        &change.project_id,
+        ProjectEvent::ChangeReviewed {
+            change_id: change.id.clone(),
+            file_path: change.file_path.clone(),
+            status: track_changes::REJECTED.to_string(),
+        },
+    );
+
+    Ok(Json(track_changes::get(&state.db.pool, &id).await?))
+}
+
+/// Withdraw a suggestion, or clear one that was reviewed.
+#[utoipa::path(
+    delete,
+    path = "/api/changes/{id}",
+    tag = "track_changes",
+    params(("id" = String, Path, description = "Suggested change id")),
+    responses((status = 200, description = "Done"))
+)]
+async fn delete_change(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<()>> {
+    let change = track_changes::get(&state.db.pool, &id).await?;
+
+    // Only author or project owner can delete
+    let is_owner = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM projects WHERE id = $1 AND owner_id = $2",
+    )
+    .bind(&change.project_id)
+    .bind(&user.id)
+    .fetch_one(&state.db.pool)
+    .await?;
+
+    if change.author_id != user.id && is_owner == 0 {
+        return Err(AppError::Forbidden("Cannot delete this change".to_string()));
+    }
+
+    sqlx::query("DELETE FROM tracked_changes WHERE id = $1")
+        .bind(&id)
+        .execute(&state.db.pool)
+        .await?;
+
+    Ok(Json(()))
+}
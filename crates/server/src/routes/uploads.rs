@@ -0,0 +1,401 @@
+// Resumable uploads: init a session, PATCH chunks at an offset, then finalize
+// Modeled on the tus protocol so interrupted uploads can continue where they stopped
+
+use std::path::Path as FsPath;
+
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::HeaderMap,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::files::{fetch_file, FileResponse};
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{events::ProjectEvent, filetype, freeze, uploads},
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/project/:project_id/uploads", post(create_upload))
+        .route(
+            "/project/:project_id/uploads/:upload_id",
+            get(get_upload).patch(upload_chunk).delete(cancel_upload),
+        )
+        .route(
+            "/project/:project_id/uploads/:upload_id/finalize",
+            post(finalize_upload),
+        )
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateUploadRequest {
+    pub path: String,
+    pub size: u64,
+    /// Expected SHA-256 of the complete file, checked on finalize
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+#[schema(as = UploadSession)]
+pub struct UploadResponse {
+    pub id: String,
+    pub project_id: String,
+    pub path: String,
+    pub size: i64,
+    /// Bytes received so far; the next chunk must start here
+    pub received: i64,
+    pub sha256: Option<String>,
+    pub expires_at: String,
+}
+
+const UPLOAD_COLUMNS: &str = "id, project_id, path, size, received, sha256, expires_at";
+
+// Helper to check if user has access to project
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+// Sessions are private to the user who started them
+async fn fetch_upload(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    upload_id: &str,
+    user_id: &str,
+) -> Result<UploadResponse> {
+    sqlx::query_as::<_, UploadResponse>(&format!(
+        "SELECT {UPLOAD_COLUMNS} FROM uploads WHERE id = $1 AND project_id = $2 AND user_id = $3"
+    ))
+    .bind(upload_id)
+    .bind(project_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Upload not found".to_string()))
+}
+
+fn session_expiry() -> String {
+    (Utc::now() + chrono::Duration::hours(uploads::SESSION_TTL_HOURS)).to_rfc3339()
+}
+
+async fn path_taken(pool: &crate::db::DbPool, project_id: &str, path: &str) -> Result<bool> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM files WHERE project_id = $1 AND path = $2",
+    )
+    .bind(project_id)
+    .bind(path)
+    .fetch_one(pool)
+    .await?;
+    Ok(exists > 0)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/files/project/{project_id}/uploads",
+    tag = "uploads",
+    params(("project_id" = String, Path, description = "Project id")),
+    request_body = CreateUploadRequest,
+    responses((status = 200, body = UploadSession))
+)]
+async fn create_upload(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(body): Json<CreateUploadRequest>,
+) -> Result<Json<UploadResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &project_id).await?;
+
+    let path = body.path.trim_matches('/').to_string();
+    if path.is_empty() || path.split('/').any(|part| part.is_empty() || part == "..") {
+        return Err(AppError::Validation("Invalid upload path".to_string()));
+    }
+    if body.size > state.config.max_upload_size {
+        return Err(AppError::Validation(format!(
+            "Upload exceeds the maximum size of {} bytes",
+            state.config.max_upload_size
+        )));
+    }
+    if path_taken(&state.db.pool, &project_id, &path).await? {
+        return Err(AppError::Validation(
+            "File already exists at this path".to_string(),
+        ));
+    }
+
+    let upload_id = Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO uploads (id, project_id, user_id, path, size, received, sha256, expires_at) VALUES ($1, $2, $3, $4, $5, 0, $6, $7)",
+    )
+    .bind(&upload_id)
+    .bind(&project_id)
+    .bind(&user.id)
+    .bind(&path)
+    .bind(body.size as i64)
+    .bind(body.sha256.map(|hash| hash.to_ascii_lowercase()))
+    .bind(session_expiry())
+    .execute(&state.db.pool)
+    .await?;
+
+    Ok(Json(
+        fetch_upload(&state.db.pool, &project_id, &upload_id, &user.id).await?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/files/project/{project_id}/uploads/{upload_id}",
+    tag = "uploads",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        ("upload_id" = String, Path, description = "Upload id"),
+    ),
+    responses((status = 200, body = UploadSession))
+)]
+async fn get_upload(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((project_id, upload_id)): Path<(String, String)>,
+) -> Result<Json<UploadResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    Ok(Json(
+        fetch_upload(&state.db.pool, &project_id, &upload_id, &user.id).await?,
+    ))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/files/project/{project_id}/uploads/{upload_id}",
+    tag = "uploads",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        ("upload_id" = String, Path, description = "Upload id"),
+    ),
+    request_body(content = Vec<u8>, description = "The next chunk, starting at the offset in Upload-Offset", content_type = "application/offset+octet-stream"),
+    responses((status = 200, body = UploadSession))
+)]
+async fn upload_chunk(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((project_id, upload_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<UploadResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    let upload = fetch_upload(&state.db.pool, &project_id, &upload_id, &user.id).await?;
+
+    let offset = headers
+        .get("Upload-Offset")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(|| AppError::BadRequest("Upload-Offset header is required".to_string()))?;
+    if offset != upload.received as u64 {
+        return Err(AppError::Conflict(format!(
+            "Upload offset is {}, not {offset}",
+            upload.received
+        )));
+    }
+
+    let part = uploads::part_path(FsPath::new(&state.config.upload_path), &upload_id);
+    let result =
+        uploads::append_chunk(&part, offset, upload.size as u64, body.into_data_stream()).await?;
+
+    // Record whatever arrived, even if the connection dropped part way
+    let updated = sqlx::query(
+        "UPDATE uploads SET received = $1, expires_at = $2 WHERE id = $3 AND received = $4",
+    )
+    .bind(result.received as i64)
+    .bind(session_expiry())
+    .bind(&upload_id)
+    .bind(offset as i64)
+    .execute(&state.db.pool)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        return Err(AppError::Conflict(
+            "Upload was modified concurrently".to_string(),
+        ));
+    }
+    if let Some(e) = result.error {
+        return Err(e);
+    }
+
+    Ok(Json(
+        fetch_upload(&state.db.pool, &project_id, &upload_id, &user.id).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/files/project/{project_id}/uploads/{upload_id}/finalize",
+    tag = "uploads",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        ("upload_id" = String, Path, description = "Upload id"),
+    ),
+    responses((status = 200, body = FileResponse))
+)]
+async fn finalize_upload(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((project_id, upload_id)): Path<(String, String)>,
+) -> Result<Json<FileResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &project_id).await?;
+
+    let upload = fetch_upload(&state.db.pool, &project_id, &upload_id, &user.id).await?;
+
+    if upload.received != upload.size {
+        return Err(AppError::Validation(format!(
+            "Upload incomplete: received {} of {} bytes",
+            upload.received, upload.size
+        )));
+    }
+    if path_taken(&state.db.pool, &project_id, &upload.path).await? {
+        return Err(AppError::Validation(
+            "File already exists at this path".to_string(),
+        ));
+    }
+
+    let part = uploads::part_path(FsPath::new(&state.config.upload_path), &upload_id);
+    if upload.size == 0 {
+        // Nothing was ever PATCHed, so no part file exists yet
+        tokio::fs::write(&part, b"")
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to write upload: {e}")))?;
+    }
+
+    if let Some(expected) = &upload.sha256 {
+        let actual = crate::services::storage::hash_local_file(&part).await?;
+        if &actual != expected {
+            return Err(AppError::Validation(format!(
+                "Checksum mismatch: expected {expected}, got {actual}"
+            )));
+        }
+    }
+
+    let hash = state
+        .storage
+        .write_file_from_path(&project_id, &upload.path, &part)
+        .await?;
+
+    let head = read_head(&part).await?;
+    let file_type = filetype::detect(&upload.path, &head);
+    let name = upload
+        .path
+        .rsplit('/')
+        .next()
+        .unwrap_or(&upload.path)
+        .to_string();
+    let file_id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+
+    let mut tx = state.db.pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO files (id, project_id, name, path, is_folder, hash, mime_type, language, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+    )
+    .bind(&file_id)
+    .bind(&project_id)
+    .bind(&name)
+    .bind(&upload.path)
+    .bind(false)
+    .bind(&hash)
+    .bind(&file_type.mime_type)
+    .bind(&file_type.language)
+    .bind(&now)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM uploads WHERE id = $1")
+        .bind(&upload_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    let _ = tokio::fs::remove_file(&part).await;
+
+    state.events.publish(
+        &project_id,
+        ProjectEvent::FileCreated {
+            file_id: file_id.clone(),
+            path: upload.path,
+            is_folder: false,
+        },
+    );
+
+    Ok(Json(fetch_file(&state.db.pool, &file_id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/files/project/{project_id}/uploads/{upload_id}",
+    tag = "uploads",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        ("upload_id" = String, Path, description = "Upload id"),
+    ),
+    responses((status = 200, description = "Done"))
+)]
+async fn cancel_upload(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((project_id, upload_id)): Path<(String, String)>,
+) -> Result<Json<()>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    fetch_upload(&state.db.pool, &project_id, &upload_id, &user.id).await?;
+
+    sqlx::query("DELETE FROM uploads WHERE id = $1")
+        .bind(&upload_id)
+        .execute(&state.db.pool)
+        .await?;
+
+    let part = uploads::part_path(FsPath::new(&state.config.upload_path), &upload_id);
+    let _ = tokio::fs::remove_file(&part).await;
+
+    Ok(Json(()))
+}
+
+// Enough of the file for type sniffing
+async fn read_head(path: &FsPath) -> Result<Vec<u8>> {
+    use tokio::io::AsyncReadExt;
+
+    let file = tokio::fs::File::open(path)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to read upload: {e}")))?;
+    let mut head = Vec::new();
+    file.take(8192)
+        .read_to_end(&mut head)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to read upload: {e}")))?;
+    Ok(head)
+}
@@ -0,0 +1,44 @@
+use axum::{
+    body::Body,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::Response,
+    routing::get,
+    Router,
+};
+
+use crate::{
+    error::{AppError, Result},
+    services::identicon,
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new().route("/:id/identicon.svg", get(get_identicon))
+}
+
+/// The user's generated avatar. It depends only on the id, so it can be
+/// cached for good.
+#[utoipa::path(
+    get,
+    path = "/api/users/{id}/identicon.svg",
+    tag = "users",
+    params(("id" = String, Path, description = "User id")),
+    responses((status = 200, content_type = "image/svg+xml", body = String))
+)]
+async fn get_identicon(State(state): State<AppState>, Path(id): Path<String>) -> Result<Response> {
+    let exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE id = $1")
+        .bind(&id)
+        .fetch_one(&state.db.pool)
+        .await?;
+    if exists == 0 {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "image/svg+xml")
+        .header(header::CACHE_CONTROL, "public, max-age=31536000, immutable")
+        .body(Body::from(identicon::svg(&id)))
+        .map_err(|e| AppError::Internal(format!("Failed to build response: {e}")))
+}
@@ -0,0 +1,260 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{
+        diff::DiffResult,
+        events::ProjectEvent,
+        freeze,
+        versions::{self, FileChange, ProjectVersion, RestoreReport, VersionFile},
+    },
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/:id/versions", get(list_versions).post(create_version))
+        .route("/:id/versions/compare", get(compare_versions))
+        .route(
+            "/:id/versions/:version_id",
+            get(get_version).delete(delete_version),
+        )
+        .route("/:id/versions/:version_id/restore", post(restore_version))
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateVersionRequest {
+    pub label: String,
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VersionsListResponse {
+    pub versions: Vec<ProjectVersion>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct VersionResponse {
+    #[serde(flatten)]
+    pub version: ProjectVersion,
+    pub files: Vec<VersionFile>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct CompareQuery {
+    pub from: String,
+    /// Version to compare with; the current project when omitted
+    pub to: Option<String>,
+    /// Diff this file instead of listing what changed
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(untagged)]
+pub enum CompareResponse {
+    Files { changes: Vec<FileChange> },
+    Diff(DiffResult),
+}
+
+/// Label a snapshot of every file in the project.
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/versions",
+    tag = "versions",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = CreateVersionRequest,
+    responses((status = 200, body = ProjectVersion))
+)]
+async fn create_version(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<CreateVersionRequest>,
+) -> Result<Json<ProjectVersion>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let version = versions::create(
+        &state.db,
+        &state.storage,
+        &state.collab,
+        &id,
+        &user.id,
+        &body.label,
+        body.description.as_deref(),
+    )
+    .await?;
+
+    Ok(Json(version))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/versions",
+    tag = "versions",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = VersionsListResponse))
+)]
+async fn list_versions(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<VersionsListResponse>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let versions = versions::list(&state.db.pool, &id).await?;
+    Ok(Json(VersionsListResponse { versions }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/versions/{version_id}",
+    tag = "versions",
+    params(
+        ("id" = String, Path, description = "Project id"),
+        ("version_id" = String, Path, description = "Version id"),
+    ),
+    responses((status = 200, body = VersionResponse))
+)]
+async fn get_version(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, version_id)): Path<(String, String)>,
+) -> Result<Json<VersionResponse>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let version = versions::get(&state.db.pool, &id, &version_id).await?;
+    let files = versions::files(&state.db.pool, &version_id).await?;
+    Ok(Json(VersionResponse { version, files }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/projects/{id}/versions/{version_id}",
+    tag = "versions",
+    params(
+        ("id" = String, Path, description = "Project id"),
+        ("version_id" = String, Path, description = "Version id"),
+    ),
+    responses((status = 200, description = "Done"))
+)]
+async fn delete_version(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, version_id)): Path<(String, String)>,
+) -> Result<Json<()>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    versions::delete(&state.db.pool, &state.storage, &id, &version_id).await?;
+    Ok(Json(()))
+}
+
+/// What changed between two versions, or since a version.
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/versions/compare",
+    tag = "versions",
+    params(
+        ("id" = String, Path, description = "Project id"),
+        CompareQuery,
+    ),
+    responses((status = 200, body = CompareResponse))
+)]
+async fn compare_versions(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Query(query): Query<CompareQuery>,
+) -> Result<Json<CompareResponse>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let response = match &query.path {
+        Some(path) => CompareResponse::Diff(
+            versions::diff_file(
+                &state.db,
+                &state.storage,
+                &state.collab,
+                &id,
+                &query.from,
+                query.to.as_deref(),
+                path,
+            )
+            .await?,
+        ),
+        None => CompareResponse::Files {
+            changes: versions::compare(
+                &state.db,
+                &state.storage,
+                &state.collab,
+                &id,
+                &query.from,
+                query.to.as_deref(),
+            )
+            .await?,
+        },
+    };
+    Ok(Json(response))
+}
+
+/// Roll every file back to a version.
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/versions/{version_id}/restore",
+    tag = "versions",
+    params(
+        ("id" = String, Path, description = "Project id"),
+        ("version_id" = String, Path, description = "Version id"),
+    ),
+    responses((status = 200, body = VersionRestoreReport))
+)]
+async fn restore_version(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path((id, version_id)): Path<(String, String)>,
+) -> Result<Json<RestoreReport>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &id).await?;
+
+    let report = versions::restore(
+        &state.db,
+        &state.storage,
+        &state.collab,
+        &id,
+        &version_id,
+        &user.id,
+    )
+    .await?;
+
+    state.events.publish(&id, ProjectEvent::FilesChanged);
+    Ok(Json(report))
+}
@@ -0,0 +1,185 @@
+use axum::{
+    extract::{Path, State},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{
+        events::ProjectEvent,
+        freeze,
+        webdav::{SyncLink, SyncReport},
+    },
+    AppState,
+};
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/:id/webdav",
+            get(get_link).put(link_folder).delete(unlink_folder),
+        )
+        .route("/:id/webdav/sync", post(sync))
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+async fn check_project_owner(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let owner_id = sqlx::query_scalar::<_, String>("SELECT owner_id FROM projects WHERE id = $1")
+        .bind(project_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Project not found".to_string()))?;
+
+    if owner_id != user_id {
+        return Err(AppError::Forbidden(
+            "Only the owner can change WebDAV sync".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct LinkRequest {
+    /// https URL of the folder, such as a Nextcloud WebDAV URL
+    pub url: String,
+    pub username: String,
+    /// Password, or an app password where the server offers them
+    pub password: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = WebDavLinkResponse)]
+pub struct LinkResponse {
+    /// None when the project is not linked
+    pub link: Option<SyncLink>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/projects/{id}/webdav",
+    operation_id = "get_webdav_link",
+    tag = "webdav",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = WebDavLinkResponse))
+)]
+async fn get_link(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<LinkResponse>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+
+    let link = state.webdav.status(&state.db, &id).await?;
+    Ok(Json(LinkResponse { link }))
+}
+
+/// Link the project to a WebDAV folder, replacing any earlier link.
+#[utoipa::path(
+    put,
+    path = "/api/projects/{id}/webdav",
+    tag = "webdav",
+    params(("id" = String, Path, description = "Project id")),
+    request_body = LinkRequest,
+    responses((status = 200, body = WebDavLinkResponse))
+)]
+async fn link_folder(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+    Json(body): Json<LinkRequest>,
+) -> Result<Json<LinkResponse>> {
+    check_project_owner(&state.db.pool, &id, &user.id).await?;
+
+    let link = state
+        .webdav
+        .link(
+            &state.db,
+            &id,
+            &user.id,
+            &body.url,
+            &body.username,
+            &body.password,
+        )
+        .await?;
+    Ok(Json(LinkResponse { link: Some(link) }))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/projects/{id}/webdav",
+    tag = "webdav",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, description = "Done"))
+)]
+async fn unlink_folder(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<()>> {
+    check_project_owner(&state.db.pool, &id, &user.id).await?;
+
+    if !state.webdav.unlink(&state.db, &id).await? {
+        return Err(AppError::NotFound(
+            "The project is not linked to a WebDAV folder".to_string(),
+        ));
+    }
+    Ok(Json(()))
+}
+
+/// Sync now rather than on the server's schedule.
+#[utoipa::path(
+    post,
+    path = "/api/projects/{id}/webdav/sync",
+    tag = "webdav",
+    params(("id" = String, Path, description = "Project id")),
+    responses((status = 200, body = SyncReport))
+)]
+async fn sync(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(id): Path<String>,
+) -> Result<Json<SyncReport>> {
+    check_project_access(&state.db.pool, &id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &id).await?;
+
+    let report = state
+        .webdav
+        .sync(&state.db, &state.storage, &state.collab, &id)
+        .await?;
+
+    if report.files_changed() {
+        state.events.publish(&id, ProjectEvent::FilesChanged);
+    }
+    Ok(Json(report))
+}
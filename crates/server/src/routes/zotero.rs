@@ -0,0 +1,500 @@
+use axum::{
+    extract::{Path, Query, State},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use super::files::{ensure_unlocked, fetch_file, write_content};
+use crate::{
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{
+        bibtex,
+        compiler::normalize_project_path,
+        events::ProjectEvent,
+        filetype, freeze,
+        zotero::{self, Export, Library, LibraryType},
+    },
+    AppState,
+};
+
+const DEFAULT_BIB_PATH: &str = "zotero.bib";
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/key", get(get_key).put(set_key).delete(delete_key))
+        .route(
+            "/project/:project_id",
+            get(get_link).put(set_link).delete(delete_link),
+        )
+        .route("/project/:project_id/sync", post(sync_project))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct KeyResponse {
+    pub connected: bool,
+    pub zotero_user_id: Option<String>,
+    pub username: Option<String>,
+    /// Last characters of the key, to tell keys apart
+    pub key_hint: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetKeyRequest {
+    pub api_key: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow, ToSchema)]
+#[schema(as = ZoteroLinkResponse)]
+pub struct LinkResponse {
+    pub project_id: String,
+    /// The user whose API key reads the library
+    pub user_id: String,
+    pub library_type: String,
+    pub library_id: String,
+    pub collection_key: Option<String>,
+    pub bib_path: String,
+    pub last_version: Option<i64>,
+    pub last_synced_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetLinkRequest {
+    /// "user" or "group"
+    pub library_type: String,
+    /// Group ID; defaults to your own library for "user"
+    pub library_id: Option<String>,
+    /// Export only this collection
+    pub collection_key: Option<String>,
+    /// The .bib file to write, "zotero.bib" by default
+    pub bib_path: Option<String>,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SyncQuery {
+    /// Export even if the library hasn't changed since the last sync
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SyncResponse {
+    pub file_id: Option<String>,
+    pub bib_path: String,
+    /// Whether the .bib file was rewritten
+    pub changed: bool,
+    pub entries: Option<usize>,
+    pub version: Option<i64>,
+    pub synced_at: String,
+}
+
+async fn check_project_access(
+    pool: &crate::db::DbPool,
+    project_id: &str,
+    user_id: &str,
+) -> Result<()> {
+    let exists = sqlx::query_scalar::<_, i64>(
+        r#"
+        SELECT COUNT(*) FROM projects p
+        LEFT JOIN project_collaborators pc ON p.id = pc.project_id
+        WHERE p.id = $1 AND (p.owner_id = $2 OR pc.user_id = $3)
+        "#,
+    )
+    .bind(project_id)
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    if exists == 0 {
+        return Err(AppError::NotFound("Project not found".to_string()));
+    }
+    Ok(())
+}
+
+async fn fetch_link(pool: &crate::db::DbPool, project_id: &str) -> Result<LinkResponse> {
+    sqlx::query_as::<_, LinkResponse>(
+        r#"
+        SELECT project_id, user_id, library_type, library_id, collection_key, bib_path,
+               last_version, last_synced_at
+        FROM zotero_links WHERE project_id = $1
+        "#,
+    )
+    .bind(project_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Project is not linked to Zotero".to_string()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/zotero/key",
+    tag = "zotero",
+    responses((status = 200, body = KeyResponse))
+)]
+async fn get_key(State(state): State<AppState>, user: AuthUser) -> Result<Json<KeyResponse>> {
+    let (api_key, zotero_user_id, username) =
+        sqlx::query_as::<_, (Option<String>, Option<String>, Option<String>)>(
+            "SELECT zotero_api_key, zotero_user_id, zotero_username FROM users WHERE id = $1",
+        )
+        .bind(&user.id)
+        .fetch_one(&state.db.pool)
+        .await?;
+
+    Ok(Json(KeyResponse {
+        connected: api_key.is_some(),
+        zotero_user_id,
+        username,
+        key_hint: api_key.map(|key| {
+            let tail: String = key.chars().rev().take(4).collect();
+            tail.chars().rev().collect()
+        }),
+    }))
+}
+
+/// Store a Zotero API key after checking it with Zotero.
+#[utoipa::path(
+    put,
+    path = "/api/zotero/key",
+    tag = "zotero",
+    request_body = SetKeyRequest,
+    responses((status = 200, body = KeyResponse))
+)]
+async fn set_key(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Json(body): Json<SetKeyRequest>,
+) -> Result<Json<KeyResponse>> {
+    let api_key = body.api_key.trim();
+    if api_key.is_empty() || !api_key.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(AppError::BadRequest("Invalid API key".to_string()));
+    }
+
+    let account = zotero::verify_key(&state.config, api_key).await?;
+    sqlx::query(
+        "UPDATE users SET zotero_api_key = $1, zotero_user_id = $2, zotero_username = $3 WHERE id = $4",
+    )
+    .bind(api_key)
+    .bind(account.user_id.to_string())
+    .bind(&account.username)
+    .bind(&user.id)
+    .execute(&state.db.pool)
+    .await?;
+
+    get_key(State(state), user).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/zotero/key",
+    tag = "zotero",
+    responses((status = 200, description = "Done"))
+)]
+async fn delete_key(State(state): State<AppState>, user: AuthUser) -> Result<Json<()>> {
+    sqlx::query(
+        "UPDATE users SET zotero_api_key = NULL, zotero_user_id = NULL, zotero_username = NULL WHERE id = $1",
+    )
+    .bind(&user.id)
+    .execute(&state.db.pool)
+    .await?;
+
+    Ok(Json(()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/zotero/project/{project_id}",
+    operation_id = "get_zotero_link",
+    tag = "zotero",
+    params(("project_id" = String, Path, description = "Project id")),
+    responses((status = 200, body = ZoteroLinkResponse))
+)]
+async fn get_link(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<LinkResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    Ok(Json(fetch_link(&state.db.pool, &project_id).await?))
+}
+
+/// Link the project to a library or collection, read with your API key.
+#[utoipa::path(
+    put,
+    path = "/api/zotero/project/{project_id}",
+    tag = "zotero",
+    params(("project_id" = String, Path, description = "Project id")),
+    request_body = SetLinkRequest,
+    responses((status = 200, body = ZoteroLinkResponse))
+)]
+async fn set_link(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Json(body): Json<SetLinkRequest>,
+) -> Result<Json<LinkResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    let zotero_user_id = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT zotero_user_id FROM users WHERE id = $1 AND zotero_api_key IS NOT NULL",
+    )
+    .bind(&user.id)
+    .fetch_optional(&state.db.pool)
+    .await?
+    .flatten()
+    .ok_or_else(|| AppError::BadRequest("Add a Zotero API key first".to_string()))?;
+
+    let library_type = LibraryType::parse(&body.library_type).ok_or_else(|| {
+        AppError::BadRequest(format!("Unknown library type '{}'", body.library_type))
+    })?;
+    let library_id = match (library_type, body.library_id) {
+        (_, Some(id)) => id,
+        (LibraryType::User, None) => zotero_user_id,
+        (LibraryType::Group, None) => {
+            return Err(AppError::BadRequest("A group ID is required".to_string()))
+        }
+    };
+    if !zotero::is_valid_library_id(&library_id) {
+        return Err(AppError::BadRequest(format!(
+            "Invalid library ID '{library_id}'"
+        )));
+    }
+    if let Some(collection) = &body.collection_key {
+        if !zotero::is_valid_collection_key(collection) {
+            return Err(AppError::BadRequest(format!(
+                "Invalid collection key '{collection}'"
+            )));
+        }
+    }
+    let bib_path = normalize_project_path(body.bib_path.as_deref().unwrap_or(DEFAULT_BIB_PATH))?;
+    if !bib_path.to_lowercase().ends_with(".bib") {
+        return Err(AppError::BadRequest(
+            "The Zotero export must go to a .bib file".to_string(),
+        ));
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO zotero_links (project_id, user_id, library_type, library_id, collection_key, bib_path, created_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (project_id) DO UPDATE SET
+            user_id = excluded.user_id,
+            library_type = excluded.library_type,
+            library_id = excluded.library_id,
+            collection_key = excluded.collection_key,
+            bib_path = excluded.bib_path,
+            last_version = NULL,
+            last_synced_at = NULL
+        "#,
+    )
+    .bind(&project_id)
+    .bind(&user.id)
+    .bind(library_type.as_str())
+    .bind(&library_id)
+    .bind(&body.collection_key)
+    .bind(&bib_path)
+    .bind(Utc::now().to_rfc3339())
+    .execute(&state.db.pool)
+    .await?;
+
+    Ok(Json(fetch_link(&state.db.pool, &project_id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/zotero/project/{project_id}",
+    tag = "zotero",
+    params(("project_id" = String, Path, description = "Project id")),
+    responses((status = 200, description = "Done"))
+)]
+async fn delete_link(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+) -> Result<Json<()>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+
+    sqlx::query("DELETE FROM zotero_links WHERE project_id = $1")
+        .bind(&project_id)
+        .execute(&state.db.pool)
+        .await?;
+
+    Ok(Json(()))
+}
+
+/// Regenerate the linked .bib file from the Zotero library. Anything edited
+/// in the file by hand is replaced.
+#[utoipa::path(
+    post,
+    path = "/api/zotero/project/{project_id}/sync",
+    tag = "zotero",
+    params(
+        ("project_id" = String, Path, description = "Project id"),
+        SyncQuery,
+    ),
+    responses((status = 200, body = SyncResponse))
+)]
+async fn sync_project(
+    State(state): State<AppState>,
+    user: AuthUser,
+    Path(project_id): Path<String>,
+    Query(query): Query<SyncQuery>,
+) -> Result<Json<SyncResponse>> {
+    check_project_access(&state.db.pool, &project_id, &user.id).await?;
+    freeze::ensure_writable(&state.db.pool, &project_id).await?;
+    let link = fetch_link(&state.db.pool, &project_id).await?;
+
+    let api_key =
+        sqlx::query_scalar::<_, Option<String>>("SELECT zotero_api_key FROM users WHERE id = $1")
+            .bind(&link.user_id)
+            .fetch_optional(&state.db.pool)
+            .await?
+            .flatten()
+            .ok_or_else(|| {
+                AppError::BadRequest(
+                    "The Zotero key used by this link was removed; link the project again"
+                        .to_string(),
+                )
+            })?;
+
+    let file_id = sqlx::query_scalar::<_, String>(
+        "SELECT id FROM files WHERE project_id = $1 AND path = $2 AND is_folder = FALSE",
+    )
+    .bind(&project_id)
+    .bind(&link.bib_path)
+    .fetch_optional(&state.db.pool)
+    .await?;
+    let file = match &file_id {
+        Some(id) => Some(fetch_file(&state.db.pool, id).await?),
+        None => None,
+    };
+    if let Some(file) = &file {
+        ensure_unlocked(file, &user.id)?;
+    }
+
+    let library = Library {
+        library_type: LibraryType::parse(&link.library_type).unwrap_or(LibraryType::User),
+        library_id: &link.library_id,
+        collection_key: link.collection_key.as_deref(),
+    };
+    // A missing file has to be written whatever the library version
+    let since = link.last_version.filter(|_| file.is_some() && !query.force);
+    let export = zotero::export(&state.config, &api_key, &library, since).await?;
+
+    let now = Utc::now().to_rfc3339();
+    let (bibtex, version) = match export {
+        Export::NotModified => {
+            sqlx::query("UPDATE zotero_links SET last_synced_at = $1 WHERE project_id = $2")
+                .bind(&now)
+                .bind(&project_id)
+                .execute(&state.db.pool)
+                .await?;
+            return Ok(Json(SyncResponse {
+                file_id,
+                bib_path: link.bib_path,
+                changed: false,
+                entries: None,
+                version: link.last_version,
+                synced_at: now,
+            }));
+        }
+        Export::Items { bibtex, version } => (bibtex, version),
+    };
+
+    let content = format!(
+        "% Exported from Zotero; changes made here are replaced on the next sync\n\n{}\n",
+        bibtex.trim()
+    );
+    let entries = bibtex::parse(&content).entries.len();
+
+    let (file_id, changed) = match file {
+        Some(file) => {
+            let current = state.collab.content(&project_id, &file.path).await?;
+            let changed = current != content;
+            if changed {
+                write_content(&state, &file, &user.id, &content).await?;
+            }
+            (file.id, changed)
+        }
+        None => (
+            create_bib_file(&state, &project_id, &link.bib_path, &content).await?,
+            true,
+        ),
+    };
+
+    sqlx::query(
+        "UPDATE zotero_links SET last_version = $1, last_synced_at = $2 WHERE project_id = $3",
+    )
+    .bind(version)
+    .bind(&now)
+    .bind(&project_id)
+    .execute(&state.db.pool)
+    .await?;
+
+    Ok(Json(SyncResponse {
+        file_id: Some(file_id),
+        bib_path: link.bib_path,
+        changed,
+        entries: Some(entries),
+        version,
+        synced_at: now,
+    }))
+}
+
+async fn create_bib_file(
+    state: &AppState,
+    project_id: &str,
+    path: &str,
+    content: &str,
+) -> Result<String> {
+    if let Some((parent, _)) = path.rsplit_once('/') {
+        let parent_exists = sqlx::query_scalar::<_, i64>(
+            "SELECT COUNT(*) FROM files WHERE project_id = $1 AND path = $2 AND is_folder = TRUE",
+        )
+        .bind(project_id)
+        .bind(parent)
+        .fetch_one(&state.db.pool)
+        .await?;
+        if parent_exists == 0 {
+            return Err(AppError::BadRequest(format!(
+                "Folder '{parent}' does not exist"
+            )));
+        }
+    }
+
+    let hash = state.storage.write_file(project_id, path, content).await?;
+    let file_type = filetype::detect(path, content.as_bytes());
+    let file_id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    sqlx::query(
+        "INSERT INTO files (id, project_id, name, path, is_folder, hash, mime_type, language, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+    )
+    .bind(&file_id)
+    .bind(project_id)
+    .bind(path.rsplit('/').next().unwrap_or(path))
+    .bind(path)
+    .bind(false)
+    .bind(&hash)
+    .bind(file_type.mime_type)
+    .bind(file_type.language)
+    .bind(&now)
+    .bind(&now)
+    .execute(&state.db.pool)
+    .await?;
+
+    state.events.publish(
+        project_id,
+        ProjectEvent::FileCreated {
+            file_id: file_id.clone(),
+            path: path.to_string(),
+            is_folder: false,
+        },
+    );
+
+    Ok(file_id)
+}
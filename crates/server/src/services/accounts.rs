@@ -0,0 +1,211 @@
+// Disabling and purging accounts
+// Accounts are never deleted outright, since comments, history, compiles and
+// tracked changes would go with them. A disabled account cannot sign in or be
+// found as a collaborator, but its name stays on what it wrote, and an admin
+// can enable it again. Once disabled for the retention window, a purge erases
+// its personal data and leaves a "Deleted user" in its place.
+
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use crate::{
+    db::DbPool,
+    error::{AppError, Result},
+    services::{git::GitService, storage::StorageService},
+};
+
+// Name left on the work of purged accounts
+const PURGED_NAME: &str = "Deleted user";
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct Account {
+    pub id: String,
+    pub email: String,
+    pub name: String,
+    pub is_admin: bool,
+    pub created_at: Option<String>,
+    pub disabled_at: Option<String>,
+    pub purged_at: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct PurgeReport {
+    pub users: usize,
+    /// Projects handed to one of their editors
+    pub projects_transferred: usize,
+    /// Projects nobody else could edit, deleted with their files
+    pub projects_deleted: usize,
+}
+
+const ACCOUNT_COLUMNS: &str = "id, email, name, is_admin, created_at, disabled_at, purged_at";
+
+/// Whether the user exists and may use the server.
+pub async fn is_active(pool: &DbPool, user_id: &str) -> Result<bool> {
+    let active = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM users WHERE id = $1 AND disabled_at IS NULL",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(active > 0)
+}
+
+/// Every account, disabled and purged ones included, oldest first.
+pub async fn list(pool: &DbPool) -> Result<Vec<Account>> {
+    let accounts = sqlx::query_as::<_, Account>(&format!(
+        "SELECT {ACCOUNT_COLUMNS} FROM users ORDER BY created_at"
+    ))
+    .fetch_all(pool)
+    .await?;
+    Ok(accounts)
+}
+
+pub async fn get(pool: &DbPool, user_id: &str) -> Result<Account> {
+    sqlx::query_as::<_, Account>(&format!(
+        "SELECT {ACCOUNT_COLUMNS} FROM users WHERE id = $1"
+    ))
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("User not found".to_string()))
+}
+
+/// Stop the user signing in or using their tokens. Disabling an account
+/// that already is keeps its original time, so the retention window does
+/// not restart.
+pub async fn disable(pool: &DbPool, user_id: &str) -> Result<Account> {
+    sqlx::query("UPDATE users SET disabled_at = $1 WHERE id = $2 AND disabled_at IS NULL")
+        .bind(Utc::now().to_rfc3339())
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    // Enabling the account again does not sign its devices back in
+    sqlx::query("DELETE FROM sessions WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    get(pool, user_id).await
+}
+
+pub async fn enable(pool: &DbPool, user_id: &str) -> Result<Account> {
+    let account = get(pool, user_id).await?;
+    if account.purged_at.is_some() {
+        return Err(AppError::Conflict(
+            "The account was purged and cannot be enabled".to_string(),
+        ));
+    }
+    sqlx::query("UPDATE users SET disabled_at = NULL WHERE id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    get(pool, user_id).await
+}
+
+/// Erase accounts disabled more than `retention_days` ago. Their projects go
+/// to an editor if they have one and are deleted otherwise; what they wrote
+/// elsewhere stays, credited to "Deleted user".
+pub async fn purge(
+    pool: &DbPool,
+    storage: &StorageService,
+    git: &GitService,
+    retention_days: u64,
+) -> Result<PurgeReport> {
+    let cutoff = (Utc::now() - Duration::days(retention_days as i64)).to_rfc3339();
+    let users = sqlx::query_scalar::<_, String>(
+        "SELECT id FROM users WHERE disabled_at IS NOT NULL AND disabled_at <= $1 \
+         AND purged_at IS NULL",
+    )
+    .bind(&cutoff)
+    .fetch_all(pool)
+    .await?;
+
+    let mut report = PurgeReport::default();
+    for user_id in users {
+        let projects =
+            sqlx::query_scalar::<_, String>("SELECT id FROM projects WHERE owner_id = $1")
+                .bind(&user_id)
+                .fetch_all(pool)
+                .await?;
+        for project_id in projects {
+            if transfer(pool, &project_id).await? {
+                report.projects_transferred += 1;
+            } else {
+                storage.delete_project_dir(&project_id).await?;
+                git.delete_repo(&project_id).await?;
+                sqlx::query("DELETE FROM projects WHERE id = $1")
+                    .bind(&project_id)
+                    .execute(pool)
+                    .await?;
+                report.projects_deleted += 1;
+            }
+        }
+
+        erase(pool, &user_id).await?;
+        report.users += 1;
+    }
+    Ok(report)
+}
+
+/// Make an editor of the project its owner. Returns false if it has none.
+async fn transfer(pool: &DbPool, project_id: &str) -> Result<bool> {
+    let editor = sqlx::query_scalar::<_, String>(
+        "SELECT pc.user_id FROM project_collaborators pc JOIN users u ON pc.user_id = u.id \
+         WHERE pc.project_id = $1 AND pc.role = 'editor' AND u.disabled_at IS NULL \
+         ORDER BY u.created_at LIMIT 1",
+    )
+    .bind(project_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(editor) = editor else {
+        return Ok(false);
+    };
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM project_collaborators WHERE project_id = $1 AND user_id = $2")
+        .bind(project_id)
+        .bind(&editor)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE projects SET owner_id = $1 WHERE id = $2")
+        .bind(&editor)
+        .bind(project_id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Replace the account's personal data and drop what only it used.
+async fn erase(pool: &DbPool, user_id: &str) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for table in [
+        "api_tokens",
+        "git_credentials",
+        "notifications",
+        "project_collaborators",
+        "sessions",
+        "uploads",
+        "zotero_links",
+    ] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE user_id = $1"))
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    // The address is freed for a new account; the password can never match
+    sqlx::query(
+        "UPDATE users SET email = $1, name = $2, password_hash = '', is_admin = FALSE, \
+         zotero_api_key = NULL, zotero_user_id = NULL, zotero_username = NULL, \
+         email_notifications = 'off', purged_at = $3 WHERE id = $4",
+    )
+    .bind(format!("deleted-{user_id}@invalid"))
+    .bind(PURGED_NAME)
+    .bind(Utc::now().to_rfc3339())
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,134 @@
+// API tokens
+// Long-lived secrets a user creates to sign in where a session is awkward:
+// git over HTTP, which sends one as the password, and scripts, which send
+// one as a bearer token. Only a hash of each token is stored, so a token is
+// shown once, when it is created.
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    db::DbPool,
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::storage::content_hash,
+};
+
+// Marks API tokens apart from session tokens
+pub const PREFIX: &str = "olt_";
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+    pub expires_at: Option<String>,
+}
+
+/// Create a token for the user, valid for `expires_in_days` or until
+/// revoked. Returns it along with the secret itself.
+pub async fn create(
+    pool: &DbPool,
+    user_id: &str,
+    name: &str,
+    expires_in_days: Option<i64>,
+) -> Result<(ApiToken, String)> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::Validation("Name is required".to_string()));
+    }
+    if expires_in_days.is_some_and(|days| days <= 0) {
+        return Err(AppError::Validation(
+            "Tokens must last at least a day".to_string(),
+        ));
+    }
+
+    let mut secret = [0u8; 32];
+    OsRng.fill_bytes(&mut secret);
+    let token = format!("{PREFIX}{}", URL_SAFE_NO_PAD.encode(secret));
+
+    let now = Utc::now();
+    let api_token = ApiToken {
+        id: Uuid::new_v4().to_string(),
+        name: name.to_string(),
+        created_at: now.to_rfc3339(),
+        last_used_at: None,
+        expires_at: expires_in_days.map(|days| (now + Duration::days(days)).to_rfc3339()),
+    };
+    sqlx::query(
+        "INSERT INTO api_tokens (id, user_id, name, token_hash, created_at, expires_at) VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(&api_token.id)
+    .bind(user_id)
+    .bind(&api_token.name)
+    .bind(content_hash(token.as_bytes()))
+    .bind(&api_token.created_at)
+    .bind(&api_token.expires_at)
+    .execute(pool)
+    .await?;
+
+    Ok((api_token, token))
+}
+
+pub async fn list(pool: &DbPool, user_id: &str) -> Result<Vec<ApiToken>> {
+    let tokens = sqlx::query_as::<_, ApiToken>(
+        "SELECT id, name, created_at, last_used_at, expires_at FROM api_tokens \
+         WHERE user_id = $1 ORDER BY created_at DESC",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(tokens)
+}
+
+/// Revoke one of the user's tokens; false if they have no such token.
+pub async fn revoke(pool: &DbPool, user_id: &str, id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM api_tokens WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// The user a token belongs to, if it is a valid, unexpired API token.
+pub async fn authenticate(pool: &DbPool, token: &str) -> Result<Option<AuthUser>> {
+    if !token.starts_with(PREFIX) {
+        return Ok(None);
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let user = sqlx::query_as::<_, (String, String, String, String)>(
+        r#"
+        SELECT t.id, u.id, u.email, u.name FROM api_tokens t
+        JOIN users u ON t.user_id = u.id
+        WHERE t.token_hash = $1 AND (t.expires_at IS NULL OR t.expires_at > $2)
+          AND u.disabled_at IS NULL
+        "#,
+    )
+    .bind(content_hash(token.as_bytes()))
+    .bind(&now)
+    .fetch_optional(pool)
+    .await?;
+    let Some((token_id, id, email, name)) = user else {
+        return Ok(None);
+    };
+
+    sqlx::query("UPDATE api_tokens SET last_used_at = $1 WHERE id = $2")
+        .bind(&now)
+        .bind(&token_id)
+        .execute(pool)
+        .await?;
+    Ok(Some(AuthUser {
+        id,
+        email,
+        name,
+        session_id: None,
+    }))
+}
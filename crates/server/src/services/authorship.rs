@@ -0,0 +1,182 @@
+// Authorship
+// Credits every character of a file to the update that wrote it, by replaying
+// the file's history and diffing the text before and after each record.
+// Characters that predate the history, or that changed outside the
+// collaborative editor, have no author.
+
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+use similar::{Algorithm, DiffTag};
+use utoipa::ToSchema;
+
+use crate::{
+    db::DbPool,
+    error::{AppError, Result},
+    services::{
+        history::{self, StoredRecord, UPDATE},
+        identicon,
+    },
+};
+
+// Time one diff may take before it settles for a coarser result
+const DIFF_DEADLINE: Duration = Duration::from_millis(50);
+
+/// Consecutive characters written by the same user. Offsets are in
+/// characters, end exclusive.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuthorshipRange {
+    pub start: usize,
+    pub end: usize,
+    pub user_id: Option<String>,
+    pub user_name: Option<String>,
+    /// The latest edit among the range's characters
+    pub edited_at: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuthorSummary {
+    pub user_id: String,
+    pub user_name: Option<String>,
+    /// The user's display color, for highlighting their ranges
+    pub color: String,
+    pub characters: usize,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Authorship {
+    pub ranges: Vec<AuthorshipRange>,
+    /// Everyone with text in the file, most characters first
+    pub authors: Vec<AuthorSummary>,
+}
+
+/// Who wrote each part of `content`, the file's current text.
+pub async fn authorship(pool: &DbPool, file_id: &str, content: String) -> Result<Authorship> {
+    let records = history::records(pool, file_id).await?;
+
+    let mut ranges = tokio::task::spawn_blocking(move || attribute(&records, &content))
+        .await
+        .map_err(|e| AppError::Internal(format!("Authorship task failed: {e}")))??;
+
+    let user_ids: HashSet<String> = ranges.iter().filter_map(|r| r.user_id.clone()).collect();
+    let mut names = HashMap::new();
+    for user_id in user_ids {
+        let name = sqlx::query_scalar::<_, String>("SELECT name FROM users WHERE id = $1")
+            .bind(&user_id)
+            .fetch_optional(pool)
+            .await?;
+        names.insert(user_id, name);
+    }
+
+    let mut characters: HashMap<String, usize> = HashMap::new();
+    for range in &mut ranges {
+        if let Some(user_id) = &range.user_id {
+            range.user_name = names.get(user_id).cloned().flatten();
+            *characters.entry(user_id.clone()).or_default() += range.end - range.start;
+        }
+    }
+    let mut authors: Vec<AuthorSummary> = characters
+        .into_iter()
+        .map(|(user_id, characters)| AuthorSummary {
+            user_name: names.get(&user_id).cloned().flatten(),
+            color: identicon::color(&user_id).to_string(),
+            user_id,
+            characters,
+        })
+        .collect();
+    authors.sort_by(|a, b| {
+        b.characters
+            .cmp(&a.characters)
+            .then(a.user_id.cmp(&b.user_id))
+    });
+
+    Ok(Authorship { ranges, authors })
+}
+
+/// Replay the history keeping, for every character, the index of the record
+/// that wrote it, then fold the result into ranges of the current text.
+fn attribute(records: &[StoredRecord], content: &str) -> Result<Vec<AuthorshipRange>> {
+    let mut text: Vec<char> = Vec::new();
+    let mut written_by: Vec<Option<usize>> = Vec::new();
+    let mut index = 0;
+
+    history::replay(records, |record, after| {
+        let after: Vec<char> = after.chars().collect();
+        // A snapshot only restates the document; whatever it changes came
+        // from outside the editor
+        let writer = (record.kind == UPDATE).then_some(index);
+        written_by = carry(&written_by, &text, &after, writer);
+        text = after;
+        index += 1;
+    })?;
+
+    let content: Vec<char> = content.chars().collect();
+    written_by = carry(&written_by, &text, &content, None);
+
+    let mut ranges: Vec<AuthorshipRange> = Vec::new();
+    for (offset, writer) in written_by.into_iter().enumerate() {
+        let record = writer.map(|index| &records[index]);
+        let user_id = record.and_then(|r| r.user_id.as_deref());
+        let edited_at = record.map(|r| r.created_at.as_str());
+
+        match ranges.last_mut() {
+            Some(range) if range.end == offset && range.user_id.as_deref() == user_id => {
+                range.end += 1;
+                if edited_at > range.edited_at.as_deref() {
+                    range.edited_at = edited_at.map(str::to_string);
+                }
+            }
+            _ => ranges.push(AuthorshipRange {
+                start: offset,
+                end: offset + 1,
+                user_id: user_id.map(str::to_string),
+                user_name: None,
+                edited_at: edited_at.map(str::to_string),
+            }),
+        }
+    }
+    Ok(ranges)
+}
+
+/// Attribution of `after`: characters kept from `before` keep theirs, the
+/// rest go to `writer`.
+fn carry(
+    written_by: &[Option<usize>],
+    before: &[char],
+    after: &[char],
+    writer: Option<usize>,
+) -> Vec<Option<usize>> {
+    // Most updates touch one spot; only diff what lies between the common
+    // prefix and suffix
+    let prefix = before.iter().zip(after).take_while(|(a, b)| a == b).count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &before[prefix..before.len() - suffix];
+    let new = &after[prefix..after.len() - suffix];
+
+    let mut result = Vec::with_capacity(after.len());
+    result.extend_from_slice(&written_by[..prefix]);
+    if old.is_empty() {
+        result.extend(std::iter::repeat_n(writer, new.len()));
+    } else if !new.is_empty() {
+        let deadline = Some(Instant::now() + DIFF_DEADLINE);
+        for op in similar::capture_diff_slices_deadline(Algorithm::Myers, old, new, deadline) {
+            match op.as_tag_tuple() {
+                (DiffTag::Equal, old_range, _) => result.extend_from_slice(
+                    &written_by[prefix + old_range.start..prefix + old_range.end],
+                ),
+                (DiffTag::Delete, ..) => {}
+                (_, _, new_range) => result.extend(std::iter::repeat_n(writer, new_range.len())),
+            }
+        }
+    }
+    result.extend_from_slice(&written_by[before.len() - suffix..]);
+    result
+}
@@ -0,0 +1,677 @@
+// Project backups
+// Each backup is a gzipped tarball of the project's files plus its database rows
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::{Component, Path};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use futures::TryStreamExt;
+use object_store::{local::LocalFileSystem, path::Path as ObjectPath, ObjectStore, PutPayload};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    config::Config,
+    db::Database,
+    error::{AppError, Result},
+    services::{
+        collab::CollabService,
+        exclude::ExcludeRules,
+        filetype, reconcile, spellcheck,
+        storage::{build_s3_store, content_hash, StorageService},
+    },
+};
+
+const MANIFEST_NAME: &str = "manifest.json";
+const FILES_DIR: &str = "files";
+const ARCHIVE_EXT: &str = ".tar.gz";
+const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BackupInfo {
+    pub id: String,
+    pub project_id: String,
+    pub size: u64,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[schema(as = BackupRestoreReport)]
+pub struct RestoreReport {
+    pub project_id: String,
+    pub backup_id: String,
+    pub files: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+struct ProjectRecord {
+    id: String,
+    name: String,
+    owner_id: String,
+    created_at: String,
+    updated_at: String,
+    // Absent from manifests written before project settings existed
+    #[serde(default)]
+    engine: Option<String>,
+    #[serde(default)]
+    bib_tool: Option<String>,
+    #[serde(default)]
+    shell_escape: bool,
+    #[serde(default)]
+    build_targets: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+struct FileRecord {
+    id: String,
+    name: String,
+    path: String,
+    is_folder: bool,
+    hash: Option<String>,
+    #[serde(default)]
+    sort_order: Option<i64>,
+    created_at: String,
+    updated_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+struct CollaboratorRecord {
+    user_id: String,
+    role: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow)]
+struct CommentRecord {
+    id: String,
+    file_path: String,
+    author_id: String,
+    content: String,
+    line_start: i32,
+    line_end: i32,
+    resolved: bool,
+    created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    created_at: String,
+    project: ProjectRecord,
+    files: Vec<FileRecord>,
+    collaborators: Vec<CollaboratorRecord>,
+    comments: Vec<CommentRecord>,
+    /// Spell check words the project accepts
+    #[serde(default)]
+    dictionary: Vec<String>,
+}
+
+// Project contents as stored in an archive
+struct Snapshot {
+    folders: Vec<String>,
+    files: Vec<(String, Vec<u8>)>,
+}
+
+#[derive(Clone)]
+pub struct BackupService {
+    store: Arc<dyn ObjectStore>,
+    prefix: String,
+    retention: usize,
+}
+
+impl BackupService {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let backup = &config.backup;
+        let (store, prefix): (Arc<dyn ObjectStore>, String) = match backup.target.as_str() {
+            "local" => {
+                std::fs::create_dir_all(&backup.path)?;
+                (
+                    Arc::new(LocalFileSystem::new_with_prefix(&backup.path)?),
+                    String::new(),
+                )
+            }
+            "s3" => {
+                let s3 = config
+                    .s3
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("BACKUP_TARGET=s3 requires S3_BUCKET"))?;
+                (
+                    Arc::new(build_s3_store(s3)?),
+                    backup.s3_prefix.trim_matches('/').to_string(),
+                )
+            }
+            other => return Err(anyhow::anyhow!("Unknown backup target: {other}")),
+        };
+
+        Ok(Self {
+            store,
+            prefix,
+            retention: backup.retention,
+        })
+    }
+
+    fn path(&self, key: &str) -> ObjectPath {
+        if self.prefix.is_empty() {
+            ObjectPath::from(key)
+        } else {
+            ObjectPath::from(format!("{}/{key}", self.prefix))
+        }
+    }
+
+    fn archive_path(&self, project_id: &str, backup_id: &str) -> ObjectPath {
+        self.path(&format!("{project_id}/{backup_id}{ARCHIVE_EXT}"))
+    }
+
+    /// Archive the project's files and database rows and store the result.
+    pub async fn backup_project(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        project_id: &str,
+    ) -> Result<BackupInfo> {
+        let manifest = load_manifest(db, project_id).await?;
+
+        // Build artifacts can be regenerated, so they are left out
+        let rules = ExcludeRules::load(storage, project_id).await?;
+        let mut snapshot = Snapshot {
+            folders: Vec::new(),
+            files: Vec::new(),
+        };
+        for entry in storage.list_tree(project_id).await? {
+            if rules.is_excluded(&entry.path, entry.is_folder) {
+                continue;
+            }
+            if entry.is_folder {
+                snapshot.folders.push(entry.path);
+            } else {
+                let content = storage.read_bytes(project_id, &entry.path).await?;
+                snapshot.files.push((entry.path, content));
+            }
+        }
+
+        let archive = tokio::task::spawn_blocking(move || build_archive(&manifest, &snapshot))
+            .await
+            .map_err(|e| AppError::Internal(format!("Backup task failed: {e}")))??;
+
+        let now = Utc::now();
+        let backup_id = now.format("%Y%m%dT%H%M%S%3fZ").to_string();
+        let size = archive.len() as u64;
+
+        self.store
+            .put(
+                &self.archive_path(project_id, &backup_id),
+                PutPayload::from(archive),
+            )
+            .await
+            .map_err(backup_error)?;
+
+        self.prune(project_id).await?;
+
+        Ok(BackupInfo {
+            id: backup_id,
+            project_id: project_id.to_string(),
+            size,
+            created_at: now.to_rfc3339(),
+        })
+    }
+
+    /// Back up every project, logging failures rather than stopping at the first one.
+    pub async fn backup_all(&self, db: &Database, storage: &StorageService) -> Result<usize> {
+        let project_ids = sqlx::query_scalar::<_, String>("SELECT id FROM projects")
+            .fetch_all(&db.pool)
+            .await?;
+
+        let mut completed = 0;
+        for project_id in project_ids {
+            match self.backup_project(db, storage, &project_id).await {
+                Ok(_) => completed += 1,
+                Err(e) => tracing::warn!("Failed to back up project {}: {}", project_id, e),
+            }
+        }
+        Ok(completed)
+    }
+
+    /// Backups newest first, for one project or for every project when `project_id` is None.
+    pub async fn list(&self, project_id: Option<&str>) -> Result<Vec<BackupInfo>> {
+        let root = match project_id {
+            Some(project_id) => Some(self.path(project_id)),
+            None if self.prefix.is_empty() => None,
+            None => Some(ObjectPath::from(self.prefix.as_str())),
+        };
+
+        let objects: Vec<_> = self
+            .store
+            .list(root.as_ref())
+            .try_collect()
+            .await
+            .map_err(backup_error)?;
+
+        let mut backups: Vec<BackupInfo> = objects
+            .into_iter()
+            .filter_map(|meta| {
+                let mut parts = meta.location.parts().collect::<Vec<_>>().into_iter().rev();
+                let id = parts
+                    .next()?
+                    .as_ref()
+                    .strip_suffix(ARCHIVE_EXT)?
+                    .to_string();
+                let project_id = parts.next()?.as_ref().to_string();
+                Some(BackupInfo {
+                    id,
+                    project_id,
+                    size: meta.size as u64,
+                    created_at: meta.last_modified.to_rfc3339(),
+                })
+            })
+            .collect();
+
+        backups.sort_by(|a, b| b.id.cmp(&a.id));
+        Ok(backups)
+    }
+
+    /// Drop the oldest backups beyond the retention limit.
+    async fn prune(&self, project_id: &str) -> Result<()> {
+        if self.retention == 0 {
+            return Ok(());
+        }
+
+        for stale in self
+            .list(Some(project_id))
+            .await?
+            .iter()
+            .skip(self.retention)
+        {
+            self.store
+                .delete(&self.archive_path(project_id, &stale.id))
+                .await
+                .map_err(backup_error)?;
+        }
+        Ok(())
+    }
+
+    /// Replace the project's files, file rows, collaborators and comments with
+    /// the contents of a backup. Recreates the project if it was deleted.
+    pub async fn restore(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+        backup_id: &str,
+    ) -> Result<RestoreReport> {
+        if backup_id.is_empty() || !backup_id.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(AppError::BadRequest("Invalid backup id".to_string()));
+        }
+
+        let archive = match self
+            .store
+            .get(&self.archive_path(project_id, backup_id))
+            .await
+        {
+            Ok(result) => result.bytes().await.map_err(backup_error)?.to_vec(),
+            Err(object_store::Error::NotFound { .. }) => {
+                return Err(AppError::NotFound("Backup not found".to_string()))
+            }
+            Err(e) => return Err(backup_error(e)),
+        };
+
+        let (manifest, snapshot) = tokio::task::spawn_blocking(move || read_archive(&archive))
+            .await
+            .map_err(|e| AppError::Internal(format!("Restore task failed: {e}")))??;
+
+        if manifest.project.id != project_id {
+            return Err(AppError::BadRequest(
+                "Backup belongs to a different project".to_string(),
+            ));
+        }
+
+        let owner_exists = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM users WHERE id = $1")
+            .bind(&manifest.project.owner_id)
+            .fetch_one(&db.pool)
+            .await?;
+        if owner_exists == 0 {
+            return Err(AppError::BadRequest(
+                "Project owner no longer exists".to_string(),
+            ));
+        }
+
+        let written: HashMap<&str, _> = snapshot
+            .files
+            .iter()
+            .map(|(path, content)| {
+                (
+                    path.as_str(),
+                    (content_hash(content), filetype::detect(path, content)),
+                )
+            })
+            .collect();
+
+        // Open documents still hold the files being replaced and would save
+        // them back over the restored ones
+        collab.close_project(project_id).await;
+
+        let now = Utc::now().to_rfc3339();
+        let mut tx = db.pool.begin().await?;
+
+        let updated = sqlx::query(
+            "UPDATE projects SET name = $1, engine = $2, bib_tool = $3, shell_escape = $4, build_targets = $5, updated_at = $6 WHERE id = $7",
+        )
+        .bind(&manifest.project.name)
+        .bind(&manifest.project.engine)
+        .bind(&manifest.project.bib_tool)
+        .bind(manifest.project.shell_escape)
+        .bind(&manifest.project.build_targets)
+        .bind(&now)
+        .bind(project_id)
+        .execute(&mut *tx)
+        .await?;
+        if updated.rows_affected() == 0 {
+            sqlx::query(
+                "INSERT INTO projects (id, name, owner_id, engine, bib_tool, shell_escape, build_targets, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+            )
+            .bind(project_id)
+            .bind(&manifest.project.name)
+            .bind(&manifest.project.owner_id)
+            .bind(&manifest.project.engine)
+            .bind(&manifest.project.bib_tool)
+            .bind(manifest.project.shell_escape)
+            .bind(&manifest.project.build_targets)
+            .bind(&manifest.project.created_at)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for table in [
+            "files",
+            "comments",
+            "project_collaborators",
+            "project_dictionary",
+        ] {
+            sqlx::query(&format!("DELETE FROM {table} WHERE project_id = $1"))
+                .bind(project_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        for file in &manifest.files {
+            let (hash, mime_type, language) = match written.get(file.path.as_str()) {
+                Some((hash, file_type)) => (
+                    Some(hash.clone()),
+                    Some(file_type.mime_type.clone()),
+                    file_type.language.clone(),
+                ),
+                None => (file.hash.clone(), None, None),
+            };
+            sqlx::query(
+                "INSERT INTO files (id, project_id, name, path, is_folder, hash, mime_type, language, sort_order, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            )
+            .bind(&file.id)
+            .bind(project_id)
+            .bind(&file.name)
+            .bind(&file.path)
+            .bind(file.is_folder)
+            .bind(hash)
+            .bind(mime_type)
+            .bind(language)
+            .bind(file.sort_order)
+            .bind(&file.created_at)
+            .bind(&file.updated_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        // Users removed since the backup was taken are skipped
+        for collaborator in &manifest.collaborators {
+            sqlx::query(
+                "INSERT INTO project_collaborators (project_id, user_id, role) SELECT $1, id, $2 FROM users WHERE id = $3",
+            )
+            .bind(project_id)
+            .bind(&collaborator.role)
+            .bind(&collaborator.user_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for comment in &manifest.comments {
+            sqlx::query(
+                r#"
+                INSERT INTO comments (id, project_id, file_path, author_id, content, line_start, line_end, resolved, created_at)
+                SELECT $1, $2, $3, id, $4, $5, $6, $7, $8 FROM users WHERE id = $9
+                "#,
+            )
+            .bind(&comment.id)
+            .bind(project_id)
+            .bind(&comment.file_path)
+            .bind(&comment.content)
+            .bind(comment.line_start)
+            .bind(comment.line_end)
+            .bind(comment.resolved)
+            .bind(&comment.created_at)
+            .bind(&comment.author_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        for word in &manifest.dictionary {
+            sqlx::query(
+                "INSERT INTO project_dictionary (project_id, word, created_at) VALUES ($1, $2, $3) \
+                 ON CONFLICT DO NOTHING",
+            )
+            .bind(project_id)
+            .bind(word)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+
+        // Stored files are only replaced once the rows are in, so a restore
+        // the database refuses leaves the project as it was
+        storage.delete_project_dir(project_id).await?;
+        storage.create_project_dir(project_id).await?;
+        for folder in &snapshot.folders {
+            storage.create_folder(project_id, folder).await?;
+        }
+        for (path, content) in &snapshot.files {
+            storage.write_file(project_id, path, content).await?;
+        }
+
+        // Register anything archived on disk that had no row at backup time
+        reconcile::rescan_project(db, storage, project_id).await?;
+
+        Ok(RestoreReport {
+            project_id: project_id.to_string(),
+            backup_id: backup_id.to_string(),
+            files: snapshot.files.len(),
+        })
+    }
+}
+
+fn backup_error(e: object_store::Error) -> AppError {
+    AppError::Internal(format!("Backup storage error: {e}"))
+}
+
+async fn load_manifest(db: &Database, project_id: &str) -> Result<Manifest> {
+    let project = sqlx::query_as::<_, ProjectRecord>(
+        "SELECT id, name, owner_id, created_at, updated_at, engine, bib_tool, shell_escape, build_targets FROM projects WHERE id = $1",
+    )
+    .bind(project_id)
+    .fetch_optional(&db.pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Project not found".to_string()))?;
+
+    let files = sqlx::query_as::<_, FileRecord>(
+        "SELECT id, name, path, is_folder, hash, sort_order, created_at, updated_at FROM files WHERE project_id = $1 ORDER BY path",
+    )
+    .bind(project_id)
+    .fetch_all(&db.pool)
+    .await?;
+
+    let collaborators = sqlx::query_as::<_, CollaboratorRecord>(
+        "SELECT user_id, role FROM project_collaborators WHERE project_id = $1",
+    )
+    .bind(project_id)
+    .fetch_all(&db.pool)
+    .await?;
+
+    let comments = sqlx::query_as::<_, CommentRecord>(
+        "SELECT id, file_path, author_id, content, line_start, line_end, resolved, created_at FROM comments WHERE project_id = $1",
+    )
+    .bind(project_id)
+    .fetch_all(&db.pool)
+    .await?;
+
+    let dictionary = spellcheck::dictionary(&db.pool, project_id).await?;
+
+    Ok(Manifest {
+        version: MANIFEST_VERSION,
+        created_at: Utc::now().to_rfc3339(),
+        project,
+        files,
+        collaborators,
+        comments,
+        dictionary,
+    })
+}
+
+fn build_archive(manifest: &Manifest, snapshot: &Snapshot) -> Result<Vec<u8>> {
+    let archive_error =
+        |e: std::io::Error| AppError::Internal(format!("Failed to build backup: {e}"));
+    let mtime = Utc::now().timestamp().max(0) as u64;
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+
+    let manifest_json = serde_json::to_vec_pretty(manifest)
+        .map_err(|e| AppError::Internal(format!("Failed to encode backup manifest: {e}")))?;
+    let mut header = file_header(manifest_json.len() as u64, mtime);
+    builder
+        .append_data(&mut header, MANIFEST_NAME, manifest_json.as_slice())
+        .map_err(archive_error)?;
+
+    for folder in &snapshot.folders {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o755);
+        header.set_mtime(mtime);
+        builder
+            .append_data(
+                &mut header,
+                format!("{FILES_DIR}/{folder}/"),
+                std::io::empty(),
+            )
+            .map_err(archive_error)?;
+    }
+
+    for (path, content) in &snapshot.files {
+        let mut header = file_header(content.len() as u64, mtime);
+        builder
+            .append_data(
+                &mut header,
+                format!("{FILES_DIR}/{path}"),
+                content.as_slice(),
+            )
+            .map_err(archive_error)?;
+    }
+
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(archive_error)
+}
+
+fn file_header(size: u64, mtime: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(size);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header
+}
+
+fn read_archive(data: &[u8]) -> Result<(Manifest, Snapshot)> {
+    let invalid = |e: std::io::Error| AppError::Internal(format!("Corrupt backup archive: {e}"));
+
+    let mut archive = tar::Archive::new(GzDecoder::new(data));
+    let mut manifest = None;
+    let mut snapshot = Snapshot {
+        folders: Vec::new(),
+        files: Vec::new(),
+    };
+
+    for entry in archive.entries().map_err(invalid)? {
+        let mut entry = entry.map_err(invalid)?;
+        let path = entry.path().map_err(invalid)?.into_owned();
+
+        if path == Path::new(MANIFEST_NAME) {
+            let mut json = Vec::new();
+            entry.read_to_end(&mut json).map_err(invalid)?;
+            manifest = Some(
+                serde_json::from_slice::<Manifest>(&json)
+                    .map_err(|e| AppError::Internal(format!("Corrupt backup manifest: {e}")))?,
+            );
+            continue;
+        }
+
+        let Ok(relative) = path.strip_prefix(FILES_DIR) else {
+            continue;
+        };
+        // Never let an archive write outside the project
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(AppError::Internal(
+                "Backup archive contains an unsafe path".to_string(),
+            ));
+        }
+        let Some(relative) = relative.to_str().filter(|p| !p.is_empty()) else {
+            continue;
+        };
+        let relative = relative.replace('\\', "/");
+
+        if entry.header().entry_type().is_dir() {
+            snapshot.folders.push(relative);
+        } else if entry.header().entry_type().is_file() {
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).map_err(invalid)?;
+            snapshot.files.push((relative, content));
+        }
+    }
+
+    let manifest =
+        manifest.ok_or_else(|| AppError::Internal("Backup archive has no manifest".to_string()))?;
+    if manifest.version > MANIFEST_VERSION {
+        return Err(AppError::BadRequest(format!(
+            "Backup format version {} is newer than this server supports",
+            manifest.version
+        )));
+    }
+
+    Ok((manifest, snapshot))
+}
+
+/// Back up every project on a fixed interval, starting one interval after launch.
+pub fn spawn_scheduler(
+    db: Database,
+    storage: StorageService,
+    backups: BackupService,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            match backups.backup_all(&db, &storage).await {
+                Ok(count) => tracing::info!("Scheduled backup finished: {} projects", count),
+                Err(e) => tracing::warn!("Scheduled backup failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,524 @@
+// Reference import
+// Looks up a DOI on Crossref or an arXiv ID on the arXiv API and turns the
+// metadata into BibTeX fields and a citation key
+
+use std::time::Duration;
+
+use quick_xml::events::Event;
+use serde_json::Value;
+
+use crate::{
+    config::Config,
+    error::{AppError, Result},
+    services::bibtex::{BibEntry, BibField, MONTHS},
+};
+
+const LOOKUP_TIMEOUT: Duration = Duration::from_secs(20);
+
+// Title words too common to make a key memorable
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "for", "from", "how", "in", "is", "of", "on", "the", "to", "towards",
+    "with",
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Identifier {
+    Doi(String),
+    Arxiv(String),
+}
+
+/// Metadata ready to be written as a BibTeX entry.
+#[derive(Debug)]
+pub struct Reference {
+    pub entry_type: &'static str,
+    pub fields: Vec<BibField>,
+    /// Family name of the first author, for the key
+    pub first_author: Option<String>,
+    pub year: Option<String>,
+    pub title: Option<String>,
+    pub doi: Option<String>,
+}
+
+/// Recognize a DOI or arXiv ID, bare or as a URL.
+pub fn parse_identifier(input: &str) -> Result<Identifier> {
+    let input = input.trim();
+    let lower = input.to_lowercase();
+
+    let doi = [
+        "https://doi.org/",
+        "http://doi.org/",
+        "https://dx.doi.org/",
+        "http://dx.doi.org/",
+        "doi:",
+    ]
+    .iter()
+    .find_map(|prefix| {
+        lower
+            .starts_with(prefix)
+            .then(|| input[prefix.len()..].trim())
+    })
+    .unwrap_or(input);
+    if doi.starts_with("10.") && doi.contains('/') && !doi.contains(char::is_whitespace) {
+        return Ok(Identifier::Doi(doi.to_string()));
+    }
+
+    let arxiv = [
+        "https://arxiv.org/abs/",
+        "http://arxiv.org/abs/",
+        "https://arxiv.org/pdf/",
+        "http://arxiv.org/pdf/",
+        "arxiv:",
+    ]
+    .iter()
+    .find_map(|prefix| lower.starts_with(prefix).then(|| &input[prefix.len()..]))
+    .unwrap_or(input);
+    let arxiv = arxiv.trim_end_matches(".pdf");
+    if is_arxiv_id(arxiv) {
+        return Ok(Identifier::Arxiv(arxiv.to_string()));
+    }
+
+    Err(AppError::BadRequest(format!(
+        "'{input}' is not a DOI or arXiv ID"
+    )))
+}
+
+// "2101.00001", "2101.00001v2" or the old "hep-th/9901001"
+fn is_arxiv_id(id: &str) -> bool {
+    let id = match id.rsplit_once('v') {
+        Some((id, version))
+            if !version.is_empty() && version.chars().all(|c| c.is_ascii_digit()) =>
+        {
+            id
+        }
+        _ => id,
+    };
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    match id.split_once('/') {
+        Some((archive, number)) => {
+            archive
+                .chars()
+                .all(|c| c.is_ascii_alphabetic() || matches!(c, '-' | '.'))
+                && !archive.is_empty()
+                && number.len() == 7
+                && digits(number)
+        }
+        None => match id.split_once('.') {
+            Some((month, number)) => {
+                month.len() == 4
+                    && digits(month)
+                    && (4..=5).contains(&number.len())
+                    && digits(number)
+            }
+            None => false,
+        },
+    }
+}
+
+fn client(config: &Config) -> Result<reqwest::Client> {
+    // Crossref serves requests that say who they're from more reliably
+    let user_agent = match &config.crossref_mailto {
+        Some(mailto) => format!("openleaf/{} (mailto:{mailto})", env!("CARGO_PKG_VERSION")),
+        None => format!("openleaf/{}", env!("CARGO_PKG_VERSION")),
+    };
+    reqwest::Client::builder()
+        .user_agent(user_agent)
+        .timeout(LOOKUP_TIMEOUT)
+        .build()
+        .map_err(|e| AppError::Internal(format!("Failed to create HTTP client: {e}")))
+}
+
+/// Fetch the metadata for an identifier.
+pub async fn lookup(config: &Config, identifier: &Identifier) -> Result<Reference> {
+    let client = client(config)?;
+    match identifier {
+        Identifier::Doi(doi) => crossref(&client, &config.crossref_url, doi).await,
+        Identifier::Arxiv(id) => arxiv(&client, &config.arxiv_url, id).await,
+    }
+}
+
+async fn crossref(client: &reqwest::Client, base_url: &str, doi: &str) -> Result<Reference> {
+    let url = format!("{}/works/{doi}", base_url.trim_end_matches('/'));
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .map_err(|e| AppError::Internal(format!("Crossref request failed: {e}")))?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(AppError::NotFound(format!("DOI '{doi}' was not found")));
+    }
+    if !response.status().is_success() {
+        return Err(AppError::Internal(format!(
+            "Crossref returned {}",
+            response.status()
+        )));
+    }
+    let body: Value = response
+        .json()
+        .await
+        .map_err(|e| AppError::Internal(format!("Invalid Crossref response: {e}")))?;
+
+    Ok(from_crossref(&body["message"], doi))
+}
+
+fn from_crossref(work: &Value, doi: &str) -> Reference {
+    let first = |key: &str| {
+        work[key]
+            .as_array()
+            .and_then(|values| values.first())
+            .and_then(Value::as_str)
+            .map(clean_text)
+            .filter(|value| !value.is_empty())
+    };
+    let text = |key: &str| {
+        work[key]
+            .as_str()
+            .map(clean_text)
+            .filter(|value| !value.is_empty())
+    };
+
+    let entry_type = match work["type"].as_str().unwrap_or_default() {
+        "journal-article" => "article",
+        "proceedings-article" => "inproceedings",
+        "book" | "monograph" | "edited-book" | "reference-book" => "book",
+        "book-chapter" | "book-section" | "book-part" => "incollection",
+        "dissertation" => "phdthesis",
+        "report" => "techreport",
+        _ => "misc",
+    };
+
+    let authors: Vec<(Option<String>, String)> = work["author"]
+        .as_array()
+        .map(|authors| {
+            authors
+                .iter()
+                .filter_map(
+                    |author| match (author["family"].as_str(), author["name"].as_str()) {
+                        (Some(family), _) => Some((
+                            Some(clean_text(family)),
+                            match author["given"].as_str() {
+                                Some(given) => {
+                                    format!("{}, {}", clean_text(family), clean_text(given))
+                                }
+                                None => clean_text(family),
+                            },
+                        )),
+                        // Organizations; braces keep BibTeX from splitting the name
+                        (None, Some(name)) => Some((None, format!("{{{}}}", clean_text(name)))),
+                        (None, None) => None,
+                    },
+                )
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let date = ["issued", "published-print", "published-online", "created"]
+        .iter()
+        .find_map(|key| {
+            work[key]["date-parts"][0]
+                .as_array()
+                .filter(|parts| !parts.is_empty())
+        });
+    let year = date
+        .and_then(|parts| parts[0].as_i64())
+        .map(|year| year.to_string());
+    let month = date.and_then(|parts| parts.get(1)?.as_i64());
+
+    let title = first("title");
+    let container = first("container-title");
+    let mut fields = Vec::new();
+    let mut push = |name: &str, value: Option<String>| {
+        if let Some(value) = value {
+            fields.push(BibField {
+                name: name.to_string(),
+                value,
+            });
+        }
+    };
+    push(
+        "author",
+        (!authors.is_empty()).then(|| {
+            authors
+                .iter()
+                .map(|(_, name)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(" and ")
+        }),
+    );
+    push("title", title.clone());
+    match entry_type {
+        "article" => push("journal", container),
+        "inproceedings" | "incollection" => push("booktitle", container),
+        _ => {}
+    }
+    push("year", year.clone());
+    push(
+        "month",
+        month
+            .filter(|month| (1..=12).contains(month))
+            .map(|month| MONTHS[month as usize - 1].to_string()),
+    );
+    push("volume", text("volume"));
+    push("number", text("issue"));
+    push("pages", text("page").map(|pages| pages.replace('-', "--")));
+    if matches!(entry_type, "book" | "incollection" | "misc") {
+        push("publisher", text("publisher"));
+    }
+    if entry_type == "phdthesis" {
+        push("school", text("publisher"));
+    }
+    if entry_type == "techreport" {
+        push("institution", text("publisher"));
+    }
+    // DOIs go in as they are; escaping would change them
+    let doi = work["DOI"].as_str().unwrap_or(doi).trim().to_string();
+    push("doi", Some(doi.clone()));
+    push("url", Some(format!("https://doi.org/{doi}")));
+
+    Reference {
+        entry_type,
+        fields,
+        first_author: authors.into_iter().next().and_then(|(family, _)| family),
+        year,
+        title,
+        doi: Some(doi),
+    }
+}
+
+async fn arxiv(client: &reqwest::Client, base_url: &str, id: &str) -> Result<Reference> {
+    let url = format!("{}/query", base_url.trim_end_matches('/'));
+    let response = client
+        .get(&url)
+        .query(&[("id_list", id)])
+        .send()
+        .await
+        .map_err(|e| AppError::Internal(format!("arXiv request failed: {e}")))?;
+    if !response.status().is_success() {
+        return Err(AppError::Internal(format!(
+            "arXiv returned {}",
+            response.status()
+        )));
+    }
+    let feed = response
+        .text()
+        .await
+        .map_err(|e| AppError::Internal(format!("Invalid arXiv response: {e}")))?;
+
+    from_arxiv(&feed, id)
+}
+
+#[derive(Default)]
+struct ArxivEntry {
+    title: String,
+    published: String,
+    authors: Vec<String>,
+    doi: String,
+    category: Option<String>,
+}
+
+// The first <entry> of an Atom feed from the arXiv API
+fn from_arxiv(feed: &str, id: &str) -> Result<Reference> {
+    let invalid = |e: quick_xml::Error| AppError::Internal(format!("Invalid arXiv response: {e}"));
+    let mut reader = quick_xml::Reader::from_str(feed);
+    let mut path: Vec<String> = Vec::new();
+    let mut entry: Option<ArxivEntry> = None;
+
+    loop {
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(tag) => {
+                let name = String::from_utf8_lossy(tag.local_name().as_ref()).into_owned();
+                if name == "entry" && entry.is_none() {
+                    entry = Some(ArxivEntry::default());
+                }
+                if name == "author" {
+                    if let Some(entry) = entry.as_mut() {
+                        entry.authors.push(String::new());
+                    }
+                }
+                path.push(name);
+            }
+            Event::Empty(tag) if tag.local_name().as_ref() == b"primary_category" => {
+                if let Some(entry) = entry.as_mut() {
+                    entry.category = tag
+                        .try_get_attribute("term")
+                        .ok()
+                        .flatten()
+                        .and_then(|term| term.unescape_value().ok())
+                        .map(|term| term.into_owned());
+                }
+            }
+            Event::End(tag) => {
+                path.pop();
+                if tag.local_name().as_ref() == b"entry" && entry.is_some() {
+                    break;
+                }
+            }
+            Event::Text(text) => {
+                let Some(entry) = entry.as_mut() else {
+                    continue;
+                };
+                let text = text.unescape().map_err(invalid)?;
+                let field = match path
+                    .iter()
+                    .rev()
+                    .take(2)
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()[..]
+                {
+                    ["title", "entry"] => &mut entry.title,
+                    ["published", "entry"] => &mut entry.published,
+                    ["doi", "entry"] => &mut entry.doi,
+                    ["name", "author"] => match entry.authors.last_mut() {
+                        Some(author) => author,
+                        None => continue,
+                    },
+                    _ => continue,
+                };
+                field.push_str(&text);
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    // Unknown IDs come back as an entry titled "Error" or no entry at all
+    let entry = entry
+        .filter(|entry| !entry.title.trim().is_empty() && entry.title.trim() != "Error")
+        .ok_or_else(|| AppError::NotFound(format!("arXiv ID '{id}' was not found")))?;
+
+    let title = clean_text(&entry.title);
+    let year = entry.published.get(..4).map(str::to_string);
+    let authors: Vec<String> = entry
+        .authors
+        .iter()
+        .map(|author| clean_text(author))
+        .filter(|author| !author.is_empty())
+        .collect();
+    // arXiv gives "Given Family"
+    let first_author = authors
+        .first()
+        .and_then(|author| author.split_whitespace().last())
+        .map(str::to_string);
+    let doi = Some(entry.doi.trim().to_string()).filter(|doi| !doi.is_empty());
+    let bare_id = match id.rsplit_once('v') {
+        Some((bare, version)) if version.chars().all(|c| c.is_ascii_digit()) => bare,
+        _ => id,
+    };
+
+    let mut fields = Vec::new();
+    let mut push = |name: &str, value: Option<String>| {
+        if let Some(value) = value {
+            fields.push(BibField {
+                name: name.to_string(),
+                value,
+            });
+        }
+    };
+    push(
+        "author",
+        (!authors.is_empty()).then(|| authors.join(" and ")),
+    );
+    push("title", Some(title.clone()));
+    push("year", year.clone());
+    push("eprint", Some(bare_id.to_string()));
+    push("archiveprefix", Some("arXiv".to_string()));
+    push("primaryclass", entry.category);
+    push("doi", doi.clone());
+    push("url", Some(format!("https://arxiv.org/abs/{bare_id}")));
+
+    Ok(Reference {
+        entry_type: "misc",
+        fields,
+        first_author,
+        year,
+        title: Some(title),
+        doi,
+    })
+}
+
+// Collapses whitespace, drops the JATS/HTML tags Crossref puts in titles and
+// escapes characters that are special in LaTeX
+fn clean_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    let mut previous = None;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if in_tag => {}
+            '&' | '%' | '#' | '_' | '$' if previous != Some('\\') => {
+                out.push('\\');
+                out.push(c);
+            }
+            // Lone braces would unbalance the entry
+            '{' | '}' => {}
+            _ => out.push(c),
+        }
+        previous = Some(c);
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A key like "smith2020deep" that isn't used in `existing` yet.
+pub fn citation_key(reference: &Reference, existing: &[BibEntry]) -> String {
+    let ascii = |text: &str| -> String {
+        text.to_lowercase()
+            .chars()
+            .map(fold_accent)
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect()
+    };
+    let author = reference
+        .first_author
+        .as_deref()
+        .map(ascii)
+        .filter(|author| !author.is_empty())
+        .unwrap_or_else(|| "ref".to_string());
+    let word = reference
+        .title
+        .as_deref()
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(ascii)
+        .find(|word| word.len() > 1 && !STOP_WORDS.contains(&word.as_str()))
+        .unwrap_or_default();
+    let base = format!(
+        "{author}{}{word}",
+        reference.year.as_deref().unwrap_or_default()
+    );
+
+    let taken = |key: &str| {
+        existing
+            .iter()
+            .any(|entry| entry.key.eq_ignore_ascii_case(key))
+    };
+    if !taken(&base) {
+        return base;
+    }
+    ('a'..='z')
+        .map(|suffix| format!("{base}{suffix}"))
+        .find(|key| !taken(key))
+        .unwrap_or_else(|| format!("{base}{}", existing.len()))
+}
+
+// Keys are plain ASCII, so "Müller" becomes "muller" rather than "mller"
+fn fold_accent(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => 'a',
+        'ç' | 'ć' | 'č' => 'c',
+        'ď' | 'đ' => 'd',
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ę' | 'ě' => 'e',
+        'ğ' => 'g',
+        'ì' | 'í' | 'î' | 'ï' | 'ı' => 'i',
+        'ł' | 'ľ' => 'l',
+        'ñ' | 'ń' | 'ň' => 'n',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ő' => 'o',
+        'ř' => 'r',
+        'ś' | 'š' | 'ş' | 'ß' => 's',
+        'ť' | 'ţ' => 't',
+        'ù' | 'ú' | 'û' | 'ü' | 'ů' | 'ű' => 'u',
+        'ý' | 'ÿ' => 'y',
+        'ź' | 'ż' | 'ž' => 'z',
+        _ => c,
+    }
+}
@@ -0,0 +1,154 @@
+// Bibliography diagnostics
+// Pulls missing citations, undefined references and database errors out of
+// BibTeX/Biber .blg files and the LaTeX log
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BibIssueKind {
+    /// \cite key with no entry in any database
+    MissingEntry,
+    /// LaTeX saw a citation that never got resolved
+    UndefinedCitation,
+    /// \ref/\eqref label that was never defined
+    UndefinedReference,
+    /// The .bib file could not be found or parsed
+    DatabaseError,
+    /// Anything else the bibliography tool warned about
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BibIssue {
+    pub kind: BibIssueKind,
+    pub key: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<i32>,
+    pub message: String,
+}
+
+impl BibIssue {
+    fn new(kind: BibIssueKind, message: &str) -> Self {
+        Self {
+            kind,
+            key: None,
+            file: None,
+            line: None,
+            message: message.trim().to_string(),
+        }
+    }
+}
+
+/// Parse a .blg written by either BibTeX or Biber.
+pub fn parse_blg(blg: &str) -> Vec<BibIssue> {
+    let mut issues = Vec::new();
+
+    for line in blg.lines() {
+        // Biber: "[123] Utils.pm:409> WARN - ..." or "WARN - ..." / "ERROR - ..."
+        if let Some((_, message)) = line.split_once("WARN - ") {
+            issues.push(biber_issue(message, false));
+        } else if let Some((_, message)) = line.split_once("ERROR - ") {
+            issues.push(biber_issue(message, true));
+        }
+        // BibTeX
+        else if let Some(rest) = line.strip_prefix("Warning--I didn't find a database entry for ")
+        {
+            let mut issue = BibIssue::new(
+                BibIssueKind::MissingEntry,
+                line.trim_start_matches("Warning--"),
+            );
+            issue.key = Some(rest.trim().trim_matches('"').to_string());
+            issues.push(issue);
+        } else if let Some(message) = line.strip_prefix("Warning--") {
+            issues.push(BibIssue::new(BibIssueKind::Warning, message));
+        } else if line.starts_with("I couldn't open database file")
+            || line.starts_with("I found no \\bibdata command")
+            || line.starts_with("I found no \\citation commands")
+        {
+            issues.push(BibIssue::new(BibIssueKind::DatabaseError, line));
+        } else if let Some((message, location)) = line.split_once("---line ") {
+            // "I was expecting a `,' or a `}'---line 12 of file refs.bib"
+            let mut issue = BibIssue::new(BibIssueKind::DatabaseError, message);
+            if let Some((num, file)) = location.split_once(" of file ") {
+                issue.line = num.trim().parse().ok();
+                issue.file = Some(file.trim().to_string());
+            }
+            issues.push(issue);
+        }
+    }
+
+    issues
+}
+
+fn biber_issue(message: &str, error: bool) -> BibIssue {
+    let message = message.trim();
+
+    if let Some(rest) = message.strip_prefix("I didn't find a database entry for '") {
+        let mut issue = BibIssue::new(BibIssueKind::MissingEntry, message);
+        issue.key = rest.split('\'').next().map(str::to_string);
+        return issue;
+    }
+
+    // "BibTeX subsystem: /tmp/x/refs.bib_123.utf8, line 5, syntax error: ..."
+    if let Some(rest) = message.strip_prefix("BibTeX subsystem: ") {
+        let mut issue = BibIssue::new(BibIssueKind::DatabaseError, message);
+        let mut parts = rest.splitn(3, ", ");
+        if let (Some(file), Some(line)) = (parts.next(), parts.next()) {
+            // Biber parses a temporary copy named after the original file
+            let file = file.rsplit('/').next().unwrap_or(file);
+            let file = file.split(".bib_").next().unwrap_or(file);
+            issue.file = Some(format!("{}.bib", file.trim_end_matches(".bib")));
+            issue.line = line.trim_start_matches("line ").parse().ok();
+        }
+        return issue;
+    }
+
+    let kind = if error {
+        BibIssueKind::DatabaseError
+    } else {
+        BibIssueKind::Warning
+    };
+    BibIssue::new(kind, message)
+}
+
+/// Undefined citations and references reported by LaTeX itself.
+pub fn parse_latex_log(log: &str) -> Vec<BibIssue> {
+    let mut issues = Vec::new();
+
+    for line in log.lines() {
+        let (kind, rest) = if let Some((_, rest)) = line.split_once("Warning: Citation `") {
+            (BibIssueKind::UndefinedCitation, rest)
+        } else if let Some((_, rest)) = line.split_once("Warning: Reference `") {
+            (BibIssueKind::UndefinedReference, rest)
+        } else {
+            continue;
+        };
+        if !rest.contains("undefined") {
+            continue;
+        }
+
+        let Some((key, _)) = rest.split_once('\'') else {
+            continue;
+        };
+        let message = line
+            .split_once("Warning: ")
+            .map_or(line, |(_, message)| message);
+
+        let mut issue = BibIssue::new(kind, message);
+        issue.key = Some(key.to_string());
+        issue.line = rest
+            .split_once("on input line ")
+            .and_then(|(_, num)| num.trim_end_matches('.').trim().parse().ok());
+
+        // The same warning repeats on every LaTeX pass
+        if !issues.iter().any(|known: &BibIssue| {
+            known.kind == issue.kind && known.key == issue.key && known.line == issue.line
+        }) {
+            issues.push(issue);
+        }
+    }
+
+    issues
+}
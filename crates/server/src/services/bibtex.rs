@@ -0,0 +1,415 @@
+// BibTeX databases
+// Parses .bib files into entries with their positions, writes single entries
+// back without touching the rest of the file, and finds duplicate references
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::{AppError, Result};
+
+// Macros every BibTeX style defines
+pub(crate) const MONTHS: &[&str] = &[
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BibField {
+    /// Lowercased, e.g. "title"
+    pub name: String,
+    /// Without the outer braces or quotes; inner braces are kept
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BibEntry {
+    /// Lowercased, e.g. "article"
+    pub entry_type: String,
+    pub key: String,
+    pub fields: Vec<BibField>,
+    /// 1-based line of the '@'
+    pub line: usize,
+    /// Byte range of the whole entry in the file
+    #[serde(skip)]
+    pub span: Range<usize>,
+}
+
+impl BibEntry {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.name == name)
+            .map(|field| field.value.as_str())
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct BibError {
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct BibDatabase {
+    pub entries: Vec<BibEntry>,
+    /// Names defined with @string
+    pub strings: Vec<String>,
+    pub errors: Vec<BibError>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Duplicate {
+    /// "key", "doi" or "title"
+    pub reason: &'static str,
+    /// The shared key, DOI or normalized title
+    pub value: String,
+    pub keys: Vec<String>,
+    pub lines: Vec<usize>,
+}
+
+/// Parse a .bib file. Text outside entries is ignored, as BibTeX does.
+pub fn parse(source: &str) -> BibDatabase {
+    let bytes = source.as_bytes();
+    let line_of = |offset: usize| bytes[..offset].iter().filter(|b| **b == b'\n').count() + 1;
+    let mut database = BibDatabase::default();
+
+    let mut i = 0;
+    while let Some(at) = bytes[i..].iter().position(|b| *b == b'@').map(|p| p + i) {
+        let mut j = at + 1;
+        while j < bytes.len() && bytes[j].is_ascii_alphabetic() {
+            j += 1;
+        }
+        let entry_type = source[at + 1..j].to_lowercase();
+        while j < bytes.len() && bytes[j].is_ascii_whitespace() {
+            j += 1;
+        }
+        let close = match bytes.get(j) {
+            Some(b'{') => b'}',
+            Some(b'(') => b')',
+            _ => {
+                i = at + 1;
+                continue;
+            }
+        };
+        if entry_type.is_empty() {
+            i = at + 1;
+            continue;
+        }
+
+        let Some(end) = closing(bytes, j, close) else {
+            database.errors.push(BibError {
+                line: line_of(at),
+                message: format!("Unterminated @{entry_type} entry"),
+            });
+            break;
+        };
+        i = end + 1;
+        let body = &source[j + 1..end];
+
+        match entry_type.as_str() {
+            "comment" | "preamble" => {}
+            "string" => {
+                for field in split_top_level(body, ',') {
+                    if let Some((name, _)) = field.split_once('=') {
+                        database.strings.push(name.trim().to_lowercase());
+                    }
+                }
+            }
+            _ => {
+                let mut parts = split_top_level(body, ',').into_iter();
+                let key = parts.next().unwrap_or_default().trim();
+                if key.is_empty() || key.contains('=') {
+                    database.errors.push(BibError {
+                        line: line_of(at),
+                        message: format!("@{entry_type} entry has no key"),
+                    });
+                    continue;
+                }
+                let mut fields = Vec::new();
+                for part in parts {
+                    if part.trim().is_empty() {
+                        continue;
+                    }
+                    match part.split_once('=') {
+                        Some((name, value)) => fields.push(BibField {
+                            name: name.trim().to_lowercase(),
+                            value: unquote(value.trim()).to_string(),
+                        }),
+                        None => database.errors.push(BibError {
+                            line: line_of(at),
+                            message: format!("Field without a value in '{key}'"),
+                        }),
+                    }
+                }
+                database.entries.push(BibEntry {
+                    entry_type,
+                    key: key.to_string(),
+                    fields,
+                    line: line_of(at),
+                    span: at..end + 1,
+                });
+            }
+        }
+    }
+
+    database
+}
+
+// The index of the bracket closing the one at `open`. Braces nest inside
+// either kind of entry; parentheses only close at the top level.
+fn closing(bytes: &[u8], open: usize, close: u8) -> Option<usize> {
+    let mut depth = 0usize;
+    for (offset, b) in bytes[open + 1..].iter().enumerate() {
+        match *b {
+            b'}' if depth == 0 => return (close == b'}').then_some(open + 1 + offset),
+            b'{' => depth += 1,
+            b'}' => depth -= 1,
+            b')' if depth == 0 && close == b')' => return Some(open + 1 + offset),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Splits on `separator` outside braces and quotes.
+pub(crate) fn split_top_level(text: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0i32;
+    let mut quoted = false;
+    let mut start = 0;
+    for (index, c) in text.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            '"' if depth == 0 => quoted = !quoted,
+            c if c == separator && depth == 0 && !quoted => {
+                parts.push(&text[start..index]);
+                start = index + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+}
+
+// A single braced or quoted value loses its delimiters; numbers, macros and
+// concatenations are kept as written
+fn unquote(value: &str) -> &str {
+    let delimited = |open: char, close: char| {
+        let inner = value.strip_prefix(open)?.strip_suffix(close)?;
+        // "{a} # {b}" starts and ends with braces but is two values
+        (split_top_level(value, '#').len() == 1 && braces_balanced(inner)).then_some(inner)
+    };
+    delimited('{', '}')
+        .or_else(|| delimited('"', '"'))
+        .unwrap_or(value)
+}
+
+fn braces_balanced(text: &str) -> bool {
+    let mut depth = 0i32;
+    for c in text.chars() {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => {}
+        }
+        if depth < 0 {
+            return false;
+        }
+    }
+    depth == 0
+}
+
+/// A field value as plain text, for display.
+pub fn plain(value: &str) -> String {
+    value
+        .replace(['{', '}'], "")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.chars().all(|c| {
+            !c.is_whitespace()
+                && !matches!(
+                    c,
+                    ',' | '{' | '}' | '(' | ')' | '"' | '#' | '%' | '=' | '\\'
+                )
+        })
+}
+
+/// Check an entry before it is written, so a bad value can't break the file.
+pub fn validate(entry_type: &str, key: &str, fields: &[BibField]) -> Result<()> {
+    if entry_type.is_empty()
+        || !entry_type.chars().all(|c| c.is_ascii_alphabetic())
+        || matches!(
+            entry_type.to_lowercase().as_str(),
+            "comment" | "string" | "preamble"
+        )
+    {
+        return Err(AppError::BadRequest(format!(
+            "Invalid entry type '{entry_type}'"
+        )));
+    }
+    if !is_valid_key(key) {
+        return Err(AppError::BadRequest(format!("Invalid key '{key}'")));
+    }
+    for (index, field) in fields.iter().enumerate() {
+        let valid_name = field
+            .name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic())
+            && field
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':' | '.' | '+'));
+        if !valid_name {
+            return Err(AppError::BadRequest(format!(
+                "Invalid field name '{}'",
+                field.name
+            )));
+        }
+        if fields[..index]
+            .iter()
+            .any(|other| other.name.eq_ignore_ascii_case(&field.name))
+        {
+            return Err(AppError::BadRequest(format!(
+                "Field '{}' is given twice",
+                field.name
+            )));
+        }
+        if !braces_balanced(&field.value) {
+            return Err(AppError::BadRequest(format!(
+                "Unbalanced braces in field '{}'",
+                field.name
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Write an entry in the usual one-field-per-line layout.
+pub fn format_entry(
+    entry_type: &str,
+    key: &str,
+    fields: &[BibField],
+    strings: &[String],
+) -> String {
+    let mut out = format!("@{}{{{key},\n", entry_type.to_lowercase());
+    for field in fields {
+        let value = field.value.trim();
+        let lower = value.to_lowercase();
+        // Numbers and macros stay bare so they keep their meaning
+        let bare = (!value.is_empty() && value.chars().all(|c| c.is_ascii_digit()))
+            || MONTHS.contains(&lower.as_str())
+            || strings.contains(&lower);
+        if bare {
+            out.push_str(&format!("  {} = {value},\n", field.name.to_lowercase()));
+        } else {
+            out.push_str(&format!("  {} = {{{value}}},\n", field.name.to_lowercase()));
+        }
+    }
+    out.push('}');
+    out
+}
+
+/// Replace the text of `span` in `source`, or append when there's no span.
+pub fn splice(source: &str, span: Option<Range<usize>>, text: &str) -> String {
+    match span {
+        Some(span) => format!("{}{text}{}", &source[..span.start], &source[span.end..]),
+        None => {
+            let trimmed = source.trim_end();
+            if trimmed.is_empty() {
+                format!("{text}\n")
+            } else {
+                format!("{trimmed}\n\n{text}\n")
+            }
+        }
+    }
+}
+
+/// Remove an entry along with the rest of its line and one blank line.
+pub fn remove(source: &str, span: Range<usize>) -> String {
+    let mut end = span.end;
+    let rest = &source[end..];
+    let line_end = rest.find('\n').map_or(rest.len(), |n| n + 1);
+    if rest[..line_end].trim().is_empty() {
+        end += line_end;
+        if source[end..].starts_with('\n') && source[..span.start].ends_with("\n\n") {
+            end += 1;
+        }
+    }
+    format!("{}{}", &source[..span.start], &source[end..])
+}
+
+/// Entries sharing a key, DOI, or title and year.
+pub fn duplicates(entries: &[BibEntry]) -> Vec<Duplicate> {
+    let mut groups: Vec<(&'static str, String, Vec<usize>)> = Vec::new();
+    let mut index: HashMap<(&'static str, String), usize> = HashMap::new();
+    let mut add = |reason: &'static str, value: String, entry: usize| match index
+        .get(&(reason, value.clone()))
+    {
+        Some(&group) => groups[group].2.push(entry),
+        None => {
+            index.insert((reason, value.clone()), groups.len());
+            groups.push((reason, value, vec![entry]));
+        }
+    };
+
+    for (i, entry) in entries.iter().enumerate() {
+        add("key", entry.key.to_lowercase(), i);
+        if let Some(doi) = entry.field("doi").map(normalize_doi) {
+            if !doi.is_empty() {
+                add("doi", doi, i);
+            }
+        }
+        if let Some(title) = entry.field("title").map(normalize_title) {
+            // Short titles like "Introduction" collide too easily
+            if title.len() >= 10 {
+                let year = entry.field("year").map(plain).unwrap_or_default();
+                add("title", format!("{title} {year}").trim_end().to_string(), i);
+            }
+        }
+    }
+
+    groups
+        .into_iter()
+        .filter(|(_, _, members)| members.len() > 1)
+        .map(|(reason, value, members)| Duplicate {
+            reason,
+            value,
+            keys: members.iter().map(|&i| entries[i].key.clone()).collect(),
+            lines: members.iter().map(|&i| entries[i].line).collect(),
+        })
+        .collect()
+}
+
+fn normalize_doi(doi: &str) -> String {
+    let doi = plain(doi).to_lowercase();
+    [
+        "https://doi.org/",
+        "http://doi.org/",
+        "https://dx.doi.org/",
+        "http://dx.doi.org/",
+        "doi:",
+    ]
+    .iter()
+    .find_map(|prefix| doi.strip_prefix(prefix))
+    .unwrap_or(&doi)
+    .trim()
+    .to_string()
+}
+
+fn normalize_title(title: &str) -> String {
+    plain(title)
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .collect()
+}
@@ -0,0 +1,260 @@
+// Per-project build directories
+// Holds everything a compile writes (PDF, logs, aux files) outside the project
+// sources. Aux files are kept between compiles so latexmk only reruns the
+// passes a change actually needs, and the last result is remembered so an
+// unchanged project isn't compiled again at all
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{
+    error::{AppError, Result},
+    services::compiler::{CompileOptions, CompileResult},
+};
+
+const STATE_FILE: &str = "build-state.json";
+
+// Build directory of compiles that don't name a target; target names start
+// with a letter or digit, so this can't clash
+const DEFAULT_TARGET: &str = "_default";
+
+/// A file the last compile left in the build directory.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Artifact {
+    pub name: String,
+    /// "pdf", "synctex", "bibliography", "log" or "aux"
+    pub kind: &'static str,
+    pub size: u64,
+    pub modified: Option<String>,
+}
+
+impl Artifact {
+    fn kind(name: &str) -> &'static str {
+        if name.ends_with(".pdf") {
+            "pdf"
+        } else if name.ends_with(".synctex.gz") || name.ends_with(".synctex") {
+            "synctex"
+        } else if name.ends_with(".bbl") {
+            "bibliography"
+        } else if name.ends_with(".log") || name.ends_with(".blg") {
+            "log"
+        } else {
+            "aux"
+        }
+    }
+}
+
+/// What the build directory was last used for.
+#[derive(Debug, Serialize, Deserialize)]
+struct BuildState {
+    fingerprint: String,
+    source_hash: Option<String>,
+    /// Set only when the compile produced a PDF
+    result: Option<CompileResult>,
+}
+
+pub struct BuildCache {
+    dir: PathBuf,
+}
+
+impl BuildCache {
+    /// The build directory of one target of the project, or of its default
+    /// build. Targets build separately so one doesn't wipe another's output.
+    pub fn new(cache_root: &Path, project_id: &str, target: Option<&str>) -> Self {
+        Self {
+            dir: project_dir(cache_root, project_id).join(target.unwrap_or(DEFAULT_TARGET)),
+        }
+    }
+
+    /// The build directory holding the output `name`, taking the most
+    /// recently built one if several targets have a file by that name.
+    pub async fn containing(cache_root: &Path, project_id: &str, name: &str) -> Option<Self> {
+        let mut entries = tokio::fs::read_dir(project_dir(cache_root, project_id))
+            .await
+            .ok()?;
+        let mut newest: Option<(std::time::SystemTime, Self)> = None;
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let cache = Self { dir: entry.path() };
+            let Some(path) = cache.artifact_path(name) else {
+                continue;
+            };
+            let modified = tokio::fs::metadata(&path)
+                .await
+                .and_then(|metadata| metadata.modified())
+                .unwrap_or(std::time::UNIX_EPOCH);
+            if newest.as_ref().is_none_or(|(time, _)| modified > *time) {
+                newest = Some((modified, cache));
+            }
+        }
+        newest.map(|(_, cache)| cache)
+    }
+
+    /// Delete the build directories of every target of the project.
+    pub async fn clear_project(cache_root: &Path, project_id: &str) -> Result<()> {
+        Self {
+            dir: project_dir(cache_root, project_id),
+        }
+        .clear()
+        .await
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Identifies the toolchain settings a build directory was produced with;
+    /// aux files from a different engine or main file are useless or harmful.
+    pub fn fingerprint(options: &CompileOptions, backend: &str) -> String {
+        format!(
+            "{backend}:{}:{}:{}:{}:{}",
+            options.engine.as_str(),
+            options.bib_tool.map_or("auto", |tool| tool.as_str()),
+            options.shell_escape,
+            options.trusted,
+            options.main_file
+        )
+    }
+
+    /// The previous result, if it was built from exactly these sources and
+    /// settings and its PDF is still in place.
+    pub async fn lookup(&self, fingerprint: &str, source_hash: &str) -> Option<CompileResult> {
+        let state = self.read_state().await?;
+        if state.fingerprint != fingerprint || state.source_hash.as_deref() != Some(source_hash) {
+            return None;
+        }
+        let result = state.result?;
+        let pdf_path = result.pdf_path.as_deref()?;
+        self.dir.join(pdf_path).exists().then_some(result)
+    }
+
+    /// Get the build directory ready for a compile, starting from scratch when
+    /// the settings changed or the last compile didn't produce a PDF, since
+    /// half-written aux files can break the next run.
+    pub async fn prepare(&self, fingerprint: &str) -> Result<PathBuf> {
+        let reusable = self
+            .read_state()
+            .await
+            .is_some_and(|state| state.fingerprint == fingerprint && state.result.is_some());
+        if !reusable {
+            self.clear().await?;
+        }
+
+        tokio::fs::create_dir_all(&self.dir)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to create build directory: {e}")))?;
+        // The compiler runs inside the project, so hand it an absolute path
+        tokio::fs::canonicalize(&self.dir)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to resolve build directory: {e}")))
+    }
+
+    /// Remember how the compile went, for `lookup` and the next `prepare`.
+    pub async fn record(
+        &self,
+        fingerprint: &str,
+        source_hash: Option<String>,
+        outcome: &Result<CompileResult>,
+    ) {
+        let state = BuildState {
+            fingerprint: fingerprint.to_string(),
+            source_hash,
+            result: match outcome {
+                Ok(result) if result.success => Some(result.clone()),
+                _ => None,
+            },
+        };
+
+        let written = match serde_json::to_vec(&state) {
+            Ok(json) => tokio::fs::write(self.dir.join(STATE_FILE), json)
+                .await
+                .map_err(|e| e.to_string()),
+            Err(e) => Err(e.to_string()),
+        };
+        // Without the state file the next compile simply starts clean
+        if let Err(e) = written {
+            tracing::warn!(
+                "Failed to save build state for {}: {}",
+                self.dir.display(),
+                e
+            );
+        }
+    }
+
+    /// The PDF of the last compile, if it succeeded.
+    pub async fn last_pdf(&self) -> Option<String> {
+        self.read_state().await?.result?.pdf_path
+    }
+
+    /// Files in the build directory, by name. Server bookkeeping (the build
+    /// state and page thumbnails) is left out.
+    pub async fn artifacts(&self) -> Result<Vec<Artifact>> {
+        let io_error =
+            |e: std::io::Error| AppError::Internal(format!("Failed to list build outputs: {e}"));
+
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(io_error(e)),
+        };
+        let mut artifacts = Vec::new();
+        while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if !is_artifact_name(&name) {
+                continue;
+            }
+            let metadata = entry.metadata().await.map_err(io_error)?;
+            if !metadata.is_file() {
+                continue;
+            }
+            artifacts.push(Artifact {
+                kind: Artifact::kind(&name),
+                size: metadata.len(),
+                modified: metadata
+                    .modified()
+                    .ok()
+                    .map(|time| chrono::DateTime::<chrono::Utc>::from(time).to_rfc3339()),
+                name,
+            });
+        }
+        artifacts.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(artifacts)
+    }
+
+    /// Path of a build output by name, if it exists.
+    pub fn artifact_path(&self, name: &str) -> Option<PathBuf> {
+        if !is_artifact_name(name) {
+            return None;
+        }
+        let path = self.dir.join(name);
+        path.is_file().then_some(path)
+    }
+
+    /// Delete everything in the build directory.
+    async fn clear(&self) -> Result<()> {
+        match tokio::fs::remove_dir_all(&self.dir).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(AppError::Internal(format!(
+                "Failed to clear build directory: {e}"
+            ))),
+        }
+    }
+
+    async fn read_state(&self) -> Option<BuildState> {
+        let json = tokio::fs::read(self.dir.join(STATE_FILE)).await.ok()?;
+        serde_json::from_slice(&json).ok()
+    }
+}
+
+fn project_dir(cache_root: &Path, project_id: &str) -> PathBuf {
+    cache_root.join("builds").join(project_id)
+}
+
+// A plain file name in the build directory that isn't the server's own
+fn is_artifact_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with('.') && !name.contains('/') && name != STATE_FILE
+}
@@ -0,0 +1,118 @@
+// Project chat
+// Messages are sent over the project channel and stored so people who join
+// later can scroll back through the conversation
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+
+// Longest message accepted, in characters
+const MAX_LENGTH: usize = 4000;
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct ChatMessage {
+    pub id: String,
+    pub user_id: String,
+    pub user_name: String,
+    pub content: String,
+    pub created_at: String,
+}
+
+/// Store a message from `user_id`, returning it as it should be broadcast.
+pub async fn post(
+    pool: &DbPool,
+    project_id: &str,
+    user_id: &str,
+    user_name: &str,
+    content: &str,
+) -> Result<ChatMessage> {
+    let content = content.trim();
+    if content.is_empty() {
+        return Err(AppError::Validation("Message is empty".to_string()));
+    }
+    if content.chars().count() > MAX_LENGTH {
+        return Err(AppError::Validation(format!(
+            "Messages are limited to {MAX_LENGTH} characters"
+        )));
+    }
+
+    let message = ChatMessage {
+        id: Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        user_name: user_name.to_string(),
+        content: content.to_string(),
+        created_at: Utc::now().to_rfc3339(),
+    };
+    sqlx::query(
+        "INSERT INTO chat_messages (id, project_id, user_id, content, created_at) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(&message.id)
+    .bind(project_id)
+    .bind(&message.user_id)
+    .bind(&message.content)
+    .bind(&message.created_at)
+    .execute(pool)
+    .await?;
+
+    Ok(message)
+}
+
+/// Up to `limit` messages, newest first, older than message `before` if
+/// given.
+pub async fn history(
+    pool: &DbPool,
+    project_id: &str,
+    before: Option<&str>,
+    limit: i64,
+) -> Result<Vec<ChatMessage>> {
+    let cursor = match before {
+        Some(id) => Some(
+            sqlx::query_scalar::<_, String>(
+                "SELECT created_at FROM chat_messages WHERE id = $1 AND project_id = $2",
+            )
+            .bind(id)
+            .bind(project_id)
+            .fetch_optional(pool)
+            .await?
+            .map(|created_at| (created_at, id))
+            .ok_or_else(|| AppError::NotFound("Message not found".to_string()))?,
+        ),
+        None => None,
+    };
+
+    // Messages sent in the same instant are ordered by ID
+    let messages = match cursor {
+        Some((created_at, id)) => {
+            sqlx::query_as::<_, ChatMessage>(
+                "SELECT m.id, m.user_id, u.name AS user_name, m.content, m.created_at \
+                 FROM chat_messages m JOIN users u ON m.user_id = u.id \
+                 WHERE m.project_id = $1 AND (m.created_at < $2 OR (m.created_at = $3 AND m.id < $4)) \
+                 ORDER BY m.created_at DESC, m.id DESC LIMIT $5",
+            )
+            .bind(project_id)
+            .bind(&created_at)
+            .bind(&created_at)
+            .bind(id)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?
+        }
+        None => {
+            sqlx::query_as::<_, ChatMessage>(
+                "SELECT m.id, m.user_id, u.name AS user_name, m.content, m.created_at \
+                 FROM chat_messages m JOIN users u ON m.user_id = u.id \
+                 WHERE m.project_id = $1 ORDER BY m.created_at DESC, m.id DESC LIMIT $2",
+            )
+            .bind(project_id)
+            .bind(limit)
+            .fetch_all(pool)
+            .await?
+        }
+    };
+    Ok(messages)
+}
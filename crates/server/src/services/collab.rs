@@ -1,57 +1,1065 @@
 // Real-time collaboration service using yrs (Yjs Rust)
-// TODO: Implement full collaboration in future version
+// The server holds the authoritative document of every open file and speaks
+// the y-sync protocol, so clients that join late or reconnect converge on it.
+// Documents are loaded from storage when a room opens and written back
+// periodically and when the last client leaves; rooms nobody rejoins within
+// the idle TTL are then dropped from memory. Awareness (who is connected
+// and where their cursor is) is tracked per connection and relayed.
 
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
 
-use tokio::sync::RwLock;
-use yrs::{Doc, GetString, Transact};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::{broadcast, mpsc, RwLock};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use yrs::{
+    updates::{decoder::Decode, encoder::Encode},
+    Doc, GetString, ReadTxn, StateVector, Text, Transact, Update,
+};
 
-#[allow(dead_code)]
+use crate::{
+    db::Database,
+    error::{AppError, Result},
+    services::{
+        filetype, freeze,
+        history::{self, Record},
+        identicon, search,
+        storage::StorageService,
+    },
+};
+
+// Outer message types of the y-websocket protocol
+const MESSAGE_SYNC: u64 = 0;
+const MESSAGE_AWARENESS: u64 = 1;
+const MESSAGE_QUERY_AWARENESS: u64 = 3;
+// Server to client only: a token for resuming the session after a dropped
+// connection, and whether this connection resumed one. Outside the range
+// y-websocket uses, so standard clients ignore it.
+const MESSAGE_SESSION: u64 = 100;
+
+// Sync message types
+const SYNC_STEP1: u64 = 0;
+const SYNC_STEP2: u64 = 1;
+const SYNC_UPDATE: u64 = 2;
+
+// Room broadcasts a slow client may fall behind by before it misses some
+const BROADCAST_CAPACITY: usize = 256;
+
+// Sender of edits the server makes itself; connection IDs start at 1
+const SERVER: u64 = 0;
+
+// Name of the shared text holding the file content; clients bind their
+// editor to `doc.getText("content")`
+pub const TEXT_NAME: &str = "content";
+
+/// A protocol message from a client.
+#[derive(Debug)]
+pub enum Message<'a> {
+    /// The client's state vector; answered with what it is missing
+    SyncStep1(&'a [u8]),
+    /// What the client has that the server's state vector lacked
+    SyncStep2(&'a [u8]),
+    Update(&'a [u8]),
+    Awareness(&'a [u8]),
+    QueryAwareness,
+    /// Auth and custom messages, which the server ignores
+    Other(u64),
+}
+
+impl<'a> Message<'a> {
+    pub fn decode(data: &'a [u8]) -> Option<Self> {
+        let mut reader = Reader::new(data);
+        let message = match reader.var_uint()? {
+            MESSAGE_SYNC => match reader.var_uint()? {
+                SYNC_STEP1 => Message::SyncStep1(reader.var_bytes()?),
+                SYNC_STEP2 => Message::SyncStep2(reader.var_bytes()?),
+                SYNC_UPDATE => Message::Update(reader.var_bytes()?),
+                _ => return None,
+            },
+            MESSAGE_AWARENESS => Message::Awareness(reader.var_bytes()?),
+            MESSAGE_QUERY_AWARENESS => Message::QueryAwareness,
+            other => Message::Other(other),
+        };
+        Some(message)
+    }
+}
+
+fn encode_sync(kind: u64, payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(payload.len() + 8);
+    write_var_uint(&mut out, MESSAGE_SYNC);
+    write_var_uint(&mut out, kind);
+    write_var_bytes(&mut out, payload);
+    out
+}
+
+/// One client's entry in an awareness update; `state` is None once the
+/// client went away.
+struct AwarenessEntry {
+    client_id: u64,
+    clock: u64,
+    state: Option<Value>,
+}
+
+fn decode_awareness(update: &[u8]) -> Option<Vec<AwarenessEntry>> {
+    let mut reader = Reader::new(update);
+    let count = reader.var_uint()?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let client_id = reader.var_uint()?;
+        let clock = reader.var_uint()?;
+        let state: Value = serde_json::from_slice(reader.var_bytes()?).ok()?;
+        entries.push(AwarenessEntry {
+            client_id,
+            clock,
+            state: (!state.is_null()).then_some(state),
+        });
+    }
+    Some(entries)
+}
+
+fn encode_awareness_update(entries: &[AwarenessEntry]) -> Vec<u8> {
+    let mut update = Vec::new();
+    write_var_uint(&mut update, entries.len() as u64);
+    for entry in entries {
+        write_var_uint(&mut update, entry.client_id);
+        write_var_uint(&mut update, entry.clock);
+        let state = entry.state.as_ref().unwrap_or(&Value::Null).to_string();
+        write_var_bytes(&mut update, state.as_bytes());
+    }
+    encode_awareness(&update)
+}
+
+/// The session message sent on connect.
+pub fn encode_session(token: &str, resumed: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(token.len() + 8);
+    write_var_uint(&mut out, MESSAGE_SESSION);
+    write_var_bytes(&mut out, token.as_bytes());
+    write_var_uint(&mut out, u64::from(resumed));
+    out
+}
+
+/// Wrap a message for a socket carrying several documents, prefixing it with
+/// the file path as a lib0 string.
+pub fn encode_envelope(file_path: &str, message: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(file_path.len() + message.len() + 4);
+    write_var_bytes(&mut out, file_path.as_bytes());
+    out.extend_from_slice(message);
+    out
+}
+
+/// Split an enveloped message into its file path and the message itself.
+pub fn decode_envelope(data: &[u8]) -> Option<(&str, &[u8])> {
+    let mut reader = Reader::new(data);
+    let file_path = std::str::from_utf8(reader.var_bytes()?).ok()?;
+    Some((file_path, &data[reader.pos..]))
+}
+
+fn encode_awareness(update: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(update.len() + 8);
+    write_var_uint(&mut out, MESSAGE_AWARENESS);
+    write_var_bytes(&mut out, update);
+    out
+}
+
+// lib0 variable-length unsigned integer: 7 bits per byte, low bits first
+fn write_var_uint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_var_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    write_var_uint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn var_uint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = *self.data.get(self.pos)?;
+            self.pos += 1;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn var_bytes(&mut self) -> Option<&'a [u8]> {
+        let len = usize::try_from(self.var_uint()?).ok()?;
+        let end = self.pos.checked_add(len)?;
+        let bytes = self.data.get(self.pos..end)?;
+        self.pos = end;
+        Some(bytes)
+    }
+}
+
+/// A message for the clients of a room, except the connection it came from.
+#[derive(Debug, Clone)]
+pub struct RoomEvent {
+    pub from: u64,
+    pub message: Arc<Vec<u8>>,
+}
+
+/// The authenticated user behind a connection.
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub user_id: String,
+    pub name: String,
+}
+
+struct Connection {
+    peer: Peer,
+    // Awareness states by Yjs client ID; one per open editor of the file
+    clients: HashMap<u64, (u64, Value)>,
+    last_active: String,
+}
+
+/// Someone editing a file, as shown in the online users list.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Presence {
+    pub user_id: String,
+    pub name: String,
+    /// Assigned by the server, so every client shows the same one
+    pub color: String,
+    pub avatar_url: String,
+    pub file_path: String,
+    /// The editor's cursor and selection as published by the client
+    pub cursor: Option<Value>,
+    pub selection: Option<Value>,
+    pub last_active: String,
+}
+
+/// One open file: its document and the clients editing it.
+pub struct Room {
+    pub project_id: String,
+    pub file_path: String,
+    file_id: String,
+    // yrs allows one mutable transaction at a time, so all access goes
+    // through this lock
+    doc: Mutex<Doc>,
+    events: broadcast::Sender<RoomEvent>,
+    connections: Mutex<HashMap<u64, Connection>>,
+    // When the last connection left, for idle eviction
+    emptied_at: Mutex<Instant>,
+    // Set by every applied update, cleared when the content is written back
+    dirty: AtomicBool,
+    // Keeps an older snapshot from being written over a newer one
+    persisting: tokio::sync::Mutex<()>,
+    history: mpsc::UnboundedSender<Record>,
+    // Updates recorded since the last history snapshot
+    since_snapshot: AtomicU64,
+    // Set while the project is frozen; edits are refused
+    read_only: AtomicBool,
+    // Set once the room is dropped because its file was replaced; the
+    // document is stale, so it is neither edited nor saved again
+    closed: AtomicBool,
+}
+
+impl Room {
+    fn new(
+        project_id: &str,
+        file_path: &str,
+        file_id: String,
+        content: &str,
+        history: mpsc::UnboundedSender<Record>,
+        read_only: bool,
+    ) -> Self {
+        let doc = Doc::new();
+        {
+            let text = doc.get_or_insert_text(TEXT_NAME);
+            let mut txn = doc.transact_mut();
+            text.insert(&mut txn, 0, content);
+        }
+
+        let (events, _) = broadcast::channel(BROADCAST_CAPACITY);
+        Self {
+            project_id: project_id.to_string(),
+            file_path: file_path.to_string(),
+            file_id,
+            doc: Mutex::new(doc),
+            events,
+            connections: Mutex::new(HashMap::new()),
+            emptied_at: Mutex::new(Instant::now()),
+            dirty: AtomicBool::new(false),
+            persisting: tokio::sync::Mutex::new(()),
+            history,
+            // The first update is preceded by a snapshot of what was loaded
+            since_snapshot: AtomicU64::new(history::SNAPSHOT_INTERVAL),
+            read_only: AtomicBool::new(read_only),
+            closed: AtomicBool::new(false),
+        }
+    }
+
+    fn doc(&self) -> std::sync::MutexGuard<'_, Doc> {
+        self.doc.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The current content of the file.
+    pub fn text(&self) -> String {
+        let doc = self.doc();
+        let text = doc.get_or_insert_text(TEXT_NAME);
+        let txn = doc.transact();
+        text.get_string(&txn)
+    }
+
+    fn connections(&self) -> std::sync::MutexGuard<'_, HashMap<u64, Connection>> {
+        self.connections.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RoomEvent> {
+        self.events.subscribe()
+    }
+
+    fn broadcast(&self, from: u64, message: Vec<u8>) {
+        // Nobody listening is fine
+        let _ = self.events.send(RoomEvent {
+            from,
+            message: Arc::new(message),
+        });
+    }
+
+    /// Sync step 1 with the server's state vector, sent to every client on
+    /// connect so it replies with whatever the server is missing.
+    pub fn sync_step1(&self) -> Vec<u8> {
+        encode_sync(SYNC_STEP1, &self.state_vector().encode_v1())
+    }
+
+    fn state_vector(&self) -> StateVector {
+        self.doc().transact().state_vector()
+    }
+
+    /// The whole document as an update, for clients that missed some
+    /// broadcasts; applying what they already have is a no-op.
+    pub fn full_update(&self) -> Vec<u8> {
+        let update = self
+            .doc()
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        encode_sync(SYNC_UPDATE, &update)
+    }
+
+    /// Sync step 2 with everything that happened after `state_vector`.
+    fn sync_step2(&self, state_vector: &StateVector) -> Vec<u8> {
+        let diff = self.doc().transact().encode_diff_v1(state_vector);
+        encode_sync(SYNC_STEP2, &diff)
+    }
+
+    /// Handle a message from connection `from`, returning the reply for that
+    /// connection, if any. Document updates are applied and passed on to the
+    /// rest of the room.
+    pub fn handle(&self, from: u64, message: Message<'_>) -> Result<Option<Vec<u8>>> {
+        match message {
+            Message::SyncStep1(state_vector) => {
+                let state_vector = StateVector::decode_v1(state_vector)
+                    .map_err(|e| AppError::BadRequest(format!("Invalid state vector: {e}")))?;
+                Ok(Some(self.sync_step2(&state_vector)))
+            }
+            Message::SyncStep2(update) | Message::Update(update) => {
+                let user_id = self
+                    .connections()
+                    .get(&from)
+                    .map(|connection| connection.peer.user_id.clone());
+                self.apply_update(user_id.as_deref(), update)?;
+                self.broadcast(from, encode_sync(SYNC_UPDATE, update));
+                Ok(None)
+            }
+            Message::Awareness(update) => {
+                let entries = decode_awareness(update)
+                    .ok_or_else(|| AppError::BadRequest("Invalid awareness update".to_string()))?;
+                let accepted = self.update_awareness(from, entries);
+                if !accepted.is_empty() {
+                    self.broadcast(from, encode_awareness_update(&accepted));
+                }
+                Ok(None)
+            }
+            Message::QueryAwareness => Ok(self.awareness()),
+            Message::Other(_) => Ok(None),
+        }
+    }
+
+    /// Record a connection's awareness changes, returning the ones to relay.
+    /// Outdated entries and entries for Yjs clients owned by another
+    /// connection are dropped.
+    fn update_awareness(&self, from: u64, entries: Vec<AwarenessEntry>) -> Vec<AwarenessEntry> {
+        let mut connections = self.connections();
+        let owned_elsewhere = |client_id: u64| {
+            connections
+                .iter()
+                .any(|(id, conn)| *id != from && conn.clients.contains_key(&client_id))
+        };
+        let accepted: Vec<AwarenessEntry> = entries
+            .into_iter()
+            .filter(|entry| !owned_elsewhere(entry.client_id))
+            .collect();
+
+        let Some(connection) = connections.get_mut(&from) else {
+            return Vec::new();
+        };
+        let accepted: Vec<AwarenessEntry> = accepted
+            .into_iter()
+            .filter(|entry| {
+                let known = connection.clients.get(&entry.client_id);
+                known.is_none_or(|(clock, _)| entry.clock > *clock)
+            })
+            .collect();
+
+        for entry in &accepted {
+            match &entry.state {
+                Some(state) => {
+                    connection
+                        .clients
+                        .insert(entry.client_id, (entry.clock, state.clone()));
+                }
+                None => {
+                    connection.clients.remove(&entry.client_id);
+                }
+            }
+        }
+        if !accepted.is_empty() {
+            connection.last_active = Utc::now().to_rfc3339();
+        }
+        accepted
+    }
+
+    /// Every known awareness state, for clients that just joined or asked.
+    pub fn awareness(&self) -> Option<Vec<u8>> {
+        let connections = self.connections();
+        let entries: Vec<AwarenessEntry> = connections
+            .values()
+            .flat_map(|conn| conn.clients.iter())
+            .map(|(client_id, (clock, state))| AwarenessEntry {
+                client_id: *client_id,
+                clock: *clock,
+                state: Some(state.clone()),
+            })
+            .collect();
+        (!entries.is_empty()).then(|| encode_awareness_update(&entries))
+    }
+
+    /// Who is connected, one entry per open editor.
+    pub fn presence(&self) -> Vec<Presence> {
+        let connections = self.connections();
+        let mut presence = Vec::new();
+        for conn in connections.values() {
+            let entry = |state: Option<&Value>| {
+                let field = |name: &str| state.and_then(|s| s.get(name)).cloned();
+                Presence {
+                    user_id: conn.peer.user_id.clone(),
+                    name: conn.peer.name.clone(),
+                    color: identicon::color(&conn.peer.user_id).to_string(),
+                    avatar_url: identicon::url(&conn.peer.user_id),
+                    file_path: self.file_path.clone(),
+                    cursor: field("cursor"),
+                    selection: field("selection"),
+                    last_active: conn.last_active.clone(),
+                }
+            };
+            if conn.clients.is_empty() {
+                // Connected but not publishing awareness yet
+                presence.push(entry(None));
+            } else {
+                presence.extend(conn.clients.values().map(|(_, state)| entry(Some(state))));
+            }
+        }
+        presence
+    }
+
+    fn apply_update(&self, user_id: Option<&str>, data: &[u8]) -> Result<()> {
+        let update = Update::decode_v1(data)
+            .map_err(|e| AppError::BadRequest(format!("Invalid update: {e}")))?;
+        if update.is_empty() {
+            return Ok(());
+        }
+        if self.read_only.load(Ordering::Acquire) {
+            return Err(freeze::frozen_error());
+        }
+        if self.closed.load(Ordering::Acquire) {
+            return Err(closed_error());
+        }
+        let doc = self.doc();
+        self.snapshot_if_due(&doc);
+        {
+            let mut txn = doc.transact_mut();
+            txn.apply_update(update);
+        }
+        self.record(user_id, history::UPDATE, data.to_vec());
+        self.dirty.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    /// Record the whole document ahead of the next update once enough
+    /// updates piled up since the last snapshot. Called with the document
+    /// locked, like [`Room::record`], so history is sent in the order the
+    /// document changed.
+    fn snapshot_if_due(&self, doc: &Doc) {
+        if self.since_snapshot.load(Ordering::Relaxed) < history::SNAPSHOT_INTERVAL {
+            return;
+        }
+        let state = doc
+            .transact()
+            .encode_state_as_update_v1(&StateVector::default());
+        self.since_snapshot.store(0, Ordering::Relaxed);
+        self.record(None, history::SNAPSHOT, state);
+    }
+
+    fn record(&self, user_id: Option<&str>, kind: &'static str, data: Vec<u8>) {
+        if kind == history::UPDATE {
+            self.since_snapshot.fetch_add(1, Ordering::Relaxed);
+        }
+        // The writer only stops at shutdown
+        let _ = self
+            .history
+            .send(Record::new(&self.file_id, user_id, kind, data));
+    }
+
+    /// Replace part of the text on the server's behalf and pass the change on
+    /// to the room; history credits it to `author`. `edit` gets the current
+    /// text and returns the byte range to replace and what to put there.
+    fn edit<F>(&self, author: &str, edit: F) -> Result<()>
+    where
+        F: FnOnce(&str) -> Result<(Range<usize>, String)>,
+    {
+        if self.read_only.load(Ordering::Acquire) {
+            return Err(freeze::frozen_error());
+        }
+        if self.closed.load(Ordering::Acquire) {
+            return Err(closed_error());
+        }
+        let update = {
+            let doc = self.doc();
+            self.snapshot_if_due(&doc);
+            let text = doc.get_or_insert_text(TEXT_NAME);
+            let (before, current) = {
+                let txn = doc.transact();
+                (txn.state_vector(), text.get_string(&txn))
+            };
+            let (range, content) = edit(&current)?;
+            {
+                let mut txn = doc.transact_mut();
+                if !range.is_empty() {
+                    text.remove_range(&mut txn, range.start as u32, range.len() as u32);
+                }
+                if !content.is_empty() {
+                    text.insert(&mut txn, range.start as u32, &content);
+                }
+            }
+            let update = doc.transact().encode_diff_v1(&before);
+            self.record(Some(author), history::UPDATE, update.clone());
+            update
+        };
+        self.dirty.store(true, Ordering::Release);
+        self.broadcast(SERVER, encode_sync(SYNC_UPDATE, &update));
+        Ok(())
+    }
+}
+
+/// Open rooms and connections, for the metrics endpoint.
+#[derive(Debug, Clone, Copy)]
+pub struct CollabStats {
+    pub rooms: usize,
+    pub connections: usize,
+    pub evicted: u64,
+}
+
+/// What a dropped connection had seen, kept for the resume window.
+struct Session {
+    user_id: String,
+    // The exact room instance; a room reopened from storage has a new
+    // document history the old state vector means nothing to
+    room: Weak<Room>,
+    state_vector: StateVector,
+    expires: Instant,
+}
+
+/// Open rooms keyed by "project_id:file_path".
+#[derive(Clone)]
 pub struct CollabService {
-    documents: Arc<RwLock<HashMap<String, Arc<Doc>>>>,
+    db: Database,
+    storage: StorageService,
+    rooms: Arc<RwLock<HashMap<String, Arc<Room>>>>,
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+    resume_window: Duration,
+    next_connection: Arc<AtomicU64>,
+    evicted: Arc<AtomicU64>,
+    history: mpsc::UnboundedSender<Record>,
 }
 
-#[allow(dead_code)]
 impl CollabService {
-    pub fn new() -> Self {
+    /// Create the service along with the task recording document history.
+    pub fn new(db: Database, storage: StorageService, resume_window: Duration) -> Self {
         Self {
-            documents: Arc::new(RwLock::new(HashMap::new())),
+            history: history::spawn_writer(db.clone()),
+            db,
+            storage,
+            rooms: Arc::default(),
+            sessions: Arc::default(),
+            resume_window,
+            next_connection: Arc::default(),
+            evicted: Arc::default(),
+        }
+    }
+
+    /// A process-wide unique ID for a new connection.
+    pub fn connection_id(&self) -> u64 {
+        self.next_connection.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn sessions(&self) -> std::sync::MutexGuard<'_, HashMap<String, Session>> {
+        self.sessions.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// A new resume token for a connection.
+    pub fn session_token(&self) -> String {
+        Uuid::new_v4().to_string()
+    }
+
+    /// Remember where a closing connection left off, so a reconnect within
+    /// the resume window only needs what changed since.
+    pub fn suspend(&self, token: String, user_id: &str, room: &Arc<Room>) {
+        let session = Session {
+            user_id: user_id.to_string(),
+            room: Arc::downgrade(room),
+            state_vector: room.state_vector(),
+            expires: Instant::now() + self.resume_window,
+        };
+
+        let mut sessions = self.sessions();
+        let now = Instant::now();
+        sessions.retain(|_, session| session.expires > now);
+        sessions.insert(token, session);
+    }
+
+    /// Pick up a suspended session in `room`, returning the sync step 2 with
+    /// what the client missed. None when the token is unknown, expired, not
+    /// the user's, or from an earlier instance of the room; the client then
+    /// needs to start over with a fresh document.
+    pub fn resume(&self, token: &str, user_id: &str, room: &Arc<Room>) -> Option<Vec<u8>> {
+        let session = self.sessions().remove(token)?;
+        let same_room = session
+            .room
+            .upgrade()
+            .is_some_and(|previous| Arc::ptr_eq(&previous, room));
+        if session.expires <= Instant::now() || session.user_id != user_id || !same_room {
+            return None;
         }
+        Some(room.sync_step2(&session.state_vector))
     }
 
-    pub async fn get_or_create_doc(&self, project_id: &str, file_path: &str) -> Arc<Doc> {
+    /// Join the room of a file, opening it from storage if nobody has it
+    /// open yet. Pair with [`CollabService::leave`].
+    pub async fn join(
+        &self,
+        project_id: &str,
+        file_path: &str,
+        connection: u64,
+        peer: Peer,
+    ) -> Result<Arc<Room>> {
         let key = format!("{project_id}:{file_path}");
+        loop {
+            {
+                // Registering under the read lock keeps eviction, which takes
+                // the write lock, from dropping the room in between
+                let rooms = self.rooms.read().await;
+                if let Some(room) = rooms.get(&key) {
+                    room.connections().insert(
+                        connection,
+                        Connection {
+                            peer,
+                            clients: HashMap::new(),
+                            last_active: Utc::now().to_rfc3339(),
+                        },
+                    );
+                    return Ok(Arc::clone(room));
+                }
+            }
+            self.open(&key, project_id, file_path).await?;
+        }
+    }
 
-        {
-            let docs = self.documents.read().await;
-            if let Some(doc) = docs.get(&key) {
-                return Arc::clone(doc);
+    /// The room of a file without joining it, opening it if needed.
+    async fn room(&self, project_id: &str, file_path: &str) -> Result<Arc<Room>> {
+        let key = format!("{project_id}:{file_path}");
+        loop {
+            if let Some(room) = self.rooms.read().await.get(&key) {
+                return Ok(Arc::clone(room));
             }
+            self.open(&key, project_id, file_path).await?;
         }
+    }
 
-        let mut docs = self.documents.write().await;
-        let doc = Arc::new(Doc::new());
-        docs.insert(key, Arc::clone(&doc));
-        doc
+    /// The current content of a file: its open document if someone has it
+    /// open, otherwise what is in storage.
+    pub async fn content(&self, project_id: &str, file_path: &str) -> Result<String> {
+        let key = format!("{project_id}:{file_path}");
+        if let Some(room) = self.rooms.read().await.get(&key) {
+            return Ok(room.text());
+        }
+        self.storage.read_file(project_id, file_path).await
     }
 
-    pub async fn get_text(&self, project_id: &str, file_path: &str) -> String {
-        let doc = self.get_or_create_doc(project_id, file_path).await;
-        let text = doc.get_or_insert_text("content");
-        let result = text.get_string(&doc.transact());
-        result
+    /// Change a file through its document, so clients editing it get the
+    /// change like any other update, and save it right away. See
+    /// [`Room::edit`].
+    pub async fn edit<F>(
+        &self,
+        project_id: &str,
+        file_path: &str,
+        author: &str,
+        edit: F,
+    ) -> Result<()>
+    where
+        F: FnOnce(&str) -> Result<(Range<usize>, String)>,
+    {
+        let room = self.room(project_id, file_path).await?;
+        self.apply(&room, author, edit).await
     }
 
-    pub async fn remove_doc(&self, project_id: &str, file_path: &str) {
+    /// Replace the whole content of a file if someone has it open, so the
+    /// document does not later overwrite what is written to storage. Returns
+    /// false when the file is not open and storage can be written directly.
+    pub async fn replace_open(
+        &self,
+        project_id: &str,
+        file_path: &str,
+        author: &str,
+        content: &str,
+    ) -> Result<bool> {
         let key = format!("{project_id}:{file_path}");
-        let mut docs = self.documents.write().await;
-        docs.remove(&key);
+        let Some(room) = self.rooms.read().await.get(&key).cloned() else {
+            return Ok(false);
+        };
+        self.apply(&room, author, |text| {
+            Ok((0..text.len(), content.to_string()))
+        })
+        .await?;
+        Ok(true)
+    }
+
+    async fn apply<F>(&self, room: &Room, author: &str, edit: F) -> Result<()>
+    where
+        F: FnOnce(&str) -> Result<(Range<usize>, String)>,
+    {
+        room.edit(author, edit)?;
+        // The edit is in the document either way; a failed save is retried
+        // by the scheduler
+        if let Err(e) = self.persist(room).await {
+            tracing::warn!(
+                "Failed to save {} in project {}: {}",
+                room.file_path,
+                room.project_id,
+                e
+            );
+        }
+        Ok(())
+    }
+
+    /// Leave a room, telling the others that the connection's editors went
+    /// away and writing the document back once its last client is gone.
+    pub async fn leave(&self, room: &Room, connection: u64) {
+        let (removed, empty) = {
+            let mut connections = room.connections();
+            let removed = connections.remove(&connection);
+            if connections.is_empty() {
+                *room.emptied_at.lock().unwrap_or_else(|e| e.into_inner()) = Instant::now();
+            }
+            (removed, connections.is_empty())
+        };
+
+        if let Some(removed) = removed {
+            let entries: Vec<AwarenessEntry> = removed
+                .clients
+                .into_iter()
+                .map(|(client_id, (clock, _))| AwarenessEntry {
+                    client_id,
+                    clock: clock + 1,
+                    state: None,
+                })
+                .collect();
+            if !entries.is_empty() {
+                room.broadcast(connection, encode_awareness_update(&entries));
+            }
+        }
+
+        if empty {
+            if let Err(e) = self.persist(room).await {
+                tracing::warn!(
+                    "Failed to save {} in project {}: {}",
+                    room.file_path,
+                    room.project_id,
+                    e
+                );
+            }
+        }
+    }
+
+    async fn open(&self, key: &str, project_id: &str, file_path: &str) -> Result<()> {
+        // Load outside the lock so a slow read does not hold up other rooms
+        let file_id = sqlx::query_scalar::<_, String>(
+            "SELECT id FROM files WHERE project_id = $1 AND path = $2 AND is_folder = FALSE",
+        )
+        .bind(project_id)
+        .bind(file_path)
+        .fetch_optional(&self.db.pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("File not found".to_string()))?;
+        let content = self.storage.read_file(project_id, file_path).await?;
+        let read_only = freeze::frozen_at(&self.db.pool, project_id)
+            .await?
+            .is_some();
+
+        // Another connection may have opened the room meanwhile; keep theirs
+        let mut rooms = self.rooms.write().await;
+        rooms.entry(key.to_string()).or_insert_with(|| {
+            Arc::new(Room::new(
+                project_id,
+                file_path,
+                file_id,
+                &content,
+                self.history.clone(),
+                read_only,
+            ))
+        });
+        Ok(())
+    }
+
+    /// Write a room's document back to its file if it changed since the last
+    /// save. Returns whether anything was written.
+    pub async fn persist(&self, room: &Room) -> Result<bool> {
+        let _guard = room.persisting.lock().await;
+        if room.closed.load(Ordering::Acquire) || !room.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(false);
+        }
+
+        let content = room.text();
+        if let Err(e) = self.write(room, &content).await {
+            // Try again on the next save
+            room.dirty.store(true, Ordering::Release);
+            return Err(e);
+        }
+        Ok(true)
+    }
+
+    async fn write(&self, room: &Room, content: &str) -> Result<()> {
+        let hash = self
+            .storage
+            .write_file(&room.project_id, &room.file_path, content)
+            .await?;
+        let file_type = filetype::detect(&room.file_path, content.as_bytes());
+
+        sqlx::query(
+            "UPDATE files SET hash = $1, mime_type = $2, language = $3, updated_at = $4 WHERE id = $5",
+        )
+        .bind(&hash)
+        .bind(file_type.mime_type)
+        .bind(file_type.language)
+        .bind(Utc::now().to_rfc3339())
+        .bind(&room.file_id)
+        .execute(&self.db.pool)
+        .await?;
+
+        // A search catches up on files that failed to index
+        if let Err(e) = search::index_file(
+            &self.db.pool,
+            &room.project_id,
+            &room.file_id,
+            &room.file_path,
+            &hash,
+            content.as_bytes(),
+        )
+        .await
+        {
+            tracing::warn!("Failed to index {} for search: {}", room.file_path, e);
+        }
+
+        Ok(())
+    }
+
+    /// Drop rooms nobody has been connected to for `ttl`, saving them first.
+    /// Rooms that fail to save stay open so their edits are not lost.
+    pub async fn evict_idle(&self, ttl: Duration) -> usize {
+        let idle = |room: &Room| {
+            room.connections().is_empty()
+                && room
+                    .emptied_at
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .elapsed()
+                    >= ttl
+        };
+
+        let candidates: Vec<(String, Arc<Room>)> = self
+            .rooms
+            .read()
+            .await
+            .iter()
+            .filter(|(_, room)| idle(room))
+            .map(|(key, room)| (key.clone(), Arc::clone(room)))
+            .collect();
+        if candidates.is_empty() {
+            return 0;
+        }
+
+        let mut saved = Vec::new();
+        for (key, room) in candidates {
+            match self.persist(&room).await {
+                Ok(_) => saved.push(key),
+                Err(e) => tracing::warn!(
+                    "Failed to save {} in project {}: {}",
+                    room.file_path,
+                    room.project_id,
+                    e
+                ),
+            }
+        }
+
+        // Someone may have rejoined or edited while saving
+        let mut rooms = self.rooms.write().await;
+        let mut evicted = 0;
+        for key in saved {
+            if rooms
+                .get(&key)
+                .is_some_and(|room| idle(room) && !room.is_dirty())
+            {
+                rooms.remove(&key);
+                evicted += 1;
+            }
+        }
+        self.evicted.fetch_add(evicted as u64, Ordering::Relaxed);
+        evicted
     }
-}
 
-impl Default for CollabService {
-    fn default() -> Self {
-        Self::new()
+    pub async fn stats(&self) -> CollabStats {
+        let rooms = self.rooms.read().await;
+        CollabStats {
+            rooms: rooms.len(),
+            connections: rooms.values().map(|room| room.connections().len()).sum(),
+            evicted: self.evicted.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Everyone editing a file of the project.
+    pub async fn presence(&self, project_id: &str) -> Vec<Presence> {
+        let rooms = self.rooms.read().await;
+        let mut presence: Vec<Presence> = rooms
+            .values()
+            .filter(|room| room.project_id == project_id)
+            .flat_map(|room| room.presence())
+            .collect();
+        presence.sort_by(|a, b| a.name.cmp(&b.name).then(a.file_path.cmp(&b.file_path)));
+        presence
+    }
+
+    /// Refuse or allow edits to the project's open documents, as it is
+    /// frozen or unfrozen.
+    pub async fn set_read_only(&self, project_id: &str, read_only: bool) {
+        let rooms = self.rooms.read().await;
+        for room in rooms.values().filter(|room| room.project_id == project_id) {
+            room.read_only.store(read_only, Ordering::Release);
+        }
+    }
+
+    /// Save the open documents of a project, so storage has everything that
+    /// was typed up to now.
+    pub async fn persist_project(&self, project_id: &str) -> Result<()> {
+        let rooms: Vec<Arc<Room>> = self
+            .rooms
+            .read()
+            .await
+            .values()
+            .filter(|room| room.project_id == project_id)
+            .cloned()
+            .collect();
+        for room in rooms {
+            self.persist(&room).await?;
+        }
+        Ok(())
+    }
+
+    /// Drop the open documents of a project whose files are about to be
+    /// replaced, such as by a backup restore, once any save in progress is
+    /// done. Their clients can no longer edit them and have to open the files
+    /// again.
+    pub async fn close_project(&self, project_id: &str) {
+        let mut closed = Vec::new();
+        self.rooms.write().await.retain(|_, room| {
+            if room.project_id != project_id {
+                return true;
+            }
+            room.closed.store(true, Ordering::Release);
+            closed.push(Arc::clone(room));
+            false
+        });
+        for room in closed {
+            drop(room.persisting.lock().await);
+        }
     }
+
+    /// Save every room with unsaved changes.
+    pub async fn persist_all(&self) -> usize {
+        let rooms: Vec<Arc<Room>> = self.rooms.read().await.values().cloned().collect();
+
+        let mut saved = 0;
+        for room in rooms.iter().filter(|room| room.is_dirty()) {
+            match self.persist(room).await {
+                Ok(true) => saved += 1,
+                Ok(false) => {}
+                Err(e) => tracing::warn!(
+                    "Failed to save {} in project {}: {}",
+                    room.file_path,
+                    room.project_id,
+                    e
+                ),
+            }
+        }
+        saved
+    }
+}
+
+fn closed_error() -> AppError {
+    AppError::Conflict("This file was replaced; open it again to edit it".to_string())
+}
+
+/// Save edited documents every `interval`, so a crash loses at most that
+/// much, and close rooms that have been empty for `room_ttl`.
+pub fn spawn_scheduler(collab: CollabService, interval: Duration, room_ttl: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            let saved = collab.persist_all().await;
+            if saved > 0 {
+                tracing::debug!("Saved {} collaborative documents", saved);
+            }
+            let evicted = collab.evict_idle(room_ttl).await;
+            if evicted > 0 {
+                tracing::debug!("Closed {} idle collaboration rooms", evicted);
+            }
+        }
+    });
 }
@@ -0,0 +1,414 @@
+// Compile history
+// Records every compile run and keeps the PDFs of the most recent ones so
+// output can be compared before and after a change. Each run's errors and
+// warnings are kept too, so a new run can report which it fixed.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use crate::{
+    config::CompileConfig,
+    db::DbPool,
+    error::{AppError, Result},
+    services::{
+        compile_jobs::{CompileJob, JobStatus},
+        compiler::{CompileError, CompileOptions, CompileResult, CompileWarning},
+    },
+};
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct CompileRecord {
+    pub id: String,
+    pub user_id: String,
+    pub user_name: Option<String>,
+    pub status: String,
+    pub engine: String,
+    pub main_file: String,
+    pub started_at: String,
+    pub finished_at: String,
+    /// Time spent compiling, not counting the wait for a slot
+    pub duration_ms: i64,
+    pub queue_ms: i64,
+    pub success: bool,
+    pub error_count: i64,
+    pub warning_count: i64,
+    /// Whether the run's PDF is still kept
+    pub has_pdf: bool,
+}
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct CompileRecordDetail {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub record: CompileRecord,
+    pub error: Option<String>,
+    pub log: String,
+}
+
+/// How a run's errors and warnings differ from the previous run of the same
+/// main file. Diagnostics are matched by file and message, numbers aside,
+/// since the lines and pages they mention move as the document is edited.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DiagnosticsDelta {
+    pub previous_compile_id: String,
+    pub new_errors: Vec<CompileError>,
+    /// As they were reported by the previous run
+    pub resolved_errors: Vec<CompileError>,
+    pub persisting_errors: Vec<CompileError>,
+    pub new_warnings: Vec<CompileWarning>,
+    pub resolved_warnings: Vec<CompileWarning>,
+    pub persisting_warnings: Vec<CompileWarning>,
+}
+
+// What is stored of a run's diagnostics
+#[derive(Serialize, Deserialize)]
+struct Diagnostics {
+    errors: Vec<CompileError>,
+    warnings: Vec<CompileWarning>,
+}
+
+const RECORD_COLUMNS: &str = "c.id, c.user_id, u.name AS user_name, c.status, c.engine, \
+     c.main_file, c.started_at, c.finished_at, c.duration_ms, c.queue_ms, c.success, \
+     c.error_count, c.warning_count, c.has_pdf";
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DurationStats {
+    pub avg_ms: i64,
+    pub p50_ms: i64,
+    pub p95_ms: i64,
+    pub max_ms: i64,
+}
+
+impl DurationStats {
+    fn from_samples(mut samples: Vec<i64>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let percentile = |p: usize| samples[(samples.len() - 1) * p / 100];
+        Some(Self {
+            avg_ms: samples.iter().sum::<i64>() / samples.len() as i64,
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            max_ms: samples[samples.len() - 1],
+        })
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CompileStats {
+    /// Runs in the window that are still in the history
+    pub total: i64,
+    /// Produced a PDF without errors
+    pub succeeded: i64,
+    /// Ran but had errors or no PDF
+    pub failed: i64,
+    pub timed_out: i64,
+    pub cancelled: i64,
+    /// Could not be run at all
+    pub errored: i64,
+    /// Share of runs that succeeded, `None` without runs
+    pub success_rate: Option<f64>,
+    /// Of runs that got to compile
+    pub duration: Option<DurationStats>,
+    pub queue_wait: Option<DurationStats>,
+}
+
+/// Where a run's PDF is kept.
+pub fn pdf_path(cache_root: &Path, project_id: &str, compile_id: &str) -> PathBuf {
+    cache_root
+        .join("history")
+        .join(project_id)
+        .join(format!("{compile_id}.pdf"))
+}
+
+/// Store a finished job, copying its PDF out of the build directory.
+pub async fn record(
+    pool: &DbPool,
+    cache_root: &Path,
+    config: &CompileConfig,
+    job: &CompileJob,
+    options: &CompileOptions,
+    outcome: &Result<CompileResult>,
+    build_dir: &Path,
+) -> Result<()> {
+    let info = job.info();
+    let finished_at = info
+        .finished_at
+        .clone()
+        .unwrap_or_else(|| Utc::now().to_rfc3339());
+    let (queue, run) = job.timings();
+
+    let (success, engine, error_count, warning_count, log, pdf) = match outcome {
+        Ok(result) => (
+            result.success && info.status == JobStatus::Finished,
+            result.engine,
+            result.errors.len() as i64,
+            result.warnings.len() as i64,
+            result.log.clone(),
+            result.pdf_path.as_deref().filter(|_| result.success),
+        ),
+        Err(_) => (false, options.engine, 0, 0, job.log(), None),
+    };
+    // Runs that never got to compile have nothing to compare against
+    let diagnostics = match outcome {
+        Ok(result) => serde_json::to_string(&Diagnostics {
+            errors: result.errors.clone(),
+            warnings: result.warnings.clone(),
+        })
+        .ok(),
+        Err(_) => None,
+    };
+
+    let mut has_pdf = false;
+    if let (Some(pdf), true) = (pdf, config.history_pdfs > 0) {
+        let target = pdf_path(cache_root, &job.project_id, &job.id);
+        let copied = async {
+            if let Some(parent) = target.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::copy(build_dir.join(pdf), &target).await
+        };
+        // The run is still worth recording without its PDF
+        match copied.await {
+            Ok(_) => has_pdf = true,
+            Err(e) => tracing::warn!("Failed to keep PDF of compile {}: {}", job.id, e),
+        }
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO compiles (id, project_id, user_id, status, engine, main_file, started_at,
+                              finished_at, duration_ms, queue_ms, success, error_count,
+                              warning_count, error, log, has_pdf, diagnostics)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
+        "#,
+    )
+    .bind(&job.id)
+    .bind(&job.project_id)
+    .bind(&job.user_id)
+    .bind(info.status.as_str())
+    .bind(engine.as_str())
+    .bind(&options.main_file)
+    .bind(&info.started_at)
+    .bind(&finished_at)
+    .bind(run.as_millis() as i64)
+    .bind(queue.as_millis() as i64)
+    .bind(success)
+    .bind(error_count)
+    .bind(warning_count)
+    .bind(&info.error)
+    .bind(&log)
+    .bind(has_pdf)
+    .bind(diagnostics)
+    .execute(pool)
+    .await?;
+
+    prune(pool, cache_root, config, &job.project_id).await
+}
+
+// Drop runs beyond the history limit and PDFs beyond the PDF limit
+async fn prune(
+    pool: &DbPool,
+    cache_root: &Path,
+    config: &CompileConfig,
+    project_id: &str,
+) -> Result<()> {
+    let runs = sqlx::query_as::<_, (String, bool)>(
+        "SELECT id, has_pdf FROM compiles WHERE project_id = $1 ORDER BY started_at DESC",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut pdfs_kept = 0;
+    for (index, (id, has_pdf)) in runs.into_iter().enumerate() {
+        let drop_run = config.history_limit > 0 && index as u64 >= config.history_limit;
+        let drop_pdf = has_pdf && (drop_run || pdfs_kept >= config.history_pdfs);
+        if has_pdf && !drop_pdf {
+            pdfs_kept += 1;
+        }
+
+        if drop_pdf {
+            match tokio::fs::remove_file(pdf_path(cache_root, project_id, &id)).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(AppError::Internal(format!("Failed to delete old PDF: {e}"))),
+            }
+        }
+        if drop_run {
+            sqlx::query("DELETE FROM compiles WHERE id = $1")
+                .bind(&id)
+                .execute(pool)
+                .await?;
+        } else if drop_pdf {
+            sqlx::query("UPDATE compiles SET has_pdf = FALSE WHERE id = $1")
+                .bind(&id)
+                .execute(pool)
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Compare a run's diagnostics with those of the last recorded run of the
+/// same main file. None when there is no earlier run to compare with.
+pub async fn delta(
+    pool: &DbPool,
+    project_id: &str,
+    main_file: &str,
+    result: &CompileResult,
+) -> Result<Option<DiagnosticsDelta>> {
+    let previous = sqlx::query_as::<_, (String, String)>(
+        "SELECT id, diagnostics FROM compiles \
+         WHERE project_id = $1 AND main_file = $2 AND diagnostics IS NOT NULL \
+         ORDER BY started_at DESC LIMIT 1",
+    )
+    .bind(project_id)
+    .bind(main_file)
+    .fetch_optional(pool)
+    .await?;
+    let Some((previous_compile_id, diagnostics)) = previous else {
+        return Ok(None);
+    };
+    let Ok(previous) = serde_json::from_str::<Diagnostics>(&diagnostics) else {
+        return Ok(None);
+    };
+
+    let (new_errors, persisting_errors, resolved_errors) =
+        compare(&previous.errors, &result.errors, |e: &CompileError| {
+            (e.file.clone(), without_numbers(&e.message))
+        });
+    let (new_warnings, persisting_warnings, resolved_warnings) = compare(
+        &previous.warnings,
+        &result.warnings,
+        |w: &CompileWarning| (w.file.clone(), without_numbers(&w.message)),
+    );
+    Ok(Some(DiagnosticsDelta {
+        previous_compile_id,
+        new_errors,
+        resolved_errors,
+        persisting_errors,
+        new_warnings,
+        resolved_warnings,
+        persisting_warnings,
+    }))
+}
+
+// "on input line 12" and "at lines 20--25" stay the same warning when the
+// text moves
+fn without_numbers(message: &str) -> String {
+    let mut key = String::with_capacity(message.len());
+    for c in message.chars() {
+        if c.is_ascii_digit() {
+            if !key.ends_with('#') {
+                key.push('#');
+            }
+        } else {
+            key.push(c);
+        }
+    }
+    key
+}
+
+/// Split diagnostics into new, persisting and resolved ones. Repeats count
+/// separately, so a run that fixes one of two identical errors resolves one.
+fn compare<T: Clone>(
+    previous: &[T],
+    current: &[T],
+    key: impl Fn(&T) -> (String, String),
+) -> (Vec<T>, Vec<T>, Vec<T>) {
+    let mut unmatched: HashMap<(String, String), usize> = HashMap::new();
+    for item in previous {
+        *unmatched.entry(key(item)).or_default() += 1;
+    }
+
+    let (mut new, mut persisting) = (Vec::new(), Vec::new());
+    for item in current {
+        match unmatched.get_mut(&key(item)) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                persisting.push(item.clone());
+            }
+            _ => new.push(item.clone()),
+        }
+    }
+
+    // Whatever the current run did not match was fixed
+    let mut resolved = Vec::new();
+    for item in previous {
+        if let Some(count) = unmatched.get_mut(&key(item)).filter(|count| **count > 0) {
+            *count -= 1;
+            resolved.push(item.clone());
+        }
+    }
+    (new, persisting, resolved)
+}
+
+/// Most recent runs first.
+pub async fn list(pool: &DbPool, project_id: &str, limit: i64) -> Result<Vec<CompileRecord>> {
+    let records = sqlx::query_as::<_, CompileRecord>(&format!(
+        "SELECT {RECORD_COLUMNS} FROM compiles c LEFT JOIN users u ON c.user_id = u.id \
+         WHERE c.project_id = $1 ORDER BY c.started_at DESC LIMIT $2"
+    ))
+    .bind(project_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(records)
+}
+
+/// Outcomes and timings of the runs started in the last `days` days.
+pub async fn stats(pool: &DbPool, project_id: &str, days: i64) -> Result<CompileStats> {
+    let since = (Utc::now() - Duration::days(days)).to_rfc3339();
+    let runs = sqlx::query_as::<_, (String, bool, i64, i64)>(
+        "SELECT status, success, duration_ms, queue_ms FROM compiles \
+         WHERE project_id = $1 AND started_at >= $2",
+    )
+    .bind(project_id)
+    .bind(&since)
+    .fetch_all(pool)
+    .await?;
+
+    let count = |wanted: &str| runs.iter().filter(|(status, ..)| status == wanted).count() as i64;
+    let total = runs.len() as i64;
+    let succeeded = runs.iter().filter(|(_, success, ..)| *success).count() as i64;
+    let finished = count("finished");
+
+    Ok(CompileStats {
+        total,
+        succeeded,
+        failed: finished - succeeded,
+        timed_out: count("timedout"),
+        cancelled: count("cancelled"),
+        errored: count("failed"),
+        success_rate: (total > 0).then(|| succeeded as f64 / total as f64),
+        duration: DurationStats::from_samples(
+            runs.iter()
+                .filter(|(status, ..)| status == "finished" || status == "timedout")
+                .map(|(_, _, duration_ms, _)| *duration_ms)
+                .collect(),
+        ),
+        queue_wait: DurationStats::from_samples(
+            runs.iter().map(|(_, _, _, queue_ms)| *queue_ms).collect(),
+        ),
+    })
+}
+
+pub async fn get(pool: &DbPool, project_id: &str, compile_id: &str) -> Result<CompileRecordDetail> {
+    sqlx::query_as::<_, CompileRecordDetail>(&format!(
+        "SELECT {RECORD_COLUMNS}, c.error, c.log FROM compiles c \
+         LEFT JOIN users u ON c.user_id = u.id WHERE c.project_id = $1 AND c.id = $2"
+    ))
+    .bind(project_id)
+    .bind(compile_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Compile not found".to_string()))
+}
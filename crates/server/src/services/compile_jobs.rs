@@ -0,0 +1,460 @@
+// Compile jobs
+// Tracks running and recently finished compiles so clients can follow their
+// output live and fetch the result after the request that started them, and
+// hands out compile slots so no single user can take all of them
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use serde::Serialize;
+use tokio::sync::{broadcast, oneshot, watch};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, Result},
+    services::compiler::CompileResult,
+};
+
+// How long a finished job stays available for status and log requests
+const FINISHED_JOB_TTL: Duration = Duration::from_secs(3600);
+
+// Lines buffered per subscriber before a slow log stream starts skipping
+const LOG_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Waiting for a free compile slot
+    Queued,
+    Running,
+    /// latexmk ran to completion; the result says whether a PDF came out
+    Finished,
+    /// The compile could not be run at all
+    Failed,
+    Cancelled,
+    /// Killed for exceeding the time limit; the result has the partial log
+    TimedOut,
+}
+
+impl JobStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Finished => "finished",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+            JobStatus::TimedOut => "timedout",
+        }
+    }
+
+    /// Whether the job is still queued or running.
+    pub fn is_active(self) -> bool {
+        matches!(self, JobStatus::Queued | JobStatus::Running)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum LogEvent {
+    Output(String),
+    Done(JobStatus),
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct JobInfo {
+    pub id: String,
+    pub project_id: String,
+    pub status: JobStatus,
+    pub started_at: String,
+    /// When the job got a compile slot
+    pub running_at: Option<String>,
+    pub finished_at: Option<String>,
+    pub error: Option<String>,
+}
+
+struct JobState {
+    log: String,
+    status: JobStatus,
+    running_at: Option<String>,
+    running: Option<Instant>,
+    finished_at: Option<String>,
+    finished: Option<Instant>,
+    error: Option<String>,
+    result: Option<CompileResult>,
+}
+
+pub struct CompileJob {
+    pub id: String,
+    pub project_id: String,
+    pub user_id: String,
+    /// Build target from the project settings, if the compile named one
+    pub target: Option<String>,
+    pub started_at: String,
+    created: Instant,
+    state: Mutex<JobState>,
+    events: broadcast::Sender<LogEvent>,
+    cancel: watch::Sender<bool>,
+}
+
+impl CompileJob {
+    fn new(project_id: &str, user_id: &str, target: Option<&str>) -> Self {
+        let (events, _) = broadcast::channel(LOG_CHANNEL_CAPACITY);
+        Self {
+            id: Uuid::new_v4().to_string(),
+            project_id: project_id.to_string(),
+            user_id: user_id.to_string(),
+            target: target.map(str::to_string),
+            started_at: Utc::now().to_rfc3339(),
+            created: Instant::now(),
+            state: Mutex::new(JobState {
+                log: String::new(),
+                status: JobStatus::Queued,
+                running_at: None,
+                running: None,
+                finished_at: None,
+                finished: None,
+                error: None,
+                result: None,
+            }),
+            events,
+            cancel: watch::Sender::new(false),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, JobState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn append_log(&self, text: &str) {
+        let mut state = self.state();
+        state.log.push_str(text);
+        // Sent under the lock so subscribers never miss or repeat output
+        let _ = self.events.send(LogEvent::Output(text.to_string()));
+    }
+
+    pub fn log(&self) -> String {
+        self.state().log.clone()
+    }
+
+    pub fn status(&self) -> JobStatus {
+        self.state().status
+    }
+
+    /// The output so far plus a receiver for everything after it.
+    pub fn subscribe(&self) -> (String, JobStatus, broadcast::Receiver<LogEvent>) {
+        let state = self.state();
+        (state.log.clone(), state.status, self.events.subscribe())
+    }
+
+    /// Ask the compile to stop; returns false if it already finished.
+    pub fn cancel(&self) -> bool {
+        if !self.status().is_active() {
+            return false;
+        }
+        self.cancel.send_replace(true);
+        true
+    }
+
+    fn set_running(&self) {
+        let mut state = self.state();
+        state.status = JobStatus::Running;
+        state.running_at = Some(Utc::now().to_rfc3339());
+        state.running = Some(Instant::now());
+    }
+
+    /// Time spent waiting for a slot and time spent compiling, so far.
+    pub fn timings(&self) -> (Duration, Duration) {
+        let state = self.state();
+        let end = state.finished.unwrap_or_else(Instant::now);
+        match state.running {
+            Some(running) => (
+                running - self.created,
+                end.saturating_duration_since(running),
+            ),
+            // Cancelled or failed before it got a slot
+            None => (end - self.created, Duration::ZERO),
+        }
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        *self.cancel.borrow()
+    }
+
+    /// Resolves once the job has been cancelled.
+    pub async fn cancelled(&self) {
+        let mut receiver = self.cancel.subscribe();
+        let _ = receiver.wait_for(|cancelled| *cancelled).await;
+    }
+
+    pub fn finish(&self, outcome: &Result<CompileResult>) {
+        let mut state = self.state();
+        match outcome {
+            _ if self.is_cancelled() => state.status = JobStatus::Cancelled,
+            Ok(result) => {
+                state.status = if result.timed_out {
+                    JobStatus::TimedOut
+                } else {
+                    JobStatus::Finished
+                };
+                state.result = Some(result.clone());
+            }
+            Err(e) => {
+                state.status = JobStatus::Failed;
+                state.error = Some(e.to_string());
+            }
+        }
+        state.finished_at = Some(Utc::now().to_rfc3339());
+        state.finished = Some(Instant::now());
+        let _ = self.events.send(LogEvent::Done(state.status));
+    }
+
+    pub fn info(&self) -> JobInfo {
+        let state = self.state();
+        JobInfo {
+            id: self.id.clone(),
+            project_id: self.project_id.clone(),
+            status: state.status,
+            started_at: self.started_at.clone(),
+            running_at: state.running_at.clone(),
+            finished_at: state.finished_at.clone(),
+            error: state.error.clone(),
+        }
+    }
+
+    pub fn result(&self) -> Option<CompileResult> {
+        self.state().result.clone()
+    }
+
+    fn expired(&self) -> bool {
+        self.state()
+            .finished
+            .is_some_and(|finished| finished.elapsed() > FINISHED_JOB_TTL)
+    }
+}
+
+/// How many compiles may run at once; 0 means no limit.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JobLimits {
+    /// Across all users
+    pub max_running: usize,
+    pub max_running_per_user: usize,
+    /// Jobs a user may have waiting on top of the running ones
+    pub max_queued_per_user: usize,
+}
+
+struct Waiter {
+    job_id: String,
+    user_id: String,
+    ready: oneshot::Sender<()>,
+}
+
+#[derive(Default)]
+struct Slots {
+    running: HashMap<String, usize>,
+    total: usize,
+    /// In arrival order
+    waiting: Vec<Waiter>,
+}
+
+impl Slots {
+    fn has_room(&self, limits: &JobLimits, user_id: &str) -> bool {
+        let user_running = self.running.get(user_id).copied().unwrap_or(0);
+        (limits.max_running == 0 || self.total < limits.max_running)
+            && (limits.max_running_per_user == 0 || user_running < limits.max_running_per_user)
+    }
+
+    fn take(&mut self, user_id: &str) {
+        *self.running.entry(user_id.to_string()).or_default() += 1;
+        self.total += 1;
+    }
+
+    fn give_back(&mut self, user_id: &str) {
+        if let Some(count) = self.running.get_mut(user_id) {
+            *count -= 1;
+            if *count == 0 {
+                self.running.remove(user_id);
+            }
+        }
+        self.total = self.total.saturating_sub(1);
+    }
+
+    fn release(&mut self, user_id: &str, limits: &JobLimits) {
+        self.give_back(user_id);
+        self.dispatch(limits);
+    }
+
+    // Start waiting jobs while there is room. Among the users who can run
+    // more, the one with the fewest running jobs goes first, so a user with a
+    // long queue can't keep everyone else waiting behind it.
+    fn dispatch(&mut self, limits: &JobLimits) {
+        loop {
+            let next = self
+                .waiting
+                .iter()
+                .enumerate()
+                .filter(|(_, waiter)| self.has_room(limits, &waiter.user_id))
+                .min_by_key(|(position, waiter)| {
+                    (
+                        self.running.get(&waiter.user_id).copied().unwrap_or(0),
+                        *position,
+                    )
+                })
+                .map(|(position, _)| position);
+            let Some(position) = next else {
+                return;
+            };
+
+            let waiter = self.waiting.remove(position);
+            self.take(&waiter.user_id);
+            // The job went away while waiting; give the slot to the next one
+            if waiter.ready.send(()).is_err() {
+                self.give_back(&waiter.user_id);
+            }
+        }
+    }
+}
+
+/// A compile slot, given back when dropped.
+pub struct SlotGuard {
+    slots: Arc<Mutex<Slots>>,
+    limits: JobLimits,
+    user_id: String,
+}
+
+impl Drop for SlotGuard {
+    fn drop(&mut self) {
+        self.slots
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .release(&self.user_id, &self.limits);
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct CompileJobs {
+    jobs: Arc<RwLock<HashMap<String, Arc<CompileJob>>>>,
+    slots: Arc<Mutex<Slots>>,
+    limits: JobLimits,
+}
+
+impl CompileJobs {
+    pub fn new(limits: JobLimits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    /// Register a new queued job, unless the user already has as many
+    /// running and queued as they are allowed.
+    pub fn start(
+        &self,
+        project_id: &str,
+        user_id: &str,
+        target: Option<&str>,
+    ) -> Result<Arc<CompileJob>> {
+        let mut jobs = self.jobs.write().unwrap_or_else(|e| e.into_inner());
+        jobs.retain(|_, job| !job.expired());
+
+        let limits = &self.limits;
+        if limits.max_running_per_user > 0 {
+            let allowed = limits.max_running_per_user + limits.max_queued_per_user;
+            let active = jobs
+                .values()
+                .filter(|job| job.user_id == user_id && job.status().is_active())
+                .count();
+            if active >= allowed {
+                return Err(AppError::TooManyRequests {
+                    message: format!(
+                        "You already have {active} compiles running or queued (limit {allowed})"
+                    ),
+                    retry_after: None,
+                });
+            }
+        }
+
+        let job = Arc::new(CompileJob::new(project_id, user_id, target));
+        jobs.insert(job.id.clone(), job.clone());
+        Ok(job)
+    }
+
+    /// Wait for a compile slot and mark the job running. Returns `None` if the
+    /// job was cancelled while it waited.
+    pub async fn acquire(&self, job: &CompileJob) -> Option<SlotGuard> {
+        // Everyone goes through the queue so the same fairness rules apply
+        let mut ready = {
+            let (ready, receiver) = oneshot::channel();
+            let mut slots = self.slots();
+            slots.waiting.push(Waiter {
+                job_id: job.id.clone(),
+                user_id: job.user_id.clone(),
+                ready,
+            });
+            slots.dispatch(&self.limits);
+            receiver
+        };
+
+        if ready.try_recv().is_err() {
+            job.append_log("Waiting for a free compile slot\n");
+            tokio::select! {
+                _ = &mut ready => {}
+                _ = job.cancelled() => {
+                    let mut slots = self.slots();
+                    let queued = slots.waiting.iter().position(|waiter| waiter.job_id == job.id);
+                    match queued {
+                        Some(position) => {
+                            slots.waiting.remove(position);
+                        }
+                        // Handed a slot just as it was cancelled
+                        None => slots.release(&job.user_id, &self.limits),
+                    }
+                    return None;
+                }
+            }
+        }
+
+        job.set_running();
+        Some(SlotGuard {
+            slots: self.slots.clone(),
+            limits: self.limits,
+            user_id: job.user_id.clone(),
+        })
+    }
+
+    fn slots(&self) -> std::sync::MutexGuard<'_, Slots> {
+        self.slots.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Whether a compile of the project is queued or running.
+    pub fn has_active(&self, project_id: &str) -> bool {
+        self.jobs
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .values()
+            .any(|job| job.project_id == project_id && job.status().is_active())
+    }
+
+    /// Compiles running and compiles waiting for a slot, across all users.
+    pub fn load(&self) -> (usize, usize) {
+        let slots = self.slots();
+        (slots.total, slots.waiting.len())
+    }
+
+    /// Wait until no compile is running or waiting for a slot.
+    pub async fn drained(&self) {
+        while self.load() != (0, 0) {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    }
+
+    pub fn get(&self, id: &str) -> Option<Arc<CompileJob>> {
+        self.jobs
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .cloned()
+    }
+}
@@ -1,70 +1,720 @@
 // LaTeX compilation service
-// TODO: Implement in Phase 5
+// Runs latexmk for a compile job, streaming its output into the job log
 
 use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
 
-use crate::error::{AppError, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+use tokio::process::{Child, Command};
+use utoipa::ToSchema;
 
-#[allow(dead_code)]
-pub struct CompilerService {
-    storage_path: String,
+use crate::{
+    config::CompileConfig,
+    error::{AppError, Result},
+    services::{
+        bibliography::{self, BibIssue},
+        compile_history::DiagnosticsDelta,
+        compile_jobs::CompileJob,
+        packages::{self, MissingPackage},
+    },
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    #[default]
+    Pdflatex,
+    Xelatex,
+    Lualatex,
+}
+
+impl Engine {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Engine::Pdflatex => "pdflatex",
+            Engine::Xelatex => "xelatex",
+            Engine::Lualatex => "lualatex",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "pdflatex" => Some(Engine::Pdflatex),
+            "xelatex" => Some(Engine::Xelatex),
+            "lualatex" => Some(Engine::Lualatex),
+            _ => None,
+        }
+    }
+
+    // All three produce a PDF directly
+    fn latexmk_flag(self) -> &'static str {
+        match self {
+            Engine::Pdflatex => "-pdf",
+            Engine::Xelatex => "-pdfxe",
+            Engine::Lualatex => "-pdflua",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BibTool {
+    Bibtex,
+    Biber,
+}
+
+impl BibTool {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BibTool::Bibtex => "bibtex",
+            BibTool::Biber => "biber",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "bibtex" => Some(BibTool::Bibtex),
+            "biber" => Some(BibTool::Biber),
+            _ => None,
+        }
+    }
+}
+
+/// What to build for a compile job.
+#[derive(Debug, Clone)]
+pub struct CompileOptions {
+    pub main_file: String,
+    pub engine: Engine,
+    /// Backend for biblatex; `None` leaves it to the document
+    pub bib_tool: Option<BibTool>,
+    /// The project asks for --shell-escape
+    pub shell_escape: bool,
+    /// The project is on the server's allowlist for running its own code
+    /// (shell escape and a project .latexmkrc)
+    pub trusted: bool,
 }
 
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct CompileResult {
     pub success: bool,
+    /// PDF path relative to the build directory
     pub pdf_path: Option<String>,
+    pub engine: Engine,
     pub log: String,
     pub errors: Vec<CompileError>,
     pub warnings: Vec<CompileWarning>,
+    /// Missing citations, undefined references and .bib errors
+    pub bibliography: Vec<BibIssue>,
+    /// Packages the document uses that aren't installed
+    #[serde(default)]
+    pub missing_packages: Vec<MissingPackage>,
+    /// The compile was stopped for running over its time limit
+    pub timed_out: bool,
+    /// Errors and warnings compared with the previous run, filled in once
+    /// the run is done
+    #[serde(default)]
+    pub diagnostics_delta: Option<DiagnosticsDelta>,
 }
 
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct CompileError {
     pub file: String,
     pub line: Option<i32>,
     pub message: String,
 }
 
-#[derive(Debug)]
-#[allow(dead_code)]
+#[derive(Debug, Serialize, Deserialize, Clone, ToSchema)]
 pub struct CompileWarning {
     pub file: String,
     pub line: Option<i32>,
     pub message: String,
 }
 
-#[allow(dead_code)]
-impl CompilerService {
-    pub fn new(storage_path: String) -> Self {
-        Self { storage_path }
+enum Stop {
+    Exited,
+    Cancelled,
+    TimedOut,
+}
+
+/// Compile the main file inside `project_path` into `build_dir`, appending
+/// output to the job's log as it is produced. Auxiliary files left in
+/// `build_dir` by the last compile let latexmk rerun only what changed.
+pub async fn compile(
+    project_path: &Path,
+    build_dir: &Path,
+    options: &CompileOptions,
+    job: &CompileJob,
+    config: &CompileConfig,
+) -> Result<CompileResult> {
+    let result = run(project_path, build_dir, options, job, config).await?;
+
+    // Tectonic fetches packages itself
+    if !config.install_packages
+        || config.backend != "latexmk"
+        || result.missing_packages.is_empty()
+        || result.timed_out
+    {
+        return Ok(result);
+    }
+    match packages::install(&result.missing_packages, job).await {
+        Ok(true) => {
+            job.append_log("\nCompiling again with the installed packages\n");
+            run(project_path, build_dir, options, job, config).await
+        }
+        Ok(false) => Ok(result),
+        Err(e) => {
+            job.append_log(&format!("\n{e}\n"));
+            Ok(result)
+        }
+    }
+}
+
+async fn run(
+    project_path: &Path,
+    build_dir: &Path,
+    options: &CompileOptions,
+    job: &CompileJob,
+    config: &CompileConfig,
+) -> Result<CompileResult> {
+    // The job log covers every run; results are parsed from this one only
+    let log_start = job.log().len();
+
+    // TeX resolves \input and \include against the directory it runs in, so
+    // run from the main file's directory
+    let project_root = tokio::fs::canonicalize(project_path)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to resolve project path: {e}")))?;
+    let (main_dir, main_file) = match options.main_file.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir), name),
+        None => (None, options.main_file.as_str()),
+    };
+    let work_dir = main_dir.map_or_else(|| project_root.clone(), |dir| project_root.join(dir));
+
+    let mut denials = Vec::new();
+    let permissions = permissions(project_path, options, config, &mut denials);
+    for denial in &denials {
+        job.append_log(&format!("{denial}\n"));
+    }
+
+    let (program, args, engine) = match config.backend.as_str() {
+        // Tectonic is XeTeX-based and fetches packages on demand
+        "tectonic" => (
+            "tectonic",
+            tectonic_args(main_file, build_dir, &permissions),
+            Engine::Xelatex,
+        ),
+        "latexmk" => (
+            "latexmk",
+            latexmk_args(main_file, &project_root, build_dir, options, &permissions),
+            options.engine,
+        ),
+        other => {
+            return Err(AppError::Internal(format!(
+                "Unknown compile backend '{other}'"
+            )))
+        }
+    };
+    let mut command = match config.sandbox.as_str() {
+        "bubblewrap" => sandboxed_command(program, &args, &project_root, &work_dir, build_dir),
+        "none" => {
+            let mut command = Command::new(program);
+            command.args(&args);
+            command
+        }
+        other => {
+            return Err(AppError::Internal(format!(
+                "Unknown compile sandbox '{other}'"
+            )))
+        }
+    };
+    if main_dir.is_some() {
+        // Also look files up from the project root, for documents that name
+        // them relative to it; the trailing separator keeps the defaults
+        let search_path = format!(".:{}:", project_root.display());
+        command
+            .env("TEXINPUTS", &search_path)
+            .env("BIBINPUTS", &search_path);
+    }
+    command
+        .current_dir(&work_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    // Own process group, so cancelling also reaches pdflatex, bibtex, etc.
+    #[cfg(unix)]
+    command.process_group(0);
+    #[cfg(unix)]
+    apply_resource_limits(&mut command, config);
+
+    let mut child = command.spawn().map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound if config.sandbox == "bubblewrap" => AppError::Internal(
+            "COMPILE_SANDBOX is bubblewrap but bwrap is not installed".to_string(),
+        ),
+        _ => AppError::Internal(format!("Failed to run {program}: {e}")),
+    })?;
+
+    let stdout = child.stdout.take().map(BufReader::new);
+    let stderr = child.stderr.take().map(BufReader::new);
+    let timeout = async {
+        match config.timeout_secs {
+            0 => std::future::pending().await,
+            secs => tokio::time::sleep(Duration::from_secs(secs)).await,
+        }
+    };
+    let mut cpu_limited = false;
+    let stop = tokio::select! {
+        (status, _, _) = async {
+            tokio::join!(
+                child.wait(),
+                forward_output(stdout, job),
+                forward_output(stderr, job)
+            )
+        } => {
+            let status =
+                status.map_err(|e| AppError::Internal(format!("Failed to run {program}: {e}")))?;
+            cpu_limited = killed_by_cpu_limit(&status);
+            Stop::Exited
+        }
+        _ = job.cancelled() => Stop::Cancelled,
+        _ = timeout => Stop::TimedOut,
+    };
+
+    let mut limit_error = None;
+    match stop {
+        Stop::Exited if cpu_limited => {
+            limit_error = Some(format!(
+                "Compilation exceeded the CPU time limit of {} seconds",
+                config.cpu_limit_secs
+            ));
+        }
+        Stop::Exited => {}
+        Stop::Cancelled => {
+            kill_process_group(&mut child);
+            let _ = child.wait().await;
+            job.append_log("\nCompile cancelled\n");
+            return Err(AppError::Conflict("Compile was cancelled".to_string()));
+        }
+        Stop::TimedOut => {
+            kill_process_group(&mut child);
+            let _ = child.wait().await;
+            limit_error = Some(format!(
+                "Compilation timed out after {} seconds",
+                config.timeout_secs
+            ));
+        }
+    }
+    if let Some(message) = &limit_error {
+        job.append_log(&format!("\n{message}\n"));
+    }
+
+    let log = job.log();
+    let run_log = &log[log_start..];
+    let (mut errors, warnings) = parse_latex_log(run_log);
+    if let Some(dir) = main_dir {
+        for error in &mut errors {
+            error.file = project_relative(&project_root, dir, &error.file);
+        }
+    }
+    let timed_out = limit_error.is_some();
+    // Denied features are reported first, since they often explain the rest
+    for message in denials.into_iter().rev() {
+        errors.insert(
+            0,
+            CompileError {
+                file: String::new(),
+                line: None,
+                message,
+            },
+        );
+    }
+    if let Some(message) = limit_error {
+        errors.push(CompileError {
+            file: String::new(),
+            line: None,
+            message,
+        });
+    }
+
+    let mut bib_issues = bibliography::parse_latex_log(run_log);
+    let missing_packages = packages::parse_missing(run_log);
+    if let Ok(blg) = tokio::fs::read(build_dir.join(output_name(main_file, "blg"))).await {
+        bib_issues.extend(bibliography::parse_blg(&String::from_utf8_lossy(&blg)));
+    }
+
+    // Consider compilation successful if PDF exists, even if latexmk reported warnings
+    let pdf_name = output_name(main_file, "pdf");
+    let pdf_path = build_dir.join(&pdf_name).exists().then_some(pdf_name);
+
+    Ok(CompileResult {
+        // A PDF left over from a killed run is incomplete
+        success: pdf_path.is_some() && !timed_out,
+        pdf_path: pdf_path.filter(|_| !timed_out),
+        engine,
+        log,
+        errors,
+        warnings,
+        bibliography: bib_issues,
+        missing_packages,
+        timed_out,
+        diagnostics_delta: None,
+    })
+}
+
+/// Resolve `path` within the project, rejecting anything that would leave it.
+/// "chapters/../main.tex" becomes "main.tex".
+pub fn normalize_project_path(path: &str) -> Result<String> {
+    let invalid = || AppError::BadRequest(format!("Invalid path '{path}'"));
+    if path.starts_with('/') {
+        return Err(invalid());
+    }
+
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop().ok_or_else(invalid)?;
+            }
+            part => parts.push(part),
+        }
     }
+    if parts.is_empty() {
+        return Err(invalid());
+    }
+    Ok(parts.join("/"))
+}
 
-    pub async fn compile(&self, project_id: &str, main_file: &str) -> Result<CompileResult> {
-        let project_path = Path::new(&self.storage_path).join(project_id);
+// TeX reports files relative to the directory it ran in, or absolute when
+// found through TEXINPUTS; map both back to paths in the project
+fn project_relative(project_root: &Path, main_dir: &str, file: &str) -> String {
+    if file.is_empty() {
+        return String::new();
+    }
+    let resolved = match Path::new(file).strip_prefix(project_root) {
+        Ok(relative) => normalize_project_path(&relative.to_string_lossy()),
+        Err(_) if file.starts_with('/') => return file.to_string(),
+        Err(_) => normalize_project_path(&format!("{main_dir}/{file}")),
+    };
+    resolved.unwrap_or_else(|_| file.to_string())
+}
 
-        if !project_path.exists() {
-            return Err(AppError::NotFound(format!(
-                "Project {project_id} not found"
-            )));
+// TeX names its outputs after the main file, without its directory
+pub fn output_name(main_file: &str, extension: &str) -> String {
+    Path::new(main_file)
+        .with_extension(extension)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Features that let a document run arbitrary code, as granted for one compile.
+struct Permissions {
+    shell_escape: bool,
+    /// The project's latexmkrc, which latexmk evaluates as Perl
+    latexmkrc: Option<&'static str>,
+}
+
+// Running the document's own code needs both a project the server trusts and
+// a sandbox to run it in. Anything requested but refused is explained in
+// `denials`, and the compile goes ahead without it.
+fn permissions(
+    project_path: &Path,
+    options: &CompileOptions,
+    config: &CompileConfig,
+    denials: &mut Vec<String>,
+) -> Permissions {
+    let refusal = if !options.trusted {
+        Some("this project is not on the server's allowlist")
+    } else if config.sandbox == "none" {
+        Some("compiles on this server are not sandboxed")
+    } else {
+        None
+    };
+
+    let latexmkrc = if config.backend == "latexmk" {
+        ["latexmkrc", ".latexmkrc"]
+            .into_iter()
+            .find(|name| project_path.join(name).is_file())
+    } else {
+        None
+    };
+
+    if let Some(reason) = refusal {
+        if options.shell_escape {
+            denials.push(format!(
+                "Shell escape was requested but is not allowed: {reason}"
+            ));
+        }
+        if let Some(name) = latexmkrc {
+            denials.push(format!("Ignoring {name}: {reason}"));
         }
+        return Permissions {
+            shell_escape: false,
+            latexmkrc: None,
+        };
+    }
 
-        // TODO: Implement actual compilation in Phase 5
-        // This will:
-        // 1. Run latexmk -pdf -interaction=nonstopmode {main_file}
-        // 2. Parse the log file for errors and warnings
-        // 3. Return the result
+    Permissions {
+        shell_escape: options.shell_escape,
+        latexmkrc,
+    }
+}
 
-        let _ = main_file; // Suppress unused warning for now
+// latexmk compares sources against the state it recorded in the output
+// directory and runs only the passes that are out of date
+fn latexmk_args(
+    main_file: &str,
+    project_root: &Path,
+    build_dir: &Path,
+    options: &CompileOptions,
+    permissions: &Permissions,
+) -> Vec<String> {
+    let mut args = vec![
+        format!("-outdir={}", build_dir.display()),
+        // latexmk would otherwise pick up a latexmkrc from the project on its
+        // own; this also skips the system-wide rc files
+        "-norc".to_string(),
+    ];
+    if let Some(latexmkrc) = permissions.latexmkrc {
+        args.push("-r".to_string());
+        args.push(project_root.join(latexmkrc).display().to_string());
+    }
+    args.extend(
+        [
+            options.engine.latexmk_flag(),
+            // Run BibTeX/Biber whenever the document needs it
+            "-bibtex",
+            // Source <-> PDF position mapping for the editor
+            "-synctex=1",
+            "-interaction=nonstopmode",
+            "-file-line-error",
+        ]
+        .map(str::to_string),
+    );
+    if permissions.shell_escape {
+        args.push("-shell-escape".to_string());
+    }
+    if let Some(bib_tool) = options.bib_tool {
+        // latexmk runs whichever tool biblatex asks for, so steer biblatex
+        args.push(format!(
+            "-usepretex=\\PassOptionsToPackage{{backend={}}}{{biblatex}}",
+            bib_tool.as_str()
+        ));
+    }
+    args.push(main_file.to_string());
+    args
+}
 
-        Ok(CompileResult {
-            success: false,
-            pdf_path: None,
-            log: "Compilation not yet implemented".to_string(),
-            errors: vec![],
-            warnings: vec![],
-        })
+// Tectonic reruns TeX and the bibliography tool itself; keeping the .log
+// means the same log parsing applies to both backends
+fn tectonic_args(main_file: &str, build_dir: &Path, permissions: &Permissions) -> Vec<String> {
+    let mut args = vec!["--outdir".to_string(), build_dir.display().to_string()];
+    args.extend(["--keep-logs", "--synctex", "--chatter", "minimal"].map(str::to_string));
+    if permissions.shell_escape {
+        args.push("-Z".to_string());
+        args.push("shell-escape".to_string());
     }
+    args.push(main_file.to_string());
+    args
+}
+
+// System directories the TeX toolchain needs, mounted read-only when present
+const SANDBOX_SYSTEM_DIRS: &[&str] = &[
+    "/usr",
+    "/bin",
+    "/sbin",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/etc",
+    "/opt",
+    "/var/lib/texmf",
+];
+
+// Runs the compile under bubblewrap with no network, the system read-only,
+// the project sources read-only and only the build directory writable.
+// Nothing else on the server (other projects, the database) is visible.
+pub(crate) fn sandboxed_command(
+    program: &str,
+    args: &[String],
+    project_root: &Path,
+    work_dir: &Path,
+    build_dir: &Path,
+) -> Command {
+    let mut command = Command::new("bwrap");
+    for dir in SANDBOX_SYSTEM_DIRS {
+        command.args(["--ro-bind-try", dir, dir]);
+    }
+    command
+        .args(["--dev", "/dev", "--proc", "/proc", "--tmpfs", "/tmp"])
+        .arg("--ro-bind")
+        .args([project_root, project_root])
+        .arg("--bind")
+        .args([build_dir, build_dir])
+        .arg("--chdir")
+        .arg(work_dir)
+        .args(["--setenv", "HOME", "/tmp"])
+        .args(["--unshare-all", "--die-with-parent", "--"])
+        .arg(program)
+        .args(args);
+    command
+}
+
+// Limits are inherited by everything latexmk starts. The CPU limit is
+// per process, so the wall-clock timeout still bounds the compile as a whole.
+#[cfg(unix)]
+pub(crate) fn apply_resource_limits(command: &mut Command, config: &CompileConfig) {
+    let cpu_secs = config.cpu_limit_secs as libc::rlim_t;
+    let memory_bytes = (config.memory_limit_mb * 1024 * 1024) as libc::rlim_t;
+    if cpu_secs == 0 && memory_bytes == 0 {
+        return;
+    }
+
+    // SAFETY: only async-signal-safe calls (setrlimit) run between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            if cpu_secs > 0 {
+                // SIGXCPU at the soft limit, SIGKILL shortly after if ignored
+                let limit = libc::rlimit {
+                    rlim_cur: cpu_secs,
+                    rlim_max: cpu_secs + 5,
+                };
+                if libc::setrlimit(libc::RLIMIT_CPU, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            if memory_bytes > 0 {
+                let limit = libc::rlimit {
+                    rlim_cur: memory_bytes,
+                    rlim_max: memory_bytes,
+                };
+                if libc::setrlimit(libc::RLIMIT_AS, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+            Ok(())
+        });
+    }
+}
+
+fn killed_by_cpu_limit(status: &std::process::ExitStatus) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        matches!(status.signal(), Some(libc::SIGXCPU))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = status;
+        false
+    }
+}
+
+fn kill_process_group(child: &mut Child) {
+    #[cfg(unix)]
+    if let Some(pid) = child.id() {
+        // A negative pid signals every process in the group
+        unsafe {
+            libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+        }
+        return;
+    }
+    let _ = child.start_kill();
+}
+
+// TeX writes logs in whatever encoding the document uses, so lines are read
+// as bytes and decoded lossily rather than with `lines()`
+async fn forward_output(reader: Option<impl AsyncBufRead + Unpin>, job: &CompileJob) {
+    let Some(mut reader) = reader else {
+        return;
+    };
+
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => job.append_log(&String::from_utf8_lossy(&line)),
+        }
+    }
+}
+
+pub fn parse_latex_log(log: &str) -> (Vec<CompileError>, Vec<CompileWarning>) {
+    let mut errors = Vec::new();
+    let mut warnings = Vec::new();
+
+    let lines: Vec<&str> = log.lines().collect();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+
+        // Look for error patterns
+        if line.starts_with('!') {
+            let message = line.trim_start_matches('!').trim().to_string();
+            let mut file = String::new();
+            let mut line_num = None;
+
+            // Look back for file:line pattern
+            for j in (0..i).rev() {
+                let prev_line = lines[j];
+                if let Some(pos) = prev_line.find(".tex:") {
+                    if let Some(colon_pos) = prev_line[pos + 5..].find(':') {
+                        let line_str = &prev_line[pos + 5..pos + 5 + colon_pos];
+                        line_num = line_str.parse().ok();
+                    } else if let Some(space_pos) = prev_line[pos + 5..].find(' ') {
+                        let line_str = &prev_line[pos + 5..pos + 5 + space_pos];
+                        line_num = line_str.parse().ok();
+                    }
+                    file = prev_line[..pos + 4].to_string();
+                    // Extract just the filename
+                    if let Some(last_paren) = file.rfind('(') {
+                        file = file[last_paren + 1..].to_string();
+                    }
+                    break;
+                }
+            }
+
+            errors.push(CompileError {
+                file,
+                line: line_num,
+                message,
+            });
+        }
+
+        // Tectonic reports errors as "error: file.tex:12: message"
+        if let Some(rest) = line.strip_prefix("error: ") {
+            let mut parts = rest.splitn(3, ':');
+            let (file, line_num, message) = match (parts.next(), parts.next(), parts.next()) {
+                (Some(file), Some(num), Some(message)) if num.trim().parse::<i32>().is_ok() => (
+                    file.to_string(),
+                    num.trim().parse().ok(),
+                    message.trim().to_string(),
+                ),
+                _ => (String::new(), None, rest.trim().to_string()),
+            };
+            errors.push(CompileError {
+                file,
+                line: line_num,
+                message,
+            });
+        }
+
+        // Look for warning patterns
+        if line.contains("Warning:") || line.contains("warning:") {
+            let message = line.to_string();
+            warnings.push(CompileWarning {
+                file: String::new(),
+                line: None,
+                message,
+            });
+        }
+
+        i += 1;
+    }
+
+    (errors, warnings)
 }
@@ -0,0 +1,208 @@
+// Figure conversion
+// pdflatex can only include PDF/PNG/JPEG, so other figure formats are converted
+// with external tools (or in-process for raster images)
+
+use std::path::Path;
+use std::time::Duration;
+
+use image::ImageFormat;
+use serde::Deserialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::{AppError, Result};
+
+const TOOL_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Resolution for rasterizing vector figures
+const RASTER_DPI: &str = "300";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetFormat {
+    Pdf,
+    Png,
+}
+
+impl TargetFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            TargetFormat::Pdf => "pdf",
+            TargetFormat::Png => "png",
+        }
+    }
+}
+
+/// How a source format is turned into a target format.
+enum Converter {
+    /// Decode and re-encode with the `image` crate
+    Raster,
+    /// Run an external program; `{in}` and `{out}` are replaced with file paths
+    External {
+        program: &'static str,
+        args: &'static [&'static str],
+    },
+}
+
+fn converter_for(source_ext: &str, target: TargetFormat) -> Option<Converter> {
+    use TargetFormat::*;
+
+    let converter = match (source_ext, target) {
+        ("eps" | "ps", Pdf) => Converter::External {
+            program: "epstopdf",
+            args: &["{in}", "--outfile={out}"],
+        },
+        ("eps" | "ps", Png) => Converter::External {
+            program: "gs",
+            args: &[
+                "-dSAFER",
+                "-dBATCH",
+                "-dNOPAUSE",
+                "-dEPSCrop",
+                "-sDEVICE=png16m",
+                "-r300",
+                "-sOutputFile={out}",
+                "{in}",
+            ],
+        },
+        ("svg", Pdf) => Converter::External {
+            program: "rsvg-convert",
+            args: &["-f", "pdf", "-o", "{out}", "{in}"],
+        },
+        ("svg", Png) => Converter::External {
+            program: "rsvg-convert",
+            args: &[
+                "-f", "png", "-d", RASTER_DPI, "-p", RASTER_DPI, "-o", "{out}", "{in}",
+            ],
+        },
+        ("pdf", Png) => Converter::External {
+            program: "pdftocairo",
+            args: &[
+                "-png",
+                "-singlefile",
+                "-r",
+                RASTER_DPI,
+                "{in}",
+                "{out_stem}",
+            ],
+        },
+        ("tif" | "tiff" | "bmp" | "gif" | "webp" | "jpg" | "jpeg", Png) => Converter::Raster,
+        ("tif" | "tiff" | "bmp" | "gif" | "webp", Pdf) => Converter::External {
+            program: "img2pdf",
+            args: &["-o", "{out}", "{in}"],
+        },
+        _ => return None,
+    };
+    Some(converter)
+}
+
+fn extension_of(path: &str) -> Option<String> {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase)
+}
+
+/// Path of the converted file: the source path with the target's extension.
+pub fn target_path(source_path: &str, target: TargetFormat) -> String {
+    Path::new(source_path)
+        .with_extension(target.extension())
+        .to_string_lossy()
+        .replace('\\', "/")
+}
+
+/// Convert `source` (named `source_path`) into `target`, returning the new bytes.
+pub async fn convert(source_path: &str, source: Vec<u8>, target: TargetFormat) -> Result<Vec<u8>> {
+    let ext = extension_of(source_path)
+        .ok_or_else(|| AppError::BadRequest("File has no extension".to_string()))?;
+    let converter = converter_for(&ext, target).ok_or_else(|| {
+        AppError::BadRequest(format!(
+            "Cannot convert .{ext} files to {}",
+            target.extension()
+        ))
+    })?;
+
+    match converter {
+        Converter::Raster => tokio::task::spawn_blocking(move || rasterize(&source))
+            .await
+            .map_err(|e| AppError::Internal(format!("Conversion task failed: {e}")))?,
+        Converter::External { program, args } => {
+            let scratch = std::env::temp_dir().join(format!("openleaf-convert-{}", Uuid::new_v4()));
+            let result = run_external(program, args, &scratch, &ext, &source, target).await;
+            let _ = tokio::fs::remove_dir_all(&scratch).await;
+            result
+        }
+    }
+}
+
+fn rasterize(source: &[u8]) -> Result<Vec<u8>> {
+    let img = image::load_from_memory(source)
+        .map_err(|e| AppError::BadRequest(format!("Failed to decode image: {e}")))?;
+
+    let mut out = std::io::Cursor::new(Vec::new());
+    img.write_to(&mut out, ImageFormat::Png)
+        .map_err(|e| AppError::Internal(format!("Failed to encode PNG: {e}")))?;
+    Ok(out.into_inner())
+}
+
+async fn run_external(
+    program: &str,
+    args: &[&str],
+    scratch: &Path,
+    source_ext: &str,
+    source: &[u8],
+    target: TargetFormat,
+) -> Result<Vec<u8>> {
+    let io_error = |e: std::io::Error| AppError::Internal(format!("Conversion failed: {e}"));
+
+    tokio::fs::create_dir_all(scratch).await.map_err(io_error)?;
+    let input = scratch.join(format!("input.{source_ext}"));
+    let output = scratch.join(format!("output.{}", target.extension()));
+    tokio::fs::write(&input, source).await.map_err(io_error)?;
+
+    let input_str = input.to_string_lossy();
+    let output_str = output.to_string_lossy();
+    let output_stem = scratch.join("output");
+    let output_stem_str = output_stem.to_string_lossy();
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| {
+            arg.replace("{in}", &input_str)
+                .replace("{out_stem}", &output_stem_str)
+                .replace("{out}", &output_str)
+        })
+        .collect();
+
+    let child = tokio::process::Command::new(program)
+        .args(&args)
+        .current_dir(scratch)
+        .kill_on_drop(true)
+        .output();
+
+    let result = match tokio::time::timeout(TOOL_TIMEOUT, child).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::Internal(format!(
+                "Conversion tool '{program}' is not installed"
+            )));
+        }
+        Ok(Err(e)) => return Err(io_error(e)),
+        Err(_) => {
+            return Err(AppError::Internal(format!(
+                "Conversion with '{program}' timed out"
+            )));
+        }
+    };
+
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        return Err(AppError::BadRequest(format!(
+            "{program} failed: {}",
+            stderr.trim()
+        )));
+    }
+
+    tokio::fs::read(&output)
+        .await
+        .map_err(|_| AppError::Internal(format!("{program} produced no output")))
+}
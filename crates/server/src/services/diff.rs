@@ -0,0 +1,45 @@
+// Line-based diffing for change review
+
+use serde::Serialize;
+use similar::{ChangeTag, TextDiff};
+use utoipa::ToSchema;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DiffResult {
+    pub diff: String,
+    pub additions: usize,
+    pub deletions: usize,
+}
+
+/// Compute a unified diff between two texts using the Myers algorithm.
+pub fn unified_diff(
+    old: &str,
+    new: &str,
+    old_label: &str,
+    new_label: &str,
+    context: usize,
+) -> DiffResult {
+    let diff = TextDiff::from_lines(old, new);
+
+    let mut additions = 0;
+    let mut deletions = 0;
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            ChangeTag::Insert => additions += 1,
+            ChangeTag::Delete => deletions += 1,
+            ChangeTag::Equal => {}
+        }
+    }
+
+    let diff = diff
+        .unified_diff()
+        .context_radius(context)
+        .header(old_label, new_label)
+        .to_string();
+
+    DiffResult {
+        diff,
+        additions,
+        deletions,
+    }
+}
@@ -0,0 +1,240 @@
+// Project digest
+// What happened in a project since a given moment: files changed, with lines
+// added and removed where the editor's history can tell, comments added, and
+// compiles run. Backs the "since you last looked" view and the daily email.
+
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+
+use crate::{
+    db::DbPool,
+    error::Result,
+    services::{diff, history},
+};
+
+// Changed files whose history is replayed for line counts; the rest are
+// listed without them
+const MAX_COUNTED_FILES: usize = 50;
+
+// Characters of each new comment included
+const COMMENT_EXCERPT: usize = 200;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ProjectChanges {
+    pub since: String,
+    /// Most recently changed first
+    pub files: Vec<FileChange>,
+    /// Totals over the files that have line counts
+    pub lines_added: usize,
+    pub lines_removed: usize,
+    /// Oldest first
+    pub comments: Vec<NewComment>,
+    pub compiles: CompileActivity,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FileChange {
+    pub file_id: String,
+    pub path: String,
+    /// The file did not exist yet at `since`
+    pub created: bool,
+    pub updated_at: String,
+    /// None when the file was changed outside the editor, which keeps no
+    /// history to count from
+    pub lines_added: Option<usize>,
+    pub lines_removed: Option<usize>,
+    /// Names of the people who edited it in the editor
+    pub edited_by: Vec<String>,
+}
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct NewComment {
+    pub id: String,
+    pub file_path: String,
+    pub author_name: Option<String>,
+    /// The start of the comment
+    pub content: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CompileActivity {
+    pub total: i64,
+    pub failed: i64,
+    pub latest_at: Option<String>,
+    pub latest_succeeded: Option<bool>,
+}
+
+impl ProjectChanges {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty() && self.comments.is_empty() && self.compiles.total == 0
+    }
+
+    /// One line for an email, such as "3 files changed (+40 -12), 2 comments".
+    pub fn summary(&self) -> String {
+        let plural = |count: usize, one: &str, many: &str| {
+            if count == 1 {
+                format!("1 {one}")
+            } else {
+                format!("{count} {many}")
+            }
+        };
+        let mut parts = Vec::new();
+        if !self.files.is_empty() {
+            let mut files = format!("{} changed", plural(self.files.len(), "file", "files"));
+            if self.lines_added > 0 || self.lines_removed > 0 {
+                files.push_str(&format!(" (+{} -{})", self.lines_added, self.lines_removed));
+            }
+            parts.push(files);
+        }
+        if !self.comments.is_empty() {
+            parts.push(plural(self.comments.len(), "comment", "comments"));
+        }
+        if self.compiles.total > 0 {
+            let mut compiles = plural(self.compiles.total as usize, "compile", "compiles");
+            if self.compiles.failed > 0 {
+                compiles.push_str(&format!(" ({} failed)", self.compiles.failed));
+            }
+            parts.push(compiles);
+        }
+        parts.join(", ")
+    }
+}
+
+/// Changes to a project since `since`, an RFC 3339 time.
+pub async fn since(pool: &DbPool, project_id: &str, since: &str) -> Result<ProjectChanges> {
+    let changed = sqlx::query_as::<_, (String, String, String, String)>(
+        "SELECT id, path, created_at, updated_at FROM files \
+         WHERE project_id = $1 AND is_folder = FALSE AND updated_at >= $2 \
+         ORDER BY updated_at DESC",
+    )
+    .bind(project_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    let mut files = Vec::with_capacity(changed.len());
+    let (mut lines_added, mut lines_removed) = (0, 0);
+    for (i, (file_id, path, created_at, updated_at)) in changed.into_iter().enumerate() {
+        let created = created_at.as_str() >= since;
+        let counted = if i < MAX_COUNTED_FILES {
+            count_lines(pool, &file_id, since, created).await?
+        } else {
+            None
+        };
+        if let Some((added, removed)) = counted {
+            lines_added += added;
+            lines_removed += removed;
+        }
+        files.push(FileChange {
+            edited_by: editors(pool, &file_id, since).await?,
+            file_id,
+            path,
+            created,
+            updated_at,
+            lines_added: counted.map(|(added, _)| added),
+            lines_removed: counted.map(|(_, removed)| removed),
+        });
+    }
+
+    let mut comments = sqlx::query_as::<_, NewComment>(
+        "SELECT c.id, c.file_path, u.name AS author_name, c.content, c.created_at \
+         FROM comments c LEFT JOIN users u ON c.author_id = u.id \
+         WHERE c.project_id = $1 AND c.created_at >= $2 ORDER BY c.created_at",
+    )
+    .bind(project_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+    for comment in &mut comments {
+        if let Some((end, _)) = comment.content.char_indices().nth(COMMENT_EXCERPT) {
+            comment.content.truncate(end);
+            comment.content.push('…');
+        }
+    }
+
+    let (total, failed) = sqlx::query_as::<_, (i64, Option<i64>)>(
+        "SELECT COUNT(*), SUM(CASE WHEN success THEN 0 ELSE 1 END) FROM compiles \
+         WHERE project_id = $1 AND started_at >= $2",
+    )
+    .bind(project_id)
+    .bind(since)
+    .fetch_one(pool)
+    .await?;
+    let latest = sqlx::query_as::<_, (String, bool)>(
+        "SELECT started_at, success FROM compiles \
+         WHERE project_id = $1 AND started_at >= $2 ORDER BY started_at DESC LIMIT 1",
+    )
+    .bind(project_id)
+    .bind(since)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(ProjectChanges {
+        since: since.to_string(),
+        files,
+        lines_added,
+        lines_removed,
+        comments,
+        compiles: CompileActivity {
+            total,
+            failed: failed.unwrap_or(0),
+            latest_at: latest.as_ref().map(|(at, _)| at.clone()),
+            latest_succeeded: latest.map(|(_, success)| success),
+        },
+    })
+}
+
+/// Lines added and removed in the editor since `since`, comparing the file
+/// before its first update since then with the file after its last.
+async fn count_lines(
+    pool: &DbPool,
+    file_id: &str,
+    since: &str,
+    created: bool,
+) -> Result<Option<(usize, usize)>> {
+    let (first, last) = sqlx::query_as::<_, (Option<i64>, Option<i64>)>(
+        "SELECT MIN(id), MAX(id) FROM document_history \
+         WHERE file_id = $1 AND kind = $2 AND created_at >= $3",
+    )
+    .bind(file_id)
+    .bind(history::UPDATE)
+    .bind(since)
+    .fetch_one(pool)
+    .await?;
+    let (Some(first), Some(last)) = (first, last) else {
+        return Ok(None);
+    };
+
+    // The snapshot a room takes before its first update, or the update before
+    let before = sqlx::query_scalar::<_, Option<i64>>(
+        "SELECT MAX(id) FROM document_history WHERE file_id = $1 AND id < $2",
+    )
+    .bind(file_id)
+    .bind(first)
+    .fetch_one(pool)
+    .await?;
+    let before = match before {
+        Some(seq) => history::content_at(pool, file_id, seq).await?,
+        None if created => String::new(),
+        None => return Ok(None),
+    };
+    let after = history::content_at(pool, file_id, last).await?;
+
+    let counted = diff::unified_diff(&before, &after, "", "", 0);
+    Ok(Some((counted.additions, counted.deletions)))
+}
+
+async fn editors(pool: &DbPool, file_id: &str, since: &str) -> Result<Vec<String>> {
+    let names = sqlx::query_scalar::<_, String>(
+        "SELECT DISTINCT u.name FROM document_history h JOIN users u ON h.user_id = u.id \
+         WHERE h.file_id = $1 AND h.kind = $2 AND h.created_at >= $3 ORDER BY u.name",
+    )
+    .bind(file_id)
+    .bind(history::UPDATE)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+    Ok(names)
+}
@@ -0,0 +1,88 @@
+// Recent errors
+// A tracing layer that keeps the latest warnings and errors in memory, so an
+// operator can see what has been going wrong from the admin API without
+// reading the server's logs.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use serde::Serialize;
+use tracing::{
+    field::{Field, Visit},
+    Event, Level, Subscriber,
+};
+use tracing_subscriber::layer::{Context, Layer};
+use utoipa::ToSchema;
+
+// Older entries are dropped once this many are kept
+const CAPACITY: usize = 100;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LoggedError {
+    pub at: String,
+    /// "error" or "warn"
+    pub level: String,
+    /// Module that logged it
+    pub target: String,
+    pub message: String,
+}
+
+#[derive(Clone, Default)]
+pub struct ErrorLog {
+    entries: Arc<Mutex<VecDeque<LoggedError>>>,
+}
+
+impl ErrorLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Up to `limit` entries, newest first.
+    pub fn recent(&self, limit: usize) -> Vec<LoggedError> {
+        let entries = self.entries.lock().unwrap();
+        entries.iter().rev().take(limit).cloned().collect()
+    }
+}
+
+impl<S: Subscriber> Layer<S> for ErrorLog {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        // Levels compare by verbosity, so this is warnings and errors
+        if *metadata.level() > Level::WARN {
+            return;
+        }
+
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+        let entry = LoggedError {
+            at: Utc::now().to_rfc3339(),
+            level: metadata.level().as_str().to_lowercase(),
+            target: metadata.target().to_string(),
+            message: message.0,
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+}
+
+/// The formatted message, followed by any other fields as `name=value`.
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        if field.name() == "message" {
+            let _ = write!(self.0, "{value:?}");
+        } else {
+            let _ = write!(self.0, "{}={value:?}", field.name());
+        }
+    }
+}
@@ -0,0 +1,213 @@
+// Project event channel
+// Tells every open client of a project about changes outside the document
+// being edited (file tree, compiles, comments, members) so nobody has to poll.
+// Also carries project chat and follow mode: a client following someone gets
+// their cursor and scroll position as they move around the project.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+
+use crate::services::chat::ChatMessage;
+
+// Events a slow client may fall behind by before it has to resync
+const CHANNEL_CAPACITY: usize = 128;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProjectEvent {
+    FileCreated {
+        file_id: String,
+        path: String,
+        is_folder: bool,
+    },
+    FileRenamed {
+        file_id: String,
+        old_path: String,
+        path: String,
+    },
+    /// The file's content was replaced outside collaborative editing
+    FileUpdated {
+        file_id: String,
+        path: String,
+    },
+    FileDeleted {
+        file_id: String,
+        path: String,
+        is_folder: bool,
+    },
+    /// Several files changed at once (bulk operations, rescans, reordering);
+    /// clients refetch the tree
+    FilesChanged,
+    CompileFinished {
+        job_id: String,
+        status: String,
+        success: bool,
+    },
+    CommentAdded {
+        comment_id: String,
+        file_path: String,
+        author_id: String,
+        author_name: String,
+    },
+    /// A comment's content was edited by its author
+    CommentEdited {
+        comment_id: String,
+        file_path: String,
+    },
+    /// A comment mentions a project member; meant for `user_id`
+    UserMentioned {
+        comment_id: String,
+        file_path: String,
+        user_id: String,
+        author_id: String,
+        author_name: String,
+    },
+    /// A comment was resolved or reopened
+    CommentResolved {
+        comment_id: String,
+        file_path: String,
+        resolved: bool,
+    },
+    /// A change was suggested in track-changes mode
+    ChangeSuggested {
+        change_id: String,
+        file_path: String,
+        author_id: String,
+        author_name: String,
+    },
+    /// A suggested change was accepted or rejected
+    ChangeReviewed {
+        change_id: String,
+        file_path: String,
+        status: String,
+    },
+    CollaboratorJoined {
+        user_id: String,
+        name: String,
+        role: String,
+    },
+    CollaboratorRemoved {
+        user_id: String,
+    },
+    /// The owner froze or unfroze the project; editors go read-only while
+    /// it is frozen
+    ProjectFrozen {
+        frozen: bool,
+        user_id: String,
+        name: String,
+    },
+    ChatMessage(ChatMessage),
+    /// `follower_id` started following `user_id`
+    FollowStarted {
+        user_id: String,
+        follower_id: String,
+        follower_name: String,
+    },
+    FollowStopped {
+        user_id: String,
+        follower_id: String,
+    },
+    /// Where a followed user is looking; only sent to their followers
+    FollowUpdate {
+        user_id: String,
+        file_path: String,
+        cursor: Option<Value>,
+        scroll: Option<Value>,
+    },
+    /// The client missed events and should refetch everything it shows
+    Resync,
+}
+
+/// A message from a client on the project channel.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Chat {
+        content: String,
+    },
+    Follow {
+        user_id: String,
+    },
+    Unfollow,
+    /// The sender's cursor and scroll position, passed on to their followers
+    Viewport {
+        file_path: String,
+        cursor: Option<Value>,
+        scroll: Option<Value>,
+    },
+}
+
+struct Channel {
+    sender: broadcast::Sender<ProjectEvent>,
+    // Number of follower connections by followed user ID
+    followers: HashMap<String, usize>,
+}
+
+/// A broadcast channel per project with clients listening.
+#[derive(Clone, Default)]
+pub struct ProjectEvents {
+    channels: Arc<Mutex<HashMap<String, Channel>>>,
+}
+
+impl ProjectEvents {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn channels(&self) -> std::sync::MutexGuard<'_, HashMap<String, Channel>> {
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn subscribe(&self, project_id: &str) -> broadcast::Receiver<ProjectEvent> {
+        let mut channels = self.channels();
+        // Drop channels of projects whose clients have all gone
+        channels.retain(|_, channel| channel.sender.receiver_count() > 0);
+        channels
+            .entry(project_id.to_string())
+            .or_insert_with(|| Channel {
+                sender: broadcast::channel(CHANNEL_CAPACITY).0,
+                followers: HashMap::new(),
+            })
+            .sender
+            .subscribe()
+    }
+
+    /// Send an event to the project's clients; a no-op when nobody listens.
+    pub fn publish(&self, project_id: &str, event: ProjectEvent) {
+        let mut channels = self.channels();
+        if let Some(channel) = channels.get(project_id) {
+            if channel.sender.send(event).is_err() {
+                channels.remove(project_id);
+            }
+        }
+    }
+
+    pub fn follow(&self, project_id: &str, user_id: &str) {
+        if let Some(channel) = self.channels().get_mut(project_id) {
+            *channel.followers.entry(user_id.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn unfollow(&self, project_id: &str, user_id: &str) {
+        if let Some(channel) = self.channels().get_mut(project_id) {
+            if let Some(count) = channel.followers.get_mut(user_id) {
+                *count -= 1;
+                if *count == 0 {
+                    channel.followers.remove(user_id);
+                }
+            }
+        }
+    }
+
+    /// Whether anyone follows the user, so clients that nobody watches do not
+    /// flood the channel with viewport updates.
+    pub fn is_followed(&self, project_id: &str, user_id: &str) -> bool {
+        self.channels()
+            .get(project_id)
+            .is_some_and(|channel| channel.followers.contains_key(user_id))
+    }
+}
@@ -0,0 +1,74 @@
+// Exclusion of build artifacts from project file listings
+// Server defaults plus an optional gitignore-style file at the project root
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+
+use crate::{
+    error::{AppError, Result},
+    services::storage::StorageService,
+};
+
+pub const IGNORE_FILE: &str = ".openleafignore";
+
+// Files LaTeX toolchains generate next to the sources
+const DEFAULT_PATTERNS: &[&str] = &[
+    "*.aux",
+    "*.bbl",
+    "*.bcf",
+    "*.blg",
+    "*.dvi",
+    "*.fdb_latexmk",
+    "*.fls",
+    "*.lof",
+    "*.log",
+    "*.lot",
+    "*.nav",
+    "*.out",
+    "*.run.xml",
+    "*.snm",
+    "*.synctex",
+    "*.synctex.gz",
+    "*.synctex(busy)",
+    "*.toc",
+    "*.vrb",
+    "*.xdv",
+];
+
+pub struct ExcludeRules {
+    matcher: Gitignore,
+}
+
+impl ExcludeRules {
+    /// Defaults followed by the project's own rules, which may re-include
+    /// defaults with `!pattern`.
+    pub fn new(project_rules: Option<&str>) -> Self {
+        let mut builder = GitignoreBuilder::new("");
+        for pattern in DEFAULT_PATTERNS {
+            let _ = builder.add_line(None, pattern);
+        }
+        for line in project_rules.unwrap_or_default().lines() {
+            // Invalid lines are skipped rather than failing every listing
+            if let Err(e) = builder.add_line(None, line) {
+                tracing::debug!("Ignoring invalid exclude pattern {:?}: {}", line, e);
+            }
+        }
+
+        let matcher = builder.build().unwrap_or_else(|_| Gitignore::empty());
+        Self { matcher }
+    }
+
+    pub async fn load(storage: &StorageService, project_id: &str) -> Result<Self> {
+        let rules = match storage.read_file(project_id, IGNORE_FILE).await {
+            Ok(rules) => Some(rules),
+            Err(AppError::NotFound(_)) => None,
+            Err(e) => return Err(e),
+        };
+        Ok(Self::new(rules.as_deref()))
+    }
+
+    pub fn is_excluded(&self, path: &str, is_folder: bool) -> bool {
+        self.matcher
+            .matched_path_or_any_parents(path, is_folder)
+            .is_ignore()
+    }
+}
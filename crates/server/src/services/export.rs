@@ -0,0 +1,167 @@
+// Document export
+// Converts a project's main file to Word, HTML or EPUB with pandoc, for
+// collaborators who don't read LaTeX. pandoc runs under the same sandbox and
+// resource limits as compiles, and with its own --sandbox on top whatever the
+// compile sandbox is, so it reads nothing but the main file: \input,
+// \include, figures and bibliographies are left out of the export.
+
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::process::Command;
+use uuid::Uuid;
+
+use crate::{
+    config::CompileConfig,
+    error::{AppError, Result},
+    services::compiler::{apply_resource_limits, sandboxed_command},
+};
+
+const PANDOC_TIMEOUT: Duration = Duration::from_secs(120);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Docx,
+    Html,
+    Epub,
+}
+
+impl ExportFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "docx" => Some(Self::Docx),
+            "html" => Some(Self::Html),
+            "epub" => Some(Self::Epub),
+            _ => None,
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Docx => "docx",
+            Self::Html => "html",
+            Self::Epub => "epub",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Docx => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+            Self::Html => "text/html; charset=utf-8",
+            Self::Epub => "application/epub+zip",
+        }
+    }
+
+    // pandoc writer options; HTML carries its figures and styles inline so
+    // the one file stands alone
+    fn pandoc_args(self) -> &'static [&'static str] {
+        match self {
+            Self::Docx => &["--to=docx"],
+            Self::Html => &["--to=html5", "--standalone", "--embed-resources"],
+            Self::Epub => &["--to=epub3"],
+        }
+    }
+}
+
+/// Convert `main_file` of the project at `project_path` to `format`,
+/// returning the document.
+pub async fn export(
+    project_path: &Path,
+    main_file: &str,
+    format: ExportFormat,
+    config: &CompileConfig,
+) -> Result<Vec<u8>> {
+    let io_error = |e: std::io::Error| AppError::Internal(format!("Export failed: {e}"));
+
+    let project_root = tokio::fs::canonicalize(project_path)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to resolve project path: {e}")))?;
+    // Like TeX, pandoc resolves \input against the directory it runs in
+    let (work_dir, main_name) = match main_file.rsplit_once('/') {
+        Some((dir, name)) => (project_root.join(dir), name),
+        None => (project_root.clone(), main_file),
+    };
+
+    let scratch = std::env::temp_dir().join(format!("openleaf-export-{}", Uuid::new_v4()));
+    tokio::fs::create_dir_all(&scratch)
+        .await
+        .map_err(io_error)?;
+    let output = scratch.join(format!("output.{}", format.extension()));
+
+    let mut args = vec![
+        main_name.to_string(),
+        "--sandbox".to_string(),
+        "--from=latex".to_string(),
+        "--citeproc".to_string(),
+        format!("--resource-path=.:{}", project_root.display()),
+        format!("--output={}", output.display()),
+    ];
+    args.extend(format.pandoc_args().iter().map(|arg| arg.to_string()));
+
+    let result = run_pandoc(&args, &project_root, &work_dir, &scratch, config).await;
+    let document = match result {
+        Ok(()) => tokio::fs::read(&output)
+            .await
+            .map_err(|_| AppError::Internal("pandoc produced no output".to_string())),
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_dir_all(&scratch).await;
+    document
+}
+
+async fn run_pandoc(
+    args: &[String],
+    project_root: &Path,
+    work_dir: &Path,
+    scratch: &Path,
+    config: &CompileConfig,
+) -> Result<()> {
+    let mut command = match config.sandbox.as_str() {
+        "bubblewrap" => sandboxed_command("pandoc", args, project_root, work_dir, scratch),
+        "none" => {
+            let mut command = Command::new("pandoc");
+            command.args(args);
+            command
+        }
+        other => {
+            return Err(AppError::Internal(format!(
+                "Unknown compile sandbox '{other}'"
+            )))
+        }
+    };
+    command
+        .current_dir(work_dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    apply_resource_limits(&mut command, config);
+
+    let child = command.output();
+    let result = match tokio::time::timeout(PANDOC_TIMEOUT, child).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::Internal(match config.sandbox.as_str() {
+                "bubblewrap" => {
+                    "COMPILE_SANDBOX is bubblewrap but bwrap is not installed".to_string()
+                }
+                _ => "pandoc is not installed".to_string(),
+            }));
+        }
+        Ok(Err(e)) => return Err(AppError::Internal(format!("Failed to run pandoc: {e}"))),
+        Err(_) => return Err(AppError::Internal("Export timed out".to_string())),
+    };
+
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        return Err(AppError::BadRequest(format!(
+            "pandoc failed: {}",
+            stderr.trim()
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,195 @@
+// Project figures
+// The images in a project and the .tex files that include each of them, so
+// figures nothing uses any more can be found and cleaned up. Names are
+// resolved as the compile would where that can be told from the sources:
+// with the folders \graphicspath adds and the extensions pdflatex tries, from
+// the project root or from the folder of the file that includes them.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    db::DbPool,
+    error::Result,
+    services::{storage::StorageService, submission},
+};
+
+// Files counted as figures, whether or not pdflatex can include them
+const FIGURE_EXTENSIONS: &[&str] = &[
+    "pdf", "png", "jpg", "jpeg", "eps", "ps", "svg", "gif", "tif", "tiff", "bmp", "webp",
+];
+
+// Commands that include a figure, with the extensions tried for a name
+// given without one
+const INCLUDE_COMMANDS: &[(&str, &[&str])] = &[
+    ("includegraphics", submission::GRAPHICS_EXTENSIONS),
+    ("includepdf", &[".pdf"]),
+    ("includesvg", &[".svg"]),
+];
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FigureReport {
+    /// By path
+    pub figures: Vec<Figure>,
+    /// How many figures no .tex file includes
+    pub unused: usize,
+    /// Bytes the unused figures take up
+    pub unused_size: u64,
+    /// Included names that match no file in the project, such as figures
+    /// not uploaded yet or ones TeX finds in its own tree
+    pub missing: Vec<MissingFigure>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Figure {
+    pub file_id: String,
+    pub path: String,
+    pub size: u64,
+    /// Paths of the .tex files that include it
+    pub used_by: Vec<String>,
+    pub unused: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MissingFigure {
+    /// The .tex file with the reference
+    pub path: String,
+    /// The name as written
+    pub name: String,
+}
+
+// Whether the path names a figure
+fn is_figure(path: &str) -> bool {
+    path.rsplit_once('.').is_some_and(|(_, extension)| {
+        FIGURE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+    })
+}
+
+/// The project's figures with the .tex files that include them.
+pub async fn list(
+    pool: &DbPool,
+    storage: &StorageService,
+    project_id: &str,
+) -> Result<FigureReport> {
+    let files = sqlx::query_as::<_, (String, String)>(
+        "SELECT id, path FROM files WHERE project_id = $1 AND is_folder = FALSE ORDER BY path",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+    let paths: HashSet<&str> = files.iter().map(|(_, path)| path.as_str()).collect();
+
+    let mut sources = Vec::new();
+    for (_, path) in files.iter().filter(|(_, path)| path.ends_with(".tex")) {
+        match storage.read_file(project_id, path).await {
+            Ok(content) => sources.push((path.as_str(), submission::strip_comments(&content))),
+            // Binary or missing files include nothing
+            Err(e) => tracing::debug!("Skipping {} for figures: {}", path, e),
+        }
+    }
+
+    // \graphicspath is usually set once in the preamble and holds for every
+    // file the main file inputs
+    let mut graphics_dirs = BTreeSet::from([String::new()]);
+    for (_, source) in &sources {
+        graphics_dirs.extend(submission::graphics_paths(source));
+    }
+
+    let mut used_by: HashMap<&str, BTreeSet<&str>> = HashMap::new();
+    let mut missing = Vec::new();
+    for (tex, source) in &sources {
+        let folder = tex.rsplit_once('/').map_or("", |(folder, _)| folder);
+        for (command, extensions) in INCLUDE_COMMANDS {
+            for name in submission::arguments(source, command, false) {
+                let name = name.trim_matches('"');
+                // Names built from macros can't be told without expanding them
+                if name.is_empty() || name.contains(['\\', '#']) {
+                    continue;
+                }
+                let found = [String::new(), format!("{folder}/")]
+                    .iter()
+                    .flat_map(|base| {
+                        graphics_dirs
+                            .iter()
+                            .map(move |dir| format!("{base}{dir}{name}"))
+                    })
+                    .filter_map(|candidate| normalize(&candidate))
+                    .find_map(|candidate| {
+                        std::iter::once(candidate.clone())
+                            .chain(
+                                extensions
+                                    .iter()
+                                    .map(|extension| format!("{candidate}{extension}")),
+                            )
+                            .find_map(|candidate| paths.get(candidate.as_str()).copied())
+                    });
+                match found {
+                    Some(figure) => {
+                        used_by.entry(figure).or_default().insert(tex);
+                    }
+                    None if !missing
+                        .iter()
+                        .any(|m: &MissingFigure| m.path == *tex && m.name == name) =>
+                    {
+                        missing.push(MissingFigure {
+                            path: tex.to_string(),
+                            name: name.to_string(),
+                        })
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    let sizes: BTreeMap<String, u64> = storage
+        .list_tree(project_id)
+        .await?
+        .into_iter()
+        .map(|entry| (entry.path, entry.size))
+        .collect();
+    let figures: Vec<Figure> = files
+        .iter()
+        .filter(|(_, path)| is_figure(path))
+        .map(|(file_id, path)| {
+            let used_by: Vec<String> = used_by
+                .remove(path.as_str())
+                .unwrap_or_default()
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+            Figure {
+                file_id: file_id.clone(),
+                path: path.clone(),
+                size: sizes.get(path).copied().unwrap_or(0),
+                unused: used_by.is_empty(),
+                used_by,
+            }
+        })
+        .collect();
+
+    let unused: Vec<&Figure> = figures.iter().filter(|figure| figure.unused).collect();
+    Ok(FigureReport {
+        unused: unused.len(),
+        unused_size: unused.iter().map(|figure| figure.size).sum(),
+        figures,
+        missing,
+    })
+}
+
+// Resolve "." and ".." in a project path; None if it leaves the project
+fn normalize(path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
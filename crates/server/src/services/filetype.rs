@@ -0,0 +1,169 @@
+// MIME type and editor language detection
+// Extension decides for text files; magic bytes win for binary formats
+
+use std::path::Path;
+
+use crate::{db::Database, error::Result, services::storage::StorageService};
+
+// Only the start of a file is inspected when sniffing
+const SNIFF_LEN: usize = 8192;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileType {
+    pub mime_type: String,
+    /// Editor language for text files; None means the file is binary
+    pub language: Option<String>,
+}
+
+const MAGIC: &[(&[u8], &str)] = &[
+    (b"%PDF-", "application/pdf"),
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"II*\0", "image/tiff"),
+    (b"MM\0*", "image/tiff"),
+    (b"%!PS", "application/postscript"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+];
+
+const LANGUAGES: &[(&[&str], &str)] = &[
+    (
+        &[
+            "tex", "ltx", "latex", "sty", "cls", "dtx", "ins", "clo", "def", "tikz",
+        ],
+        "latex",
+    ),
+    (&["bib"], "bibtex"),
+    (&["md", "markdown"], "markdown"),
+    (&["py"], "python"),
+    (&["r"], "r"),
+    (&["jl"], "julia"),
+    (&["lua"], "lua"),
+    (&["pl", "pm", "latexmkrc"], "perl"),
+    (&["sh", "bash"], "shell"),
+    (&["json"], "json"),
+    (&["yaml", "yml"], "yaml"),
+    (&["toml"], "toml"),
+    (&["xml", "svg"], "xml"),
+    (&["html", "htm"], "html"),
+    (&["css"], "css"),
+    (&["js"], "javascript"),
+    (&["csv", "tsv"], "csv"),
+    (&["gnuplot", "gp", "plt"], "gnuplot"),
+];
+
+/// Detect the type of a file from its path and (a prefix of) its content.
+pub fn detect(path: &str, content: &[u8]) -> FileType {
+    let head = &content[..content.len().min(SNIFF_LEN)];
+
+    if let Some(mime_type) = sniff(head) {
+        return FileType {
+            mime_type: mime_type.to_string(),
+            language: None,
+        };
+    }
+
+    let file_name = path.rsplit('/').next().unwrap_or(path);
+    let extension = Path::new(file_name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        // Dotfiles such as .latexmkrc are named by what follows the dot
+        .or_else(|| file_name.strip_prefix('.'))
+        .map(str::to_ascii_lowercase);
+    let guessed = extension
+        .as_deref()
+        .and_then(|ext| mime_guess::from_ext(ext).first());
+
+    if !is_text(head) {
+        return FileType {
+            mime_type: guessed
+                .map(|mime| mime.essence_str().to_string())
+                .unwrap_or_else(|| "application/octet-stream".to_string()),
+            language: None,
+        };
+    }
+
+    let language = extension
+        .as_deref()
+        .and_then(|ext| {
+            LANGUAGES
+                .iter()
+                .find(|(extensions, _)| extensions.contains(&ext))
+                .map(|(_, language)| *language)
+        })
+        .unwrap_or("plaintext");
+
+    // Keep the guessed type only when it agrees that this is text
+    let mime_type = guessed
+        .filter(|mime| {
+            mime.type_() == mime_guess::mime::TEXT
+                || matches!(
+                    mime.essence_str(),
+                    "application/x-tex"
+                        | "application/x-latex"
+                        | "application/json"
+                        | "application/xml"
+                        | "application/javascript"
+                        | "application/toml"
+                        | "image/svg+xml"
+                )
+        })
+        .map(|mime| mime.essence_str().to_string())
+        .unwrap_or_else(|| match language {
+            "plaintext" => "text/plain".to_string(),
+            other => format!("text/x-{other}"),
+        });
+
+    FileType {
+        mime_type,
+        language: Some(language.to_string()),
+    }
+}
+
+fn sniff(head: &[u8]) -> Option<&'static str> {
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    MAGIC
+        .iter()
+        .find(|(magic, _)| head.starts_with(magic))
+        .map(|(_, mime_type)| *mime_type)
+}
+
+fn is_text(head: &[u8]) -> bool {
+    if head.contains(&0) {
+        return false;
+    }
+    match std::str::from_utf8(head) {
+        Ok(_) => true,
+        // The prefix may end in the middle of a multi-byte character
+        Err(e) => e.error_len().is_none(),
+    }
+}
+
+/// Fill in types for files stored before detection existed.
+pub async fn backfill(db: &Database, storage: &StorageService) -> Result<usize> {
+    let rows = sqlx::query_as::<_, (String, String, String)>(
+        "SELECT id, project_id, path FROM files WHERE mime_type IS NULL AND is_folder = FALSE",
+    )
+    .fetch_all(&db.pool)
+    .await?;
+
+    let mut updated = 0;
+    for (id, project_id, path) in rows {
+        let Ok(content) = storage.read_bytes(&project_id, &path).await else {
+            continue;
+        };
+        let file_type = detect(&path, &content);
+        sqlx::query("UPDATE files SET mime_type = $1, language = $2 WHERE id = $3")
+            .bind(&file_type.mime_type)
+            .bind(&file_type.language)
+            .bind(&id)
+            .execute(&db.pool)
+            .await?;
+        updated += 1;
+    }
+    Ok(updated)
+}
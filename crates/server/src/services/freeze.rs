@@ -0,0 +1,62 @@
+// Frozen projects
+// An owner can freeze a project, typically once it has been submitted, so
+// nobody changes what was sent off by mistake. Files cannot be edited,
+// created, moved or deleted while it is frozen, through the API or the
+// editor, but it can still be read, compiled, exported and commented on.
+
+use chrono::Utc;
+
+use crate::{
+    db::DbPool,
+    error::{AppError, Result},
+};
+
+/// When the project was frozen, or None if it is not.
+pub async fn frozen_at(pool: &DbPool, project_id: &str) -> Result<Option<String>> {
+    let frozen_at =
+        sqlx::query_scalar::<_, Option<String>>("SELECT frozen_at FROM projects WHERE id = $1")
+            .bind(project_id)
+            .fetch_optional(pool)
+            .await?
+            .flatten();
+    Ok(frozen_at)
+}
+
+/// Reject a change to the project's files while it is frozen.
+pub async fn ensure_writable(pool: &DbPool, project_id: &str) -> Result<()> {
+    match frozen_at(pool, project_id).await? {
+        Some(_) => Err(frozen_error()),
+        None => Ok(()),
+    }
+}
+
+pub fn frozen_error() -> AppError {
+    AppError::Conflict(
+        "The project is frozen; its owner must unfreeze it before it can be changed".to_string(),
+    )
+}
+
+/// Freeze the project on behalf of `user_id`, or unfreeze it when None.
+/// Freezing a frozen project keeps its original time.
+pub async fn set(pool: &DbPool, project_id: &str, user_id: Option<&str>) -> Result<()> {
+    match user_id {
+        Some(user_id) => {
+            sqlx::query(
+                "UPDATE projects SET frozen_at = $1, frozen_by = $2 \
+                 WHERE id = $3 AND frozen_at IS NULL",
+            )
+            .bind(Utc::now().to_rfc3339())
+            .bind(user_id)
+            .bind(project_id)
+            .execute(pool)
+            .await?;
+        }
+        None => {
+            sqlx::query("UPDATE projects SET frozen_at = NULL, frozen_by = NULL WHERE id = $1")
+                .bind(project_id)
+                .execute(pool)
+                .await?;
+        }
+    }
+    Ok(())
+}
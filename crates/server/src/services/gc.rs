@@ -0,0 +1,263 @@
+// Garbage collection for orphaned storage
+// Finds project directories and files on disk that no database row refers to,
+// such as the leftovers of a project delete that crashed half way
+
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, SystemTime};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    db::{Database, DbPool},
+    error::{AppError, Result},
+    services::{exclude::ExcludeRules, storage::StorageService},
+};
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct GcReport {
+    /// Top-level storage directories with no matching project
+    pub orphaned_projects: Vec<String>,
+    /// Files and folders inside existing projects with no matching file row
+    pub orphaned_files: Vec<OrphanedFile>,
+    /// Local working copies of projects that no longer exist (remote backends only)
+    pub orphaned_work_dirs: Vec<String>,
+    /// Stored objects no project version or template refers to, such as the
+    /// contents of versions of deleted projects
+    pub orphaned_objects: Vec<String>,
+    /// Whether the orphans were deleted or only reported
+    pub deleted: bool,
+}
+
+// Queries for the hashes of stored objects each table refers to
+const OBJECT_REFERENCES: [&str; 3] = [
+    "SELECT hash FROM project_version_files WHERE hash IS NOT NULL",
+    "SELECT hash FROM template_files",
+    "SELECT preview_hash AS hash FROM templates WHERE preview_hash IS NOT NULL",
+];
+
+// How long anything written is left alone. A file or object is written
+// before the row that refers to it is committed, so a young one may only be
+// waiting for its row.
+const MIN_AGE: Duration = Duration::from_secs(60 * 60);
+
+// Whether something was written within MIN_AGE; clocks that put it in the
+// future count as recent too
+fn is_recent(modified: Option<SystemTime>) -> bool {
+    modified.is_some_and(|modified| modified.elapsed().map_or(true, |age| age < MIN_AGE))
+}
+
+/// Whether a version or template still uses a stored object.
+pub async fn object_in_use(pool: &DbPool, hash: &str) -> Result<bool> {
+    for references in OBJECT_REFERENCES {
+        let used = sqlx::query_scalar::<_, i64>(&format!(
+            "SELECT COUNT(*) FROM ({references}) refs WHERE refs.hash = $1"
+        ))
+        .bind(hash)
+        .fetch_one(pool)
+        .await?;
+        if used > 0 {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct OrphanedFile {
+    pub project_id: String,
+    pub path: String,
+    pub is_folder: bool,
+}
+
+impl GcReport {
+    pub fn is_empty(&self) -> bool {
+        self.orphaned_projects.is_empty()
+            && self.orphaned_files.is_empty()
+            && self.orphaned_work_dirs.is_empty()
+            && self.orphaned_objects.is_empty()
+    }
+}
+
+/// Find orphaned storage older than an hour, deleting it when `delete` is set.
+pub async fn collect(db: &Database, storage: &StorageService, delete: bool) -> Result<GcReport> {
+    let on_disk = storage.list_all().await?;
+    let objects = storage.list_objects().await?;
+    let work_dirs = if storage.is_local() {
+        Vec::new()
+    } else {
+        list_work_dirs(storage).await?
+    };
+
+    let projects: HashSet<String> = sqlx::query_scalar::<_, String>("SELECT id FROM projects")
+        .fetch_all(&db.pool)
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut report = GcReport {
+        deleted: delete,
+        ..Default::default()
+    };
+
+    // Paths written within MIN_AGE, with every folder above them, so a folder
+    // that is still being filled is not taken for an orphan either
+    let mut recent: HashSet<&str> = HashSet::new();
+    for entry in on_disk.iter().filter(|entry| is_recent(entry.modified)) {
+        let mut path = entry.path.as_str();
+        recent.insert(path);
+        while let Some((parent, _)) = path.rsplit_once('/') {
+            recent.insert(parent);
+            path = parent;
+        }
+    }
+
+    // Group what's on disk by the project directory it belongs to
+    let mut by_project: HashMap<&str, Vec<(&str, bool)>> = HashMap::new();
+    for entry in &on_disk {
+        match entry.path.split_once('/') {
+            Some((project_id, path)) => by_project
+                .entry(project_id)
+                .or_default()
+                .push((path, entry.is_folder)),
+            None if entry.is_folder => {
+                by_project.entry(&entry.path).or_default();
+            }
+            None => {}
+        }
+    }
+
+    let mut project_ids: Vec<&str> = by_project.keys().copied().collect();
+    project_ids.sort_unstable();
+
+    for project_id in project_ids {
+        if !projects.contains(project_id) {
+            if !recent.contains(project_id) {
+                report.orphaned_projects.push(project_id.to_string());
+            }
+            continue;
+        }
+
+        let known: HashSet<String> =
+            sqlx::query_scalar::<_, String>("SELECT path FROM files WHERE project_id = $1")
+                .bind(project_id)
+                .fetch_all(&db.pool)
+                .await?
+                .into_iter()
+                .collect();
+        // Build artifacts are expected to exist without rows
+        let rules = ExcludeRules::load(storage, project_id).await?;
+
+        let mut orphaned_folders: Vec<&str> = Vec::new();
+        for &(path, is_folder) in &by_project[project_id] {
+            if known.contains(path)
+                || rules.is_excluded(path, is_folder)
+                || recent.contains(format!("{project_id}/{path}").as_str())
+            {
+                continue;
+            }
+            // Entries are sorted, so an orphaned folder precedes its contents
+            // and reporting the folder covers everything below it
+            if orphaned_folders
+                .iter()
+                .any(|folder| path.starts_with(&format!("{folder}/")))
+            {
+                continue;
+            }
+            if is_folder {
+                orphaned_folders.push(path);
+            }
+            report.orphaned_files.push(OrphanedFile {
+                project_id: project_id.to_string(),
+                path: path.to_string(),
+                is_folder,
+            });
+        }
+    }
+
+    report.orphaned_work_dirs = work_dirs
+        .into_iter()
+        .filter(|project_id| !projects.contains(project_id))
+        .collect();
+
+    let mut referenced = HashSet::new();
+    for references in OBJECT_REFERENCES {
+        referenced.extend(
+            sqlx::query_scalar::<_, String>(references)
+                .fetch_all(&db.pool)
+                .await?,
+        );
+    }
+    report.orphaned_objects = objects
+        .into_iter()
+        .filter(|object| !referenced.contains(&object.path) && !is_recent(object.modified))
+        .map(|object| object.path)
+        .collect();
+
+    if delete {
+        for project_id in &report.orphaned_projects {
+            storage.delete_project_dir(project_id).await?;
+        }
+        for file in &report.orphaned_files {
+            storage.delete_file(&file.project_id, &file.path).await?;
+        }
+        for project_id in &report.orphaned_work_dirs {
+            tokio::fs::remove_dir_all(storage.project_path(project_id))
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to delete working copy: {e}")))?;
+        }
+        for hash in &report.orphaned_objects {
+            storage.delete_object(hash).await?;
+        }
+    }
+
+    Ok(report)
+}
+
+async fn list_work_dirs(storage: &StorageService) -> Result<Vec<String>> {
+    let io_error = |e: std::io::Error| AppError::Internal(format!("Failed to list work dir: {e}"));
+
+    let mut dirs = Vec::new();
+    let mut entries = match tokio::fs::read_dir(storage.base_path()).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(dirs),
+        Err(e) => return Err(io_error(e)),
+    };
+    while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+        let metadata = entry.metadata().await.map_err(io_error)?;
+        if !metadata.is_dir() || is_recent(metadata.modified().ok()) {
+            continue;
+        }
+        if let Some(name) = entry.file_name().to_str() {
+            if !name.starts_with('.') {
+                dirs.push(name.to_string());
+            }
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+/// Sweep orphaned storage on a fixed interval, starting one interval after launch.
+pub fn spawn_scheduler(db: Database, storage: StorageService, interval: Duration, delete: bool) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            match collect(&db, &storage, delete).await {
+                Ok(report) if report.is_empty() => {}
+                Ok(report) => tracing::warn!(
+                    "Orphaned storage {}: {} projects, {} files, {} working copies, {} objects",
+                    if delete { "deleted" } else { "found" },
+                    report.orphaned_projects.len(),
+                    report.orphaned_files.len(),
+                    report.orphaned_work_dirs.len(),
+                    report.orphaned_objects.len()
+                ),
+                Err(e) => tracing::warn!("Storage garbage collection failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,1590 @@
+// Git backing
+// A project can keep its history in a bare git repository, one per project
+// under GIT_PATH, which any git tool can read. Commits are built from the
+// project's stored files, on request and on a schedule while the project
+// changes. Checking out a commit puts its files back into the project the way
+// restoring a version does. A project can also be linked to a remote, such as
+// a GitHub repository, and pushed to or pulled from with the requesting
+// user's token for that host. Members can also clone a project over smart
+// HTTP and push back to it, which updates the project's files. The
+// repositories have no working tree: the server drives the git binary and
+// writes commits with fast-import.
+
+use std::collections::BTreeSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::process::{Output, Stdio};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use axum::body::Bytes;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, ReadBuf};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    db::Database,
+    error::{AppError, Result},
+    middleware::auth::AuthUser,
+    services::{
+        collab::CollabService,
+        compiler,
+        git_credentials::{normalize_host, GitCredentials},
+        storage::{content_hash, StorageService},
+        versions::{self, VersionFile},
+    },
+};
+
+const BRANCH: &str = "refs/heads/main";
+
+// Who commits made by the server itself are from
+const SERVER_NAME: &str = "OpenLeaf";
+const SERVER_EMAIL: &str = "openleaf@localhost";
+
+// Fields of a commit as printed by `git log`, NUL-separated, one commit per
+// record separator
+const LOG_FORMAT: &str = "--format=%H%x00%P%x00%an%x00%ae%x00%aI%x00%B%x1e";
+
+// The tree with nothing in it, which a root commit is diffed against
+const EMPTY_TREE: &str = "4b825dc642cb6eb9a060e54bf8d69288fbee4904";
+
+// Patch text a diff returns before it is cut short
+const MAX_PATCH_BYTES: usize = 1024 * 1024;
+
+// Time a push or pull may take
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Commit {
+    pub sha: String,
+    pub parents: Vec<String>,
+    pub author_name: String,
+    pub author_email: String,
+    pub date: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Remote {
+    pub url: String,
+    pub branch: String,
+    pub last_push_at: Option<String>,
+    pub last_pull_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GitStatus {
+    pub enabled: bool,
+    pub auto_commit: bool,
+    /// The latest commit, None before the first
+    pub head: Option<Commit>,
+    pub remote: Option<Remote>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DiffFile {
+    pub path: String,
+    /// Where the file was before a rename
+    pub old_path: Option<String>,
+    /// Line counts; None for binary files
+    pub additions: Option<u64>,
+    pub deletions: Option<u64>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GitDiff {
+    pub from: String,
+    pub to: String,
+    pub files: Vec<DiffFile>,
+    pub patch: String,
+    /// Whether the patch was cut short
+    pub truncated: bool,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CheckoutReport {
+    pub commit: String,
+    pub restored: usize,
+    pub removed: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PullOutcome {
+    UpToDate,
+    FastForward,
+    Merged,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PullReport {
+    pub outcome: PullOutcome,
+    pub head: Commit,
+    /// Files the pull changed in the project
+    pub restored: usize,
+    pub removed: usize,
+}
+
+/// One smart HTTP request to a project's repository.
+pub struct HttpRequest<'a> {
+    pub method: &'a str,
+    /// What follows the repository in the URL, such as "/info/refs"
+    pub path: &'a str,
+    pub query: &'a str,
+    pub content_type: Option<&'a str>,
+    pub content_encoding: Option<&'a str>,
+    /// The client's Git-Protocol header
+    pub git_protocol: Option<&'a str>,
+}
+
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Box<dyn AsyncRead + Send + Unpin>,
+    /// Whether a push changed the project's files
+    pub files_changed: bool,
+}
+
+/// Output of `git http-backend` still being read; owning the process keeps
+/// it running until the response is sent.
+struct BackendOutput {
+    stdout: BufReader<ChildStdout>,
+    _child: Child,
+}
+
+impl AsyncRead for BackendOutput {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stdout).poll_read(cx, buf)
+    }
+}
+
+fn git_error(e: io::Error) -> AppError {
+    AppError::Internal(format!("Failed to run git: {e}"))
+}
+
+fn not_enabled() -> AppError {
+    AppError::NotFound("Git is not enabled for this project".to_string())
+}
+
+/// Check that a revision names a commit rather than smuggling in an option.
+fn check_rev(rev: &str) -> Result<()> {
+    let valid = !rev.is_empty()
+        && rev.len() <= 256
+        && !rev.starts_with('-')
+        && rev
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '/' | '~' | '^' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::Validation(format!("Invalid revision: {rev}")))
+    }
+}
+
+/// A path as fast-import reads it: quoted, with C-style escapes.
+fn quote_path(path: &str) -> String {
+    let mut quoted = String::with_capacity(path.len() + 2);
+    quoted.push('"');
+    for c in path.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A name or email as it may appear in a commit header.
+fn ident(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !matches!(c, '<' | '>' | '\n' | '\0'))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// Check that a remote branch name is one git accepts.
+fn check_branch(branch: &str) -> Result<()> {
+    let valid = !branch.is_empty()
+        && branch.len() <= 256
+        && !branch.starts_with(['-', '/', '.'])
+        && !branch.ends_with(['/', '.'])
+        && !branch.ends_with(".lock")
+        && !branch.contains("..")
+        && !branch.contains("//")
+        && branch
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '/' | '-'));
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::Validation(format!("Invalid branch: {branch}")))
+    }
+}
+
+/// Check a remote URL and return its host, as credentials are kept by.
+fn remote_host(url: &str) -> Result<String> {
+    let invalid = || AppError::Validation(format!("Invalid remote URL: {url}"));
+    let parsed = reqwest::Url::parse(url).map_err(|_| invalid())?;
+    if parsed.scheme() != "https" {
+        return Err(AppError::Validation(
+            "Remote URL must use https".to_string(),
+        ));
+    }
+    if !parsed.username().is_empty() || parsed.password().is_some() {
+        return Err(AppError::Validation(
+            "Remote URL must not hold credentials; add a token for its host instead".to_string(),
+        ));
+    }
+    let host = parsed.host_str().ok_or_else(invalid)?;
+    match parsed.port() {
+        Some(port) => normalize_host(&format!("{host}:{port}")),
+        None => normalize_host(host),
+    }
+}
+
+/// The error for a push or pull git gave up on.
+fn remote_error(action: &str, host: &str, stderr: &[u8]) -> AppError {
+    let stderr = String::from_utf8_lossy(stderr);
+    let stderr = stderr.trim();
+    if stderr.contains("Authentication failed")
+        || stderr.contains("could not read Username")
+        || stderr.contains("403")
+    {
+        AppError::BadRequest(format!("{host} refused access; check your token for it"))
+    } else {
+        AppError::BadRequest(format!("{action} failed: {stderr}"))
+    }
+}
+
+fn short(sha: &str) -> &str {
+    &sha[..sha.len().min(7)]
+}
+
+fn parse_log(output: &[u8]) -> Vec<Commit> {
+    String::from_utf8_lossy(output)
+        .split('\x1e')
+        .filter_map(|record| {
+            let mut fields = record.trim_start_matches('\n').splitn(6, '\0');
+            Some(Commit {
+                sha: fields.next().filter(|sha| !sha.is_empty())?.to_string(),
+                parents: fields
+                    .next()?
+                    .split_whitespace()
+                    .map(str::to_string)
+                    .collect(),
+                author_name: fields.next()?.to_string(),
+                author_email: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                message: fields.next()?.trim_end().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Files of `git diff --numstat -z`. A rename leaves the path empty and
+/// follows with the old and new paths.
+fn parse_numstat(output: &[u8]) -> Vec<DiffFile> {
+    let output = String::from_utf8_lossy(output);
+    let mut tokens = output.split('\0');
+    let mut files = Vec::new();
+    while let Some(token) = tokens.next() {
+        let mut fields = token.splitn(3, '\t');
+        let (Some(additions), Some(deletions), Some(path)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            continue;
+        };
+        let (path, old_path) = if path.is_empty() {
+            let old_path = tokens.next().unwrap_or_default().to_string();
+            (
+                tokens.next().unwrap_or_default().to_string(),
+                Some(old_path),
+            )
+        } else {
+            (path.to_string(), None)
+        };
+        files.push(DiffFile {
+            path,
+            old_path,
+            additions: additions.parse().ok(),
+            deletions: deletions.parse().ok(),
+        });
+    }
+    files
+}
+
+#[derive(Clone)]
+pub struct GitService {
+    root: PathBuf,
+    credentials: GitCredentials,
+    // Commits and checkouts run one at a time, so two never race to move a
+    // branch
+    writing: Arc<Mutex<()>>,
+}
+
+impl GitService {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            root: PathBuf::from(&config.git.path),
+            credentials: GitCredentials::from_config(config)?,
+            writing: Arc::default(),
+        })
+    }
+
+    pub fn credentials(&self) -> &GitCredentials {
+        &self.credentials
+    }
+
+    fn repo(&self, project_id: &str) -> PathBuf {
+        self.root.join(format!("{project_id}.git"))
+    }
+
+    fn command(&self, project_id: &str) -> Command {
+        let mut command = Command::new("git");
+        command
+            .arg("--git-dir")
+            .arg(self.repo(project_id))
+            // Settings of whoever runs the server have no say in the repository
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        command
+    }
+
+    async fn run(&self, project_id: &str, args: &[&str]) -> Result<Output> {
+        self.command(project_id)
+            .args(args)
+            .output()
+            .await
+            .map_err(git_error)
+    }
+
+    async fn git(&self, project_id: &str, args: &[&str]) -> Result<Vec<u8>> {
+        let output = self.run(project_id, args).await?;
+        if !output.status.success() {
+            return Err(AppError::Internal(format!(
+                "git {} failed: {}",
+                args[0],
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(output.stdout)
+    }
+
+    /// The commit a revision names, if any.
+    async fn resolve(&self, project_id: &str, rev: &str) -> Result<Option<String>> {
+        check_rev(rev)?;
+        let output = self
+            .run(
+                project_id,
+                &[
+                    "rev-parse",
+                    "--verify",
+                    "--quiet",
+                    &format!("{rev}^{{commit}}"),
+                ],
+            )
+            .await?;
+        Ok(output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+    }
+
+    async fn commit_info(&self, project_id: &str, sha: &str) -> Result<Commit> {
+        let output = self
+            .git(project_id, &["log", LOG_FORMAT, "--max-count=1", sha])
+            .await?;
+        parse_log(&output)
+            .into_iter()
+            .next()
+            .ok_or_else(|| AppError::NotFound("Commit not found".to_string()))
+    }
+
+    pub async fn status(&self, db: &Database, project_id: &str) -> Result<GitStatus> {
+        let row = sqlx::query_as::<
+            _,
+            (
+                bool,
+                Option<String>,
+                Option<String>,
+                Option<String>,
+                Option<String>,
+            ),
+        >(
+            "SELECT auto_commit, remote_url, remote_branch, last_push_at, last_pull_at \
+             FROM project_git WHERE project_id = $1",
+        )
+        .bind(project_id)
+        .fetch_optional(&db.pool)
+        .await?;
+        let Some((auto_commit, remote_url, remote_branch, last_push_at, last_pull_at)) = row else {
+            return Ok(GitStatus {
+                enabled: false,
+                auto_commit: false,
+                head: None,
+                remote: None,
+            });
+        };
+
+        let head = match self.resolve(project_id, BRANCH).await? {
+            Some(sha) => Some(self.commit_info(project_id, &sha).await?),
+            None => None,
+        };
+        Ok(GitStatus {
+            enabled: true,
+            auto_commit,
+            head,
+            remote: remote_url.map(|url| Remote {
+                url,
+                branch: remote_branch.unwrap_or_else(|| "main".to_string()),
+                last_push_at,
+                last_pull_at,
+            }),
+        })
+    }
+
+    /// Back a project with a repository and commit its files. Enabling an
+    /// enabled project only updates `auto_commit`.
+    pub async fn enable(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+        user_id: &str,
+        auto_commit: bool,
+    ) -> Result<GitStatus> {
+        let repo = self.repo(project_id);
+        if !tokio::fs::try_exists(&repo).await.map_err(git_error)? {
+            tokio::fs::create_dir_all(&self.root).await.map_err(|e| {
+                AppError::Internal(format!("Failed to create {:?}: {e}", self.root))
+            })?;
+            let output = Command::new("git")
+                .args(["init", "--bare", "--quiet", "--initial-branch=main"])
+                .arg(&repo)
+                .env("GIT_CONFIG_NOSYSTEM", "1")
+                .env("GIT_CONFIG_GLOBAL", "/dev/null")
+                .output()
+                .await
+                .map_err(git_error)?;
+            if !output.status.success() {
+                return Err(AppError::Internal(format!(
+                    "git init failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )));
+            }
+        }
+
+        sqlx::query(
+            "INSERT INTO project_git (project_id, auto_commit, created_at) VALUES ($1, $2, $3) \
+             ON CONFLICT (project_id) DO UPDATE SET auto_commit = excluded.auto_commit",
+        )
+        .bind(project_id)
+        .bind(auto_commit)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&db.pool)
+        .await?;
+
+        self.commit(
+            db,
+            storage,
+            collab,
+            project_id,
+            Some(user_id),
+            "Initial commit",
+        )
+        .await?;
+        self.status(db, project_id).await
+    }
+
+    /// Stop backing a project with a repository, deleting it.
+    pub async fn disable(&self, db: &Database, project_id: &str) -> Result<()> {
+        let _writing = self.writing.lock().await;
+        let deleted = sqlx::query("DELETE FROM project_git WHERE project_id = $1")
+            .bind(project_id)
+            .execute(&db.pool)
+            .await?;
+        if deleted.rows_affected() == 0 {
+            return Err(not_enabled());
+        }
+        self.delete_repo(project_id).await
+    }
+
+    /// Delete a project's repository, if it has one.
+    pub async fn delete_repo(&self, project_id: &str) -> Result<()> {
+        match tokio::fs::remove_dir_all(self.repo(project_id)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(AppError::Internal(format!(
+                "Failed to delete git repository: {e}"
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Commit the project's files as they are now, by `user_id` or by the
+    /// server. Returns None when nothing changed since the last commit.
+    pub async fn commit(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+        user_id: Option<&str>,
+        message: &str,
+    ) -> Result<Option<Commit>> {
+        let _writing = self.writing.lock().await;
+        self.commit_locked(db, storage, collab, project_id, user_id, message)
+            .await
+    }
+
+    async fn commit_locked(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+        user_id: Option<&str>,
+        message: &str,
+    ) -> Result<Option<Commit>> {
+        let committed = sqlx::query_scalar::<_, Option<String>>(
+            "SELECT fingerprint FROM project_git WHERE project_id = $1",
+        )
+        .bind(project_id)
+        .fetch_optional(&db.pool)
+        .await?
+        .ok_or_else(not_enabled)?;
+
+        // Include what is being typed right now
+        collab.persist_project(project_id).await?;
+
+        let files = sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT path, hash FROM files WHERE project_id = $1 AND is_folder = FALSE ORDER BY path",
+        )
+        .bind(project_id)
+        .fetch_all(&db.pool)
+        .await?;
+        let mut listing = String::new();
+        for (path, hash) in &files {
+            let hash = match hash {
+                Some(hash) => hash.clone(),
+                // Rows from before hashes were recorded
+                None => storage.hash_file(project_id, path).await?,
+            };
+            listing.push_str(&format!("{path}\0{hash}\n"));
+        }
+        let fingerprint = content_hash(listing.as_bytes());
+
+        let parent = self.resolve(project_id, BRANCH).await?;
+        if parent.is_some() && committed.as_deref() == Some(fingerprint.as_str()) {
+            return Ok(None);
+        }
+
+        let (name, email) = identity(db, user_id).await?;
+
+        let now = Utc::now().timestamp();
+        let mut header = format!(
+            "commit {BRANCH}\nauthor {} <{}> {now} +0000\ncommitter {SERVER_NAME} <{SERVER_EMAIL}> {now} +0000\ndata {}\n{message}\n",
+            ident(&name),
+            ident(&email),
+            message.len(),
+        );
+        if let Some(parent) = &parent {
+            header.push_str(&format!("from {parent}\n"));
+        }
+        header.push_str("deleteall\n");
+
+        let mut child = self
+            .command(project_id)
+            .args(["fast-import", "--quiet", "--date-format=raw"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(git_error)?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+
+        let mut written: Result<()> = async {
+            stdin
+                .write_all(header.as_bytes())
+                .await
+                .map_err(git_error)?;
+            for (path, _) in &files {
+                let content = storage.read_bytes(project_id, path).await?;
+                let entry = format!(
+                    "M 100644 inline {}\ndata {}\n",
+                    quote_path(path),
+                    content.len()
+                );
+                stdin.write_all(entry.as_bytes()).await.map_err(git_error)?;
+                stdin.write_all(&content).await.map_err(git_error)?;
+                stdin.write_all(b"\n").await.map_err(git_error)?;
+            }
+            Ok(())
+        }
+        .await;
+        drop(stdin);
+
+        let output = child.wait_with_output().await.map_err(git_error)?;
+        if !output.status.success() {
+            // What git says beats the broken pipe the write ran into
+            written = Err(AppError::Internal(format!(
+                "git fast-import failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        written?;
+
+        let head = self
+            .resolve(project_id, BRANCH)
+            .await?
+            .ok_or_else(|| AppError::Internal("git fast-import made no commit".to_string()))?;
+
+        // Files can differ from the last commit's fingerprint and still match
+        // its tree, as when the repository outlived an earlier enabling
+        let mut commit = Some(head.clone());
+        if let Some(parent) = &parent {
+            let trees = self
+                .git(
+                    project_id,
+                    &[
+                        "rev-parse",
+                        &format!("{parent}^{{tree}}"),
+                        &format!("{head}^{{tree}}"),
+                    ],
+                )
+                .await?;
+            let trees = String::from_utf8_lossy(&trees);
+            let mut trees = trees.lines();
+            if trees.next() == trees.next() {
+                self.git(project_id, &["update-ref", BRANCH, parent, &head])
+                    .await?;
+                commit = None;
+            }
+        }
+
+        sqlx::query(
+            "UPDATE project_git SET fingerprint = $1, last_commit_at = COALESCE($2, last_commit_at) WHERE project_id = $3",
+        )
+        .bind(&fingerprint)
+        .bind(commit.as_ref().map(|_| Utc::now().to_rfc3339()))
+        .bind(project_id)
+        .execute(&db.pool)
+        .await?;
+
+        match commit {
+            Some(sha) => Ok(Some(self.commit_info(project_id, &sha).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Commits, newest first.
+    pub async fn log(
+        &self,
+        db: &Database,
+        project_id: &str,
+        limit: usize,
+        skip: usize,
+    ) -> Result<Vec<Commit>> {
+        self.ensure_enabled(db, project_id).await?;
+        if self.resolve(project_id, BRANCH).await?.is_none() {
+            return Ok(Vec::new());
+        }
+        let output = self
+            .git(
+                project_id,
+                &[
+                    "log",
+                    LOG_FORMAT,
+                    &format!("--max-count={limit}"),
+                    &format!("--skip={skip}"),
+                    BRANCH,
+                ],
+            )
+            .await?;
+        Ok(parse_log(&output))
+    }
+
+    /// Changes between two commits. `to` defaults to the latest commit and
+    /// `from` to its parent; `path` limits the diff to one file or folder.
+    pub async fn diff(
+        &self,
+        db: &Database,
+        project_id: &str,
+        from: Option<&str>,
+        to: Option<&str>,
+        path: Option<&str>,
+    ) -> Result<GitDiff> {
+        self.ensure_enabled(db, project_id).await?;
+        let commit_not_found = || AppError::NotFound("Commit not found".to_string());
+
+        let to = self
+            .resolve(project_id, to.unwrap_or(BRANCH))
+            .await?
+            .ok_or_else(commit_not_found)?;
+        let from = match from {
+            Some(from) => self
+                .resolve(project_id, from)
+                .await?
+                .ok_or_else(commit_not_found)?,
+            None => self
+                .resolve(project_id, &format!("{to}^"))
+                .await?
+                .unwrap_or_else(|| EMPTY_TREE.to_string()),
+        };
+
+        let mut args = vec![
+            "diff",
+            "--no-color",
+            "--no-ext-diff",
+            "--find-renames",
+            from.as_str(),
+            to.as_str(),
+            "--",
+        ];
+        args.extend(path);
+        let patch = self.git(project_id, &args).await?;
+        args.insert(1, "--numstat");
+        args.insert(2, "-z");
+        let files = parse_numstat(&self.git(project_id, &args).await?);
+
+        let mut patch = String::from_utf8_lossy(&patch).into_owned();
+        let truncated = patch.len() > MAX_PATCH_BYTES;
+        if truncated {
+            let mut end = MAX_PATCH_BYTES;
+            while !patch.is_char_boundary(end) {
+                end -= 1;
+            }
+            patch.truncate(end);
+        }
+
+        Ok(GitDiff {
+            from,
+            to,
+            files,
+            patch,
+            truncated,
+        })
+    }
+
+    /// Put the project's files back the way a commit has them. The current
+    /// state is committed first and the result is committed again, so the
+    /// branch only ever moves forward.
+    pub async fn checkout(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+        rev: &str,
+        user_id: &str,
+    ) -> Result<CheckoutReport> {
+        let _writing = self.writing.lock().await;
+        self.ensure_enabled(db, project_id).await?;
+        let sha = self
+            .resolve(project_id, rev)
+            .await?
+            .ok_or_else(|| AppError::NotFound("Commit not found".to_string()))?;
+
+        self.commit_locked(
+            db,
+            storage,
+            collab,
+            project_id,
+            Some(user_id),
+            &format!("Save before checking out {}", short(&sha)),
+        )
+        .await?;
+        let (restored, removed) = self
+            .apply_commit(db, storage, collab, project_id, &sha, user_id)
+            .await?;
+
+        self.commit_locked(
+            db,
+            storage,
+            collab,
+            project_id,
+            Some(user_id),
+            &format!("Check out {}", short(&sha)),
+        )
+        .await?;
+
+        Ok(CheckoutReport {
+            commit: sha,
+            restored,
+            removed,
+        })
+    }
+
+    /// Make the project's files match a commit. Returns how many files were
+    /// restored and how many removed.
+    async fn apply_commit(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+        sha: &str,
+        user_id: &str,
+    ) -> Result<(usize, usize)> {
+        // The commit's files travel into the project as a version, which keeps
+        // their stored contents in use until applied and is dropped afterwards
+        let files = self.tree_files(storage, project_id, sha).await?;
+        let version = versions::insert(
+            db,
+            project_id,
+            user_id,
+            &format!("Git commit {}", short(sha)),
+            None,
+            &files,
+        )
+        .await?;
+        let applied = versions::apply(db, storage, collab, project_id, &files, user_id).await;
+        versions::delete(&db.pool, storage, project_id, &version.id).await?;
+        applied
+    }
+
+    /// Link the project to a remote repository and the branch on it to sync
+    /// with.
+    pub async fn set_remote(
+        &self,
+        db: &Database,
+        project_id: &str,
+        url: &str,
+        branch: &str,
+    ) -> Result<GitStatus> {
+        remote_host(url)?;
+        check_branch(branch)?;
+        let updated = sqlx::query(
+            "UPDATE project_git SET remote_url = $1, remote_branch = $2, last_push_at = NULL, last_pull_at = NULL \
+             WHERE project_id = $3",
+        )
+        .bind(url)
+        .bind(branch)
+        .bind(project_id)
+        .execute(&db.pool)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(not_enabled());
+        }
+        self.status(db, project_id).await
+    }
+
+    pub async fn remove_remote(&self, db: &Database, project_id: &str) -> Result<()> {
+        let updated = sqlx::query(
+            "UPDATE project_git SET remote_url = NULL, remote_branch = NULL, last_push_at = NULL, last_pull_at = NULL \
+             WHERE project_id = $1",
+        )
+        .bind(project_id)
+        .execute(&db.pool)
+        .await?;
+        if updated.rows_affected() == 0 {
+            return Err(not_enabled());
+        }
+        Ok(())
+    }
+
+    /// The remote URL and branch a project syncs with.
+    async fn remote(&self, db: &Database, project_id: &str) -> Result<(String, String)> {
+        let (url, branch) = sqlx::query_as::<_, (Option<String>, Option<String>)>(
+            "SELECT remote_url, remote_branch FROM project_git WHERE project_id = $1",
+        )
+        .bind(project_id)
+        .fetch_optional(&db.pool)
+        .await?
+        .ok_or_else(not_enabled)?;
+        let url = url.ok_or_else(|| {
+            AppError::BadRequest("Link the project to a remote repository first".to_string())
+        })?;
+        Ok((url, branch.unwrap_or_else(|| "main".to_string())))
+    }
+
+    /// Run git against a remote, signed in with the user's token for its
+    /// host if they have one.
+    async fn run_remote(
+        &self,
+        db: &Database,
+        project_id: &str,
+        user_id: &str,
+        host: &str,
+        args: &[&str],
+    ) -> Result<Output> {
+        let mut command = self.command(project_id);
+        command
+            .env("GIT_ALLOW_PROTOCOL", "https")
+            .env_remove("GIT_ASKPASS")
+            .env_remove("SSH_ASKPASS")
+            .args(args);
+        // Passed in the environment, where other users cannot read it, rather
+        // than on the command line or in the repository's config
+        if let Some((username, token)) = self.credentials.get(&db.pool, user_id, host).await? {
+            let basic = STANDARD.encode(format!("{username}:{token}"));
+            command
+                .env("GIT_CONFIG_COUNT", "1")
+                .env("GIT_CONFIG_KEY_0", "http.extraHeader")
+                .env(
+                    "GIT_CONFIG_VALUE_0",
+                    format!("Authorization: Basic {basic}"),
+                );
+        }
+
+        match tokio::time::timeout(REMOTE_TIMEOUT, command.output()).await {
+            Ok(output) => output.map_err(git_error),
+            Err(_) => Err(AppError::BadRequest(format!(
+                "{host} did not answer in time"
+            ))),
+        }
+    }
+
+    /// Commit the project and push it to its remote branch. A push that would
+    /// drop commits on the remote is refused.
+    pub async fn push(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+        user_id: &str,
+    ) -> Result<Commit> {
+        let _writing = self.writing.lock().await;
+        let (url, branch) = self.remote(db, project_id).await?;
+        let host = remote_host(&url)?;
+
+        self.commit_locked(
+            db,
+            storage,
+            collab,
+            project_id,
+            Some(user_id),
+            "Update project",
+        )
+        .await?;
+        let head = self
+            .resolve(project_id, BRANCH)
+            .await?
+            .ok_or_else(|| AppError::BadRequest("Nothing to push yet".to_string()))?;
+
+        let refspec = format!("{BRANCH}:refs/heads/{branch}");
+        let output = self
+            .run_remote(
+                db,
+                project_id,
+                user_id,
+                &host,
+                &["push", "--quiet", &url, &refspec],
+            )
+            .await?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("[rejected]") || stderr.contains("non-fast-forward") {
+                return Err(AppError::Conflict(
+                    "The remote has commits this project does not; pull first".to_string(),
+                ));
+            }
+            return Err(remote_error("Push", &host, &output.stderr));
+        }
+
+        sqlx::query("UPDATE project_git SET last_push_at = $1 WHERE project_id = $2")
+            .bind(Utc::now().to_rfc3339())
+            .bind(project_id)
+            .execute(&db.pool)
+            .await?;
+        self.commit_info(project_id, &head).await
+    }
+
+    /// Fetch the remote branch and bring its changes into the project,
+    /// merging when both sides have new commits. A merge with conflicts is
+    /// refused and changes nothing.
+    pub async fn pull(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+        user_id: &str,
+    ) -> Result<PullReport> {
+        let _writing = self.writing.lock().await;
+        let (url, branch) = self.remote(db, project_id).await?;
+        let host = remote_host(&url)?;
+
+        let refspec = format!("refs/heads/{branch}");
+        let output = self
+            .run_remote(
+                db,
+                project_id,
+                user_id,
+                &host,
+                &["fetch", "--quiet", "--no-tags", &url, &refspec],
+            )
+            .await?;
+        if !output.status.success() {
+            return Err(remote_error("Pull", &host, &output.stderr));
+        }
+        let fetched = self
+            .resolve(project_id, "FETCH_HEAD")
+            .await?
+            .ok_or_else(|| AppError::Internal("git fetch left no FETCH_HEAD".to_string()))?;
+
+        self.commit_locked(
+            db,
+            storage,
+            collab,
+            project_id,
+            Some(user_id),
+            "Save before pulling",
+        )
+        .await?;
+        let local = self.resolve(project_id, BRANCH).await?;
+
+        let is_ancestor = |ancestor: String, descendant: String| async move {
+            let output = self
+                .run(
+                    project_id,
+                    &["merge-base", "--is-ancestor", &ancestor, &descendant],
+                )
+                .await?;
+            Ok::<_, AppError>(output.status.success())
+        };
+
+        let (outcome, head) = match local {
+            Some(local) if is_ancestor(fetched.clone(), local.clone()).await? => {
+                (PullOutcome::UpToDate, local)
+            }
+            Some(local) if !is_ancestor(local.clone(), fetched.clone()).await? => {
+                let merged = self
+                    .merge(db, project_id, user_id, &local, &fetched, &url, &branch)
+                    .await?;
+                (PullOutcome::Merged, merged)
+            }
+            _ => (PullOutcome::FastForward, fetched),
+        };
+
+        let (mut restored, mut removed) = (0, 0);
+        if !matches!(outcome, PullOutcome::UpToDate) {
+            self.git(project_id, &["update-ref", BRANCH, &head]).await?;
+            (restored, removed) = self
+                .apply_commit(db, storage, collab, project_id, &head, user_id)
+                .await?;
+            // Records the new files; only commits if the project could not
+            // hold all of them, such as symlinks
+            self.commit_locked(
+                db,
+                storage,
+                collab,
+                project_id,
+                Some(user_id),
+                "Update project after pulling",
+            )
+            .await?;
+        }
+
+        sqlx::query("UPDATE project_git SET last_pull_at = $1 WHERE project_id = $2")
+            .bind(Utc::now().to_rfc3339())
+            .bind(project_id)
+            .execute(&db.pool)
+            .await?;
+        Ok(PullReport {
+            outcome,
+            head: self.commit_info(project_id, &head).await?,
+            restored,
+            removed,
+        })
+    }
+
+    /// Merge commit of `local` and `fetched`, by the user. Fails with the
+    /// conflicting paths if the two cannot be merged cleanly.
+    #[allow(clippy::too_many_arguments)]
+    async fn merge(
+        &self,
+        db: &Database,
+        project_id: &str,
+        user_id: &str,
+        local: &str,
+        fetched: &str,
+        url: &str,
+        branch: &str,
+    ) -> Result<String> {
+        // "<tree>", then the conflicted paths if there are any
+        let output = self
+            .run(
+                project_id,
+                &[
+                    "merge-tree",
+                    "--write-tree",
+                    "--name-only",
+                    "--no-messages",
+                    "--allow-unrelated-histories",
+                    local,
+                    fetched,
+                ],
+            )
+            .await?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let mut lines = stdout.lines();
+        let tree = lines.next().unwrap_or_default().to_string();
+        match output.status.code() {
+            Some(0) => {}
+            Some(1) => {
+                let conflicts: Vec<&str> = lines.filter(|line| !line.is_empty()).collect();
+                return Err(AppError::Conflict(format!(
+                    "Pulling would conflict in {}; reconcile them on one side and pull again",
+                    conflicts.join(", ")
+                )));
+            }
+            _ => {
+                return Err(AppError::Internal(format!(
+                    "git merge-tree failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                )))
+            }
+        }
+
+        let (name, email) = identity(db, Some(user_id)).await?;
+        let message = format!("Merge branch '{branch}' of {url}");
+        let output = self
+            .command(project_id)
+            .args([
+                "commit-tree",
+                &tree,
+                "-p",
+                local,
+                "-p",
+                fetched,
+                "-m",
+                &message,
+            ])
+            .env("GIT_AUTHOR_NAME", ident(&name))
+            .env("GIT_AUTHOR_EMAIL", ident(&email))
+            .env("GIT_COMMITTER_NAME", SERVER_NAME)
+            .env("GIT_COMMITTER_EMAIL", SERVER_EMAIL)
+            .output()
+            .await
+            .map_err(git_error)?;
+        if !output.status.success() {
+            return Err(AppError::Internal(format!(
+                "git commit-tree failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    async fn is_enabled(&self, db: &Database, project_id: &str) -> Result<bool> {
+        let enabled =
+            sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM project_git WHERE project_id = $1")
+                .bind(project_id)
+                .fetch_one(&db.pool)
+                .await?;
+        Ok(enabled > 0)
+    }
+
+    async fn ensure_enabled(&self, db: &Database, project_id: &str) -> Result<()> {
+        if !self.is_enabled(db, project_id).await? {
+            return Err(not_enabled());
+        }
+        Ok(())
+    }
+
+    /// The commit a revision names, if the project keeps its history in git
+    /// and the revision is one git could mean.
+    pub async fn find_commit(
+        &self,
+        db: &Database,
+        project_id: &str,
+        rev: &str,
+    ) -> Result<Option<String>> {
+        if check_rev(rev).is_err() || !self.is_enabled(db, project_id).await? {
+            return Ok(None);
+        }
+        self.resolve(project_id, rev).await
+    }
+
+    /// Write the files of a commit under `dest`. Like `tree_files`, symlinks
+    /// and submodules are left out.
+    pub async fn export_tree(&self, project_id: &str, sha: &str, dest: &Path) -> Result<()> {
+        check_rev(sha)?;
+        let archive = self
+            .git(project_id, &["archive", "--format=tar", sha])
+            .await?;
+        let dest = dest.to_path_buf();
+        tokio::task::spawn_blocking(move || -> io::Result<()> {
+            let mut archive = tar::Archive::new(io::Cursor::new(archive));
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let kind = entry.header().entry_type();
+                if kind.is_file() || kind.is_dir() {
+                    // Refuses paths that would leave `dest`
+                    entry.unpack_in(&dest)?;
+                }
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to write commit files: {e}")))?
+        .map_err(|e| AppError::Internal(format!("Failed to write commit files: {e}")))
+    }
+
+    /// The files of a commit, with their contents stored as objects, and the
+    /// folders holding them. Symlinks and submodules have no place in a
+    /// project and are left out.
+    async fn tree_files(
+        &self,
+        storage: &StorageService,
+        project_id: &str,
+        sha: &str,
+    ) -> Result<Vec<VersionFile>> {
+        let listing = self
+            .git(project_id, &["ls-tree", "-r", "-z", "--full-tree", sha])
+            .await?;
+        let listing = String::from_utf8_lossy(&listing);
+        // "<mode> <type> <object>\t<path>"
+        let blobs: Vec<(&str, &str)> = listing
+            .split('\0')
+            .filter_map(|entry| {
+                let (info, path) = entry.split_once('\t')?;
+                let mut info = info.split(' ');
+                let (mode, kind, object) = (info.next()?, info.next()?, info.next()?);
+                if kind != "blob" || mode == "120000" {
+                    return None;
+                }
+                // A pushed tree can hold names git itself would never write,
+                // such as "..", which must not reach storage
+                if compiler::normalize_project_path(path).ok().as_deref() != Some(path) {
+                    tracing::warn!("Skipping unsafe path {:?} in commit {}", path, sha);
+                    return None;
+                }
+                Some((object, path))
+            })
+            .collect();
+
+        let mut child = self
+            .command(project_id)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .spawn()
+            .map_err(git_error)?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let requests: String = blobs
+            .iter()
+            .map(|(object, _)| format!("{object}\n"))
+            .collect();
+        let writer = tokio::spawn(async move { stdin.write_all(requests.as_bytes()).await });
+
+        let mut reader = BufReader::new(stdout);
+        let mut files = Vec::with_capacity(blobs.len());
+        let mut folders = BTreeSet::new();
+        for (object, path) in &blobs {
+            // "<object> blob <size>", then the content and a newline
+            let mut header = String::new();
+            reader.read_line(&mut header).await.map_err(git_error)?;
+            let size: usize = header
+                .trim_end()
+                .rsplit(' ')
+                .next()
+                .and_then(|size| size.parse().ok())
+                .ok_or_else(|| {
+                    AppError::Internal(format!("Unexpected git output for {object}: {header}"))
+                })?;
+            let mut content = vec![0; size + 1];
+            reader.read_exact(&mut content).await.map_err(git_error)?;
+            content.truncate(size);
+
+            let mut parent = *path;
+            while let Some((folder, _)) = parent.rsplit_once('/') {
+                folders.insert(folder.to_string());
+                parent = folder;
+            }
+            files.push(VersionFile {
+                file_id: Uuid::new_v4().to_string(),
+                name: path.rsplit('/').next().unwrap_or(path).to_string(),
+                path: path.to_string(),
+                is_folder: false,
+                hash: Some(storage.write_object(&content).await?),
+                size: size as i64,
+            });
+        }
+        let _ = writer.await;
+        child.wait().await.map_err(git_error)?;
+
+        files.extend(folders.into_iter().map(|path| VersionFile {
+            file_id: Uuid::new_v4().to_string(),
+            name: path.rsplit('/').next().unwrap_or(&path).to_string(),
+            is_folder: true,
+            hash: None,
+            size: 0,
+            path,
+        }));
+        // Folders before their contents, as a restore needs them
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+
+    /// Answer a smart HTTP request from a member's git client. The project is
+    /// committed when a fetch or push starts, so clients see the latest edits,
+    /// and a push to the main branch is applied to the project's files.
+    /// Cloning a project that is not backed by git yet turns it on.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn serve_http<S, E>(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+        user: &AuthUser,
+        request: HttpRequest<'_>,
+        body: S,
+    ) -> Result<HttpResponse>
+    where
+        S: Stream<Item = std::result::Result<Bytes, E>> + Send + Unpin + 'static,
+        E: Send + 'static,
+    {
+        if self.ensure_enabled(db, project_id).await.is_err() {
+            self.enable(db, storage, collab, project_id, &user.id, true)
+                .await?;
+        }
+
+        if request.path == "/info/refs" {
+            self.commit(db, storage, collab, project_id, None, "Update project")
+                .await?;
+        }
+        if request.path != "/git-receive-pack" {
+            let (status, headers, stdout, child) =
+                self.http_backend(project_id, user, &request, body).await?;
+            return Ok(HttpResponse {
+                status,
+                headers,
+                body: Box::new(BackendOutput {
+                    stdout,
+                    _child: child,
+                }),
+                files_changed: false,
+            });
+        }
+
+        // A push holds the lock until its commits are in the project, so no
+        // commit of the project's own lands in between
+        let _writing = self.writing.lock().await;
+        self.commit_locked(db, storage, collab, project_id, None, "Update project")
+            .await?;
+        let before = self.resolve(project_id, BRANCH).await?;
+
+        // The report a push answers with is small; read it whole so the
+        // process has finished before the branch is looked at
+        let (status, headers, mut stdout, mut child) =
+            self.http_backend(project_id, user, &request, body).await?;
+        let mut report = Vec::new();
+        stdout.read_to_end(&mut report).await.map_err(git_error)?;
+        child.wait().await.map_err(git_error)?;
+
+        let after = self.resolve(project_id, BRANCH).await?;
+        let mut files_changed = false;
+        if let Some(head) = after.filter(|after| Some(after) != before.as_ref()) {
+            let (restored, removed) = self
+                .apply_commit(db, storage, collab, project_id, &head, &user.id)
+                .await?;
+            files_changed = restored > 0 || removed > 0;
+            // Records the new files; only commits if the project could not
+            // hold all of them, such as symlinks
+            self.commit_locked(
+                db,
+                storage,
+                collab,
+                project_id,
+                Some(&user.id),
+                "Update project after push",
+            )
+            .await?;
+        }
+
+        Ok(HttpResponse {
+            status,
+            headers,
+            body: Box::new(io::Cursor::new(report)),
+            files_changed,
+        })
+    }
+
+    /// Start `git http-backend` on a request, feeding it `body`, and read the
+    /// status and headers it answers with.
+    async fn http_backend<S, E>(
+        &self,
+        project_id: &str,
+        user: &AuthUser,
+        request: &HttpRequest<'_>,
+        body: S,
+    ) -> Result<(u16, Vec<(String, String)>, BufReader<ChildStdout>, Child)>
+    where
+        S: Stream<Item = std::result::Result<Bytes, E>> + Send + Unpin + 'static,
+        E: Send + 'static,
+    {
+        let mut command = Command::new("git");
+        command
+            .arg("http-backend")
+            .env("GIT_CONFIG_NOSYSTEM", "1")
+            .env("GIT_CONFIG_GLOBAL", "/dev/null")
+            .env("GIT_PROJECT_ROOT", &self.root)
+            .env("GIT_HTTP_EXPORT_ALL", "1")
+            .env("PATH_INFO", format!("/{project_id}.git{}", request.path))
+            .env("REQUEST_METHOD", request.method)
+            .env("QUERY_STRING", request.query)
+            .env("REMOTE_USER", &user.email)
+            .env("GIT_COMMITTER_NAME", ident(&user.name))
+            .env("GIT_COMMITTER_EMAIL", ident(&user.email))
+            // Pushes may only move branches forward, and must hold well-formed
+            // objects, so a tree cannot name paths such as ".."
+            .env("GIT_CONFIG_COUNT", "4")
+            .env("GIT_CONFIG_KEY_0", "http.receivepack")
+            .env("GIT_CONFIG_VALUE_0", "true")
+            .env("GIT_CONFIG_KEY_1", "receive.denyDeletes")
+            .env("GIT_CONFIG_VALUE_1", "true")
+            .env("GIT_CONFIG_KEY_2", "receive.denyNonFastForwards")
+            .env("GIT_CONFIG_VALUE_2", "true")
+            .env("GIT_CONFIG_KEY_3", "receive.fsckObjects")
+            .env("GIT_CONFIG_VALUE_3", "true")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true);
+        if let Some(content_type) = request.content_type {
+            command.env("CONTENT_TYPE", content_type);
+        }
+        if let Some(content_encoding) = request.content_encoding {
+            command.env("HTTP_CONTENT_ENCODING", content_encoding);
+        }
+        if let Some(git_protocol) = request.git_protocol {
+            command.env("HTTP_GIT_PROTOCOL", git_protocol);
+        }
+
+        let mut child = command.spawn().map_err(git_error)?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        // Feed the body while the output is read, as git answers before it
+        // has read everything
+        tokio::spawn(async move {
+            let mut body = body;
+            while let Some(chunk) = body.next().await {
+                let Ok(chunk) = chunk else { break };
+                if stdin.write_all(&chunk).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        // CGI headers, then a blank line
+        let mut stdout = BufReader::new(stdout);
+        let mut status = 200;
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            if stdout.read_line(&mut line).await.map_err(git_error)? == 0 {
+                return Err(AppError::Internal(
+                    "git http-backend ended without answering".to_string(),
+                ));
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            let Some((name, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("Status") {
+                status = value
+                    .split(' ')
+                    .next()
+                    .and_then(|code| code.parse().ok())
+                    .unwrap_or(500);
+            } else {
+                headers.push((name.to_string(), value.to_string()));
+            }
+        }
+
+        Ok((status, headers, stdout, child))
+    }
+
+    /// Commit every project that has automatic commits on and changed since
+    /// its last commit. Returns how many were committed.
+    pub async fn commit_all(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+    ) -> Result<usize> {
+        let projects = sqlx::query_scalar::<_, String>(
+            "SELECT project_id FROM project_git WHERE auto_commit = TRUE",
+        )
+        .fetch_all(&db.pool)
+        .await?;
+
+        let mut committed = 0;
+        for project_id in projects {
+            match self
+                .commit(db, storage, collab, &project_id, None, "Automatic commit")
+                .await
+            {
+                Ok(Some(_)) => committed += 1,
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Automatic git commit of {} failed: {}", project_id, e),
+            }
+        }
+        Ok(committed)
+    }
+}
+
+/// The name and email commits by a user carry, or the server's own.
+async fn identity(db: &Database, user_id: Option<&str>) -> Result<(String, String)> {
+    let user = match user_id {
+        Some(user_id) => {
+            sqlx::query_as::<_, (String, String)>("SELECT name, email FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(&db.pool)
+                .await?
+        }
+        None => None,
+    };
+    Ok(user.unwrap_or_else(|| (SERVER_NAME.to_string(), SERVER_EMAIL.to_string())))
+}
+
+pub fn spawn_scheduler(
+    git: GitService,
+    db: Database,
+    storage: StorageService,
+    collab: CollabService,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            match git.commit_all(&db, &storage, &collab).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Committed {} git-backed projects", count),
+                Err(e) => tracing::warn!("Automatic git commits failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,152 @@
+// Git host credentials
+// Access tokens users keep for the git hosts their projects sync with, such
+// as GitHub or a lab's GitLab, one per user and host. Tokens are stored
+// sealed and only opened to hand them to git.
+
+use chrono::Utc;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    config::Config,
+    db::DbPool,
+    error::{AppError, Result},
+    services::secrets::Sealer,
+};
+
+// Username sent with a token when none is given; GitHub and GitLab only look
+// at the token
+const DEFAULT_USERNAME: &str = "oauth2";
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CredentialInfo {
+    pub host: String,
+    pub username: String,
+    /// Last characters of the token, to tell tokens apart
+    pub token_hint: String,
+    pub created_at: String,
+}
+
+#[derive(Clone)]
+pub struct GitCredentials {
+    sealer: Sealer,
+}
+
+/// The host a credential is for, lowercased: "github.com" or
+/// "gitlab.example.org:8443".
+pub fn normalize_host(host: &str) -> Result<String> {
+    let host = host.trim().to_ascii_lowercase();
+    let valid = !host.is_empty()
+        && host.len() <= 253
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':'));
+    if !valid {
+        return Err(AppError::Validation(format!("Invalid host: {host}")));
+    }
+    Ok(host)
+}
+
+impl GitCredentials {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            sealer: Sealer::from_config(config, "git credentials")?,
+        })
+    }
+
+    pub async fn list(&self, pool: &DbPool, user_id: &str) -> Result<Vec<CredentialInfo>> {
+        let rows = sqlx::query_as::<_, (String, String, String, String)>(
+            "SELECT host, username, token, created_at FROM git_credentials WHERE user_id = $1 ORDER BY host",
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+        let mut credentials = Vec::with_capacity(rows.len());
+        for (host, username, token, created_at) in rows {
+            // A token sealed under a key the server no longer has still shows
+            // up, so it can be replaced
+            let token_hint = match self.sealer.open(&token) {
+                Ok(token) => {
+                    let tail: String = token.chars().rev().take(4).collect();
+                    tail.chars().rev().collect()
+                }
+                Err(_) => String::new(),
+            };
+            credentials.push(CredentialInfo {
+                host,
+                username,
+                token_hint,
+                created_at,
+            });
+        }
+        Ok(credentials)
+    }
+
+    /// Store a user's token for a host, replacing any earlier one.
+    pub async fn set(
+        &self,
+        pool: &DbPool,
+        user_id: &str,
+        host: &str,
+        username: Option<&str>,
+        token: &str,
+    ) -> Result<()> {
+        let host = normalize_host(host)?;
+        let token = token.trim();
+        if token.is_empty() || token.chars().any(char::is_control) {
+            return Err(AppError::Validation("Invalid token".to_string()));
+        }
+        let username = username
+            .map(str::trim)
+            .filter(|username| !username.is_empty())
+            .unwrap_or(DEFAULT_USERNAME);
+        if username.contains(':') || username.chars().any(char::is_control) {
+            return Err(AppError::Validation("Invalid username".to_string()));
+        }
+
+        sqlx::query(
+            "INSERT INTO git_credentials (user_id, host, username, token, created_at) VALUES ($1, $2, $3, $4, $5) \
+             ON CONFLICT (user_id, host) DO UPDATE SET username = excluded.username, \
+             token = excluded.token, created_at = excluded.created_at",
+        )
+        .bind(user_id)
+        .bind(&host)
+        .bind(username)
+        .bind(self.sealer.seal(token)?)
+        .bind(Utc::now().to_rfc3339())
+        .execute(pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Forget a user's token for a host; false if they had none.
+    pub async fn remove(&self, pool: &DbPool, user_id: &str, host: &str) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM git_credentials WHERE user_id = $1 AND host = $2")
+            .bind(user_id)
+            .bind(normalize_host(host)?)
+            .execute(pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The username and token a user keeps for a host, if any.
+    pub async fn get(
+        &self,
+        pool: &DbPool,
+        user_id: &str,
+        host: &str,
+    ) -> Result<Option<(String, String)>> {
+        let row = sqlx::query_as::<_, (String, String)>(
+            "SELECT username, token FROM git_credentials WHERE user_id = $1 AND host = $2",
+        )
+        .bind(user_id)
+        .bind(normalize_host(host)?)
+        .fetch_optional(pool)
+        .await?;
+        match row {
+            Some((username, token)) => Ok(Some((username, self.sealer.open(&token)?))),
+            None => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,154 @@
+// Health checks
+// Liveness only says the process is answering; readiness checks what
+// requests depend on — the database, writable storage and the TeX
+// toolchain — so an orchestrator holds traffic back until they all work.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{config::CompileConfig, db::Database, services::storage::StorageService};
+
+// A check that takes longer than this counts as failed
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Unavailable,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComponentStatus {
+    pub status: Status,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    /// Ok only when every component is
+    pub status: Status,
+    pub components: BTreeMap<&'static str, ComponentStatus>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.status == Status::Ok
+    }
+}
+
+/// Run every readiness check concurrently.
+pub async fn readiness(
+    db: &Database,
+    storage: &StorageService,
+    cache_path: &str,
+    compile: &CompileConfig,
+) -> Readiness {
+    let writable = [storage.base_path(), Path::new(cache_path)];
+    let (database, storage, toolchain) = tokio::join!(
+        timed(check_database(db)),
+        timed(check_writable(&writable)),
+        timed(check_toolchain(compile)),
+    );
+
+    let components = BTreeMap::from([
+        ("database", database),
+        ("storage", storage),
+        ("toolchain", toolchain),
+    ]);
+    let status = if components.values().all(|c| c.status == Status::Ok) {
+        Status::Ok
+    } else {
+        Status::Unavailable
+    };
+    Readiness { status, components }
+}
+
+async fn timed<F>(check: F) -> ComponentStatus
+where
+    F: std::future::Future<Output = Result<(), String>>,
+{
+    let started = Instant::now();
+    let result = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("Timed out after {}s", CHECK_TIMEOUT.as_secs())),
+    };
+    let latency_ms = started.elapsed().as_millis() as u64;
+    match result {
+        Ok(()) => ComponentStatus {
+            status: Status::Ok,
+            latency_ms,
+            error: None,
+        },
+        Err(error) => ComponentStatus {
+            status: Status::Unavailable,
+            latency_ms,
+            error: Some(error),
+        },
+    }
+}
+
+async fn check_database(db: &Database) -> Result<(), String> {
+    sqlx::query("SELECT 1")
+        .execute(&db.pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Database query failed: {e}"))
+}
+
+/// Create and remove a file in each directory. The cache directory only
+/// appears with the first compile, so missing directories are created.
+async fn check_writable(dirs: &[&Path]) -> Result<(), String> {
+    for dir in dirs {
+        let probe = dir.join(format!(".health-{}", Uuid::new_v4()));
+        let written = match tokio::fs::create_dir_all(dir).await {
+            Ok(()) => tokio::fs::write(&probe, b"").await,
+            Err(e) => Err(e),
+        };
+        written.map_err(|e| format!("{} is not writable: {e}", dir.display()))?;
+        let _ = tokio::fs::remove_file(&probe).await;
+    }
+    Ok(())
+}
+
+/// The compile backend's program, and bwrap when compiles are sandboxed.
+async fn check_toolchain(compile: &CompileConfig) -> Result<(), String> {
+    let mut programs = vec![compile.backend.as_str()];
+    if compile.sandbox == "bubblewrap" {
+        programs.push("bwrap");
+    }
+    let missing: Vec<&str> = programs
+        .into_iter()
+        .filter(|program| find_program(program).is_none())
+        .collect();
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("Not found on PATH: {}", missing.join(", ")))
+    }
+}
+
+pub(crate) fn find_program(name: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(name))
+        .find(|candidate| is_executable(candidate))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
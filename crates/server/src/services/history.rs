@@ -0,0 +1,201 @@
+// Document history
+// Every update applied to a collaborative document is recorded with its
+// author. A room also records a snapshot of the whole document before its
+// first update and every so many updates after, so a file's content at any
+// point can be rebuilt by replaying the updates that follow the nearest
+// snapshot. Updates of different room instances never mix: each instance
+// starts from a snapshot of its own.
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::FromRow;
+use tokio::sync::mpsc;
+use utoipa::ToSchema;
+use yrs::{updates::decoder::Decode, Doc, GetString, Transact, Update};
+
+use crate::{
+    db::{Database, DbPool},
+    error::{AppError, Result},
+    services::collab::TEXT_NAME,
+};
+
+pub const SNAPSHOT: &str = "snapshot";
+pub const UPDATE: &str = "update";
+
+// Updates a room applies between snapshots, bounding how many a replay needs
+pub const SNAPSHOT_INTERVAL: u64 = 500;
+
+// Records written in one transaction at most
+const BATCH_SIZE: usize = 256;
+
+/// A snapshot or update on its way to the database.
+#[derive(Debug)]
+pub struct Record {
+    pub file_id: String,
+    pub user_id: Option<String>,
+    pub kind: &'static str,
+    /// A yrs v1 update; for snapshots, the whole document
+    pub data: Vec<u8>,
+    pub created_at: String,
+}
+
+impl Record {
+    pub fn new(file_id: &str, user_id: Option<&str>, kind: &'static str, data: Vec<u8>) -> Self {
+        Self {
+            file_id: file_id.to_string(),
+            user_id: user_id.map(str::to_string),
+            kind,
+            data,
+            created_at: Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// Write records in the order they are sent, from a background task, so
+/// applying an update never waits for the database.
+pub fn spawn_writer(db: Database) -> mpsc::UnboundedSender<Record> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<Record>();
+    tokio::spawn(async move {
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        while receiver.recv_many(&mut batch, BATCH_SIZE).await > 0 {
+            if let Err(e) = write(&db.pool, &batch).await {
+                // Usually a file deleted while it was being edited
+                tracing::warn!("Failed to record {} history entries: {}", batch.len(), e);
+            }
+            batch.clear();
+        }
+    });
+    sender
+}
+
+async fn write(pool: &DbPool, records: &[Record]) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    for record in records {
+        sqlx::query(
+            "INSERT INTO document_history (file_id, user_id, kind, data, created_at) VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(&record.file_id)
+        .bind(&record.user_id)
+        .bind(record.kind)
+        .bind(&record.data)
+        .bind(&record.created_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// One update in a file's timeline.
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct HistoryEntry {
+    /// Position in the history; pass it back to see the file as it was
+    /// right after this update
+    pub seq: i64,
+    pub user_id: Option<String>,
+    pub user_name: Option<String>,
+    pub created_at: String,
+}
+
+/// Updates to a file, newest first, older than `before` if given.
+pub async fn timeline(
+    pool: &DbPool,
+    file_id: &str,
+    before: Option<i64>,
+    limit: i64,
+) -> Result<Vec<HistoryEntry>> {
+    let entries = sqlx::query_as::<_, HistoryEntry>(
+        "SELECT h.id AS seq, h.user_id, u.name AS user_name, h.created_at \
+         FROM document_history h LEFT JOIN users u ON h.user_id = u.id \
+         WHERE h.file_id = $1 AND h.kind = $2 AND ($3 IS NULL OR h.id < $4) \
+         ORDER BY h.id DESC LIMIT $5",
+    )
+    .bind(file_id)
+    .bind(UPDATE)
+    .bind(before)
+    .bind(before)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(entries)
+}
+
+/// A snapshot or update as stored.
+#[derive(Debug, FromRow)]
+pub struct StoredRecord {
+    pub id: i64,
+    pub user_id: Option<String>,
+    pub kind: String,
+    pub data: Vec<u8>,
+    pub created_at: String,
+}
+
+/// A file's whole history, oldest first.
+pub async fn records(pool: &DbPool, file_id: &str) -> Result<Vec<StoredRecord>> {
+    let records = sqlx::query_as::<_, StoredRecord>(
+        "SELECT id, user_id, kind, data, created_at FROM document_history \
+         WHERE file_id = $1 ORDER BY id",
+    )
+    .bind(file_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(records)
+}
+
+/// The records needed to rebuild a file as of `seq`: the nearest snapshot at
+/// or before it and the updates from there on.
+async fn records_until(pool: &DbPool, file_id: &str, seq: i64) -> Result<Vec<StoredRecord>> {
+    let snapshot = sqlx::query_scalar::<_, Option<i64>>(
+        "SELECT MAX(id) FROM document_history WHERE file_id = $1 AND kind = $2 AND id <= $3",
+    )
+    .bind(file_id)
+    .bind(SNAPSHOT)
+    .bind(seq)
+    .fetch_one(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("History entry not found".to_string()))?;
+
+    let records = sqlx::query_as::<_, StoredRecord>(
+        "SELECT id, user_id, kind, data, created_at FROM document_history \
+         WHERE file_id = $1 AND id >= $2 AND id <= $3 ORDER BY id",
+    )
+    .bind(file_id)
+    .bind(snapshot)
+    .bind(seq)
+    .fetch_all(pool)
+    .await?;
+    Ok(records)
+}
+
+/// The file's content right after update `seq`, rebuilt by replaying the
+/// history from the nearest snapshot.
+pub async fn content_at(pool: &DbPool, file_id: &str, seq: i64) -> Result<String> {
+    let records = records_until(pool, file_id, seq).await?;
+    if records.last().map(|record| record.id) != Some(seq) {
+        return Err(AppError::NotFound("History entry not found".to_string()));
+    }
+
+    let mut content = String::new();
+    replay(&records, |_, text| content = text.to_string())?;
+    Ok(content)
+}
+
+/// Apply records in order, starting over from an empty document at every
+/// snapshot, and pass each record to `visit` with the text right after it.
+pub fn replay(records: &[StoredRecord], mut visit: impl FnMut(&StoredRecord, &str)) -> Result<()> {
+    let mut doc = Doc::new();
+    for record in records {
+        if record.kind == SNAPSHOT {
+            doc = Doc::new();
+        }
+        let update = Update::decode_v1(&record.data)
+            .map_err(|e| AppError::Internal(format!("Corrupt history entry: {e}")))?;
+        doc.transact_mut().apply_update(update);
+
+        let text = doc
+            .get_or_insert_text(TEXT_NAME)
+            .get_string(&doc.transact());
+        visit(record, &text);
+    }
+    Ok(())
+}
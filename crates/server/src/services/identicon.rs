@@ -0,0 +1,63 @@
+// User colors and identicons
+// Every user gets a display color and a generated avatar derived from their
+// id alone, so cursors, presence lists and comments look the same in every
+// client without anyone uploading a picture.
+
+use sha2::{Digest, Sha256};
+
+// Distinct on both light and dark backgrounds
+const COLORS: [&str; 8] = [
+    "#e6194b", "#3cb44b", "#4363d8", "#f58231", "#911eb4", "#008080", "#f032e6", "#9a6324",
+];
+
+// Cells per side; the left half is mirrored onto the right
+const GRID: usize = 5;
+const CELL: usize = 10;
+const MARGIN: usize = 5;
+const BACKGROUND: &str = "#f0f0f0";
+
+/// The user's display color, as a CSS hex color.
+pub fn color(user_id: &str) -> &'static str {
+    let hash = user_id.bytes().fold(0usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte as usize)
+    });
+    COLORS[hash % COLORS.len()]
+}
+
+/// Where clients fetch the user's identicon.
+pub fn url(user_id: &str) -> String {
+    format!("/api/users/{user_id}/identicon.svg")
+}
+
+/// A symmetric grid of cells in the user's color, as an SVG document.
+pub fn svg(user_id: &str) -> String {
+    let hash = Sha256::digest(user_id.as_bytes());
+    let size = GRID * CELL + 2 * MARGIN;
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" height=\"{size}\" \
+         viewBox=\"0 0 {size} {size}\" shape-rendering=\"crispEdges\">\
+         <rect width=\"{size}\" height=\"{size}\" fill=\"{BACKGROUND}\"/><g fill=\"{}\">",
+        color(user_id)
+    );
+    let columns = GRID.div_ceil(2);
+    for row in 0..GRID {
+        for column in 0..columns {
+            let bit = row * columns + column;
+            if hash[bit / 8] & (1 << (bit % 8)) == 0 {
+                continue;
+            }
+            for x in [column, GRID - 1 - column] {
+                svg.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{CELL}\" height=\"{CELL}\"/>",
+                    MARGIN + x * CELL,
+                    MARGIN + row * CELL
+                ));
+                if x == GRID - 1 - x {
+                    break;
+                }
+            }
+        }
+    }
+    svg.push_str("</g></svg>");
+    svg
+}
@@ -0,0 +1,107 @@
+// Instance statistics
+// Totals across every user and project, for operators checking on an
+// instance from the admin API.
+
+use chrono::{Duration, Utc};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    db::Database,
+    error::Result,
+    services::{
+        collab::CollabService,
+        compile_jobs::CompileJobs,
+        error_log::{ErrorLog, LoggedError},
+        storage::StorageService,
+    },
+};
+
+// Warnings and errors included in the report
+const RECENT_ERRORS: usize = 20;
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct InstanceStats {
+    pub users: i64,
+    pub projects: i64,
+    pub files: i64,
+    /// Bytes used by project files, as stored
+    pub storage_bytes: u64,
+    pub compiles: CompileVolume,
+    pub sessions: SessionStats,
+    /// Latest warnings and errors the server logged, newest first
+    pub recent_errors: Vec<LoggedError>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CompileVolume {
+    pub last_24h: i64,
+    pub failed_last_24h: i64,
+    pub last_7d: i64,
+    pub running: usize,
+    pub queued: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SessionStats {
+    /// Documents open for collaborative editing
+    pub open_documents: usize,
+    /// Editor websocket connections
+    pub connections: usize,
+}
+
+pub async fn collect(
+    db: &Database,
+    storage: &StorageService,
+    compile_jobs: &CompileJobs,
+    collab: &CollabService,
+    errors: &ErrorLog,
+) -> Result<InstanceStats> {
+    let count = |sql: &'static str| sqlx::query_scalar::<_, i64>(sql).fetch_one(&db.pool);
+    let users = count("SELECT COUNT(*) FROM users").await?;
+    let projects = count("SELECT COUNT(*) FROM projects").await?;
+    let files = count("SELECT COUNT(*) FROM files WHERE is_folder = FALSE").await?;
+
+    let compiles_since = |days: i64, failed_only: bool| {
+        let since = (Utc::now() - Duration::days(days)).to_rfc3339();
+        async move {
+            sqlx::query_scalar::<_, i64>(
+                "SELECT COUNT(*) FROM compiles WHERE started_at >= $1 AND (NOT $2 OR NOT success)",
+            )
+            .bind(since)
+            .bind(failed_only)
+            .fetch_one(&db.pool)
+            .await
+        }
+    };
+    let (running, queued) = compile_jobs.load();
+    let compiles = CompileVolume {
+        last_24h: compiles_since(1, false).await?,
+        failed_last_24h: compiles_since(1, true).await?,
+        last_7d: compiles_since(7, false).await?,
+        running,
+        queued,
+    };
+
+    let storage_bytes = storage
+        .list_all()
+        .await?
+        .iter()
+        .map(|entry| entry.size)
+        .sum();
+
+    let collab = collab.stats().await;
+
+    Ok(InstanceStats {
+        users,
+        projects,
+        files,
+        storage_bytes,
+        compiles,
+        sessions: SessionStats {
+            open_documents: collab.rooms,
+            connections: collab.connections,
+        },
+        recent_errors: errors.recent(RECENT_ERRORS),
+    })
+}
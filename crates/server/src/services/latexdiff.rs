@@ -0,0 +1,174 @@
+// Change-highlighted PDFs
+// Marks up what changed in a document between two states of its project, each
+// a labelled version, a git revision or the project as it is now, with
+// latexdiff, for reviewers of a revised paper. latexdiff runs under the same
+// sandbox and resource limits as compiles and flattens \input and \include,
+// so changes anywhere in the document show. The marked-up document is then
+// compiled like any other.
+
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+
+use tokio::process::Command;
+
+use crate::{
+    config::CompileConfig,
+    db::Database,
+    error::{AppError, Result},
+    services::{
+        collab::CollabService,
+        compiler::{apply_resource_limits, sandboxed_command},
+        git::GitService,
+        storage::StorageService,
+        versions,
+    },
+};
+
+const LATEXDIFF_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// One side of a comparison.
+#[derive(Debug, Clone)]
+pub enum Revision {
+    /// The project as it is now
+    Current,
+    /// A version, by ID
+    Version(String),
+    /// A git commit, by full hash
+    Commit(String),
+}
+
+/// The version with this ID or label, or else the git revision with this
+/// name; the project as it is now when `name` is None.
+pub async fn resolve(
+    db: &Database,
+    git: &GitService,
+    project_id: &str,
+    name: Option<&str>,
+) -> Result<Revision> {
+    let Some(name) = name else {
+        return Ok(Revision::Current);
+    };
+    if let Some(version) = versions::find(&db.pool, project_id, name).await? {
+        return Ok(Revision::Version(version.id));
+    }
+    match git.find_commit(db, project_id, name).await? {
+        Some(sha) => Ok(Revision::Commit(sha)),
+        None => Err(AppError::NotFound(format!(
+            "No version or git revision named '{name}'"
+        ))),
+    }
+}
+
+/// Write the project's files as they are in `revision` under `dest`.
+pub async fn write_tree(
+    db: &Database,
+    storage: &StorageService,
+    collab: &CollabService,
+    git: &GitService,
+    project_id: &str,
+    revision: &Revision,
+    dest: &Path,
+) -> Result<()> {
+    let write = |path: &str, content: Vec<u8>| {
+        let local = dest.join(path);
+        async move {
+            if let Some(parent) = local.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            tokio::fs::write(&local, content).await
+        }
+    };
+    let io_error = |e: std::io::Error| AppError::Internal(format!("Failed to write files: {e}"));
+
+    tokio::fs::create_dir_all(dest).await.map_err(io_error)?;
+    match revision {
+        Revision::Current => {
+            // Include what is being typed right now
+            collab.persist_project(project_id).await?;
+            for entry in storage.list_tree(project_id).await? {
+                if !entry.is_folder {
+                    let content = storage.read_bytes(project_id, &entry.path).await?;
+                    write(&entry.path, content).await.map_err(io_error)?;
+                }
+            }
+        }
+        Revision::Version(version_id) => {
+            for file in versions::files(&db.pool, version_id).await? {
+                if let Some(hash) = &file.hash {
+                    let content = storage.read_object(hash).await?;
+                    write(&file.path, content).await.map_err(io_error)?;
+                }
+            }
+        }
+        Revision::Commit(sha) => git.export_tree(project_id, sha, dest).await?,
+    }
+    Ok(())
+}
+
+/// Run latexdiff on `main_file` of the project trees `old` and `new`, both
+/// inside `root`, and return the marked-up document.
+pub async fn markup(
+    root: &Path,
+    old: &Path,
+    new: &Path,
+    main_file: &str,
+    config: &CompileConfig,
+) -> Result<String> {
+    let root = tokio::fs::canonicalize(root)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to resolve latexdiff directory: {e}")))?;
+    let args = vec![
+        "--flatten".to_string(),
+        "--encoding=utf8".to_string(),
+        old.join(main_file).display().to_string(),
+        new.join(main_file).display().to_string(),
+    ];
+
+    let mut command = match config.sandbox.as_str() {
+        // Both trees are scratch copies, so nothing is lost if it writes there
+        "bubblewrap" => sandboxed_command("latexdiff", &args, &root, &root, &root),
+        "none" => {
+            let mut command = Command::new("latexdiff");
+            command.args(&args);
+            command
+        }
+        other => {
+            return Err(AppError::Internal(format!(
+                "Unknown compile sandbox '{other}'"
+            )))
+        }
+    };
+    command
+        .current_dir(&root)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    #[cfg(unix)]
+    apply_resource_limits(&mut command, config);
+
+    let child = command.output();
+    let result = match tokio::time::timeout(LATEXDIFF_TIMEOUT, child).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::Internal(match config.sandbox.as_str() {
+                "bubblewrap" => {
+                    "COMPILE_SANDBOX is bubblewrap but bwrap is not installed".to_string()
+                }
+                _ => "latexdiff is not installed".to_string(),
+            }));
+        }
+        Ok(Err(e)) => return Err(AppError::Internal(format!("Failed to run latexdiff: {e}"))),
+        Err(_) => return Err(AppError::Internal("latexdiff timed out".to_string())),
+    };
+
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        return Err(AppError::BadRequest(format!(
+            "latexdiff failed: {}",
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&result.stdout).into_owned())
+}
@@ -0,0 +1,94 @@
+// LaTeX linting
+// Runs chktex on a single source file and returns its warnings in a form the
+// editor can turn into squiggles
+
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::error::{AppError, Result};
+
+const CHKTEX_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Unit separator between fields, since messages and paths can contain colons
+const SEP: char = '\u{1f}';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LintSeverity {
+    Error,
+    Warning,
+    Message,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LintWarning {
+    pub line: u32,
+    pub column: u32,
+    /// Number of characters the warning covers
+    pub length: u32,
+    pub severity: LintSeverity,
+    /// chktex warning number, for looking up or suppressing the check
+    pub code: u32,
+    pub message: String,
+}
+
+/// Lint `file` on its own, without following \input.
+pub async fn check(project_path: &Path, file: &str) -> Result<Vec<LintWarning>> {
+    let format = format!("%l{SEP}%c{SEP}%d{SEP}%k{SEP}%n{SEP}%m\n");
+    let child = tokio::process::Command::new("chktex")
+        .args(["-q", "-I0", "-f", &format, file])
+        .current_dir(project_path)
+        .kill_on_drop(true)
+        .output();
+
+    let output = match tokio::time::timeout(CHKTEX_TIMEOUT, child).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::Internal("chktex is not installed".to_string()));
+        }
+        Ok(Err(e)) => return Err(AppError::Internal(format!("Failed to run chktex: {e}"))),
+        Err(_) => return Err(AppError::Internal("chktex timed out".to_string())),
+    };
+
+    // chktex exits non-zero whenever it found something, so only treat it as
+    // a failure when nothing came out
+    let warnings = parse(&String::from_utf8_lossy(&output.stdout));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() && warnings.is_empty() && !stderr.trim().is_empty() {
+        return Err(AppError::BadRequest(format!(
+            "chktex failed: {}",
+            stderr.trim()
+        )));
+    }
+    Ok(warnings)
+}
+
+fn parse(output: &str) -> Vec<LintWarning> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(6, SEP);
+            let line = fields.next()?.parse().ok()?;
+            let column = fields.next()?.parse().ok()?;
+            let length = fields.next()?.parse().ok()?;
+            let severity = match fields.next()? {
+                "Error" => LintSeverity::Error,
+                "Warning" => LintSeverity::Warning,
+                _ => LintSeverity::Message,
+            };
+            let code = fields.next()?.parse().ok()?;
+            let message = fields.next()?.trim().to_string();
+            Some(LintWarning {
+                line,
+                column,
+                length,
+                severity,
+                code,
+                message,
+            })
+        })
+        .collect()
+}
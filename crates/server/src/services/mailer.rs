@@ -0,0 +1,296 @@
+// Mailer
+// Sends plain-text email over SMTP from a background queue, so whatever
+// triggers an email never waits on the mail server. A failed delivery is
+// retried with growing delays, then dropped with a warning. Speaks just enough
+// SMTP for that: implicit TLS, STARTTLS or plaintext, and AUTH PLAIN.
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_rustls::{
+    rustls::{self, pki_types::ServerName},
+    TlsConnector,
+};
+use uuid::Uuid;
+
+use crate::config::MailConfig;
+
+// Time one delivery may take, from connecting to QUIT
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Delay before the first retry; doubled for each one after
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+struct Queued {
+    email: Email,
+    attempts: u32,
+}
+
+#[derive(Clone)]
+pub struct Mailer {
+    queue: mpsc::UnboundedSender<Queued>,
+    config: Arc<MailConfig>,
+}
+
+impl Mailer {
+    /// Start the task that delivers queued email.
+    pub fn spawn(config: MailConfig) -> Self {
+        let config = Arc::new(config);
+        let (queue, mut receiver) = mpsc::unbounded_channel::<Queued>();
+        let requeue = queue.downgrade();
+        let tls = TlsConnector::from(Arc::new(tls_config()));
+
+        let worker_config = config.clone();
+        tokio::spawn(async move {
+            while let Some(mut queued) = receiver.recv().await {
+                let error = match tokio::time::timeout(
+                    DELIVERY_TIMEOUT,
+                    deliver(&worker_config, &tls, &queued.email),
+                )
+                .await
+                {
+                    Ok(Ok(())) => continue,
+                    Ok(Err(e)) => e.to_string(),
+                    Err(_) => "timed out".to_string(),
+                };
+
+                queued.attempts += 1;
+                if queued.attempts >= worker_config.max_attempts {
+                    tracing::warn!(
+                        "Giving up on email to {} after {} attempts: {}",
+                        queued.email.to,
+                        queued.attempts,
+                        error
+                    );
+                    continue;
+                }
+                tracing::debug!("Email to {} failed, will retry: {}", queued.email.to, error);
+
+                // Wait aside so other email keeps going out meanwhile
+                let delay = RETRY_DELAY * 2u32.pow(queued.attempts - 1);
+                let requeue = requeue.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(delay).await;
+                    if let Some(queue) = requeue.upgrade() {
+                        let _ = queue.send(queued);
+                    }
+                });
+            }
+        });
+
+        Self { queue, config }
+    }
+
+    pub fn send(&self, email: Email) {
+        // The address goes into the SMTP envelope and headers as it is
+        if !is_valid_address(&email.to) {
+            tracing::warn!("Not sending email to invalid address {:?}", email.to);
+            return;
+        }
+        let _ = self.queue.send(Queued { email, attempts: 0 });
+    }
+
+    /// Link to a page of the web app.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.config.app_url, path)
+    }
+}
+
+fn tls_config() -> rustls::ClientConfig {
+    let mut roots = rustls::RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs();
+    for error in &native.errors {
+        tracing::warn!("Failed to load system certificates: {}", error);
+    }
+    roots.add_parsable_certificates(native.certs);
+
+    rustls::ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .expect("ring supports the default TLS versions")
+        .with_root_certificates(roots)
+        .with_no_client_auth()
+}
+
+trait Connection: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> Connection for T {}
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+struct Session {
+    stream: BufReader<Box<dyn Connection>>,
+}
+
+impl Session {
+    fn new(stream: Box<dyn Connection>) -> Self {
+        Self {
+            stream: BufReader::new(stream),
+        }
+    }
+
+    /// Read a reply, which may span several lines, and check its class: 2 for
+    /// success, 3 for "go on".
+    async fn expect(&mut self, class: u16) -> io::Result<()> {
+        loop {
+            let mut line = String::new();
+            if self.stream.read_line(&mut line).await? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let line = line.trim_end();
+            let code = line
+                .get(..3)
+                .and_then(|code| code.parse::<u16>().ok())
+                .ok_or_else(|| protocol_error(format!("Malformed reply: {line}")))?;
+            // "250-" continues the reply, "250 " ends it
+            if line.as_bytes().get(3) == Some(&b'-') {
+                continue;
+            }
+            if code / 100 != class {
+                return Err(protocol_error(format!("Mail server replied: {line}")));
+            }
+            return Ok(());
+        }
+    }
+
+    async fn write(&mut self, data: &str) -> io::Result<()> {
+        let stream = self.stream.get_mut();
+        stream.write_all(data.as_bytes()).await?;
+        stream.flush().await
+    }
+
+    async fn command(&mut self, line: &str, class: u16) -> io::Result<()> {
+        self.write(&format!("{line}\r\n")).await?;
+        self.expect(class).await
+    }
+}
+
+async fn deliver(config: &MailConfig, tls: &TlsConnector, email: &Email) -> io::Result<()> {
+    let server_name = ServerName::try_from(config.host.clone())
+        .map_err(|_| protocol_error(format!("Invalid SMTP host: {}", config.host)))?;
+    let tcp = TcpStream::connect((config.host.as_str(), config.port)).await?;
+    let stream: Box<dyn Connection> = if config.security == "tls" {
+        Box::new(tls.connect(server_name.clone(), tcp).await?)
+    } else {
+        Box::new(tcp)
+    };
+
+    let mut session = Session::new(stream);
+    session.expect(2).await?;
+    let hello = format!("EHLO {}", domain(&config.from));
+    session.command(&hello, 2).await?;
+
+    if config.security == "starttls" {
+        session.command("STARTTLS", 2).await?;
+        let stream = session.stream.into_inner();
+        session = Session::new(Box::new(tls.connect(server_name, stream).await?));
+        // What the server offers may change once the connection is private
+        session.command(&hello, 2).await?;
+    }
+
+    if let Some(username) = &config.username {
+        let password = config.password.as_deref().unwrap_or_default();
+        let credentials = STANDARD.encode(format!("\0{username}\0{password}"));
+        session
+            .command(&format!("AUTH PLAIN {credentials}"), 2)
+            .await?;
+    }
+
+    session
+        .command(&format!("MAIL FROM:<{}>", address(&config.from)), 2)
+        .await?;
+    session
+        .command(&format!("RCPT TO:<{}>", email.to), 2)
+        .await?;
+    session.command("DATA", 3).await?;
+    session.write(&message(config, email)).await?;
+    session.expect(2).await?;
+    // Delivered; a failed goodbye changes nothing
+    let _ = session.command("QUIT", 2).await;
+    Ok(())
+}
+
+/// Whether the text is a plain `local@domain` address, with nothing that
+/// could end an SMTP command or header: no whitespace, control characters,
+/// angle brackets or quoting.
+pub fn is_valid_address(address: &str) -> bool {
+    let Some((local, domain)) = address.split_once('@') else {
+        return false;
+    };
+    let dotted = |part: &str| {
+        !part.is_empty() && !part.starts_with('.') && !part.ends_with('.') && !part.contains("..")
+    };
+    address.len() <= 254
+        && dotted(local)
+        && dotted(domain)
+        && local
+            .chars()
+            .all(|c| c.is_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(c))
+        && domain
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '.')
+}
+
+/// The bare address of a "Name <address>" mailbox.
+fn address(mailbox: &str) -> &str {
+    match (mailbox.find('<'), mailbox.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &mailbox[start + 1..end],
+        _ => mailbox.trim(),
+    }
+}
+
+fn domain(mailbox: &str) -> &str {
+    address(mailbox).rsplit('@').next().unwrap_or("localhost")
+}
+
+/// A header value, encoded when it is not plain ASCII. Line breaks would end
+/// the header early, so they become spaces.
+fn header_value(value: &str) -> String {
+    let value: String = value
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    if value.is_ascii() {
+        value
+    } else {
+        format!("=?UTF-8?B?{}?=", STANDARD.encode(value))
+    }
+}
+
+/// The message as sent after DATA, terminator included. The body is base64
+/// so it needs neither dot-stuffing nor 8-bit support from the server.
+fn message(config: &MailConfig, email: &Email) -> String {
+    let body = STANDARD.encode(email.body.replace('\n', "\r\n"));
+
+    let mut message = format!(
+        "From: {}\r\nTo: <{}>\r\nSubject: {}\r\nDate: {}\r\nMessage-ID: <{}@{}>\r\n\
+         MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\
+         Content-Transfer-Encoding: base64\r\n\r\n",
+        config.from,
+        email.to,
+        header_value(&email.subject),
+        Utc::now().to_rfc2822(),
+        Uuid::new_v4(),
+        domain(&config.from),
+    );
+    // base64 is ASCII, so any split of it is valid UTF-8
+    for line in body.as_bytes().chunks(76) {
+        message.push_str(std::str::from_utf8(line).unwrap_or_default());
+        message.push_str("\r\n");
+    }
+    message.push_str(".\r\n");
+    message
+}
@@ -0,0 +1,149 @@
+// Comment mentions
+// "@handle" in a comment's content mentions a project member. A handle is
+// either a member's email or a name: the local part of their email or their
+// display name without spaces. Both match ignoring case. Handles that match no
+// member, or more than one, are left as plain text.
+
+use std::collections::HashSet;
+
+use sqlx::FromRow;
+
+use crate::db::DbPool;
+use crate::error::Result;
+
+#[derive(Debug, Clone, FromRow)]
+pub struct Member {
+    pub id: String,
+    pub name: String,
+    pub email: String,
+}
+
+impl Member {
+    fn answers_to(&self, handle: &str) -> bool {
+        if handle.contains('@') {
+            return self.email.eq_ignore_ascii_case(handle);
+        }
+        let local_part = self.email.split('@').next().unwrap_or_default();
+        let compact_name: String = self.name.split_whitespace().collect();
+        local_part.eq_ignore_ascii_case(handle)
+            || compact_name.to_lowercase() == handle.to_lowercase()
+    }
+}
+
+fn is_handle_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '_' | '%' | '+' | '-')
+}
+
+/// The handles mentioned in `content`, without the `@`, in order of
+/// appearance. An `@` inside a word, as in an email address written out,
+/// starts no mention.
+pub fn parse(content: &str) -> Vec<&str> {
+    let mut handles = Vec::new();
+    let mut previous = None;
+
+    for (at, c) in content.char_indices() {
+        let starts_mention =
+            c == '@' && !previous.is_some_and(|p: char| is_handle_char(p) || p == '@');
+        previous = Some(c);
+        if !starts_mention {
+            continue;
+        }
+
+        let rest = &content[at + 1..];
+        let mut end = rest.find(|c| !is_handle_char(c)).unwrap_or(rest.len());
+        // One more '@' continues the handle into an email domain
+        if rest[end..].starts_with('@') {
+            let domain = &rest[end + 1..];
+            let domain_len = domain
+                .find(|c: char| !(c.is_alphanumeric() || c == '.' || c == '-'))
+                .unwrap_or(domain.len());
+            if domain_len > 0 {
+                end += 1 + domain_len;
+            }
+        }
+
+        // Punctuation ending a sentence is not part of the handle
+        let handle = rest[..end].trim_end_matches(['.', '-']);
+        if !handle.is_empty() {
+            handles.push(handle);
+        }
+    }
+    handles
+}
+
+/// The owner and collaborators of a project, leaving out disabled accounts.
+pub async fn members(pool: &DbPool, project_id: &str) -> Result<Vec<Member>> {
+    let members = sqlx::query_as::<_, Member>(
+        r#"
+        SELECT id, name, email FROM users WHERE disabled_at IS NULL AND id IN (
+            SELECT owner_id FROM projects WHERE id = $1
+            UNION SELECT user_id FROM project_collaborators WHERE project_id = $2
+        )
+        "#,
+    )
+    .bind(project_id)
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(members)
+}
+
+/// Project members mentioned in `content`, each once.
+pub async fn resolve(pool: &DbPool, project_id: &str, content: &str) -> Result<Vec<Member>> {
+    let handles = parse(content);
+    if handles.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let members = members(pool, project_id).await?;
+    let mut seen = HashSet::new();
+    let mut mentioned = Vec::new();
+    for handle in handles {
+        let mut matches = members.iter().filter(|member| member.answers_to(handle));
+        if let (Some(member), None) = (matches.next(), matches.next()) {
+            if seen.insert(member.id.clone()) {
+                mentioned.push(member.clone());
+            }
+        }
+    }
+    Ok(mentioned)
+}
+
+/// Store who a comment mentions, replacing what an earlier version of it
+/// mentioned, and return the members it did not mention before.
+pub async fn record(
+    pool: &DbPool,
+    project_id: &str,
+    comment_id: &str,
+    content: &str,
+) -> Result<Vec<Member>> {
+    let mentioned = resolve(pool, project_id, content).await?;
+
+    let mut tx = pool.begin().await?;
+    let previous: HashSet<String> = sqlx::query_scalar::<_, String>(
+        "SELECT user_id FROM comment_mentions WHERE comment_id = $1",
+    )
+    .bind(comment_id)
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .collect();
+
+    sqlx::query("DELETE FROM comment_mentions WHERE comment_id = $1")
+        .bind(comment_id)
+        .execute(&mut *tx)
+        .await?;
+    for member in &mentioned {
+        sqlx::query("INSERT INTO comment_mentions (comment_id, user_id) VALUES ($1, $2)")
+            .bind(comment_id)
+            .bind(&member.id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(mentioned
+        .into_iter()
+        .filter(|member| !previous.contains(&member.id))
+        .collect())
+}
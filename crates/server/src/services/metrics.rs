@@ -0,0 +1,141 @@
+// Server metrics
+// Counters and histograms of compile outcomes and timings, plus collaboration
+// gauges, rendered in the Prometheus text format for the metrics endpoint
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::services::{collab::CollabStats, compile_jobs::JobStatus};
+
+// Histogram bucket bounds, in seconds
+const BUCKETS: [f64; 9] = [0.5, 1.0, 2.0, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations at or below each bound in BUCKETS
+    counts: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: Duration) {
+        let seconds = value.as_secs_f64();
+        for (bound, count) in BUCKETS.iter().zip(&mut self.counts) {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {name} {help}");
+        let _ = writeln!(out, "# TYPE {name} histogram");
+        for (bound, count) in BUCKETS.iter().zip(&self.counts) {
+            let _ = writeln!(out, "{name}_bucket{{le=\"{bound}\"}} {count}");
+        }
+        let _ = writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {}", self.count);
+        let _ = writeln!(out, "{name}_sum {}", self.sum);
+        let _ = writeln!(out, "{name}_count {}", self.count);
+    }
+}
+
+#[derive(Default)]
+struct CompileMetrics {
+    /// Finished compiles by final status
+    by_status: BTreeMap<&'static str, u64>,
+    succeeded: u64,
+    duration: Histogram,
+    queue_wait: Histogram,
+}
+
+/// Metrics since the server started.
+#[derive(Clone, Default)]
+pub struct Metrics {
+    compiles: Arc<Mutex<CompileMetrics>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Count a finished compile. `run` is zero for compiles that never got a
+    /// slot, which are left out of the duration histogram.
+    pub fn record_compile(&self, status: JobStatus, success: bool, queue: Duration, run: Duration) {
+        let mut compiles = self.compiles.lock().unwrap_or_else(|e| e.into_inner());
+        *compiles.by_status.entry(status.as_str()).or_default() += 1;
+        if success {
+            compiles.succeeded += 1;
+        }
+        compiles.queue_wait.observe(queue);
+        if !run.is_zero() {
+            compiles.duration.observe(run);
+        }
+    }
+
+    /// Everything in the Prometheus text format, along with the current
+    /// number of running and queued compiles and open collaboration rooms.
+    pub fn render(&self, running: usize, queued: usize, collab: CollabStats) -> String {
+        let compiles = self.compiles.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = String::new();
+
+        out.push_str("# HELP openleaf_compiles_total Finished compiles by final status.\n");
+        out.push_str("# TYPE openleaf_compiles_total counter\n");
+        for (status, count) in &compiles.by_status {
+            let _ = writeln!(
+                out,
+                "openleaf_compiles_total{{status=\"{status}\"}} {count}"
+            );
+        }
+        out.push_str(
+            "# HELP openleaf_compiles_succeeded_total Compiles that produced a PDF without errors.\n",
+        );
+        out.push_str("# TYPE openleaf_compiles_succeeded_total counter\n");
+        let _ = writeln!(
+            out,
+            "openleaf_compiles_succeeded_total {}",
+            compiles.succeeded
+        );
+
+        compiles.duration.render(
+            &mut out,
+            "openleaf_compile_duration_seconds",
+            "Time spent compiling, excluding the wait for a slot.",
+        );
+        compiles.queue_wait.render(
+            &mut out,
+            "openleaf_compile_queue_wait_seconds",
+            "Time compiles spent waiting for a slot.",
+        );
+
+        out.push_str("# HELP openleaf_compiles_running Compiles currently running.\n");
+        out.push_str("# TYPE openleaf_compiles_running gauge\n");
+        let _ = writeln!(out, "openleaf_compiles_running {running}");
+        out.push_str("# HELP openleaf_compiles_queued Compiles waiting for a slot.\n");
+        out.push_str("# TYPE openleaf_compiles_queued gauge\n");
+        let _ = writeln!(out, "openleaf_compiles_queued {queued}");
+
+        out.push_str("# HELP openleaf_collab_rooms Files open for collaborative editing.\n");
+        out.push_str("# TYPE openleaf_collab_rooms gauge\n");
+        let _ = writeln!(out, "openleaf_collab_rooms {}", collab.rooms);
+        out.push_str("# HELP openleaf_collab_connections Connected collaboration clients.\n");
+        out.push_str("# TYPE openleaf_collab_connections gauge\n");
+        let _ = writeln!(out, "openleaf_collab_connections {}", collab.connections);
+        out.push_str(
+            "# HELP openleaf_collab_rooms_evicted_total Rooms closed after sitting idle.\n",
+        );
+        out.push_str("# TYPE openleaf_collab_rooms_evicted_total counter\n");
+        let _ = writeln!(
+            out,
+            "openleaf_collab_rooms_evicted_total {}",
+            collab.evicted
+        );
+
+        out
+    }
+}
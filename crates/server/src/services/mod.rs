@@ -1,3 +1,60 @@
+pub mod accounts;
+pub mod api_tokens;
+pub mod authorship;
+pub mod backup;
+pub mod bib_import;
+pub mod bibliography;
+pub mod bibtex;
+pub mod build_cache;
+pub mod chat;
 pub mod collab;
+pub mod compile_history;
+pub mod compile_jobs;
 pub mod compiler;
+pub mod convert;
+pub mod diff;
+pub mod digest;
+pub mod error_log;
+pub mod events;
+pub mod exclude;
+pub mod export;
+pub mod figures;
+pub mod filetype;
+pub mod freeze;
+pub mod gc;
+pub mod git;
+pub mod git_credentials;
+pub mod health;
+pub mod history;
+pub mod identicon;
+pub mod instance_stats;
+pub mod latexdiff;
+pub mod lint;
+pub mod mailer;
+pub mod mentions;
+pub mod metrics;
+pub mod notifications;
+pub mod outline;
+pub mod packages;
+pub mod pdf_pages;
+pub mod provenance;
+pub mod rate_limit;
+pub mod reconcile;
+pub mod search;
+pub mod secrets;
+pub mod sessions;
+pub mod spellcheck;
 pub mod storage;
+pub mod submission;
+pub mod symbols;
+pub mod synctex;
+pub mod system_status;
+pub mod templates;
+pub mod thumbnail;
+pub mod tls;
+pub mod track_changes;
+pub mod uploads;
+pub mod versions;
+pub mod webdav;
+pub mod wordcount;
+pub mod zotero;
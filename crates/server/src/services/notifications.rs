@@ -0,0 +1,384 @@
+// Notifications
+// Tells a user about things that concern them: comments in their projects,
+// mentions, being added to a project and compiles of theirs that failed. A
+// notification is stored until read and pushed to the user's open
+// notification sockets as it arrives. With a mailer configured, users are also
+// emailed about each one, or sent a daily digest of those still unread, as
+// they choose. The digest also sums up what changed in each of their projects.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{Duration as ChronoDuration, Timelike, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    db::{Database, DbPool},
+    error::Result,
+    services::{
+        digest::{self, ProjectChanges},
+        mailer::{Email, Mailer},
+    },
+};
+
+pub const COMMENT: &str = "comment";
+pub const MENTION: &str = "mention";
+pub const INVITE: &str = "invite";
+pub const COMPILE_FAILED: &str = "compile_failed";
+
+// Email preferences
+pub const EMAIL_IMMEDIATE: &str = "immediate";
+pub const EMAIL_DAILY: &str = "daily";
+pub const EMAIL_OFF: &str = "off";
+
+// Notifications a slow socket may fall behind by before it has to refetch
+const CHANNEL_CAPACITY: usize = 32;
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct Notification {
+    pub id: String,
+    pub kind: String,
+    pub project_id: Option<String>,
+    pub project_name: Option<String>,
+    /// Who caused it, if anyone
+    pub actor_id: Option<String>,
+    pub actor_name: Option<String>,
+    /// The comment or compile job it is about
+    pub target_id: Option<String>,
+    pub file_path: Option<String>,
+    pub message: String,
+    pub read_at: Option<String>,
+    pub created_at: String,
+}
+
+// Columns selected into a Notification, from notifications n
+const NOTIFICATION_SELECT: &str = "SELECT n.id, n.kind, n.project_id, p.name AS project_name, \
+    n.actor_id, u.name AS actor_name, n.target_id, n.file_path, n.message, n.read_at, n.created_at \
+    FROM notifications n \
+    LEFT JOIN projects p ON n.project_id = p.id \
+    LEFT JOIN users u ON n.actor_id = u.id";
+
+/// What to tell the recipients of a notification.
+#[derive(Debug, Clone)]
+pub struct NewNotification {
+    pub kind: &'static str,
+    pub project_id: String,
+    pub actor_id: Option<String>,
+    pub target_id: Option<String>,
+    pub file_path: Option<String>,
+    pub message: String,
+}
+
+#[derive(Clone)]
+pub struct NotificationService {
+    db: Database,
+    // Push channel per user with a notification socket open
+    channels: Arc<Mutex<HashMap<String, broadcast::Sender<Notification>>>>,
+    mailer: Option<Mailer>,
+}
+
+impl NotificationService {
+    pub fn new(db: Database, mailer: Option<Mailer>) -> Self {
+        Self {
+            db,
+            channels: Arc::default(),
+            mailer,
+        }
+    }
+
+    /// Whether notifications can be sent by email at all.
+    pub fn email_enabled(&self) -> bool {
+        self.mailer.is_some()
+    }
+
+    fn channels(
+        &self,
+    ) -> std::sync::MutexGuard<'_, HashMap<String, broadcast::Sender<Notification>>> {
+        self.channels.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn subscribe(&self, user_id: &str) -> broadcast::Receiver<Notification> {
+        let mut channels = self.channels();
+        // Drop channels of users whose sockets have all closed
+        channels.retain(|_, sender| sender.receiver_count() > 0);
+        channels
+            .entry(user_id.to_string())
+            .or_insert_with(|| broadcast::channel(CHANNEL_CAPACITY).0)
+            .subscribe()
+    }
+
+    /// Store a notification for each recipient and push it to those online.
+    /// Best effort: whatever caused it has already happened, so failures are
+    /// only logged.
+    pub async fn notify(&self, recipients: &[String], notification: NewNotification) {
+        for user_id in recipients {
+            if let Err(e) = self.deliver(user_id, &notification).await {
+                tracing::warn!("Failed to notify {}: {}", user_id, e);
+            }
+        }
+    }
+
+    async fn deliver(&self, user_id: &str, notification: &NewNotification) -> Result<()> {
+        let stored = self.insert(user_id, notification).await?;
+        if let Some(sender) = self.channels().get(user_id) {
+            let _ = sender.send(stored.clone());
+        }
+
+        let Some(mailer) = &self.mailer else {
+            return Ok(());
+        };
+        let recipient = sqlx::query_as::<_, (String, String)>(
+            "SELECT email, email_notifications FROM users WHERE id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.db.pool)
+        .await?;
+        if let Some((email, preference)) = recipient {
+            if preference == EMAIL_IMMEDIATE {
+                mailer.send(Email {
+                    to: email,
+                    subject: stored.message.clone(),
+                    body: email_body(mailer, std::slice::from_ref(&stored), &[]),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Email each user who asked for a daily digest the notifications they
+    /// got since the last one and have not read yet, and what changed in
+    /// their projects meanwhile. Returns how many digests were sent.
+    pub async fn send_digests(&self) -> Result<usize> {
+        let Some(mailer) = &self.mailer else {
+            return Ok(0);
+        };
+        let now = Utc::now();
+        let default_since = (now - ChronoDuration::days(1)).to_rfc3339();
+
+        let users = sqlx::query_as::<_, (String, String, Option<String>)>(
+            "SELECT id, email, digest_sent_at FROM users WHERE email_notifications = $1",
+        )
+        .bind(EMAIL_DAILY)
+        .fetch_all(&self.db.pool)
+        .await?;
+
+        let mut sent = 0;
+        for (user_id, email, since) in users {
+            let since = since.unwrap_or_else(|| default_since.clone());
+            let notifications = sqlx::query_as::<_, Notification>(&format!(
+                "{NOTIFICATION_SELECT} WHERE n.user_id = $1 AND n.read_at IS NULL \
+                 AND n.created_at > $2 ORDER BY n.created_at"
+            ))
+            .bind(&user_id)
+            .bind(&since)
+            .fetch_all(&self.db.pool)
+            .await?;
+
+            let projects = sqlx::query_as::<_, (String, String)>(
+                "SELECT DISTINCT p.id, p.name FROM projects p \
+                 LEFT JOIN project_collaborators pc ON p.id = pc.project_id \
+                 WHERE p.owner_id = $1 OR pc.user_id = $2 ORDER BY p.name",
+            )
+            .bind(&user_id)
+            .bind(&user_id)
+            .fetch_all(&self.db.pool)
+            .await?;
+            let mut changes = Vec::new();
+            for (project_id, name) in projects {
+                let project_changes = digest::since(&self.db.pool, &project_id, &since).await?;
+                if !project_changes.is_empty() {
+                    changes.push((project_id, name, project_changes));
+                }
+            }
+
+            if !notifications.is_empty() || !changes.is_empty() {
+                let subject = match notifications.len() {
+                    0 => "Changes in your projects".to_string(),
+                    1 => "1 new notification".to_string(),
+                    count => format!("{count} new notifications"),
+                };
+                mailer.send(Email {
+                    to: email,
+                    subject,
+                    body: email_body(mailer, &notifications, &changes),
+                });
+                sent += 1;
+            }
+
+            sqlx::query("UPDATE users SET digest_sent_at = $1 WHERE id = $2")
+                .bind(now.to_rfc3339())
+                .bind(&user_id)
+                .execute(&self.db.pool)
+                .await?;
+        }
+        Ok(sent)
+    }
+
+    async fn insert(&self, user_id: &str, notification: &NewNotification) -> Result<Notification> {
+        let id = Uuid::new_v4().to_string();
+        sqlx::query(
+            "INSERT INTO notifications (id, user_id, kind, project_id, actor_id, target_id, file_path, message, created_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+        )
+        .bind(&id)
+        .bind(user_id)
+        .bind(notification.kind)
+        .bind(&notification.project_id)
+        .bind(&notification.actor_id)
+        .bind(&notification.target_id)
+        .bind(&notification.file_path)
+        .bind(&notification.message)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.db.pool)
+        .await?;
+
+        let stored =
+            sqlx::query_as::<_, Notification>(&format!("{NOTIFICATION_SELECT} WHERE n.id = $1"))
+                .bind(&id)
+                .fetch_one(&self.db.pool)
+                .await?;
+        Ok(stored)
+    }
+}
+
+fn email_body(
+    mailer: &Mailer,
+    notifications: &[Notification],
+    changes: &[(String, String, ProjectChanges)],
+) -> String {
+    let mut body = String::new();
+    for notification in notifications {
+        body.push_str(&notification.message);
+        if let Some(project_name) = &notification.project_name {
+            body.push_str(&format!(" ({project_name})"));
+        }
+        body.push('\n');
+        if let Some(project_id) = &notification.project_id {
+            body.push_str(&mailer.url(&format!("/project/{project_id}")));
+            body.push('\n');
+        }
+        body.push('\n');
+    }
+    if !changes.is_empty() {
+        body.push_str("Changes in your projects:\n\n");
+    }
+    for (project_id, name, project_changes) in changes {
+        body.push_str(&format!("{name}: {}\n", project_changes.summary()));
+        body.push_str(&mailer.url(&format!("/project/{project_id}")));
+        body.push_str("\n\n");
+    }
+    body.push_str(&format!(
+        "You can choose how you hear about notifications, or turn these emails off, at {}\n",
+        mailer.url("/projects")
+    ));
+    body
+}
+
+/// Send daily digests at `hour` o'clock UTC.
+pub fn spawn_digest_scheduler(service: NotificationService, hour: u32) {
+    tokio::spawn(async move {
+        let now = Utc::now();
+        let seconds_into_day = now.num_seconds_from_midnight() as u64;
+        let target = u64::from(hour) * 3600;
+        let wait = (target + 86400 - seconds_into_day) % 86400;
+
+        let day = Duration::from_secs(86400);
+        let mut ticker =
+            tokio::time::interval_at(tokio::time::Instant::now() + Duration::from_secs(wait), day);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            match service.send_digests().await {
+                Ok(0) => {}
+                Ok(sent) => tracing::info!("Sent {} notification digests", sent),
+                Err(e) => tracing::warn!("Sending notification digests failed: {}", e),
+            }
+        }
+    });
+}
+
+/// A user's email preference.
+pub async fn email_preference(pool: &DbPool, user_id: &str) -> Result<String> {
+    let preference =
+        sqlx::query_scalar::<_, String>("SELECT email_notifications FROM users WHERE id = $1")
+            .bind(user_id)
+            .fetch_one(pool)
+            .await?;
+    Ok(preference)
+}
+
+pub async fn set_email_preference(pool: &DbPool, user_id: &str, preference: &str) -> Result<()> {
+    sqlx::query("UPDATE users SET email_notifications = $1 WHERE id = $2")
+        .bind(preference)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// A user's notifications, newest first, created before `before` if given.
+pub async fn list(
+    pool: &DbPool,
+    user_id: &str,
+    unread_only: bool,
+    before: Option<&str>,
+    limit: i64,
+) -> Result<Vec<Notification>> {
+    let notifications = sqlx::query_as::<_, Notification>(&format!(
+        "{NOTIFICATION_SELECT} WHERE n.user_id = $1 AND (NOT $2 OR n.read_at IS NULL) \
+         AND ($3 IS NULL OR n.created_at < $4) ORDER BY n.created_at DESC LIMIT $5"
+    ))
+    .bind(user_id)
+    .bind(unread_only)
+    .bind(before)
+    .bind(before)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(notifications)
+}
+
+pub async fn unread_count(pool: &DbPool, user_id: &str) -> Result<i64> {
+    let count = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM notifications WHERE user_id = $1 AND read_at IS NULL",
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    Ok(count)
+}
+
+/// Mark one of the user's notifications read; false if they have no such
+/// notification.
+pub async fn mark_read(pool: &DbPool, user_id: &str, id: &str) -> Result<bool> {
+    let result = sqlx::query(
+        "UPDATE notifications SET read_at = COALESCE(read_at, $1) WHERE id = $2 AND user_id = $3",
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(id)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Mark all of the user's notifications read, or only those of a project.
+pub async fn mark_all_read(pool: &DbPool, user_id: &str, project_id: Option<&str>) -> Result<u64> {
+    let result = sqlx::query(
+        "UPDATE notifications SET read_at = $1 \
+         WHERE user_id = $2 AND read_at IS NULL AND ($3 IS NULL OR project_id = $4)",
+    )
+    .bind(Utc::now().to_rfc3339())
+    .bind(user_id)
+    .bind(project_id)
+    .bind(project_id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,342 @@
+// Document outline
+// Parses sectioning commands, labels, environments and included files out of
+// LaTeX source, with line numbers, for the outline sidebar and breadcrumbs
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+// Sectioning commands by depth
+const SECTIONS: &[(&str, u8)] = &[
+    ("part", 0),
+    ("chapter", 1),
+    ("section", 2),
+    ("subsection", 3),
+    ("subsubsection", 4),
+    ("paragraph", 5),
+    ("subparagraph", 6),
+];
+
+const INCLUDES: &[&str] = &["input", "include", "subfile"];
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Section {
+    /// "section", "subsection", ...
+    pub kind: &'static str,
+    /// 0 for \part down to 6 for \subparagraph
+    pub level: u8,
+    pub title: String,
+    /// Unnumbered (\section*)
+    pub starred: bool,
+    /// 1-based
+    pub line: usize,
+    /// The first \label after the heading, before any environment
+    pub label: Option<String>,
+    pub children: Vec<Section>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Label {
+    pub name: String,
+    pub line: usize,
+    /// The environment the label is in, if any
+    pub environment: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Environment {
+    pub name: String,
+    pub line: usize,
+    /// `None` when the environment is never closed
+    pub end_line: Option<usize>,
+    pub label: Option<String>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Include {
+    pub command: &'static str,
+    pub path: String,
+    pub line: usize,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct Outline {
+    /// Top-level sections, each holding its subsections
+    pub sections: Vec<Section>,
+    pub labels: Vec<Label>,
+    pub environments: Vec<Environment>,
+    pub includes: Vec<Include>,
+}
+
+/// Parse the structure of a LaTeX source file.
+pub fn parse(source: &str) -> Outline {
+    let chars: Vec<char> = strip_comments(source).chars().collect();
+    let lines = LineIndex::new(&chars);
+
+    let mut outline = Outline::default();
+    let mut sections: Vec<Section> = Vec::new();
+    // Open environments, as indexes into outline.environments
+    let mut open: Vec<usize> = Vec::new();
+    // Whether a \label now would belong to the latest heading
+    let mut heading_label_pending = false;
+
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '\\' {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        i += 1;
+        let name_start = i;
+        while i < chars.len() && chars[i].is_ascii_alphabetic() {
+            i += 1;
+        }
+        if i == name_start {
+            // An escaped character such as \\ or \{
+            i += 1;
+            continue;
+        }
+        let name: String = chars[name_start..i].iter().collect();
+        let line = lines.line(start);
+
+        if let Some(&(kind, level)) = SECTIONS.iter().find(|(kind, _)| *kind == name) {
+            let starred = chars.get(i) == Some(&'*');
+            if starred {
+                i += 1;
+            }
+            i = skip_options(&chars, i);
+            let Some((title, after)) = argument(&chars, i) else {
+                continue;
+            };
+            i = after;
+            sections.push(Section {
+                kind,
+                level,
+                title: collapse_whitespace(&title),
+                starred,
+                line,
+                label: None,
+                children: Vec::new(),
+            });
+            heading_label_pending = true;
+            continue;
+        }
+
+        match name.as_str() {
+            "label" => {
+                let Some((label, after)) = argument(&chars, i) else {
+                    continue;
+                };
+                i = after;
+                let label = label.trim().to_string();
+                let environment = match open.last() {
+                    Some(&index) => {
+                        // The first label in an environment names it
+                        let environment = &mut outline.environments[index];
+                        environment.label.get_or_insert_with(|| label.clone());
+                        Some(environment.name.clone())
+                    }
+                    None => {
+                        if heading_label_pending {
+                            if let Some(section) = sections.last_mut() {
+                                section.label = Some(label.clone());
+                            }
+                            heading_label_pending = false;
+                        }
+                        None
+                    }
+                };
+                outline.labels.push(Label {
+                    name: label,
+                    line,
+                    environment,
+                });
+            }
+            "begin" => {
+                let Some((environment, after)) = argument(&chars, i) else {
+                    continue;
+                };
+                i = after;
+                let environment = environment.trim().to_string();
+                if environment == "document" {
+                    continue;
+                }
+                heading_label_pending = false;
+                open.push(outline.environments.len());
+                outline.environments.push(Environment {
+                    name: environment,
+                    line,
+                    end_line: None,
+                    label: None,
+                });
+            }
+            "end" => {
+                let Some((environment, after)) = argument(&chars, i) else {
+                    continue;
+                };
+                i = after;
+                let environment = environment.trim();
+                // Close the innermost matching environment; anything opened
+                // inside it and left open stays unclosed
+                if let Some(position) = open
+                    .iter()
+                    .rposition(|&index| outline.environments[index].name == environment)
+                {
+                    outline.environments[open[position]].end_line = Some(line);
+                    open.truncate(position);
+                }
+            }
+            _ => {
+                if let Some(&command) = INCLUDES.iter().find(|command| **command == name) {
+                    let Some((path, after)) = argument(&chars, i) else {
+                        continue;
+                    };
+                    i = after;
+                    outline.includes.push(Include {
+                        command,
+                        path: path.trim().to_string(),
+                        line,
+                    });
+                }
+            }
+        }
+    }
+
+    outline.sections = nest(sections);
+    outline
+}
+
+// Turns headings in document order into a tree by level
+fn nest(sections: Vec<Section>) -> Vec<Section> {
+    let mut roots: Vec<Section> = Vec::new();
+    // Chain of headings from a root down to the latest one
+    let mut stack: Vec<Section> = Vec::new();
+
+    for section in sections {
+        while let Some(done) = stack.pop_if(|parent| parent.level >= section.level) {
+            attach(&mut stack, &mut roots, done);
+        }
+        stack.push(section);
+    }
+    while let Some(done) = stack.pop() {
+        attach(&mut stack, &mut roots, done);
+    }
+    roots
+}
+
+fn attach(stack: &mut [Section], roots: &mut Vec<Section>, section: Section) {
+    match stack.last_mut() {
+        Some(parent) => parent.children.push(section),
+        None => roots.push(section),
+    }
+}
+
+// Replaces comments with spaces, keeping offsets and line breaks in place
+pub(crate) fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut in_comment = false;
+    let mut escaped = false;
+    for c in source.chars() {
+        if c == '\n' {
+            in_comment = false;
+            escaped = false;
+            out.push(c);
+            continue;
+        }
+        if in_comment {
+            out.push(' ');
+            continue;
+        }
+        if c == '%' && !escaped {
+            in_comment = true;
+            out.push(' ');
+            continue;
+        }
+        escaped = c == '\\' && !escaped;
+        out.push(c);
+    }
+    out
+}
+
+pub(crate) struct LineIndex {
+    /// Character offset of the start of each line
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub(crate) fn new(chars: &[char]) -> Self {
+        let mut starts = vec![0];
+        starts.extend(
+            chars
+                .iter()
+                .enumerate()
+                .filter(|(_, c)| **c == '\n')
+                .map(|(index, _)| index + 1),
+        );
+        Self { starts }
+    }
+
+    /// 1-based line of a character offset.
+    pub(crate) fn line(&self, offset: usize) -> usize {
+        self.starts.partition_point(|start| *start <= offset)
+    }
+}
+
+pub(crate) fn skip_whitespace(chars: &[char], mut i: usize) -> usize {
+    while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+        i += 1;
+    }
+    i
+}
+
+// Skips optional arguments such as the short title in \section[short]{long}
+pub(crate) fn skip_options(chars: &[char], from: usize) -> usize {
+    let mut i = skip_whitespace(chars, from);
+    while chars.get(i) == Some(&'[') {
+        let mut depth = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '[' => depth += 1,
+                ']' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        i += 1;
+                        break;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        i = skip_whitespace(chars, i);
+    }
+    i
+}
+
+// A braced argument at `from` and the index after it
+pub(crate) fn argument(chars: &[char], from: usize) -> Option<(String, usize)> {
+    let start = skip_whitespace(chars, from);
+    if chars.get(start) != Some(&'{') {
+        return None;
+    }
+    let mut depth = 0;
+    for (offset, c) in chars[start..].iter().enumerate() {
+        match c {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    let end = start + offset;
+                    return Some((chars[start + 1..end].iter().collect(), end + 1));
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// Titles may be wrapped across lines
+pub(crate) fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
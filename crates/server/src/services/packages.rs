@@ -0,0 +1,161 @@
+// Missing LaTeX packages
+// Spots "File `foo.sty' not found" in compile logs, names the TeX Live
+// package that provides the file, and can install it with tlmgr
+
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use utoipa::ToSchema;
+
+use crate::{
+    error::{AppError, Result},
+    services::compile_jobs::CompileJob,
+};
+
+const TLMGR_TIMEOUT: Duration = Duration::from_secs(600);
+
+// File extensions that belong to installable packages rather than the project
+const PACKAGE_EXTENSIONS: &[&str] = &["sty", "cls", "bst", "bbx", "cbx", "def", "fd", "clo", "cfg"];
+
+// Files whose TeX Live package isn't simply named after them
+const PROVIDERS: &[(&str, &str)] = &[
+    ("afterpage.sty", "tools"),
+    ("algorithm.sty", "algorithms"),
+    ("algorithmic.sty", "algorithms"),
+    ("algpseudocode.sty", "algorithmicx"),
+    ("amssymb.sty", "amsfonts"),
+    ("amsthm.sty", "amscls"),
+    ("array.sty", "tools"),
+    ("bm.sty", "tools"),
+    ("calc.sty", "tools"),
+    ("color.sty", "graphics"),
+    ("dcolumn.sty", "tools"),
+    ("epstopdf.sty", "epstopdf-pkg"),
+    ("expl3.sty", "l3kernel"),
+    ("fontenc.sty", "latex"),
+    ("graphicx.sty", "graphics"),
+    ("helvet.sty", "psnfss"),
+    ("IEEEtran.bst", "ieeetran"),
+    ("IEEEtran.cls", "ieeetran"),
+    ("inputenc.sty", "latex"),
+    ("lmodern.sty", "lm"),
+    ("longtable.sty", "tools"),
+    ("mathptmx.sty", "psnfss"),
+    ("multicol.sty", "tools"),
+    ("plainnat.bst", "natbib"),
+    ("pgfplots.sty", "pgfplots"),
+    ("revtex4-2.cls", "revtex"),
+    ("subcaption.sty", "caption"),
+    ("tabularx.sty", "tools"),
+    ("tikz.sty", "pgf"),
+    ("times.sty", "psnfss"),
+    ("verbatim.sty", "tools"),
+    ("xparse.sty", "l3packages"),
+    ("xspace.sty", "tools"),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct MissingPackage {
+    /// The file LaTeX looked for, e.g. "minted.sty"
+    pub file: String,
+    /// TeX Live package that provides it
+    pub package: String,
+    pub url: String,
+}
+
+/// Files the log says could not be found, one entry per file.
+pub fn parse_missing(log: &str) -> Vec<MissingPackage> {
+    let mut missing: Vec<MissingPackage> = Vec::new();
+
+    for line in log.lines() {
+        // "! LaTeX Error: File `minted.sty' not found."
+        let Some((_, rest)) = line.split_once("File `") else {
+            continue;
+        };
+        let Some((file, after)) = rest.split_once('\'') else {
+            continue;
+        };
+        if !after.trim_start().starts_with("not found") {
+            continue;
+        }
+        let is_package_file = file
+            .rsplit_once('.')
+            .is_some_and(|(_, ext)| PACKAGE_EXTENSIONS.contains(&ext));
+        if !is_package_file || file.contains('/') || missing.iter().any(|m| m.file == file) {
+            continue;
+        }
+
+        let package = provider(file);
+        missing.push(MissingPackage {
+            file: file.to_string(),
+            url: format!("https://ctan.org/pkg/{package}"),
+            package,
+        });
+    }
+
+    missing
+}
+
+fn provider(file: &str) -> String {
+    PROVIDERS
+        .iter()
+        .find(|(known, _)| *known == file)
+        .map(|(_, package)| package.to_string())
+        .unwrap_or_else(|| {
+            file.rsplit_once('.')
+                .map_or(file, |(stem, _)| stem)
+                .to_string()
+        })
+}
+
+// Names come from the document, so keep anything that tlmgr could read as an
+// option off its command line
+fn is_valid_package_name(name: &str) -> bool {
+    name.chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphanumeric())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+/// Install the packages with tlmgr, logging its output to the job. Returns
+/// whether tlmgr succeeded.
+pub async fn install(packages: &[MissingPackage], job: &CompileJob) -> Result<bool> {
+    let mut names: Vec<&str> = packages
+        .iter()
+        .map(|missing| missing.package.as_str())
+        .filter(|name| is_valid_package_name(name))
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    if names.is_empty() {
+        return Ok(false);
+    }
+
+    job.append_log(&format!(
+        "\nInstalling missing packages: {}\n",
+        names.join(" ")
+    ));
+    let child = Command::new("tlmgr")
+        .arg("install")
+        .args(&names)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    let output = match tokio::time::timeout(TLMGR_TIMEOUT, child).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::Internal("tlmgr is not installed".to_string()));
+        }
+        Ok(Err(e)) => return Err(AppError::Internal(format!("Failed to run tlmgr: {e}"))),
+        Err(_) => return Err(AppError::Internal("tlmgr timed out".to_string())),
+    };
+    job.append_log(&String::from_utf8_lossy(&output.stdout));
+    job.append_log(&String::from_utf8_lossy(&output.stderr));
+
+    Ok(output.status.success())
+}
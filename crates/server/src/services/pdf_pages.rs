@@ -0,0 +1,176 @@
+// Compiled PDF pages
+// Page sizes for laying out the preview, and per-page PNG thumbnails rendered
+// with pdftoppm so the preview can navigate without fetching the whole PDF
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use lopdf::{Dictionary, Document, Object};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, Result},
+    services::thumbnail,
+};
+
+const PDFTOPPM_TIMEOUT: Duration = Duration::from_secs(120);
+
+// US Letter, for pages that don't say
+const DEFAULT_PAGE_SIZE: (f32, f32) = (612.0, 792.0);
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PageInfo {
+    pub page: u32,
+    /// In PDF points, as displayed (rotation applied)
+    pub width: f32,
+    pub height: f32,
+}
+
+/// Size of every page in the PDF, in page order.
+pub async fn pages(pdf_path: &Path) -> Result<Vec<PageInfo>> {
+    let pdf_path = pdf_path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let doc = Document::load(&pdf_path)
+            .map_err(|e| AppError::Internal(format!("Failed to load PDF: {e}")))?;
+
+        Ok(doc
+            .get_pages()
+            .into_iter()
+            .map(|(page, id)| {
+                let dict = doc.get_dictionary(id).ok();
+                let (width, height) = dict
+                    .and_then(|dict| media_box(&doc, dict))
+                    .unwrap_or(DEFAULT_PAGE_SIZE);
+                let rotate = dict
+                    .and_then(|dict| inherited(&doc, dict, b"Rotate"))
+                    .and_then(|rotate| rotate.as_i64().ok())
+                    .unwrap_or(0);
+                let (width, height) = if rotate.rem_euclid(180) == 90 {
+                    (height, width)
+                } else {
+                    (width, height)
+                };
+                PageInfo {
+                    page,
+                    width,
+                    height,
+                }
+            })
+            .collect())
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("PDF task failed: {e}")))?
+}
+
+fn media_box(doc: &Document, page: &Dictionary) -> Option<(f32, f32)> {
+    let values = inherited(doc, page, b"MediaBox")?.as_array().ok()?;
+    let coords: Vec<f32> = values
+        .iter()
+        .filter_map(|value| doc.dereference(value).ok()?.1.as_float().ok())
+        .collect();
+    match coords[..] {
+        [x0, y0, x1, y1] => Some(((x1 - x0).abs(), (y1 - y0).abs())),
+        _ => None,
+    }
+}
+
+// MediaBox and Rotate may be set on any ancestor in the page tree
+fn inherited<'a>(doc: &'a Document, page: &'a Dictionary, key: &[u8]) -> Option<&'a Object> {
+    let mut dict = page;
+    for _ in 0..32 {
+        if let Ok(value) = dict.get(key) {
+            return doc.dereference(value).ok().map(|(_, value)| value);
+        }
+        let parent = dict.get(b"Parent").ok()?.as_reference().ok()?;
+        dict = doc.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+/// Where the thumbnails of a compiled PDF are kept, next to it in the build
+/// directory.
+pub fn thumbnail_dir(build_dir: &Path, pdf_name: &str) -> PathBuf {
+    build_dir.join(".pages").join(pdf_name)
+}
+
+pub fn thumbnail_path(build_dir: &Path, pdf_name: &str, page: u32) -> PathBuf {
+    thumbnail_dir(build_dir, pdf_name).join(format!("{page}.png"))
+}
+
+/// Drop thumbnails of an earlier version of the PDF.
+pub async fn clear_thumbnails(build_dir: &Path, pdf_name: &str) {
+    let _ = tokio::fs::remove_dir_all(thumbnail_dir(build_dir, pdf_name)).await;
+}
+
+/// Render a thumbnail of every page, replacing any existing ones once all
+/// are done so requests never see a half-rendered set.
+pub async fn generate_thumbnails(build_dir: &Path, pdf_name: &str) -> Result<()> {
+    let io_error =
+        |e: std::io::Error| AppError::Internal(format!("Failed to store thumbnails: {e}"));
+
+    let target = thumbnail_dir(build_dir, pdf_name);
+    let staging = build_dir
+        .join(".pages")
+        .join(format!(".{}", Uuid::new_v4()));
+    tokio::fs::create_dir_all(&staging)
+        .await
+        .map_err(io_error)?;
+
+    let rendered = render(&build_dir.join(pdf_name), &staging).await;
+    let result = match rendered {
+        Ok(()) => {
+            let _ = tokio::fs::remove_dir_all(&target).await;
+            tokio::fs::rename(&staging, &target).await.map_err(io_error)
+        }
+        Err(e) => Err(e),
+    };
+    let _ = tokio::fs::remove_dir_all(&staging).await;
+    result
+}
+
+async fn render(pdf_path: &Path, dir: &Path) -> Result<()> {
+    let width = thumbnail::DEFAULT_WIDTH.to_string();
+    let child = tokio::process::Command::new("pdftoppm")
+        .args(["-png", "-scale-to-x", &width, "-scale-to-y", "-1"])
+        .arg(pdf_path)
+        .arg(dir.join("page"))
+        .kill_on_drop(true)
+        .output();
+
+    let output = match tokio::time::timeout(PDFTOPPM_TIMEOUT, child).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::Internal("pdftoppm is not installed".to_string()));
+        }
+        Ok(Err(e)) => return Err(AppError::Internal(format!("Failed to run pdftoppm: {e}"))),
+        Err(_) => return Err(AppError::Internal("pdftoppm timed out".to_string())),
+    };
+    if !output.status.success() {
+        return Err(AppError::Internal(format!(
+            "pdftoppm failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    // pdftoppm zero-pads page numbers to the width of the page count
+    // ("page-007.png"), so rename them to plain numbers
+    let io_error =
+        |e: std::io::Error| AppError::Internal(format!("Failed to store thumbnails: {e}"));
+    let mut entries = tokio::fs::read_dir(dir).await.map_err(io_error)?;
+    while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+        let name = entry.file_name();
+        let Some(page) = name
+            .to_str()
+            .and_then(|name| name.strip_prefix("page-")?.strip_suffix(".png"))
+            .and_then(|page| page.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        tokio::fs::rename(entry.path(), dir.join(format!("{page}.png")))
+            .await
+            .map_err(io_error)?;
+    }
+    Ok(())
+}
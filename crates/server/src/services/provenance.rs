@@ -0,0 +1,190 @@
+// Compile provenance stamped into PDFs as XMP metadata
+// Lets any exported PDF be traced back to the exact source state that produced it
+
+use std::path::Path;
+
+use lopdf::{dictionary, Document, Object, Stream};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use crate::error::{AppError, Result};
+
+const XMP_NAMESPACE: &str = "https://openleaf.dev/ns/provenance/1.0/";
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Provenance {
+    pub project_id: String,
+    pub source_hash: String,
+    pub compile_id: String,
+    pub toolchain: String,
+    pub compiled_at: String,
+}
+
+const FIELDS: [&str; 5] = [
+    "ProjectId",
+    "SourceHash",
+    "CompileId",
+    "Toolchain",
+    "CompiledAt",
+];
+
+impl Provenance {
+    fn values(&self) -> [&str; 5] {
+        [
+            &self.project_id,
+            &self.source_hash,
+            &self.compile_id,
+            &self.toolchain,
+            &self.compiled_at,
+        ]
+    }
+}
+
+/// Hash the project's source files in path order so identical trees always
+/// produce the same digest.
+pub async fn source_hash(project_path: &Path, file_paths: &[String]) -> Result<String> {
+    let mut sorted: Vec<&String> = file_paths.iter().collect();
+    sorted.sort();
+
+    let mut hasher = Sha256::new();
+    for path in sorted {
+        let content = tokio::fs::read(project_path.join(path))
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to read {path}: {e}")))?;
+        hasher.update(path.as_bytes());
+        hasher.update([0]);
+        hasher.update(&content);
+        hasher.update([0]);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// First line of `latexmk -v`, or "unknown" when the toolchain can't be queried.
+pub async fn toolchain_version() -> String {
+    tokio::process::Command::new("latexmk")
+        .arg("-v")
+        .output()
+        .await
+        .ok()
+        .and_then(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty())
+                .map(str::to_string)
+        })
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+fn escape_xml(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn build_xmp(provenance: &Provenance) -> String {
+    let mut xmp = String::new();
+    xmp.push_str("<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n");
+    xmp.push_str("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n");
+    xmp.push_str("<rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n");
+    xmp.push_str(&format!(
+        "<rdf:Description rdf:about=\"\" xmlns:openleaf=\"{XMP_NAMESPACE}\">\n"
+    ));
+    for (field, value) in FIELDS.iter().zip(provenance.values()) {
+        xmp.push_str(&format!(
+            "<openleaf:{field}>{}</openleaf:{field}>\n",
+            escape_xml(value)
+        ));
+    }
+    xmp.push_str("</rdf:Description>\n</rdf:RDF>\n</x:xmpmeta>\n");
+    xmp.push_str("<?xpacket end=\"r\"?>");
+    xmp
+}
+
+fn parse_xmp(xmp: &str) -> Option<Provenance> {
+    let field = |name: &str| -> Option<String> {
+        let open = format!("<openleaf:{name}>");
+        let close = format!("</openleaf:{name}>");
+        let start = xmp.find(&open)? + open.len();
+        let end = xmp[start..].find(&close)? + start;
+        Some(
+            xmp[start..end]
+                .replace("&quot;", "\"")
+                .replace("&gt;", ">")
+                .replace("&lt;", "<")
+                .replace("&amp;", "&"),
+        )
+    };
+
+    Some(Provenance {
+        project_id: field(FIELDS[0])?,
+        source_hash: field(FIELDS[1])?,
+        compile_id: field(FIELDS[2])?,
+        toolchain: field(FIELDS[3])?,
+        compiled_at: field(FIELDS[4])?,
+    })
+}
+
+/// Attach an XMP metadata stream to the document catalog, replacing any
+/// metadata the engine wrote.
+pub async fn stamp(pdf_path: &Path, provenance: &Provenance) -> Result<()> {
+    let pdf_path = pdf_path.to_path_buf();
+    let xmp = build_xmp(provenance);
+    tokio::task::spawn_blocking(move || {
+        let mut doc = Document::load(&pdf_path)
+            .map_err(|e| AppError::Internal(format!("Failed to load PDF: {e}")))?;
+
+        let stream = Stream::new(
+            dictionary! {
+                "Type" => "Metadata",
+                "Subtype" => "XML",
+            },
+            xmp.into_bytes(),
+        );
+        let metadata_id = doc.add_object(stream);
+
+        doc.catalog_mut()
+            .map_err(|e| AppError::Internal(format!("PDF has no catalog: {e}")))?
+            .set("Metadata", Object::Reference(metadata_id));
+
+        doc.save(&pdf_path)
+            .map_err(|e| AppError::Internal(format!("Failed to write PDF: {e}")))?;
+
+        Ok(())
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("PDF task failed: {e}")))?
+}
+
+/// Read back the provenance stamped by [`stamp`], if any.
+pub async fn read(pdf_path: &Path) -> Result<Option<Provenance>> {
+    let pdf_path = pdf_path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        let doc = Document::load(&pdf_path)
+            .map_err(|e| AppError::Internal(format!("Failed to load PDF: {e}")))?;
+
+        let metadata = doc
+            .catalog()
+            .and_then(|catalog| catalog.get(b"Metadata"))
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_object(id))
+            .and_then(Object::as_stream);
+
+        let stream = match metadata {
+            Ok(stream) => stream,
+            Err(_) => return Ok(None),
+        };
+
+        let content = stream
+            .decompressed_content()
+            .unwrap_or_else(|_| stream.content.clone());
+
+        Ok(parse_xmp(&String::from_utf8_lossy(&content)))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("PDF task failed: {e}")))?
+}
@@ -0,0 +1,114 @@
+// Request rate limits
+// Token buckets per client: one for the API as a whole, and tighter ones for
+// sign-in, compiles and uploads, which are costly or attractive to abuse.
+// A runaway auto-save loop in one editor then gets 429s instead of slowing
+// everyone else down.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::config::{RateBudget, RateLimitConfig};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Budget {
+    Global,
+    Auth,
+    Compile,
+    Upload,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Client {
+    User(String),
+    Ip(IpAddr),
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    buckets: Arc<Mutex<HashMap<(Budget, Client), Bucket>>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn trust_proxy_headers(&self) -> bool {
+        self.config.trust_proxy_headers
+    }
+
+    fn budget(&self, budget: Budget) -> RateBudget {
+        match budget {
+            Budget::Global => self.config.global,
+            Budget::Auth => self.config.auth,
+            Budget::Compile => self.config.compile,
+            Budget::Upload => self.config.upload,
+        }
+    }
+
+    /// Take one request from the client's budget. When it is used up,
+    /// returns the seconds until the next request would be allowed.
+    pub fn check(&self, budget: Budget, client: &Client) -> Result<(), u64> {
+        let RateBudget { per_minute, burst } = self.budget(budget);
+        if per_minute == 0 {
+            return Ok(());
+        }
+        let rate = f64::from(per_minute) / 60.0;
+        let burst = f64::from(burst.max(1));
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry((budget, client.clone())).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / rate).ceil() as u64)
+        }
+    }
+
+    /// Forget clients whose buckets have refilled; they start full anyway.
+    fn prune(&self) -> usize {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let before = buckets.len();
+        buckets.retain(|(budget, _), bucket| {
+            let RateBudget { per_minute, burst } = self.budget(*budget);
+            let rate = f64::from(per_minute) / 60.0;
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * rate < f64::from(burst.max(1))
+        });
+        before - buckets.len()
+    }
+}
+
+pub fn spawn_cleanup(limiter: RateLimiter) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(300));
+        loop {
+            ticker.tick().await;
+            let removed = limiter.prune();
+            if removed > 0 {
+                tracing::debug!("Dropped {} idle rate limit buckets", removed);
+            }
+        }
+    });
+}
@@ -0,0 +1,197 @@
+// Reconcile the files table with what is actually on disk
+// Covers edits made directly in the storage directory (vim, rsync, mounts)
+
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::Utc;
+use notify::{RecursiveMode, Watcher};
+use serde::Serialize;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    db::Database,
+    error::Result,
+    services::{
+        exclude::ExcludeRules,
+        filetype,
+        storage::{self, StorageService},
+    },
+};
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct RescanReport {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub updated: Vec<String>,
+}
+
+impl RescanReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.updated.is_empty()
+    }
+}
+
+/// Register files present on disk but missing from the database, drop rows
+/// whose files are gone, and refresh checksums of files edited out of band.
+pub async fn rescan_project(
+    db: &Database,
+    storage: &StorageService,
+    project_id: &str,
+) -> Result<RescanReport> {
+    // Build artifacts on disk are not project files
+    let rules = ExcludeRules::load(storage, project_id).await?;
+    let mut on_disk = storage.list_tree(project_id).await?;
+    on_disk.retain(|entry| !rules.is_excluded(&entry.path, entry.is_folder));
+
+    let rows = sqlx::query_as::<_, (String, String, bool, Option<String>)>(
+        "SELECT id, path, is_folder, hash FROM files WHERE project_id = $1",
+    )
+    .bind(project_id)
+    .fetch_all(&db.pool)
+    .await?;
+
+    let known: HashMap<&str, (&str, bool, Option<&str>)> = rows
+        .iter()
+        .map(|(id, path, is_folder, hash)| {
+            (path.as_str(), (id.as_str(), *is_folder, hash.as_deref()))
+        })
+        .collect();
+    let disk_paths: HashSet<&str> = on_disk.iter().map(|e| e.path.as_str()).collect();
+
+    let mut report = RescanReport::default();
+    let now = Utc::now().to_rfc3339();
+    let mut tx = db.pool.begin().await?;
+
+    for entry in &on_disk {
+        let (hash, file_type) = if entry.is_folder {
+            (None, None)
+        } else {
+            let content = storage.read_bytes(project_id, &entry.path).await?;
+            (
+                Some(storage::content_hash(&content)),
+                Some(filetype::detect(&entry.path, &content)),
+            )
+        };
+        let (mime_type, language) = file_type
+            .map(|file_type| (Some(file_type.mime_type), file_type.language))
+            .unwrap_or_default();
+
+        match known.get(entry.path.as_str()) {
+            None => {
+                let name = entry.path.rsplit('/').next().unwrap_or(&entry.path);
+                sqlx::query(
+                    "INSERT INTO files (id, project_id, name, path, is_folder, hash, mime_type, language, created_at, updated_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+                )
+                .bind(Uuid::new_v4().to_string())
+                .bind(project_id)
+                .bind(name)
+                .bind(&entry.path)
+                .bind(entry.is_folder)
+                .bind(&hash)
+                .bind(&mime_type)
+                .bind(&language)
+                .bind(&now)
+                .bind(&now)
+                .execute(&mut *tx)
+                .await?;
+                report.added.push(entry.path.clone());
+            }
+            Some((id, is_folder, stored_hash)) => {
+                if *is_folder != entry.is_folder || *stored_hash != hash.as_deref() {
+                    sqlx::query(
+                        "UPDATE files SET is_folder = $1, hash = $2, mime_type = $3, language = $4, updated_at = $5 WHERE id = $6",
+                    )
+                    .bind(entry.is_folder)
+                    .bind(&hash)
+                    .bind(&mime_type)
+                    .bind(&language)
+                    .bind(&now)
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+                    report.updated.push(entry.path.clone());
+                }
+            }
+        }
+    }
+
+    for (path, (id, _, _)) in &known {
+        if !disk_paths.contains(path) {
+            sqlx::query("DELETE FROM files WHERE id = $1")
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            report.removed.push(path.to_string());
+        }
+    }
+
+    tx.commit().await?;
+    report.removed.sort();
+
+    Ok(report)
+}
+
+/// Watch the storage directory and rescan projects touched out of band.
+/// Events are batched so a burst of writes triggers one rescan per project.
+pub fn spawn_watcher(db: Database, storage: StorageService) -> anyhow::Result<()> {
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+    // inotify reports paths relative to what was watched, so watch a canonical path
+    let base = std::fs::canonicalize(storage.base_path())?;
+    let watch_base = base.clone();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else {
+            return;
+        };
+        for path in &event.paths {
+            if let Some(project_id) = project_of(&watch_base, path) {
+                let _ = tx.send(project_id);
+            }
+        }
+    })?;
+    watcher.watch(&base, RecursiveMode::Recursive)?;
+
+    tokio::spawn(async move {
+        // Keep the watcher alive for as long as the task runs
+        let _watcher = watcher;
+
+        while let Some(first) = rx.recv().await {
+            let mut pending = HashSet::from([first]);
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            while let Ok(project_id) = rx.try_recv() {
+                pending.insert(project_id);
+            }
+
+            for project_id in pending {
+                let exists =
+                    sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM projects WHERE id = $1")
+                        .bind(&project_id)
+                        .fetch_one(&db.pool)
+                        .await
+                        .unwrap_or(0);
+                if exists == 0 {
+                    continue;
+                }
+
+                match rescan_project(&db, &storage, &project_id).await {
+                    Ok(report) if !report.is_empty() => {
+                        tracing::info!("Reconciled project {}: {:?}", project_id, report);
+                    }
+                    Ok(_) => {}
+                    Err(e) => tracing::warn!("Failed to rescan project {}: {}", project_id, e),
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+fn project_of(base: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(base).ok()?;
+    let first = relative.components().next()?;
+    first.as_os_str().to_str().map(str::to_string)
+}
@@ -0,0 +1,433 @@
+// Full-text search
+// File contents and names, comments and project names in a full-text index
+// (FTS5 on SQLite, tsvector on Postgres), so searching a large project does
+// not mean reading every file. Files are indexed when saved and comments when
+// written; anything changed another way (git pulls, WebDAV, restores, renames)
+// is caught up by comparing hashes before each search.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    db::DbPool,
+    error::{AppError, Result},
+    services::{filetype, storage::StorageService},
+};
+
+pub const FILE: &str = "file";
+pub const COMMENT: &str = "comment";
+pub const PROJECT: &str = "project";
+
+// Text past this is left out of the index
+const MAX_BODY_BYTES: usize = 512 * 1024;
+
+// Words of a query that are searched for
+const MAX_TERMS: usize = 12;
+
+// Marks around matched words in snippets, before they become highlights
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SearchHit {
+    pub project_id: String,
+    pub project_name: String,
+    /// "file", "comment" or "project"
+    pub kind: String,
+    /// Id of the file, comment or project
+    pub id: String,
+    /// The file path, the commented file or the project name
+    pub title: String,
+    /// Text around the best match
+    pub snippet: String,
+    pub highlights: Vec<Highlight>,
+    /// Higher is a better match
+    pub score: f64,
+}
+
+/// A matched word in a snippet. Offsets are in characters, end exclusive.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Highlight {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Index a file's content as just saved. Binary files are indexed by name.
+pub async fn index_file(
+    pool: &DbPool,
+    project_id: &str,
+    file_id: &str,
+    path: &str,
+    hash: &str,
+    content: &[u8],
+) -> Result<()> {
+    let body = if filetype::detect(path, content).language.is_some() {
+        std::str::from_utf8(content).map(truncate).unwrap_or("")
+    } else {
+        ""
+    };
+    upsert(pool, project_id, FILE, file_id, path, Some(hash), body).await
+}
+
+/// Index a comment as just written; `version` is its `updated_at`, or its
+/// `created_at` if it was never edited.
+pub async fn index_comment(
+    pool: &DbPool,
+    project_id: &str,
+    comment_id: &str,
+    file_path: &str,
+    version: &str,
+    content: &str,
+) -> Result<()> {
+    upsert(
+        pool,
+        project_id,
+        COMMENT,
+        comment_id,
+        file_path,
+        Some(version),
+        truncate(content),
+    )
+    .await
+}
+
+/// Bring a project's index up to date: add what is missing, reindex what
+/// changed, and drop what was deleted. Returns the number of entries
+/// written.
+pub async fn refresh(pool: &DbPool, storage: &StorageService, project_id: &str) -> Result<usize> {
+    let mut written = 0;
+
+    let files = sqlx::query_as::<_, (String, String, Option<String>)>(
+        r#"
+        SELECT f.id, f.path, f.hash FROM files f
+        LEFT JOIN search_entries e ON e.kind = 'file' AND e.ref_id = f.id
+        WHERE f.project_id = $1 AND f.is_folder = FALSE
+          AND (e.id IS NULL OR e.title <> f.path OR COALESCE(e.version, '') <> COALESCE(f.hash, ''))
+        "#,
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+    for (id, path, hash) in files {
+        let content = match storage.read_bytes(project_id, &path).await {
+            Ok(content) => content,
+            // Listed but not stored; index the name so the file can be found
+            Err(AppError::NotFound(_)) => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let hash = hash.unwrap_or_default();
+        index_file(pool, project_id, &id, &path, &hash, &content).await?;
+        written += 1;
+    }
+
+    let comments = sqlx::query_as::<_, (String, String, String, String)>(
+        r#"
+        SELECT c.id, c.file_path, c.content, COALESCE(c.updated_at, c.created_at) FROM comments c
+        LEFT JOIN search_entries e ON e.kind = 'comment' AND e.ref_id = c.id
+        WHERE c.project_id = $1
+          AND (e.id IS NULL OR e.title <> c.file_path
+               OR COALESCE(e.version, '') <> COALESCE(c.updated_at, c.created_at))
+        "#,
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+    for (id, file_path, content, version) in comments {
+        index_comment(pool, project_id, &id, &file_path, &version, &content).await?;
+        written += 1;
+    }
+
+    let renamed = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT p.name FROM projects p
+        LEFT JOIN search_entries e ON e.kind = 'project' AND e.ref_id = p.id
+        WHERE p.id = $1 AND (e.id IS NULL OR e.title <> p.name)
+        "#,
+    )
+    .bind(project_id)
+    .fetch_optional(pool)
+    .await?;
+    if let Some(name) = renamed {
+        upsert(pool, project_id, PROJECT, project_id, &name, None, "").await?;
+        written += 1;
+    }
+
+    sqlx::query(
+        r#"
+        DELETE FROM search_entries
+        WHERE project_id = $1 AND (
+            (kind = 'file' AND ref_id NOT IN (
+                SELECT id FROM files WHERE project_id = $2 AND is_folder = FALSE))
+            OR (kind = 'comment' AND ref_id NOT IN (
+                SELECT id FROM comments WHERE project_id = $3))
+        )
+        "#,
+    )
+    .bind(project_id)
+    .bind(project_id)
+    .bind(project_id)
+    .execute(pool)
+    .await?;
+
+    Ok(written)
+}
+
+/// Best matches for `query` in the projects `user_id` can access, or only in
+/// `project_id`. Every word has to match; the last one may be the start of a
+/// word, for searching as the user types.
+pub async fn search(
+    pool: &DbPool,
+    user_id: &str,
+    project_id: Option<&str>,
+    query: &str,
+    limit: i64,
+) -> Result<Vec<SearchHit>> {
+    let terms: Vec<String> = query
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .take(MAX_TERMS)
+        .map(str::to_lowercase)
+        .collect();
+    if terms.is_empty() {
+        return Err(AppError::Validation(
+            "Search for at least one word".to_string(),
+        ));
+    }
+
+    let rows = find(pool, user_id, project_id, &terms, limit).await?;
+    Ok(rows
+        .into_iter()
+        .map(
+            |(project_id, project_name, kind, id, title, snippet, score)| {
+                let (snippet, highlights) = highlights(&snippet);
+                SearchHit {
+                    project_id,
+                    project_name,
+                    kind,
+                    id,
+                    title,
+                    snippet,
+                    highlights,
+                    score,
+                }
+            },
+        )
+        .collect())
+}
+
+type HitRow = (String, String, String, String, String, String, f64);
+
+// Projects the user owns or collaborates on
+const ACCESSIBLE: &str = "SELECT p.id FROM projects p \
+     LEFT JOIN project_collaborators pc ON p.id = pc.project_id \
+     WHERE p.owner_id = $2 OR pc.user_id = $3";
+
+#[cfg(not(feature = "postgres"))]
+async fn upsert(
+    pool: &DbPool,
+    project_id: &str,
+    kind: &str,
+    ref_id: &str,
+    title: &str,
+    version: Option<&str>,
+    body: &str,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let id = upsert_entry(&mut tx, project_id, kind, ref_id, title, version).await?;
+    // FTS5 tables have no upsert
+    sqlx::query("DELETE FROM search_index WHERE rowid = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("INSERT INTO search_index (rowid, title, body) VALUES ($1, $2, $3)")
+        .bind(id)
+        .bind(title)
+        .bind(body)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(feature = "postgres")]
+async fn upsert(
+    pool: &DbPool,
+    project_id: &str,
+    kind: &str,
+    ref_id: &str,
+    title: &str,
+    version: Option<&str>,
+    body: &str,
+) -> Result<()> {
+    let mut tx = pool.begin().await?;
+    let id = upsert_entry(&mut tx, project_id, kind, ref_id, title, version).await?;
+    sqlx::query(
+        "INSERT INTO search_index (entry_id, title, body) VALUES ($1, $2, $3) \
+         ON CONFLICT (entry_id) DO UPDATE SET title = excluded.title, body = excluded.body",
+    )
+    .bind(id)
+    .bind(title)
+    .bind(body)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn upsert_entry(
+    tx: &mut sqlx::Transaction<'_, crate::db::Db>,
+    project_id: &str,
+    kind: &str,
+    ref_id: &str,
+    title: &str,
+    version: Option<&str>,
+) -> Result<i64> {
+    let id = sqlx::query_scalar::<_, i64>(
+        "INSERT INTO search_entries (project_id, kind, ref_id, title, version) \
+         VALUES ($1, $2, $3, $4, $5) \
+         ON CONFLICT (kind, ref_id) DO UPDATE SET title = excluded.title, version = excluded.version \
+         RETURNING id",
+    )
+    .bind(project_id)
+    .bind(kind)
+    .bind(ref_id)
+    .bind(title)
+    .bind(version)
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok(id)
+}
+
+#[cfg(not(feature = "postgres"))]
+async fn find(
+    pool: &DbPool,
+    user_id: &str,
+    project_id: Option<&str>,
+    terms: &[String],
+    limit: i64,
+) -> Result<Vec<HitRow>> {
+    // Quoted, so no term is read as FTS5 syntax
+    let last = terms.len() - 1;
+    let query = terms
+        .iter()
+        .enumerate()
+        .map(|(i, term)| {
+            if i == last {
+                format!("\"{term}\"*")
+            } else {
+                format!("\"{term}\"")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let rows = sqlx::query_as::<_, HitRow>(&format!(
+        r#"
+        SELECT e.project_id, p.name, e.kind, e.ref_id, e.title,
+               snippet(search_index, -1, char(2), char(3), '…', 24),
+               -bm25(search_index, 4.0, 1.0) AS score
+        FROM search_index
+        JOIN search_entries e ON e.id = search_index.rowid
+        JOIN projects p ON p.id = e.project_id
+        WHERE search_index MATCH $1
+          AND e.project_id IN ({ACCESSIBLE})
+          AND ($4 IS NULL OR e.project_id = $5)
+        ORDER BY score DESC
+        LIMIT $6
+        "#
+    ))
+    .bind(query)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(project_id)
+    .bind(project_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+#[cfg(feature = "postgres")]
+async fn find(
+    pool: &DbPool,
+    user_id: &str,
+    project_id: Option<&str>,
+    terms: &[String],
+    limit: i64,
+) -> Result<Vec<HitRow>> {
+    // Terms are letters and digits only, so none is read as tsquery syntax
+    let query = format!("{}:*", terms.join(" & "));
+
+    // Snippets are made for the returned rows only; ts_headline re-parses
+    // the whole text
+    let rows = sqlx::query_as::<_, HitRow>(&format!(
+        r#"
+        SELECT hit.project_id, hit.name, hit.kind, hit.ref_id, hit.title,
+               CASE WHEN to_tsvector('simple', hit.body) @@ hit.q
+                    THEN ts_headline('simple', hit.body, hit.q, hit.options)
+                    ELSE ts_headline('simple', hit.title, hit.q, hit.options) END,
+               hit.score
+        FROM (
+            SELECT e.project_id, p.name, e.kind, e.ref_id, e.title, s.body, q,
+                   'StartSel=' || chr(2) || ', StopSel=' || chr(3)
+                       || ', MaxWords=24, MinWords=12, MaxFragments=1' AS options,
+                   ts_rank(s.document, q)::float8 AS score
+            FROM search_index s
+            JOIN search_entries e ON e.id = s.entry_id
+            JOIN projects p ON p.id = e.project_id,
+            to_tsquery('simple', $1) q
+            WHERE s.document @@ q
+              AND e.project_id IN ({ACCESSIBLE})
+              AND ($4::text IS NULL OR e.project_id = $5)
+            ORDER BY score DESC
+            LIMIT $6
+        ) hit
+        ORDER BY hit.score DESC
+        "#
+    ))
+    .bind(query)
+    .bind(user_id)
+    .bind(user_id)
+    .bind(project_id)
+    .bind(project_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows)
+}
+
+/// Cut text to what is indexed, on a character boundary.
+fn truncate(text: &str) -> &str {
+    if text.len() <= MAX_BODY_BYTES {
+        return text;
+    }
+    let mut end = MAX_BODY_BYTES;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    &text[..end]
+}
+
+/// Strip the match marks from a snippet, returning where they were.
+fn highlights(marked: &str) -> (String, Vec<Highlight>) {
+    let mut snippet = String::with_capacity(marked.len());
+    let mut highlights = Vec::new();
+    let mut chars = 0;
+    let mut start = None;
+    for c in marked.chars() {
+        match c {
+            MATCH_START => start = Some(chars),
+            MATCH_END => {
+                if let Some(start) = start.take() {
+                    highlights.push(Highlight { start, end: chars });
+                }
+            }
+            c => {
+                // Newlines would break the snippet across lines in a list
+                snippet.push(if c == '\n' { ' ' } else { c });
+                chars += 1;
+            }
+        }
+    }
+    (snippet, highlights)
+}
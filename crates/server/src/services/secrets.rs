@@ -0,0 +1,68 @@
+// Secrets
+// Seals credentials the server keeps for other services, such as git host
+// tokens and WebDAV passwords, with AES-GCM under the storage master key, or
+// a key derived from JWT_SECRET when storage is not encrypted. Sealed values
+// are base64 of the nonce followed by the ciphertext.
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::Config,
+    error::{AppError, Result},
+    services::storage::parse_master_key,
+};
+
+const NONCE_LEN: usize = 12;
+
+#[derive(Clone)]
+pub struct Sealer {
+    cipher: Aes256Gcm,
+}
+
+impl Sealer {
+    /// `purpose` separates the keys derived from JWT_SECRET, so a secret
+    /// sealed for one feature cannot be opened by another.
+    pub fn from_config(config: &Config, purpose: &str) -> anyhow::Result<Self> {
+        let key = match &config.encryption_key {
+            Some(key) => parse_master_key(key)?,
+            None => Sha256::new()
+                .chain_update(format!("openleaf {purpose}\0").as_bytes())
+                .chain_update(config.jwt_secret.as_bytes())
+                .finalize()
+                .into(),
+        };
+        Ok(Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key)),
+        })
+    }
+
+    pub fn seal(&self, secret: &str) -> Result<String> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, secret.as_bytes())
+            .map_err(|_| AppError::Internal("Failed to encrypt secret".to_string()))?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(STANDARD.encode(sealed))
+    }
+
+    pub fn open(&self, sealed: &str) -> Result<String> {
+        let failed = || AppError::Internal("Failed to decrypt secret".to_string());
+        let sealed = STANDARD.decode(sealed).map_err(|_| failed())?;
+        if sealed.len() < NONCE_LEN {
+            return Err(failed());
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let secret = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| failed())?;
+        String::from_utf8(secret).map_err(|_| failed())
+    }
+}
@@ -0,0 +1,141 @@
+// Sign-in sessions
+// Each session token names a row here, recording where it was issued, so
+// users can see which devices are signed in and sign any of them out. A
+// token whose row is gone is refused even though its signature still holds.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{db::DbPool, error::Result};
+
+// How long a session token is valid
+const LIFETIME_DAYS: i64 = 7;
+
+// Longest stored user agent; browsers send a few hundred bytes at most
+const MAX_USER_AGENT: usize = 512;
+
+// How stale last_used_at may get before a request records it again, so
+// most requests do not write
+const TOUCH_INTERVAL_SECS: i64 = 60;
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct Session {
+    pub id: String,
+    /// Address the session was last used from
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: String,
+    pub last_used_at: String,
+    pub expires_at: String,
+    /// The session making this request
+    #[sqlx(skip)]
+    pub current: bool,
+}
+
+/// Record a sign-in and return the session id for its token. Expired
+/// sessions of the user are dropped on the way.
+pub async fn create(
+    pool: &DbPool,
+    user_id: &str,
+    ip: Option<String>,
+    user_agent: Option<&str>,
+) -> Result<(String, DateTime<Utc>)> {
+    let now = Utc::now();
+    let expires_at = now + Duration::days(LIFETIME_DAYS);
+    sqlx::query("DELETE FROM sessions WHERE user_id = $1 AND expires_at <= $2")
+        .bind(user_id)
+        .bind(now.to_rfc3339())
+        .execute(pool)
+        .await?;
+
+    let id = Uuid::new_v4().to_string();
+    let user_agent = user_agent.map(|agent| {
+        let mut end = agent.len().min(MAX_USER_AGENT);
+        while !agent.is_char_boundary(end) {
+            end -= 1;
+        }
+        agent[..end].to_string()
+    });
+    sqlx::query(
+        "INSERT INTO sessions (id, user_id, ip, user_agent, created_at, last_used_at, expires_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(&id)
+    .bind(user_id)
+    .bind(ip)
+    .bind(user_agent)
+    .bind(now.to_rfc3339())
+    .bind(now.to_rfc3339())
+    .bind(expires_at.to_rfc3339())
+    .execute(pool)
+    .await?;
+    Ok((id, expires_at))
+}
+
+/// Whether the session is still signed in, noting that it was just used.
+pub async fn check(pool: &DbPool, id: &str, user_id: &str, ip: Option<String>) -> Result<bool> {
+    let now = Utc::now();
+    let last_used = sqlx::query_scalar::<_, String>(
+        "SELECT last_used_at FROM sessions WHERE id = $1 AND user_id = $2 AND expires_at > $3",
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(now.to_rfc3339())
+    .fetch_optional(pool)
+    .await?;
+    let Some(last_used) = last_used else {
+        return Ok(false);
+    };
+
+    let stale = DateTime::parse_from_rfc3339(&last_used).map_or(true, |at| {
+        now - at.with_timezone(&Utc) > Duration::seconds(TOUCH_INTERVAL_SECS)
+    });
+    if stale {
+        sqlx::query("UPDATE sessions SET last_used_at = $1, ip = COALESCE($2, ip) WHERE id = $3")
+            .bind(now.to_rfc3339())
+            .bind(ip)
+            .bind(id)
+            .execute(pool)
+            .await?;
+    }
+    Ok(true)
+}
+
+/// The user's signed-in sessions, most recently used first.
+pub async fn list(pool: &DbPool, user_id: &str, current: Option<&str>) -> Result<Vec<Session>> {
+    let mut sessions = sqlx::query_as::<_, Session>(
+        "SELECT id, ip, user_agent, created_at, last_used_at, expires_at FROM sessions \
+         WHERE user_id = $1 AND expires_at > $2 ORDER BY last_used_at DESC",
+    )
+    .bind(user_id)
+    .bind(Utc::now().to_rfc3339())
+    .fetch_all(pool)
+    .await?;
+    for session in &mut sessions {
+        session.current = current == Some(session.id.as_str());
+    }
+    Ok(sessions)
+}
+
+/// Sign one of the user's sessions out; false if they have no such session.
+pub async fn revoke(pool: &DbPool, user_id: &str, id: &str) -> Result<bool> {
+    let result = sqlx::query("DELETE FROM sessions WHERE id = $1 AND user_id = $2")
+        .bind(id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Sign out every session of the user but `keep`. Returns how many ended.
+pub async fn revoke_others(pool: &DbPool, user_id: &str, keep: Option<&str>) -> Result<u64> {
+    let result = sqlx::query("DELETE FROM sessions WHERE user_id = $1 AND id <> $2")
+        .bind(user_id)
+        .bind(keep.unwrap_or(""))
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,451 @@
+// Spell checking
+// Pulls the prose out of LaTeX source (skipping commands, math, comments and
+// arguments that aren't text) and checks it with hunspell
+
+use std::collections::{HashMap, HashSet};
+use std::process::Stdio;
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use utoipa::ToSchema;
+
+use crate::db::DbPool;
+use crate::error::{AppError, Result};
+
+const HUNSPELL_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Commands whose mandatory arguments are labels, keys, paths or code rather
+// than prose, with how many of them to skip
+const NON_TEXT_ARGS: &[(&str, usize)] = &[
+    ("addbibresource", 1),
+    ("autocite", 1),
+    ("autoref", 1),
+    ("begin", 1),
+    ("bibliography", 1),
+    ("bibliographystyle", 1),
+    ("cite", 1),
+    ("citeauthor", 1),
+    ("citep", 1),
+    ("citet", 1),
+    ("citeyear", 1),
+    ("color", 1),
+    ("cref", 1),
+    ("Cref", 1),
+    ("DeclareMathOperator", 2),
+    ("def", 1),
+    ("documentclass", 1),
+    ("end", 1),
+    ("eqref", 1),
+    ("graphicspath", 1),
+    ("hspace", 1),
+    ("href", 1),
+    ("hyperref", 0),
+    ("include", 1),
+    ("includegraphics", 1),
+    ("input", 1),
+    ("label", 1),
+    ("lstinputlisting", 1),
+    ("newcommand", 2),
+    ("newenvironment", 3),
+    ("nocite", 1),
+    ("pageref", 1),
+    ("pagestyle", 1),
+    ("parencite", 1),
+    ("providecommand", 2),
+    ("ref", 1),
+    ("renewcommand", 2),
+    ("RequirePackage", 1),
+    ("setcounter", 2),
+    ("setlength", 2),
+    ("textcite", 1),
+    ("textcolor", 1),
+    ("thispagestyle", 1),
+    ("url", 1),
+    ("usepackage", 1),
+    ("vspace", 1),
+];
+
+// Environments whose body is math or code
+const SKIPPED_ENVIRONMENTS: &[&str] = &[
+    "align",
+    "align*",
+    "alignat",
+    "alignat*",
+    "displaymath",
+    "equation",
+    "equation*",
+    "eqnarray",
+    "eqnarray*",
+    "gather",
+    "gather*",
+    "lstlisting",
+    "math",
+    "minted",
+    "multline",
+    "multline*",
+    "tikzpicture",
+    "verbatim",
+    "verbatim*",
+];
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Misspelling {
+    pub word: String,
+    /// Position in the text, in characters
+    pub offset: usize,
+    pub length: usize,
+    /// 1-based
+    pub line: usize,
+    pub column: usize,
+    pub suggestions: Vec<String>,
+}
+
+/// A word of prose and where it starts, in characters.
+#[derive(Debug)]
+struct Word {
+    text: String,
+    offset: usize,
+}
+
+/// Misspelled words in `text`, ignoring words in `dictionary`.
+pub async fn check(
+    text: &str,
+    language: &str,
+    dictionary: &HashSet<String>,
+) -> Result<Vec<Misspelling>> {
+    let words: Vec<Word> = words(text)
+        .into_iter()
+        .filter(|word| {
+            !dictionary.contains(&word.text) && !dictionary.contains(&word.text.to_lowercase())
+        })
+        .collect();
+
+    let mut unique: Vec<&str> = words.iter().map(|word| word.text.as_str()).collect();
+    unique.sort_unstable();
+    unique.dedup();
+    if unique.is_empty() {
+        return Ok(Vec::new());
+    }
+    let verdicts = hunspell(&unique, language).await?;
+
+    let mut misspellings = Vec::new();
+    let mut position = Position::new(text);
+    for word in words {
+        let Some(suggestions) = verdicts.get(&word.text) else {
+            continue;
+        };
+        let (line, column) = position.at(word.offset);
+        misspellings.push(Misspelling {
+            length: word.text.chars().count(),
+            word: word.text,
+            offset: word.offset,
+            line,
+            column,
+            suggestions: suggestions.clone(),
+        });
+    }
+    Ok(misspellings)
+}
+
+// Maps character offsets to lines and columns, for offsets in increasing order
+struct Position<'a> {
+    chars: std::str::Chars<'a>,
+    offset: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Position<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            chars: text.chars(),
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    fn at(&mut self, offset: usize) -> (usize, usize) {
+        while self.offset < offset {
+            match self.chars.next() {
+                Some('\n') => {
+                    self.line += 1;
+                    self.column = 1;
+                }
+                Some(_) => self.column += 1,
+                None => break,
+            }
+            self.offset += 1;
+        }
+        (self.line, self.column)
+    }
+}
+
+// Runs hunspell in pipe mode with one word per line. Returns the misspelled
+// words with their suggestions.
+async fn hunspell(words: &[&str], language: &str) -> Result<HashMap<String, Vec<String>>> {
+    let mut child = tokio::process::Command::new("hunspell")
+        .args(["-a", "-i", "utf-8", "-d", language])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => {
+                AppError::Internal("hunspell is not installed".to_string())
+            }
+            _ => AppError::Internal(format!("Failed to run hunspell: {e}")),
+        })?;
+
+    // "^" makes hunspell take the rest of the line as text, whatever it starts with
+    let input: String = words.iter().map(|word| format!("^{word}\n")).collect();
+    let mut stdin = child
+        .stdin
+        .take()
+        .ok_or_else(|| AppError::Internal("hunspell has no input".to_string()))?;
+    // Written concurrently so a full output pipe can't stall the input
+    let writer = tokio::spawn(async move {
+        let _ = stdin.write_all(input.as_bytes()).await;
+    });
+
+    let output = match tokio::time::timeout(HUNSPELL_TIMEOUT, child.wait_with_output()).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) => return Err(AppError::Internal(format!("Failed to run hunspell: {e}"))),
+        Err(_) => return Err(AppError::Internal("hunspell timed out".to_string())),
+    };
+    let _ = writer.await;
+    if !output.status.success() {
+        // Usually a missing dictionary
+        return Err(AppError::BadRequest(format!(
+            "hunspell failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    Ok(parse(&String::from_utf8_lossy(&output.stdout), words))
+}
+
+// After a version banner, hunspell answers each input line with one result
+// per token it found, then a blank line. "&" and "#" mark misspellings:
+// "& word count offset: sugg, sugg" or "# word offset".
+fn parse(output: &str, words: &[&str]) -> HashMap<String, Vec<String>> {
+    let mut misspelled = HashMap::new();
+    let groups = output
+        .lines()
+        .skip_while(|line| line.starts_with("@(#)"))
+        .collect::<Vec<_>>();
+    let groups = groups.split(|line| line.is_empty());
+
+    for (word, results) in words.iter().zip(groups) {
+        let mut wrong = false;
+        let mut suggestions = Vec::new();
+        for result in results {
+            if let Some(rest) = result.strip_prefix("& ") {
+                wrong = true;
+                if let Some((_, list)) = rest.split_once(": ") {
+                    suggestions.extend(list.split(", ").map(str::to_string));
+                }
+            } else if result.starts_with("# ") {
+                wrong = true;
+            }
+        }
+        if wrong {
+            misspelled.insert(word.to_string(), suggestions);
+        }
+    }
+    misspelled
+}
+
+// Collects the words of prose in LaTeX source
+fn words(text: &str) -> Vec<Word> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        match chars[i] {
+            '%' => i = skip_line(&chars, i),
+            '\\' => i = skip_command(&chars, i),
+            '$' if chars.get(i + 1) == Some(&'$') => i = find(&chars, i + 2, "$$"),
+            '$' => i = find(&chars, i + 1, "$"),
+            c if c.is_alphabetic() => {
+                let start = i;
+                while i < chars.len()
+                    && (chars[i].is_alphabetic()
+                        // Apostrophes inside words ("don't")
+                        || (chars[i] == '\''
+                            && chars.get(i + 1).is_some_and(|c| c.is_alphabetic())))
+                {
+                    i += 1;
+                }
+                // Words run together with digits are identifiers, not prose
+                if chars
+                    .get(i)
+                    .is_some_and(|c| c.is_ascii_digit() || *c == '_')
+                {
+                    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                        i += 1;
+                    }
+                    continue;
+                }
+                words.push(Word {
+                    text: chars[start..i].iter().collect(),
+                    offset: start,
+                });
+            }
+            _ => i += 1,
+        }
+    }
+
+    words
+}
+
+fn skip_line(chars: &[char], from: usize) -> usize {
+    chars[from..]
+        .iter()
+        .position(|c| *c == '\n')
+        .map_or(chars.len(), |end| from + end + 1)
+}
+
+// Index just past the next unescaped `pattern` at or after `from`
+fn find(chars: &[char], from: usize, pattern: &str) -> usize {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let mut i = from;
+    while i + pattern.len() <= chars.len() {
+        if chars[i..i + pattern.len()] == pattern[..] {
+            return i + pattern.len();
+        }
+        // Skip escapes such as \$
+        i += if chars[i] == '\\' { 2 } else { 1 };
+    }
+    chars.len()
+}
+
+// Skips a command at `from` (a backslash) and any arguments that aren't prose
+fn skip_command(chars: &[char], from: usize) -> usize {
+    let mut i = from + 1;
+    let Some(&first) = chars.get(i) else {
+        return i;
+    };
+    if !first.is_ascii_alphabetic() {
+        return match first {
+            '(' => find(chars, i + 1, "\\)"),
+            '[' => find(chars, i + 1, "\\]"),
+            // An escaped character such as \% or \\
+            _ => i + 1,
+        };
+    }
+
+    let start = i;
+    while i < chars.len() && chars[i].is_ascii_alphabetic() {
+        i += 1;
+    }
+    let name: String = chars[start..i].iter().collect();
+    if name == "verb" {
+        return match chars.get(i) {
+            Some(&delimiter) => chars[i + 1..]
+                .iter()
+                .position(|c| *c == delimiter)
+                .map_or(chars.len(), |end| i + 1 + end + 1),
+            None => i,
+        };
+    }
+    let Some(&(_, count)) = NON_TEXT_ARGS.iter().find(|(command, _)| *command == name) else {
+        return i;
+    };
+
+    if name == "begin" {
+        let (environment, after) = argument(chars, i);
+        if SKIPPED_ENVIRONMENTS.contains(&environment.as_str()) {
+            return find(chars, after, &format!("\\end{{{environment}}}"));
+        }
+        return after;
+    }
+
+    for _ in 0..count {
+        i = skip_options(chars, i);
+        i = argument(chars, i).1;
+    }
+    skip_options(chars, i)
+}
+
+fn skip_whitespace(chars: &[char], mut i: usize) -> usize {
+    while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+        i += 1;
+    }
+    i
+}
+
+fn skip_options(chars: &[char], from: usize) -> usize {
+    let mut i = skip_whitespace(chars, from);
+    while chars.get(i) == Some(&'[') {
+        i = chars[i..]
+            .iter()
+            .position(|c| *c == ']')
+            .map_or(chars.len(), |end| i + end + 1);
+        i = skip_whitespace(chars, i);
+    }
+    if chars.get(i) == Some(&'*') {
+        i += 1;
+    }
+    i
+}
+
+// A braced argument (or a single token) at `from`, and the index after it
+fn argument(chars: &[char], from: usize) -> (String, usize) {
+    let i = skip_whitespace(chars, from);
+    match chars.get(i) {
+        Some('{') => {
+            let mut depth = 0;
+            for (offset, c) in chars[i..].iter().enumerate() {
+                match c {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            let end = i + offset;
+                            return (chars[i + 1..end].iter().collect(), end + 1);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            (chars[i + 1..].iter().collect(), chars.len())
+        }
+        // \newcommand\foo or \def\foo
+        Some('\\') => {
+            let mut end = i + 1;
+            while chars.get(end).is_some_and(|c| c.is_ascii_alphabetic()) {
+                end += 1;
+            }
+            (
+                chars[i..end].iter().collect(),
+                end.max(i + 2).min(chars.len()),
+            )
+        }
+        Some(c) => (c.to_string(), i + 1),
+        None => (String::new(), i),
+    }
+}
+
+/// Words the project added to its dictionary.
+pub async fn dictionary(pool: &DbPool, project_id: &str) -> Result<Vec<String>> {
+    let words = sqlx::query_scalar::<_, String>(
+        "SELECT word FROM project_dictionary WHERE project_id = $1 ORDER BY word",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(words)
+}
+
+/// Whether a word is acceptable for a project dictionary: one word, no spaces.
+pub fn is_valid_word(word: &str) -> bool {
+    !word.is_empty()
+        && word.chars().count() <= 100
+        && word
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '\'' | '-'))
+}
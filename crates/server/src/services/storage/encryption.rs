@@ -0,0 +1,158 @@
+// Transparent encryption at rest for any storage backend
+// Each project gets a random data key, stored wrapped by the server master key
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Key, Nonce,
+};
+use async_trait::async_trait;
+use tokio::sync::{Mutex, RwLock};
+
+use super::{DiskEntry, StorageBackend};
+use crate::error::{AppError, Result};
+
+// Header identifying encrypted objects; anything without it is read as plaintext
+// so existing unencrypted projects keep working after encryption is enabled
+const MAGIC: &[u8; 4] = b"OLE1";
+const NONCE_LEN: usize = 12;
+
+// Wrapped project data key, stored alongside the project's files
+const DATA_KEY_NAME: &str = ".openleaf-data-key";
+
+pub struct EncryptedBackend {
+    inner: Arc<dyn StorageBackend>,
+    master: Aes256Gcm,
+    data_keys: RwLock<HashMap<String, Aes256Gcm>>,
+    // Serializes data key creation so concurrent first writes agree on one key
+    create_lock: Mutex<()>,
+}
+
+impl EncryptedBackend {
+    pub fn new(inner: Arc<dyn StorageBackend>, master_key: &[u8; 32]) -> Self {
+        Self {
+            inner,
+            master: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(master_key)),
+            data_keys: RwLock::new(HashMap::new()),
+            create_lock: Mutex::new(()),
+        }
+    }
+
+    async fn data_key(&self, key: &str, create: bool) -> Result<Option<Aes256Gcm>> {
+        let project_id = key.split('/').next().unwrap_or(key).to_string();
+
+        if let Some(cipher) = self.data_keys.read().await.get(&project_id) {
+            return Ok(Some(cipher.clone()));
+        }
+
+        let _guard = self.create_lock.lock().await;
+        let key_path = format!("{project_id}/{DATA_KEY_NAME}");
+
+        let raw = match self.inner.read(&key_path).await? {
+            Some(wrapped) => decrypt(&self.master, &wrapped)?,
+            None if create => {
+                let raw = Aes256Gcm::generate_key(OsRng).to_vec();
+                self.inner
+                    .write(&key_path, &encrypt(&self.master, &raw)?)
+                    .await?;
+                raw
+            }
+            None => return Ok(None),
+        };
+
+        let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&raw));
+        self.data_keys
+            .write()
+            .await
+            .insert(project_id, cipher.clone());
+        Ok(Some(cipher))
+    }
+}
+
+fn encrypt(cipher: &Aes256Gcm, plaintext: &[u8]) -> Result<Vec<u8>> {
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| AppError::Internal("Failed to encrypt file".to_string()))?;
+
+    let mut out = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&nonce);
+    out.extend_from_slice(&ciphertext);
+    Ok(out)
+}
+
+fn decrypt(cipher: &Aes256Gcm, data: &[u8]) -> Result<Vec<u8>> {
+    let body = data
+        .strip_prefix(MAGIC.as_slice())
+        .filter(|body| body.len() >= NONCE_LEN)
+        .ok_or_else(|| AppError::Internal("Malformed encrypted object".to_string()))?;
+    let (nonce, ciphertext) = body.split_at(NONCE_LEN);
+
+    cipher
+        .decrypt(Nonce::from_slice(nonce), ciphertext)
+        .map_err(|_| AppError::Internal("Failed to decrypt file".to_string()))
+}
+
+#[async_trait]
+impl StorageBackend for EncryptedBackend {
+    async fn write(&self, key: &str, content: &[u8]) -> Result<()> {
+        let cipher = self
+            .data_key(key, true)
+            .await?
+            .expect("data key is created on demand");
+        self.inner.write(key, &encrypt(&cipher, content)?).await
+    }
+
+    async fn read(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let Some(data) = self.inner.read(key).await? else {
+            return Ok(None);
+        };
+        if !data.starts_with(MAGIC) {
+            return Ok(Some(data));
+        }
+
+        let cipher = self
+            .data_key(key, false)
+            .await?
+            .ok_or_else(|| AppError::Internal("Missing project data key".to_string()))?;
+        decrypt(&cipher, &data).map(Some)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        self.inner.delete(key).await?;
+        if !key.contains('/') {
+            // Whole project removed along with its data key
+            self.data_keys.write().await.remove(key);
+        }
+        Ok(())
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<bool> {
+        // Renames stay within a project, so the data key still applies
+        self.inner.rename(from, to).await
+    }
+
+    async fn create_dir(&self, key: &str) -> Result<()> {
+        self.inner.create_dir(key).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<DiskEntry>> {
+        let mut entries = self.inner.list(prefix).await?;
+        // Listed under the project directory when listing across projects
+        entries.retain(|entry| entry.path.rsplit('/').next() != Some(DATA_KEY_NAME));
+        Ok(entries)
+    }
+}
+
+/// Parse a base64-encoded 256-bit master key.
+pub fn parse_master_key(encoded: &str) -> anyhow::Result<[u8; 32]> {
+    use base64::Engine;
+
+    let bytes = base64::engine::general_purpose::STANDARD.decode(encoded.trim())?;
+    bytes
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Encryption key must be 32 bytes (base64-encoded)"))
+}
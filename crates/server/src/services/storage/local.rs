@@ -0,0 +1,375 @@
+// Local filesystem storage backend
+// Writes are crash-safe: temp file, fsync, rename
+// Large files can optionally be deduplicated into shared, hardlinked blobs
+
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use tokio::{fs, io::AsyncWriteExt};
+use uuid::Uuid;
+
+use super::{content_hash, DiskEntry, StorageBackend};
+use crate::error::{AppError, Result};
+
+// Content-addressed blobs shared by every project, named by SHA-256
+const BLOB_DIR: &str = ".blobs";
+
+pub struct LocalBackend {
+    base_path: PathBuf,
+    // Files at least this large are stored once and hardlinked into projects
+    dedup_min_size: Option<u64>,
+}
+
+impl LocalBackend {
+    pub fn new(base_path: PathBuf) -> Self {
+        Self {
+            base_path,
+            dedup_min_size: None,
+        }
+    }
+
+    pub fn with_dedup(mut self, min_size: u64) -> Self {
+        self.dedup_min_size = Some(min_size);
+        self
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.base_path.join(key)
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.base_path.join(BLOB_DIR).join(&hash[..2]).join(hash)
+    }
+
+    fn should_dedup(&self, content: &[u8]) -> bool {
+        self.dedup_min_size
+            .is_some_and(|min_size| content.len() as u64 >= min_size)
+    }
+
+    /// Store the content once under its hash and hardlink it to `path`.
+    async fn write_deduplicated(&self, path: &Path, content: &[u8]) -> std::io::Result<()> {
+        let blob = self.blob_path(&content_hash(content));
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("file");
+        let tmp_path = path.with_file_name(temp_file_name(file_name));
+
+        // A concurrent sweep may remove an unreferenced blob between the
+        // existence check and the link, so retry once after rewriting it
+        for _ in 0..2 {
+            if !blob.exists() {
+                if let Some(parent) = blob.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                write_atomic(&blob, content).await?;
+                // Shared inode: discourage in-place edits that would change every copy
+                let mut permissions = fs::metadata(&blob).await?.permissions();
+                permissions.set_readonly(true);
+                fs::set_permissions(&blob, permissions).await?;
+            }
+
+            match fs::hard_link(&blob, &tmp_path).await {
+                Ok(()) => {
+                    if let Err(e) = fs::rename(&tmp_path, path).await {
+                        let _ = fs::remove_file(&tmp_path).await;
+                        return Err(e);
+                    }
+                    return Ok(());
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            }
+        }
+
+        // Fall back to a private copy
+        write_atomic(path, content).await
+    }
+
+    /// The blob `path` is linked to, if it is a deduplicated file.
+    async fn linked_blob(&self, path: &Path) -> std::io::Result<Option<PathBuf>> {
+        if self.dedup_min_size.is_none() {
+            return Ok(None);
+        }
+        match fs::metadata(path).await {
+            Ok(meta) if meta.is_file() && link_count(&meta) > 1 => {
+                Ok(Some(self.blob_path(&content_hash(&fs::read(path).await?))))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Delete blobs that no project file links to.
+    async fn sweep_blobs(&self) -> Result<usize> {
+        let root = self.base_path.join(BLOB_DIR);
+
+        tokio::task::spawn_blocking(move || {
+            let mut removed = 0;
+            if !root.exists() {
+                return Ok(removed);
+            }
+            for entry in walkdir::WalkDir::new(&root).min_depth(2).max_depth(2) {
+                let entry =
+                    entry.map_err(|e| AppError::Internal(format!("Failed to walk blobs: {e}")))?;
+                let Ok(meta) = entry.metadata() else {
+                    continue;
+                };
+                if meta.is_file() && link_count(&meta) == 1 {
+                    std::fs::remove_file(entry.path())
+                        .map_err(|e| AppError::Internal(format!("Failed to remove blob: {e}")))?;
+                    removed += 1;
+                }
+            }
+            Ok(removed)
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Blob sweep failed: {e}")))?
+    }
+}
+
+#[async_trait]
+impl StorageBackend for LocalBackend {
+    async fn write(&self, key: &str, content: &[u8]) -> Result<()> {
+        let path = self.path(key);
+
+        // Create parent directories if needed
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to create directories: {e}")))?;
+        }
+
+        let result = async {
+            let previous = self.linked_blob(&path).await?;
+            if self.should_dedup(content) {
+                self.write_deduplicated(&path, content).await?;
+            } else {
+                write_atomic(&path, content).await?;
+            }
+            // The old contents may have been the last link to their blob
+            match previous {
+                Some(blob) => release_blob(&blob).await,
+                None => Ok(()),
+            }
+        }
+        .await;
+        result.map_err(|e| AppError::Internal(format!("Failed to write file: {e}")))
+    }
+
+    async fn write_from_file(&self, key: &str, source: &Path) -> Result<()> {
+        if self.dedup_min_size.is_some() {
+            // Deduplication needs the content hash, so take the buffered path
+            let content = fs::read(source)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to read upload: {e}")))?;
+            return self.write(key, &content).await;
+        }
+
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to create directories: {e}")))?;
+        }
+
+        copy_atomic(source, &path)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to write file: {e}")))
+    }
+
+    async fn read(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        let path = self.path(key);
+
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        fs::read(&path)
+            .await
+            .map(Some)
+            .map_err(|e| AppError::Internal(format!("Failed to read file: {e}")))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let path = self.path(key);
+
+        if path.exists() {
+            if path.is_dir() {
+                fs::remove_dir_all(&path)
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Failed to delete directory: {e}")))?;
+                if self.dedup_min_size.is_some() {
+                    let removed = self.sweep_blobs().await?;
+                    if removed > 0 {
+                        tracing::debug!("Removed {} unreferenced blobs", removed);
+                    }
+                }
+            } else {
+                let blob = self
+                    .linked_blob(&path)
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Failed to read file: {e}")))?;
+                fs::remove_file(&path)
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Failed to delete file: {e}")))?;
+                if let Some(blob) = blob {
+                    release_blob(&blob)
+                        .await
+                        .map_err(|e| AppError::Internal(format!("Failed to release blob: {e}")))?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<bool> {
+        let old = self.path(from);
+        let new = self.path(to);
+
+        if !old.exists() {
+            return Ok(false);
+        }
+
+        // Create parent directories for new path if needed
+        if let Some(parent) = new.parent() {
+            fs::create_dir_all(parent)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to create directories: {e}")))?;
+        }
+
+        fs::rename(&old, &new)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to rename: {e}")))?;
+
+        Ok(true)
+    }
+
+    async fn create_dir(&self, key: &str) -> Result<()> {
+        fs::create_dir_all(self.path(key))
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to create folder: {e}")))
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<DiskEntry>> {
+        let root = self.path(prefix);
+
+        tokio::task::spawn_blocking(move || {
+            let mut entries = Vec::new();
+            if !root.exists() {
+                return Ok(entries);
+            }
+            for entry in walkdir::WalkDir::new(&root).min_depth(1) {
+                let entry = entry
+                    .map_err(|e| AppError::Internal(format!("Failed to walk project: {e}")))?;
+                let Ok(relative) = entry.path().strip_prefix(&root) else {
+                    continue;
+                };
+                let Some(path) = relative.to_str() else {
+                    continue;
+                };
+                let is_folder = entry.file_type().is_dir();
+                let metadata = entry.metadata().ok();
+                entries.push(DiskEntry {
+                    path: path.replace('\\', "/"),
+                    is_folder,
+                    size: match &metadata {
+                        Some(metadata) if !is_folder => metadata.len(),
+                        _ => 0,
+                    },
+                    modified: metadata.and_then(|metadata| metadata.modified().ok()),
+                });
+            }
+            Ok(entries)
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Project walk failed: {e}")))?
+    }
+}
+
+async fn release_blob(blob: &Path) -> std::io::Result<()> {
+    match fs::metadata(blob).await {
+        Ok(meta) if link_count(&meta) == 1 => fs::remove_file(blob).await,
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(unix)]
+fn link_count(meta: &std::fs::Metadata) -> u64 {
+    use std::os::unix::fs::MetadataExt;
+    meta.nlink()
+}
+
+// Link counts are not exposed here, so blobs are never treated as unreferenced
+#[cfg(not(unix))]
+fn link_count(_meta: &std::fs::Metadata) -> u64 {
+    0
+}
+
+fn temp_file_name(file_name: &str) -> String {
+    format!(".{file_name}.{}.tmp", Uuid::new_v4())
+}
+
+/// Like [`write_atomic`], streaming the content from another file.
+async fn copy_atomic(source: &Path, path: &Path) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("file");
+    let tmp_path = path.with_file_name(temp_file_name(file_name));
+
+    let result = async {
+        fs::copy(source, &tmp_path).await?;
+        fs::File::open(&tmp_path).await?.sync_all().await?;
+        fs::rename(&tmp_path, path).await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path).await;
+        return result;
+    }
+
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = fs::File::open(parent).await {
+            let _ = dir.sync_all().await;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write `content` to a temporary sibling, fsync it, then rename it over
+/// `path` so readers never observe a partially written file.
+async fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("file");
+    let tmp_path = path.with_file_name(temp_file_name(file_name));
+
+    let result = async {
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(content).await?;
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&tmp_path, path).await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path).await;
+        return result;
+    }
+
+    // Persist the rename itself
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = fs::File::open(parent).await {
+            let _ = dir.sync_all().await;
+        }
+    }
+
+    Ok(())
+}
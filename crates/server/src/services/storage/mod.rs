@@ -0,0 +1,349 @@
+// File storage service
+// All project file access goes through here; the backend decides where the bytes live
+
+mod encryption;
+mod local;
+mod s3;
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::Config,
+    error::{AppError, Result},
+};
+
+pub use encryption::{parse_master_key, EncryptedBackend};
+pub use local::LocalBackend;
+pub use s3::{build_s3_store, S3Backend};
+
+// Content-addressed objects shared by every project, such as the contents of
+// project versions; hidden so they are never taken for a project
+const OBJECT_DIR: &str = ".objects";
+
+/// A file or folder found in storage, relative to the project root
+#[derive(Debug, Clone)]
+pub struct DiskEntry {
+    pub path: String,
+    pub is_folder: bool,
+    // Bytes as stored, so encrypted files count their overhead; 0 for folders
+    pub size: u64,
+    // Last write, where the backend keeps one
+    pub modified: Option<SystemTime>,
+}
+
+/// Where project bytes are kept. Keys are `project_id/relative/path`.
+#[async_trait]
+pub trait StorageBackend: Send + Sync {
+    /// Durably write `content`, replacing any existing object.
+    async fn write(&self, key: &str, content: &[u8]) -> Result<()>;
+
+    /// Store the contents of a local file. Backends that can avoid loading
+    /// it into memory override this.
+    async fn write_from_file(&self, key: &str, source: &Path) -> Result<()> {
+        let content = tokio::fs::read(source)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to read upload: {e}")))?;
+        self.write(key, &content).await
+    }
+
+    /// Read an object, returning `None` if it doesn't exist.
+    async fn read(&self, key: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Delete a file, or a folder and everything beneath it.
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Move a file or folder, returning `false` if the source doesn't exist.
+    async fn rename(&self, from: &str, to: &str) -> Result<bool>;
+
+    async fn create_dir(&self, key: &str) -> Result<()>;
+
+    /// List everything below `prefix`, with paths relative to it.
+    async fn list(&self, prefix: &str) -> Result<Vec<DiskEntry>>;
+}
+
+#[derive(Clone)]
+pub struct StorageService {
+    backend: Arc<dyn StorageBackend>,
+    // Local directory holding plaintext project files for tools that need a
+    // real filesystem; with the plain local backend this is the storage itself
+    work_path: PathBuf,
+    is_local: bool,
+}
+
+impl StorageService {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let backend: Arc<dyn StorageBackend> = match config.storage_backend.as_str() {
+            "local" => {
+                let local = LocalBackend::new(PathBuf::from(&config.storage_path));
+                // Encrypted objects never match, so deduplication would only add overhead
+                match config.dedup_min_size {
+                    Some(min_size) if config.encryption_key.is_none() => {
+                        Arc::new(local.with_dedup(min_size))
+                    }
+                    _ => Arc::new(local),
+                }
+            }
+            "s3" => {
+                let s3 = config
+                    .s3
+                    .as_ref()
+                    .ok_or_else(|| anyhow::anyhow!("STORAGE_BACKEND=s3 requires S3_BUCKET"))?;
+                Arc::new(S3Backend::new(s3)?)
+            }
+            other => return Err(anyhow::anyhow!("Unknown storage backend: {other}")),
+        };
+
+        let (backend, encrypted): (Arc<dyn StorageBackend>, bool) = match &config.encryption_key {
+            Some(key) => {
+                let key = encryption::parse_master_key(key)?;
+                (Arc::new(EncryptedBackend::new(backend, &key)), true)
+            }
+            None => (backend, false),
+        };
+
+        // Plain local storage can be used in place; anything else is copied
+        // into a plaintext working directory when tools need it
+        let is_local = config.storage_backend == "local" && !encrypted;
+        let work_path = if is_local {
+            PathBuf::from(&config.storage_path)
+        } else {
+            PathBuf::from(&config.work_path)
+        };
+
+        Ok(Self {
+            backend,
+            work_path,
+            is_local,
+        })
+    }
+
+    pub async fn init(&self) -> Result<()> {
+        tokio::fs::create_dir_all(&self.work_path)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to create storage directory: {e}")))?;
+        Ok(())
+    }
+
+    /// Whether plaintext project files live directly under `base_path` on this machine.
+    pub fn is_local(&self) -> bool {
+        self.is_local
+    }
+
+    pub fn base_path(&self) -> &Path {
+        &self.work_path
+    }
+
+    pub fn project_path(&self, project_id: &str) -> PathBuf {
+        self.work_path.join(project_id)
+    }
+
+    pub fn file_path(&self, project_id: &str, file_path: &str) -> PathBuf {
+        self.work_path.join(project_id).join(file_path)
+    }
+
+    /// Make the project's plaintext files available under [`Self::project_path`].
+    pub async fn materialize(&self, project_id: &str) -> Result<PathBuf> {
+        let dest = self.project_path(project_id);
+        if self.is_local {
+            return Ok(dest);
+        }
+
+        for entry in self.list_tree(project_id).await? {
+            let local = dest.join(&entry.path);
+            if entry.is_folder {
+                tokio::fs::create_dir_all(&local).await.map_err(|e| {
+                    AppError::Internal(format!("Failed to create working directory: {e}"))
+                })?;
+                continue;
+            }
+
+            let content = self.read_bytes(project_id, &entry.path).await?;
+            if let Some(parent) = local.parent() {
+                tokio::fs::create_dir_all(parent).await.map_err(|e| {
+                    AppError::Internal(format!("Failed to create working directory: {e}"))
+                })?;
+            }
+            tokio::fs::write(&local, content)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to write working copy: {e}")))?;
+        }
+
+        Ok(dest)
+    }
+
+    pub async fn create_project_dir(&self, project_id: &str) -> Result<()> {
+        self.backend.create_dir(project_id).await
+    }
+
+    pub async fn delete_project_dir(&self, project_id: &str) -> Result<()> {
+        self.backend.delete(project_id).await?;
+        if !self.is_local {
+            // Drop the local working copy as well
+            let _ = tokio::fs::remove_dir_all(self.project_path(project_id)).await;
+        }
+        Ok(())
+    }
+
+    /// Durably write a file and return the SHA-256 of its contents.
+    pub async fn write_file(
+        &self,
+        project_id: &str,
+        file_path: &str,
+        content: impl AsRef<[u8]>,
+    ) -> Result<String> {
+        let content = content.as_ref();
+        self.backend
+            .write(&key(project_id, file_path), content)
+            .await?;
+        Ok(content_hash(content))
+    }
+
+    /// Store a local file (such as a finished upload) and return its SHA-256.
+    pub async fn write_file_from_path(
+        &self,
+        project_id: &str,
+        file_path: &str,
+        source: &Path,
+    ) -> Result<String> {
+        let hash = hash_local_file(source).await?;
+        self.backend
+            .write_from_file(&key(project_id, file_path), source)
+            .await?;
+        Ok(hash)
+    }
+
+    /// Re-hash the file as it currently exists in storage.
+    pub async fn hash_file(&self, project_id: &str, file_path: &str) -> Result<String> {
+        let content = self.read_bytes(project_id, file_path).await?;
+        Ok(content_hash(&content))
+    }
+
+    pub async fn read_file(&self, project_id: &str, file_path: &str) -> Result<String> {
+        let content = self.read_bytes(project_id, file_path).await?;
+        String::from_utf8(content)
+            .map_err(|_| AppError::BadRequest(format!("File is not valid UTF-8: {file_path}")))
+    }
+
+    pub async fn read_bytes(&self, project_id: &str, file_path: &str) -> Result<Vec<u8>> {
+        self.backend
+            .read(&key(project_id, file_path))
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("File not found: {file_path}")))
+    }
+
+    pub async fn delete_file(&self, project_id: &str, file_path: &str) -> Result<()> {
+        self.backend.delete(&key(project_id, file_path)).await
+    }
+
+    /// List everything stored for the project, skipping in-flight temp files
+    /// from atomic writes.
+    pub async fn list_tree(&self, project_id: &str) -> Result<Vec<DiskEntry>> {
+        let mut entries = self.backend.list(project_id).await?;
+        entries.retain(|entry| {
+            let name = entry.path.rsplit('/').next().unwrap_or(&entry.path);
+            !is_temp_file(name)
+        });
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    /// List everything in storage across all projects, with paths of the form
+    /// `{project_id}/{path}`.
+    pub async fn list_all(&self) -> Result<Vec<DiskEntry>> {
+        let mut entries = self.backend.list("").await?;
+        entries.retain(|entry| {
+            let name = entry.path.rsplit('/').next().unwrap_or(&entry.path);
+            // Hidden top-level entries such as the dedup blob store are not projects
+            !is_temp_file(name) && !entry.path.starts_with('.')
+        });
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    pub async fn create_folder(&self, project_id: &str, folder_path: &str) -> Result<()> {
+        self.backend.create_dir(&key(project_id, folder_path)).await
+    }
+
+    pub async fn rename(&self, project_id: &str, old_path: &str, new_path: &str) -> Result<()> {
+        let renamed = self
+            .backend
+            .rename(&key(project_id, old_path), &key(project_id, new_path))
+            .await?;
+
+        if !renamed {
+            return Err(AppError::NotFound(format!("Path not found: {old_path}")));
+        }
+        Ok(())
+    }
+
+    /// Store content by its SHA-256, returning the hash. Storing the same
+    /// content again is harmless.
+    pub async fn write_object(&self, content: &[u8]) -> Result<String> {
+        let hash = content_hash(content);
+        self.backend
+            .write(&format!("{OBJECT_DIR}/{hash}"), content)
+            .await?;
+        Ok(hash)
+    }
+
+    pub async fn read_object(&self, hash: &str) -> Result<Vec<u8>> {
+        if !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(AppError::BadRequest("Invalid object hash".to_string()));
+        }
+        self.backend
+            .read(&format!("{OBJECT_DIR}/{hash}"))
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Object not found: {hash}")))
+    }
+
+    pub async fn delete_object(&self, hash: &str) -> Result<()> {
+        self.backend.delete(&format!("{OBJECT_DIR}/{hash}")).await
+    }
+
+    /// Hashes of every stored object.
+    pub async fn list_objects(&self) -> Result<Vec<DiskEntry>> {
+        let mut entries = self.backend.list(OBJECT_DIR).await?;
+        entries.retain(|entry| !entry.is_folder && !is_temp_file(&entry.path));
+        Ok(entries)
+    }
+}
+
+fn key(project_id: &str, file_path: &str) -> String {
+    format!("{project_id}/{}", file_path.trim_start_matches('/'))
+}
+
+pub fn is_temp_file(file_name: &str) -> bool {
+    file_name.starts_with('.') && file_name.ends_with(".tmp")
+}
+
+/// SHA-256 of a local file, read in chunks.
+pub async fn hash_local_file(path: &Path) -> Result<String> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || {
+        use std::io::Read;
+
+        let mut file = std::fs::File::open(&path)?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+        }
+        Ok::<_, std::io::Error>(format!("{:x}", hasher.finalize()))
+    })
+    .await
+    .map_err(|e| AppError::Internal(format!("Hash task failed: {e}")))?
+    .map_err(|e| AppError::Internal(format!("Failed to hash file: {e}")))
+}
+
+pub fn content_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
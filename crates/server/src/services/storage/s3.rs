@@ -0,0 +1,185 @@
+// S3-compatible object storage backend
+// Lets stateless server replicas share project storage
+
+use std::collections::BTreeSet;
+
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use object_store::{
+    aws::{AmazonS3, AmazonS3Builder},
+    path::Path as ObjectPath,
+    ObjectMeta, ObjectStore, PutPayload,
+};
+
+use super::{DiskEntry, StorageBackend};
+use crate::{
+    config::S3Config,
+    error::{AppError, Result},
+};
+
+// S3 has no directories, so empty folders are kept alive with a marker object
+const FOLDER_MARKER: &str = ".openleaf-folder";
+
+pub struct S3Backend {
+    store: AmazonS3,
+    prefix: String,
+}
+
+impl S3Backend {
+    pub fn new(config: &S3Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            store: build_s3_store(config)?,
+            prefix: config.prefix.trim_matches('/').to_string(),
+        })
+    }
+
+    fn path(&self, key: &str) -> ObjectPath {
+        let key = key.trim_matches('/');
+        if self.prefix.is_empty() {
+            ObjectPath::from(key)
+        } else {
+            ObjectPath::from(format!("{}/{key}", self.prefix))
+        }
+    }
+
+    /// Every object stored at or below `key`, with its path relative to `key`.
+    async fn objects_under(&self, key: &str) -> Result<Vec<(ObjectMeta, String)>> {
+        let root = self.path(key);
+        let root_str = root.as_ref().to_string();
+
+        let objects: Vec<_> = self
+            .store
+            .list(Some(&root))
+            .try_collect()
+            .await
+            .map_err(storage_error)?;
+
+        Ok(objects
+            .into_iter()
+            .filter_map(|meta| {
+                let relative = meta
+                    .location
+                    .as_ref()
+                    .strip_prefix(&root_str)?
+                    .trim_start_matches('/')
+                    .to_string();
+                Some((meta, relative))
+            })
+            .collect())
+    }
+}
+
+/// Client for the configured bucket, shared with other users of S3 such as backups.
+pub fn build_s3_store(config: &S3Config) -> anyhow::Result<AmazonS3> {
+    let mut builder = AmazonS3Builder::new()
+        .with_bucket_name(&config.bucket)
+        .with_region(&config.region);
+
+    if let Some(endpoint) = &config.endpoint {
+        builder = builder
+            .with_endpoint(endpoint)
+            .with_allow_http(endpoint.starts_with("http://"));
+    }
+    if let (Some(key_id), Some(secret)) = (&config.access_key_id, &config.secret_access_key) {
+        builder = builder
+            .with_access_key_id(key_id)
+            .with_secret_access_key(secret);
+    }
+
+    Ok(builder.build()?)
+}
+
+fn storage_error(e: object_store::Error) -> AppError {
+    AppError::Internal(format!("Object storage error: {e}"))
+}
+
+#[async_trait]
+impl StorageBackend for S3Backend {
+    async fn write(&self, key: &str, content: &[u8]) -> Result<()> {
+        // A single PUT is atomic: readers see the old or the new object, never a mix
+        self.store
+            .put(&self.path(key), PutPayload::from(content.to_vec()))
+            .await
+            .map_err(storage_error)?;
+        Ok(())
+    }
+
+    async fn read(&self, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.store.get(&self.path(key)).await {
+            Ok(result) => Ok(Some(result.bytes().await.map_err(storage_error)?.to_vec())),
+            Err(object_store::Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        for (meta, _) in self.objects_under(key).await? {
+            self.store
+                .delete(&meta.location)
+                .await
+                .map_err(storage_error)?;
+        }
+
+        match self.store.delete(&self.path(key)).await {
+            Ok(()) | Err(object_store::Error::NotFound { .. }) => Ok(()),
+            Err(e) => Err(storage_error(e)),
+        }
+    }
+
+    async fn rename(&self, from: &str, to: &str) -> Result<bool> {
+        let children = self.objects_under(from).await?;
+
+        if children.is_empty() {
+            // Plain file
+            return match self.store.rename(&self.path(from), &self.path(to)).await {
+                Ok(()) => Ok(true),
+                Err(object_store::Error::NotFound { .. }) => Ok(false),
+                Err(e) => Err(storage_error(e)),
+            };
+        }
+
+        for (meta, relative) in children {
+            self.store
+                .rename(&meta.location, &self.path(&format!("{to}/{relative}")))
+                .await
+                .map_err(storage_error)?;
+        }
+        Ok(true)
+    }
+
+    async fn create_dir(&self, key: &str) -> Result<()> {
+        self.write(&format!("{key}/{FOLDER_MARKER}"), &[]).await
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<DiskEntry>> {
+        let mut folders = BTreeSet::new();
+        let mut entries = Vec::new();
+
+        for (meta, relative) in self.objects_under(prefix).await? {
+            // Every intermediate path component is an implicit folder
+            let mut parent = relative.as_str();
+            while let Some((dir, _)) = parent.rsplit_once('/') {
+                folders.insert(dir.to_string());
+                parent = dir;
+            }
+
+            let name = relative.rsplit('/').next().unwrap_or(&relative);
+            if name != FOLDER_MARKER {
+                entries.push(DiskEntry {
+                    path: relative,
+                    is_folder: false,
+                    size: meta.size as u64,
+                    modified: Some(meta.last_modified.into()),
+                });
+            }
+        }
+
+        entries.extend(folders.into_iter().map(|path| DiskEntry {
+            path,
+            is_folder: true,
+            size: 0,
+            modified: None,
+        }));
+        Ok(entries)
+    }
+}
@@ -0,0 +1,386 @@
+// Submission bundles
+// Packs a project the way arXiv and journals want it: the main file with
+// every \input and \include flattened into it and comments stripped, the
+// .bbl of the last compile in place of the .bib files, and only the figures
+// and local classes and packages the document uses. The archive is laid out
+// from the main file's directory, where TeX looks files up.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::Utc;
+use flate2::{write::GzEncoder, Compression};
+
+use crate::error::{AppError, Result};
+
+// Nesting beyond this is an \input cycle the visited check missed
+const MAX_DEPTH: usize = 32;
+
+/// What pdflatex tries, in its order, for a figure named without an extension.
+pub const GRAPHICS_EXTENSIONS: &[&str] = &[".pdf", ".png", ".jpg", ".jpeg", ".eps", ".ps"];
+
+// Environments whose contents are printed as they are, % included
+const VERBATIM_ENVIRONMENTS: &[&str] = &["verbatim", "Verbatim", "lstlisting", "minted"];
+
+/// Where a referenced file was found: its name in the archive and on disk.
+struct Found {
+    name: String,
+    path: PathBuf,
+}
+
+struct Bundler<'a> {
+    project_root: &'a Path,
+    main_dir: PathBuf,
+    /// Archive name to file on disk, besides the flattened main file
+    files: BTreeMap<String, PathBuf>,
+    /// Files flattened so far, to stop at \input cycles
+    inputs: Vec<PathBuf>,
+}
+
+/// Whether the name is a relative path that stays inside the project.
+fn is_safe_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('/')
+        && !name.contains('\\')
+        && name.split('/').all(|part| part != "..")
+}
+
+/// Remove comments, keeping the % that ends a line with code so TeX still
+/// joins it with the next, and drop comment environments.
+pub fn strip_comments(source: &str) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut verbatim: Option<&str> = None;
+    let mut in_comment = false;
+
+    for line in source.lines() {
+        if in_comment {
+            if line.contains("\\end{comment}") {
+                in_comment = false;
+            }
+            continue;
+        }
+        if let Some(environment) = verbatim {
+            if line.contains(&format!("\\end{{{environment}}}")) {
+                verbatim = None;
+            }
+            out.push_str(line);
+            out.push('\n');
+            continue;
+        }
+        if line.trim_start().starts_with("\\begin{comment}") {
+            in_comment = !line.contains("\\end{comment}");
+            continue;
+        }
+
+        let code = match unescaped_percent(line) {
+            Some(index) => {
+                let code = &line[..index];
+                // A line that was only a comment disappears entirely
+                if code.trim().is_empty() {
+                    continue;
+                }
+                &line[..=index]
+            }
+            None => line,
+        };
+        if let Some(environment) = VERBATIM_ENVIRONMENTS
+            .iter()
+            .find(|environment| code.contains(&format!("\\begin{{{environment}}}")))
+        {
+            if !code.contains(&format!("\\end{{{environment}}}")) {
+                verbatim = Some(environment);
+            }
+        }
+        out.push_str(code.trim_end_matches([' ', '\t']));
+        out.push('\n');
+    }
+    out
+}
+
+/// Byte index of the first % not escaped by a backslash.
+fn unescaped_percent(line: &str) -> Option<usize> {
+    let mut backslashes = 0;
+    for (index, c) in line.char_indices() {
+        match c {
+            '%' if backslashes % 2 == 0 => return Some(index),
+            '\\' => backslashes += 1,
+            _ => backslashes = 0,
+        }
+    }
+    None
+}
+
+/// The argument of a command at `rest`, which follows its name: `{arg}`,
+/// optionally after `[options]`. Returns the argument and the length taken.
+fn argument(rest: &str) -> Option<(&str, usize)> {
+    let mut offset = 0;
+    let trimmed = rest.trim_start();
+    offset += rest.len() - trimmed.len();
+    let mut rest = trimmed;
+    if rest.starts_with('[') {
+        let end = rest.find(']')?;
+        offset += end + 1;
+        let trimmed = rest[end + 1..].trim_start();
+        offset += rest[end + 1..].len() - trimmed.len();
+        rest = trimmed;
+    }
+    let body = rest.strip_prefix('{')?;
+    let end = body.find('}')?;
+    Some((body[..end].trim(), offset + end + 2))
+}
+
+/// Every argument given to `command` in the source, such as each name in
+/// `\usepackage{a,b}`, when `split` is set.
+pub fn arguments<'s>(source: &'s str, command: &str, split: bool) -> Vec<&'s str> {
+    let pattern = format!("\\{command}");
+    let mut found = Vec::new();
+    let mut from = 0;
+    while let Some(index) = source[from..].find(&pattern) {
+        let start = from + index + pattern.len();
+        from = start;
+        // \input must not match \inputencoding
+        if source[start..]
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '@')
+        {
+            continue;
+        }
+        let Some((argument, _)) = argument(&source[start..]) else {
+            continue;
+        };
+        if split {
+            found.extend(argument.split(',').map(str::trim).filter(|a| !a.is_empty()));
+        } else {
+            found.push(argument);
+        }
+    }
+    found
+}
+
+impl Bundler<'_> {
+    /// A file as TeX finds it: next to the main file first, then from the
+    /// project root, as is or with one of `extensions`.
+    fn find(&self, name: &str, extensions: &[&str]) -> Option<Found> {
+        if !is_safe_name(name) {
+            return None;
+        }
+        for base in [self.main_dir.as_path(), self.project_root] {
+            let candidates = std::iter::once(name.to_string()).chain(
+                extensions
+                    .iter()
+                    .map(|extension| format!("{name}{extension}")),
+            );
+            for candidate in candidates {
+                let path = base.join(&candidate);
+                if path.is_file() {
+                    return Some(Found {
+                        name: candidate,
+                        path,
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    /// The source of a .tex file with comments stripped and its \input and
+    /// \include commands replaced by the files they name.
+    fn flatten(&mut self, path: &Path, depth: usize) -> Result<String> {
+        if depth > MAX_DEPTH {
+            return Err(AppError::BadRequest(
+                "\\input files nest too deeply to flatten".to_string(),
+            ));
+        }
+        let source = std::fs::read(path)
+            .map_err(|e| AppError::Internal(format!("Failed to read {}: {e}", path.display())))?;
+        let source = strip_comments(&String::from_utf8_lossy(&source));
+        self.inputs.push(path.to_path_buf());
+
+        let mut out = String::with_capacity(source.len());
+        let mut rest = source.as_str();
+        while let Some(index) = rest.find("\\in") {
+            out.push_str(&rest[..index]);
+            let tail = &rest[index..];
+            let (command, include) = if tail.starts_with("\\include")
+                && !tail["\\include".len()..]
+                    .starts_with(|c: char| c.is_ascii_alphabetic() || c == '@')
+            {
+                ("\\include", true)
+            } else if tail.starts_with("\\input")
+                && !tail["\\input".len()..]
+                    .starts_with(|c: char| c.is_ascii_alphabetic() || c == '@')
+            {
+                ("\\input", false)
+            } else {
+                out.push_str("\\in");
+                rest = &tail[3..];
+                continue;
+            };
+
+            let after = &tail[command.len()..];
+            let Some((name, taken)) = argument(after) else {
+                out.push_str(command);
+                rest = after;
+                continue;
+            };
+            let found = self.find(name, &[".tex"]);
+            match found {
+                Some(found) if found.name.ends_with(".tex") => {
+                    if self.inputs.contains(&found.path) {
+                        return Err(AppError::BadRequest(format!(
+                            "{} \\inputs itself",
+                            found.name
+                        )));
+                    }
+                    let content = self.flatten(&found.path, depth + 1)?;
+                    if include {
+                        out.push_str("\\clearpage\n");
+                    }
+                    out.push_str(content.trim_end());
+                    if include {
+                        out.push_str("\n\\clearpage");
+                    }
+                    // The end of a file ends a line, but the line the command
+                    // was on already ends after it; a second newline would
+                    // start a new paragraph
+                    if !after[taken..].starts_with('\n') {
+                        out.push('\n');
+                    }
+                }
+                // Files that are not TeX sources, such as Inkscape's
+                // .pdf_tex, travel as they are
+                Some(found) => {
+                    out.push_str(&tail[..command.len() + taken]);
+                    self.files.insert(found.name, found.path);
+                }
+                // Left for TeX to find in its own tree
+                None => out.push_str(&tail[..command.len() + taken]),
+            }
+            rest = &after[taken..];
+        }
+        out.push_str(rest);
+
+        self.inputs.pop();
+        Ok(out)
+    }
+
+    /// Add the figures, classes, packages and styles the flattened source
+    /// refers to that live in the project.
+    fn collect(&mut self, source: &str) {
+        let mut graphics_dirs = vec![String::new()];
+        graphics_dirs.extend(graphics_paths(source));
+        for name in arguments(source, "includegraphics", false)
+            .into_iter()
+            .chain(arguments(source, "includepdf", false))
+        {
+            let found = graphics_dirs
+                .iter()
+                .find_map(|dir| self.find(&format!("{dir}{name}"), GRAPHICS_EXTENSIONS));
+            if let Some(found) = found {
+                self.files.insert(found.name, found.path);
+            }
+        }
+
+        let local = [
+            ("documentclass", ".cls"),
+            ("usepackage", ".sty"),
+            ("RequirePackage", ".sty"),
+            ("bibliographystyle", ".bst"),
+        ];
+        for (command, extension) in local {
+            for name in arguments(source, command, true) {
+                if let Some(found) = self.find(name, &[extension]) {
+                    self.files.insert(found.name, found.path);
+                }
+            }
+        }
+    }
+}
+
+/// The folders \graphicspath adds, each as `dir/`.
+pub fn graphics_paths(source: &str) -> Vec<String> {
+    arguments(source, "graphicspath", false)
+        .into_iter()
+        .flat_map(|paths| paths.split(['{', '}']))
+        .map(str::trim)
+        .filter(|dir| !dir.is_empty())
+        .map(|dir| format!("{}/", dir.trim_end_matches('/')))
+        .collect()
+}
+
+/// Whether the document prints a bibliography, and so needs its .bbl.
+fn uses_bibliography(source: &str) -> bool {
+    !arguments(source, "bibliography", false).is_empty() || source.contains("\\printbibliography")
+}
+
+/// Build the bundle for `main_file` as a .tar.gz. `bbl` is the bibliography
+/// the last compile of the main file produced, if any.
+pub async fn bundle(project_path: &Path, main_file: &str, bbl: Option<Vec<u8>>) -> Result<Vec<u8>> {
+    let project_root = tokio::fs::canonicalize(project_path)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to resolve project path: {e}")))?;
+    let main_file = main_file.to_string();
+
+    tokio::task::spawn_blocking(move || build(&project_root, &main_file, bbl))
+        .await
+        .map_err(|e| AppError::Internal(format!("Bundling task failed: {e}")))?
+}
+
+fn build(project_root: &Path, main_file: &str, bbl: Option<Vec<u8>>) -> Result<Vec<u8>> {
+    let (main_dir, main_name) = match main_file.rsplit_once('/') {
+        Some((dir, name)) => (project_root.join(dir), name),
+        None => (project_root.to_path_buf(), main_file),
+    };
+    let mut bundler = Bundler {
+        project_root,
+        main_dir,
+        files: BTreeMap::new(),
+        inputs: Vec::new(),
+    };
+
+    let source = bundler.flatten(&project_root.join(main_file), 0)?;
+    bundler.collect(&source);
+
+    let stem = main_name
+        .rsplit_once('.')
+        .map_or(main_name, |(stem, _)| stem);
+    let bbl = match bbl {
+        Some(bbl) => Some(bbl),
+        None if uses_bibliography(&source) => {
+            return Err(AppError::BadRequest(
+                "Compile the project first so its bibliography can be included".to_string(),
+            ))
+        }
+        None => None,
+    };
+
+    let archive_error =
+        |e: std::io::Error| AppError::Internal(format!("Failed to build bundle: {e}"));
+    let mtime = Utc::now().timestamp().max(0) as u64;
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let mut append = |name: &str, content: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+        builder
+            .append_data(&mut header, name, content)
+            .map_err(archive_error)
+    };
+
+    append(main_name, source.as_bytes())?;
+    if let Some(bbl) = &bbl {
+        append(&format!("{stem}.bbl"), bbl)?;
+    }
+    for (name, path) in &bundler.files {
+        let content = std::fs::read(path)
+            .map_err(|e| AppError::Internal(format!("Failed to read {name}: {e}")))?;
+        append(name, &content)?;
+    }
+
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .map_err(archive_error)
+}
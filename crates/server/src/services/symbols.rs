@@ -0,0 +1,373 @@
+// Project symbols
+// Labels, bibliography keys, glossary entries and custom commands defined
+// anywhere in a project, for autocompleting \ref{}, \cite{} and friends.
+// Each file is parsed once per version and reused until its hash changes.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{
+    db::DbPool,
+    error::Result,
+    services::{
+        bibtex,
+        outline::{self, argument, collapse_whitespace, skip_options, skip_whitespace, LineIndex},
+        storage::StorageService,
+    },
+};
+
+// Commands that define a new command, taking its name as the first argument
+const COMMAND_DEFINITIONS: &[&str] = &[
+    "newcommand",
+    "renewcommand",
+    "providecommand",
+    "DeclareMathOperator",
+    "NewDocumentCommand",
+    "RenewDocumentCommand",
+    "DeclareDocumentCommand",
+    "def",
+];
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LabelSymbol {
+    pub name: String,
+    pub file: String,
+    pub line: usize,
+    /// The environment the label is in, e.g. "figure"
+    pub environment: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CitationSymbol {
+    pub key: String,
+    pub file: String,
+    pub line: usize,
+    /// "article", "book", ...; `None` for \bibitem
+    pub entry_type: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub year: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct GlossarySymbol {
+    pub key: String,
+    pub file: String,
+    pub line: usize,
+    /// "entry" or "acronym"
+    pub kind: &'static str,
+    /// The entry's name, or the acronym's long form
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CommandSymbol {
+    /// Without the backslash
+    pub name: String,
+    pub file: String,
+    pub line: usize,
+    /// Mandatory and optional arguments, when declared with [n]
+    pub arguments: Option<u32>,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct Symbols {
+    pub labels: Vec<LabelSymbol>,
+    pub citations: Vec<CitationSymbol>,
+    pub glossary: Vec<GlossarySymbol>,
+    pub commands: Vec<CommandSymbol>,
+}
+
+impl Symbols {
+    fn extend(&mut self, other: &Symbols) {
+        self.labels.extend_from_slice(&other.labels);
+        self.citations.extend_from_slice(&other.citations);
+        self.glossary.extend_from_slice(&other.glossary);
+        self.commands.extend_from_slice(&other.commands);
+    }
+}
+
+struct CachedFile {
+    hash: String,
+    symbols: Arc<Symbols>,
+}
+
+/// Parsed symbols per file, keyed by project and path.
+#[derive(Clone, Default)]
+pub struct SymbolIndex {
+    files: Arc<Mutex<HashMap<(String, String), CachedFile>>>,
+}
+
+impl SymbolIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Symbols across all .tex and .bib files of the project. Only files that
+    /// changed since the last call are read and parsed again.
+    pub async fn project(
+        &self,
+        pool: &DbPool,
+        storage: &StorageService,
+        project_id: &str,
+    ) -> Result<Symbols> {
+        let files = sqlx::query_as::<_, (String, Option<String>)>(
+            r#"
+            SELECT path, hash FROM files
+            WHERE project_id = $1 AND is_folder = FALSE AND (path LIKE '%.tex' OR path LIKE '%.bib')
+            ORDER BY path
+            "#,
+        )
+        .bind(project_id)
+        .fetch_all(pool)
+        .await?;
+
+        let mut symbols = Symbols::default();
+        for (path, hash) in &files {
+            let cached = hash
+                .as_ref()
+                .and_then(|hash| self.cached(project_id, path, hash));
+            let file_symbols = match cached {
+                Some(file_symbols) => file_symbols,
+                None => {
+                    let content = match storage.read_file(project_id, path).await {
+                        Ok(content) => content,
+                        // Binary or missing files just have no symbols
+                        Err(e) => {
+                            tracing::debug!("Skipping {} for symbols: {}", path, e);
+                            continue;
+                        }
+                    };
+                    let file_symbols = Arc::new(parse_file(path, &content));
+                    if let Some(hash) = hash {
+                        self.store(project_id, path, hash, file_symbols.clone());
+                    }
+                    file_symbols
+                }
+            };
+            symbols.extend(&file_symbols);
+        }
+
+        // Forget files that were deleted or renamed
+        let current: HashSet<&str> = files.iter().map(|(path, _)| path.as_str()).collect();
+        self.lock()
+            .retain(|(project, path), _| project != project_id || current.contains(path.as_str()));
+
+        Ok(symbols)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<(String, String), CachedFile>> {
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn cached(&self, project_id: &str, path: &str, hash: &str) -> Option<Arc<Symbols>> {
+        self.lock()
+            .get(&(project_id.to_string(), path.to_string()))
+            .filter(|cached| cached.hash == hash)
+            .map(|cached| cached.symbols.clone())
+    }
+
+    fn store(&self, project_id: &str, path: &str, hash: &str, symbols: Arc<Symbols>) {
+        self.lock().insert(
+            (project_id.to_string(), path.to_string()),
+            CachedFile {
+                hash: hash.to_string(),
+                symbols,
+            },
+        );
+    }
+}
+
+fn parse_file(path: &str, content: &str) -> Symbols {
+    if path.ends_with(".bib") {
+        Symbols {
+            citations: parse_bib(path, content),
+            ..Symbols::default()
+        }
+    } else {
+        parse_tex(path, content)
+    }
+}
+
+fn parse_tex(path: &str, content: &str) -> Symbols {
+    let mut symbols = Symbols {
+        labels: outline::parse(content)
+            .labels
+            .into_iter()
+            .map(|label| LabelSymbol {
+                name: label.name,
+                file: path.to_string(),
+                line: label.line,
+                environment: label.environment,
+            })
+            .collect(),
+        ..Symbols::default()
+    };
+
+    let chars: Vec<char> = outline::strip_comments(content).chars().collect();
+    let lines = LineIndex::new(&chars);
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] != '\\' {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let (name, after) = command_name(&chars, i);
+        i = after.max(i + 1);
+        let Some(name) = name else {
+            continue;
+        };
+        let line = lines.line(start);
+
+        match name.as_str() {
+            "bibitem" => {
+                i = skip_options(&chars, i);
+                if let Some((key, after)) = argument(&chars, i) {
+                    i = after;
+                    symbols.citations.push(CitationSymbol {
+                        key: key.trim().to_string(),
+                        file: path.to_string(),
+                        line,
+                        entry_type: None,
+                        title: None,
+                        author: None,
+                        year: None,
+                    });
+                }
+            }
+            "newglossaryentry" => {
+                let Some((key, after)) = argument(&chars, i) else {
+                    continue;
+                };
+                i = after;
+                let text = argument(&chars, i).and_then(|(fields, after)| {
+                    i = after;
+                    key_value(&fields, "name")
+                });
+                symbols.glossary.push(GlossarySymbol {
+                    key: key.trim().to_string(),
+                    file: path.to_string(),
+                    line,
+                    kind: "entry",
+                    text,
+                });
+            }
+            "newacronym" => {
+                i = skip_options(&chars, i);
+                let Some((key, after)) = argument(&chars, i) else {
+                    continue;
+                };
+                i = after;
+                // \newacronym{key}{short}{long}
+                let long = argument(&chars, i).and_then(|(_, after)| {
+                    let (long, after) = argument(&chars, after)?;
+                    i = after;
+                    Some(collapse_whitespace(&long))
+                });
+                symbols.glossary.push(GlossarySymbol {
+                    key: key.trim().to_string(),
+                    file: path.to_string(),
+                    line,
+                    kind: "acronym",
+                    text: long,
+                });
+            }
+            name if COMMAND_DEFINITIONS.contains(&name) => {
+                if chars.get(i) == Some(&'*') {
+                    i += 1;
+                }
+                // \newcommand{\foo} or \newcommand\foo
+                let j = skip_whitespace(&chars, i);
+                let defined = match chars.get(j) {
+                    Some('{') => argument(&chars, j).map(|(inner, after)| {
+                        (inner.trim().trim_start_matches('\\').to_string(), after)
+                    }),
+                    Some('\\') => match command_name(&chars, j) {
+                        (Some(defined), after) => Some((defined, after)),
+                        (None, _) => None,
+                    },
+                    _ => None,
+                };
+                let Some((defined, after)) = defined else {
+                    continue;
+                };
+                i = after;
+                if defined.is_empty() {
+                    continue;
+                }
+                symbols.commands.push(CommandSymbol {
+                    name: defined,
+                    file: path.to_string(),
+                    line,
+                    arguments: argument_count(&chars, i),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    symbols
+}
+
+// The name of the command whose backslash is at `from`, and the index after it
+fn command_name(chars: &[char], from: usize) -> (Option<String>, usize) {
+    let mut i = from + 1;
+    while chars
+        .get(i)
+        .is_some_and(|c| c.is_ascii_alphabetic() || *c == '@')
+    {
+        i += 1;
+    }
+    if i == from + 1 {
+        return (None, i + 1);
+    }
+    (Some(chars[from + 1..i].iter().collect()), i)
+}
+
+// The [n] after a \newcommand name
+fn argument_count(chars: &[char], from: usize) -> Option<u32> {
+    let i = skip_whitespace(chars, from);
+    if chars.get(i) != Some(&'[') {
+        return None;
+    }
+    let end = chars[i..].iter().position(|c| *c == ']')? + i;
+    chars[i + 1..end]
+        .iter()
+        .collect::<String>()
+        .trim()
+        .parse()
+        .ok()
+}
+
+// The value of `key` in a "key=value, key={value}" list
+fn key_value(fields: &str, key: &str) -> Option<String> {
+    bibtex::split_top_level(fields, ',')
+        .into_iter()
+        .find_map(|field| {
+            let (name, value) = field.split_once('=')?;
+            (name.trim() == key).then(|| bibtex::plain(value))
+        })
+}
+
+fn parse_bib(path: &str, content: &str) -> Vec<CitationSymbol> {
+    bibtex::parse(content)
+        .entries
+        .into_iter()
+        .map(|entry| CitationSymbol {
+            file: path.to_string(),
+            line: entry.line,
+            title: entry.field("title").map(bibtex::plain),
+            author: entry.field("author").map(bibtex::plain),
+            year: entry
+                .field("year")
+                .or_else(|| entry.field("date"))
+                .map(|year| bibtex::plain(year).chars().take(4).collect()),
+            entry_type: Some(entry.entry_type),
+            key: entry.key,
+        })
+        .collect()
+}
@@ -0,0 +1,180 @@
+// SyncTeX lookups between source and PDF
+// Wraps the `synctex` CLI, which reads the .synctex.gz written during compile
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::error::{AppError, Result};
+
+const SYNCTEX_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A box in the PDF, in PDF points from the top-left corner of the page.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PdfLocation {
+    pub page: u32,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SourceLocation {
+    /// Path relative to the project root
+    pub file: String,
+    pub line: u32,
+    pub column: Option<u32>,
+}
+
+/// Map a source position to the places it appears in the PDF.
+pub async fn forward(
+    project_path: &Path,
+    pdf: &Path,
+    file: &str,
+    line: u32,
+    column: u32,
+) -> Result<Vec<PdfLocation>> {
+    let input = project_root(project_path).await.join(file);
+    let output = run(
+        project_path,
+        &[
+            "view",
+            "-i",
+            &format!("{line}:{column}:{}", input.display()),
+            "-o",
+            &pdf.to_string_lossy(),
+        ],
+    )
+    .await?;
+
+    let mut locations = Vec::new();
+    let mut current: Option<PdfLocation> = None;
+    for (key, value) in records(&output) {
+        match key {
+            "Page" => {
+                locations.extend(current.take());
+                current = value.parse().ok().map(|page| PdfLocation {
+                    page,
+                    x: 0.0,
+                    y: 0.0,
+                    width: 0.0,
+                    height: 0.0,
+                });
+            }
+            // h/v is the box origin (its baseline), W/H its size
+            "h" | "v" | "W" | "H" => {
+                if let (Some(location), Ok(value)) = (current.as_mut(), value.parse::<f64>()) {
+                    match key {
+                        "h" => location.x = value,
+                        "v" => location.y = value,
+                        "W" => location.width = value,
+                        _ => location.height = value,
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    locations.extend(current);
+
+    // v is the baseline; report the top of the box instead
+    for location in &mut locations {
+        location.y -= location.height;
+    }
+    Ok(locations)
+}
+
+/// Map a point in the PDF back to the source line that produced it.
+pub async fn inverse(
+    project_path: &Path,
+    pdf: &Path,
+    page: u32,
+    x: f64,
+    y: f64,
+) -> Result<Option<SourceLocation>> {
+    let output = run(
+        project_path,
+        &["edit", "-o", &format!("{page}:{x}:{y}:{}", pdf.display())],
+    )
+    .await?;
+
+    let root = project_root(project_path).await;
+
+    let mut file = None;
+    let mut line = None;
+    let mut column = None;
+    for (key, value) in records(&output) {
+        match key {
+            "Input" if file.is_none() => file = Some(relative_path(&root, value)),
+            "Line" if line.is_none() => line = value.parse().ok(),
+            // -1 means unknown
+            "Column" if column.is_none() => column = value.parse::<i64>().ok(),
+            _ => {}
+        }
+    }
+
+    Ok(match (file, line) {
+        (Some(file), Some(line)) => Some(SourceLocation {
+            file,
+            line,
+            column: column.and_then(|c| u32::try_from(c).ok()),
+        }),
+        _ => None,
+    })
+}
+
+// synctex records absolute paths, while the storage path may be relative
+async fn project_root(project_path: &Path) -> PathBuf {
+    tokio::fs::canonicalize(project_path)
+        .await
+        .unwrap_or_else(|_| project_path.to_path_buf())
+}
+
+// synctex prints "Key:value" lines between SyncTeX result begin/end markers
+fn records(output: &str) -> impl Iterator<Item = (&str, &str)> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("SyncTeX result begin"))
+        .take_while(|line| !line.starts_with("SyncTeX result end"))
+        .filter_map(|line| line.split_once(':'))
+}
+
+fn relative_path(root: &Path, input: &str) -> String {
+    let input = Path::new(input);
+    // Paths may be recorded as "/abs/project/./chapters/intro.tex"
+    let relative = input.strip_prefix(root).unwrap_or(input);
+    relative
+        .components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+async fn run(project_path: &Path, args: &[&str]) -> Result<String> {
+    let child = tokio::process::Command::new("synctex")
+        .args(args)
+        .current_dir(project_path)
+        .kill_on_drop(true)
+        .output();
+
+    let output = match tokio::time::timeout(SYNCTEX_TIMEOUT, child).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::Internal("synctex is not installed".to_string()));
+        }
+        Ok(Err(e)) => return Err(AppError::Internal(format!("Failed to run synctex: {e}"))),
+        Err(_) => return Err(AppError::Internal("synctex timed out".to_string())),
+    };
+
+    if !output.status.success() {
+        return Err(AppError::BadRequest(format!(
+            "synctex failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
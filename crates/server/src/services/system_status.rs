@@ -0,0 +1,125 @@
+// Maintenance mode and announcements
+// Operators can make the instance read-only while they migrate or back it up,
+// and post a banner for every user. Both are kept in the database so every
+// server process sees them, and cached here so the check that guards each
+// request costs no query.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+use utoipa::ToSchema;
+
+use crate::{db::Database, error::Result};
+
+// How often the cache is refreshed, to pick up changes made by other processes
+const REFRESH_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema, sqlx::FromRow)]
+pub struct SystemStatus {
+    /// Changes are refused while this is set
+    pub maintenance: bool,
+    /// Why, and for how long, shown to users during maintenance
+    pub maintenance_message: Option<String>,
+    /// Banner shown to every user, in maintenance or not
+    pub announcement: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+/// Fields left out keep their current value; an empty message clears it.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct StatusUpdate {
+    pub maintenance: Option<bool>,
+    pub maintenance_message: Option<String>,
+    pub announcement: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct SystemStatusService {
+    db: Database,
+    current: Arc<watch::Sender<SystemStatus>>,
+}
+
+impl SystemStatusService {
+    pub async fn load(db: Database) -> Result<Self> {
+        let status = read(&db).await?;
+        Ok(Self {
+            db,
+            current: Arc::new(watch::Sender::new(status)),
+        })
+    }
+
+    pub fn current(&self) -> SystemStatus {
+        self.current.borrow().clone()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.current.borrow().maintenance
+    }
+
+    /// Notified whenever the status changes, so open connections can react
+    /// to maintenance starting.
+    pub fn subscribe(&self) -> watch::Receiver<SystemStatus> {
+        self.current.subscribe()
+    }
+
+    pub async fn update(&self, update: StatusUpdate, user_id: &str) -> Result<SystemStatus> {
+        let current = self.current();
+        let message = |value: Option<String>, current: Option<String>| match value {
+            Some(value) if value.trim().is_empty() => None,
+            Some(value) => Some(value.trim().to_string()),
+            None => current,
+        };
+        sqlx::query(
+            "UPDATE system_status SET maintenance = $1, maintenance_message = $2, \
+             announcement = $3, updated_at = $4, updated_by = $5 WHERE id = 1",
+        )
+        .bind(update.maintenance.unwrap_or(current.maintenance))
+        .bind(message(
+            update.maintenance_message,
+            current.maintenance_message,
+        ))
+        .bind(message(update.announcement, current.announcement))
+        .bind(Utc::now().to_rfc3339())
+        .bind(user_id)
+        .execute(&self.db.pool)
+        .await?;
+
+        self.refresh().await?;
+        Ok(self.current())
+    }
+
+    async fn refresh(&self) -> Result<()> {
+        let status = read(&self.db).await?;
+        self.current.send_if_modified(|current| {
+            let changed = *current != status;
+            *current = status;
+            changed
+        });
+        Ok(())
+    }
+}
+
+async fn read(db: &Database) -> Result<SystemStatus> {
+    let status = sqlx::query_as::<_, SystemStatus>(
+        "SELECT maintenance, maintenance_message, announcement, updated_at \
+         FROM system_status WHERE id = 1",
+    )
+    .fetch_optional(&db.pool)
+    .await?;
+    Ok(status.unwrap_or_default())
+}
+
+pub fn spawn_refresh(service: SystemStatusService) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(REFRESH_INTERVAL);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = service.refresh().await {
+                tracing::warn!("Failed to refresh system status: {}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,837 @@
+// Project templates
+// What a new project starts out with: one of the templates that come with the
+// server, or a project someone published for everyone on the server, such as
+// a lab's style guide. Publishing snapshots the project's files, so later
+// work on it doesn't change the template. The .tex files of a template may
+// hold {{title}}, {{author}} and {{institution}} placeholders, which are
+// filled in from the create request when the project is made, so a new
+// document starts with its own title page rather than someone else's.
+// Operators can also offer templates of their own, such as an institution's
+// thesis class, by dropping them in a directory on disk; it is read at
+// startup and again whenever an admin asks for it.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    db::DbPool,
+    error::{AppError, Result},
+    services::{
+        build_cache::BuildCache, collab::CollabService, filetype, gc, pdf_pages,
+        storage::StorageService,
+    },
+};
+
+/// The template a project gets when the request names none.
+pub const DEFAULT_TEMPLATE: &str = "article";
+
+// Placeholders a template can use
+const VARIABLES: [&str; 3] = ["title", "author", "institution"];
+
+const ARTICLE_MAIN: &str = r#"\documentclass{article}
+\usepackage[utf8]{inputenc}
+
+\title{{{title}}}
+\author{{{author}} \\ {{institution}}}
+\date{\today}
+
+\begin{document}
+
+\maketitle
+
+\section{Introduction}
+
+Your content here.
+
+\end{document}
+"#;
+
+// Limits on what a template is published with
+const MAX_NAME: usize = 100;
+const MAX_DESCRIPTION: usize = 2000;
+const MAX_TAGS: usize = 10;
+const MAX_TAG: usize = 32;
+
+// What a template directory may hold besides its files
+const METADATA_FILE: &str = "template.toml";
+const PREVIEW_FILE: &str = "preview.png";
+
+// Limits on a template read from disk
+const MAX_ID: usize = 64;
+const MAX_DISK_FILES: usize = 1000;
+const MAX_DISK_BYTES: u64 = 200 * 1024 * 1024;
+
+struct Builtin {
+    id: &'static str,
+    name: &'static str,
+    description: &'static str,
+    tags: &'static [&'static str],
+    files: &'static [(&'static str, &'static str)],
+}
+
+const BUILTIN: &[Builtin] = &[Builtin {
+    id: "article",
+    name: "Article",
+    description: "A blank article with a title page",
+    tags: &["article"],
+    files: &[("main.tex", ARTICLE_MAIN)],
+}];
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct TemplateInfo {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    #[sqlx(skip)]
+    pub tags: Vec<String>,
+    /// None for the templates that come with the server
+    pub published_by: Option<String>,
+    pub published_by_name: Option<String>,
+    /// The project it was published from, while it exists
+    pub project_id: Option<String>,
+    /// Image of the first page, if the project had been compiled
+    #[sqlx(skip)]
+    pub preview_url: Option<String>,
+    #[serde(skip)]
+    pub preview_hash: Option<String>,
+    pub created_at: Option<String>,
+    pub updated_at: Option<String>,
+}
+
+/// What a project is published as.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TemplateDetails {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Lowercase words to find it by, such as "thesis" or "ieee"
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Values for a template's placeholders. Missing ones default to the project
+/// name, the creating user's name and nothing.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct TemplateVariables {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub institution: Option<String>,
+}
+
+impl TemplateVariables {
+    /// Each placeholder with the text it stands for, escaped for LaTeX.
+    pub fn resolve(self, project_name: &str, user_name: &str) -> Vec<(&'static str, String)> {
+        let values = [
+            self.title.unwrap_or_else(|| project_name.to_string()),
+            self.author.unwrap_or_else(|| user_name.to_string()),
+            self.institution.unwrap_or_default(),
+        ];
+        VARIABLES
+            .into_iter()
+            .zip(values)
+            .map(|(name, value)| (name, escape(value.trim())))
+            .collect()
+    }
+}
+
+/// A file of a template, before its placeholders are filled in.
+pub struct TemplateFile {
+    pub path: String,
+    pub content: Vec<u8>,
+}
+
+/// The templates found in the templates directory. Each subdirectory is one,
+/// named by the subdirectory, and one with the name of a built-in template
+/// takes its place. An optional template.toml gives its name, description and
+/// tags, and an optional preview.png its preview; neither is copied into
+/// projects. Files are read when a project is created, so editing one takes
+/// effect at once, while new and removed templates need a reload.
+#[derive(Clone, Default)]
+pub struct TemplateLibrary {
+    root: Option<PathBuf>,
+    templates: Arc<RwLock<Vec<DiskTemplate>>>,
+}
+
+#[derive(Clone)]
+struct DiskTemplate {
+    id: String,
+    name: String,
+    description: String,
+    tags: BTreeSet<String>,
+    dir: PathBuf,
+    files: Vec<String>,
+    has_preview: bool,
+}
+
+// Contents of template.toml
+#[derive(Deserialize)]
+struct DiskMetadata {
+    name: Option<String>,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// What a reload of the templates directory found.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct LibraryReport {
+    /// None when no templates directory is configured
+    pub path: Option<String>,
+    /// Ids of the templates now offered
+    pub loaded: Vec<String>,
+    pub skipped: Vec<SkippedTemplate>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SkippedTemplate {
+    pub directory: String,
+    pub reason: String,
+}
+
+impl TemplateLibrary {
+    pub fn new(root: Option<&str>) -> Self {
+        Self {
+            root: root.map(PathBuf::from),
+            templates: Arc::default(),
+        }
+    }
+
+    /// Read the templates directory again, replacing what was loaded before.
+    /// If the directory cannot be read at all, the templates stay as they were.
+    pub async fn reload(&self) -> Result<LibraryReport> {
+        let Some(root) = self.root.clone() else {
+            return Ok(LibraryReport::default());
+        };
+        let (templates, report) = tokio::task::spawn_blocking(move || scan(&root))
+            .await
+            .map_err(|e| AppError::Internal(format!("Template scan failed: {e}")))??;
+        for skipped in &report.skipped {
+            tracing::warn!(
+                "Skipped template directory {}: {}",
+                skipped.directory,
+                skipped.reason
+            );
+        }
+        tracing::info!(
+            "Loaded {} templates from {}",
+            templates.len(),
+            report.path.as_deref().unwrap_or_default()
+        );
+        *self.templates.write().unwrap() = templates;
+        Ok(report)
+    }
+
+    fn find(&self, id: &str) -> Option<DiskTemplate> {
+        self.templates
+            .read()
+            .unwrap()
+            .iter()
+            .find(|template| template.id == id)
+            .cloned()
+    }
+
+    fn all(&self) -> Vec<DiskTemplate> {
+        self.templates.read().unwrap().clone()
+    }
+}
+
+// Every usable template under the root, by id, and what was passed over
+fn scan(root: &Path) -> Result<(Vec<DiskTemplate>, LibraryReport)> {
+    let mut report = LibraryReport {
+        path: Some(root.display().to_string()),
+        ..Default::default()
+    };
+    let entries = std::fs::read_dir(root).map_err(|e| {
+        AppError::Internal(format!(
+            "Cannot read templates directory {}: {e}",
+            root.display()
+        ))
+    })?;
+    let mut dirs: Vec<(String, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_ok_and(|kind| kind.is_dir()))
+        .filter_map(|entry| Some((entry.file_name().into_string().ok()?, entry.path())))
+        .filter(|(name, _)| !name.starts_with('.'))
+        .collect();
+    dirs.sort();
+
+    let mut templates = Vec::with_capacity(dirs.len());
+    for (id, dir) in dirs {
+        match load(&id, &dir) {
+            Ok(template) => {
+                report.loaded.push(id);
+                templates.push(template);
+            }
+            Err(reason) => report.skipped.push(SkippedTemplate {
+                directory: id,
+                reason,
+            }),
+        }
+    }
+    Ok((templates, report))
+}
+
+fn load(id: &str, dir: &Path) -> std::result::Result<DiskTemplate, String> {
+    if id.len() > MAX_ID
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(format!(
+            "the name must be at most {MAX_ID} letters, digits, '-', '_' and '.'"
+        ));
+    }
+
+    let metadata = match std::fs::read_to_string(dir.join(METADATA_FILE)) {
+        Ok(text) => toml::from_str(&text).map_err(|e| format!("invalid {METADATA_FILE}: {e}"))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => DiskMetadata {
+            name: None,
+            description: String::new(),
+            tags: Vec::new(),
+        },
+        Err(e) => return Err(format!("cannot read {METADATA_FILE}: {e}")),
+    };
+    let (name, description, tags) = validate(TemplateDetails {
+        name: metadata.name.unwrap_or_else(|| id.to_string()),
+        description: metadata.description,
+        tags: metadata.tags,
+    })
+    .map_err(|e| e.to_string())?;
+
+    let mut files = Vec::new();
+    let mut size = 0;
+    walk(dir, "", &mut files, &mut size)?;
+    files.retain(|path| path != METADATA_FILE && path != PREVIEW_FILE);
+    if files.is_empty() {
+        return Err("it holds no files".to_string());
+    }
+    files.sort();
+
+    Ok(DiskTemplate {
+        id: id.to_string(),
+        name,
+        description,
+        tags,
+        dir: dir.to_path_buf(),
+        files,
+        has_preview: dir.join(PREVIEW_FILE).is_file(),
+    })
+}
+
+// Collect the regular files below `dir` as paths relative to the template.
+// Hidden entries are left out, and symlinks too, so a template cannot pull
+// in files from elsewhere on the server.
+fn walk(
+    dir: &Path,
+    prefix: &str,
+    files: &mut Vec<String>,
+    size: &mut u64,
+) -> std::result::Result<(), String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("cannot read {prefix}: {e}"))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| e.to_string())?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+        let metadata = entry.metadata().map_err(|e| e.to_string())?;
+        if metadata.is_dir() {
+            walk(&entry.path(), &path, files, size)?;
+        } else if metadata.is_file() {
+            *size += metadata.len();
+            files.push(path);
+        }
+        if files.len() > MAX_DISK_FILES || *size > MAX_DISK_BYTES {
+            return Err(format!(
+                "it is larger than {MAX_DISK_FILES} files or {} MiB",
+                MAX_DISK_BYTES / (1024 * 1024)
+            ));
+        }
+    }
+    Ok(())
+}
+
+impl From<DiskTemplate> for TemplateInfo {
+    fn from(template: DiskTemplate) -> Self {
+        Self {
+            preview_url: template
+                .has_preview
+                .then(|| format!("/api/templates/{}/preview.png", template.id)),
+            id: template.id,
+            name: template.name,
+            description: template.description,
+            tags: template.tags.into_iter().collect(),
+            published_by: None,
+            published_by_name: None,
+            project_id: None,
+            preview_hash: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+impl From<&Builtin> for TemplateInfo {
+    fn from(template: &Builtin) -> Self {
+        Self {
+            id: template.id.to_string(),
+            name: template.name.to_string(),
+            description: template.description.to_string(),
+            tags: template.tags.iter().map(|tag| tag.to_string()).collect(),
+            published_by: None,
+            published_by_name: None,
+            project_id: None,
+            preview_url: None,
+            preview_hash: None,
+            created_at: None,
+            updated_at: None,
+        }
+    }
+}
+
+// Columns of a published template with its publisher's name
+const SELECT: &str = "SELECT t.id, t.name, t.description, t.published_by, \
+    u.name AS published_by_name, t.project_id, t.preview_hash, t.created_at, t.updated_at \
+    FROM templates t LEFT JOIN users u ON t.published_by = u.id";
+
+/// The templates a project can be created from: those that come with the
+/// server, then those from the templates directory, then the published ones,
+/// newest first. With a tag, only those tagged with it.
+pub async fn list(
+    pool: &DbPool,
+    library: &TemplateLibrary,
+    tag: Option<&str>,
+) -> Result<Vec<TemplateInfo>> {
+    let tag = tag.map(|tag| tag.trim().to_lowercase());
+    let disk = library.all();
+    let mut templates: Vec<TemplateInfo> = BUILTIN
+        .iter()
+        .filter(|template| !disk.iter().any(|shadow| shadow.id == template.id))
+        .filter(|template| {
+            tag.as_deref()
+                .is_none_or(|tag| template.tags.contains(&tag))
+        })
+        .map(TemplateInfo::from)
+        .collect();
+    templates.extend(
+        disk.into_iter()
+            .filter(|template| tag.as_ref().is_none_or(|tag| template.tags.contains(tag)))
+            .map(TemplateInfo::from),
+    );
+
+    let published = match &tag {
+        Some(tag) => {
+            sqlx::query_as::<_, TemplateInfo>(&format!(
+                "{SELECT} WHERE t.id IN (SELECT template_id FROM template_tags WHERE tag = $1) \
+                 ORDER BY t.created_at DESC"
+            ))
+            .bind(tag)
+            .fetch_all(pool)
+            .await?
+        }
+        None => {
+            sqlx::query_as::<_, TemplateInfo>(&format!("{SELECT} ORDER BY t.created_at DESC"))
+                .fetch_all(pool)
+                .await?
+        }
+    };
+    let mut tags: HashMap<String, Vec<String>> = HashMap::new();
+    for (template_id, tag) in sqlx::query_as::<_, (String, String)>(
+        "SELECT template_id, tag FROM template_tags ORDER BY tag",
+    )
+    .fetch_all(pool)
+    .await?
+    {
+        tags.entry(template_id).or_default().push(tag);
+    }
+    for mut template in published {
+        template.tags = tags.remove(&template.id).unwrap_or_default();
+        template.preview_url = preview_url(&template);
+        templates.push(template);
+    }
+    Ok(templates)
+}
+
+/// Any template a project can be created from.
+pub async fn get(pool: &DbPool, library: &TemplateLibrary, id: &str) -> Result<TemplateInfo> {
+    if let Some(template) = library.find(id) {
+        return Ok(template.into());
+    }
+    if let Some(template) = BUILTIN.iter().find(|template| template.id == id) {
+        return Ok(template.into());
+    }
+    published(pool, id).await
+}
+
+/// A published template.
+pub async fn published(pool: &DbPool, id: &str) -> Result<TemplateInfo> {
+    let mut template = sqlx::query_as::<_, TemplateInfo>(&format!("{SELECT} WHERE t.id = $1"))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Template not found".to_string()))?;
+    template.tags = sqlx::query_scalar::<_, String>(
+        "SELECT tag FROM template_tags WHERE template_id = $1 ORDER BY tag",
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+    template.preview_url = preview_url(&template);
+    Ok(template)
+}
+
+fn preview_url(template: &TemplateInfo) -> Option<String> {
+    template
+        .preview_hash
+        .as_ref()
+        .map(|_| format!("/api/templates/{}/preview.png", template.id))
+}
+
+/// The files of a template.
+pub async fn files(
+    pool: &DbPool,
+    storage: &StorageService,
+    library: &TemplateLibrary,
+    id: &str,
+) -> Result<Vec<TemplateFile>> {
+    if let Some(template) = library.find(id) {
+        let mut files = Vec::with_capacity(template.files.len());
+        for path in template.files {
+            let content = tokio::fs::read(template.dir.join(&path))
+                .await
+                .map_err(|e| {
+                    AppError::Internal(format!("Failed to read template file {path}: {e}"))
+                })?;
+            files.push(TemplateFile { path, content });
+        }
+        return Ok(files);
+    }
+    if let Some(template) = BUILTIN.iter().find(|template| template.id == id) {
+        return Ok(template
+            .files
+            .iter()
+            .map(|(path, content)| TemplateFile {
+                path: path.to_string(),
+                content: content.as_bytes().to_vec(),
+            })
+            .collect());
+    }
+
+    let stored = sqlx::query_as::<_, (String, String)>(
+        "SELECT path, hash FROM template_files WHERE template_id = $1 ORDER BY path",
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+    if stored.is_empty() {
+        // Published from a project without files, or not there at all
+        published(pool, id).await?;
+    }
+    let mut files = Vec::with_capacity(stored.len());
+    for (path, hash) in stored {
+        files.push(TemplateFile {
+            content: storage.read_object(&hash).await?,
+            path,
+        });
+    }
+    Ok(files)
+}
+
+/// Publish a snapshot of the project's files as a template, with the first
+/// page of its last compile as the preview.
+pub async fn publish(
+    pool: &DbPool,
+    storage: &StorageService,
+    collab: &CollabService,
+    cache_root: &Path,
+    project_id: &str,
+    user_id: &str,
+    details: TemplateDetails,
+) -> Result<TemplateInfo> {
+    let (name, description, tags) = validate(details)?;
+
+    // Include what is being typed right now
+    collab.persist_project(project_id).await?;
+    let paths = sqlx::query_scalar::<_, String>(
+        "SELECT path FROM files WHERE project_id = $1 AND is_folder = FALSE ORDER BY path",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+    let mut files = Vec::with_capacity(paths.len());
+    for path in paths {
+        let content = storage.read_bytes(project_id, &path).await?;
+        files.push((storage.write_object(&content).await?, path));
+    }
+    let preview_hash = preview(storage, cache_root, project_id).await;
+
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO templates (id, name, description, project_id, published_by, preview_hash, created_at, updated_at) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+    )
+    .bind(&id)
+    .bind(&name)
+    .bind(&description)
+    .bind(project_id)
+    .bind(user_id)
+    .bind(&preview_hash)
+    .bind(&now)
+    .bind(&now)
+    .execute(&mut *tx)
+    .await?;
+    for (hash, path) in &files {
+        sqlx::query("INSERT INTO template_files (template_id, path, hash) VALUES ($1, $2, $3)")
+            .bind(&id)
+            .bind(path)
+            .bind(hash)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for tag in &tags {
+        sqlx::query("INSERT INTO template_tags (template_id, tag) VALUES ($1, $2)")
+            .bind(&id)
+            .bind(tag)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    published(pool, &id).await
+}
+
+/// Take a template down, along with the stored contents nothing else uses.
+/// Projects created from it keep their files.
+pub async fn unpublish(pool: &DbPool, storage: &StorageService, id: &str) -> Result<()> {
+    let template = published(pool, id).await?;
+    let mut hashes: BTreeSet<String> =
+        sqlx::query_scalar::<_, String>("SELECT hash FROM template_files WHERE template_id = $1")
+            .bind(id)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .collect();
+    hashes.extend(template.preview_hash);
+
+    let mut tx = pool.begin().await?;
+    for table in ["template_files", "template_tags"] {
+        sqlx::query(&format!("DELETE FROM {table} WHERE template_id = $1"))
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query("DELETE FROM templates WHERE id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    for hash in hashes {
+        if !gc::object_in_use(pool, &hash).await? {
+            storage.delete_object(&hash).await?;
+        }
+    }
+    Ok(())
+}
+
+/// The PNG of a template's first page.
+pub async fn preview_png(
+    pool: &DbPool,
+    storage: &StorageService,
+    library: &TemplateLibrary,
+    id: &str,
+) -> Result<Vec<u8>> {
+    if let Some(template) = library.find(id) {
+        if !template.has_preview {
+            return Err(AppError::NotFound(
+                "The template has no preview".to_string(),
+            ));
+        }
+        return tokio::fs::read(template.dir.join(PREVIEW_FILE))
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to read template preview: {e}")));
+    }
+    let hash = published(pool, id)
+        .await?
+        .preview_hash
+        .ok_or_else(|| AppError::NotFound("The template has no preview".to_string()))?;
+    storage.read_object(&hash).await
+}
+
+// Store the first page of the project's last successful compile. A template
+// is still worth publishing without one, so failures are only logged.
+async fn preview(storage: &StorageService, cache_root: &Path, project_id: &str) -> Option<String> {
+    let cache = BuildCache::new(cache_root, project_id, None);
+    let pdf = cache.last_pdf().await?;
+    cache.artifact_path(&pdf)?;
+    let page = pdf_pages::thumbnail_path(cache.dir(), &pdf, 1);
+    if !page.exists() {
+        if let Err(e) = pdf_pages::generate_thumbnails(cache.dir(), &pdf).await {
+            tracing::warn!(
+                "Failed to render a template preview of {}: {}",
+                project_id,
+                e
+            );
+            return None;
+        }
+    }
+    let png = tokio::fs::read(&page).await.ok()?;
+    match storage.write_object(&png).await {
+        Ok(hash) => Some(hash),
+        Err(e) => {
+            tracing::warn!(
+                "Failed to store a template preview of {}: {}",
+                project_id,
+                e
+            );
+            None
+        }
+    }
+}
+
+// Trimmed name and description, and tags lowercased without duplicates
+fn validate(details: TemplateDetails) -> Result<(String, String, BTreeSet<String>)> {
+    let name = details.name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_NAME {
+        return Err(AppError::Validation(format!(
+            "Template name must be 1 to {MAX_NAME} characters"
+        )));
+    }
+    let description = details.description.trim().to_string();
+    if description.chars().count() > MAX_DESCRIPTION {
+        return Err(AppError::Validation(format!(
+            "Template description must be at most {MAX_DESCRIPTION} characters"
+        )));
+    }
+    let tags: BTreeSet<String> = details
+        .tags
+        .iter()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    if tags.len() > MAX_TAGS {
+        return Err(AppError::Validation(format!(
+            "A template can have at most {MAX_TAGS} tags"
+        )));
+    }
+    if let Some(tag) = tags.iter().find(|tag| {
+        tag.len() > MAX_TAG
+            || !tag
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    }) {
+        return Err(AppError::Validation(format!(
+            "Invalid tag '{tag}'; use letters, digits, '-', '_' and '.', at most {MAX_TAG} characters"
+        )));
+    }
+    Ok((name, description, tags))
+}
+
+/// Write a template's files into a new project, placeholders filled in, and
+/// record them and the folders holding them.
+pub async fn instantiate(
+    pool: &DbPool,
+    storage: &StorageService,
+    project_id: &str,
+    files: Vec<TemplateFile>,
+    variables: &[(&str, String)],
+) -> Result<()> {
+    let now = Utc::now().to_rfc3339();
+
+    let folders: BTreeSet<&str> = files
+        .iter()
+        .flat_map(|file| {
+            file.path
+                .match_indices('/')
+                .map(|(end, _)| &file.path[..end])
+        })
+        .collect();
+    for folder in folders {
+        storage.create_folder(project_id, folder).await?;
+        sqlx::query(
+            "INSERT INTO files (id, project_id, name, path, is_folder, created_at, updated_at) VALUES ($1, $2, $3, $4, TRUE, $5, $6)",
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(project_id)
+        .bind(folder.rsplit('/').next().unwrap_or(folder))
+        .bind(folder)
+        .bind(&now)
+        .bind(&now)
+        .execute(pool)
+        .await?;
+    }
+
+    for file in files {
+        let content = if file.path.ends_with(".tex") {
+            fill(file.content, variables)
+        } else {
+            file.content
+        };
+        let hash = storage.write_file(project_id, &file.path, &content).await?;
+        let file_type = filetype::detect(&file.path, &content);
+        sqlx::query(
+            "INSERT INTO files (id, project_id, name, path, is_folder, hash, mime_type, language, created_at, updated_at) VALUES ($1, $2, $3, $4, FALSE, $5, $6, $7, $8, $9)",
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(project_id)
+        .bind(file.path.rsplit('/').next().unwrap_or(&file.path))
+        .bind(&file.path)
+        .bind(&hash)
+        .bind(&file_type.mime_type)
+        .bind(&file_type.language)
+        .bind(&now)
+        .bind(&now)
+        .execute(pool)
+        .await?;
+    }
+    Ok(())
+}
+
+// Substitute placeholders in a text file; anything else is left as it is
+fn fill(content: Vec<u8>, variables: &[(&str, String)]) -> Vec<u8> {
+    let mut text = match String::from_utf8(content) {
+        Ok(text) => text,
+        Err(e) => return e.into_bytes(),
+    };
+    for (name, value) in variables {
+        text = text.replace(&format!("{{{{{name}}}}}"), value);
+    }
+    text.into_bytes()
+}
+
+// Values are typed by users, so characters TeX treats specially are
+// escaped rather than interpreted
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\textbackslash{}"),
+            '~' => escaped.push_str("\\textasciitilde{}"),
+            '^' => escaped.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push(' '),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
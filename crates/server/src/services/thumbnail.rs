@@ -0,0 +1,53 @@
+// Thumbnail generation for image assets
+
+use std::path::{Path, PathBuf};
+
+use image::{imageops::FilterType, ImageFormat};
+
+use crate::error::{AppError, Result};
+
+pub const DEFAULT_WIDTH: u32 = 256;
+pub const MAX_WIDTH: u32 = 1024;
+
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "gif", "webp", "bmp", "tif", "tiff"];
+
+pub fn is_image(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| IMAGE_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// Thumbnails are keyed by content hash so edits never serve a stale preview.
+pub fn cache_path(
+    cache_root: &Path,
+    project_id: &str,
+    file_id: &str,
+    hash: &str,
+    width: u32,
+) -> PathBuf {
+    let hash = &hash[..hash.len().min(16)];
+    cache_root
+        .join("thumbnails")
+        .join(project_id)
+        .join(format!("{file_id}_{hash}_{width}.png"))
+}
+
+/// Render a PNG thumbnail of the given image bytes.
+pub fn generate(source: &[u8], width: u32) -> Result<Vec<u8>> {
+    let img = image::load_from_memory(source)
+        .map_err(|e| AppError::BadRequest(format!("Failed to decode image: {e}")))?;
+
+    // Never upscale small images
+    let width = width.min(img.width()).max(1);
+    let height = ((img.height() as u64 * width as u64) / img.width().max(1) as u64).max(1) as u32;
+    let resized = img.resize(width, height, FilterType::Triangle);
+
+    let mut bytes = Vec::new();
+    resized
+        .write_to(&mut std::io::Cursor::new(&mut bytes), ImageFormat::Png)
+        .map_err(|e| AppError::Internal(format!("Failed to encode thumbnail: {e}")))?;
+
+    Ok(bytes)
+}
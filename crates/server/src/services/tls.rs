@@ -0,0 +1,192 @@
+// HTTPS
+// TLS is terminated here for setups without a reverse proxy. The certificate
+// and key are read from PEM files and checked for changes every minute, so a
+// renewal by certbot or similar takes effect without a restart.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use axum::{extract::ConnectInfo, http::Request, Router};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use tokio::net::TcpListener;
+use tokio_rustls::{
+    rustls::{
+        self,
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        server::{ClientHello, ResolvesServerCert},
+        sign::CertifiedKey,
+    },
+    TlsAcceptor,
+};
+use tower::ServiceExt;
+
+use crate::config::TlsConfig;
+
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+// Clients that connect and never finish the handshake are dropped after this
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Load the certificate and build the acceptor, and start watching the files.
+pub fn acceptor(config: &TlsConfig) -> anyhow::Result<TlsAcceptor> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let resolver = Arc::new(CertResolver {
+        config: config.clone(),
+        provider: provider.clone(),
+        current: RwLock::new(Loaded::read(config, &provider)?),
+    });
+    spawn_reloader(resolver.clone());
+
+    let mut server = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    server.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(server)))
+}
+
+/// Serve the app over TLS until `shutdown` resolves, then wait for open
+/// connections to finish, like `axum::serve` with graceful shutdown.
+pub async fn serve(
+    listener: TcpListener,
+    app: Router,
+    acceptor: TlsAcceptor,
+    shutdown: impl Future<Output = ()>,
+) -> std::io::Result<()> {
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let (stream, remote) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    // Usually out of file descriptors; give connections time to close
+                    tracing::warn!("Failed to accept connection: {}", e);
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    continue;
+                }
+            },
+            () = &mut shutdown => break,
+        };
+
+        let acceptor = acceptor.clone();
+        let builder = builder.clone();
+        let service = TowerToHyperService::new(app.clone().map_request(connect_info(remote)));
+        let watcher = graceful.watcher();
+        tokio::spawn(async move {
+            let stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        tracing::debug!("TLS handshake with {} failed: {}", remote, e);
+                        return;
+                    }
+                    Err(_) => return,
+                };
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            if let Err(e) = watcher.watch(connection.into_owned()).await {
+                tracing::debug!("Connection with {} ended: {}", remote, e);
+            }
+        });
+    }
+
+    graceful.shutdown().await;
+    Ok(())
+}
+
+/// Record the peer address on each request, as `axum::serve` does with
+/// `into_make_service_with_connect_info`.
+fn connect_info<B>(remote: SocketAddr) -> impl Fn(Request<B>) -> Request<B> + Clone {
+    move |mut request| {
+        request.extensions_mut().insert(ConnectInfo(remote));
+        request
+    }
+}
+
+#[derive(Debug)]
+struct CertResolver {
+    config: TlsConfig,
+    provider: Arc<rustls::crypto::CryptoProvider>,
+    current: RwLock<Loaded>,
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().key.clone())
+    }
+}
+
+/// A certificate and key, with the modification times of the files they
+/// were read from.
+#[derive(Debug)]
+struct Loaded {
+    key: Arc<CertifiedKey>,
+    modified: (Option<SystemTime>, Option<SystemTime>),
+}
+
+impl Loaded {
+    fn read(config: &TlsConfig, provider: &rustls::crypto::CryptoProvider) -> anyhow::Result<Self> {
+        let modified = modified_times(config);
+        let certs = CertificateDer::pem_file_iter(&config.cert_path)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .with_context(|| format!("Failed to read {}", config.cert_path.display()))?;
+        if certs.is_empty() {
+            anyhow::bail!("No certificates in {}", config.cert_path.display());
+        }
+        let key = PrivateKeyDer::from_pem_file(&config.key_path)
+            .with_context(|| format!("Failed to read {}", config.key_path.display()))?;
+        let key = CertifiedKey::from_der(certs, key, provider).with_context(|| {
+            format!(
+                "{} does not go with {}",
+                config.key_path.display(),
+                config.cert_path.display()
+            )
+        })?;
+        Ok(Self {
+            key: Arc::new(key),
+            modified,
+        })
+    }
+}
+
+fn modified_times(config: &TlsConfig) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &Path| path.metadata().and_then(|m| m.modified()).ok();
+    (modified(&config.cert_path), modified(&config.key_path))
+}
+
+/// Reload the certificate when either file changes. A renewal can replace
+/// the two files one at a time, so a pair that fails to load is retried on
+/// the next check while the old certificate stays in use.
+fn spawn_reloader(resolver: Arc<CertResolver>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let modified = modified_times(&resolver.config);
+            if modified == resolver.current.read().unwrap().modified {
+                continue;
+            }
+            match Loaded::read(&resolver.config, &resolver.provider) {
+                Ok(loaded) => {
+                    *resolver.current.write().unwrap() = loaded;
+                    tracing::info!(
+                        "Reloaded TLS certificate from {}",
+                        resolver.config.cert_path.display()
+                    );
+                }
+                Err(e) => tracing::warn!("Keeping the current TLS certificate: {:#}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,240 @@
+// Track changes
+// Edits made in suggestion mode do not touch the document. They are stored as
+// pending changes with their author and range, and reviewers accept them,
+// which applies them to the live document, or reject them.
+
+use std::ops::Range;
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    db::DbPool,
+    error::{AppError, Result},
+    services::collab::CollabService,
+};
+
+pub const PENDING: &str = "pending";
+pub const ACCEPTED: &str = "accepted";
+pub const REJECTED: &str = "rejected";
+
+// Columns of a change as returned to clients; `end` is derived from the
+// replaced text, counted in characters like `start`
+const SELECT: &str = "SELECT c.id, c.project_id, c.file_path, c.author_id, u.name AS author_name, \
+    c.start_offset AS start, c.start_offset + length(c.original) AS \"end\", c.original, \
+    c.content, c.status, c.reviewed_by, c.reviewed_at, c.created_at \
+    FROM tracked_changes c JOIN users u ON c.author_id = u.id";
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct TrackedChange {
+    pub id: String,
+    pub project_id: String,
+    pub file_path: String,
+    pub author_id: String,
+    pub author_name: String,
+    /// Character offsets of the replaced text when the change was made
+    pub start: i64,
+    pub end: i64,
+    /// The text the change replaces, empty for insertions
+    pub original: String,
+    /// The suggested text, empty for deletions
+    pub content: String,
+    pub status: String,
+    pub reviewed_by: Option<String>,
+    pub reviewed_at: Option<String>,
+    pub created_at: String,
+}
+
+/// Record a suggestion to replace characters `range` of a file with
+/// `content`, returning its ID.
+pub async fn suggest(
+    pool: &DbPool,
+    collab: &CollabService,
+    project_id: &str,
+    file_path: &str,
+    author_id: &str,
+    range: Range<usize>,
+    content: &str,
+) -> Result<String> {
+    if range.end < range.start {
+        return Err(AppError::Validation("Invalid range".to_string()));
+    }
+    if range.is_empty() && content.is_empty() {
+        return Err(AppError::Validation("The change is empty".to_string()));
+    }
+
+    let is_file = sqlx::query_scalar::<_, i64>(
+        "SELECT COUNT(*) FROM files WHERE project_id = $1 AND path = $2 AND is_folder = FALSE",
+    )
+    .bind(project_id)
+    .bind(file_path)
+    .fetch_one(pool)
+    .await?;
+    if is_file == 0 {
+        return Err(AppError::NotFound("File not found".to_string()));
+    }
+
+    let text = collab.content(project_id, file_path).await?;
+    let bytes = byte_offset(&text, range.start)..byte_offset(&text, range.end);
+    if text[..bytes.end].chars().count() < range.end {
+        return Err(AppError::Validation(
+            "The range is past the end of the file".to_string(),
+        ));
+    }
+    let original = &text[bytes];
+
+    let id = Uuid::new_v4().to_string();
+    sqlx::query(
+        "INSERT INTO tracked_changes (id, project_id, file_path, author_id, start_offset, original, content, status, created_at) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)",
+    )
+    .bind(&id)
+    .bind(project_id)
+    .bind(file_path)
+    .bind(author_id)
+    .bind(range.start as i64)
+    .bind(original)
+    .bind(content)
+    .bind(PENDING)
+    .bind(Utc::now().to_rfc3339())
+    .execute(pool)
+    .await?;
+
+    Ok(id)
+}
+
+pub async fn get(pool: &DbPool, id: &str) -> Result<TrackedChange> {
+    sqlx::query_as::<_, TrackedChange>(&format!("{SELECT} WHERE c.id = $1"))
+        .bind(id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Change not found".to_string()))
+}
+
+/// Changes of a project in document order, optionally of one file and with
+/// one status.
+pub async fn list(
+    pool: &DbPool,
+    project_id: &str,
+    file_path: Option<&str>,
+    status: Option<&str>,
+) -> Result<Vec<TrackedChange>> {
+    let changes = sqlx::query_as::<_, TrackedChange>(&format!(
+        "{SELECT} WHERE c.project_id = $1 AND ($2 IS NULL OR c.file_path = $3) \
+         AND ($4 IS NULL OR c.status = $5) \
+         ORDER BY c.file_path, c.start_offset, c.created_at"
+    ))
+    .bind(project_id)
+    .bind(file_path)
+    .bind(file_path)
+    .bind(status)
+    .bind(status)
+    .fetch_all(pool)
+    .await?;
+    Ok(changes)
+}
+
+/// Apply a pending change to its file and mark it accepted. Other pending
+/// changes further down the file are moved along with the text.
+pub async fn accept(
+    pool: &DbPool,
+    collab: &CollabService,
+    change: &TrackedChange,
+    reviewer_id: &str,
+) -> Result<()> {
+    // Claiming the change first keeps two reviewers from applying it twice
+    review(pool, &change.id, ACCEPTED, reviewer_id).await?;
+
+    let applied = collab
+        // The text is the author's even though a reviewer applied it
+        .edit(
+            &change.project_id,
+            &change.file_path,
+            &change.author_id,
+            |text| {
+                let range =
+                    locate(text, change.start as usize, &change.original).ok_or_else(|| {
+                        AppError::Conflict(
+                            "The text this change replaces has been edited since".to_string(),
+                        )
+                    })?;
+                Ok((range, change.content.clone()))
+            },
+        )
+        .await;
+    if let Err(e) = applied {
+        sqlx::query(
+            "UPDATE tracked_changes SET status = $1, reviewed_by = NULL, reviewed_at = NULL WHERE id = $2",
+        )
+        .bind(PENDING)
+        .bind(&change.id)
+        .execute(pool)
+        .await?;
+        return Err(e);
+    }
+
+    let shift = change.content.chars().count() as i64 - (change.end - change.start);
+    if shift != 0 {
+        sqlx::query(
+            "UPDATE tracked_changes SET start_offset = start_offset + $1 \
+             WHERE project_id = $2 AND file_path = $3 AND status = $4 AND start_offset >= $5",
+        )
+        .bind(shift)
+        .bind(&change.project_id)
+        .bind(&change.file_path)
+        .bind(PENDING)
+        .bind(change.end)
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Mark a pending change rejected, leaving the document as it is.
+pub async fn reject(pool: &DbPool, id: &str, reviewer_id: &str) -> Result<()> {
+    review(pool, id, REJECTED, reviewer_id).await
+}
+
+async fn review(pool: &DbPool, id: &str, status: &str, reviewer_id: &str) -> Result<()> {
+    let result = sqlx::query(
+        "UPDATE tracked_changes SET status = $1, reviewed_by = $2, reviewed_at = $3 WHERE id = $4 AND status = $5",
+    )
+    .bind(status)
+    .bind(reviewer_id)
+    .bind(Utc::now().to_rfc3339())
+    .bind(id)
+    .bind(PENDING)
+    .execute(pool)
+    .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::Conflict(
+            "The change has already been reviewed".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Byte offset of character `offset`, or the end of the text.
+fn byte_offset(text: &str, offset: usize) -> usize {
+    text.char_indices()
+        .nth(offset)
+        .map_or(text.len(), |(index, _)| index)
+}
+
+/// Where `original` is now, as a byte range. Edits made since the change was
+/// suggested may have moved it, so the occurrence closest to where it was is
+/// taken; insertions stay at their offset.
+fn locate(text: &str, start: usize, original: &str) -> Option<Range<usize>> {
+    let at = byte_offset(text, start);
+    if original.is_empty() {
+        return Some(at..at);
+    }
+    text.match_indices(original)
+        .map(|(index, _)| index)
+        .min_by_key(|index| index.abs_diff(at))
+        .map(|index| index..index + original.len())
+}
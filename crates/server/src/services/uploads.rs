@@ -0,0 +1,127 @@
+// Staging area for resumable uploads
+// Chunks are appended to a part file until the upload is finalized into storage
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::Utc;
+use futures::{Stream, StreamExt};
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+
+use crate::{
+    db::Database,
+    error::{AppError, Result},
+};
+
+// Sessions idle for longer than this are discarded
+pub const SESSION_TTL_HOURS: i64 = 24;
+
+pub fn part_path(upload_root: &Path, upload_id: &str) -> PathBuf {
+    upload_root.join(format!("{upload_id}.part"))
+}
+
+/// Outcome of appending one chunk; partial chunks are kept so the client can resume.
+pub struct ChunkResult {
+    pub received: u64,
+    pub error: Option<AppError>,
+}
+
+/// Write `body` into the part file starting at `offset`, never past `size`.
+pub async fn append_chunk<S, E>(part: &Path, offset: u64, size: u64, body: S) -> Result<ChunkResult>
+where
+    S: Stream<Item = std::result::Result<axum::body::Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    if let Some(parent) = part.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to create upload directory: {e}")))?;
+    }
+
+    let io_error = |e: std::io::Error| AppError::Internal(format!("Failed to write upload: {e}"));
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(part)
+        .await
+        .map_err(io_error)?;
+    // Drop anything past the acknowledged offset from an interrupted chunk
+    file.set_len(offset).await.map_err(io_error)?;
+    file.seek(std::io::SeekFrom::Start(offset))
+        .await
+        .map_err(io_error)?;
+
+    let mut received = offset;
+    let mut error = None;
+    let mut body = body;
+
+    while let Some(chunk) = body.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                error = Some(AppError::BadRequest(format!("Upload interrupted: {e}")));
+                break;
+            }
+        };
+        if received + chunk.len() as u64 > size {
+            error = Some(AppError::BadRequest(
+                "Chunk extends past the declared upload size".to_string(),
+            ));
+            break;
+        }
+        file.write_all(&chunk).await.map_err(io_error)?;
+        received += chunk.len() as u64;
+    }
+
+    file.flush().await.map_err(io_error)?;
+    file.sync_data().await.map_err(io_error)?;
+
+    Ok(ChunkResult { received, error })
+}
+
+/// Remove expired sessions and part files that no session refers to,
+/// returning how many part files were deleted.
+pub async fn cleanup(db: &Database, upload_root: &Path) -> Result<usize> {
+    sqlx::query("DELETE FROM uploads WHERE expires_at < $1")
+        .bind(Utc::now().to_rfc3339())
+        .execute(&db.pool)
+        .await?;
+
+    // Sessions also disappear when their project is deleted
+    let live: HashSet<String> = sqlx::query_scalar::<_, String>("SELECT id FROM uploads")
+        .fetch_all(&db.pool)
+        .await?
+        .into_iter()
+        .collect();
+
+    let mut removed = 0;
+    if let Ok(mut entries) = tokio::fs::read_dir(upload_root).await {
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let name = entry.file_name();
+            let Some(upload_id) = name.to_str().and_then(|n| n.strip_suffix(".part")) else {
+                continue;
+            };
+            if !live.contains(upload_id) && tokio::fs::remove_file(entry.path()).await.is_ok() {
+                removed += 1;
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+pub fn spawn_cleanup(db: Database, upload_root: PathBuf) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(3600));
+        loop {
+            ticker.tick().await;
+            match cleanup(&db, &upload_root).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Removed {} stale uploads", count),
+                Err(e) => tracing::warn!("Upload cleanup failed: {}", e),
+            }
+        }
+    });
+}
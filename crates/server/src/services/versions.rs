@@ -0,0 +1,534 @@
+// Project versions
+// A version labels the state of every file in a project at one moment, such
+// as "submitted to ICML", so later work can be compared with it or rolled
+// back to it. File contents are kept as content-addressed storage objects
+// shared between versions, so labelling a project that barely changed costs
+// next to nothing.
+
+use std::collections::{BTreeMap, HashSet};
+
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::FromRow;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    db::{Database, DbPool},
+    error::{AppError, Result},
+    services::{
+        collab::CollabService,
+        diff::{self, DiffResult},
+        filetype, gc,
+        storage::StorageService,
+    },
+};
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct ProjectVersion {
+    pub id: String,
+    pub project_id: String,
+    pub label: String,
+    pub description: Option<String>,
+    pub created_by: Option<String>,
+    pub created_by_name: Option<String>,
+    pub created_at: String,
+    pub file_count: i64,
+    pub total_size: i64,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow, ToSchema)]
+pub struct VersionFile {
+    #[serde(skip)]
+    pub file_id: String,
+    #[serde(skip)]
+    pub name: String,
+    pub path: String,
+    pub is_folder: bool,
+    pub hash: Option<String>,
+    pub size: i64,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    Modified,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct FileChange {
+    pub path: String,
+    pub change: ChangeKind,
+}
+
+// Columns of a version with its author and file totals
+const SELECT: &str = "SELECT v.id, v.project_id, v.label, v.description, v.created_by, \
+    u.name AS created_by_name, v.created_at, \
+    (SELECT COUNT(*) FROM project_version_files f WHERE f.version_id = v.id AND f.is_folder = FALSE) AS file_count, \
+    (SELECT CAST(COALESCE(SUM(f.size), 0) AS BIGINT) FROM project_version_files f WHERE f.version_id = v.id) AS total_size \
+    FROM project_versions v LEFT JOIN users u ON v.created_by = u.id";
+
+/// Label the current state of every file in the project.
+pub async fn create(
+    db: &Database,
+    storage: &StorageService,
+    collab: &CollabService,
+    project_id: &str,
+    user_id: &str,
+    label: &str,
+    description: Option<&str>,
+) -> Result<ProjectVersion> {
+    let label = label.trim();
+    if label.is_empty() {
+        return Err(AppError::Validation("Label is required".to_string()));
+    }
+
+    // Include what is being typed right now
+    collab.persist_project(project_id).await?;
+
+    let files = sqlx::query_as::<_, (String, String, String, bool)>(
+        "SELECT id, name, path, is_folder FROM files WHERE project_id = $1 ORDER BY path",
+    )
+    .bind(project_id)
+    .fetch_all(&db.pool)
+    .await?;
+
+    // Store contents before any row refers to them
+    let mut snapshot = Vec::with_capacity(files.len());
+    for (file_id, name, path, is_folder) in files {
+        let (hash, size) = if is_folder {
+            (None, 0)
+        } else {
+            let content = storage.read_bytes(project_id, &path).await?;
+            let hash = storage.write_object(&content).await?;
+            (Some(hash), content.len() as i64)
+        };
+        snapshot.push(VersionFile {
+            file_id,
+            name,
+            path,
+            is_folder,
+            hash,
+            size,
+        });
+    }
+
+    insert(db, project_id, user_id, label, description, &snapshot).await
+}
+
+/// Record a version made of `files`, whose contents are already stored as
+/// objects.
+pub async fn insert(
+    db: &Database,
+    project_id: &str,
+    user_id: &str,
+    label: &str,
+    description: Option<&str>,
+    files: &[VersionFile],
+) -> Result<ProjectVersion> {
+    let id = Uuid::new_v4().to_string();
+    let mut tx = db.pool.begin().await?;
+    sqlx::query(
+        "INSERT INTO project_versions (id, project_id, label, description, created_by, created_at) VALUES ($1, $2, $3, $4, $5, $6)",
+    )
+    .bind(&id)
+    .bind(project_id)
+    .bind(label)
+    .bind(description.map(str::trim).filter(|d| !d.is_empty()))
+    .bind(user_id)
+    .bind(Utc::now().to_rfc3339())
+    .execute(&mut *tx)
+    .await?;
+    for file in files {
+        sqlx::query(
+            "INSERT INTO project_version_files (version_id, file_id, name, path, is_folder, hash, size) VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(&id)
+        .bind(&file.file_id)
+        .bind(&file.name)
+        .bind(&file.path)
+        .bind(file.is_folder)
+        .bind(&file.hash)
+        .bind(file.size)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    get(&db.pool, project_id, &id).await
+}
+
+/// Versions of a project, newest first.
+pub async fn list(pool: &DbPool, project_id: &str) -> Result<Vec<ProjectVersion>> {
+    let versions = sqlx::query_as::<_, ProjectVersion>(&format!(
+        "{SELECT} WHERE v.project_id = $1 ORDER BY v.created_at DESC"
+    ))
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(versions)
+}
+
+pub async fn get(pool: &DbPool, project_id: &str, id: &str) -> Result<ProjectVersion> {
+    sqlx::query_as::<_, ProjectVersion>(&format!("{SELECT} WHERE v.id = $1 AND v.project_id = $2"))
+        .bind(id)
+        .bind(project_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| AppError::NotFound("Version not found".to_string()))
+}
+
+/// The version with this ID, or else the newest with this label.
+pub async fn find(pool: &DbPool, project_id: &str, name: &str) -> Result<Option<ProjectVersion>> {
+    let version = sqlx::query_as::<_, ProjectVersion>(&format!(
+        "{SELECT} WHERE v.project_id = $1 AND (v.id = $2 OR v.label = $3) \
+         ORDER BY CASE WHEN v.id = $4 THEN 0 ELSE 1 END, v.created_at DESC LIMIT 1"
+    ))
+    .bind(project_id)
+    .bind(name)
+    .bind(name)
+    .bind(name)
+    .fetch_optional(pool)
+    .await?;
+    Ok(version)
+}
+
+pub async fn files(pool: &DbPool, version_id: &str) -> Result<Vec<VersionFile>> {
+    let files = sqlx::query_as::<_, VersionFile>(
+        "SELECT file_id, name, path, is_folder, hash, size FROM project_version_files WHERE version_id = $1 ORDER BY path",
+    )
+    .bind(version_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(files)
+}
+
+/// Delete a version along with the stored contents nothing else uses.
+pub async fn delete(
+    pool: &DbPool,
+    storage: &StorageService,
+    project_id: &str,
+    id: &str,
+) -> Result<()> {
+    let hashes: HashSet<String> = files(pool, id)
+        .await?
+        .into_iter()
+        .filter_map(|file| file.hash)
+        .collect();
+
+    let deleted = sqlx::query("DELETE FROM project_versions WHERE id = $1 AND project_id = $2")
+        .bind(id)
+        .bind(project_id)
+        .execute(pool)
+        .await?;
+    if deleted.rows_affected() == 0 {
+        return Err(AppError::NotFound("Version not found".to_string()));
+    }
+
+    for hash in hashes {
+        if !gc::object_in_use(pool, &hash).await? {
+            storage.delete_object(&hash).await?;
+        }
+    }
+    Ok(())
+}
+
+/// One side of a comparison: a version, or the project as it is now when
+/// `version_id` is None. Files map from path to content hash, None for
+/// folders.
+async fn side(
+    db: &Database,
+    storage: &StorageService,
+    collab: &CollabService,
+    project_id: &str,
+    version_id: Option<&str>,
+) -> Result<BTreeMap<String, Option<String>>> {
+    if let Some(version_id) = version_id {
+        get(&db.pool, project_id, version_id).await?;
+        return Ok(files(&db.pool, version_id)
+            .await?
+            .into_iter()
+            .map(|file| (file.path, file.hash))
+            .collect());
+    }
+
+    collab.persist_project(project_id).await?;
+    let files = sqlx::query_as::<_, (String, bool, Option<String>)>(
+        "SELECT path, is_folder, hash FROM files WHERE project_id = $1",
+    )
+    .bind(project_id)
+    .fetch_all(&db.pool)
+    .await?;
+
+    let mut side = BTreeMap::new();
+    for (path, is_folder, hash) in files {
+        let hash = match (is_folder, hash) {
+            (true, _) => None,
+            (false, Some(hash)) => Some(hash),
+            // Rows from before hashes were recorded
+            (false, None) => Some(storage.hash_file(project_id, &path).await?),
+        };
+        side.insert(path, hash);
+    }
+    Ok(side)
+}
+
+/// Files that differ between two versions, or between a version and the
+/// current project when `to` is None.
+pub async fn compare(
+    db: &Database,
+    storage: &StorageService,
+    collab: &CollabService,
+    project_id: &str,
+    from: &str,
+    to: Option<&str>,
+) -> Result<Vec<FileChange>> {
+    let old = side(db, storage, collab, project_id, Some(from)).await?;
+    let new = side(db, storage, collab, project_id, to).await?;
+
+    let mut changes = Vec::new();
+    for (path, hash) in &old {
+        match new.get(path) {
+            None => changes.push(FileChange {
+                path: path.clone(),
+                change: ChangeKind::Removed,
+            }),
+            Some(new_hash) if new_hash != hash => changes.push(FileChange {
+                path: path.clone(),
+                change: ChangeKind::Modified,
+            }),
+            Some(_) => {}
+        }
+    }
+    for path in new.keys().filter(|path| !old.contains_key(*path)) {
+        changes.push(FileChange {
+            path: path.clone(),
+            change: ChangeKind::Added,
+        });
+    }
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(changes)
+}
+
+/// Line diff of one file between two versions, or between a version and the
+/// current project. A file missing on one side diffs against nothing.
+pub async fn diff_file(
+    db: &Database,
+    storage: &StorageService,
+    collab: &CollabService,
+    project_id: &str,
+    from: &str,
+    to: Option<&str>,
+    path: &str,
+) -> Result<DiffResult> {
+    let old = side(db, storage, collab, project_id, Some(from)).await?;
+    let new = side(db, storage, collab, project_id, to).await?;
+    if !old.contains_key(path) && !new.contains_key(path) {
+        return Err(AppError::NotFound("File not found".to_string()));
+    }
+
+    let old_content = match old.get(path) {
+        Some(Some(hash)) => text(storage.read_object(hash).await?, path)?,
+        _ => String::new(),
+    };
+    let new_content = match (to, new.get(path)) {
+        (Some(_), Some(Some(hash))) => text(storage.read_object(hash).await?, path)?,
+        (None, Some(Some(_))) => storage.read_file(project_id, path).await?,
+        _ => String::new(),
+    };
+
+    Ok(diff::unified_diff(
+        &old_content,
+        &new_content,
+        &format!("a/{path}"),
+        &format!("b/{path}"),
+        3,
+    ))
+}
+
+/// The ID a file had when the version was taken, unless a file renamed since
+/// has it now.
+async fn file_id(pool: &DbPool, id: &str) -> Result<String> {
+    let taken = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM files WHERE id = $1")
+        .bind(id)
+        .fetch_one(pool)
+        .await?;
+    Ok(if taken == 0 {
+        id.to_string()
+    } else {
+        Uuid::new_v4().to_string()
+    })
+}
+
+fn text(content: Vec<u8>, path: &str) -> Result<String> {
+    String::from_utf8(content)
+        .map_err(|_| AppError::BadRequest(format!("File is not valid UTF-8: {path}")))
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+#[schema(as = VersionRestoreReport)]
+pub struct RestoreReport {
+    /// Version holding the project as it was before the restore
+    pub backup_version_id: String,
+    pub restored: usize,
+    pub removed: usize,
+}
+
+/// Put every file back the way it was in a version. The current state is
+/// labelled first, so a restore can itself be undone. Files that still exist
+/// keep their IDs, and so their comments and locks.
+pub async fn restore(
+    db: &Database,
+    storage: &StorageService,
+    collab: &CollabService,
+    project_id: &str,
+    id: &str,
+    user_id: &str,
+) -> Result<RestoreReport> {
+    let version = get(&db.pool, project_id, id).await?;
+    let target = files(&db.pool, id).await?;
+
+    let backup = create(
+        db,
+        storage,
+        collab,
+        project_id,
+        user_id,
+        &format!("Before restoring \"{}\"", version.label),
+        None,
+    )
+    .await?;
+    let (restored, removed) = apply(db, storage, collab, project_id, &target, user_id).await?;
+
+    Ok(RestoreReport {
+        backup_version_id: backup.id,
+        restored,
+        removed,
+    })
+}
+
+/// Make the project's files match `target`, whose contents are stored
+/// objects, with folders listed before their contents. Returns how many files
+/// were restored and how many removed.
+pub async fn apply(
+    db: &Database,
+    storage: &StorageService,
+    collab: &CollabService,
+    project_id: &str,
+    target: &[VersionFile],
+    user_id: &str,
+) -> Result<(usize, usize)> {
+    // Folders map to None
+    let current = side(db, storage, collab, project_id, None).await?;
+
+    let (mut restored, mut removed) = (0, 0);
+    let now = Utc::now().to_rfc3339();
+
+    for file in target {
+        let existing = current.get(&file.path).map(|hash| (hash.is_none(), hash));
+        if file.is_folder {
+            if existing.is_none() {
+                storage.create_folder(project_id, &file.path).await?;
+                sqlx::query(
+                    "INSERT INTO files (id, project_id, name, path, is_folder, created_at, updated_at) VALUES ($1, $2, $3, $4, TRUE, $5, $6)",
+                )
+                .bind(file_id(&db.pool, &file.file_id).await?)
+                .bind(project_id)
+                .bind(&file.name)
+                .bind(&file.path)
+                .bind(&now)
+                .bind(&now)
+                .execute(&db.pool)
+                .await?;
+                restored += 1;
+            }
+            continue;
+        }
+
+        let Some(hash) = &file.hash else { continue };
+        if existing.is_some_and(|(is_folder, current)| !is_folder && current.as_ref() == Some(hash))
+        {
+            continue;
+        }
+
+        let content = storage.read_object(hash).await?;
+        let file_type = filetype::detect(&file.path, &content);
+        match existing {
+            Some((false, _)) => {
+                let open = match std::str::from_utf8(&content) {
+                    Ok(text) => {
+                        collab
+                            .replace_open(project_id, &file.path, user_id, text)
+                            .await?
+                    }
+                    Err(_) => false,
+                };
+                if !open {
+                    storage.write_file(project_id, &file.path, &content).await?;
+                }
+                sqlx::query(
+                    "UPDATE files SET hash = $1, mime_type = $2, language = $3, updated_at = $4 WHERE project_id = $5 AND path = $6",
+                )
+                .bind(hash)
+                .bind(&file_type.mime_type)
+                .bind(&file_type.language)
+                .bind(&now)
+                .bind(project_id)
+                .bind(&file.path)
+                .execute(&db.pool)
+                .await?;
+            }
+            Some((true, _)) => {
+                return Err(AppError::Conflict(format!(
+                    "{} is now a folder; move it out of the way to restore this version",
+                    file.path
+                )));
+            }
+            None => {
+                storage.write_file(project_id, &file.path, &content).await?;
+                sqlx::query(
+                    "INSERT INTO files (id, project_id, name, path, is_folder, hash, mime_type, language, created_at, updated_at) VALUES ($1, $2, $3, $4, FALSE, $5, $6, $7, $8, $9)",
+                )
+                .bind(file_id(&db.pool, &file.file_id).await?)
+                .bind(project_id)
+                .bind(&file.name)
+                .bind(&file.path)
+                .bind(hash)
+                .bind(&file_type.mime_type)
+                .bind(&file_type.language)
+                .bind(&now)
+                .bind(&now)
+                .execute(&db.pool)
+                .await?;
+            }
+        }
+        restored += 1;
+    }
+
+    // Remove what the version did not have, contents before their folders
+    let wanted: HashSet<&str> = target.iter().map(|file| file.path.as_str()).collect();
+    for (path, hash) in current.iter().rev() {
+        if wanted.contains(path.as_str()) {
+            continue;
+        }
+        // A folder still holding restored files stays
+        if hash.is_none()
+            && wanted
+                .iter()
+                .any(|wanted| wanted.starts_with(&format!("{path}/")))
+        {
+            continue;
+        }
+        storage.delete_file(project_id, path).await?;
+        sqlx::query("DELETE FROM files WHERE project_id = $1 AND path = $2")
+            .bind(project_id)
+            .bind(path)
+            .execute(&db.pool)
+            .await?;
+        removed += 1;
+    }
+
+    Ok((restored, removed))
+}
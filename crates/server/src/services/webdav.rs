@@ -0,0 +1,889 @@
+// WebDAV sync
+// Keeps a project in two-way sync with a folder on a WebDAV server, such as
+// Nextcloud or ownCloud, for users who want a copy of their sources in their
+// own cloud storage. Each sync compares both sides with how they were after
+// the previous one: a change on one side is carried to the other, and a file
+// changed on both keeps the project's version, with the server's saved next
+// to it as a conflicted copy on both sides.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use percent_encoding::percent_decode_str;
+use quick_xml::events::Event;
+use reqwest::{header, Method, StatusCode, Url};
+use serde::Serialize;
+use sqlx::FromRow;
+use tokio::sync::Mutex;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    db::Database,
+    error::{AppError, Result},
+    services::{
+        collab::CollabService,
+        events::{ProjectEvent, ProjectEvents},
+        secrets::Sealer,
+        storage::{is_temp_file, StorageService},
+        versions::{self, VersionFile},
+    },
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Folders beyond this are almost certainly linked by mistake
+const MAX_FILES: usize = 10_000;
+
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<d:propfind xmlns:d="DAV:"><d:prop><d:resourcetype/><d:getetag/><d:getlastmodified/><d:getcontentlength/></d:prop></d:propfind>"#;
+
+#[derive(Debug, Serialize, FromRow, ToSchema)]
+pub struct SyncLink {
+    pub url: String,
+    pub username: String,
+    pub created_by: String,
+    pub created_at: String,
+    pub last_sync_at: Option<String>,
+    /// Why the last sync failed; None once one succeeds
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct SyncReport {
+    pub uploaded: usize,
+    pub downloaded: usize,
+    pub deleted_remote: usize,
+    pub deleted_local: usize,
+    /// Conflicted copies made of files changed on both sides
+    pub conflicts: Vec<String>,
+}
+
+impl SyncReport {
+    pub fn files_changed(&self) -> bool {
+        self.downloaded > 0 || self.deleted_local > 0 || !self.conflicts.is_empty()
+    }
+}
+
+/// One resource in a PROPFIND answer.
+struct Entry {
+    href: String,
+    is_collection: bool,
+    /// The ETag, or the modification time and size for servers without one
+    etag: String,
+}
+
+/// A linked folder and the credentials for it.
+struct Remote {
+    base: Url,
+    username: String,
+    password: String,
+}
+
+impl Remote {
+    /// The URL of a project path inside the folder.
+    fn url(&self, path: &str) -> Url {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .expect("linked URLs have a path")
+            .pop_if_empty()
+            .extend(path.split('/'));
+        url
+    }
+
+    /// The project path of a URL inside the folder, if it is a valid one.
+    fn path(&self, href: &str) -> Option<String> {
+        let url = self.base.join(href).ok()?;
+        let rest = url.path().strip_prefix(self.base.path())?;
+        let rest = rest.trim_end_matches('/');
+        if rest.is_empty() {
+            return None;
+        }
+        let mut segments = Vec::new();
+        for segment in rest.split('/') {
+            let segment = percent_decode_str(segment).decode_utf8().ok()?;
+            let valid = !segment.is_empty()
+                && segment != "."
+                && segment != ".."
+                && !segment.contains(['/', '\\'])
+                && !segment.chars().any(char::is_control);
+            if !valid {
+                return None;
+            }
+            segments.push(segment.into_owned());
+        }
+        Some(segments.join("/"))
+    }
+}
+
+#[derive(Clone)]
+pub struct WebDavService {
+    client: reqwest::Client,
+    sealer: Sealer,
+    max_file_size: u64,
+    // A project's syncs run one at a time, so two never apply the same
+    // changes; keyed by project ID
+    syncing: Arc<std::sync::Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+}
+
+/// Check a folder URL and give it a trailing slash.
+fn folder_url(url: &str) -> Result<Url> {
+    let invalid = || AppError::Validation("Enter the https URL of a WebDAV folder".to_string());
+    let mut url = Url::parse(url.trim()).map_err(|_| invalid())?;
+    if url.scheme() != "https"
+        || url.host_str().is_none()
+        || !url.username().is_empty()
+        || url.password().is_some()
+        || url.cannot_be_a_base()
+    {
+        return Err(invalid());
+    }
+    url.set_query(None);
+    url.set_fragment(None);
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    Ok(url)
+}
+
+/// A free name for the server's copy of a file changed on both sides.
+fn conflict_path(path: &str, taken: &dyn Fn(&str) -> bool) -> String {
+    let (folder, name) = match path.rsplit_once('/') {
+        Some((folder, name)) => (format!("{folder}/"), name),
+        None => (String::new(), path),
+    };
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{extension}")),
+        _ => (name, String::new()),
+    };
+    let date = Utc::now().format("%Y-%m-%d");
+    let mut n = 1;
+    loop {
+        let suffix = if n == 1 {
+            String::new()
+        } else {
+            format!(" {n}")
+        };
+        let candidate = format!("{folder}{stem} (conflicted copy {date}{suffix}){extension}");
+        if !taken(&candidate) {
+            return candidate;
+        }
+        n += 1;
+    }
+}
+
+fn request_error(e: reqwest::Error) -> AppError {
+    // The innermost cause says what went wrong, such as an untrusted
+    // certificate
+    let mut cause: &dyn std::error::Error = &e;
+    while let Some(source) = cause.source() {
+        cause = source;
+    }
+    AppError::BadRequest(format!("WebDAV request failed: {cause}"))
+}
+
+/// The error for a response a sync cannot go on after.
+fn status_error(action: &str, path: &str, status: StatusCode) -> AppError {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => AppError::BadRequest(
+            "The WebDAV server refused access; check the username and password".to_string(),
+        ),
+        StatusCode::NOT_FOUND => {
+            AppError::BadRequest(format!("{path} is not on the WebDAV server"))
+        }
+        StatusCode::INSUFFICIENT_STORAGE => {
+            AppError::BadRequest("The WebDAV server is out of space".to_string())
+        }
+        status => AppError::BadRequest(format!("{action} {path} failed: {status}")),
+    }
+}
+
+/// Resources in a PROPFIND multistatus answer.
+fn parse_multistatus(xml: &str) -> Result<Vec<Entry>> {
+    let invalid =
+        |e: quick_xml::Error| AppError::BadRequest(format!("Invalid WebDAV response: {e}"));
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut path: Vec<String> = Vec::new();
+    let mut entries = Vec::new();
+    // href, collection, etag, modified, length of the response being read
+    let mut current: Option<(String, bool, String, String, String)> = None;
+
+    loop {
+        match reader.read_event().map_err(invalid)? {
+            Event::Start(tag) => {
+                let name = String::from_utf8_lossy(tag.local_name().as_ref()).into_owned();
+                if name == "response" {
+                    current = Some(Default::default());
+                }
+                if name == "collection" && path.last().is_some_and(|p| p == "resourcetype") {
+                    if let Some(current) = current.as_mut() {
+                        current.1 = true;
+                    }
+                }
+                path.push(name);
+            }
+            Event::Empty(tag)
+                if tag.local_name().as_ref() == b"collection"
+                    && path.last().is_some_and(|p| p == "resourcetype") =>
+            {
+                if let Some(current) = current.as_mut() {
+                    current.1 = true;
+                }
+            }
+            Event::End(tag) => {
+                path.pop();
+                if tag.local_name().as_ref() == b"response" {
+                    if let Some((href, is_collection, etag, modified, length)) = current.take() {
+                        let etag = if etag.is_empty() {
+                            format!("{modified}/{length}")
+                        } else {
+                            etag
+                        };
+                        entries.push(Entry {
+                            href: href.trim().to_string(),
+                            is_collection,
+                            etag,
+                        });
+                    }
+                }
+            }
+            Event::Text(text) => {
+                let Some(current) = current.as_mut() else {
+                    continue;
+                };
+                let text = text.unescape().map_err(invalid)?;
+                let field = match path.last().map(String::as_str) {
+                    Some("href") => &mut current.0,
+                    Some("getetag") => &mut current.2,
+                    Some("getlastmodified") => &mut current.3,
+                    Some("getcontentlength") => &mut current.4,
+                    _ => continue,
+                };
+                field.push_str(text.trim());
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(entries)
+}
+
+impl WebDavService {
+    pub fn from_config(config: &Config) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .user_agent(format!("openleaf/{}", env!("CARGO_PKG_VERSION")))
+            .timeout(REQUEST_TIMEOUT)
+            // A redirect would drop the credentials, or hand them elsewhere
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?;
+        Ok(Self {
+            client,
+            sealer: Sealer::from_config(config, "webdav passwords")?,
+            max_file_size: config.max_upload_size,
+            syncing: Arc::default(),
+        })
+    }
+
+    // The lock held while a project syncs or is unlinked
+    fn project_lock(&self, project_id: &str) -> Arc<Mutex<()>> {
+        let mut locks = self.syncing.lock().unwrap_or_else(|e| e.into_inner());
+        // Forget the locks nobody holds or waits for
+        locks.retain(|_, lock| Arc::strong_count(lock) > 1);
+        Arc::clone(locks.entry(project_id.to_string()).or_default())
+    }
+
+    fn request(&self, remote: &Remote, method: Method, url: Url) -> reqwest::RequestBuilder {
+        self.client
+            .request(method, url)
+            .basic_auth(&remote.username, Some(&remote.password))
+    }
+
+    async fn propfind(&self, remote: &Remote, url: Url, depth: &str) -> Result<Vec<Entry>> {
+        let path = url.path().to_string();
+        let response = self
+            .request(
+                remote,
+                Method::from_bytes(b"PROPFIND").expect("valid method"),
+                url,
+            )
+            .header("Depth", depth)
+            .header(header::CONTENT_TYPE, "application/xml; charset=utf-8")
+            .body(PROPFIND_BODY)
+            .send()
+            .await
+            .map_err(request_error)?;
+        let status = response.status();
+        if status != StatusCode::MULTI_STATUS {
+            return Err(status_error("Listing", &path, status));
+        }
+        let body = response.text().await.map_err(request_error)?;
+        parse_multistatus(&body)
+    }
+
+    /// Every file in the folder and its ETag, walking one level at a time as
+    /// many servers refuse an infinite depth.
+    async fn list(&self, remote: &Remote) -> Result<BTreeMap<String, String>> {
+        let mut files = BTreeMap::new();
+        let mut pending = vec![remote.base.clone()];
+        let mut seen = HashSet::new();
+        while let Some(url) = pending.pop() {
+            for entry in self.propfind(remote, url, "1").await? {
+                let Some(path) = remote.path(&entry.href) else {
+                    continue;
+                };
+                if entry.is_collection {
+                    if seen.insert(path.clone()) {
+                        let mut url = remote.url(&path);
+                        url.path_segments_mut()
+                            .expect("linked URLs have a path")
+                            .push("");
+                        pending.push(url);
+                    }
+                } else if !path.rsplit('/').next().is_some_and(is_temp_file) {
+                    files.insert(path, entry.etag);
+                }
+            }
+            if files.len() > MAX_FILES {
+                return Err(AppError::BadRequest(format!(
+                    "The WebDAV folder holds more than {MAX_FILES} files"
+                )));
+            }
+        }
+        Ok(files)
+    }
+
+    async fn download(&self, remote: &Remote, path: &str) -> Result<Vec<u8>> {
+        let response = self
+            .request(remote, Method::GET, remote.url(path))
+            .send()
+            .await
+            .map_err(request_error)?;
+        if !response.status().is_success() {
+            return Err(status_error("Downloading", path, response.status()));
+        }
+        if response
+            .content_length()
+            .is_some_and(|length| length > self.max_file_size)
+        {
+            return Err(AppError::BadRequest(format!(
+                "{path} is larger than the upload limit"
+            )));
+        }
+        let content = response.bytes().await.map_err(request_error)?;
+        Ok(content.to_vec())
+    }
+
+    /// Store a file, making the folders above it, and return its new ETag.
+    async fn upload(
+        &self,
+        remote: &Remote,
+        path: &str,
+        content: Vec<u8>,
+        folders: &mut HashSet<String>,
+    ) -> Result<String> {
+        let mut parent = String::new();
+        for segment in path
+            .split('/')
+            .collect::<Vec<_>>()
+            .split_last()
+            .map_or(&[][..], |(_, rest)| rest)
+        {
+            if !parent.is_empty() {
+                parent.push('/');
+            }
+            parent.push_str(segment);
+            if !folders.insert(parent.clone()) {
+                continue;
+            }
+            let mut url = remote.url(&parent);
+            url.path_segments_mut()
+                .expect("linked URLs have a path")
+                .push("");
+            let response = self
+                .request(
+                    remote,
+                    Method::from_bytes(b"MKCOL").expect("valid method"),
+                    url,
+                )
+                .send()
+                .await
+                .map_err(request_error)?;
+            // 405 when the folder is already there
+            let status = response.status();
+            if !status.is_success() && status != StatusCode::METHOD_NOT_ALLOWED {
+                return Err(status_error("Creating", &parent, status));
+            }
+        }
+
+        let response = self
+            .request(remote, Method::PUT, remote.url(path))
+            .body(content)
+            .send()
+            .await
+            .map_err(request_error)?;
+        if !response.status().is_success() {
+            return Err(status_error("Uploading", path, response.status()));
+        }
+        if let Some(etag) = response
+            .headers()
+            .get(header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+        {
+            return Ok(etag.to_string());
+        }
+
+        // Not every server answers a PUT with the ETag
+        self.propfind(remote, remote.url(path), "0")
+            .await?
+            .into_iter()
+            .next()
+            .map(|entry| entry.etag)
+            .ok_or_else(|| AppError::BadRequest(format!("The WebDAV server lost {path}")))
+    }
+
+    async fn delete(&self, remote: &Remote, path: &str) -> Result<()> {
+        let response = self
+            .request(remote, Method::DELETE, remote.url(path))
+            .send()
+            .await
+            .map_err(request_error)?;
+        let status = response.status();
+        if !status.is_success() && status != StatusCode::NOT_FOUND {
+            return Err(status_error("Deleting", path, status));
+        }
+        Ok(())
+    }
+
+    pub async fn status(&self, db: &Database, project_id: &str) -> Result<Option<SyncLink>> {
+        let link = sqlx::query_as::<_, SyncLink>(
+            "SELECT url, username, created_by, created_at, last_sync_at, last_error \
+             FROM project_sync WHERE project_id = $1",
+        )
+        .bind(project_id)
+        .fetch_optional(&db.pool)
+        .await?;
+        Ok(link)
+    }
+
+    /// Link the project to a WebDAV folder, checking the folder and
+    /// credentials first. The first sync after linking treats files on both
+    /// sides as new.
+    pub async fn link(
+        &self,
+        db: &Database,
+        project_id: &str,
+        user_id: &str,
+        url: &str,
+        username: &str,
+        password: &str,
+    ) -> Result<SyncLink> {
+        let url = folder_url(url)?;
+        let username = username.trim();
+        if username.is_empty() || username.contains(':') || username.chars().any(char::is_control) {
+            return Err(AppError::Validation("Invalid username".to_string()));
+        }
+        if password.is_empty() || password.chars().any(char::is_control) {
+            return Err(AppError::Validation("Invalid password".to_string()));
+        }
+
+        let remote = Remote {
+            base: url.clone(),
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+        let folder = self.propfind(&remote, url.clone(), "0").await?;
+        if !folder.first().is_some_and(|entry| entry.is_collection) {
+            return Err(AppError::Validation(
+                "That URL is a file, not a folder".to_string(),
+            ));
+        }
+
+        let mut tx = db.pool.begin().await?;
+        sqlx::query(
+            "INSERT INTO project_sync (project_id, url, username, password, created_by, created_at) VALUES ($1, $2, $3, $4, $5, $6) \
+             ON CONFLICT (project_id) DO UPDATE SET url = excluded.url, username = excluded.username, \
+             password = excluded.password, created_by = excluded.created_by, created_at = excluded.created_at, \
+             last_sync_at = NULL, last_error = NULL",
+        )
+        .bind(project_id)
+        .bind(url.as_str())
+        .bind(username)
+        .bind(self.sealer.seal(password)?)
+        .bind(user_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM project_sync_files WHERE project_id = $1")
+            .bind(project_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        self.status(db, project_id)
+            .await?
+            .ok_or_else(|| AppError::Internal("Link was not saved".to_string()))
+    }
+
+    /// Stop syncing; files stay where they are on both sides.
+    pub async fn unlink(&self, db: &Database, project_id: &str) -> Result<bool> {
+        let lock = self.project_lock(project_id);
+        let _syncing = lock.lock().await;
+        let mut tx = db.pool.begin().await?;
+        sqlx::query("DELETE FROM project_sync_files WHERE project_id = $1")
+            .bind(project_id)
+            .execute(&mut *tx)
+            .await?;
+        let result = sqlx::query("DELETE FROM project_sync WHERE project_id = $1")
+            .bind(project_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Sync the project with its folder, recording the outcome on the link.
+    pub async fn sync(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+    ) -> Result<SyncReport> {
+        let lock = self.project_lock(project_id);
+        let _syncing = lock.lock().await;
+        let result = self.sync_locked(db, storage, collab, project_id).await;
+
+        let now = Utc::now().to_rfc3339();
+        let error = result.as_ref().err().map(ToString::to_string);
+        sqlx::query(
+            "UPDATE project_sync SET last_sync_at = CASE WHEN $1 IS NULL THEN $2 ELSE last_sync_at END, \
+             last_error = $3 WHERE project_id = $4",
+        )
+        .bind(&error)
+        .bind(&now)
+        .bind(&error)
+        .bind(project_id)
+        .execute(&db.pool)
+        .await?;
+        result
+    }
+
+    async fn sync_locked(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+    ) -> Result<SyncReport> {
+        let (url, username, password, user_id) = sqlx::query_as::<
+            _,
+            (String, String, String, String),
+        >(
+            "SELECT url, username, password, created_by FROM project_sync WHERE project_id = $1",
+        )
+        .bind(project_id)
+        .fetch_optional(&db.pool)
+        .await?
+        .ok_or_else(|| {
+            AppError::BadRequest("The project is not linked to a WebDAV folder".to_string())
+        })?;
+        let remote = Remote {
+            base: folder_url(&url)?,
+            username,
+            password: self.sealer.open(&password)?,
+        };
+
+        // Include what is being typed right now
+        collab.persist_project(project_id).await?;
+        let mut local = BTreeMap::new();
+        let files = sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT path, hash FROM files WHERE project_id = $1 AND is_folder = FALSE",
+        )
+        .bind(project_id)
+        .fetch_all(&db.pool)
+        .await?;
+        for (path, hash) in files {
+            let hash = match hash {
+                Some(hash) => hash,
+                // Rows from before hashes were recorded
+                None => storage.hash_file(project_id, &path).await?,
+            };
+            local.insert(path, hash);
+        }
+
+        let remote_files = self.list(&remote).await?;
+        let base: BTreeMap<String, (String, String)> =
+            sqlx::query_as::<_, (String, String, String)>(
+                "SELECT path, hash, etag FROM project_sync_files WHERE project_id = $1",
+            )
+            .bind(project_id)
+            .fetch_all(&db.pool)
+            .await?
+            .into_iter()
+            .map(|(path, hash, etag)| (path, (hash, etag)))
+            .collect();
+
+        // An emptied folder is more likely a mistake, such as a moved folder,
+        // than a wish to delete every file from the project
+        if remote_files.is_empty() && !base.is_empty() {
+            return Err(AppError::Conflict(
+                "The WebDAV folder is empty; link the project again to start over".to_string(),
+            ));
+        }
+
+        let mut report = SyncReport::default();
+        let mut next = base.clone();
+        // Paths to change in the project: new content, or None to delete
+        let mut incoming: BTreeMap<String, Option<(String, i64)>> = BTreeMap::new();
+        let mut folders = HashSet::new();
+
+        let paths: BTreeSet<String> = local
+            .keys()
+            .chain(remote_files.keys())
+            .chain(base.keys())
+            .cloned()
+            .collect();
+        for path in &paths {
+            let ours = local.get(path);
+            let theirs = remote_files.get(path);
+            let last = base.get(path);
+            let ours_changed = ours != last.map(|(hash, _)| hash);
+            let theirs_changed = theirs != last.map(|(_, etag)| etag);
+
+            // Which side's file the other gets
+            let take_ours = match (ours, theirs) {
+                _ if !ours_changed && !theirs_changed => continue,
+                _ if !theirs_changed => true,
+                _ if !ours_changed => false,
+                (None, None) => {
+                    next.remove(path);
+                    continue;
+                }
+                // An edit beats a delete
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (Some(ours), Some(etag)) => {
+                    let content = self.download(&remote, path).await?;
+                    let hash = storage.write_object(&content).await?;
+                    if &hash != ours {
+                        let copy = conflict_path(path, &|candidate| {
+                            paths.contains(candidate) || incoming.contains_key(candidate)
+                        });
+                        let size = content.len() as i64;
+                        let copy_etag = self.upload(&remote, &copy, content, &mut folders).await?;
+                        incoming.insert(copy.clone(), Some((hash.clone(), size)));
+                        next.insert(copy.clone(), (hash, copy_etag));
+                        report.conflicts.push(copy);
+                        true
+                    } else {
+                        next.insert(path.clone(), (hash, etag.clone()));
+                        continue;
+                    }
+                }
+            };
+
+            if take_ours {
+                match ours {
+                    Some(hash) => {
+                        let content = storage.read_bytes(project_id, path).await?;
+                        let etag = self.upload(&remote, path, content, &mut folders).await?;
+                        next.insert(path.clone(), (hash.clone(), etag));
+                        report.uploaded += 1;
+                    }
+                    None => {
+                        self.delete(&remote, path).await?;
+                        next.remove(path);
+                        report.deleted_remote += 1;
+                    }
+                }
+            } else {
+                match theirs {
+                    Some(etag) => {
+                        let content = self.download(&remote, path).await?;
+                        let hash = storage.write_object(&content).await?;
+                        incoming.insert(path.clone(), Some((hash.clone(), content.len() as i64)));
+                        next.insert(path.clone(), (hash, etag.clone()));
+                        report.downloaded += 1;
+                    }
+                    None => {
+                        incoming.insert(path.clone(), None);
+                        next.remove(path);
+                        report.deleted_local += 1;
+                    }
+                }
+            }
+        }
+
+        if !incoming.is_empty() {
+            self.apply(db, storage, collab, project_id, &user_id, &incoming)
+                .await?;
+        }
+
+        let mut tx = db.pool.begin().await?;
+        sqlx::query("DELETE FROM project_sync_files WHERE project_id = $1")
+            .bind(project_id)
+            .execute(&mut *tx)
+            .await?;
+        for (path, (hash, etag)) in &next {
+            sqlx::query(
+                "INSERT INTO project_sync_files (project_id, path, hash, etag) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(project_id)
+            .bind(path)
+            .bind(hash)
+            .bind(etag)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        Ok(report)
+    }
+
+    /// Bring files from the server into the project, leaving the rest as they
+    /// are now.
+    async fn apply(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        project_id: &str,
+        user_id: &str,
+        incoming: &BTreeMap<String, Option<(String, i64)>>,
+    ) -> Result<()> {
+        let name = |path: &str| path.rsplit('/').next().unwrap_or(path).to_string();
+
+        // The files as they are now, edits made during the sync included
+        collab.persist_project(project_id).await?;
+        let rows = sqlx::query_as::<_, (String, String, String, bool, Option<String>)>(
+            "SELECT id, name, path, is_folder, hash FROM files WHERE project_id = $1",
+        )
+        .bind(project_id)
+        .fetch_all(&db.pool)
+        .await?;
+        let mut target: BTreeMap<String, VersionFile> = BTreeMap::new();
+        for (file_id, name, path, is_folder, hash) in rows {
+            let hash = match (is_folder, hash) {
+                (true, _) => None,
+                (false, Some(hash)) => Some(hash),
+                (false, None) => Some(storage.hash_file(project_id, &path).await?),
+            };
+            target.insert(
+                path.clone(),
+                VersionFile {
+                    file_id,
+                    name,
+                    path,
+                    is_folder,
+                    hash,
+                    size: 0,
+                },
+            );
+        }
+
+        let mut arriving = Vec::new();
+        for (path, change) in incoming {
+            let Some((hash, size)) = change else {
+                target.remove(path);
+                continue;
+            };
+            let mut parent = path.as_str();
+            while let Some((folder, _)) = parent.rsplit_once('/') {
+                target
+                    .entry(folder.to_string())
+                    .or_insert_with(|| VersionFile {
+                        file_id: Uuid::new_v4().to_string(),
+                        name: name(folder),
+                        path: folder.to_string(),
+                        is_folder: true,
+                        hash: None,
+                        size: 0,
+                    });
+                parent = folder;
+            }
+            let file = VersionFile {
+                file_id: target
+                    .get(path)
+                    .map(|file| file.file_id.clone())
+                    .unwrap_or_else(|| Uuid::new_v4().to_string()),
+                name: name(path),
+                path: path.clone(),
+                is_folder: false,
+                hash: Some(hash.clone()),
+                size: *size,
+            };
+            arriving.push(file.clone());
+            target.insert(path.clone(), file);
+        }
+
+        // The downloaded files travel into the project as a version, which
+        // keeps their stored contents in use until applied and is dropped
+        // afterwards. The map is sorted, so folders come before their contents
+        let version =
+            versions::insert(db, project_id, user_id, "WebDAV sync", None, &arriving).await?;
+        let target: Vec<VersionFile> = target.into_values().collect();
+        let applied = versions::apply(db, storage, collab, project_id, &target, user_id).await;
+        versions::delete(&db.pool, storage, project_id, &version.id).await?;
+        applied.map(|_| ())
+    }
+
+    /// Sync every linked project, returning how many succeeded.
+    pub async fn sync_all(
+        &self,
+        db: &Database,
+        storage: &StorageService,
+        collab: &CollabService,
+        events: &ProjectEvents,
+    ) -> Result<usize> {
+        // Frozen projects take no changes from their folders
+        let projects = sqlx::query_scalar::<_, String>(
+            "SELECT s.project_id FROM project_sync s JOIN projects p ON s.project_id = p.id \
+             WHERE p.frozen_at IS NULL",
+        )
+        .fetch_all(&db.pool)
+        .await?;
+
+        let mut synced = 0;
+        for project_id in projects {
+            match self.sync(db, storage, collab, &project_id).await {
+                Ok(report) => {
+                    if report.files_changed() {
+                        events.publish(&project_id, ProjectEvent::FilesChanged);
+                    }
+                    synced += 1;
+                }
+                Err(e) => {
+                    tracing::warn!("WebDAV sync of project {} failed: {}", project_id, e)
+                }
+            }
+        }
+        Ok(synced)
+    }
+}
+
+pub fn spawn_scheduler(
+    webdav: WebDavService,
+    db: Database,
+    storage: StorageService,
+    collab: CollabService,
+    events: ProjectEvents,
+    interval: Duration,
+) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            match webdav.sync_all(&db, &storage, &collab, &events).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Synced {} projects with WebDAV", count),
+                Err(e) => tracing::warn!("WebDAV sync failed: {}", e),
+            }
+        }
+    });
+}
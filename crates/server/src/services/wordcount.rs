@@ -0,0 +1,83 @@
+// Word counts
+// Runs texcount over the main file and everything it includes
+
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::error::{AppError, Result};
+
+const TEXCOUNT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct WordCount {
+    /// Words in the body text
+    pub words: u64,
+    /// Words in section titles
+    pub header_words: u64,
+    /// Words outside the body text, mostly figure and table captions
+    pub caption_words: u64,
+    pub headers: u64,
+    /// Figures, tables and other floats
+    pub floats: u64,
+    pub inline_math: u64,
+    pub display_math: u64,
+    /// Number of files counted, including the main file
+    pub files: u64,
+}
+
+/// Count words in `main_file`, following \input and \include.
+pub async fn count(project_path: &Path, main_file: &str) -> Result<WordCount> {
+    let child = tokio::process::Command::new("texcount")
+        .args(["-inc", "-total", "-utf8", main_file])
+        .current_dir(project_path)
+        .kill_on_drop(true)
+        .output();
+
+    let output = match tokio::time::timeout(TEXCOUNT_TIMEOUT, child).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(AppError::Internal("texcount is not installed".to_string()));
+        }
+        Ok(Err(e)) => return Err(AppError::Internal(format!("Failed to run texcount: {e}"))),
+        Err(_) => return Err(AppError::Internal("texcount timed out".to_string())),
+    };
+
+    if !output.status.success() {
+        return Err(AppError::BadRequest(format!(
+            "texcount failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// texcount prints one "Label: count" line per counter
+fn parse(output: &str) -> WordCount {
+    let mut count = WordCount::default();
+
+    for line in output.lines() {
+        let Some((label, value)) = line.rsplit_once(':') else {
+            continue;
+        };
+        let Ok(value) = value.trim().parse::<u64>() else {
+            continue;
+        };
+        let field = match label.trim() {
+            "Words in text" => &mut count.words,
+            "Words in headers" => &mut count.header_words,
+            "Words outside text (captions, etc.)" => &mut count.caption_words,
+            "Number of headers" => &mut count.headers,
+            "Number of floats/tables/figures" => &mut count.floats,
+            "Number of math inlines" => &mut count.inline_math,
+            "Number of math displayed" => &mut count.display_math,
+            "Files" => &mut count.files,
+            _ => continue,
+        };
+        *field = value;
+    }
+
+    count
+}
@@ -0,0 +1,216 @@
+// Zotero
+// Checks API keys and exports a user or group library, or one collection of
+// it, as BibTeX through the Zotero web API
+
+use std::time::Duration;
+
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    error::{AppError, Result},
+};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Largest page the API serves
+const PAGE_SIZE: usize = 100;
+
+// Libraries beyond this are almost certainly linked by mistake
+const MAX_ITEMS: usize = 10_000;
+
+#[derive(Debug, Deserialize)]
+pub struct Account {
+    #[serde(rename = "userID")]
+    pub user_id: u64,
+    pub username: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LibraryType {
+    User,
+    Group,
+}
+
+impl LibraryType {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "user" => Some(Self::User),
+            "group" => Some(Self::Group),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::User => "user",
+            Self::Group => "group",
+        }
+    }
+}
+
+pub struct Library<'a> {
+    pub library_type: LibraryType,
+    pub library_id: &'a str,
+    pub collection_key: Option<&'a str>,
+}
+
+impl Library<'_> {
+    fn items_path(&self) -> String {
+        let owner = match self.library_type {
+            LibraryType::User => "users",
+            LibraryType::Group => "groups",
+        };
+        match self.collection_key {
+            Some(collection) => format!(
+                "{owner}/{}/collections/{collection}/items/top",
+                self.library_id
+            ),
+            None => format!("{owner}/{}/items/top", self.library_id),
+        }
+    }
+}
+
+pub enum Export {
+    /// The library hasn't changed since the given version
+    NotModified,
+    Items {
+        bibtex: String,
+        /// Library version the export reflects
+        version: Option<i64>,
+    },
+}
+
+pub fn is_valid_library_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= 20 && id.chars().all(|c| c.is_ascii_digit())
+}
+
+// Collection keys are eight characters such as "ABCD2345"
+pub fn is_valid_collection_key(key: &str) -> bool {
+    key.len() == 8 && key.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+fn client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .user_agent(format!("openleaf/{}", env!("CARGO_PKG_VERSION")))
+        .timeout(REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| AppError::Internal(format!("Failed to create HTTP client: {e}")))
+}
+
+fn request(client: &reqwest::Client, url: &str, api_key: &str) -> reqwest::RequestBuilder {
+    client
+        .get(url)
+        .header("Zotero-API-Version", "3")
+        .header("Zotero-API-Key", api_key)
+}
+
+/// The account an API key belongs to.
+pub async fn verify_key(config: &Config, api_key: &str) -> Result<Account> {
+    let url = format!("{}/keys/current", config.zotero_url.trim_end_matches('/'));
+    let response = request(&client()?, &url, api_key)
+        .send()
+        .await
+        .map_err(|e| AppError::Internal(format!("Zotero request failed: {e}")))?;
+    match response.status() {
+        status if status.is_success() => response
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(format!("Invalid Zotero response: {e}"))),
+        StatusCode::FORBIDDEN | StatusCode::NOT_FOUND => Err(AppError::BadRequest(
+            "Zotero did not accept the API key".to_string(),
+        )),
+        status => Err(AppError::Internal(format!("Zotero returned {status}"))),
+    }
+}
+
+/// Export the top-level items of a library as BibTeX, page by page. With
+/// `since_version`, returns `NotModified` when nothing changed since then.
+pub async fn export(
+    config: &Config,
+    api_key: &str,
+    library: &Library<'_>,
+    since_version: Option<i64>,
+) -> Result<Export> {
+    let client = client()?;
+    let url = format!(
+        "{}/{}",
+        config.zotero_url.trim_end_matches('/'),
+        library.items_path()
+    );
+
+    let mut pages = Vec::new();
+    let mut version = None;
+    let mut start = 0;
+    loop {
+        let mut builder = request(&client, &url, api_key).query(&[
+            ("format", "bibtex".to_string()),
+            ("limit", PAGE_SIZE.to_string()),
+            ("start", start.to_string()),
+        ]);
+        if let (Some(since), 0) = (since_version, start) {
+            builder = builder.header("If-Modified-Since-Version", since.to_string());
+        }
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| AppError::Internal(format!("Zotero request failed: {e}")))?;
+
+        match response.status() {
+            StatusCode::NOT_MODIFIED => return Ok(Export::NotModified),
+            status if status.is_success() => {}
+            StatusCode::FORBIDDEN => {
+                return Err(AppError::Forbidden(
+                    "The Zotero API key has no access to this library".to_string(),
+                ))
+            }
+            StatusCode::NOT_FOUND => {
+                return Err(AppError::NotFound(
+                    "Zotero library or collection not found".to_string(),
+                ))
+            }
+            StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => {
+                return Err(AppError::TooManyRequests {
+                    message: "Zotero is rate limiting requests; try again later".to_string(),
+                    retry_after: header_number(response.headers(), "Retry-After")
+                        .and_then(|secs| u64::try_from(secs).ok()),
+                })
+            }
+            status => return Err(AppError::Internal(format!("Zotero returned {status}"))),
+        }
+
+        let headers = response.headers().clone();
+        if start == 0 {
+            version = header_number(&headers, "Last-Modified-Version");
+        }
+        let total = header_number(&headers, "Total-Results")
+            .and_then(|total| usize::try_from(total).ok())
+            .unwrap_or(0);
+        if total > MAX_ITEMS {
+            return Err(AppError::BadRequest(format!(
+                "The library has {total} items; link a collection of at most {MAX_ITEMS} instead"
+            )));
+        }
+        let page = response
+            .text()
+            .await
+            .map_err(|e| AppError::Internal(format!("Invalid Zotero response: {e}")))?;
+        pages.push(page.trim().to_string());
+
+        start += PAGE_SIZE;
+        if start >= total {
+            break;
+        }
+    }
+
+    pages.retain(|page| !page.is_empty());
+    Ok(Export::Items {
+        bibtex: pages.join("\n\n"),
+        version,
+    })
+}
+
+fn header_number(headers: &HeaderMap, name: &str) -> Option<i64> {
+    headers.get(name)?.to_str().ok()?.trim().parse().ok()
+}